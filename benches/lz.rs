@@ -0,0 +1,59 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use ravends::{
+    lz::CompressionLevel,
+    lz10::{compress_lz10, decompress_lz10},
+};
+
+/// Data resembling game assets: text, tiled graphics with long runs, and noise.
+fn samples() -> Vec<(&'static str, Vec<u8>)> {
+    let mut state = 0x1234_5678u32;
+    let noise = (0..0x40000)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        })
+        .collect();
+    let tiles = (0..0x40000u32)
+        .map(|index| match (index / 0x20) % 4 {
+            0 => 0,
+            1 => (index % 0x10) as u8,
+            _ => (index / 0x400) as u8,
+        })
+        .collect();
+    let text = include_str!("../src/main.rs")
+        .bytes()
+        .cycle()
+        .take(0x40000)
+        .collect();
+    vec![("text", text), ("tiles", tiles), ("noise", noise)]
+}
+
+fn decompression(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decompress_lz10");
+    for (name, data) in samples() {
+        let compressed = compress_lz10(&data, CompressionLevel::Fast).unwrap();
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            &compressed,
+            |b, compressed| b.iter(|| decompress_lz10(compressed.as_slice()).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+fn compression(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compress_lz10");
+    group.sample_size(10);
+    for (name, data) in samples() {
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &data, |b, data| {
+            b.iter(|| compress_lz10(data, CompressionLevel::Fast).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decompression, compression);
+criterion_main!(benches);
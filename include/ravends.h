@@ -0,0 +1,129 @@
+#ifndef RAVENDS_H
+#define RAVENDS_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// A ROM read into memory, along with the details handed out about it.
+typedef struct RavendsRom RavendsRom;
+
+// Bytes allocated by ravends, to be freed with [`ravends_buffer_free`].
+typedef struct RavendsBuffer {
+  uint8_t *data;
+  size_t len;
+} RavendsBuffer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Describes the last error that happened in the calling thread, or null if none has.
+//
+// The string stays valid until the next failing call made by the same thread.
+const char *ravends_last_error(void);
+
+// Frees a buffer handed out by ravends.
+//
+// # Safety
+//
+// `buffer` must have been handed out by ravends and not freed already.
+void ravends_buffer_free(struct RavendsBuffer buffer);
+
+// Opens the ROM at a path, reading it whole into memory. Returns null if it can't be read.
+//
+// # Safety
+//
+// `path` must point to a NUL-terminated UTF-8 string.
+struct RavendsRom *ravends_rom_open(const char *path);
+
+// Opens a ROM from memory, copying it. Returns null if it isn't a valid ROM.
+//
+// # Safety
+//
+// `data` must point to `len` readable bytes.
+struct RavendsRom *ravends_rom_open_memory(const uint8_t *data, size_t len);
+
+// Closes a ROM, freeing it along with every string handed out about it.
+//
+// # Safety
+//
+// `rom` must be null or have been opened by ravends and not closed already.
+void ravends_rom_free(struct RavendsRom *rom);
+
+// The title in the ROM's header. The string lives as long as the ROM.
+//
+// # Safety
+//
+// `rom` must have been opened by ravends and not closed.
+const char *ravends_rom_title(const struct RavendsRom *rom);
+
+// The game code in the ROM's header, such as `AMCE`. The string lives as long as the ROM.
+//
+// # Safety
+//
+// `rom` must have been opened by ravends and not closed.
+const char *ravends_rom_game_code(const struct RavendsRom *rom);
+
+// How many files the ROM's NitroFS holds.
+//
+// # Safety
+//
+// `rom` must have been opened by ravends and not closed.
+size_t ravends_rom_file_count(const struct RavendsRom *rom);
+
+// The NitroFS path of the file at `index`, in file ID order, or null if there are fewer files.
+// The string lives as long as the ROM.
+//
+// # Safety
+//
+// `rom` must have been opened by ravends and not closed.
+const char *ravends_rom_file_path(const struct RavendsRom *rom, size_t index);
+
+// Copies a file of the ROM's NitroFS, exactly as stored, into `out`.
+//
+// # Safety
+//
+// `rom` must have been opened by ravends and not closed, `path` must point to a NUL-terminated
+// UTF-8 string, and `out` must point to a writable buffer.
+bool ravends_rom_read_file(const struct RavendsRom *rom,
+                           const char *path,
+                           struct RavendsBuffer *out);
+
+// Decompresses LZ10-compressed data into `out`.
+//
+// # Safety
+//
+// `data` must point to `len` readable bytes and `out` to a writable buffer.
+bool ravends_lz10_decompress(const uint8_t *data, size_t len, struct RavendsBuffer *out);
+
+// Compresses data with LZ10 into `out`.
+//
+// # Safety
+//
+// `data` must point to `len` readable bytes and `out` to a writable buffer.
+bool ravends_lz10_compress(const uint8_t *data, size_t len, struct RavendsBuffer *out);
+
+// Decompresses LZ11-compressed data into `out`.
+//
+// # Safety
+//
+// `data` must point to `len` readable bytes and `out` to a writable buffer.
+bool ravends_lz11_decompress(const uint8_t *data, size_t len, struct RavendsBuffer *out);
+
+// Compresses data with LZ11 into `out`.
+//
+// # Safety
+//
+// `data` must point to `len` readable bytes and `out` to a writable buffer.
+bool ravends_lz11_compress(const uint8_t *data, size_t len, struct RavendsBuffer *out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RAVENDS_H */
@@ -0,0 +1,246 @@
+//! Python bindings for ravends, published as the `ravends` module.
+
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use pyo3::{create_exception, exceptions::PyException, prelude::*, types::PyBytes};
+use ravends::{
+    lz::CompressionLevel,
+    lz10::{compress_lz10, decompress_lz10},
+    lz11::{compress_lz11, decompress_lz11},
+    rom,
+    text::{EncodingKind, TextArchive, TextEncoding, TextLayout},
+};
+
+create_exception!(ravends, RavendsError, PyException);
+
+fn error(error: impl Into<anyhow::Error>) -> PyErr {
+    RavendsError::new_err(format!("{:#}", error.into()))
+}
+
+/// Parses the name of a variant of one of the enums also taken on the command line.
+fn parse_enum<T: ValueEnum>(value: &str, what: &str) -> PyResult<T> {
+    T::from_str(value, true).map_err(|_| error(anyhow::anyhow!("invalid {what}: {value:?}")))
+}
+
+/// An NDS ROM, held in memory.
+#[pyclass(module = "ravends")]
+struct Rom {
+    data: Vec<u8>,
+}
+
+impl Rom {
+    fn file_id(&self, path: &str) -> PyResult<u16> {
+        let fs = rom::filesystem(&self.data).map_err(error)?;
+        let path = path.trim_matches('/');
+        fs.files()
+            .into_iter()
+            .find(|entry| rom::nitro_path(&entry.path) == path)
+            .map(|entry| entry.id)
+            .ok_or_else(|| error(anyhow::anyhow!("no file in the ROM has the path {path:?}")))
+    }
+}
+
+#[pymethods]
+impl Rom {
+    #[new]
+    fn new(data: &[u8]) -> PyResult<Self> {
+        rom::check_header(data).map_err(error)?;
+        Ok(Self {
+            data: data.to_vec(),
+        })
+    }
+
+    /// Reads the ROM at a path.
+    #[staticmethod]
+    fn open(path: PathBuf) -> PyResult<Self> {
+        Ok(Self {
+            data: rom::read_rom(&path).map_err(error)?,
+        })
+    }
+
+    #[getter]
+    fn title(&self) -> String {
+        rom::header_text(&self.data, rom::TITLE_RANGE)
+    }
+
+    #[getter]
+    fn game_code(&self) -> String {
+        rom::header_text(&self.data, rom::GAME_CODE_RANGE)
+    }
+
+    /// The NitroFS paths of the files of the ROM, in file ID order.
+    fn files(&self) -> PyResult<Vec<String>> {
+        let fs = rom::filesystem(&self.data).map_err(error)?;
+        let mut entries = fs.files();
+        entries.sort_by_key(|entry| entry.id);
+        Ok(entries
+            .into_iter()
+            .map(|entry| rom::nitro_path(&entry.path))
+            .collect())
+    }
+
+    /// The data of a file as stored in the ROM, or decompressed if it's LZ10- or
+    /// LZ11-compressed and `decompress` is set.
+    #[pyo3(signature = (path, decompress = false))]
+    fn read_file<'py>(
+        &self,
+        py: Python<'py>,
+        path: &str,
+        decompress: bool,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let id = self.file_id(path)?;
+        let fs = rom::filesystem(&self.data).map_err(error)?;
+        let entry = fs.files().into_iter().find(|entry| entry.id == id).unwrap();
+        let data = rom::checked_file_data(&self.data, entry).map_err(error)?;
+        if decompress {
+            if let Some(data) = decompress_lz10(data)
+                .ok()
+                .or_else(|| decompress_lz11(data).ok())
+            {
+                return Ok(PyBytes::new(py, &data));
+            }
+        }
+        Ok(PyBytes::new(py, data))
+    }
+
+    /// Replaces the data of a file, moving it to the end of the ROM if it no longer fits.
+    fn replace_file(&mut self, path: &str, data: &[u8]) -> PyResult<()> {
+        let id = self.file_id(path)?;
+        rom::replace_file(&mut self.data, id, data).map_err(error)?;
+        Ok(())
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.data)
+    }
+
+    fn save(&self, path: PathBuf) -> PyResult<()> {
+        std::fs::write(&path, &self.data)
+            .map_err(|source| error(anyhow::Error::new(source).context(format!("{path:?}"))))
+    }
+}
+
+fn load_encoding(
+    encoding: Option<&str>,
+    table: Option<PathBuf>,
+    control_codes: Option<PathBuf>,
+) -> PyResult<TextEncoding> {
+    let kind = encoding
+        .map(|encoding| parse_enum::<EncodingKind>(encoding, "encoding"))
+        .transpose()?;
+    TextEncoding::load(kind, table.as_deref(), control_codes.as_deref()).map_err(error)
+}
+
+/// The strings of a text file or BMG file, along with the encoding they're stored in.
+///
+/// The encoding is given by name (`utf16le`, `shift-jis`, `ascii` or `table`), along with the
+/// path of a character table or control code file, as on the command line.
+#[pyclass(name = "TextArchive", module = "ravends")]
+struct PyTextArchive {
+    archive: TextArchive,
+    encoding: TextEncoding,
+}
+
+#[pymethods]
+impl PyTextArchive {
+    #[new]
+    #[pyo3(signature = (strings, encoding = None, table = None, control_codes = None))]
+    fn new(
+        strings: Vec<String>,
+        encoding: Option<&str>,
+        table: Option<PathBuf>,
+        control_codes: Option<PathBuf>,
+    ) -> PyResult<Self> {
+        Ok(Self {
+            archive: TextArchive::new(strings),
+            encoding: load_encoding(encoding, table, control_codes)?,
+        })
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (data, encoding = None, table = None, control_codes = None))]
+    fn parse(
+        data: &[u8],
+        encoding: Option<&str>,
+        table: Option<PathBuf>,
+        control_codes: Option<PathBuf>,
+    ) -> PyResult<Self> {
+        let encoding = load_encoding(encoding, table, control_codes)?;
+        Ok(Self {
+            archive: TextArchive::parse(data, &encoding).map_err(error)?,
+            encoding,
+        })
+    }
+
+    #[getter]
+    fn strings(&self) -> Vec<String> {
+        self.archive.strings.clone()
+    }
+
+    #[setter]
+    fn set_strings(&mut self, strings: Vec<String>) {
+        self.archive = std::mem::take(&mut self.archive).with_strings(strings);
+    }
+
+    fn __len__(&self) -> usize {
+        self.archive.strings.len()
+    }
+
+    /// Builds the file, laying its strings out as given by `layout` (`sequential`,
+    /// `deduplicated` or `preserve`).
+    #[pyo3(signature = (layout = "deduplicated"))]
+    fn to_bytes<'py>(&self, py: Python<'py>, layout: &str) -> PyResult<Bound<'py, PyBytes>> {
+        let layout = parse_enum::<TextLayout>(layout, "layout")?;
+        let data = self
+            .archive
+            .to_bytes(&self.encoding, layout)
+            .map_err(error)?;
+        Ok(PyBytes::new(py, &data))
+    }
+}
+
+#[pyfunction]
+fn lz10_decompress<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    Ok(PyBytes::new(py, &decompress_lz10(data).map_err(error)?))
+}
+
+/// Compresses data with LZ10, trying as hard as `level` says (`fast` or `best`).
+#[pyfunction]
+#[pyo3(signature = (data, level = "fast"))]
+fn lz10_compress<'py>(py: Python<'py>, data: &[u8], level: &str) -> PyResult<Bound<'py, PyBytes>> {
+    let level = parse_enum::<CompressionLevel>(level, "compression level")?;
+    Ok(PyBytes::new(
+        py,
+        &compress_lz10(data, level).map_err(error)?,
+    ))
+}
+
+#[pyfunction]
+fn lz11_decompress<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyBytes>> {
+    Ok(PyBytes::new(py, &decompress_lz11(data).map_err(error)?))
+}
+
+/// Compresses data with LZ11, trying as hard as `level` says (`fast` or `best`).
+#[pyfunction]
+#[pyo3(signature = (data, level = "fast"))]
+fn lz11_compress<'py>(py: Python<'py>, data: &[u8], level: &str) -> PyResult<Bound<'py, PyBytes>> {
+    let level = parse_enum::<CompressionLevel>(level, "compression level")?;
+    Ok(PyBytes::new(
+        py,
+        &compress_lz11(data, level).map_err(error)?,
+    ))
+}
+
+#[pymodule]
+#[pyo3(name = "ravends")]
+fn ravends_python(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add("RavendsError", module.py().get_type::<RavendsError>())?;
+    module.add_class::<Rom>()?;
+    module.add_class::<PyTextArchive>()?;
+    module.add_function(wrap_pyfunction!(lz10_decompress, module)?)?;
+    module.add_function(wrap_pyfunction!(lz10_compress, module)?)?;
+    module.add_function(wrap_pyfunction!(lz11_decompress, module)?)?;
+    module.add_function(wrap_pyfunction!(lz11_compress, module)?)?;
+    Ok(())
+}
@@ -0,0 +1,1911 @@
+use std::collections::{HashMap, HashSet};
+
+use thiserror::Error;
+
+use crate::{
+    freespace::Allocator,
+    memory::{self, Binary, MapAddressError},
+    symbols::SymbolMap,
+};
+
+#[derive(Error, Debug)]
+pub enum AssembleError {
+    #[error("line {line}: {message}")]
+    Syntax { line: usize, message: String },
+    #[error("line {line}")]
+    Address {
+        line: usize,
+        source: MapAddressError,
+    },
+    #[error("line {line}: no free space left for 0x{size:X} bytes")]
+    OutOfFreeSpace { line: usize, size: usize },
+}
+
+/// Instruction set being assembled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Arm,
+    Thumb,
+}
+
+/// Assembled bytes to place at a RAM address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// ARM9 overlay the address refers to, or `None` for the static ARM9 code or the only
+    /// overlay holding it.
+    pub overlay_id: Option<u32>,
+    pub address: u32,
+    pub data: Vec<u8>,
+    /// Line of the source the chunk starts at.
+    pub line: usize,
+}
+
+#[derive(Debug)]
+enum Statement<'s> {
+    Org(&'s str),
+    /// Places the following code in free space, up to the next `.org` or `.freespace`.
+    FreeSpace,
+    /// Selects the ARM9 overlay following addresses refer to, or the static ARM9 code.
+    Overlay(Option<&'s str>),
+    Mode(Mode),
+    Align(&'s str),
+    Equ(&'s str, &'s str),
+    Data {
+        size: usize,
+        values: Vec<&'s str>,
+    },
+    Ascii(Vec<u8>),
+    Space(&'s str),
+    Instruction {
+        mnemonic: String,
+        operands: &'s str,
+    },
+}
+
+type Symbols = HashMap<String, i64>;
+
+/// Removes `;`, `@` and `//` comments from a line, leaving those inside strings alone.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (index, char) in line.char_indices() {
+        match char {
+            '"' => in_string = !in_string,
+            ';' | '@' if !in_string => return &line[..index],
+            '/' if !in_string && line[index..].starts_with("//") => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Splits operands at the commas outside of brackets and braces.
+fn split_operands(operands: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start, mut in_string) = (0, 0, false);
+    for (index, char) in operands.char_indices() {
+        match char {
+            '"' => in_string = !in_string,
+            '[' | '{' | '(' if !in_string => depth += 1,
+            ']' | '}' | ')' if !in_string => depth -= 1,
+            ',' if depth == 0 && !in_string => {
+                parts.push(operands[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = operands[start..].trim();
+    if !last.is_empty() || !parts.is_empty() {
+        parts.push(last);
+    }
+    parts
+}
+
+fn parse_string(operand: &str) -> Result<Vec<u8>, String> {
+    let inner = operand
+        .strip_prefix('"')
+        .and_then(|operand| operand.strip_suffix('"'))
+        .ok_or_else(|| format!("expected a string, found {operand:?}"))?;
+    let mut bytes = Vec::new();
+    let mut chars = inner.chars();
+    while let Some(char) = chars.next() {
+        let char = match char {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some(escaped @ ('\\' | '"')) => escaped,
+                other => {
+                    return Err(format!(
+                        "invalid escape sequence \\{}",
+                        other.unwrap_or(' ')
+                    ))
+                }
+            },
+            char => char,
+        };
+        let mut buffer = [0; 4];
+        bytes.extend_from_slice(char.encode_utf8(&mut buffer).as_bytes());
+    }
+    Ok(bytes)
+}
+
+fn parse_statement(text: &str) -> Result<Statement<'_>, String> {
+    let (name, operands) = text
+        .split_once(char::is_whitespace)
+        .map_or((text, ""), |(name, operands)| (name, operands.trim()));
+    let lowercase = name.to_ascii_lowercase();
+    if !lowercase.starts_with('.') {
+        return Ok(Statement::Instruction {
+            mnemonic: lowercase,
+            operands,
+        });
+    }
+    Ok(match lowercase.as_str() {
+        ".org" => Statement::Org(operands),
+        ".freespace" => Statement::FreeSpace,
+        ".arm9" => Statement::Overlay(None),
+        ".overlay" => Statement::Overlay(Some(operands)),
+        ".arm" => Statement::Mode(Mode::Arm),
+        ".thumb" => Statement::Mode(Mode::Thumb),
+        ".align" => Statement::Align(if operands.is_empty() { "4" } else { operands }),
+        ".equ" | ".set" => match split_operands(operands)[..] {
+            [name, value] => Statement::Equ(name, value),
+            _ => return Err(format!("{name} takes a name and a value")),
+        },
+        ".byte" | ".db" => Statement::Data {
+            size: 1,
+            values: split_operands(operands),
+        },
+        ".hword" | ".halfword" | ".short" | ".dh" => Statement::Data {
+            size: 2,
+            values: split_operands(operands),
+        },
+        ".word" | ".dw" => Statement::Data {
+            size: 4,
+            values: split_operands(operands),
+        },
+        ".ascii" => Statement::Ascii(parse_string(operands)?),
+        ".asciz" | ".string" => {
+            let mut bytes = parse_string(operands)?;
+            bytes.push(0);
+            Statement::Ascii(bytes)
+        }
+        ".space" | ".skip" => Statement::Space(operands),
+        _ => return Err(format!("unknown directive {name}")),
+    })
+}
+
+/// Evaluates an expression made of numbers, symbols, parentheses and the `+ - * << >> & | ~`
+/// operators.
+fn eval(expression: &str, symbols: &Symbols) -> Result<i64, String> {
+    let tokens = tokenize(expression)?;
+    let mut parser = ExpressionParser {
+        tokens: &tokens,
+        position: 0,
+        symbols,
+    };
+    let value = parser.bitwise()?;
+    if parser.position != tokens.len() {
+        return Err(format!("invalid expression {expression:?}"));
+    }
+    Ok(value)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Symbol(String),
+    Operator(&'static str),
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    const OPERATORS: [&str; 10] = ["<<", ">>", "+", "-", "*", "&", "|", "~", "(", ")"];
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim();
+    while !rest.is_empty() {
+        if let Some(operator) = OPERATORS
+            .iter()
+            .find(|operator| rest.starts_with(*operator))
+        {
+            tokens.push(Token::Operator(operator));
+            rest = rest[operator.len()..].trim_start();
+            continue;
+        }
+        let len = rest
+            .find(|char: char| !(char.is_ascii_alphanumeric() || matches!(char, '_' | '.' | '$')))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(format!("invalid expression {expression:?}"));
+        }
+        let word = &rest[..len];
+        let token = if word.starts_with(|char: char| char.is_ascii_digit()) {
+            let lowercase = word.to_ascii_lowercase();
+            let parsed = if let Some(hex) = lowercase.strip_prefix("0x") {
+                i64::from_str_radix(hex, 16)
+            } else if let Some(binary) = lowercase.strip_prefix("0b") {
+                i64::from_str_radix(binary, 2)
+            } else {
+                lowercase.parse()
+            };
+            Token::Number(parsed.map_err(|_| format!("invalid number {word:?}"))?)
+        } else {
+            Token::Symbol(word.to_owned())
+        };
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+struct ExpressionParser<'t> {
+    tokens: &'t [Token],
+    position: usize,
+    symbols: &'t Symbols,
+}
+
+impl ExpressionParser<'_> {
+    fn eat(&mut self, operators: &[&'static str]) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(operator)) if operators.contains(operator) => {
+                self.position += 1;
+                Some(operator)
+            }
+            _ => None,
+        }
+    }
+
+    fn bitwise(&mut self) -> Result<i64, String> {
+        let mut value = self.shift()?;
+        while let Some(operator) = self.eat(&["&", "|"]) {
+            let rhs = self.shift()?;
+            value = if operator == "&" {
+                value & rhs
+            } else {
+                value | rhs
+            };
+        }
+        Ok(value)
+    }
+
+    fn shift(&mut self) -> Result<i64, String> {
+        let mut value = self.sum()?;
+        while let Some(operator) = self.eat(&["<<", ">>"]) {
+            let rhs = self.sum()?.clamp(0, 63) as u32;
+            value = if operator == "<<" {
+                value << rhs
+            } else {
+                value >> rhs
+            };
+        }
+        Ok(value)
+    }
+
+    fn sum(&mut self) -> Result<i64, String> {
+        let mut value = self.product()?;
+        while let Some(operator) = self.eat(&["+", "-"]) {
+            let rhs = self.product()?;
+            value = if operator == "+" {
+                value.wrapping_add(rhs)
+            } else {
+                value.wrapping_sub(rhs)
+            };
+        }
+        Ok(value)
+    }
+
+    fn product(&mut self) -> Result<i64, String> {
+        let mut value = self.unary()?;
+        while self.eat(&["*"]).is_some() {
+            value = value.wrapping_mul(self.unary()?);
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        match self.eat(&["-", "~", "+", "("]) {
+            Some("-") => return Ok(self.unary()?.wrapping_neg()),
+            Some("~") => return Ok(!self.unary()?),
+            Some("+") => return self.unary(),
+            Some(_) => {
+                let value = self.bitwise()?;
+                return match self.eat(&[")"]) {
+                    Some(_) => Ok(value),
+                    None => Err("unbalanced parentheses".to_owned()),
+                };
+            }
+            None => {}
+        }
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Symbol(name)) => self
+                .symbols
+                .get(&name)
+                .copied()
+                .ok_or_else(|| format!("undefined symbol {name:?}")),
+            _ => Err("expected a value".to_owned()),
+        }
+    }
+}
+
+/// Size of an instruction, known before its operands are.
+fn instruction_size(mode: Mode, mnemonic: &str, operands: &str) -> usize {
+    match mode {
+        Mode::Arm => 4,
+        Mode::Thumb => {
+            // BL and BLX to a label are made of two halfwords.
+            let is_long_branch = matches!(mnemonic, "bl" | "blx") && parse_reg(operands).is_err();
+            if is_long_branch {
+                4
+            } else {
+                2
+            }
+        }
+    }
+}
+
+/// Assembles ARM & Thumb source into chunks of bytes to place in memory.
+///
+/// Besides instructions and labels, the source may use `.org` to set the address following
+/// code is placed at, `.freespace` to have it placed in free space, `.arm`/`.thumb` to switch instruction sets, `.overlay N`/`.arm9` to pick
+/// whether addresses refer to an ARM9 overlay or the static ARM9 code, along with the
+/// `.equ`, `.align`, `.byte`, `.hword`, `.word`, `.ascii`, `.asciz` and `.space` directives.
+///
+/// Names from the symbol map given can be used like labels, which take precedence over them.
+/// An `.org` at an overlay symbol targets that overlay, unless `.overlay`/`.arm9` says otherwise.
+///
+/// Sources using `.freespace` must be assembled with [`assemble_with_allocator`].
+pub fn assemble(source: &str, symbol_map: &SymbolMap) -> Result<Vec<Chunk>, AssembleError> {
+    assemble_inner(source, symbol_map, None)
+}
+
+/// Assembles source like [`assemble`], placing the code following each `.freespace` directive
+/// in free space taken from the allocator: in the overlay selected with `.overlay`, or in the
+/// ARM9 binary.
+pub fn assemble_with_allocator(
+    source: &str,
+    symbol_map: &SymbolMap,
+    allocator: &mut Allocator,
+) -> Result<Vec<Chunk>, AssembleError> {
+    assemble_inner(source, symbol_map, Some(allocator))
+}
+
+/// Size and alignment of the block of statements following a `.freespace`, which runs up to the
+/// next `.org` or `.freespace`. The block is aligned to a word, or to its largest `.align`.
+fn free_space_block(
+    statements: &[(usize, Result<Statement, &str>)],
+    mut mode: Mode,
+    symbols: &Symbols,
+) -> Result<(usize, usize), AssembleError> {
+    let syntax = |line: usize| move |message: String| AssembleError::Syntax { line, message };
+    let block = statements
+        .iter()
+        .take_while(|(_, statement)| {
+            !matches!(statement, Ok(Statement::Org(_) | Statement::FreeSpace))
+        })
+        .collect::<Vec<_>>();
+    let mut alignment = 4;
+    for (line, statement) in &block {
+        if let Ok(Statement::Align(value)) = statement {
+            alignment = alignment.max(eval(value, symbols).map_err(syntax(*line))?.max(1));
+        }
+    }
+    let mut size = 0;
+    for (line, statement) in block {
+        size += match statement {
+            Ok(Statement::Mode(new_mode)) => {
+                mode = *new_mode;
+                0
+            }
+            Ok(Statement::Align(value)) => {
+                let value = eval(value, symbols).map_err(syntax(*line))?.max(1);
+                (size + value - 1) / value * value - size
+            }
+            Ok(Statement::Data { size, values }) => (size * values.len()) as i64,
+            Ok(Statement::Ascii(bytes)) => bytes.len() as i64,
+            Ok(Statement::Space(value)) => eval(value, symbols).map_err(syntax(*line))?,
+            Ok(Statement::Instruction { mnemonic, operands }) => {
+                instruction_size(mode, mnemonic, operands) as i64
+            }
+            _ => 0,
+        };
+    }
+    Ok((size as usize, alignment as usize))
+}
+
+fn assemble_inner(
+    source: &str,
+    symbol_map: &SymbolMap,
+    mut allocator: Option<&mut Allocator>,
+) -> Result<Vec<Chunk>, AssembleError> {
+    let syntax = |line: usize| move |message: String| AssembleError::Syntax { line, message };
+
+    let mut statements = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let mut text = strip_comment(line).trim();
+        while let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            let is_label = !label.is_empty()
+                && label
+                    .chars()
+                    .all(|char| char.is_ascii_alphanumeric() || matches!(char, '_' | '.' | '$'));
+            if !is_label || rest.starts_with(':') {
+                break;
+            }
+            statements.push((index + 1, Err(label)));
+            text = rest.trim();
+        }
+        if !text.is_empty() {
+            let statement = parse_statement(text).map_err(syntax(index + 1))?;
+            statements.push((index + 1, Ok(statement)));
+        }
+    }
+
+    // First pass: find the address of every label.
+    let mut symbols: Symbols = symbol_map
+        .iter()
+        .map(|(name, symbol)| (name.to_owned(), symbol.address as i64))
+        .collect();
+    let mut defined = HashSet::new();
+    let mut mode = Mode::Arm;
+    let mut address: Option<i64> = None;
+    let mut overlay_id = None;
+    // Where the code following each `.freespace` is placed.
+    let mut allocations = Vec::new();
+    for (index, (line, statement)) in statements.iter().enumerate() {
+        let here = |address: Option<i64>| {
+            address.ok_or_else(|| syntax(*line)("code or data found before any .org".to_owned()))
+        };
+        let size = match statement {
+            Err(label) => {
+                let value = here(address)?;
+                if !defined.insert(*label) {
+                    return Err(syntax(*line)(format!("label {label:?} defined twice")));
+                }
+                symbols.insert(label.to_string(), value);
+                0
+            }
+            Ok(Statement::Org(expression)) => {
+                address = Some(eval(expression, &symbols).map_err(syntax(*line))?);
+                0
+            }
+            Ok(Statement::FreeSpace) => {
+                let Some(allocator) = allocator.as_deref_mut() else {
+                    return Err(syntax(*line)(
+                        ".freespace used, but no free space was given".to_owned(),
+                    ));
+                };
+                let (size, alignment) = free_space_block(&statements[index + 1..], mode, &symbols)?;
+                let user = overlay_id.map_or(Binary::Arm9, Binary::Overlay);
+                let allocation = allocator
+                    .allocate(size, alignment, user)
+                    .ok_or(AssembleError::OutOfFreeSpace { line: *line, size })?;
+                address = Some(allocation.address as i64);
+                allocations.push(allocation);
+                0
+            }
+            Ok(Statement::Overlay(id)) => {
+                overlay_id = match id {
+                    Some(id) => Some(eval(id, &symbols).map_err(syntax(*line))? as u32),
+                    None => None,
+                };
+                0
+            }
+            Ok(Statement::Mode(new_mode)) => {
+                mode = *new_mode;
+                0
+            }
+            Ok(Statement::Equ(name, value)) => {
+                let value = eval(value, &symbols).map_err(syntax(*line))?;
+                defined.insert(*name);
+                symbols.insert(name.to_string(), value);
+                0
+            }
+            Ok(Statement::Align(alignment)) => {
+                let alignment = eval(alignment, &symbols).map_err(syntax(*line))?.max(1);
+                let current = here(address)?;
+                (current + alignment - 1) / alignment * alignment - current
+            }
+            Ok(Statement::Data { size, values }) => (size * values.len()) as i64,
+            Ok(Statement::Ascii(bytes)) => bytes.len() as i64,
+            Ok(Statement::Space(size)) => eval(size, &symbols).map_err(syntax(*line))?,
+            Ok(Statement::Instruction { mnemonic, operands }) => {
+                instruction_size(mode, mnemonic, operands) as i64
+            }
+        };
+        if size != 0 {
+            address = Some(here(address)? + size);
+        }
+    }
+
+    // Second pass: encode everything.
+    let mut chunks: Vec<Chunk> = Vec::new();
+    // The overlay selected with `.overlay`/`.arm9`, if any.
+    let mut selected_overlay: Option<Option<u32>> = None;
+    let mut allocations = allocations.into_iter();
+    mode = Mode::Arm;
+    for (line, statement) in statements {
+        let Ok(statement) = statement else {
+            continue;
+        };
+        let error = syntax(line);
+        let start_chunk = |chunks: &mut Vec<Chunk>, overlay_id, address| {
+            chunks.push(Chunk {
+                overlay_id,
+                address,
+                data: Vec::new(),
+                line,
+            });
+        };
+        match statement {
+            Statement::Org(expression) => {
+                let address = eval(expression, &symbols).map_err(&error)? as u32;
+                let overlay_id = selected_overlay.unwrap_or_else(|| {
+                    // Overlays share memory, so the overlay of a symbol tells which one is meant.
+                    tokenize(expression)
+                        .into_iter()
+                        .flatten()
+                        .find_map(|token| match token {
+                            Token::Symbol(name) if !defined.contains(name.as_str()) => {
+                                symbol_map.get(&name)?.overlay_id
+                            }
+                            _ => None,
+                        })
+                });
+                start_chunk(&mut chunks, overlay_id, address);
+                continue;
+            }
+            Statement::FreeSpace => {
+                let allocation = allocations
+                    .next()
+                    .expect("the first pass allocates space for every .freespace");
+                let overlay_id = match allocation.binary {
+                    Binary::Overlay(overlay_id) => Some(overlay_id),
+                    Binary::Arm9 | Binary::Arm7 => None,
+                };
+                start_chunk(&mut chunks, overlay_id, allocation.address);
+                continue;
+            }
+            Statement::Overlay(id) => {
+                let overlay_id = match id {
+                    Some(id) => Some(eval(id, &symbols).map_err(&error)? as u32),
+                    None => None,
+                };
+                selected_overlay = Some(overlay_id);
+                if let Some(chunk) = chunks.last() {
+                    let address = chunk.address + chunk.data.len() as u32;
+                    start_chunk(&mut chunks, overlay_id, address);
+                }
+                continue;
+            }
+            Statement::Mode(new_mode) => {
+                mode = new_mode;
+                continue;
+            }
+            Statement::Equ(..) => continue,
+            _ => {}
+        }
+
+        let chunk = chunks.last_mut().unwrap();
+        let address = chunk.address + chunk.data.len() as u32;
+        match statement {
+            Statement::Align(alignment) => {
+                let alignment = eval(alignment, &symbols).map_err(&error)?.max(1) as u32;
+                let padding = address.next_multiple_of(alignment) - address;
+                chunk.data.extend(std::iter::repeat_n(0, padding as usize));
+            }
+            Statement::Data { size, values } => {
+                for value in values {
+                    let value = eval(value, &symbols).map_err(&error)?;
+                    chunk.data.extend_from_slice(&value.to_le_bytes()[..size]);
+                }
+            }
+            Statement::Ascii(bytes) => chunk.data.extend_from_slice(&bytes),
+            Statement::Space(size) => {
+                let size = eval(size, &symbols).map_err(&error)?;
+                chunk.data.extend(std::iter::repeat_n(0, size as usize));
+            }
+            Statement::Instruction { mnemonic, operands } => {
+                let operands = split_operands(operands);
+                match mode {
+                    Mode::Arm => {
+                        let instruction =
+                            encode_arm(&mnemonic, &operands, address, &symbols).map_err(&error)?;
+                        chunk.data.extend_from_slice(&instruction.to_le_bytes());
+                    }
+                    Mode::Thumb => {
+                        let halfwords = encode_thumb(&mnemonic, &operands, address, &symbols)
+                            .map_err(&error)?;
+                        for halfword in halfwords {
+                            chunk.data.extend_from_slice(&halfword.to_le_bytes());
+                        }
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+    chunks.retain(|chunk| !chunk.data.is_empty());
+    Ok(chunks)
+}
+
+/// Writes assembled chunks into the ARM9 binary and overlays of a ROM. Either all of them are
+/// written, or none are.
+pub fn patch_rom(rom_data: &mut [u8], chunks: &[Chunk]) -> Result<(), AssembleError> {
+    let offsets = chunks
+        .iter()
+        .map(|chunk| {
+            memory::rom_offset(rom_data, chunk.address, chunk.data.len(), chunk.overlay_id).map_err(
+                |source| AssembleError::Address {
+                    line: chunk.line,
+                    source,
+                },
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    for (chunk, offset) in chunks.iter().zip(offsets) {
+        rom_data[offset..offset + chunk.data.len()].copy_from_slice(&chunk.data);
+    }
+    Ok(())
+}
+
+const CONDITIONS: [(&str, u32); 17] = [
+    ("eq", 0x0),
+    ("ne", 0x1),
+    ("cs", 0x2),
+    ("hs", 0x2),
+    ("cc", 0x3),
+    ("lo", 0x3),
+    ("mi", 0x4),
+    ("pl", 0x5),
+    ("vs", 0x6),
+    ("vc", 0x7),
+    ("hi", 0x8),
+    ("ls", 0x9),
+    ("ge", 0xA),
+    ("lt", 0xB),
+    ("gt", 0xC),
+    ("le", 0xD),
+    ("al", 0xE),
+];
+const ALWAYS: u32 = 0xE;
+
+/// Mnemonics without their condition and S suffixes, and whether they take an S suffix.
+const MNEMONICS: [(&str, bool); 55] = [
+    ("and", true),
+    ("eor", true),
+    ("sub", true),
+    ("rsb", true),
+    ("add", true),
+    ("adc", true),
+    ("sbc", true),
+    ("rsc", true),
+    ("tst", false),
+    ("teq", false),
+    ("cmp", false),
+    ("cmn", false),
+    ("orr", true),
+    ("mov", true),
+    ("bic", true),
+    ("mvn", true),
+    ("neg", true),
+    ("lsl", true),
+    ("lsr", true),
+    ("asr", true),
+    ("ror", true),
+    ("mul", true),
+    ("mla", true),
+    ("umull", true),
+    ("umlal", true),
+    ("smull", true),
+    ("smlal", true),
+    ("ldr", false),
+    ("ldrb", false),
+    ("ldrh", false),
+    ("ldrsb", false),
+    ("ldrsh", false),
+    ("str", false),
+    ("strb", false),
+    ("strh", false),
+    ("ldm", false),
+    ("ldmia", false),
+    ("ldmib", false),
+    ("ldmda", false),
+    ("ldmdb", false),
+    ("ldmfd", false),
+    ("ldmea", false),
+    ("stm", false),
+    ("stmia", false),
+    ("stmib", false),
+    ("stmda", false),
+    ("stmdb", false),
+    ("stmfd", false),
+    ("stmea", false),
+    ("push", false),
+    ("pop", false),
+    ("b", false),
+    ("bl", false),
+    ("bx", false),
+    ("blx", false),
+];
+/// Mnemonics taking neither suffix.
+const PLAIN_MNEMONICS: [&str; 4] = ["swi", "svc", "nop", "adr"];
+
+/// Splits a mnemonic into its base, condition code and whether it sets the flags, accepting
+/// both the `addeqs` and `addseq` orders.
+fn split_mnemonic(mnemonic: &str) -> Result<(&'static str, u32, bool), String> {
+    let condition = |suffix: &str| {
+        if suffix.is_empty() {
+            return Some(ALWAYS);
+        }
+        CONDITIONS
+            .iter()
+            .find(|(name, _)| *name == suffix)
+            .map(|(_, code)| *code)
+    };
+    if let Some(plain) = PLAIN_MNEMONICS.iter().find(|plain| **plain == mnemonic) {
+        return Ok((plain, ALWAYS, false));
+    }
+    for (base, takes_s) in MNEMONICS {
+        let Some(rest) = mnemonic.strip_prefix(base) else {
+            continue;
+        };
+        if let Some(code) = condition(rest) {
+            return Ok((base, code, false));
+        }
+        if !takes_s {
+            continue;
+        }
+        let with_s = rest
+            .strip_prefix('s')
+            .and_then(condition)
+            .or_else(|| rest.strip_suffix('s').and_then(condition));
+        if let Some(code) = with_s {
+            return Ok((base, code, true));
+        }
+    }
+    Err(format!("unknown instruction {mnemonic:?}"))
+}
+
+fn parse_reg(operand: &str) -> Result<u32, String> {
+    let operand = operand.trim();
+    match operand.to_ascii_lowercase().as_str() {
+        "sb" => Ok(9),
+        "sl" => Ok(10),
+        "fp" => Ok(11),
+        "ip" => Ok(12),
+        "sp" => Ok(13),
+        "lr" => Ok(14),
+        "pc" => Ok(15),
+        name => name
+            .strip_prefix('r')
+            .and_then(|number| number.parse::<u32>().ok())
+            .filter(|&number| number < 16)
+            .ok_or_else(|| format!("expected a register, found {operand:?}")),
+    }
+}
+
+fn parse_low_reg(operand: &str) -> Result<u32, String> {
+    let reg = parse_reg(operand)?;
+    if reg > 7 {
+        return Err(format!("only r0-r7 can be used here, found {operand:?}"));
+    }
+    Ok(reg)
+}
+
+/// Parses a `{r0-r3, lr}` register list into a mask of registers.
+fn parse_reg_list(operand: &str) -> Result<u32, String> {
+    let inner = operand
+        .trim()
+        .strip_prefix('{')
+        .and_then(|operand| operand.strip_suffix('}'))
+        .ok_or_else(|| format!("expected a register list, found {operand:?}"))?;
+    let mut mask = 0;
+    for item in inner
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        match item.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse_reg(first)?, parse_reg(last)?);
+                if first > last {
+                    return Err(format!("invalid register range {item:?}"));
+                }
+                for reg in first..=last {
+                    mask |= 1 << reg;
+                }
+            }
+            None => mask |= 1 << parse_reg(item)?,
+        }
+    }
+    if mask == 0 {
+        return Err("empty register list".to_owned());
+    }
+    Ok(mask)
+}
+
+fn operand<'o>(operands: &[&'o str], index: usize) -> Result<&'o str, String> {
+    operands
+        .get(index)
+        .copied()
+        .ok_or_else(|| "missing operand".to_owned())
+}
+
+fn expect_operand_count(operands: &[&str], counts: &[usize]) -> Result<(), String> {
+    if counts.contains(&operands.len()) {
+        Ok(())
+    } else {
+        Err(format!("wrong number of operands ({})", operands.len()))
+    }
+}
+
+fn parse_immediate(operand: &str, symbols: &Symbols) -> Result<i64, String> {
+    eval(operand.trim().strip_prefix('#').unwrap_or(operand), symbols)
+}
+
+fn is_immediate(operand: &str) -> bool {
+    operand.trim().starts_with('#')
+}
+
+/// Offset from the address given to a branch target, checking its alignment and range.
+fn branch_offset(
+    target: i64,
+    address: u32,
+    pipeline: i64,
+    alignment: i64,
+    bits: u32,
+) -> Result<i64, String> {
+    let offset = target - (address as i64 + pipeline);
+    if offset % alignment != 0 {
+        return Err(format!("misaligned branch target 0x{target:08X}"));
+    }
+    let limit = alignment << (bits - 1);
+    if !(-limit..limit).contains(&offset) {
+        return Err(format!("branch target 0x{target:08X} is out of range"));
+    }
+    Ok(offset)
+}
+
+/// Encodes a value as an ARM rotated 8-bit immediate.
+fn arm_immediate(value: u32) -> Option<u32> {
+    (0..16).find_map(|rotation| {
+        let imm8 = value.rotate_left(rotation * 2);
+        (imm8 <= 0xFF).then_some(rotation << 8 | imm8)
+    })
+}
+
+/// Encodes a shift applied to a register operand, such as `lsl #2` or `ror r3`.
+fn arm_shift(shift: &str, symbols: &Symbols, allow_register: bool) -> Result<u32, String> {
+    let shift = shift.trim().to_ascii_lowercase();
+    if shift == "rrx" {
+        return Ok(3 << 5);
+    }
+    let (kind, amount) = shift
+        .split_once(char::is_whitespace)
+        .ok_or_else(|| format!("invalid shift {shift:?}"))?;
+    let kind = match kind {
+        "lsl" | "asl" => 0,
+        "lsr" => 1,
+        "asr" => 2,
+        "ror" => 3,
+        _ => return Err(format!("invalid shift {shift:?}")),
+    };
+    if let Ok(reg) = parse_reg(amount) {
+        if !allow_register {
+            return Err("register-specified shifts can't be used here".to_owned());
+        }
+        return Ok(reg << 8 | kind << 5 | 1 << 4);
+    }
+    let amount = parse_immediate(amount, symbols)?;
+    let valid = match kind {
+        0 => (0..=31).contains(&amount),
+        3 => (1..=31).contains(&amount),
+        _ => (1..=32).contains(&amount),
+    };
+    if !valid {
+        return Err(format!("invalid shift amount {amount}"));
+    }
+    Ok(((amount as u32) & 31) << 7 | kind << 5)
+}
+
+/// The flexible second operand of ARM data processing instructions.
+enum Operand2 {
+    Immediate(u32),
+    Register(u32),
+}
+
+fn arm_operand2(operands: &[&str], symbols: &Symbols) -> Result<Operand2, String> {
+    match operands {
+        [value] if !is_register(value) => {
+            Ok(Operand2::Immediate(parse_immediate(value, symbols)? as u32))
+        }
+        [reg] => Ok(Operand2::Register(parse_reg(reg)?)),
+        [reg, shift] => Ok(Operand2::Register(
+            parse_reg(reg)? | arm_shift(shift, symbols, true)?,
+        )),
+        _ => Err(format!("wrong number of operands ({})", operands.len())),
+    }
+}
+
+fn is_register(operand: &str) -> bool {
+    parse_reg(operand).is_ok()
+}
+
+fn is_shift(operand: &str) -> bool {
+    let lowercase = operand.trim().to_ascii_lowercase();
+    lowercase == "rrx"
+        || ["lsl ", "asl ", "lsr ", "asr ", "ror "]
+            .iter()
+            .any(|kind| lowercase.starts_with(kind))
+}
+
+fn arm_data_processing(
+    base: &str,
+    condition: u32,
+    set_flags: bool,
+    operands: &[&str],
+    symbols: &Symbols,
+) -> Result<u32, String> {
+    let mut opcode = match base {
+        "and" => 0x0,
+        "eor" => 0x1,
+        "sub" => 0x2,
+        "rsb" => 0x3,
+        "add" => 0x4,
+        "adc" => 0x5,
+        "sbc" => 0x6,
+        "rsc" => 0x7,
+        "tst" => 0x8,
+        "teq" => 0x9,
+        "cmp" => 0xA,
+        "cmn" => 0xB,
+        "orr" => 0xC,
+        "mov" => 0xD,
+        "bic" => 0xE,
+        _ => 0xF,
+    };
+    let (rd, rn, rest) = match opcode {
+        0xD | 0xF => (parse_reg(operand(operands, 0)?)?, 0, &operands[1..]),
+        0x8..=0xB => (0, parse_reg(operand(operands, 0)?)?, &operands[1..]),
+        _ if operands.len() >= 3 && !is_shift(operands[2]) => (
+            parse_reg(operands[0])?,
+            parse_reg(operands[1])?,
+            &operands[2..],
+        ),
+        _ => {
+            let rd = parse_reg(operand(operands, 0)?)?;
+            (rd, rd, &operands[1..])
+        }
+    };
+    let set_flags = set_flags || (0x8..=0xB).contains(&opcode);
+    let operand2 = match arm_operand2(rest, symbols)? {
+        Operand2::Register(bits) => bits,
+        Operand2::Immediate(value) => {
+            // Some immediates only fit once the instruction is swapped for its counterpart.
+            let swapped = match opcode {
+                0xD => Some((0xF, !value)),
+                0xF => Some((0xD, !value)),
+                0x4 => Some((0x2, value.wrapping_neg())),
+                0x2 => Some((0x4, value.wrapping_neg())),
+                0xA => Some((0xB, value.wrapping_neg())),
+                0xB => Some((0xA, value.wrapping_neg())),
+                0x0 => Some((0xE, !value)),
+                0xE => Some((0x0, !value)),
+                0x5 => Some((0x6, !value)),
+                0x6 => Some((0x5, !value)),
+                _ => None,
+            };
+            let encoded = arm_immediate(value).or_else(|| {
+                let (swapped_opcode, swapped_value) = swapped?;
+                let encoded = arm_immediate(swapped_value)?;
+                opcode = swapped_opcode;
+                Some(encoded)
+            });
+            1 << 25
+                | encoded.ok_or_else(|| {
+                    format!("immediate 0x{value:X} can't be encoded as a rotated 8-bit value")
+                })?
+        }
+    };
+    Ok(condition << 28 | opcode << 21 | (set_flags as u32) << 20 | rn << 16 | rd << 12 | operand2)
+}
+
+/// The address operand of a load or store.
+struct AddressOperand<'o> {
+    rn: u32,
+    /// The offset operands: an immediate, or a register with an optional shift.
+    offset: Vec<&'o str>,
+    /// Whether the offset is applied before the access (`[rn, offset]`) rather than after it
+    /// (`[rn], offset`, which always writes back).
+    pre_indexed: bool,
+    writeback: bool,
+    /// Offset from the PC to a label, which is addressed relative to it.
+    pc_offset: Option<i64>,
+}
+
+/// Parses the address operand of a load or store: `[rn, offset]{!}`, `[rn], offset` or a
+/// label.
+fn parse_address<'o>(
+    operands: &[&'o str],
+    address: u32,
+    pipeline: i64,
+    symbols: &Symbols,
+) -> Result<AddressOperand<'o>, String> {
+    let first = operand(operands, 0)?;
+    if first.starts_with('=') {
+        return Err("literal pools are not supported; load the value with mov/orr instead".into());
+    }
+    let Some(bracketed) = first.strip_prefix('[') else {
+        let target = parse_immediate(first, symbols)?;
+        return Ok(AddressOperand {
+            rn: 15,
+            offset: Vec::new(),
+            pre_indexed: true,
+            writeback: false,
+            pc_offset: Some(target - (address as i64 + pipeline)),
+        });
+    };
+    let (inner, writeback) = match bracketed.strip_suffix('!') {
+        Some(inner) => (inner.trim_end(), true),
+        None => (bracketed, false),
+    };
+    let inner = inner
+        .strip_suffix(']')
+        .ok_or_else(|| format!("invalid address {first:?}"))?;
+    let parts = split_operands(inner);
+    let rn = parse_reg(operand(&parts, 0)?)?;
+    if operands.len() > 1 {
+        if parts.len() > 1 || writeback {
+            return Err(format!("invalid post-indexed address {first:?}"));
+        }
+        Ok(AddressOperand {
+            rn,
+            offset: operands[1..].to_vec(),
+            pre_indexed: false,
+            writeback: false,
+            pc_offset: None,
+        })
+    } else {
+        Ok(AddressOperand {
+            rn,
+            offset: parts[1..].to_vec(),
+            pre_indexed: true,
+            writeback,
+            pc_offset: None,
+        })
+    }
+}
+
+fn arm_load_store(
+    base: &str,
+    condition: u32,
+    operands: &[&str],
+    address: u32,
+    symbols: &Symbols,
+) -> Result<u32, String> {
+    let rd = parse_reg(operand(operands, 0)?)?;
+    let AddressOperand {
+        rn,
+        offset,
+        pre_indexed,
+        writeback,
+        pc_offset,
+    } = parse_address(&operands[1..], address, 8, symbols)?;
+    let load = base.starts_with("ldr");
+    let halfword = matches!(base, "ldrh" | "strh" | "ldrsb" | "ldrsh");
+
+    // The offset as a signed immediate, or as a register with its shift and sign.
+    let (immediate, register) = match (pc_offset, &offset[..]) {
+        (Some(pc_offset), _) => (Some(pc_offset), None),
+        (None, []) => (Some(0), None),
+        (None, [value])
+            if is_immediate(value) || !is_register(value.trim_start_matches(['-', '+'])) =>
+        {
+            (Some(parse_immediate(value, symbols)?), None)
+        }
+        (None, [reg, shift @ ..]) => {
+            let negative = reg.trim().starts_with('-');
+            let reg = parse_reg(reg.trim().trim_start_matches(['-', '+']))?;
+            let shift = match shift {
+                [] => 0,
+                [shift] if !halfword => arm_shift(shift, symbols, false)?,
+                _ => return Err("invalid offset".to_owned()),
+            };
+            (None, Some((reg | shift, negative)))
+        }
+    };
+    let indexing = (pre_indexed as u32) << 24 | (writeback as u32) << 21 | (load as u32) << 20;
+
+    if halfword {
+        let kind = match base {
+            "strh" | "ldrh" => 0xB0,
+            "ldrsb" => 0xD0,
+            _ => 0xF0,
+        };
+        let (up, offset_bits) = match (immediate, register) {
+            (Some(value), _) => {
+                if value.abs() > 0xFF {
+                    return Err(format!("offset {value} out of range (maximum: 255)"));
+                }
+                let magnitude = value.unsigned_abs() as u32;
+                (
+                    value >= 0,
+                    1 << 22 | (magnitude & 0xF0) << 4 | (magnitude & 0xF),
+                )
+            }
+            (None, Some((reg, negative))) => (!negative, reg),
+            (None, None) => unreachable!(),
+        };
+        return Ok(condition << 28
+            | indexing
+            | (up as u32) << 23
+            | rn << 16
+            | rd << 12
+            | kind
+            | offset_bits);
+    }
+
+    let byte = matches!(base, "ldrb" | "strb");
+    let (up, offset_bits) = match (immediate, register) {
+        (Some(value), _) => {
+            if value.abs() > 0xFFF {
+                return Err(format!("offset {value} out of range (maximum: 4095)"));
+            }
+            (value >= 0, value.unsigned_abs() as u32)
+        }
+        (None, Some((reg, negative))) => (!negative, 1 << 25 | reg),
+        (None, None) => unreachable!(),
+    };
+    Ok(condition << 28
+        | 1 << 26
+        | indexing
+        | (up as u32) << 23
+        | (byte as u32) << 22
+        | rn << 16
+        | rd << 12
+        | offset_bits)
+}
+
+fn arm_block_transfer(base: &str, condition: u32, operands: &[&str]) -> Result<u32, String> {
+    let (load, mode) = base.split_at(3);
+    let load = load == "ldm";
+    // Bits P & U of each addressing mode, including the stack-oriented aliases.
+    let (pre, up) = match (mode, load) {
+        ("" | "ia", _) | ("fd", true) | ("ea", false) => (false, true),
+        ("ib", _) | ("ed", true) | ("fa", false) => (true, true),
+        ("da", _) | ("fa", true) | ("ed", false) => (false, false),
+        _ => (true, false),
+    };
+    expect_operand_count(operands, &[2])?;
+    let (rn, writeback) = match operands[0].strip_suffix('!') {
+        Some(rn) => (parse_reg(rn)?, true),
+        None => (parse_reg(operands[0])?, false),
+    };
+    let (list, user_mode) = match operands[1].trim().strip_suffix('^') {
+        Some(list) => (list, true),
+        None => (operands[1], false),
+    };
+    Ok(condition << 28
+        | 0b100 << 25
+        | (pre as u32) << 24
+        | (up as u32) << 23
+        | (user_mode as u32) << 22
+        | (writeback as u32) << 21
+        | (load as u32) << 20
+        | rn << 16
+        | parse_reg_list(list)?)
+}
+
+fn encode_arm(
+    mnemonic: &str,
+    operands: &[&str],
+    address: u32,
+    symbols: &Symbols,
+) -> Result<u32, String> {
+    let (base, condition, set_flags) = split_mnemonic(mnemonic)?;
+    let target = |operands: &[&str]| {
+        expect_operand_count(operands, &[1])?;
+        parse_immediate(operands[0], symbols)
+    };
+    match base {
+        "and" | "eor" | "sub" | "rsb" | "add" | "adc" | "sbc" | "rsc" | "tst" | "teq" | "cmp"
+        | "cmn" | "orr" | "mov" | "bic" | "mvn" => {
+            arm_data_processing(base, condition, set_flags, operands, symbols)
+        }
+        "neg" => {
+            expect_operand_count(operands, &[2])?;
+            arm_data_processing(
+                "rsb",
+                condition,
+                set_flags,
+                &[operands[0], operands[1], "#0"],
+                symbols,
+            )
+        }
+        "lsl" | "lsr" | "asr" | "ror" => {
+            let (rd, rm, amount) = match operands {
+                [rd, amount] => (rd, rd, amount),
+                [rd, rm, amount] => (rd, rm, amount),
+                _ => return Err(format!("wrong number of operands ({})", operands.len())),
+            };
+            let amount = amount.trim().trim_start_matches('#');
+            let shift = format!(
+                "{base} {}",
+                if is_register(amount) {
+                    amount.to_owned()
+                } else {
+                    format!("#{amount}")
+                }
+            );
+            arm_data_processing("mov", condition, set_flags, &[rd, rm, &shift], symbols)
+        }
+        "mul" | "mla" => {
+            expect_operand_count(operands, &[if base == "mul" { 3 } else { 4 }])?;
+            let (rd, rm, rs) = (
+                parse_reg(operands[0])?,
+                parse_reg(operands[1])?,
+                parse_reg(operands[2])?,
+            );
+            let (accumulate, rn) = match operands.get(3) {
+                Some(rn) => (1, parse_reg(rn)?),
+                None => (0, 0),
+            };
+            Ok(condition << 28
+                | accumulate << 21
+                | (set_flags as u32) << 20
+                | rd << 16
+                | rn << 12
+                | rs << 8
+                | 0x90
+                | rm)
+        }
+        "umull" | "umlal" | "smull" | "smlal" => {
+            expect_operand_count(operands, &[4])?;
+            let kind = match base {
+                "umull" => 0x4,
+                "umlal" => 0x5,
+                "smull" => 0x6,
+                _ => 0x7,
+            };
+            let regs = operands
+                .iter()
+                .map(|reg| parse_reg(reg))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(condition << 28
+                | kind << 21
+                | (set_flags as u32) << 20
+                | regs[1] << 16
+                | regs[0] << 12
+                | regs[3] << 8
+                | 0x90
+                | regs[2])
+        }
+        "ldr" | "ldrb" | "ldrh" | "ldrsb" | "ldrsh" | "str" | "strb" | "strh" => {
+            arm_load_store(base, condition, operands, address, symbols)
+        }
+        "push" | "pop" => {
+            expect_operand_count(operands, &[1])?;
+            let base = if base == "push" { "stmdb" } else { "ldmia" };
+            arm_block_transfer(base, condition, &["sp!", operands[0]])
+        }
+        _ if base.starts_with("ldm") || base.starts_with("stm") => {
+            arm_block_transfer(base, condition, operands)
+        }
+        "b" | "bl" => {
+            let offset = branch_offset(target(operands)?, address, 8, 4, 24)?;
+            Ok(condition << 28
+                | 0b101 << 25
+                | ((base == "bl") as u32) << 24
+                | (offset as u32 >> 2) & 0xFF_FFFF)
+        }
+        "bx" => {
+            expect_operand_count(operands, &[1])?;
+            Ok(condition << 28 | 0x012F_FF10 | parse_reg(operands[0])?)
+        }
+        "blx" => {
+            expect_operand_count(operands, &[1])?;
+            if let Ok(reg) = parse_reg(operands[0]) {
+                return Ok(condition << 28 | 0x012F_FF30 | reg);
+            }
+            if condition != ALWAYS {
+                return Err("BLX to a label can't be conditional".to_owned());
+            }
+            let offset = branch_offset(target(operands)?, address, 8, 2, 25)?;
+            Ok(0xFA00_0000 | ((offset as u32 >> 1) & 1) << 24 | (offset as u32 >> 2) & 0xFF_FFFF)
+        }
+        "swi" | "svc" => {
+            let comment = target(operands)?;
+            Ok(condition << 28 | 0x0F00_0000 | (comment as u32) & 0xFF_FFFF)
+        }
+        "nop" => Ok(0xE1A0_0000),
+        "adr" => {
+            expect_operand_count(operands, &[2])?;
+            let offset = parse_immediate(operands[1], symbols)? - (address as i64 + 8);
+            let (opcode, magnitude) = if offset >= 0 {
+                ("add", offset)
+            } else {
+                ("sub", -offset)
+            };
+            arm_data_processing(
+                opcode,
+                condition,
+                false,
+                &[operands[0], "pc", &format!("#{magnitude}")],
+                symbols,
+            )
+        }
+        _ => Err(format!("{base} is not supported in ARM mode")),
+    }
+}
+
+fn encode_thumb(
+    mnemonic: &str,
+    operands: &[&str],
+    address: u32,
+    symbols: &Symbols,
+) -> Result<Vec<u16>, String> {
+    let (base, condition, _) = split_mnemonic(mnemonic)?;
+    if condition != ALWAYS && base != "b" {
+        return Err("only branches can be conditional in Thumb mode".to_owned());
+    }
+    let low = |index: usize| parse_low_reg(operand(operands, index)?);
+    let imm = |index: usize| parse_immediate(operand(operands, index)?, symbols);
+    let is_imm = |index: usize| {
+        operands
+            .get(index)
+            .is_some_and(|operand| !is_register(operand))
+    };
+    let ranged = |value: i64, max: i64, scale: i64| {
+        if value < 0 || value > max * scale || value % scale != 0 {
+            Err(format!("immediate {value} out of range"))
+        } else {
+            Ok((value / scale) as u16)
+        }
+    };
+    let alu = |opcode: u16, rd: u32, rm: u32| 0x4000 | opcode << 6 | (rm as u16) << 3 | rd as u16;
+    let hi_reg = |opcode: u16, rd: u32, rm: u32| {
+        0x4400 | opcode << 8 | ((rd & 8) as u16) << 4 | (rm as u16) << 3 | (rd & 7) as u16
+    };
+    let pc_relative =
+        |index: usize| -> Result<i64, String> { Ok(imm(index)? - ((address as i64 + 4) & !3)) };
+
+    let halfword = match base {
+        "lsl" | "lsr" | "asr" if operands.len() == 3 && is_imm(2) => {
+            let opcode = match base {
+                "lsl" => 0,
+                "lsr" => 1,
+                _ => 2,
+            };
+            let amount = imm(2)?;
+            let amount = match base {
+                "lsl" => ranged(amount, 31, 1)?,
+                _ if (1..=32).contains(&amount) => (amount & 31) as u16,
+                _ => return Err(format!("invalid shift amount {amount}")),
+            };
+            opcode << 11 | amount << 6 | (low(1)? as u16) << 3 | low(0)? as u16
+        }
+        "add" | "sub" if operands.len() == 3 => {
+            let (rd, rn) = (parse_reg(operands[0])?, parse_reg(operands[1])?);
+            let sub = base == "sub";
+            if is_imm(2) {
+                let value = if sub { -imm(2)? } else { imm(2)? };
+                match (rd, rn) {
+                    (13, 13) => {
+                        let opcode = if value >= 0 { 0xB000 } else { 0xB080 };
+                        opcode | ranged(value.abs(), 127, 4)?
+                    }
+                    (rd, 13 | 15) if rd < 8 && !sub => {
+                        let kind = if rn == 13 { 0xA800 } else { 0xA000 };
+                        kind | (rd as u16) << 8 | ranged(value, 255, 4)?
+                    }
+                    (rd, rn) if rd < 8 && rn < 8 => {
+                        let (opcode, magnitude) = if value >= 0 { (0, value) } else { (1, -value) };
+                        if magnitude <= 7 {
+                            0x1C00
+                                | opcode << 9
+                                | (magnitude as u16) << 6
+                                | (rn as u16) << 3
+                                | rd as u16
+                        } else if rd == rn {
+                            0x3000 | opcode << 11 | (rd as u16) << 8 | ranged(magnitude, 255, 1)?
+                        } else {
+                            return Err(format!("immediate {magnitude} out of range"));
+                        }
+                    }
+                    _ => return Err("only r0-r7 can be used here".to_owned()),
+                }
+            } else {
+                0x1800
+                    | (sub as u16) << 9
+                    | (low(2)? as u16) << 6
+                    | (parse_low_reg(operands[1])? as u16) << 3
+                    | parse_low_reg(operands[0])? as u16
+            }
+        }
+        "add" | "sub" => {
+            expect_operand_count(operands, &[2])?;
+            let rd = parse_reg(operands[0])?;
+            let sub = base == "sub";
+            if is_imm(1) {
+                let value = if sub { -imm(1)? } else { imm(1)? };
+                if rd == 13 {
+                    let opcode = if value >= 0 { 0xB000 } else { 0xB080 };
+                    opcode | ranged(value.abs(), 127, 4)?
+                } else {
+                    let (opcode, magnitude) = if value >= 0 { (0, value) } else { (1, -value) };
+                    0x3000
+                        | opcode << 11
+                        | (parse_low_reg(operands[0])? as u16) << 8
+                        | ranged(magnitude, 255, 1)?
+                }
+            } else {
+                let rm = parse_reg(operands[1])?;
+                if rd < 8 && rm < 8 {
+                    0x1800 | (sub as u16) << 9 | (rm as u16) << 6 | (rd as u16) << 3 | rd as u16
+                } else if !sub {
+                    hi_reg(0, rd, rm)
+                } else {
+                    return Err("only r0-r7 can be used here".to_owned());
+                }
+            }
+        }
+        "mov" => {
+            expect_operand_count(operands, &[2])?;
+            if is_imm(1) {
+                0x2000 | (low(0)? as u16) << 8 | ranged(imm(1)?, 255, 1)?
+            } else {
+                let (rd, rm) = (parse_reg(operands[0])?, parse_reg(operands[1])?);
+                if rd < 8 && rm < 8 {
+                    // Moves between low registers are encoded as adding 0.
+                    0x1C00 | (rm as u16) << 3 | rd as u16
+                } else {
+                    hi_reg(2, rd, rm)
+                }
+            }
+        }
+        "cmp" => {
+            expect_operand_count(operands, &[2])?;
+            if is_imm(1) {
+                0x2800 | (low(0)? as u16) << 8 | ranged(imm(1)?, 255, 1)?
+            } else {
+                let (rn, rm) = (parse_reg(operands[0])?, parse_reg(operands[1])?);
+                if rn < 8 && rm < 8 {
+                    alu(0xA, rn, rm)
+                } else {
+                    hi_reg(1, rn, rm)
+                }
+            }
+        }
+        "and" | "eor" | "lsl" | "lsr" | "asr" | "adc" | "sbc" | "ror" | "tst" | "neg" | "cmn"
+        | "orr" | "mul" | "bic" | "mvn" => {
+            let opcode = match base {
+                "and" => 0x0,
+                "eor" => 0x1,
+                "lsl" => 0x2,
+                "lsr" => 0x3,
+                "asr" => 0x4,
+                "adc" => 0x5,
+                "sbc" => 0x6,
+                "ror" => 0x7,
+                "tst" => 0x8,
+                "neg" => 0x9,
+                "cmn" => 0xB,
+                "orr" => 0xC,
+                "mul" => 0xD,
+                "bic" => 0xE,
+                _ => 0xF,
+            };
+            let rm = match operands.len() {
+                2 => low(1)?,
+                // Three-operand forms are accepted when the destination is also a source.
+                3 if operands[0].eq_ignore_ascii_case(operands[1]) => low(2)?,
+                3 if base == "mul" && operands[0].eq_ignore_ascii_case(operands[2]) => low(1)?,
+                _ => return Err(format!("wrong number of operands ({})", operands.len())),
+            };
+            alu(opcode, low(0)?, rm)
+        }
+        "bx" | "blx" if operands.len() == 1 && is_register(operands[0]) => {
+            let link = (base == "blx") as u16;
+            0x4700 | link << 7 | (parse_reg(operands[0])? as u16) << 3
+        }
+        "bl" | "blx" => {
+            expect_operand_count(operands, &[1])?;
+            let target = imm(0)?;
+            let offset = if base == "bl" {
+                branch_offset(target, address, 4, 2, 22)?
+            } else {
+                // BLX switches to ARM code, so the offset is from the PC aligned down to 4.
+                branch_offset(target, address & !3, 4, 4, 21)?
+            };
+            let high = 0xF000 | ((offset >> 12) & 0x7FF) as u16;
+            let low_half = if base == "bl" { 0xF800 } else { 0xE800 };
+            return Ok(vec![high, low_half | ((offset >> 1) & 0x7FF) as u16]);
+        }
+        "b" => {
+            expect_operand_count(operands, &[1])?;
+            if condition == ALWAYS {
+                let offset = branch_offset(imm(0)?, address, 4, 2, 11)?;
+                0xE000 | ((offset >> 1) & 0x7FF) as u16
+            } else {
+                let offset = branch_offset(imm(0)?, address, 4, 2, 8)?;
+                0xD000 | (condition as u16) << 8 | ((offset >> 1) & 0xFF) as u16
+            }
+        }
+        "ldr" | "str" | "ldrb" | "strb" | "ldrh" | "strh" | "ldrsb" | "ldrsh" => {
+            expect_operand_count(operands, &[2])?;
+            let rd = low(0)? as u16;
+            if !operands[1].starts_with('[') {
+                if base != "ldr" {
+                    return Err(format!("{base} can't load from a label"));
+                }
+                return Ok(vec![0x4800 | rd << 8 | ranged(pc_relative(1)?, 255, 4)?]);
+            }
+            let AddressOperand {
+                rn,
+                offset,
+                pre_indexed,
+                writeback,
+                ..
+            } = parse_address(&operands[1..], address, 4, symbols)?;
+            if !pre_indexed || writeback {
+                return Err("Thumb loads and stores can't write back".to_owned());
+            }
+            match &offset[..] {
+                [reg] if is_register(reg) => {
+                    let opcode = match base {
+                        "str" => 0x5000,
+                        "strh" => 0x5200,
+                        "strb" => 0x5400,
+                        "ldrsb" => 0x5600,
+                        "ldr" => 0x5800,
+                        "ldrh" => 0x5A00,
+                        "ldrb" => 0x5C00,
+                        _ => 0x5E00,
+                    };
+                    opcode | (parse_low_reg(reg)? as u16) << 6 | (rn as u16) << 3 | rd
+                }
+                [] | [_] => {
+                    let value = match offset.first() {
+                        Some(value) => parse_immediate(value, symbols)?,
+                        None => 0,
+                    };
+                    match (base, rn) {
+                        ("ldr" | "str", 13) => {
+                            let opcode = if base == "ldr" { 0x9800 } else { 0x9000 };
+                            opcode | rd << 8 | ranged(value, 255, 4)?
+                        }
+                        ("ldr", 15) => 0x4800 | rd << 8 | ranged(value, 255, 4)?,
+                        (_, rn) if rn < 8 => {
+                            let (opcode, scale) = match base {
+                                "str" => (0x6000, 4),
+                                "ldr" => (0x6800, 4),
+                                "strb" => (0x7000, 1),
+                                "ldrb" => (0x7800, 1),
+                                "strh" => (0x8000, 2),
+                                "ldrh" => (0x8800, 2),
+                                _ => return Err(format!("{base} only takes a register offset")),
+                            };
+                            opcode | ranged(value, 31, scale)? << 6 | (rn as u16) << 3 | rd
+                        }
+                        _ => return Err("only r0-r7 can be used here".to_owned()),
+                    }
+                }
+                _ => return Err("shifted offsets are not supported in Thumb mode".to_owned()),
+            }
+        }
+        "push" | "pop" => {
+            expect_operand_count(operands, &[1])?;
+            let mask = parse_reg_list(operands[0])?;
+            let extra = if base == "push" { 1 << 14 } else { 1 << 15 };
+            if mask & !(0xFF | extra) != 0 {
+                return Err(format!(
+                    "{base} only takes r0-r7 and {}",
+                    if base == "push" { "lr" } else { "pc" }
+                ));
+            }
+            let opcode = if base == "push" { 0xB400 } else { 0xBC00 };
+            opcode | ((mask & extra != 0) as u16) << 8 | (mask & 0xFF) as u16
+        }
+        "ldm" | "ldmia" | "ldmfd" | "stm" | "stmia" | "stmea" => {
+            expect_operand_count(operands, &[2])?;
+            let rn = operands[0]
+                .strip_suffix('!')
+                .ok_or("Thumb block transfers always write back")?;
+            let mask = parse_reg_list(operands[1])?;
+            if mask > 0xFF {
+                return Err("only r0-r7 can be used here".to_owned());
+            }
+            let opcode = if base.starts_with("ldm") {
+                0xC800
+            } else {
+                0xC000
+            };
+            opcode | (parse_low_reg(rn)? as u16) << 8 | mask as u16
+        }
+        "swi" | "svc" => 0xDF00 | ranged(imm(0)?, 255, 1)?,
+        "nop" => 0x46C0,
+        "adr" => {
+            expect_operand_count(operands, &[2])?;
+            0xA000 | (low(0)? as u16) << 8 | ranged(pc_relative(1)?, 255, 4)?
+        }
+        _ => return Err(format!("{base} is not supported in Thumb mode")),
+    };
+    Ok(vec![halfword])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::freespace::{FreeKind, FreeRegion};
+
+    fn words(chunks: &[Chunk]) -> Vec<u32> {
+        chunks
+            .iter()
+            .flat_map(|chunk| chunk.data.chunks(4))
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect()
+    }
+
+    fn arm(mnemonic: &str, operands: &str, address: u32) -> Result<u32, String> {
+        encode_arm(
+            mnemonic,
+            &split_operands(operands),
+            address,
+            &Symbols::new(),
+        )
+    }
+
+    fn thumb(mnemonic: &str, operands: &str, address: u32) -> Result<Vec<u16>, String> {
+        encode_thumb(
+            mnemonic,
+            &split_operands(operands),
+            address,
+            &Symbols::new(),
+        )
+    }
+
+    #[test]
+    fn splits_conditions_and_s_suffixes() {
+        for (mnemonic, expected) in [
+            ("b", ("b", ALWAYS, false)),
+            ("bls", ("b", 0x9, false)),
+            ("bleq", ("bl", 0x0, false)),
+            ("bl", ("bl", ALWAYS, false)),
+            ("ldrhi", ("ldr", 0x8, false)),
+            ("ldrh", ("ldrh", ALWAYS, false)),
+            ("movs", ("mov", ALWAYS, true)),
+            ("addeqs", ("add", 0x0, true)),
+            ("addseq", ("add", 0x0, true)),
+            ("subsal", ("sub", ALWAYS, true)),
+        ] {
+            assert_eq!(split_mnemonic(mnemonic), Ok(expected), "{mnemonic}");
+        }
+        assert!(split_mnemonic("cmps").is_err());
+        assert!(split_mnemonic("bxx").is_err());
+        assert!(split_mnemonic("frob").is_err());
+    }
+
+    #[test]
+    fn encodes_arm() {
+        for (mnemonic, operands, expected) in [
+            ("mov", "r0, #1", 0xE3A00001),
+            ("movs", "r1, r2", 0xE1B01002),
+            ("add", "r0, r1, r2", 0xE0810002),
+            ("addeq", "r0, r0, #0x100", 0x02800C01),
+            ("subs", "r3, r3, #1", 0xE2533001),
+            ("cmp", "r0, #0", 0xE3500000),
+            ("mov", "r0, r1, lsl #2", 0xE1A00101),
+            ("ldr", "r0, [r1, #4]", 0xE5910004),
+            ("ldrhi", "r0, [r1]", 0x85910000),
+            ("ldrh", "r0, [r1, #2]", 0xE1D100B2),
+            ("strb", "r2, [r3], #1", 0xE4C32001),
+            ("push", "{r4-r6, lr}", 0xE92D4070),
+            ("pop", "{r4-r6, pc}", 0xE8BD8070),
+            ("mul", "r0, r1, r2", 0xE0000291),
+            ("bx", "lr", 0xE12FFF1E),
+            ("blx", "r3", 0xE12FFF33),
+            ("swi", "#5", 0xEF000005),
+            ("nop", "", 0xE1A00000),
+        ] {
+            assert_eq!(
+                arm(mnemonic, operands, 0x02000000),
+                Ok(expected),
+                "{mnemonic} {operands}"
+            );
+        }
+    }
+
+    #[test]
+    fn encodes_thumb() {
+        for (mnemonic, operands, expected) in [
+            ("mov", "r0, #1", 0x2001),
+            ("add", "r0, r1, #2", 0x1C88),
+            ("add", "sp, #8", 0xB002),
+            ("sub", "sp, #8", 0xB082),
+            ("lsl", "r0, r1, #2", 0x0088),
+            ("cmp", "r0, r8", 0x4540),
+            ("mov", "r8, r0", 0x4680),
+            ("ldr", "r0, [r1, #4]", 0x6848),
+            ("push", "{r4, lr}", 0xB510),
+            ("pop", "{r4, pc}", 0xBD10),
+            ("bx", "lr", 0x4770),
+            ("nop", "", 0x46C0),
+        ] {
+            assert_eq!(
+                thumb(mnemonic, operands, 0x02000000),
+                Ok(vec![expected]),
+                "{mnemonic} {operands}"
+            );
+        }
+        assert!(thumb("addeq", "r0, r1", 0x02000000).is_err());
+    }
+
+    #[test]
+    fn encodes_branches() {
+        for (mnemonic, operands, address, expected) in [
+            ("b", "0x02000008", 0x02000000, 0xEA000000),
+            ("bls", "0x02000000", 0x02000000, 0x9AFFFFFE),
+            ("bleq", "0x02000010", 0x02000000, 0x0B000002),
+            ("bl", "0x01FFFFF8", 0x02000000, 0xEBFFFFFC),
+            // Switching to Thumb code, the halfword of the target goes in the H bit.
+            ("blx", "0x0200000A", 0x02000000, 0xFB000000),
+            ("blx", "0x02000108", 0x02000000, 0xFA000040),
+        ] {
+            assert_eq!(
+                arm(mnemonic, operands, address),
+                Ok(expected),
+                "{mnemonic} {operands}"
+            );
+        }
+        for (mnemonic, operands, address, expected) in [
+            ("b", "0x02000004", 0x02000000, vec![0xE000]),
+            ("beq", "0x02000000", 0x02000000, vec![0xD0FE]),
+            ("bl", "0x02000004", 0x02000000, vec![0xF000, 0xF800]),
+            ("bl", "0x02001000", 0x02000000, vec![0xF000, 0xFFFE]),
+            ("bl", "0x01FFF004", 0x02000000, vec![0xF7FF, 0xF800]),
+            // Switching to ARM code, the offset is taken from the word holding the BLX.
+            ("blx", "0x02000008", 0x02000000, vec![0xF000, 0xE802]),
+            ("blx", "0x02000008", 0x02000002, vec![0xF000, 0xE802]),
+        ] {
+            assert_eq!(
+                thumb(mnemonic, operands, address),
+                Ok(expected),
+                "{mnemonic} {operands} at {address:#X}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_branches() {
+        let out_of_range = |result: Result<_, String>| {
+            result.is_err_and(|message| message.contains("out of range"))
+        };
+        let misaligned =
+            |result: Result<_, String>| result.is_err_and(|message| message.contains("misaligned"));
+        assert!(out_of_range(arm("b", "0x04000008", 0x02000000).map(|_| ())));
+        assert!(out_of_range(
+            arm("bl", "0x00000000", 0x02000000).map(|_| ())
+        ));
+        assert!(misaligned(arm("b", "0x02000002", 0x02000000).map(|_| ())));
+        assert!(misaligned(arm("blx", "0x02000009", 0x02000000).map(|_| ())));
+        assert!(arm("blxeq", "0x02000008", 0x02000000).is_err());
+        assert!(out_of_range(
+            thumb("beq", "0x02000200", 0x02000000).map(|_| ())
+        ));
+        assert!(out_of_range(
+            thumb("b", "0x02001000", 0x02000000).map(|_| ())
+        ));
+        assert!(out_of_range(
+            thumb("bl", "0x02400004", 0x02000000).map(|_| ())
+        ));
+        assert!(misaligned(thumb("b", "0x02000005", 0x02000000).map(|_| ())));
+        assert!(misaligned(
+            thumb("blx", "0x02000006", 0x02000000).map(|_| ())
+        ));
+    }
+
+    #[test]
+    fn assembles_labels() {
+        let source = "
+            .org 0x02000000
+            start:
+                b end
+                nop
+            end: bl start
+            .thumb
+                bl start
+        ";
+        let chunks = assemble(source, &SymbolMap::default()).unwrap();
+        assert_eq!(chunks[0].address, 0x02000000);
+        assert_eq!(chunks[0].line, 2);
+        assert_eq!(
+            words(&chunks),
+            [0xEA000000, 0xE1A00000, 0xEBFFFFFC, 0xFFF8_F7FF]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_labels() {
+        let line = |source: &str| match assemble(source, &SymbolMap::default()) {
+            Err(AssembleError::Syntax { line, .. }) => Some(line),
+            _ => None,
+        };
+        assert_eq!(line("nop"), Some(1));
+        assert_eq!(line(".org 0x02000000\nb missing"), Some(2));
+        assert_eq!(line(".org 0x02000000\na:\nnop\na: nop"), Some(4));
+        assert_eq!(line(".org 0x02000000\n.freespace\nnop"), Some(2));
+        assert_eq!(line(".org 0x02000000\nfrob r0"), Some(2));
+    }
+
+    #[test]
+    fn sizes_free_space_blocks() {
+        let source = [
+            ".thumb",
+            "push {lr}",
+            "bl 0x02000000",
+            ".align 8",
+            ".word 1, 2",
+            ".asciz \"ab\"",
+            ".hword 3",
+            ".space 3",
+            ".arm",
+            "nop",
+            ".freespace",
+            "nop",
+        ];
+        let statements = source
+            .iter()
+            .enumerate()
+            .map(|(index, text)| (index + 1, Ok(parse_statement(text).unwrap())))
+            .collect::<Vec<_>>();
+        // 2 + 4, aligned to 8, + 8 + 3 + 2 + 3 + 4; the next `.freespace` ends the block.
+        assert_eq!(
+            free_space_block(&statements, Mode::Arm, &Symbols::new()).unwrap(),
+            (28, 8)
+        );
+        assert_eq!(
+            free_space_block(&statements[9..], Mode::Arm, &Symbols::new()).unwrap(),
+            (4, 4)
+        );
+    }
+
+    #[test]
+    fn places_free_space_blocks() {
+        let region = FreeRegion {
+            binary: Binary::Arm9,
+            address: 0x02100000,
+            size: 0x10,
+            kind: FreeKind::Padding,
+        };
+        let source = "
+            .freespace
+            .thumb
+            func: push {lr}
+                bl func
+            .freespace
+            .byte 1
+        ";
+        let mut allocator = Allocator::new(vec![region]);
+        let chunks =
+            assemble_with_allocator(source, &SymbolMap::default(), &mut allocator).unwrap();
+        let placed = chunks
+            .iter()
+            .map(|chunk| (chunk.address, chunk.data.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(placed, [(0x02100000, 6), (0x02100008, 1)]);
+        assert_eq!(allocator.used(), 9);
+
+        let source = ".freespace\n.space 0x20";
+        let mut allocator = Allocator::new(vec![region]);
+        assert!(matches!(
+            assemble_with_allocator(source, &SymbolMap::default(), &mut allocator),
+            Err(AssembleError::OutOfFreeSpace {
+                line: 1,
+                size: 0x20
+            })
+        ));
+    }
+}
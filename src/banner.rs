@@ -0,0 +1,605 @@
+use std::{io::Cursor, path::Path};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use thiserror::Error;
+
+use crate::{manifest, rom};
+
+/// Width & height of the icon, in pixels.
+pub const ICON_SIZE: usize = 32;
+/// Number of colors in an icon palette, the first of which is transparent.
+const ICON_COLORS: usize = 16;
+/// Size of a 4bpp icon bitmap.
+const BITMAP_SIZE: usize = ICON_SIZE * ICON_SIZE / 2;
+/// Size of an icon palette.
+const PALETTE_SIZE: usize = ICON_COLORS * 2;
+const ICON_BITMAP_OFFSET: usize = 0x20;
+const ICON_PALETTE_OFFSET: usize = ICON_BITMAP_OFFSET + BITMAP_SIZE;
+/// Version of the banners of DSi-enhanced games, which can hold an animated icon.
+const ANIMATED_VERSION: u16 = 0x0103;
+/// Number of bitmaps, and of palettes, an animated icon can use.
+pub const ANIMATION_SLOTS: usize = 8;
+const ANIMATED_BITMAPS_OFFSET: usize = 0x1240;
+const ANIMATED_PALETTES_OFFSET: usize = ANIMATED_BITMAPS_OFFSET + ANIMATION_SLOTS * BITMAP_SIZE;
+const SEQUENCE_OFFSET: usize = ANIMATED_PALETTES_OFFSET + ANIMATION_SLOTS * PALETTE_SIZE;
+/// Number of entries of the animation sequence, which ends early at the first empty one.
+const SEQUENCE_LEN: usize = 64;
+/// Checksums of the banner: where each is stored, the version from which it's present and the
+/// range it covers.
+const CRCS: [(usize, u16, std::ops::Range<usize>); 4] = [
+    (0x2, 0x0001, 0x20..0x840),
+    (0x4, 0x0002, 0x20..0x940),
+    (0x6, 0x0003, 0x20..0xA40),
+    (0x8, ANIMATED_VERSION, 0x1240..0x23C0),
+];
+
+/// Name of the static icon exported, as an indexed PNG.
+pub const ICON_FILE_NAME: &str = "icon.png";
+/// Name of the description of an animated icon exported: its palettes and sequence.
+pub const ANIMATION_FILE_NAME: &str = "icon.json";
+/// Name of the preview of an animated icon exported, which isn't imported back.
+pub const PREVIEW_FILE_NAME: &str = "icon.apng";
+
+#[derive(Error, Debug)]
+pub enum BannerError {
+    #[error("banner is truncated")]
+    Truncated,
+    #[error("only DSi banners (version 0x103) can hold an animated icon, but this one is version 0x{0:X}")]
+    NotAnimatable(u16),
+    #[error("an animated icon has {ANIMATION_SLOTS} bitmaps and palettes (found: {bitmaps} bitmaps and {palettes} palettes)")]
+    SlotCount { bitmaps: usize, palettes: usize },
+    #[error("frame {index} of the animation is invalid: {reason}")]
+    InvalidFrame { index: usize, reason: &'static str },
+    #[error("the animation has {0} frames, but banners hold at most {SEQUENCE_LEN}")]
+    TooManyFrames(usize),
+}
+
+#[derive(Error, Debug)]
+pub enum ImportIconError {
+    #[error("failed to decode PNG")]
+    Decoding(#[from] png::DecodingError),
+    #[error("image is {width}x{height} pixels, but icons are {ICON_SIZE}x{ICON_SIZE}")]
+    SizeMismatch { width: usize, height: usize },
+    #[error("image uses palette index {0}, but icons have {ICON_COLORS} colors")]
+    IndexOutOfRange(u8),
+    #[error("image has {0} opaque colors, but icons can only have 15; save it as an indexed PNG with 16 colors")]
+    TooManyColors(usize),
+    #[error("bitmaps of animated icons must be indexed PNGs, as their palettes are given by {ANIMATION_FILE_NAME}")]
+    NotIndexed,
+    #[error("invalid color {0:?}; colors are given as #RRGGBB")]
+    InvalidColor(String),
+}
+
+/// A 32x32 icon: its pixels as palette indices, and its palette in BGR555 format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Icon {
+    pub pixels: Vec<u8>,
+    pub palette: [u16; ICON_COLORS],
+}
+
+/// An entry of the animation sequence of a DSi icon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IconFrame {
+    pub bitmap: u8,
+    pub palette: u8,
+    /// How long the frame is shown, in 60ths of a second.
+    pub duration: u8,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub flip_h: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub flip_v: bool,
+}
+
+impl IconFrame {
+    fn parse(value: u16) -> Self {
+        Self {
+            duration: value as u8,
+            bitmap: (value >> 8) as u8 & 7,
+            palette: (value >> 11) as u8 & 7,
+            flip_h: value & 0x4000 != 0,
+            flip_v: value & 0x8000 != 0,
+        }
+    }
+
+    fn to_raw(self) -> u16 {
+        self.duration as u16
+            | (self.bitmap as u16) << 8
+            | (self.palette as u16) << 11
+            | (self.flip_h as u16) << 14
+            | (self.flip_v as u16) << 15
+    }
+}
+
+/// The animated icon of a DSi banner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnimatedIcon {
+    /// Pixels of each bitmap, as palette indices.
+    pub bitmaps: Vec<Vec<u8>>,
+    pub palettes: Vec<[u16; ICON_COLORS]>,
+    pub sequence: Vec<IconFrame>,
+}
+
+/// How an animated icon is exported: its palettes as `#RRGGBB` colors and its sequence. The
+/// bitmaps are exported alongside as indexed PNGs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AnimationFile {
+    palettes: Vec<Vec<String>>,
+    sequence: Vec<IconFrame>,
+}
+
+pub fn version(banner: &[u8]) -> Result<u16, BannerError> {
+    let bytes = banner.get(..2).ok_or(BannerError::Truncated)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_bitmap(data: &[u8]) -> Vec<u8> {
+    // Tiles of 8x8 pixels, 2 pixels a byte, the low nibble first.
+    let mut pixels = vec![0; ICON_SIZE * ICON_SIZE];
+    for (index, byte) in data.iter().enumerate() {
+        let tile = index / 32;
+        let (tile_x, tile_y) = (tile % 4 * 8, tile / 4 * 8);
+        let (x, y) = (index % 4 * 2, index % 32 / 4);
+        let pixel = (tile_y + y) * ICON_SIZE + tile_x + x;
+        pixels[pixel] = byte & 0xF;
+        pixels[pixel + 1] = byte >> 4;
+    }
+    pixels
+}
+
+fn write_bitmap(pixels: &[u8], data: &mut [u8]) {
+    for (index, byte) in data.iter_mut().enumerate() {
+        let tile = index / 32;
+        let (tile_x, tile_y) = (tile % 4 * 8, tile / 4 * 8);
+        let (x, y) = (index % 4 * 2, index % 32 / 4);
+        let pixel = (tile_y + y) * ICON_SIZE + tile_x + x;
+        *byte = pixels[pixel] | pixels[pixel + 1] << 4;
+    }
+}
+
+fn read_palette(data: &[u8]) -> [u16; ICON_COLORS] {
+    let mut palette = [0; ICON_COLORS];
+    for (color, bytes) in palette.iter_mut().zip(data.chunks_exact(2)) {
+        *color = u16::from_le_bytes([bytes[0], bytes[1]]) & 0x7FFF;
+    }
+    palette
+}
+
+fn write_palette(palette: &[u16; ICON_COLORS], data: &mut [u8]) {
+    for (color, bytes) in palette.iter().zip(data.chunks_exact_mut(2)) {
+        bytes.copy_from_slice(&color.to_le_bytes());
+    }
+}
+
+/// The static icon of a banner, which every console shows.
+pub fn icon(banner: &[u8]) -> Result<Icon, BannerError> {
+    let data = banner
+        .get(ICON_BITMAP_OFFSET..ICON_PALETTE_OFFSET + PALETTE_SIZE)
+        .ok_or(BannerError::Truncated)?;
+    Ok(Icon {
+        pixels: read_bitmap(&data[..BITMAP_SIZE]),
+        palette: read_palette(&data[BITMAP_SIZE..]),
+    })
+}
+
+/// The animated icon of a DSi banner, or `None` if the banner has none.
+pub fn animated_icon(banner: &[u8]) -> Result<Option<AnimatedIcon>, BannerError> {
+    if version(banner)? != ANIMATED_VERSION {
+        return Ok(None);
+    }
+    let data = banner
+        .get(ANIMATED_BITMAPS_OFFSET..SEQUENCE_OFFSET + SEQUENCE_LEN * 2)
+        .ok_or(BannerError::Truncated)?;
+    let sequence = data[SEQUENCE_OFFSET - ANIMATED_BITMAPS_OFFSET..]
+        .chunks_exact(2)
+        .map(|bytes| IconFrame::parse(u16::from_le_bytes([bytes[0], bytes[1]])))
+        .take_while(|frame| frame.duration != 0)
+        .collect::<Vec<_>>();
+    if sequence.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(AnimatedIcon {
+        bitmaps: data[..ANIMATION_SLOTS * BITMAP_SIZE]
+            .chunks_exact(BITMAP_SIZE)
+            .map(read_bitmap)
+            .collect(),
+        palettes: data[ANIMATION_SLOTS * BITMAP_SIZE..SEQUENCE_OFFSET - ANIMATED_BITMAPS_OFFSET]
+            .chunks_exact(PALETTE_SIZE)
+            .map(read_palette)
+            .collect(),
+        sequence,
+    }))
+}
+
+/// Recalculates the checksums of a banner, which must be done after modifying it.
+pub fn fix_crcs(banner: &mut [u8]) -> Result<(), BannerError> {
+    let version = version(banner)?;
+    for (offset, since, range) in CRCS {
+        if version < since {
+            continue;
+        }
+        let crc = rom::crc16(banner.get(range).ok_or(BannerError::Truncated)?);
+        banner[offset..offset + 2].copy_from_slice(&crc.to_le_bytes());
+    }
+    Ok(())
+}
+
+/// Replaces the static icon of a banner, updating its checksums.
+pub fn set_icon(banner: &mut [u8], icon: &Icon) -> Result<(), BannerError> {
+    let data = banner
+        .get_mut(ICON_BITMAP_OFFSET..ICON_PALETTE_OFFSET + PALETTE_SIZE)
+        .ok_or(BannerError::Truncated)?;
+    let (bitmap, palette) = data.split_at_mut(BITMAP_SIZE);
+    write_bitmap(&icon.pixels, bitmap);
+    write_palette(&icon.palette, palette);
+    fix_crcs(banner)
+}
+
+/// Replaces the animated icon of a DSi banner, updating its checksums.
+pub fn set_animated_icon(banner: &mut [u8], icon: &AnimatedIcon) -> Result<(), BannerError> {
+    let version = version(banner)?;
+    if version != ANIMATED_VERSION {
+        return Err(BannerError::NotAnimatable(version));
+    }
+    if icon.bitmaps.len() != ANIMATION_SLOTS || icon.palettes.len() != ANIMATION_SLOTS {
+        return Err(BannerError::SlotCount {
+            bitmaps: icon.bitmaps.len(),
+            palettes: icon.palettes.len(),
+        });
+    }
+    if icon.sequence.len() > SEQUENCE_LEN {
+        return Err(BannerError::TooManyFrames(icon.sequence.len()));
+    }
+    for (index, frame) in icon.sequence.iter().enumerate() {
+        let reason = if frame.duration == 0 {
+            "its duration is 0, which would end the animation"
+        } else if frame.bitmap as usize >= ANIMATION_SLOTS {
+            "its bitmap is past the last one"
+        } else if frame.palette as usize >= ANIMATION_SLOTS {
+            "its palette is past the last one"
+        } else {
+            continue;
+        };
+        return Err(BannerError::InvalidFrame { index, reason });
+    }
+
+    let data = banner
+        .get_mut(ANIMATED_BITMAPS_OFFSET..SEQUENCE_OFFSET + SEQUENCE_LEN * 2)
+        .ok_or(BannerError::Truncated)?;
+    let (bitmaps, rest) = data.split_at_mut(ANIMATION_SLOTS * BITMAP_SIZE);
+    let (palettes, sequence) = rest.split_at_mut(ANIMATION_SLOTS * PALETTE_SIZE);
+    for (pixels, data) in icon
+        .bitmaps
+        .iter()
+        .zip(bitmaps.chunks_exact_mut(BITMAP_SIZE))
+    {
+        write_bitmap(pixels, data);
+    }
+    for (palette, data) in icon
+        .palettes
+        .iter()
+        .zip(palettes.chunks_exact_mut(PALETTE_SIZE))
+    {
+        write_palette(palette, data);
+    }
+    sequence.fill(0);
+    for (frame, bytes) in icon.sequence.iter().zip(sequence.chunks_exact_mut(2)) {
+        bytes.copy_from_slice(&frame.to_raw().to_le_bytes());
+    }
+    fix_crcs(banner)
+}
+
+fn to_rgb(color: u16) -> [u8; 3] {
+    let expand = |channel: u16| ((channel << 3) | (channel >> 2)) as u8;
+    [
+        expand(color & 0x1F),
+        expand((color >> 5) & 0x1F),
+        expand((color >> 10) & 0x1F),
+    ]
+}
+
+fn to_bgr555([r, g, b]: [u8; 3]) -> u16 {
+    (r >> 3) as u16 | ((g >> 3) as u16) << 5 | ((b >> 3) as u16) << 10
+}
+
+fn color_to_hex(color: u16) -> String {
+    let [r, g, b] = to_rgb(color);
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+fn color_from_hex(text: &str) -> Result<u16, ImportIconError> {
+    let invalid = || ImportIconError::InvalidColor(text.to_owned());
+    let hex = text
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6)
+        .ok_or_else(invalid)?;
+    let value = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+    Ok(to_bgr555([
+        (value >> 16) as u8,
+        (value >> 8) as u8,
+        value as u8,
+    ]))
+}
+
+/// Encodes an icon bitmap as an indexed PNG, with its first color transparent.
+fn to_png(pixels: &[u8], palette: &[u16; ICON_COLORS]) -> Result<Vec<u8>, png::EncodingError> {
+    let mut png_data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png_data, ICON_SIZE as u32, ICON_SIZE as u32);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(
+        palette
+            .iter()
+            .flat_map(|&color| to_rgb(color))
+            .collect::<Vec<_>>(),
+    );
+    encoder.set_trns(vec![0]);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    writer.finish()?;
+    Ok(png_data)
+}
+
+/// Draws a frame of an animated icon in RGBA, with transparent pixels left clear.
+fn render_frame(icon: &AnimatedIcon, frame: &IconFrame) -> Vec<u8> {
+    let pixels = &icon.bitmaps[frame.bitmap as usize];
+    let palette = &icon.palettes[frame.palette as usize];
+    let mut rgba = Vec::with_capacity(ICON_SIZE * ICON_SIZE * 4);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let source_x = if frame.flip_h { ICON_SIZE - 1 - x } else { x };
+            let source_y = if frame.flip_v { ICON_SIZE - 1 - y } else { y };
+            match pixels[source_y * ICON_SIZE + source_x] {
+                0 => rgba.extend_from_slice(&[0; 4]),
+                index => {
+                    rgba.extend_from_slice(&to_rgb(palette[index as usize]));
+                    rgba.push(0xFF);
+                }
+            }
+        }
+    }
+    rgba
+}
+
+/// Encodes the animation of an icon as an APNG, looping forever as the console shows it.
+fn to_apng(icon: &AnimatedIcon) -> Result<Vec<u8>, png::EncodingError> {
+    let mut png_data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png_data, ICON_SIZE as u32, ICON_SIZE as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(icon.sequence.len() as u32, 0)?;
+    let mut writer = encoder.write_header()?;
+    for frame in &icon.sequence {
+        writer.set_frame_delay(frame.duration as u16, 60)?;
+        writer.set_blend_op(png::BlendOp::Source)?;
+        writer.write_image_data(&render_frame(icon, frame))?;
+    }
+    writer.finish()?;
+    Ok(png_data)
+}
+
+/// Exports the icons of a banner, as files named as they are to be written: the static icon,
+/// and for animated DSi icons, every bitmap, the palettes and sequence, and an APNG preview.
+pub fn export_icon(banner: &[u8]) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let icon = icon(banner)?;
+    let mut files = vec![(
+        ICON_FILE_NAME.to_owned(),
+        to_png(&icon.pixels, &icon.palette)?,
+    )];
+    let Some(animated) = animated_icon(banner)? else {
+        return Ok(files);
+    };
+    for (index, pixels) in animated.bitmaps.iter().enumerate() {
+        // Bitmaps are drawn with the palette they're first shown with.
+        let palette = animated
+            .sequence
+            .iter()
+            .find(|frame| frame.bitmap as usize == index)
+            .map_or(0, |frame| frame.palette as usize);
+        files.push((
+            bitmap_file_name(index),
+            to_png(pixels, &animated.palettes[palette])?,
+        ));
+    }
+    let animation = AnimationFile {
+        palettes: animated
+            .palettes
+            .iter()
+            .map(|palette| palette.iter().map(|&color| color_to_hex(color)).collect())
+            .collect(),
+        sequence: animated.sequence.clone(),
+    };
+    files.push((
+        ANIMATION_FILE_NAME.to_owned(),
+        serde_json::to_vec_pretty(&animation)?,
+    ));
+    files.push((PREVIEW_FILE_NAME.to_owned(), to_apng(&animated)?));
+    Ok(files)
+}
+
+/// Name of bitmap `index` of an animated icon exported.
+pub fn bitmap_file_name(index: usize) -> String {
+    format!("icon_bitmap{index}.png")
+}
+
+/// Decodes an icon PNG to palette indices, along with its palette if it's indexed. PNGs that
+/// aren't are given a palette of their colors, transparent pixels taking index 0.
+fn decode_png(png_data: &[u8]) -> Result<(Vec<u8>, Option<Vec<u16>>), ImportIconError> {
+    let reader = png::Decoder::new(Cursor::new(png_data)).read_info()?;
+    let info = reader.info();
+    let (width, height) = (info.width as usize, info.height as usize);
+    if (width, height) != (ICON_SIZE, ICON_SIZE) {
+        return Err(ImportIconError::SizeMismatch { width, height });
+    }
+    if info.color_type == png::ColorType::Indexed {
+        let depth = info.bit_depth as usize;
+        let palette = info
+            .palette
+            .as_deref()
+            .unwrap_or_default()
+            .chunks_exact(3)
+            .map(|rgb| to_bgr555([rgb[0], rgb[1], rgb[2]]))
+            .collect();
+        let mut reader = reader;
+        let mut buffer = vec![0; reader.output_buffer_size().unwrap_or_default()];
+        let output = reader.next_frame(&mut buffer)?;
+        // Indices narrower than a byte are packed into each row, the leftmost pixel highest.
+        let mut pixels = Vec::with_capacity(ICON_SIZE * ICON_SIZE);
+        for row in buffer.chunks_exact(output.line_size).take(ICON_SIZE) {
+            for x in 0..ICON_SIZE {
+                let bit = x * depth;
+                let index = (row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1) as u8;
+                if index as usize >= ICON_COLORS {
+                    return Err(ImportIconError::IndexOutOfRange(index));
+                }
+                pixels.push(index);
+            }
+        }
+        return Ok((pixels, Some(palette)));
+    }
+
+    let mut decoder = png::Decoder::new(Cursor::new(png_data));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size().unwrap_or_default()];
+    let output = reader.next_frame(&mut buffer)?;
+    let channels = output.color_type.samples();
+    let mut colors = vec![0];
+    let mut pixels = Vec::with_capacity(ICON_SIZE * ICON_SIZE);
+    for pixel in buffer[..output.buffer_size()].chunks_exact(channels) {
+        let (rgb, alpha) = match *pixel {
+            [gray] => ([gray; 3], 0xFF),
+            [gray, alpha] => ([gray; 3], alpha),
+            [r, g, b] => ([r, g, b], 0xFF),
+            [r, g, b, alpha, ..] => ([r, g, b], alpha),
+            [] => ([0; 3], 0),
+        };
+        if alpha < 0x80 {
+            pixels.push(0);
+            continue;
+        }
+        let color = to_bgr555(rgb);
+        let index = match colors[1..].iter().position(|&known| known == color) {
+            Some(position) => position + 1,
+            None => {
+                colors.push(color);
+                colors.len() - 1
+            }
+        };
+        pixels.push(index.min(u8::MAX as usize) as u8);
+    }
+    if colors.len() > ICON_COLORS {
+        return Err(ImportIconError::TooManyColors(colors.len() - 1));
+    }
+    Ok((pixels, Some(colors)))
+}
+
+fn read_file(dir: &Path, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let path = dir.join(name);
+    if !path.exists() {
+        return Ok(None);
+    }
+    fs::read(&path)
+        .map(Some)
+        .with_context(|| format!("failed to read {path:?}"))
+}
+
+/// SHA-256 of the files of an exported icon that are imported back, to tell whether they were
+/// edited.
+pub fn export_hash(files: &[(String, Vec<u8>)]) -> String {
+    let mut contents = Vec::new();
+    for (name, data) in files.iter().filter(|(name, _)| name != PREVIEW_FILE_NAME) {
+        contents.extend_from_slice(manifest::sha256_hex(name.as_bytes()).as_bytes());
+        contents.extend_from_slice(manifest::sha256_hex(data).as_bytes());
+    }
+    manifest::sha256_hex(&contents)
+}
+
+/// Reads the files of an icon exported to the directory given that are imported back.
+pub fn read_export(dir: &Path) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let names = [ICON_FILE_NAME.to_owned()]
+        .into_iter()
+        .chain((0..ANIMATION_SLOTS).map(bitmap_file_name))
+        .chain([ANIMATION_FILE_NAME.to_owned()]);
+    let mut files = Vec::new();
+    for name in names {
+        if let Some(data) = read_file(dir, &name)? {
+            files.push((name, data));
+        }
+    }
+    Ok(files)
+}
+
+/// Imports an icon exported with [`export_icon`] into a banner. The animated icon is only
+/// imported if its palettes and sequence are given, and then needs every bitmap.
+pub fn import_icon(banner: &mut [u8], files: &[(String, Vec<u8>)]) -> anyhow::Result<()> {
+    let find = |name: &str| {
+        files
+            .iter()
+            .find(|(file_name, _)| file_name == name)
+            .map(|(_, data)| data.as_slice())
+    };
+    if let Some(png_data) = find(ICON_FILE_NAME) {
+        let (pixels, palette) =
+            decode_png(png_data).with_context(|| format!("failed to import {ICON_FILE_NAME}"))?;
+        let mut icon = Icon {
+            pixels,
+            palette: [0; ICON_COLORS],
+        };
+        for (color, &new_color) in icon.palette.iter_mut().zip(palette.iter().flatten()) {
+            *color = new_color;
+        }
+        set_icon(banner, &icon)?;
+    }
+
+    let Some(animation_data) = find(ANIMATION_FILE_NAME) else {
+        return Ok(());
+    };
+    let animation: AnimationFile = serde_json::from_slice(animation_data)
+        .with_context(|| format!("failed to parse {ANIMATION_FILE_NAME}"))?;
+    let palettes = animation
+        .palettes
+        .iter()
+        .map(|colors| {
+            let mut palette = [0; ICON_COLORS];
+            if colors.len() != ICON_COLORS {
+                anyhow::bail!(
+                    "palettes of {ANIMATION_FILE_NAME} have {ICON_COLORS} colors, but one has {}",
+                    colors.len()
+                );
+            }
+            for (color, text) in palette.iter_mut().zip(colors) {
+                *color = color_from_hex(text)?;
+            }
+            Ok(palette)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let bitmaps = (0..ANIMATION_SLOTS)
+        .map(|index| {
+            let name = bitmap_file_name(index);
+            let png_data = find(&name)
+                .with_context(|| format!("{name} is missing, but animated icons need it"))?;
+            match decode_png(png_data).with_context(|| format!("failed to import {name}"))? {
+                (pixels, Some(_)) if png_is_indexed(png_data) => Ok(pixels),
+                _ => Err(ImportIconError::NotIndexed)
+                    .with_context(|| format!("failed to import {name}")),
+            }
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    set_animated_icon(
+        banner,
+        &AnimatedIcon {
+            bitmaps,
+            palettes,
+            sequence: animation.sequence,
+        },
+    )?;
+    Ok(())
+}
+
+fn png_is_indexed(png_data: &[u8]) -> bool {
+    png::Decoder::new(Cursor::new(png_data))
+        .read_info()
+        .is_ok_and(|reader| reader.info().color_type == png::ColorType::Indexed)
+}
@@ -0,0 +1,205 @@
+use thiserror::Error;
+
+use crate::lz::{self, CompressionLevel, Token, MIN_MATCH_LEN};
+
+#[derive(Error, Debug)]
+pub enum BlzDecompressionError {
+    #[error("data too small to hold a BLZ footer (found: {size} bytes, minimum: 8 bytes)")]
+    TooSmall { size: usize },
+    #[error("invalid BLZ footer (compressed size: 0x{compressed_size:X}, footer size: 0x{footer_size:X}, data size: 0x{data_size:X})")]
+    InvalidFooter {
+        compressed_size: usize,
+        footer_size: usize,
+        data_size: usize,
+    },
+    #[error("compressed data ends too early")]
+    Truncated,
+    #[error("error while referencing past data")]
+    CannotReferencePastData,
+}
+
+#[derive(Error, Debug)]
+pub enum BlzCompressionError {
+    #[error("compressing the data wouldn't make it any smaller")]
+    Incompressible,
+    #[error("compressed data too large (found: {size} bytes, maximum: 0xFFFFFF bytes)")]
+    TooLarge { size: usize },
+}
+
+const MAX_MATCH_LEN: usize = 0xF + MIN_MATCH_LEN;
+/// Shortest distance a match can reach back to, as distances are stored minus 3.
+const MIN_DISTANCE: usize = 3;
+/// Size of a BLZ match in bits, including its flag bit.
+const MATCH_BITS: usize = 17;
+/// Size of the footer without padding: the compressed size and footer size, followed by how
+/// much the data grows when decompressed.
+const FOOTER_SIZE: usize = 8;
+
+/// Decompresses BLZ ("backwards LZ") data, as used by compressed overlays and ARM9 binaries.
+///
+/// BLZ data is decompressed from its end towards its start, so that it can be done in place.
+/// Data whose footer says it doesn't grow is returned as-is.
+pub fn decompress_blz(data: &[u8]) -> Result<Vec<u8>, BlzDecompressionError> {
+    if data.len() < FOOTER_SIZE {
+        return Err(BlzDecompressionError::TooSmall { size: data.len() });
+    }
+    let growth = u32::from_le_bytes(data[data.len() - 4..].try_into().unwrap()) as usize;
+    if growth == 0 {
+        return Ok(data.to_vec());
+    }
+    let footer = u32::from_le_bytes(data[data.len() - 8..data.len() - 4].try_into().unwrap());
+    let compressed_size = (footer & 0xFFFFFF) as usize;
+    let footer_size = (footer >> 24) as usize;
+    if footer_size < FOOTER_SIZE || footer_size > compressed_size || compressed_size > data.len() {
+        return Err(BlzDecompressionError::InvalidFooter {
+            compressed_size,
+            footer_size,
+            data_size: data.len(),
+        });
+    }
+
+    // Everything before the compressed data is stored as-is.
+    let raw_size = data.len() - compressed_size;
+    let mut output = vec![0; data.len() + growth];
+    output[..raw_size].copy_from_slice(&data[..raw_size]);
+    let mut read = raw_size + compressed_size - footer_size;
+    let mut written = output.len();
+    let mut next_byte = || -> Result<u8, BlzDecompressionError> {
+        if read == raw_size {
+            return Err(BlzDecompressionError::Truncated);
+        }
+        read -= 1;
+        Ok(data[read])
+    };
+
+    let mut flags = 0;
+    let mut mask = 0u8;
+    while written > raw_size {
+        mask >>= 1;
+        if mask == 0 {
+            flags = next_byte()?;
+            mask = 0x80;
+        }
+        if flags & mask == 0 {
+            written -= 1;
+            output[written] = next_byte()?;
+        } else {
+            let pointer_data = u16::from_be_bytes([next_byte()?, next_byte()?]);
+            let length = ((pointer_data >> 12) as usize + MIN_MATCH_LEN).min(written - raw_size);
+            let distance = (pointer_data & 0xFFF) as usize + MIN_DISTANCE;
+            for _ in 0..length {
+                written -= 1;
+                let Some(&byte) = output.get(written + distance) else {
+                    return Err(BlzDecompressionError::CannotReferencePastData);
+                };
+                output[written] = byte;
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// Compresses data with the BLZ algorithm.
+///
+/// Only as much of the end of the data is compressed as can be decompressed in place without
+/// overwriting compressed data that's yet to be read; the rest is stored as-is.
+pub fn compress_blz(data: &[u8], level: CompressionLevel) -> Result<Vec<u8>, BlzCompressionError> {
+    let reversed = data.iter().rev().copied().collect::<Vec<_>>();
+    let tokens = match level {
+        CompressionLevel::Fast => lz::greedy_parse(&reversed, MAX_MATCH_LEN, MIN_DISTANCE),
+        CompressionLevel::Best => {
+            lz::optimal_parse(&reversed, MAX_MATCH_LEN, MIN_DISTANCE, |_| MATCH_BITS)
+        }
+    };
+
+    // The stream is written as it's read when decompressing, and reversed afterwards. It's cut
+    // where the most bytes are saved, which is also where decompressing it never overtakes the
+    // compressed data left to read.
+    let mut stream = Vec::with_capacity(data.len() + data.len() / 8);
+    let mut decompressed_size = 0;
+    // Bytes of the data compressed and bytes of the stream they take, where the stream is cut.
+    let mut cut = (0, 0);
+    for group in tokens.chunks(8) {
+        let flag_byte_idx = stream.len();
+        stream.push(0);
+        for (index, token) in group.iter().enumerate() {
+            match *token {
+                Token::Literal(byte) => {
+                    stream.push(byte);
+                    decompressed_size += 1;
+                }
+                Token::Match { len, distance } => {
+                    stream[flag_byte_idx] |= 0x80 >> index;
+                    let pointer_data = ((len - MIN_MATCH_LEN) << 12) | (distance - MIN_DISTANCE);
+                    stream.extend_from_slice(&(pointer_data as u16).to_be_bytes());
+                    decompressed_size += len;
+                }
+            }
+            if decompressed_size > stream.len() && decompressed_size - stream.len() > cut.0 - cut.1
+            {
+                cut = (decompressed_size, stream.len());
+            }
+        }
+    }
+    let (compressed_data_size, stream_size) = cut;
+    stream.truncate(stream_size);
+    stream.reverse();
+
+    let raw_size = data.len() - compressed_data_size;
+    let padding = (4 - (raw_size + stream_size) % 4) % 4;
+    let footer_size = FOOTER_SIZE + padding;
+    let Some(growth) = (compressed_data_size - stream_size).checked_sub(footer_size) else {
+        return Err(BlzCompressionError::Incompressible);
+    };
+    if growth == 0 {
+        return Err(BlzCompressionError::Incompressible);
+    }
+    if stream_size + footer_size > 0xFFFFFF {
+        return Err(BlzCompressionError::TooLarge {
+            size: stream_size + footer_size,
+        });
+    }
+
+    let mut output = Vec::with_capacity(raw_size + stream_size + footer_size);
+    output.extend_from_slice(&data[..raw_size]);
+    output.extend_from_slice(&stream);
+    output.resize(output.len() + padding, 0xFF);
+    let footer = (stream_size + footer_size) as u32 | (footer_size as u32) << 24;
+    output.extend_from_slice(&footer.to_le_bytes());
+    output.extend_from_slice(&(growth as u32).to_le_bytes());
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lz::samples::noise;
+
+    #[test]
+    fn round_trips_at_every_level() {
+        let noise = noise(0x800, 0x1234_5678);
+        let samples = [
+            include_bytes!("blz.rs").to_vec(),
+            vec![0; 0x3000],
+            [&noise[..], &[0; 0x400], &noise[..0x200]].concat(),
+            [&[0; 0x400], &noise[..]].concat(),
+        ];
+        for data in samples {
+            for level in [CompressionLevel::Fast, CompressionLevel::Best] {
+                let compressed = compress_blz(&data, level).unwrap();
+                assert!(compressed.len() < data.len());
+                assert_eq!(compressed.len() % 4, 0);
+                assert_eq!(decompress_blz(&compressed).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn refuses_incompressible_data() {
+        let data = (0..=255u8).collect::<Vec<_>>();
+        assert!(matches!(
+            compress_blz(&data, CompressionLevel::Fast),
+            Err(BlzCompressionError::Incompressible)
+        ));
+    }
+}
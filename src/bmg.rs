@@ -0,0 +1,434 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use thiserror::Error;
+
+use crate::control_codes::{ControlCodes, EncodeControlCodeError};
+
+const BMG_MAGIC: &[u8; 8] = b"MESGbmg1";
+const INF1_MAGIC: &[u8; 4] = b"INF1";
+const DAT1_MAGIC: &[u8; 4] = b"DAT1";
+const HEADER_SIZE: usize = 0x20;
+/// Alignment of the size of each section.
+const SECTION_ALIGNMENT: usize = 0x20;
+/// Character starting an escape sequence, followed by the size in bytes of the whole sequence.
+const ESCAPE: u16 = 0x1A;
+/// Prefix of the escapes BMG escape sequences are exported as, such as `{bmg:01 00 00}`.
+const ESCAPE_PREFIX: &str = "{bmg:";
+
+#[derive(Error, Debug)]
+pub enum ParseBmgError {
+    #[error("magic number does not match (expected: MESGbmg1)")]
+    MagicNumberMismatch,
+    #[error("BMG data is truncated")]
+    Truncated,
+    #[error("required section {0} not found")]
+    MissingSection(&'static str),
+    #[error("unknown BMG encoding {0}")]
+    UnknownEncoding(u8),
+    #[error("message {index} points outside of the message data")]
+    InvalidOffset { index: usize },
+    #[error("message {index} is not terminated")]
+    UnterminatedMessage { index: usize },
+    #[error("message {index} is not valid {encoding}")]
+    InvalidEncoding {
+        index: usize,
+        encoding: &'static str,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum BuildBmgError {
+    #[error("failed to encode control codes in message {index}")]
+    ControlCode {
+        index: usize,
+        #[source]
+        source: EncodeControlCodeError,
+    },
+    #[error("message {index} contains characters that can't be encoded as {encoding}")]
+    Unencodable {
+        index: usize,
+        encoding: &'static str,
+    },
+    #[error("invalid escape sequence {escape:?} in message {index}")]
+    InvalidEscape { index: usize, escape: String },
+}
+
+/// Character encodings of BMG files, given by their header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BmgEncoding {
+    Cp1252,
+    Utf16,
+    ShiftJis,
+    Utf8,
+}
+
+impl BmgEncoding {
+    fn from_raw(value: u8) -> Result<Self, ParseBmgError> {
+        match value {
+            // Encoding 0 is treated by games as CP1252 too.
+            0 | 1 => Ok(BmgEncoding::Cp1252),
+            2 => Ok(BmgEncoding::Utf16),
+            3 => Ok(BmgEncoding::ShiftJis),
+            4 => Ok(BmgEncoding::Utf8),
+            _ => Err(ParseBmgError::UnknownEncoding(value)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            BmgEncoding::Cp1252 => "CP1252",
+            BmgEncoding::Utf16 => "UTF-16",
+            BmgEncoding::ShiftJis => "Shift-JIS",
+            BmgEncoding::Utf8 => "UTF-8",
+        }
+    }
+
+    fn unit_size(self) -> usize {
+        match self {
+            BmgEncoding::Utf16 => 2,
+            BmgEncoding::Cp1252 | BmgEncoding::ShiftJis | BmgEncoding::Utf8 => 1,
+        }
+    }
+
+    /// Decodes text without escape sequences or terminator.
+    fn decode(
+        self,
+        data: &[u8],
+        control_codes: &ControlCodes,
+        index: usize,
+    ) -> Result<String, ParseBmgError> {
+        let invalid = ParseBmgError::InvalidEncoding {
+            index,
+            encoding: self.name(),
+        };
+        match self {
+            BmgEncoding::Utf16 => {
+                let terminated = data.iter().copied().chain([0, 0]).collect::<Vec<_>>();
+                control_codes.decode(&terminated).map_err(|_| invalid)
+            }
+            BmgEncoding::Cp1252 => Ok(encoding_rs::WINDOWS_1252
+                .decode_without_bom_handling(data)
+                .0
+                .into_owned()),
+            BmgEncoding::ShiftJis => encoding_rs::SHIFT_JIS
+                .decode_without_bom_handling_and_without_replacement(data)
+                .map(|text| text.into_owned())
+                .ok_or(invalid),
+            BmgEncoding::Utf8 => std::str::from_utf8(data)
+                .map(str::to_owned)
+                .map_err(|_| invalid),
+        }
+    }
+
+    /// Encodes text without escape sequences, with no terminator.
+    fn encode(
+        self,
+        text: &str,
+        control_codes: &ControlCodes,
+        index: usize,
+    ) -> Result<Vec<u8>, BuildBmgError> {
+        let unencodable = BuildBmgError::Unencodable {
+            index,
+            encoding: self.name(),
+        };
+        let bytes = match self {
+            BmgEncoding::Utf16 => {
+                let mut bytes = control_codes
+                    .encode(text)
+                    .map_err(|source| BuildBmgError::ControlCode { index, source })?;
+                bytes.truncate(bytes.len() - 2);
+                bytes
+            }
+            BmgEncoding::Cp1252 | BmgEncoding::ShiftJis => {
+                let encoding = if self == BmgEncoding::Cp1252 {
+                    encoding_rs::WINDOWS_1252
+                } else {
+                    encoding_rs::SHIFT_JIS
+                };
+                let (bytes, _, had_errors) = encoding.encode(text);
+                if had_errors {
+                    return Err(unencodable);
+                }
+                bytes.into_owned()
+            }
+            BmgEncoding::Utf8 => text.as_bytes().to_vec(),
+        };
+        if bytes
+            .chunks(self.unit_size())
+            .any(|unit| unit.iter().all(|&byte| byte == 0))
+        {
+            return Err(unencodable);
+        }
+        Ok(bytes)
+    }
+}
+
+/// A message file used by many first-party games: an INF1 section with an entry for each
+/// message, pointing to its text in the DAT1 section.
+///
+/// Text is decoded in the encoding of the file. Escape sequences, which start with 0x1A and
+/// change colors, insert values or pause the text, are exported as `{bmg:XX XX ...}` with the
+/// bytes that follow their size. In UTF-16 files, the rest of the control codes are escaped as
+/// described by [`ControlCodes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bmg {
+    pub encoding: BmgEncoding,
+    pub messages: Vec<String>,
+    /// Bytes of the INF1 entry of each message following the offset of its text, such as its
+    /// text box style. Messages added past the original ones get zeroed attributes.
+    pub attributes: Vec<Vec<u8>>,
+    /// The header, kept as-is apart from the file size and section count.
+    header: Vec<u8>,
+    /// The bytes of the INF1 section header following the message count and entry size.
+    info_header: Vec<u8>,
+    entry_size: usize,
+    /// The sections of the file in order, with the contents of the ones other than INF1 and
+    /// DAT1, such as MID1 message IDs, kept as-is.
+    sections: Vec<([u8; 4], Vec<u8>)>,
+}
+
+/// Checks whether the data given starts with a BMG header.
+pub fn is_bmg(data: &[u8]) -> bool {
+    data.starts_with(BMG_MAGIC)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, ParseBmgError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseBmgError::Truncated)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, ParseBmgError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseBmgError::Truncated)
+}
+
+impl Bmg {
+    pub fn parse(data: &[u8], control_codes: &ControlCodes) -> Result<Self, ParseBmgError> {
+        if !is_bmg(data) {
+            return Err(ParseBmgError::MagicNumberMismatch);
+        }
+        let header = data
+            .get(..HEADER_SIZE)
+            .ok_or(ParseBmgError::Truncated)?
+            .to_vec();
+        let section_count = u32_at(data, 0xC)? as usize;
+        let encoding = BmgEncoding::from_raw(header[0x10])?;
+
+        let mut sections = Vec::new();
+        let mut offset = HEADER_SIZE;
+        for _ in 0..section_count {
+            let magic: [u8; 4] = data
+                .get(offset..offset + 4)
+                .ok_or(ParseBmgError::Truncated)?
+                .try_into()
+                .unwrap();
+            let size = u32_at(data, offset + 4)? as usize;
+            let contents = data
+                .get(offset..offset + size.max(8))
+                .ok_or(ParseBmgError::Truncated)?;
+            sections.push((magic, contents.to_vec()));
+            offset += size.max(8);
+        }
+        let section = |magic: &[u8; 4], name| {
+            sections
+                .iter()
+                .find(|(section_magic, _)| section_magic == magic)
+                .map(|(_, contents)| contents.as_slice())
+                .ok_or(ParseBmgError::MissingSection(name))
+        };
+        let info = section(INF1_MAGIC, "INF1")?;
+        let text_data = &section(DAT1_MAGIC, "DAT1")?[8..];
+
+        let message_count = u16_at(info, 0x8)? as usize;
+        let entry_size = (u16_at(info, 0xA)? as usize).max(4);
+        let info_header = info.get(0xC..0x10).ok_or(ParseBmgError::Truncated)?;
+        let mut messages = Vec::with_capacity(message_count);
+        let mut attributes = Vec::with_capacity(message_count);
+        for index in 0..message_count {
+            let entry = 0x10 + index * entry_size;
+            let text_offset = u32_at(info, entry)? as usize;
+            attributes.push(
+                info.get(entry + 4..entry + entry_size)
+                    .ok_or(ParseBmgError::Truncated)?
+                    .to_vec(),
+            );
+            let text = text_data
+                .get(text_offset..)
+                .ok_or(ParseBmgError::InvalidOffset { index })?;
+            messages.push(decode_message(text, encoding, control_codes, index)?);
+        }
+        Ok(Bmg {
+            encoding,
+            messages,
+            attributes,
+            header,
+            info_header: info_header.to_vec(),
+            entry_size,
+            sections: sections
+                .into_iter()
+                .map(|(magic, contents)| {
+                    let kept = if &magic == INF1_MAGIC || &magic == DAT1_MAGIC {
+                        Vec::new()
+                    } else {
+                        contents
+                    };
+                    (magic, kept)
+                })
+                .collect(),
+        })
+    }
+
+    /// Replaces the messages of the file, keeping the rest of it.
+    pub fn with_messages(self, messages: Vec<String>) -> Self {
+        Self { messages, ..self }
+    }
+
+    /// Builds the file back. Identical messages share their text if `deduplicate` is set; empty
+    /// messages always do.
+    pub fn to_bytes(
+        &self,
+        control_codes: &ControlCodes,
+        deduplicate: bool,
+    ) -> Result<Vec<u8>, BuildBmgError> {
+        // Text starts with an empty message, which empty messages point to.
+        let unit_size = self.encoding.unit_size();
+        let mut text_data = vec![0; unit_size];
+        let mut offsets = BTreeMap::new();
+        offsets.insert(vec![0; unit_size], 0);
+        let mut text_offsets = Vec::with_capacity(self.messages.len());
+        for (index, message) in self.messages.iter().enumerate() {
+            let encoded = encode_message(message, self.encoding, control_codes, index)?;
+            if let Some(&offset) = offsets
+                .get(&encoded)
+                .filter(|&&offset| deduplicate || offset == 0)
+            {
+                text_offsets.push(offset);
+                continue;
+            }
+            let offset = text_data.len();
+            text_data.extend_from_slice(&encoded);
+            offsets.insert(encoded, offset);
+            text_offsets.push(offset);
+        }
+
+        let mut data = self.header.clone();
+        for (magic, contents) in &self.sections {
+            let mut section = match magic {
+                magic if magic == INF1_MAGIC => {
+                    let mut info = vec![0; 8];
+                    info.extend_from_slice(&(self.messages.len() as u16).to_le_bytes());
+                    info.extend_from_slice(&(self.entry_size as u16).to_le_bytes());
+                    info.extend_from_slice(&self.info_header);
+                    for (index, &text_offset) in text_offsets.iter().enumerate() {
+                        let mut entry = (text_offset as u32).to_le_bytes().to_vec();
+                        if let Some(attributes) = self.attributes.get(index) {
+                            entry.extend_from_slice(attributes);
+                        }
+                        entry.resize(self.entry_size, 0);
+                        info.extend_from_slice(&entry);
+                    }
+                    info
+                }
+                magic if magic == DAT1_MAGIC => [vec![0; 8], text_data.clone()].concat(),
+                _ => {
+                    data.extend_from_slice(contents);
+                    continue;
+                }
+            };
+            section.resize(section.len().next_multiple_of(SECTION_ALIGNMENT), 0);
+            section[..4].copy_from_slice(magic);
+            let size = section.len() as u32;
+            section[4..8].copy_from_slice(&size.to_le_bytes());
+            data.extend_from_slice(&section);
+        }
+        let file_size = data.len() as u32;
+        data[0x8..0xC].copy_from_slice(&file_size.to_le_bytes());
+        data[0xC..0x10].copy_from_slice(&(self.sections.len() as u32).to_le_bytes());
+        Ok(data)
+    }
+}
+
+/// Decodes a terminated message from the start of `data`, exporting its escape sequences.
+fn decode_message(
+    data: &[u8],
+    encoding: BmgEncoding,
+    control_codes: &ControlCodes,
+    index: usize,
+) -> Result<String, ParseBmgError> {
+    let unit_size = encoding.unit_size();
+    let unit_at = |offset: usize| {
+        let bytes = data
+            .get(offset..offset + unit_size)
+            .ok_or(ParseBmgError::UnterminatedMessage { index })?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0, |unit, &byte| unit << 8 | byte as u16))
+    };
+    let mut output = String::new();
+    let mut run_start = 0;
+    let mut offset = 0;
+    loop {
+        let unit = unit_at(offset)?;
+        if unit != 0 && unit != ESCAPE {
+            offset += unit_size;
+            continue;
+        }
+        output.push_str(&encoding.decode(&data[run_start..offset], control_codes, index)?);
+        if unit == 0 {
+            return Ok(output);
+        }
+        let size = *data
+            .get(offset + unit_size)
+            .ok_or(ParseBmgError::UnterminatedMessage { index })? as usize;
+        let escape = data
+            .get(offset + unit_size + 1..offset + size.max(unit_size + 1))
+            .ok_or(ParseBmgError::UnterminatedMessage { index })?;
+        output.push_str(ESCAPE_PREFIX);
+        for (position, byte) in escape.iter().enumerate() {
+            if position > 0 {
+                output.push(' ');
+            }
+            let _ = write!(output, "{byte:02X}");
+        }
+        output.push('}');
+        offset += size.max(unit_size + 1);
+        run_start = offset;
+    }
+}
+
+/// Encodes a message to its terminated text, turning its `{bmg:...}` escapes back into escape
+/// sequences.
+fn encode_message(
+    message: &str,
+    encoding: BmgEncoding,
+    control_codes: &ControlCodes,
+    index: usize,
+) -> Result<Vec<u8>, BuildBmgError> {
+    let unit_size = encoding.unit_size();
+    let mut output = Vec::new();
+    let mut rest = message;
+    while let Some(start) = rest.find(ESCAPE_PREFIX) {
+        output.extend(encoding.encode(&rest[..start], control_codes, index)?);
+        let escape = &rest[start..];
+        let invalid = || BuildBmgError::InvalidEscape {
+            index,
+            escape: escape.to_owned(),
+        };
+        let end = escape.find('}').ok_or_else(invalid)?;
+        let bytes = escape[ESCAPE_PREFIX.len()..end]
+            .split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16).map_err(|_| invalid()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let size = u8::try_from(unit_size + 1 + bytes.len()).map_err(|_| invalid())?;
+        output.extend_from_slice(&ESCAPE.to_le_bytes()[..unit_size]);
+        output.push(size);
+        output.extend(bytes);
+        rest = &escape[end + 1..];
+    }
+    output.extend(encoding.encode(rest, control_codes, index)?);
+    output.extend(std::iter::repeat_n(0, unit_size));
+    Ok(output)
+}
@@ -0,0 +1,363 @@
+use thiserror::Error;
+
+use crate::diff::{find_operations, Operation, SourceIndex};
+
+pub const MAGIC: &[u8; 4] = b"BPS1";
+/// Size of the source, target & patch checksums at the end of a patch.
+const FOOTER_SIZE: usize = 12;
+
+const SOURCE_READ: usize = 0;
+const TARGET_READ: usize = 1;
+const SOURCE_COPY: usize = 2;
+const TARGET_COPY: usize = 3;
+
+#[derive(Error, Debug)]
+pub enum ApplyBpsError {
+    #[error("not a BPS patch (magic number does not match)")]
+    MagicNumberMismatch,
+    #[error("patch is truncated")]
+    Truncated,
+    #[error("patch is corrupted (its checksum does not match)")]
+    PatchChecksumMismatch,
+    #[error("the ROM given is already patched")]
+    AlreadyPatched,
+    #[error("the patch expects a base ROM of 0x{expected:X} bytes, but the ROM given has 0x{found:X} bytes (is this the right base ROM?)")]
+    SourceSizeMismatch { expected: usize, found: usize },
+    #[error("the ROM given is not the patch's base ROM (its CRC32 is {found:08X}, expected {expected:08X})")]
+    SourceChecksumMismatch { expected: u32, found: u32 },
+    #[error("invalid action at offset 0x{offset:X} of the patch")]
+    InvalidAction { offset: usize },
+    #[error("the patched ROM's checksum does not match")]
+    TargetChecksumMismatch,
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut idx = 0;
+    while idx < 256 {
+        let mut crc = idx as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[idx] = crc;
+        idx += 1;
+    }
+    table
+};
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        (crc >> 8) ^ CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize]
+    })
+}
+
+/// Writes a BPS variable-length integer: little-endian groups of 7 bits, with the top bit set on
+/// the last byte. Each group is offset so that every number has a single encoding.
+fn write_number(output: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let group = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            output.push(group | 0x80);
+            return;
+        }
+        output.push(group);
+        value -= 1;
+    }
+}
+
+fn write_action(output: &mut Vec<u8>, action: usize, len: usize) {
+    write_number(output, ((len - 1) << 2) | action);
+}
+
+/// Writes the offset of a copy relative to the end of the previous copy of its kind.
+fn write_relative_offset(output: &mut Vec<u8>, relative_offset: usize, address: usize) {
+    let value = if address >= relative_offset {
+        (address - relative_offset) << 1
+    } else {
+        ((relative_offset - address) << 1) | 1
+    };
+    write_number(output, value);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.offset..self.offset.checked_add(len)?)?;
+        self.offset += len;
+        Some(bytes)
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let mut value = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.bytes(1)?[0];
+            value = value.checked_add((byte as usize & 0x7F).checked_mul(shift)?)?;
+            if byte & 0x80 != 0 {
+                return Some(value);
+            }
+            shift = shift.checked_mul(0x80)?;
+            value = value.checked_add(shift)?;
+        }
+    }
+}
+
+/// Creates a BPS patch turning `source` into `target`.
+pub fn encode(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut output = MAGIC.to_vec();
+    write_number(&mut output, source.len());
+    write_number(&mut output, target.len());
+    // No metadata.
+    write_number(&mut output, 0);
+
+    let index = SourceIndex::new(source);
+    let mut output_offset = 0;
+    let mut source_relative_offset = 0;
+    let mut target_relative_offset = 0;
+    for operation in find_operations(source, &index, target, 0, target.len()) {
+        match operation {
+            Operation::Add { start, end } => {
+                write_action(&mut output, TARGET_READ, end - start);
+                output.extend_from_slice(&target[start..end]);
+                output_offset = end;
+            }
+            Operation::Run { byte, len } => {
+                // Runs are copies from the target overlapping with themselves.
+                let mut len = len;
+                if output_offset == 0 || target[output_offset - 1] != byte {
+                    write_action(&mut output, TARGET_READ, 1);
+                    output.push(byte);
+                    output_offset += 1;
+                    len -= 1;
+                }
+                write_action(&mut output, TARGET_COPY, len);
+                write_relative_offset(&mut output, target_relative_offset, output_offset - 1);
+                target_relative_offset = output_offset - 1 + len;
+                output_offset += len;
+            }
+            Operation::Copy { address, len } => {
+                if address == output_offset {
+                    write_action(&mut output, SOURCE_READ, len);
+                } else {
+                    write_action(&mut output, SOURCE_COPY, len);
+                    write_relative_offset(&mut output, source_relative_offset, address);
+                    source_relative_offset = address + len;
+                }
+                output_offset += len;
+            }
+        }
+    }
+
+    output.extend_from_slice(&crc32(source).to_le_bytes());
+    output.extend_from_slice(&crc32(target).to_le_bytes());
+    let patch_crc = crc32(&output);
+    output.extend_from_slice(&patch_crc.to_le_bytes());
+    output
+}
+
+/// Applies a BPS patch to `source`, returning the patched data.
+pub fn decode(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, ApplyBpsError> {
+    use ApplyBpsError::*;
+
+    if !patch.starts_with(MAGIC) {
+        return Err(MagicNumberMismatch);
+    }
+    if patch.len() < MAGIC.len() + FOOTER_SIZE {
+        return Err(Truncated);
+    }
+    let (body, footer) = patch.split_at(patch.len() - FOOTER_SIZE);
+    let checksum =
+        |idx: usize| u32::from_le_bytes(footer[idx * 4..idx * 4 + 4].try_into().unwrap());
+    let (source_crc, target_crc, patch_crc) = (checksum(0), checksum(1), checksum(2));
+    if crc32(&patch[..patch.len() - 4]) != patch_crc {
+        return Err(PatchChecksumMismatch);
+    }
+
+    let mut reader = Reader {
+        data: body,
+        offset: MAGIC.len(),
+    };
+    let source_size = reader.number().ok_or(Truncated)?;
+    let target_size = reader.number().ok_or(Truncated)?;
+    let metadata_size = reader.number().ok_or(Truncated)?;
+    reader.bytes(metadata_size).ok_or(Truncated)?;
+
+    let found_crc = crc32(source);
+    if found_crc != source_crc {
+        if source.len() == target_size && found_crc == target_crc {
+            return Err(AlreadyPatched);
+        }
+        if source.len() != source_size {
+            return Err(SourceSizeMismatch {
+                expected: source_size,
+                found: source.len(),
+            });
+        }
+        return Err(SourceChecksumMismatch {
+            expected: source_crc,
+            found: found_crc,
+        });
+    }
+
+    // The target size comes from the patch, so the target only grows as actions add to it,
+    // failing once it would exceed that size.
+    let mut target = Vec::new();
+    let mut source_relative_offset = 0usize;
+    let mut target_relative_offset = 0usize;
+    while reader.offset < body.len() {
+        let offset = reader.offset;
+        let invalid = || InvalidAction { offset };
+        let action = reader.number().ok_or_else(invalid)?;
+        let len = (action >> 2) + 1;
+        if len > target_size - target.len() {
+            return Err(invalid());
+        }
+
+        let mut relative_offset = |relative_offset: &mut usize| {
+            let value = reader.number()?;
+            *relative_offset = if value & 1 == 0 {
+                relative_offset.checked_add(value >> 1)?
+            } else {
+                relative_offset.checked_sub(value >> 1)?
+            };
+            Some(*relative_offset)
+        };
+        match action & 3 {
+            SOURCE_READ => {
+                let start = target.len();
+                let end = start.checked_add(len).ok_or_else(invalid)?;
+                target.extend_from_slice(source.get(start..end).ok_or_else(invalid)?);
+            }
+            TARGET_READ => target.extend_from_slice(reader.bytes(len).ok_or_else(invalid)?),
+            SOURCE_COPY => {
+                let start = relative_offset(&mut source_relative_offset).ok_or_else(invalid)?;
+                let end = start.checked_add(len).ok_or_else(invalid)?;
+                target.extend_from_slice(source.get(start..end).ok_or_else(invalid)?);
+                source_relative_offset += len;
+            }
+            _ => {
+                let start = relative_offset(&mut target_relative_offset)
+                    .filter(|&start| start < target.len())
+                    .ok_or_else(invalid)?;
+                // The copy may overlap with the data it produces.
+                for position in start..start + len {
+                    target.push(target[position]);
+                }
+                target_relative_offset += len;
+            }
+        }
+    }
+
+    if target.len() != target_size || crc32(&target) != target_crc {
+        return Err(TargetChecksumMismatch);
+    }
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_source() -> Vec<u8> {
+        (0..0x1000u32)
+            .map(|index| (index * 7 % 251) as u8)
+            .collect()
+    }
+
+    /// Builds a patch of the header and actions given, with valid checksums.
+    fn build_patch(body: &[u8], source: &[u8], target_crc: u32) -> Vec<u8> {
+        let mut patch = body.to_vec();
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&target_crc.to_le_bytes());
+        let patch_crc = crc32(&patch);
+        patch.extend_from_slice(&patch_crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn round_trips() {
+        let source = sample_source();
+        let mut edited = source.clone();
+        edited[0x100..0x120].fill(0x42);
+        edited.copy_within(0x800..0x900, 0x10);
+        edited.extend_from_slice(b"appended data");
+        let mut padded = source.clone();
+        padded.resize(0x100000, 0xFF);
+        for target in [source.clone(), edited, padded, source[..0x10].to_vec()] {
+            let patch = encode(&source, &target);
+            assert_eq!(decode(&source, &patch).unwrap(), target);
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_sources() {
+        let source = sample_source();
+        let mut target = source.clone();
+        target[0] ^= 0xFF;
+        let patch = encode(&source, &target);
+        assert!(matches!(
+            decode(&target, &patch),
+            Err(ApplyBpsError::AlreadyPatched)
+        ));
+        assert!(matches!(
+            decode(&source[1..], &patch),
+            Err(ApplyBpsError::SourceSizeMismatch { .. })
+        ));
+        assert!(matches!(
+            decode(&[0; 0x1000], &patch),
+            Err(ApplyBpsError::SourceChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_corrupted_patches() {
+        let source = sample_source();
+        let mut patch = encode(&source, &[0x22; 0x80]);
+        for len in 0..patch.len() {
+            assert!(decode(&source, &patch[..len]).is_err(), "length {len}");
+        }
+        patch[MAGIC.len()] ^= 1;
+        assert!(matches!(
+            decode(&source, &patch),
+            Err(ApplyBpsError::PatchChecksumMismatch)
+        ));
+    }
+
+    #[test]
+    fn rejects_huge_targets_without_allocating_them() {
+        let source = sample_source();
+        let mut body = MAGIC.to_vec();
+        write_number(&mut body, source.len());
+        write_number(&mut body, 1 << 62);
+        write_number(&mut body, 0);
+        write_action(&mut body, SOURCE_READ, source.len());
+        let patch = build_patch(&body, &source, 0);
+        assert!(matches!(
+            decode(&source, &patch),
+            Err(ApplyBpsError::TargetChecksumMismatch)
+        ));
+
+        // Actions producing more than the target size declared are invalid.
+        let mut body = MAGIC.to_vec();
+        write_number(&mut body, source.len());
+        write_number(&mut body, 0x10);
+        write_number(&mut body, 0);
+        write_action(&mut body, SOURCE_READ, 0x11);
+        let patch = build_patch(&body, &source, 0);
+        assert!(matches!(
+            decode(&source, &patch),
+            Err(ApplyBpsError::InvalidAction { .. })
+        ));
+    }
+}
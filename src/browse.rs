@@ -0,0 +1,474 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, List, ListItem, ListState, Paragraph},
+    DefaultTerminal, Frame,
+};
+
+use crate::{
+    lz::CompressionLevel,
+    lz10::{compress_lz10, decompress_lz10},
+    lz11::decompress_lz11,
+    plugin::Plugins,
+    rom, survey,
+    text::TextEncoding,
+    unpack::{self, convert_file, Conversion},
+};
+
+const HELP: &str = "↑↓ move  ←→ fold  PgUp/PgDn scroll  x extract  r replace  R replace compressed  w write  q quit";
+const BYTES_PER_LINE: usize = 16;
+
+enum EntryKind {
+    Dir,
+    File { id: u16 },
+}
+
+/// A directory or file of the NitroFS, in the order they're listed in the tree.
+struct Entry {
+    path: String,
+    depth: usize,
+    kind: EntryKind,
+}
+
+impl Entry {
+    fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+}
+
+/// Lists the directories and files of a ROM's NitroFS depth-first, directories first.
+fn list_entries(fs: &nitro_fs::FileSystem) -> Vec<Entry> {
+    let mut dirs: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let mut files: BTreeMap<String, BTreeMap<String, u16>> = BTreeMap::new();
+    let parent_of = |path: &str| {
+        path.rsplit_once('/')
+            .map_or("", |(parent, _)| parent)
+            .to_owned()
+    };
+    for dir in fs.dirs.values() {
+        let path = rom::nitro_path(&dir.path);
+        if !path.is_empty() {
+            dirs.entry(parent_of(&path)).or_default().insert(path);
+        }
+    }
+    for entry in fs.files() {
+        let path = rom::nitro_path(&entry.path);
+        files
+            .entry(parent_of(&path))
+            .or_default()
+            .insert(path, entry.id);
+    }
+
+    fn add(
+        entries: &mut Vec<Entry>,
+        dir: &str,
+        depth: usize,
+        dirs: &BTreeMap<String, BTreeSet<String>>,
+        files: &BTreeMap<String, BTreeMap<String, u16>>,
+    ) {
+        for path in dirs.get(dir).into_iter().flatten() {
+            entries.push(Entry {
+                path: path.clone(),
+                depth,
+                kind: EntryKind::Dir,
+            });
+            add(entries, path, depth + 1, dirs, files);
+        }
+        for (path, &id) in files.get(dir).into_iter().flatten() {
+            entries.push(Entry {
+                path: path.clone(),
+                depth,
+                kind: EntryKind::File { id },
+            });
+        }
+    }
+    let mut entries = Vec::new();
+    add(&mut entries, "", 0, &dirs, &files);
+    entries
+}
+
+/// What's shown of the selected file.
+struct Preview {
+    description: String,
+    /// The file's contents, decompressed if it's LZ10- or LZ11-compressed.
+    contents: Vec<u8>,
+}
+
+fn hex_line(data: &[u8], offset: usize) -> String {
+    let bytes = &data[offset..(offset + BYTES_PER_LINE).min(data.len())];
+    let mut line = format!("{offset:08X} ");
+    for (index, byte) in bytes.iter().enumerate() {
+        if index % 8 == 0 {
+            line.push(' ');
+        }
+        let _ = write!(line, "{byte:02X} ");
+    }
+    let padding = (BYTES_PER_LINE - bytes.len()) * 3 + (BYTES_PER_LINE - bytes.len()) / 8;
+    line.extend(std::iter::repeat_n(' ', padding));
+    line.push_str(" |");
+    line.extend(bytes.iter().map(|&byte| {
+        if byte.is_ascii_graphic() || byte == b' ' {
+            byte as char
+        } else {
+            '.'
+        }
+    }));
+    line.push('|');
+    line
+}
+
+enum Mode {
+    Normal,
+    /// Asking for the path of the file to replace the selected one with.
+    Replace {
+        input: String,
+        compress: bool,
+    },
+    /// Quitting was asked for with unwritten changes, and must be asked for again.
+    ConfirmQuit,
+}
+
+struct Browser<'a> {
+    rom_data: Vec<u8>,
+    output: &'a Path,
+    encoding: &'a TextEncoding,
+    fs: nitro_fs::FileSystem,
+    entries: Vec<Entry>,
+    expanded: HashSet<String>,
+    /// Indices into `entries` of the rows shown, skipping the contents of folded directories.
+    rows: Vec<usize>,
+    list_state: ListState,
+    preview: Option<Preview>,
+    scroll: usize,
+    mode: Mode,
+    status: String,
+    modified: bool,
+}
+
+impl<'a> Browser<'a> {
+    fn new(
+        rom_data: Vec<u8>,
+        output: &'a Path,
+        encoding: &'a TextEncoding,
+    ) -> anyhow::Result<Self> {
+        let fs = rom::filesystem(&rom_data)?;
+        let entries = list_entries(&fs);
+        let mut browser = Self {
+            rom_data,
+            output,
+            encoding,
+            fs,
+            entries,
+            expanded: HashSet::new(),
+            rows: Vec::new(),
+            list_state: ListState::default(),
+            preview: None,
+            scroll: 0,
+            mode: Mode::Normal,
+            status: HELP.to_owned(),
+            modified: false,
+        };
+        browser.update_rows();
+        browser.select(0);
+        Ok(browser)
+    }
+
+    fn update_rows(&mut self) {
+        self.rows.clear();
+        let mut folded_depth = None;
+        for (index, entry) in self.entries.iter().enumerate() {
+            match folded_depth {
+                Some(depth) if entry.depth > depth => continue,
+                _ => folded_depth = None,
+            }
+            self.rows.push(index);
+            if matches!(entry.kind, EntryKind::Dir) && !self.expanded.contains(&entry.path) {
+                folded_depth = Some(entry.depth);
+            }
+        }
+    }
+
+    fn selected(&self) -> Option<&Entry> {
+        let row = self.list_state.selected()?;
+        Some(&self.entries[*self.rows.get(row)?])
+    }
+
+    fn file_data(&self, id: u16) -> &[u8] {
+        let entry = self.fs.files().into_iter().find(|entry| entry.id == id);
+        entry.map_or(&[], |entry| rom::file_data(&self.rom_data, entry))
+    }
+
+    fn select(&mut self, row: usize) {
+        if self.rows.is_empty() {
+            return;
+        }
+        self.list_state.select(Some(row.min(self.rows.len() - 1)));
+        self.scroll = 0;
+        self.preview = match self.selected().map(|entry| &entry.kind) {
+            Some(&EntryKind::File { id }) => {
+                let data = self.file_data(id);
+                let identification =
+                    survey::identify_file(data, self.encoding, &Plugins::default());
+                let contents = decompress_lz10(data)
+                    .or_else(|_| decompress_lz11(data).map_err(|_| ()))
+                    .unwrap_or_else(|_| data.to_vec());
+                Some(Preview {
+                    description: format!("{} bytes, {}", data.len(), identification.description),
+                    contents,
+                })
+            }
+            _ => None,
+        };
+    }
+
+    fn select_path(&mut self, path: &str) {
+        if let Some(row) = self
+            .rows
+            .iter()
+            .position(|&index| self.entries[index].path == path)
+        {
+            self.select(row);
+        }
+    }
+
+    fn set_expanded(&mut self, expanded: bool) {
+        let Some(entry) = self.selected() else {
+            return;
+        };
+        let (path, is_dir) = (entry.path.clone(), matches!(entry.kind, EntryKind::Dir));
+        if is_dir && expanded {
+            self.expanded.insert(path.clone());
+        } else if !(is_dir && self.expanded.remove(&path)) {
+            // Folding an entry that can't be folded goes up to its directory instead.
+            if let (false, Some((parent, _))) = (expanded, path.rsplit_once('/')) {
+                let parent = parent.to_owned();
+                self.select_path(&parent);
+            }
+            return;
+        }
+        self.update_rows();
+        self.select_path(&path);
+    }
+
+    fn extract(&mut self) {
+        let Some(Entry {
+            path,
+            kind: EntryKind::File { id },
+            ..
+        }) = self.selected()
+        else {
+            return;
+        };
+        let mut target_path = PathBuf::from(path);
+        let converted = convert_file(
+            self.file_data(*id),
+            &mut target_path,
+            Conversion::Auto,
+            self.encoding,
+        );
+        self.status = match unpack::write_file(&target_path, &converted.data) {
+            Ok(()) => format!("extracted to {target_path:?}: {}", converted.description),
+            Err(error) => format!("{error:#}"),
+        };
+    }
+
+    fn replace(&mut self, file_path: &str, compress: bool) -> anyhow::Result<String> {
+        let Some(Entry {
+            path,
+            kind: EntryKind::File { id },
+            ..
+        }) = self.selected()
+        else {
+            return Ok(String::new());
+        };
+        let (path, id) = (path.clone(), *id);
+        let mut new_data = fs::read(file_path).context("failed to read file to insert")?;
+        if compress {
+            new_data = compress_lz10(&new_data, CompressionLevel::default())
+                .context("failed to compress file")?;
+        }
+        let placement = rom::replace_file(&mut self.rom_data, id, &new_data)?;
+        self.fs = rom::filesystem(&self.rom_data)?;
+        self.modified = true;
+        self.select_path(&path);
+        Ok(match placement {
+            rom::Placement::InPlace => format!("{path}: replaced in place"),
+            rom::Placement::Relocated { start } => format!("{path}: relocated to 0x{start:08X}"),
+        })
+    }
+
+    fn write(&mut self) {
+        self.status = match fs::write(self.output, &self.rom_data) {
+            Ok(()) => {
+                self.modified = false;
+                format!("wrote {:?}", self.output)
+            }
+            Err(error) => format!("failed to write ROM: {error}"),
+        };
+    }
+
+    /// Handles a key press, returning whether to quit.
+    fn handle_key(&mut self, key: KeyCode, preview_height: usize) -> bool {
+        match &mut self.mode {
+            Mode::Replace { input, compress } => {
+                match key {
+                    KeyCode::Char(c) => input.push(c),
+                    KeyCode::Backspace => {
+                        input.pop();
+                    }
+                    KeyCode::Enter => {
+                        let (input, compress) = (input.clone(), *compress);
+                        self.mode = Mode::Normal;
+                        self.status = self
+                            .replace(&input, compress)
+                            .unwrap_or_else(|error| format!("{error:#}"));
+                    }
+                    KeyCode::Esc => {
+                        self.mode = Mode::Normal;
+                        self.status = HELP.to_owned();
+                    }
+                    _ => {}
+                }
+                return false;
+            }
+            Mode::ConfirmQuit if matches!(key, KeyCode::Char('q')) => return true,
+            Mode::ConfirmQuit => {
+                self.mode = Mode::Normal;
+                self.status = HELP.to_owned();
+            }
+            Mode::Normal => {}
+        }
+
+        let row = self.list_state.selected().unwrap_or(0);
+        let max_scroll = self.preview.as_ref().map_or(0, |preview| {
+            preview
+                .contents
+                .len()
+                .div_ceil(BYTES_PER_LINE)
+                .saturating_sub(1)
+        });
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc if self.modified => {
+                self.mode = Mode::ConfirmQuit;
+                self.status = "the ROM has unwritten changes; press q again to quit anyway".into();
+            }
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Up | KeyCode::Char('k') => self.select(row.saturating_sub(1)),
+            KeyCode::Down | KeyCode::Char('j') => self.select(row + 1),
+            KeyCode::Home => self.select(0),
+            KeyCode::End => self.select(usize::MAX),
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Enter => self.set_expanded(true),
+            KeyCode::Left | KeyCode::Char('h') => self.set_expanded(false),
+            KeyCode::PageDown => self.scroll = (self.scroll + preview_height).min(max_scroll),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(preview_height),
+            KeyCode::Char('x') => self.extract(),
+            KeyCode::Char(c @ ('r' | 'R'))
+                if matches!(
+                    self.selected().map(|e| &e.kind),
+                    Some(EntryKind::File { .. })
+                ) =>
+            {
+                self.mode = Mode::Replace {
+                    input: String::new(),
+                    compress: c == 'R',
+                };
+            }
+            KeyCode::Char('w') => self.write(),
+            _ => {}
+        }
+        false
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [tree_area, preview_area] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)])
+                .areas(main);
+
+        let items = self.rows.iter().map(|&index| {
+            let entry = &self.entries[index];
+            let marker = match entry.kind {
+                EntryKind::Dir if self.expanded.contains(&entry.path) => "▾ ",
+                EntryKind::Dir => "▸ ",
+                EntryKind::File { .. } => "  ",
+            };
+            ListItem::new(format!(
+                "{}{marker}{}",
+                "  ".repeat(entry.depth),
+                entry.name()
+            ))
+        });
+        let title = if self.modified {
+            "NitroFS (modified)"
+        } else {
+            "NitroFS"
+        };
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, tree_area, &mut self.list_state);
+
+        let block = Block::bordered().title(self.selected().map_or("", |entry| &entry.path));
+        let lines = match &self.preview {
+            Some(preview) => {
+                let height = block.inner(preview_area).height.saturating_sub(2) as usize;
+                let mut lines = vec![Line::from(preview.description.clone()), Line::default()];
+                lines.extend(
+                    (self.scroll * BYTES_PER_LINE..preview.contents.len())
+                        .step_by(BYTES_PER_LINE)
+                        .take(height)
+                        .map(|offset| Line::from(hex_line(&preview.contents, offset))),
+                );
+                lines
+            }
+            None => Vec::new(),
+        };
+        frame.render_widget(Paragraph::new(lines).block(block), preview_area);
+
+        let status_line = match &self.mode {
+            Mode::Replace { input, compress } => format!(
+                "replace with{}: {input}",
+                if *compress { " (LZ10-compressed)" } else { "" }
+            ),
+            _ => self.status.clone(),
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
+        loop {
+            let mut preview_height = 0;
+            terminal.draw(|frame| {
+                preview_height = frame.area().height.saturating_sub(5) as usize;
+                self.draw(frame)
+            })?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && self.handle_key(key.code, preview_height) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Browses the NitroFS of a ROM interactively, showing what each file is along with a hex dump
+/// of its contents, and allowing files to be extracted or replaced. Changes are written to
+/// `output` when asked to.
+pub fn browse(rom_data: Vec<u8>, output: &Path, encoding: &TextEncoding) -> anyhow::Result<()> {
+    let mut browser = Browser::new(rom_data, output, encoding)?;
+    let mut terminal = ratatui::try_init().context("failed to set up the terminal")?;
+    let result = browser.run(&mut terminal);
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,231 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use anyhow::Context;
+use std::fs;
+
+use crate::{
+    blz::{self, BlzCompressionError},
+    lz::CompressionLevel,
+    lz10::compress_lz10,
+    lz11::compress_lz11,
+    manifest::{self, Compression},
+};
+
+/// Directory holding cached build outputs, placed in the directory being built.
+pub const CACHE_DIR: &str = ".ravends-cache";
+/// Directory inside [`CACHE_DIR`] holding LZ10-compressed data.
+const LZ10_DIR: &str = "lz10";
+/// Directory inside [`CACHE_DIR`] holding LZ11-compressed data.
+const LZ11_DIR: &str = "lz11";
+/// Directory inside [`CACHE_DIR`] holding BLZ-compressed code binaries.
+const BLZ_DIR: &str = "blz";
+/// Directory inside [`CACHE_DIR`] holding the output of hooks.
+const HOOKS_DIR: &str = "hooks";
+/// Version of the compressors' output, part of every key. It must be bumped whenever the
+/// compressors change what they output for the same data, so that builds with a cache filled by
+/// an older version stay byte-identical to builds without one.
+const COMPRESSOR_VERSION: u32 = 1;
+
+/// Cache of LZ-compressed data, keyed by the SHA-256 hash of the uncompressed data, so that
+/// files that did not change since the last build don't need to be recompressed.
+///
+/// The cache can be shared between threads compressing files in parallel.
+pub struct CompressionCache {
+    dir: Option<PathBuf>,
+    level: CompressionLevel,
+    /// Entries used since the cache was opened, as paths relative to the cache directory.
+    used: Mutex<BTreeSet<String>>,
+    /// Number of files actually compressed since the cache was opened, and their total size.
+    compressed_files: AtomicUsize,
+    compressed_bytes: AtomicUsize,
+}
+
+/// How much data a cache had to compress, as opposed to finding it in the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompressionStats {
+    pub files: usize,
+    /// Total size of the files before compression.
+    pub bytes: usize,
+}
+
+impl CompressionCache {
+    /// Opens the cache of the directory given.
+    pub fn new(build_dir: &Path) -> Self {
+        Self {
+            dir: Some(build_dir.join(CACHE_DIR)),
+            level: CompressionLevel::default(),
+            used: Mutex::default(),
+            compressed_files: AtomicUsize::new(0),
+            compressed_bytes: AtomicUsize::new(0),
+        }
+    }
+
+    /// A cache that never stores anything.
+    pub fn disabled() -> Self {
+        Self {
+            dir: None,
+            level: CompressionLevel::default(),
+            used: Mutex::default(),
+            compressed_files: AtomicUsize::new(0),
+            compressed_bytes: AtomicUsize::new(0),
+        }
+    }
+
+    /// Makes the cache compress data at the level given. Results of each level are cached
+    /// separately.
+    pub fn with_level(mut self, level: CompressionLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// The level the cache compresses data at.
+    pub fn level(&self) -> CompressionLevel {
+        self.level
+    }
+
+    /// Compresses data with the algorithm given, reusing the result of a previous build if
+    /// there is one. Data is returned as-is for [`Compression::None`].
+    pub fn compress(&self, data: &[u8], compression: Compression) -> anyhow::Result<Vec<u8>> {
+        let subdir = match compression {
+            Compression::None => return Ok(data.to_vec()),
+            Compression::Lz10 => LZ10_DIR,
+            Compression::Lz11 => LZ11_DIR,
+        };
+        self.cached(
+            subdir,
+            data,
+            |cached| compression.decompress(cached),
+            || {
+                Ok(match compression {
+                    Compression::None => unreachable!(),
+                    Compression::Lz10 => compress_lz10(data, self.level)?,
+                    Compression::Lz11 => compress_lz11(data, self.level)?,
+                })
+            },
+        )
+    }
+
+    /// Compresses a code binary with BLZ, reusing the result of a previous build if there is
+    /// one. Returns `None` if the binary doesn't get any smaller when compressed.
+    pub fn compress_blz(&self, data: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        let result = self.cached(
+            BLZ_DIR,
+            data,
+            |cached| blz::decompress_blz(cached).ok(),
+            || Ok(blz::compress_blz(data, self.level)?),
+        );
+        match result {
+            Err(error)
+                if matches!(
+                    error.downcast_ref(),
+                    Some(BlzCompressionError::Incompressible)
+                ) =>
+            {
+                Ok(None)
+            }
+            result => result.map(Some),
+        }
+    }
+
+    /// Returns the data compressed by `compress`, from the subdirectory of the cache given if a
+    /// previous build stored it there. Cached data is checked with `decompress` before being
+    /// used, so that a damaged cache can't break a build.
+    fn cached(
+        &self,
+        subdir: &str,
+        data: &[u8],
+        decompress: impl Fn(&[u8]) -> Option<Vec<u8>>,
+        compress: impl FnOnce() -> anyhow::Result<Vec<u8>>,
+    ) -> anyhow::Result<Vec<u8>> {
+        let compress = || {
+            let compressed = compress()?;
+            self.compressed_files.fetch_add(1, Ordering::Relaxed);
+            self.compressed_bytes
+                .fetch_add(data.len(), Ordering::Relaxed);
+            Ok(compressed)
+        };
+        let Some(dir) = &self.dir else {
+            return compress();
+        };
+        let dir = dir.join(subdir);
+        let hash = manifest::sha256_hex(data);
+        let key = match self.level {
+            CompressionLevel::Fast => format!("{hash}-v{COMPRESSOR_VERSION}"),
+            CompressionLevel::Best => format!("{hash}-best-v{COMPRESSOR_VERSION}"),
+        };
+        let path = dir.join(&key);
+        self.used.lock().unwrap().insert(format!("{subdir}/{key}"));
+
+        if let Ok(cached) = fs::read(&path) {
+            if decompress(&cached).is_some_and(|decompressed| decompressed == data) {
+                return Ok(cached);
+            }
+        }
+        let compressed = compress()?;
+        fs::create_dir_all(&dir).context("failed to create cache directory")?;
+        fs::write(&path, &compressed).with_context(|| format!("failed to write {path:?}"))?;
+        Ok(compressed)
+    }
+
+    /// Returns the output of a hook for the key given, which must identify its command and
+    /// input, running `run` only if it wasn't cached by a previous build.
+    pub fn hook_output(
+        &self,
+        key: &str,
+        run: impl FnOnce() -> anyhow::Result<Vec<u8>>,
+    ) -> anyhow::Result<Vec<u8>> {
+        let Some(dir) = &self.dir else {
+            return run();
+        };
+        let dir = dir.join(HOOKS_DIR);
+        let path = dir.join(key);
+        self.used
+            .lock()
+            .unwrap()
+            .insert(format!("{HOOKS_DIR}/{key}"));
+        if let Ok(cached) = fs::read(&path) {
+            return Ok(cached);
+        }
+        let output = run()?;
+        fs::create_dir_all(&dir).context("failed to create cache directory")?;
+        fs::write(&path, &output).with_context(|| format!("failed to write {path:?}"))?;
+        Ok(output)
+    }
+
+    /// How much data was compressed since the cache was opened.
+    pub fn stats(&self) -> CompressionStats {
+        CompressionStats {
+            files: self.compressed_files.load(Ordering::Relaxed),
+            bytes: self.compressed_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Removes the entries that were not used since the cache was opened.
+    pub fn prune(&self) -> anyhow::Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let used = self.used.lock().unwrap();
+        for subdir in [LZ10_DIR, LZ11_DIR, BLZ_DIR, HOOKS_DIR] {
+            let Ok(entries) = fs::read_dir(dir.join(subdir)) else {
+                continue;
+            };
+            for entry in entries {
+                let entry = entry.context("failed to read cache directory")?;
+                let key = format!("{subdir}/{}", entry.file_name().to_string_lossy());
+                if !used.contains(&key) {
+                    fs::remove_file(entry.path())
+                        .with_context(|| format!("failed to remove {:?}", entry.path()))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use thiserror::Error;
+
+use crate::{
+    memory::{self, MapAddressError},
+    symbols::{ResolveSymbolError, SymbolMap},
+};
+
+#[derive(Error, Debug)]
+pub enum ParseCheatError {
+    #[error("line {line}: invalid code {text:?} (expected pairs of 8-digit hexadecimal numbers)")]
+    InvalidCode { line: usize, text: String },
+    #[error("line {line}")]
+    Symbol {
+        line: usize,
+        source: ResolveSymbolError,
+    },
+}
+
+/// A named Action Replay DS cheat: a list of code lines, each made of two 32-bit words.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub name: String,
+    pub codes: Vec<(u32, u32)>,
+}
+
+fn is_hex_word(token: &str) -> bool {
+    token.len() == 8 && token.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Parses a word of a code line, which is either hexadecimal or a code type digit followed by
+/// a symbol reference in braces, such as `0{gPlayerMoney}` or `2{gFlags+0x4}`. Returns `None`
+/// if the token is neither.
+fn parse_word(token: &str, symbols: &SymbolMap) -> Option<Result<u32, ResolveSymbolError>> {
+    if is_hex_word(token) {
+        return Some(Ok(u32::from_str_radix(token, 16).unwrap()));
+    }
+    let code_type = token.get(..1)?;
+    let reference = token.get(1..)?.strip_prefix('{')?.strip_suffix('}')?;
+    let code_type = u32::from_str_radix(code_type, 16).ok()?;
+    Some(
+        symbols
+            .resolve(reference)
+            .map(|symbol| code_type << 28 | symbol.address & 0x0FFF_FFFF),
+    )
+}
+
+/// Parses an Action Replay DS code list in plain text.
+///
+/// Lines of codes hold pairs of 8-digit hexadecimal numbers. Any other line names the cheat
+/// whose codes follow, optionally in brackets. Lines starting with `#`, `;` or `//` are
+/// comments. Addresses may be given as symbols from the map given, as in `0{gPlayerMoney}`.
+pub fn parse_code_list(text: &str, symbols: &SymbolMap) -> Result<Vec<Cheat>, ParseCheatError> {
+    let mut cheats: Vec<Cheat> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', ';']) || line.starts_with("//") {
+            continue;
+        }
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        if parse_word(tokens[0], symbols).is_none() {
+            let name = line
+                .strip_prefix('[')
+                .and_then(|name| name.strip_suffix(']'))
+                .unwrap_or(line);
+            cheats.push(Cheat {
+                name: name.trim().to_owned(),
+                codes: Vec::new(),
+            });
+            continue;
+        }
+        let invalid = || ParseCheatError::InvalidCode {
+            line: index + 1,
+            text: line.to_owned(),
+        };
+        if tokens.len() % 2 != 0 {
+            return Err(invalid());
+        }
+        let words = tokens
+            .iter()
+            .map(|token| {
+                parse_word(token, symbols)
+                    .ok_or_else(invalid)?
+                    .map_err(|source| ParseCheatError::Symbol {
+                        line: index + 1,
+                        source,
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if cheats.is_empty() {
+            cheats.push(Cheat {
+                name: "unnamed cheat".to_owned(),
+                codes: Vec::new(),
+            });
+        }
+        let codes = &mut cheats.last_mut().unwrap().codes;
+        codes.extend(words.chunks_exact(2).map(|pair| (pair[0], pair[1])));
+    }
+    cheats.retain(|cheat| !cheat.codes.is_empty());
+    Ok(cheats)
+}
+
+#[derive(Error, Debug)]
+pub enum BakeCheatError {
+    #[error(
+        "code {0:08X} is conditional, loops or uses the offset register, so it can't be baked"
+    )]
+    UnsupportedCode(u32),
+    #[error("patch code {0:08X} is missing its data")]
+    TruncatedPatch(u32),
+    #[error(transparent)]
+    Address(#[from] MapAddressError),
+}
+
+/// A write to memory a cheat makes every time it runs, regardless of the game's state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticWrite {
+    pub address: u32,
+    pub data: Vec<u8>,
+}
+
+/// Turns a cheat into the memory writes it makes, as long as all of its codes are
+/// unconditional writes (code types 0, 1, 2 and E) to fixed addresses.
+pub fn static_writes(cheat: &Cheat) -> Result<Vec<StaticWrite>, BakeCheatError> {
+    let mut writes = Vec::new();
+    let mut codes = cheat.codes.iter();
+    while let Some(&(code, value)) = codes.next() {
+        let address = code & 0x0FFF_FFFF;
+        let data = match code >> 28 {
+            0x0 => value.to_le_bytes().to_vec(),
+            0x1 => (value as u16).to_le_bytes().to_vec(),
+            0x2 => vec![value as u8],
+            0xE => {
+                // The data to copy follows in as many code lines as needed, 8 bytes per line.
+                let len = value as usize;
+                let data = codes
+                    .by_ref()
+                    .take(len.div_ceil(8))
+                    .flat_map(|&(first, second)| [first.to_le_bytes(), second.to_le_bytes()])
+                    .flatten()
+                    .take(len)
+                    .collect::<Vec<_>>();
+                if data.len() < len {
+                    return Err(BakeCheatError::TruncatedPatch(code));
+                }
+                data
+            }
+            // End-of-block codes do nothing when there are no conditionals or loops.
+            0xD if matches!(code >> 24, 0xD0..=0xD2) && value == 0 => continue,
+            _ => return Err(BakeCheatError::UnsupportedCode(code)),
+        };
+        writes.push(StaticWrite { address, data });
+    }
+    Ok(writes)
+}
+
+/// Writes the data of a cheat into the ARM9 binary and overlays of a ROM, where the game
+/// loads it from. Either all of the cheat's writes are baked, or none are.
+pub fn bake(rom_data: &mut [u8], cheat: &Cheat) -> Result<usize, BakeCheatError> {
+    let writes = static_writes(cheat)?;
+    let offsets = writes
+        .iter()
+        .map(|write| memory::rom_offset(rom_data, write.address, write.data.len(), None))
+        .collect::<Result<Vec<_>, _>>()?;
+    for (write, offset) in writes.iter().zip(offsets) {
+        rom_data[offset..offset + write.data.len()].copy_from_slice(&write.data);
+    }
+    Ok(writes.len())
+}
@@ -0,0 +1,266 @@
+use std::{
+    fmt,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use clap::ValueEnum;
+use rhai::{Array, Blob, Dynamic, Engine, EvalAltResult, Map, Scope};
+
+use crate::{
+    cache::CompressionCache,
+    manifest::{Codec, Compression},
+    narc::{self, Narc},
+    rom,
+};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Turns an error of the library into one Rhai reports, with its causes.
+fn script_error(error: impl Into<anyhow::Error>) -> Box<EvalAltResult> {
+    format!("{:#}", error.into()).into()
+}
+
+/// A ROM opened by a script, edited in memory until it's saved.
+#[derive(Clone)]
+pub struct ScriptRom {
+    path: PathBuf,
+    data: Vec<u8>,
+}
+
+impl fmt::Debug for ScriptRom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Rom({:?})", self.path)
+    }
+}
+
+impl ScriptRom {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let data = rom::read_rom(path)?;
+        rom::check_header(&data)?;
+        Ok(Self {
+            path: path.to_owned(),
+            data,
+        })
+    }
+
+    /// NitroFS paths of the files matching the glob pattern given, in file ID order.
+    fn files(&self, pattern: &str) -> anyhow::Result<Array> {
+        let filter = rom::PathFilter::new(&[pattern.to_owned()], &[])?;
+        let fs = rom::filesystem(&self.data)?;
+        let mut entries = fs.files();
+        entries.sort_by_key(|entry| entry.id);
+        Ok(entries
+            .into_iter()
+            .filter(|entry| filter.matches(&entry.path))
+            .map(|entry| rom::nitro_path(&entry.path).into())
+            .collect())
+    }
+
+    fn read(&self, nitro_path: &str) -> anyhow::Result<Blob> {
+        let fs = rom::filesystem(&self.data)?;
+        let entry = find_file(&fs, nitro_path)?;
+        Ok(rom::checked_file_data(&self.data, entry)?.to_vec())
+    }
+
+    /// Replaces a file as it is given, returning where it was placed.
+    fn replace(&mut self, nitro_path: &str, data: &[u8]) -> anyhow::Result<String> {
+        let file_id = find_file(&rom::filesystem(&self.data)?, nitro_path)?.id;
+        Ok(match rom::replace_file(&mut self.data, file_id, data)? {
+            rom::Placement::InPlace => "in place".to_owned(),
+            rom::Placement::Relocated { start } => format!("relocated to 0x{start:08X}"),
+        })
+    }
+
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if rom::is_zip(path) {
+            anyhow::bail!("can't write ROM to {path:?}, as it is a zip archive");
+        }
+        std::fs::write(path, &self.data).with_context(|| format!("failed to write ROM to {path:?}"))
+    }
+}
+
+fn find_file<'fs>(
+    fs: &'fs nitro_fs::FileSystem,
+    nitro_path: &str,
+) -> anyhow::Result<&'fs nitro_fs::fnt::FileEntry> {
+    fs.files()
+        .into_iter()
+        .find(|entry| rom::nitro_path(&entry.path) == nitro_path.trim_matches('/'))
+        .ok_or_else(|| anyhow!("no file in the ROM has the path {nitro_path:?}"))
+}
+
+/// Decompresses data compressed in a way ravends knows.
+fn decompress(data: &[u8]) -> anyhow::Result<Blob> {
+    Codec::detect(data)
+        .map(|(_, decompressed)| decompressed)
+        .ok_or_else(|| anyhow!("data isn't compressed in a way ravends knows"))
+}
+
+fn compress(data: &[u8], compression: &str) -> anyhow::Result<Blob> {
+    let compression = Compression::from_str(compression, true)
+        .map_err(|_| anyhow!("unknown compression {compression:?}"))?;
+    Codec::new(compression).compress(data, &CompressionCache::disabled())
+}
+
+/// The files of a NARC archive, decompressed first if needed, as maps with a `name`, unit for
+/// nameless archives, and `data`.
+fn narc_files(data: &[u8]) -> anyhow::Result<Array> {
+    let narc = match narc::open_container(data) {
+        Some((_, narc)) => narc,
+        // Parsing again reports why the archive can't be opened.
+        None => Narc::parse(data)?,
+    };
+    Ok(narc
+        .files
+        .into_iter()
+        .map(|file| {
+            let mut map = Map::new();
+            map.insert(
+                "name".into(),
+                file.name.map_or(Dynamic::UNIT, Dynamic::from),
+            );
+            map.insert("data".into(), Dynamic::from_blob(file.data));
+            map.into()
+        })
+        .collect())
+}
+
+/// A Rhai engine exposing the library, for scripts and the interactive console:
+///
+/// - `open_rom(path)` opens a ROM, with the `path`, `title` and `game_code` properties.
+/// - `rom.files()` and `rom.files(pattern)` give the NitroFS paths of its files, all of them or
+///   those matching a glob pattern.
+/// - `rom.read(path)` gives the contents of a file as a blob, as stored.
+/// - `rom.replace(path, data)` replaces a file as given, without compressing it, returning
+///   where it was placed.
+/// - `rom.save()` and `rom.save(path)` write the ROM back, over the ROM opened or elsewhere.
+/// - `decompress(data)` and `compress(data, "lz10" | "lz11")` decompress and compress blobs.
+/// - `narc_files(data)` gives the files of a NARC archive, as maps with a `name` and `data`.
+/// - `read_file(path)` and `write_file(path, data)` read and write files outside ROMs.
+pub fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .register_type_with_name::<ScriptRom>("Rom")
+        .register_fn("open_rom", |path: &str| -> ScriptResult<ScriptRom> {
+            ScriptRom::open(Path::new(path)).map_err(script_error)
+        })
+        .register_get("path", |rom: &mut ScriptRom| {
+            rom.path.to_string_lossy().into_owned()
+        })
+        .register_get("title", |rom: &mut ScriptRom| {
+            rom::header_text(&rom.data, rom::TITLE_RANGE)
+        })
+        .register_get("game_code", |rom: &mut ScriptRom| {
+            rom::header_text(&rom.data, rom::GAME_CODE_RANGE)
+        })
+        .register_fn("files", |rom: &mut ScriptRom| -> ScriptResult<Array> {
+            rom.files("*").map_err(script_error)
+        })
+        .register_fn(
+            "files",
+            |rom: &mut ScriptRom, pattern: &str| -> ScriptResult<Array> {
+                rom.files(pattern).map_err(script_error)
+            },
+        )
+        .register_fn(
+            "read",
+            |rom: &mut ScriptRom, nitro_path: &str| -> ScriptResult<Blob> {
+                rom.read(nitro_path).map_err(script_error)
+            },
+        )
+        .register_fn(
+            "replace",
+            |rom: &mut ScriptRom, nitro_path: &str, data: Blob| -> ScriptResult<String> {
+                rom.replace(nitro_path, &data).map_err(script_error)
+            },
+        )
+        .register_fn("save", |rom: &mut ScriptRom| -> ScriptResult<()> {
+            rom.save(&rom.path.clone()).map_err(script_error)
+        })
+        .register_fn(
+            "save",
+            |rom: &mut ScriptRom, path: &str| -> ScriptResult<()> {
+                rom.save(Path::new(path)).map_err(script_error)
+            },
+        )
+        .register_fn("decompress", |data: Blob| -> ScriptResult<Blob> {
+            decompress(&data).map_err(script_error)
+        })
+        .register_fn(
+            "compress",
+            |data: Blob, compression: &str| -> ScriptResult<Blob> {
+                compress(&data, compression).map_err(script_error)
+            },
+        )
+        .register_fn("narc_files", |data: Blob| -> ScriptResult<Array> {
+            narc_files(&data).map_err(script_error)
+        })
+        .register_fn("read_file", |path: &str| -> ScriptResult<Blob> {
+            std::fs::read(path)
+                .with_context(|| format!("failed to read {path:?}"))
+                .map_err(script_error)
+        })
+        .register_fn("write_file", |path: &str, data: Blob| -> ScriptResult<()> {
+            std::fs::write(path, data)
+                .with_context(|| format!("failed to write {path:?}"))
+                .map_err(script_error)
+        });
+    engine
+}
+
+/// Runs the script at the path given, with `args` holding the arguments given to it.
+pub fn run_script(path: &Path, args: Vec<String>) -> anyhow::Result<()> {
+    let source =
+        std::fs::read_to_string(path).with_context(|| format!("failed to read script {path:?}"))?;
+    let engine = engine();
+    let ast = engine
+        .compile(source)
+        .map_err(|error| anyhow!("failed to compile script {path:?}: {error}"))?;
+    let mut scope = Scope::new();
+    scope.push(
+        "args",
+        args.into_iter().map(Dynamic::from).collect::<Array>(),
+    );
+    engine
+        .run_ast_with_scope(&mut scope, &ast)
+        .map_err(|error| anyhow!("script {path:?} failed: {error}"))
+}
+
+/// Reads statements from the standard input and runs them until it ends, printing their values.
+/// Variables are kept from one statement to the next, and lines ending with `\` are continued on
+/// the next line. With a ROM given, it's opened as `rom`.
+pub fn repl(rom_path: Option<&Path>) -> anyhow::Result<()> {
+    let engine = engine();
+    let mut scope = Scope::new();
+    if let Some(rom_path) = rom_path {
+        scope.push("rom", ScriptRom::open(rom_path)?);
+        println!("opened {} as `rom`", rom_path.display());
+    }
+
+    let mut stdin = io::stdin().lock();
+    let mut input = String::new();
+    loop {
+        print!("{}", if input.is_empty() { "> " } else { "| " });
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.read_line(&mut line)? == 0 {
+            println!();
+            return Ok(());
+        }
+        let line = line.trim_end();
+        if let Some(line) = line.strip_suffix('\\') {
+            input.push_str(line);
+            input.push('\n');
+            continue;
+        }
+        input.push_str(line);
+        match engine.eval_with_scope::<Dynamic>(&mut scope, &input) {
+            Ok(value) if value.is_unit() => {}
+            Ok(value) => println!("{value:?}"),
+            Err(error) => println!("error: {error}"),
+        }
+        input.clear();
+    }
+}
@@ -0,0 +1,245 @@
+use std::{char::DecodeUtf16Error, collections::BTreeMap, fmt::Write, path::Path};
+
+use anyhow::Context;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ParseControlCodesError {
+    #[error("invalid entry on line {line_number}: {line:?}")]
+    InvalidEntry { line_number: usize, line: String },
+    #[error("control code name {name:?} is defined more than once")]
+    DuplicateName { name: String },
+}
+
+#[derive(Error, Debug)]
+pub enum EncodeControlCodeError {
+    #[error("control code {name:?} takes {expected} parameters (found: {found})")]
+    ParameterCountMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("invalid control code parameters {params:?}")]
+    InvalidParameters { params: String },
+}
+
+/// A control code with a name and a fixed number of parameter units following it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ControlCode {
+    name: String,
+    param_count: usize,
+}
+
+/// Control codes embedded in UTF-16 strings, and how they are escaped when exporting them.
+///
+/// Units below 0x20 (other than line feeds) and in the private use area are control codes, and
+/// are escaped as `{XXXX}`. Codes given a name in a control code file are escaped as `{name}`,
+/// or `{name:XXXX YYYY}` if they take parameters. Each line of a control code file has the form
+/// `XXXX=name` or `XXXX=name,N`, where `N` is the number of parameter units following the code.
+/// Blank lines and lines starting with `;` or `#` are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ControlCodes {
+    by_unit: BTreeMap<u16, ControlCode>,
+    by_name: BTreeMap<String, u16>,
+}
+
+/// Whether a unit is a control code even if it has no name.
+fn is_unnamed_control_code(unit: u16) -> bool {
+    (unit < 0x20 && unit != u16::from(b'\n')) || (0xE000..=0xF8FF).contains(&unit)
+}
+
+fn is_hex_unit(text: &str) -> bool {
+    text.len() == 4 && text.bytes().all(|digit| digit.is_ascii_hexdigit())
+}
+
+impl ControlCodes {
+    pub fn parse(contents: &str) -> Result<Self, ParseControlCodesError> {
+        let mut codes = Self::default();
+        for (line_idx, line) in contents.trim_start_matches('\u{FEFF}').lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with([';', '#']) {
+                continue;
+            }
+            let invalid_entry = || ParseControlCodesError::InvalidEntry {
+                line_number: line_idx + 1,
+                line: line.to_owned(),
+            };
+
+            let (unit, code) = line.split_once('=').ok_or_else(invalid_entry)?;
+            let unit = u16::from_str_radix(unit.trim(), 16).map_err(|_| invalid_entry())?;
+            let (name, param_count) = match code.split_once(',') {
+                Some((name, param_count)) => (
+                    name.trim(),
+                    param_count.trim().parse().map_err(|_| invalid_entry())?,
+                ),
+                None => (code.trim(), 0),
+            };
+            if name.is_empty()
+                || is_hex_unit(name)
+                || name
+                    .contains(|ch: char| ch == ':' || ch == '{' || ch == '}' || ch.is_whitespace())
+            {
+                return Err(invalid_entry());
+            }
+            if codes.by_name.insert(name.to_owned(), unit).is_some() {
+                return Err(ParseControlCodesError::DuplicateName {
+                    name: name.to_owned(),
+                });
+            }
+            codes.by_unit.insert(
+                unit,
+                ControlCode {
+                    name: name.to_owned(),
+                    param_count,
+                },
+            );
+        }
+        Ok(codes)
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read control code file {path:?}"))?;
+        Self::parse(&contents)
+            .with_context(|| format!("failed to parse control code file {path:?}"))
+    }
+
+    /// Decodes a NUL-terminated UTF-16LE string from the start of `data`, escaping its control
+    /// codes.
+    pub fn decode(&self, data: &[u8]) -> Result<String, DecodeUtf16Error> {
+        let mut units = data
+            .chunks_exact(2)
+            .map(|ch| u16::from_le_bytes(ch.try_into().unwrap()));
+        let mut output = String::new();
+        let mut plain_units = Vec::new();
+        // Positions of the literal braces in the output, which may need escaping.
+        let mut brace_positions = Vec::new();
+        let mut flush =
+            |output: &mut String, plain_units: &mut Vec<u16>| -> Result<(), DecodeUtf16Error> {
+                for ch in char::decode_utf16(plain_units.drain(..)) {
+                    let ch = ch?;
+                    if ch == '{' {
+                        brace_positions.push(output.len());
+                    }
+                    output.push(ch);
+                }
+                Ok(())
+            };
+
+        while let Some(unit) = units.next() {
+            if unit == 0 {
+                break;
+            }
+            if let Some(code) = self.by_unit.get(&unit) {
+                flush(&mut output, &mut plain_units)?;
+                let params = units.by_ref().take(code.param_count).collect::<Vec<_>>();
+                output.push('{');
+                output.push_str(&code.name);
+                for (idx, param) in params.iter().enumerate() {
+                    output.push(if idx == 0 { ':' } else { ' ' });
+                    let _ = write!(output, "{param:04X}");
+                }
+                output.push('}');
+            } else if is_unnamed_control_code(unit) {
+                flush(&mut output, &mut plain_units)?;
+                let _ = write!(output, "{{{unit:04X}}}");
+            } else {
+                plain_units.push(unit);
+            }
+        }
+        flush(&mut output, &mut plain_units)?;
+
+        // Literal braces that would be read back as an escape are escaped themselves.
+        for position in brace_positions.into_iter().rev() {
+            if !matches!(self.parse_escape(&output[position..]), Ok(None)) {
+                output.replace_range(position..position + 1, "{007B}");
+            }
+        }
+        Ok(output)
+    }
+
+    /// Encodes a string to NUL-terminated UTF-16LE, turning its escapes back into control codes.
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, EncodeControlCodeError> {
+        let mut units = Vec::with_capacity(text.len() + 1);
+        let mut rest = text;
+        while let Some(ch) = rest.chars().next() {
+            if let Some((len, escaped_units)) = self.parse_escape(rest)? {
+                units.extend(escaped_units);
+                rest = &rest[len..];
+            } else {
+                units.extend(ch.encode_utf16(&mut [0; 2]).iter());
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+        units.push(0);
+        Ok(units.into_iter().flat_map(u16::to_le_bytes).collect())
+    }
+
+    /// Removes the escaped control codes of a string, keeping the characters that are drawn.
+    /// Escapes of printable characters, such as `{007B}`, are turned back into them.
+    pub fn printable_text(&self, text: &str) -> String {
+        let mut output = String::new();
+        let mut rest = text;
+        while let Some(ch) = rest.chars().next() {
+            if let Ok(Some((len, units))) = self.parse_escape(rest) {
+                let is_control_code = units.first().is_some_and(|&unit| {
+                    self.by_unit.contains_key(&unit) || is_unnamed_control_code(unit)
+                });
+                if !is_control_code {
+                    output.extend(char::decode_utf16(units).filter_map(Result::ok));
+                }
+                rest = &rest[len..];
+            } else {
+                output.push(ch);
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+        output
+    }
+
+    /// Reads a `{XXXX}`, `{name}` or `{name:XXXX ...}` escape at the start of the text given, if
+    /// there is one. Returns the length of text consumed and the units it stands for.
+    fn parse_escape(
+        &self,
+        text: &str,
+    ) -> Result<Option<(usize, Vec<u16>)>, EncodeControlCodeError> {
+        let Some(end) = text.strip_prefix('{').and_then(|rest| rest.find('}')) else {
+            return Ok(None);
+        };
+        let contents = &text[1..end + 1];
+        let len = end + 2;
+        if is_hex_unit(contents) {
+            return Ok(Some((
+                len,
+                vec![u16::from_str_radix(contents, 16).unwrap()],
+            )));
+        }
+
+        let (name, params) = contents.split_once(':').unwrap_or((contents, ""));
+        let Some((&unit, code)) = self
+            .by_name
+            .get(name)
+            .and_then(|unit| Some((unit, self.by_unit.get(unit)?)))
+        else {
+            return Ok(None);
+        };
+        let params = params
+            .split_whitespace()
+            .map(|param| {
+                is_hex_unit(param)
+                    .then(|| u16::from_str_radix(param, 16).unwrap())
+                    .ok_or_else(|| EncodeControlCodeError::InvalidParameters {
+                        params: params.to_owned(),
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if params.len() != code.param_count {
+            return Err(EncodeControlCodeError::ParameterCountMismatch {
+                name: name.to_owned(),
+                expected: code.param_count,
+                found: params.len(),
+            });
+        }
+        Ok(Some((len, [unit].into_iter().chain(params).collect())))
+    }
+}
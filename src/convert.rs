@@ -0,0 +1,346 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use clap::ValueEnum;
+
+#[cfg(any(feature = "graphics", feature = "audio"))]
+use crate::unpack;
+#[cfg(feature = "graphics")]
+use crate::{gfx, nftr, nsbtx};
+use crate::{
+    manifest::Codec,
+    narc::Narc,
+    plugin::Plugins,
+    survey,
+    text::{TextArchive, TextEncoding},
+    unpack::write_file,
+};
+#[cfg(feature = "audio")]
+use crate::{sseq, wave};
+
+/// The kinds of file [`convert`] can convert files to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Target {
+    /// PNG images, of graphics, textures and fonts
+    Png,
+    /// WAV files, of waves and streams
+    Wav,
+    /// MIDI files, of sequences
+    Mid,
+    /// JSON files, of text files and font metrics
+    Json,
+    /// The files of NARC archives, or the decompressed contents of any other file
+    Bin,
+}
+
+impl Target {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Target::Png => "png",
+            Target::Wav => "wav",
+            Target::Mid => "mid",
+            Target::Json => "json",
+            Target::Bin => "bin",
+        }
+    }
+}
+
+/// What [`convert`] needs besides the file converted.
+#[derive(Clone, Default)]
+pub struct ConvertOptions {
+    /// Encoding text files are read with.
+    pub encoding: TextEncoding,
+    /// Plugins recognizing the game's own formats when detecting the format of a file.
+    pub plugins: Plugins,
+    /// NCLR palette graphics are drawn with, optionally LZ10-compressed.
+    pub palette: Option<Vec<u8>>,
+}
+
+/// A conversion of files of a format to a kind of file.
+struct Converter {
+    /// Name of the format converted, as [`survey::identify_file`] names it.
+    format: &'static str,
+    target: Target,
+    /// Whether the result is a directory of files rather than a single file.
+    to_dir: bool,
+    /// Converts the decompressed contents of a file, writing the result to the path given, and
+    /// describes what was written.
+    convert: fn(&[u8], &ConvertOptions, &Path) -> anyhow::Result<String>,
+}
+
+/// Conversions known to [`convert`]. Converting to [`Target::Bin`] decompresses files of any
+/// other format.
+const CONVERTERS: &[Converter] = &[
+    Converter {
+        format: "NARC archive",
+        target: Target::Bin,
+        to_dir: true,
+        convert: narc_to_files,
+    },
+    #[cfg(feature = "graphics")]
+    Converter {
+        format: "NCGR graphics",
+        target: Target::Png,
+        to_dir: false,
+        convert: ncgr_to_png,
+    },
+    #[cfg(feature = "graphics")]
+    Converter {
+        format: "NSBTX texture archive",
+        target: Target::Png,
+        to_dir: true,
+        convert: nsbtx_to_png,
+    },
+    #[cfg(feature = "graphics")]
+    Converter {
+        format: "NFTR font",
+        target: Target::Png,
+        to_dir: false,
+        convert: nftr_to_png,
+    },
+    #[cfg(feature = "graphics")]
+    Converter {
+        format: "NFTR font",
+        target: Target::Json,
+        to_dir: false,
+        convert: nftr_to_json,
+    },
+    #[cfg(feature = "audio")]
+    Converter {
+        format: "SWAV wave",
+        target: Target::Wav,
+        to_dir: false,
+        convert: swav_to_wav,
+    },
+    #[cfg(feature = "audio")]
+    Converter {
+        format: "SWAR wave archive",
+        target: Target::Wav,
+        to_dir: true,
+        convert: swar_to_wav,
+    },
+    #[cfg(feature = "audio")]
+    Converter {
+        format: "STRM stream",
+        target: Target::Wav,
+        to_dir: false,
+        convert: strm_to_wav,
+    },
+    #[cfg(feature = "audio")]
+    Converter {
+        format: "SSEQ sequence",
+        target: Target::Mid,
+        to_dir: false,
+        convert: sseq_to_mid,
+    },
+    Converter {
+        format: "BMG message file",
+        target: Target::Json,
+        to_dir: false,
+        convert: text_to_json,
+    },
+    Converter {
+        format: "text file",
+        target: Target::Json,
+        to_dir: false,
+        convert: text_to_json,
+    },
+];
+
+/// Short name a format is given as on the command line, such as `ncgr` for NCGR graphics.
+fn format_key(format: &str) -> String {
+    format
+        .split(' ')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// Short names of the formats [`convert`] can convert from.
+pub fn source_formats() -> Vec<String> {
+    let mut keys = CONVERTERS
+        .iter()
+        .map(|converter| format_key(converter.format))
+        .collect::<Vec<_>>();
+    keys.dedup();
+    keys
+}
+
+/// Result of [`convert`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conversion {
+    /// What the file was converted from, including its compression.
+    pub source: String,
+    /// What was written.
+    pub description: String,
+    /// Path written to, a directory for formats holding several files.
+    pub output: PathBuf,
+}
+
+/// Converts a file to the kind of file given. Its format is detected as `identify` and
+/// `unpack` do, unless `from` names it, and it's decompressed first if it's compressed. If
+/// `output` isn't given, the result is placed alongside `path`, with the extension of the
+/// target or without one for directories, and decompressed contents get the `.decomp` extension
+/// as when unpacking.
+pub fn convert(
+    path: &Path,
+    output: Option<&Path>,
+    from: Option<&str>,
+    target: Target,
+    options: &ConvertOptions,
+) -> anyhow::Result<Conversion> {
+    let data = std::fs::read(path).with_context(|| format!("failed to read {path:?}"))?;
+    let (codec, contents) = Codec::detect(&data).unwrap_or((Codec::NONE, data));
+    let format = match from {
+        Some(key) => CONVERTERS
+            .iter()
+            .map(|converter| converter.format)
+            .find(|&format| format_key(format) == key.to_ascii_lowercase())
+            .ok_or_else(|| {
+                anyhow!(
+                    "unknown format {key:?}, expected one of: {}",
+                    source_formats().join(", ")
+                )
+            })?
+            .to_owned(),
+        None => survey::identify_file(&contents, &options.encoding, &options.plugins).format,
+    };
+    let source = if codec == Codec::NONE {
+        format.clone()
+    } else {
+        format!("compressed {codec} file, {format}")
+    };
+
+    let converter = CONVERTERS
+        .iter()
+        .find(|converter| converter.format == format && converter.target == target);
+    let Some(converter) = converter else {
+        if target == Target::Bin && codec != Codec::NONE {
+            let output = output.map_or_else(|| path.with_extension("decomp"), Path::to_owned);
+            write_file(&output, &contents)?;
+            return Ok(Conversion {
+                source,
+                description: format!("0x{:X} bytes", contents.len()),
+                output,
+            });
+        }
+        let targets = CONVERTERS
+            .iter()
+            .filter(|converter| converter.format == format)
+            .map(|converter| converter.target.extension())
+            .collect::<Vec<_>>();
+        return Err(if targets.is_empty() {
+            anyhow!("{source} can't be converted to {}", target.extension())
+        } else {
+            anyhow!(
+                "{source} can't be converted to {}, only to: {}",
+                target.extension(),
+                targets.join(", ")
+            )
+        });
+    };
+
+    let output = output.map_or_else(
+        || {
+            if converter.to_dir {
+                path.with_extension("")
+            } else {
+                path.with_extension(target.extension())
+            }
+        },
+        Path::to_owned,
+    );
+    let description = (converter.convert)(&contents, options, &output)
+        .with_context(|| format!("failed to convert {source}"))?;
+    Ok(Conversion {
+        source,
+        description,
+        output,
+    })
+}
+
+fn narc_to_files(data: &[u8], _: &ConvertOptions, output: &Path) -> anyhow::Result<String> {
+    let narc = Narc::parse(data)?;
+    crate::narc::extract(&narc, output, false)?;
+    Ok(format!("{} files", narc.files.len()))
+}
+
+#[cfg(feature = "graphics")]
+fn ncgr_to_png(data: &[u8], options: &ConvertOptions, output: &Path) -> anyhow::Result<String> {
+    let palette = options
+        .palette
+        .as_deref()
+        .context("graphics need an NCLR palette to be drawn with")?;
+    let ncgr = gfx::Ncgr::parse(data)?;
+    let nclr = unpack::parse_maybe_compressed(palette, gfx::Nclr::parse)
+        .context("failed to parse palette file")?;
+    let image = gfx::render_graphics(&ncgr, &nclr, 0);
+    write_file(output, &image.to_png()?)?;
+    Ok(format!("{}x{} image", image.width, image.height))
+}
+
+#[cfg(feature = "graphics")]
+fn nsbtx_to_png(data: &[u8], _: &ConvertOptions, output: &Path) -> anyhow::Result<String> {
+    let nsbtx = nsbtx::Nsbtx::parse(data)?;
+    let texture_count = unpack::write_textures(output, &nsbtx, false)?;
+    Ok(format!("{texture_count} textures"))
+}
+
+#[cfg(feature = "graphics")]
+fn nftr_to_png(data: &[u8], _: &ConvertOptions, output: &Path) -> anyhow::Result<String> {
+    let font = nftr::Nftr::parse(data)?;
+    write_file(output, &font.to_sheet_png()?)?;
+    Ok(format!("sheet of {} glyphs", font.glyphs.len()))
+}
+
+#[cfg(feature = "graphics")]
+fn nftr_to_json(data: &[u8], _: &ConvertOptions, output: &Path) -> anyhow::Result<String> {
+    let font = nftr::Nftr::parse(data)?;
+    write_file(output, &serde_json::to_vec_pretty(&font.description())?)?;
+    Ok(format!("metrics of {} glyphs", font.glyphs.len()))
+}
+
+#[cfg(feature = "audio")]
+fn wave_description(wave: &wave::Wave) -> String {
+    format!(
+        "{} samples at {} Hz",
+        wave.channels.first().map_or(0, Vec::len),
+        wave.sample_rate
+    )
+}
+
+#[cfg(feature = "audio")]
+fn swav_to_wav(data: &[u8], _: &ConvertOptions, output: &Path) -> anyhow::Result<String> {
+    let wave = wave::Wave::parse_swav(data)?;
+    unpack::write_wave(output, &wave, false)?;
+    Ok(wave_description(&wave))
+}
+
+#[cfg(feature = "audio")]
+fn swar_to_wav(data: &[u8], _: &ConvertOptions, output: &Path) -> anyhow::Result<String> {
+    let waves = wave::Wave::parse_swar(data)?;
+    for (index, wave) in waves.iter().enumerate() {
+        unpack::write_wave(&output.join(unpack::wave_file_name(index)), wave, false)?;
+    }
+    Ok(format!("{} waves", waves.len()))
+}
+
+#[cfg(feature = "audio")]
+fn strm_to_wav(data: &[u8], _: &ConvertOptions, output: &Path) -> anyhow::Result<String> {
+    let wave = wave::Wave::parse_strm(data)?;
+    unpack::write_wave(output, &wave, false)?;
+    Ok(wave_description(&wave))
+}
+
+#[cfg(feature = "audio")]
+fn sseq_to_mid(data: &[u8], _: &ConvertOptions, output: &Path) -> anyhow::Result<String> {
+    let midi = sseq::Sseq::parse(data).and_then(|sseq| sseq.to_midi())?;
+    write_file(output, &midi)?;
+    Ok("sequence".to_owned())
+}
+
+fn text_to_json(data: &[u8], options: &ConvertOptions, output: &Path) -> anyhow::Result<String> {
+    let strings = TextArchive::parse(data, &options.encoding)?.strings;
+    write_file(output, &serde_json::to_vec_pretty(&strings)?)?;
+    Ok(format!("{} strings", strings.len()))
+}
@@ -0,0 +1,142 @@
+/// Minimum length of a match worth copying from the source.
+const MIN_MATCH: usize = 12;
+/// Minimum length of a run of identical bytes worth storing as a run.
+const MIN_RUN: usize = 8;
+/// Source positions are indexed every `INDEX_STEP` bytes when looking for matches.
+const INDEX_STEP: usize = 16;
+/// Length of the key used to look up matches.
+const KEY_LEN: usize = 8;
+
+/// An operation producing part of the target data.
+pub enum Operation {
+    /// Literal bytes, taken from `target[start..end]`.
+    Add { start: usize, end: usize },
+    /// A byte repeated `len` times.
+    Run { byte: u8, len: usize },
+    /// `len` bytes copied from `source[address..]`.
+    Copy { address: usize, len: usize },
+}
+
+/// Index of the positions of `source` at every [`INDEX_STEP`] bytes, by the [`KEY_LEN`] bytes
+/// starting there.
+pub struct SourceIndex {
+    slots: Vec<u32>,
+    shift: u32,
+}
+
+fn key_at(data: &[u8], position: usize) -> Option<u64> {
+    data.get(position..position + KEY_LEN)
+        .map(|key| u64::from_le_bytes(key.try_into().unwrap()))
+}
+
+impl SourceIndex {
+    pub fn new(source: &[u8]) -> Self {
+        let slot_count = (source.len() / INDEX_STEP).next_power_of_two().max(1 << 10);
+        let mut index = Self {
+            slots: vec![u32::MAX; slot_count],
+            shift: 64 - slot_count.trailing_zeros(),
+        };
+        for position in (0..source.len().saturating_sub(KEY_LEN)).step_by(INDEX_STEP) {
+            let slot = index.slot(key_at(source, position).unwrap());
+            if index.slots[slot] == u32::MAX {
+                index.slots[slot] = position as u32;
+            }
+        }
+        index
+    }
+
+    fn slot(&self, key: u64) -> usize {
+        (key.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> self.shift) as usize
+    }
+
+    fn get(&self, key: u64) -> Option<usize> {
+        let position = self.slots[self.slot(key)];
+        (position != u32::MAX).then_some(position as usize)
+    }
+}
+
+/// Finds the operations producing `target[start..end]` out of `source`, which `index` must have
+/// been built from.
+pub fn find_operations(
+    source: &[u8],
+    index: &SourceIndex,
+    target: &[u8],
+    start: usize,
+    end: usize,
+) -> Vec<Operation> {
+    let mut operations = Vec::new();
+    let mut add_start = start;
+    let mut position = start;
+    let mut last_copy_end = 0;
+    while position + MIN_MATCH <= end {
+        let Some(key) = key_at(target, position) else {
+            break;
+        };
+        // Most edits keep data where it was, or shift it by the same amount as the last match.
+        let candidates = [Some(position), Some(last_copy_end), index.get(key)];
+        let mut best: Option<(usize, usize, usize)> = None;
+        for candidate in candidates.into_iter().flatten() {
+            if key_at(source, candidate) != Some(key) {
+                continue;
+            }
+            let forward = source[candidate..]
+                .iter()
+                .zip(&target[position..end])
+                .take_while(|(a, b)| a == b)
+                .count();
+            let backward = (1..=(position - add_start).min(candidate))
+                .take_while(|&back| source[candidate - back] == target[position - back])
+                .count();
+            if best.is_none_or(|(_, _, len)| forward + backward > len) {
+                best = Some((
+                    candidate - backward,
+                    position - backward,
+                    forward + backward,
+                ));
+            }
+        }
+
+        match best {
+            Some((address, copy_start, len)) if len >= MIN_MATCH => {
+                push_literal(&mut operations, target, add_start, copy_start);
+                operations.push(Operation::Copy { address, len });
+                position = copy_start + len;
+                add_start = position;
+                last_copy_end = address + len;
+            }
+            _ => position += 1,
+        }
+    }
+    push_literal(&mut operations, target, add_start, end);
+    operations
+}
+
+/// Adds the operations producing literal data, using RUNs for long runs of identical bytes.
+fn push_literal(operations: &mut Vec<Operation>, target: &[u8], start: usize, end: usize) {
+    let mut add_start = start;
+    let mut position = start;
+    while position < end {
+        let byte = target[position];
+        let len = target[position..end]
+            .iter()
+            .take_while(|&&other| other == byte)
+            .count();
+        if len >= MIN_RUN {
+            if add_start < position {
+                operations.push(Operation::Add {
+                    start: add_start,
+                    end: position,
+                });
+            }
+            operations.push(Operation::Run { byte, len });
+            add_start = position + len;
+        }
+        position += len;
+    }
+    if add_start < end {
+        operations.push(Operation::Add {
+            start: add_start,
+            end,
+        });
+    }
+}
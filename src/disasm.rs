@@ -0,0 +1,165 @@
+use std::{collections::HashMap, fmt::Write, ops::Range};
+
+use yaxpeax_arch::{Decoder, U8Reader};
+use yaxpeax_arm::armv7::{InstDecoder, Opcode, Operand};
+
+use crate::symbols::SymbolMap;
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().unwrap(),
+    ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().unwrap(),
+    ))
+}
+
+/// Sign-extends the lowest `bits` bits of a value.
+fn sign_extend(value: u32, bits: u32) -> i32 {
+    ((value << (32 - bits)) as i32) >> (32 - bits)
+}
+
+/// Where a branch goes, worked out from the encoding itself, as the decoder doesn't give
+/// targets relative to the same base for every kind of branch.
+fn branch_target(code: &[u8], offset: usize, address: u32, thumb: bool) -> Option<u32> {
+    if !thumb {
+        let word = u32_at(code, offset)?;
+        if (word >> 25) & 0b111 != 0b101 {
+            return None;
+        }
+        let target = address
+            .wrapping_add(8)
+            .wrapping_add_signed(sign_extend(word & 0xFF_FFFF, 24) << 2);
+        // BLX to Thumb code may land on a halfword.
+        return Some(if word >> 28 == 0xF {
+            target + ((word >> 24) & 1) * 2
+        } else {
+            target
+        });
+    }
+    let halfword = u16_at(code, offset)? as u32;
+    let pc = address.wrapping_add(4);
+    match halfword >> 11 {
+        0b11010 | 0b11011 if (halfword >> 8) & 0xF < 0xE => {
+            Some(pc.wrapping_add_signed(sign_extend(halfword & 0xFF, 8) << 1))
+        }
+        0b11100 => Some(pc.wrapping_add_signed(sign_extend(halfword & 0x7FF, 11) << 1)),
+        0b11110 => {
+            let low = u16_at(code, offset + 2)? as u32;
+            let target = pc.wrapping_add_signed(
+                sign_extend(halfword & 0x7FF, 11) << 12 | ((low & 0x7FF) << 1) as i32,
+            );
+            match low >> 11 {
+                0b11111 => Some(target),
+                // BLX switches to ARM code, which is word-aligned.
+                0b11101 => Some(target & !3),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Disassembles the part of a binary given, as ARM or Thumb code, one instruction per line. The
+/// binary is loaded at `address`, and the part is given as offsets into it.
+///
+/// Lines show the address, encoding and instruction, with branch targets as addresses and the
+/// values loaded from literal pools next to the loads. Symbols from the map label the lines
+/// they're at and the addresses shown, as long as they belong to the static code or to the
+/// overlay given.
+pub fn disassemble(
+    binary: &[u8],
+    address: u32,
+    part: Range<usize>,
+    thumb: bool,
+    symbols: &SymbolMap,
+    overlay_id: Option<u32>,
+) -> String {
+    let mut names: HashMap<u32, Vec<&str>> = HashMap::new();
+    for (name, symbol) in symbols.iter() {
+        if symbol.overlay_id.is_none() || symbol.overlay_id == overlay_id {
+            // Thumb function addresses have their lowest bit set.
+            names.entry(symbol.address & !1).or_default().push(name);
+        }
+    }
+    for names in names.values_mut() {
+        names.sort_unstable();
+    }
+    let describe = |target: u32| match names.get(&(target & !1)) {
+        Some(names) => format!("0x{target:08X} <{}>", names[0]),
+        None => format!("0x{target:08X}"),
+    };
+
+    let decoder = InstDecoder::default().with_thumb_mode(thumb);
+    let mut output = String::new();
+    let code = &binary[..part.end];
+    let mut offset = part.start;
+    while offset < code.len() {
+        let here = address.wrapping_add(offset as u32);
+        for name in names.get(&here).into_iter().flatten() {
+            let _ = writeln!(output, "{name}:");
+        }
+
+        let decoded = decoder.decode(&mut U8Reader::new(&code[offset..]));
+        let size = match &decoded {
+            Ok(instruction) if thumb && instruction.wide => 4,
+            _ if thumb => 2,
+            _ => 4,
+        };
+        // Trailing bytes too few to hold an instruction.
+        let size = if code.len() - offset < size { 1 } else { size };
+        let encoding = match size {
+            4 if thumb => format!(
+                "{:04X} {:04X}",
+                u16_at(code, offset).unwrap(),
+                u16_at(code, offset + 2).unwrap()
+            ),
+            4 => format!("{:08X}", u32_at(code, offset).unwrap()),
+            2 => format!("{:04X}", u16_at(code, offset).unwrap()),
+            _ => format!("{:02X}", code[offset]),
+        };
+
+        let text = match decoded {
+            Ok(instruction) if size > 1 => {
+                let mut text = instruction.to_string();
+                if let Some(target) = branch_target(code, offset, here, thumb) {
+                    // The decoder shows branch targets as offsets, as in `bl $+0x100`.
+                    if let Some(position) = text.find('$') {
+                        text.replace_range(position.., &describe(target));
+                    }
+                    if let Some(position) = text.find(".w ") {
+                        text.replace_range(position..position + 2, "");
+                    }
+                }
+                if let (Opcode::LDR, Operand::RegDerefPreindexOffset(base, imm, add, _)) =
+                    (instruction.opcode, instruction.operands[1])
+                {
+                    if base.number() == 15 {
+                        let pc = here.wrapping_add(if thumb { 4 } else { 8 }) & !3;
+                        let literal = if add {
+                            pc.wrapping_add(imm as u32)
+                        } else {
+                            pc.wrapping_sub(imm as u32)
+                        };
+                        let value = literal
+                            .checked_sub(address)
+                            .and_then(|literal_offset| u32_at(binary, literal_offset as usize));
+                        if let Some(value) = value {
+                            let _ = write!(text, " ; ={}", describe(value));
+                        }
+                    }
+                }
+                text
+            }
+            _ if size == 4 => format!(".word 0x{:08X}", u32_at(code, offset).unwrap()),
+            _ if size == 2 => format!(".hword 0x{:04X}", u16_at(code, offset).unwrap()),
+            _ => format!(".byte 0x{:02X}", code[offset]),
+        };
+        let _ = writeln!(output, "{here:08X}  {encoding:<9}  {text}");
+        offset += size;
+    }
+    output
+}
@@ -0,0 +1,244 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Context};
+use thiserror::Error;
+
+use crate::text::{self, TextEncoding};
+
+/// Templates bundled with the software, by name.
+const PRESETS: [(&str, &str); 3] = [
+    ("plain", include_str!("templates/plain.tmpl")),
+    ("markdown", include_str!("templates/markdown.tmpl")),
+    ("atlas", include_str!("templates/atlas.tmpl")),
+];
+
+const ENTRY_START: &str = "{{#entry}}";
+const ENTRY_END: &str = "{{/entry}}";
+
+#[derive(Error, Debug)]
+pub enum ParseEntryTemplateError {
+    #[error("unterminated placeholder {0:?}")]
+    UnterminatedPlaceholder(String),
+    #[error("unknown placeholder {0:?}")]
+    UnknownPlaceholder(String),
+    #[error("unknown filter {0:?}")]
+    UnknownFilter(String),
+    #[error("placeholder {0:?} can only be used inside the {{{{#entry}}}} block")]
+    OutsideEntry(String),
+    #[error("{{{{#entry}}}} block without a matching {{{{/entry}}}}")]
+    UnterminatedEntry,
+}
+
+/// Values a placeholder stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Index,
+    Text,
+    Original,
+    /// Name of the text file exported.
+    File,
+    /// Size of the encoded string in bytes, terminator included.
+    Length,
+}
+
+/// How a value is escaped before being placed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Filter {
+    /// Placed as it is.
+    Raw,
+    /// Line breaks written as `\n`.
+    Oneline,
+    /// For Markdown table cells: pipes escaped, and line breaks written as `<br>`.
+    Markdown,
+    /// Escaped as the text blocks of the text entry template format are.
+    Escaped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder(Field, Filter),
+}
+
+/// A template strings are exported with, made of a part repeated for each string between
+/// `{{#entry}}` and `{{/entry}}`, and the parts before and after it, written once. Templates
+/// without an entry block are repeated whole.
+///
+/// Placeholders have the form `{{name}}` or `{{name|filter}}`. The names are `index`, `text`,
+/// `original`, `length` (the size of the encoded string in bytes) and `file` (the name of the
+/// text file), the only one available outside the entry block. The filters are `oneline`,
+/// writing line breaks as `\n`, `markdown`, escaping the value for a Markdown table cell, and
+/// `escaped`, escaping it as the text entry template format does. Tags alone at the end of a
+/// line take the line break with them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryTemplate {
+    header: Vec<Segment>,
+    entry: Vec<Segment>,
+    footer: Vec<Segment>,
+}
+
+fn parse_segments(contents: &str, in_entry: bool) -> Result<Vec<Segment>, ParseEntryTemplateError> {
+    let mut segments = Vec::new();
+    let mut rest = contents;
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            segments.push(Segment::Literal(rest[..start].to_owned()));
+        }
+        let end = rest[start..].find("}}").ok_or_else(|| {
+            let placeholder = rest[start..].lines().next().unwrap_or_default();
+            ParseEntryTemplateError::UnterminatedPlaceholder(placeholder.to_owned())
+        })?;
+        let placeholder = rest[start + 2..start + end].trim();
+        let (name, filter) = placeholder.split_once('|').unwrap_or((placeholder, "raw"));
+        let field = match name.trim() {
+            "index" => Field::Index,
+            "text" => Field::Text,
+            "original" => Field::Original,
+            "file" => Field::File,
+            "length" => Field::Length,
+            name => return Err(ParseEntryTemplateError::UnknownPlaceholder(name.to_owned())),
+        };
+        if !in_entry && field != Field::File {
+            return Err(ParseEntryTemplateError::OutsideEntry(
+                name.trim().to_owned(),
+            ));
+        }
+        let filter = match filter.trim() {
+            "raw" => Filter::Raw,
+            "oneline" => Filter::Oneline,
+            "markdown" => Filter::Markdown,
+            "escaped" => Filter::Escaped,
+            filter => return Err(ParseEntryTemplateError::UnknownFilter(filter.to_owned())),
+        };
+        segments.push(Segment::Placeholder(field, filter));
+        rest = &rest[start + end + 2..];
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Literal(rest.to_owned()));
+    }
+    Ok(segments)
+}
+
+/// Strips the line break following a tag, if it's right after it.
+fn after_tag(text: &str) -> &str {
+    text.strip_prefix("\r\n")
+        .or_else(|| text.strip_prefix('\n'))
+        .unwrap_or(text)
+}
+
+impl EntryTemplate {
+    pub fn parse(contents: &str) -> Result<Self, ParseEntryTemplateError> {
+        let Some(start) = contents.find(ENTRY_START) else {
+            return Ok(Self {
+                header: Vec::new(),
+                entry: parse_segments(contents, true)?,
+                footer: Vec::new(),
+            });
+        };
+        let entry_start = start + ENTRY_START.len();
+        let end = contents[entry_start..]
+            .find(ENTRY_END)
+            .ok_or(ParseEntryTemplateError::UnterminatedEntry)?
+            + entry_start;
+        let entry = after_tag(&contents[entry_start..end]);
+        let footer = after_tag(&contents[end + ENTRY_END.len()..]);
+        Ok(Self {
+            header: parse_segments(&contents[..start], false)?,
+            entry: parse_segments(entry, true)?,
+            footer: parse_segments(footer, false)?,
+        })
+    }
+
+    /// Names of the templates bundled with the software.
+    pub fn preset_names() -> impl Iterator<Item = &'static str> {
+        PRESETS.iter().map(|(name, _)| *name)
+    }
+
+    /// Loads a template file, or a bundled template if `name` is the name of one and no file
+    /// has it as its path.
+    pub fn load(name: &Path) -> anyhow::Result<Self> {
+        let contents = if name.exists() {
+            fs::read_to_string(name).with_context(|| format!("failed to read template {name:?}"))?
+        } else {
+            PRESETS
+                .iter()
+                .find(|(preset, _)| Path::new(preset) == name)
+                .map(|(_, contents)| contents.to_string())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "no template file at {name:?}, nor bundled template with that name (bundled templates: {})",
+                        Self::preset_names().collect::<Vec<_>>().join(", ")
+                    )
+                })?
+        };
+        Self::parse(&contents).with_context(|| format!("failed to parse template {name:?}"))
+    }
+
+    /// Exports the strings of the text file named `file`, along with the original strings they
+    /// are a translation of, which are the strings themselves if `None`.
+    pub fn render(
+        &self,
+        file: &str,
+        strings: &[String],
+        originals: Option<&[String]>,
+        encoding: &TextEncoding,
+    ) -> String {
+        let originals = originals.unwrap_or(strings);
+        let mut output = String::new();
+        render_segments(&mut output, &self.header, file, None);
+        for (index, text) in strings.iter().enumerate() {
+            let entry = EntryValues {
+                index,
+                text,
+                original: originals.get(index).unwrap_or(text),
+                length: encoding
+                    .encode(text, index)
+                    .map_or(0, |encoded| encoded.len()),
+            };
+            render_segments(&mut output, &self.entry, file, Some(&entry));
+        }
+        render_segments(&mut output, &self.footer, file, None);
+        output
+    }
+}
+
+struct EntryValues<'a> {
+    index: usize,
+    text: &'a str,
+    original: &'a str,
+    length: usize,
+}
+
+fn render_segments(
+    output: &mut String,
+    segments: &[Segment],
+    file: &str,
+    entry: Option<&EntryValues>,
+) {
+    for segment in segments {
+        let (field, filter) = match segment {
+            Segment::Literal(literal) => {
+                output.push_str(literal);
+                continue;
+            }
+            Segment::Placeholder(field, filter) => (field, filter),
+        };
+        // Only the file name is available outside entries, as parsing checks.
+        let value = match (field, entry) {
+            (Field::File, _) => file.to_owned(),
+            (Field::Index, Some(entry)) => entry.index.to_string(),
+            (Field::Text, Some(entry)) => entry.text.to_owned(),
+            (Field::Original, Some(entry)) => entry.original.to_owned(),
+            (Field::Length, Some(entry)) => entry.length.to_string(),
+            (_, None) => String::new(),
+        };
+        match filter {
+            Filter::Raw => output.push_str(&value),
+            Filter::Oneline => output.push_str(&value.replace('\n', "\\n")),
+            Filter::Markdown => {
+                output.push_str(&value.replace('|', "\\|").replace('\n', "<br>"));
+            }
+            Filter::Escaped => output.push_str(&text::escape_template_text(&value)),
+        }
+    }
+}
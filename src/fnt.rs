@@ -0,0 +1,287 @@
+use std::{collections::BTreeMap, ops::Range};
+
+use thiserror::Error;
+
+use crate::path_escape;
+
+/// ID of the root directory. Subsequent directories are numbered upwards from it.
+pub const ROOT_DIR_ID: u16 = 0xF000;
+/// Maximum length of a file or directory name in the FNT.
+const MAX_NAME_LEN: usize = 0x7F;
+/// Maximum number of directories the FNT can hold.
+const MAX_DIR_COUNT: usize = 0x1000;
+
+#[derive(Error, Debug)]
+pub enum BuildFntError {
+    #[error("name too long for the FNT (found: {name:?}, maximum: 127 bytes)")]
+    NameTooLong { name: String },
+    #[error("empty name found in path {path:?}")]
+    EmptyName { path: String },
+    #[error("too many directories for the FNT (maximum: 4096)")]
+    TooManyDirectories,
+    #[error("too many files for the FAT")]
+    TooManyFiles,
+    #[error("{path:?} is both a file and a directory")]
+    FileDirectoryConflict { path: String },
+}
+
+#[derive(Debug, Default)]
+struct DirNode {
+    /// Files in this directory, with their preferred file IDs.
+    files: BTreeMap<String, Option<u16>>,
+    dirs: BTreeMap<String, DirNode>,
+}
+
+pub fn child_path(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{parent}/{name}")
+    }
+}
+
+/// A file that could not keep its preferred ID when building the FNT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Renumbered {
+    pub path: String,
+    pub preferred_id: u16,
+    pub assigned_id: u16,
+}
+
+/// A File Name Table built from a set of NitroFS paths.
+#[derive(Debug)]
+pub struct BuiltFnt {
+    /// The serialized FNT.
+    pub data: Vec<u8>,
+    /// The file ID assigned to each of the paths given, in file ID order.
+    pub file_ids: Vec<(String, u16)>,
+    /// Files that were given a different ID than the one they preferred.
+    pub renumbered: Vec<Renumbered>,
+}
+
+/// Builds a FNT holding the paths given.
+///
+/// Files and directories keep their preferred IDs whenever the FNT layout allows it: the files
+/// of a directory must have consecutive IDs, and directory IDs must be consecutive from
+/// [`ROOT_DIR_ID`]. New files & directories (those without a preferred ID) are numbered after
+/// the existing ones, in name order. File IDs start at `first_file_id`.
+pub fn build_fnt<'a>(
+    files: impl IntoIterator<Item = (&'a str, Option<u16>)>,
+    preferred_dir_ids: &BTreeMap<String, u16>,
+    first_file_id: u16,
+) -> Result<BuiltFnt, BuildFntError> {
+    let mut root = DirNode::default();
+    for (path, preferred_id) in files {
+        let components = path
+            .split('/')
+            .filter(|c| !c.is_empty())
+            .collect::<Vec<_>>();
+        let Some((file_name, dir_names)) = components.split_last() else {
+            return Err(BuildFntError::EmptyName {
+                path: path.to_owned(),
+            });
+        };
+        if let Some(name) = components
+            .iter()
+            .find(|name| path_escape::encode_name(name).len() > MAX_NAME_LEN)
+        {
+            return Err(BuildFntError::NameTooLong {
+                name: name.to_string(),
+            });
+        }
+
+        let mut node = &mut root;
+        for dir_name in dir_names {
+            node = node.dirs.entry(dir_name.to_string()).or_default();
+        }
+        node.files.insert(file_name.to_string(), preferred_id);
+    }
+
+    // Flatten the tree in depth-first order, then sort the directories by their preferred ID
+    // (keeping the root first) to assign them their final IDs.
+    let mut dirs: Vec<(&DirNode, String)> = Vec::new();
+    fn flatten<'n>(node: &'n DirNode, path: String, dirs: &mut Vec<(&'n DirNode, String)>) {
+        dirs.push((node, path.clone()));
+        for (name, child) in &node.dirs {
+            flatten(child, child_path(&path, name), dirs);
+        }
+    }
+    flatten(&root, String::new(), &mut dirs);
+    if dirs.len() > MAX_DIR_COUNT {
+        return Err(BuildFntError::TooManyDirectories);
+    }
+    dirs[1..].sort_by_key(|(_, path)| preferred_dir_ids.get(path).copied().unwrap_or(u16::MAX));
+    let dir_ids: BTreeMap<&str, u16> = dirs
+        .iter()
+        .enumerate()
+        .map(|(idx, (_, path))| (path.as_str(), ROOT_DIR_ID + idx as u16))
+        .collect();
+
+    // Directories holding files with preferred IDs claim their ranges first, in ID order.
+    let min_preferred_file_id = |idx: usize| dirs[idx].0.files.values().flatten().min().copied();
+    let mut dirs_by_file_id = (0..dirs.len()).collect::<Vec<_>>();
+    dirs_by_file_id.sort_by_key(|&idx| {
+        let min_preferred = min_preferred_file_id(idx);
+        (min_preferred.is_none(), min_preferred)
+    });
+
+    let mut renumbered = Vec::new();
+    let mut file_ids = Vec::new();
+    let mut dir_files = vec![Vec::new(); dirs.len()];
+    let mut dir_start_ids = vec![0u16; dirs.len()];
+    let mut next_file_id = first_file_id as usize;
+    for idx in dirs_by_file_id {
+        let (node, path) = &dirs[idx];
+        if let Some(min_preferred) = min_preferred_file_id(idx) {
+            next_file_id = next_file_id.max(min_preferred as usize);
+        }
+        let mut names = node.files.iter().collect::<Vec<_>>();
+        names.sort_by_key(|(_, preferred_id)| preferred_id.unwrap_or(u16::MAX));
+
+        dir_start_ids[idx] = next_file_id as u16;
+        for (name, preferred_id) in names {
+            let file_path = child_path(path, name);
+            if node.dirs.contains_key(name) {
+                return Err(BuildFntError::FileDirectoryConflict { path: file_path });
+            }
+            if next_file_id >= ROOT_DIR_ID as usize {
+                return Err(BuildFntError::TooManyFiles);
+            }
+            let file_id = next_file_id as u16;
+            if let Some(preferred_id) = *preferred_id {
+                if preferred_id != file_id {
+                    renumbered.push(Renumbered {
+                        path: file_path.clone(),
+                        preferred_id,
+                        assigned_id: file_id,
+                    });
+                }
+            }
+            dir_files[idx].push(name.as_str());
+            file_ids.push((file_path, file_id));
+            next_file_id += 1;
+        }
+    }
+    file_ids.sort_by_key(|(_, file_id)| *file_id);
+
+    let mut main_table = Vec::with_capacity(dirs.len() * 8);
+    let mut sub_tables = Vec::new();
+    for (idx, (node, path)) in dirs.iter().enumerate() {
+        let sub_table_offset = dirs.len() * 8 + sub_tables.len();
+        main_table.extend_from_slice(&(sub_table_offset as u32).to_le_bytes());
+        main_table.extend_from_slice(&dir_start_ids[idx].to_le_bytes());
+        let parent_value = if idx == 0 {
+            dirs.len() as u16
+        } else {
+            let parent_path = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+            dir_ids[parent_path]
+        };
+        main_table.extend_from_slice(&parent_value.to_le_bytes());
+
+        for name in &dir_files[idx] {
+            let name = path_escape::encode_name(name);
+            sub_tables.push(name.len() as u8);
+            sub_tables.extend_from_slice(&name);
+        }
+        let mut subdirs = node
+            .dirs
+            .keys()
+            .map(|name| (dir_ids[child_path(path, name).as_str()], name))
+            .collect::<Vec<_>>();
+        subdirs.sort();
+        for (dir_id, name) in subdirs {
+            let name = path_escape::encode_name(name);
+            sub_tables.push(0x80 | name.len() as u8);
+            sub_tables.extend_from_slice(&name);
+            sub_tables.extend_from_slice(&dir_id.to_le_bytes());
+        }
+        sub_tables.push(0);
+    }
+
+    main_table.extend_from_slice(&sub_tables);
+    Ok(BuiltFnt {
+        data: main_table,
+        file_ids,
+        renumbered,
+    })
+}
+
+#[derive(Error, Debug)]
+pub enum ParseFntError {
+    #[error("FNT is truncated")]
+    Truncated,
+    #[error("invalid directory ID found (0x{id:04X})")]
+    InvalidDirectoryId { id: u16 },
+    #[error("directory 0x{id:04X} is referenced more than once")]
+    DirectoryLoop { id: u16 },
+}
+
+/// The contents of a parsed File Name Table.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedFnt {
+    /// Every file in the FNT with its file ID, in file ID order.
+    pub files: Vec<(String, u16)>,
+    /// Every directory in the FNT with its directory ID, in directory ID order.
+    pub directories: Vec<(String, u16)>,
+    /// Location of the raw name of every file & directory in the data parsed.
+    pub names: Vec<Range<usize>>,
+}
+
+/// Parses a File Name Table, validating every offset & ID in it.
+pub fn parse_fnt(data: &[u8]) -> Result<ParsedFnt, ParseFntError> {
+    let u16_at = |offset: usize| {
+        data.get(offset..offset + 2)
+            .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+            .ok_or(ParseFntError::Truncated)
+    };
+    let dir_count = u16_at(6)? as usize;
+    if dir_count == 0 || dir_count > MAX_DIR_COUNT || data.len() < dir_count * 8 {
+        return Err(ParseFntError::Truncated);
+    }
+
+    let mut parsed = ParsedFnt::default();
+    let mut visited = vec![false; dir_count];
+    // Directories still to walk, with their path.
+    let mut pending = vec![(ROOT_DIR_ID, String::new())];
+    while let Some((dir_id, path)) = pending.pop() {
+        let idx = dir_id.wrapping_sub(ROOT_DIR_ID) as usize;
+        if idx >= dir_count {
+            return Err(ParseFntError::InvalidDirectoryId { id: dir_id });
+        }
+        if std::mem::replace(&mut visited[idx], true) {
+            return Err(ParseFntError::DirectoryLoop { id: dir_id });
+        }
+        parsed.directories.push((path.clone(), dir_id));
+
+        let mut offset =
+            u32::from_le_bytes(data[idx * 8..idx * 8 + 4].try_into().unwrap()) as usize;
+        let mut file_id = u16_at(idx * 8 + 4)?;
+        loop {
+            let len = *data.get(offset).ok_or(ParseFntError::Truncated)? as usize;
+            offset += 1;
+            if len == 0 {
+                break;
+            }
+            let name_len = len & 0x7F;
+            let name = data
+                .get(offset..offset + name_len)
+                .ok_or(ParseFntError::Truncated)?;
+            let name = path_escape::decode_name(name);
+            parsed.names.push(offset..offset + name_len);
+            offset += name_len;
+
+            if len & 0x80 != 0 {
+                let child_id = u16_at(offset)?;
+                offset += 2;
+                pending.push((child_id, child_path(&path, &name)));
+            } else {
+                parsed.files.push((child_path(&path, &name), file_id));
+                file_id = file_id.wrapping_add(1);
+            }
+        }
+    }
+
+    parsed.files.sort_by_key(|(_, file_id)| *file_id);
+    parsed.directories.sort_by_key(|(_, dir_id)| *dir_id);
+    Ok(parsed)
+}
@@ -0,0 +1,212 @@
+use std::fmt;
+
+use crate::{
+    lz::CompressionLevel,
+    manifest::Processor,
+    memory::{self, Binary, MapAddressError},
+    overlay, rom,
+};
+
+/// Smallest region reported as free space by default. Shorter runs of padding bytes are common
+/// in code and data.
+pub const DEFAULT_MIN_SIZE: usize = 0x20;
+
+/// What makes a region of memory free.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FreeKind {
+    /// A run of `0x00` or `0xFF` bytes inside a binary, such as the padding between functions.
+    /// Arrays of zeroes that are part of the program look the same, so the space should be
+    /// checked before relying on it.
+    Padding,
+    /// Memory past the end of an overlay and its BSS, up to the end of the largest overlay
+    /// sharing its memory. The overlay grows into it when it's used.
+    PastBss,
+}
+
+impl fmt::Display for FreeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FreeKind::Padding => "padding",
+            FreeKind::PastBss => "past BSS",
+        })
+    }
+}
+
+/// A region of memory code and data can be placed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FreeRegion {
+    /// The binary the region belongs to, which must be loaded for the region to be usable.
+    pub binary: Binary,
+    /// RAM address of the region.
+    pub address: u32,
+    pub size: usize,
+    pub kind: FreeKind,
+}
+
+/// Finds the free space of the ARM9 binary and overlays: runs of at least `min_size` padding
+/// bytes, starting at a word boundary, and the memory overlays sharing their memory with a
+/// larger one leave unused past their BSS.
+pub fn find_free_space(
+    rom_data: &[u8],
+    min_size: usize,
+) -> Result<Vec<FreeRegion>, MapAddressError> {
+    let overlays = overlay::overlay_table(rom_data, Processor::Arm9);
+    let mut binaries = vec![Binary::Arm9];
+    binaries.extend(overlays.iter().map(|entry| Binary::Overlay(entry.id)));
+
+    let mut regions = Vec::new();
+    for binary in binaries {
+        let loaded = memory::loaded_binary(rom_data, binary)?;
+        let mut offset = 0;
+        while offset < loaded.data.len() {
+            let byte = loaded.data[offset];
+            let run = loaded.data[offset..]
+                .iter()
+                .take_while(|&&other| other == byte)
+                .count();
+            let start = rom::align_up(offset, 4);
+            let end = offset + run;
+            if matches!(byte, 0x00 | 0xFF) && end >= start + min_size.max(1) {
+                regions.push(FreeRegion {
+                    binary,
+                    address: loaded.address + start as u32,
+                    size: end - start,
+                    kind: FreeKind::Padding,
+                });
+            }
+            offset = end;
+        }
+    }
+
+    let end = |entry: &overlay::OverlayEntry| {
+        entry.ram_address as usize + entry.ram_size as usize + entry.bss_size as usize
+    };
+    for entry in &overlays {
+        let start = rom::align_up(end(entry), 4);
+        let slot_end = overlays
+            .iter()
+            .filter(|other| {
+                (other.ram_address as usize) < start && (entry.ram_address as usize) < end(other)
+            })
+            .map(end)
+            .max()
+            .unwrap_or(start);
+        if slot_end >= start + min_size.max(1) {
+            regions.push(FreeRegion {
+                binary: Binary::Overlay(entry.id),
+                address: start as u32,
+                size: slot_end - start,
+                kind: FreeKind::PastBss,
+            });
+        }
+    }
+    Ok(regions)
+}
+
+/// Space handed out by an [`Allocator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation {
+    /// The binary the space belongs to.
+    pub binary: Binary,
+    pub address: u32,
+    pub kind: FreeKind,
+}
+
+/// Hands out free space to place code and data in, such as the strings `insert-strings` moves
+/// or the blocks of assembly patches following a `.freespace` directive.
+#[derive(Debug, Clone, Default)]
+pub struct Allocator {
+    regions: Vec<FreeRegion>,
+    /// Bytes used from the start of each region.
+    used: Vec<usize>,
+}
+
+impl Allocator {
+    pub fn new(regions: Vec<FreeRegion>) -> Self {
+        let used = vec![0; regions.len()];
+        Self { regions, used }
+    }
+
+    /// Allocates `size` bytes aligned to `alignment`, in memory loaded whenever `user` is: in
+    /// `user` itself, or else in the ARM9 binary, which is always loaded. Returns `None` if no
+    /// region has room for it.
+    pub fn allocate(&mut self, size: usize, alignment: usize, user: Binary) -> Option<Allocation> {
+        let candidates =
+            self.regions
+                .iter()
+                .enumerate()
+                .filter(|(_, region)| region.binary == user)
+                .chain(
+                    self.regions.iter().enumerate().filter(|(_, region)| {
+                        region.binary == Binary::Arm9 && user != Binary::Arm9
+                    }),
+                )
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>();
+        candidates.into_iter().find_map(|index| {
+            let region = &self.regions[index];
+            let start = rom::align_up(region.address as usize + self.used[index], alignment.max(1));
+            let end = start + size;
+            if end > region.address as usize + region.size {
+                return None;
+            }
+            self.used[index] = end - region.address as usize;
+            Some(Allocation {
+                binary: region.binary,
+                address: start as u32,
+                kind: region.kind,
+            })
+        })
+    }
+
+    /// The space left in each region.
+    pub fn remaining(&self) -> Vec<FreeRegion> {
+        self.regions
+            .iter()
+            .zip(&self.used)
+            .filter(|(region, &used)| used < region.size)
+            .map(|(region, &used)| FreeRegion {
+                address: region.address + used as u32,
+                size: region.size - used,
+                ..*region
+            })
+            .collect()
+    }
+
+    /// Bytes handed out, counting the padding aligning them.
+    pub fn used(&self) -> usize {
+        self.used.iter().sum()
+    }
+
+    /// Bytes left.
+    pub fn left(&self) -> usize {
+        self.remaining().iter().map(|region| region.size).sum()
+    }
+
+    /// Grows the overlays whose memory past their BSS was handed out to cover it, so that what
+    /// is placed there gets loaded. Their BSS is stored as zeroes instead.
+    pub fn grow_overlays(
+        &self,
+        rom_data: &mut Vec<u8>,
+        level: CompressionLevel,
+    ) -> anyhow::Result<()> {
+        let overlays = overlay::overlay_table(rom_data, Processor::Arm9);
+        for (region, &used) in self.regions.iter().zip(&self.used) {
+            let (Binary::Overlay(overlay_id), FreeKind::PastBss) = (region.binary, region.kind)
+            else {
+                continue;
+            };
+            let Some(entry) = overlays.iter().find(|entry| entry.id == overlay_id) else {
+                continue;
+            };
+            if used == 0 {
+                continue;
+            }
+            let mut data = overlay::extract_overlay(rom_data, Processor::Arm9, overlay_id, true)?;
+            data.resize((region.address - entry.ram_address) as usize + used, 0);
+            overlay::replace_overlay(rom_data, Processor::Arm9, overlay_id, &data, level)?;
+            overlay::set_bss_size(rom_data, Processor::Arm9, overlay_id, 0)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::{BTreeMap, HashMap};
+
+use thiserror::Error;
+
+use crate::{
+    fnt::{self, BuildFntError, ParseFntError, Renumbered},
+    rom::{self, RomParseError, Section},
+};
+
+#[derive(Error, Debug)]
+pub enum FsEditError {
+    #[error("the ROM has no NitroFS")]
+    NoFilesystem,
+    #[error("{path:?} already exists in the ROM")]
+    AlreadyExists { path: String },
+    #[error("no file or directory in the ROM has the path {path:?}")]
+    NotFound { path: String },
+    #[error("{path:?} is a directory, which is only removed recursively")]
+    IsDirectory { path: String },
+    #[error("{path:?} is a file, so nothing can be placed inside of it")]
+    NotADirectory { path: String },
+    #[error("cannot move {from:?} inside of itself")]
+    MoveIntoItself { from: String },
+    #[error(transparent)]
+    Rom(#[from] RomParseError),
+    #[error("invalid FNT")]
+    ParseFnt(#[from] ParseFntError),
+    #[error("failed to build FNT")]
+    BuildFnt(#[from] BuildFntError),
+}
+
+/// A structural change to the NitroFS of a ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsEdit {
+    /// Adds a new file with the data given.
+    Add { path: String, data: Vec<u8> },
+    /// Removes a file, or a directory along with everything inside it if `recursive` is set.
+    Remove { path: String, recursive: bool },
+    /// Moves or renames a file or directory. If `to` is an existing directory, the file or
+    /// directory is moved inside it.
+    Move { from: String, to: String },
+}
+
+/// Where the data of a file of the edited NitroFS comes from.
+#[derive(Debug)]
+enum FileSource {
+    /// A file already in the ROM, with its original file ID.
+    Existing(u16),
+    New(Vec<u8>),
+}
+
+/// The NitroFS of a ROM as the edits go, by path.
+#[derive(Debug)]
+struct EditedTree {
+    files: BTreeMap<String, FileSource>,
+    /// Every directory but the root, with its original directory ID.
+    dirs: BTreeMap<String, u16>,
+}
+
+/// Whether `path` is `dir` or lies inside of it.
+fn is_within(path: &str, dir: &str) -> bool {
+    dir.is_empty()
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+fn normalize(path: &str) -> String {
+    path.trim_matches('/').to_owned()
+}
+
+impl EditedTree {
+    fn is_dir(&self, path: &str) -> bool {
+        path.is_empty()
+            || self.dirs.contains_key(path)
+            || self
+                .files
+                .keys()
+                .any(|file| file != path && is_within(file, path))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.files.contains_key(path) || self.is_dir(path)
+    }
+
+    /// Checks that a path is free to be given to a file or directory: that nothing has it and
+    /// that none of its parents is a file.
+    fn check_free(&self, path: &str) -> Result<(), FsEditError> {
+        if self.exists(path) {
+            return Err(FsEditError::AlreadyExists {
+                path: path.to_owned(),
+            });
+        }
+        let mut parents = path.match_indices('/').map(|(index, _)| &path[..index]);
+        match parents.find(|parent| self.files.contains_key(*parent)) {
+            Some(parent) => Err(FsEditError::NotADirectory {
+                path: parent.to_owned(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn apply(&mut self, edit: FsEdit) -> Result<(), FsEditError> {
+        match edit {
+            FsEdit::Add { path, data } => {
+                let path = normalize(&path);
+                self.check_free(&path)?;
+                self.files.insert(path, FileSource::New(data));
+            }
+            FsEdit::Remove { path, recursive } => {
+                let path = normalize(&path);
+                if self.files.remove(&path).is_some() {
+                    return Ok(());
+                }
+                if !self.is_dir(&path) {
+                    return Err(FsEditError::NotFound { path });
+                }
+                if !recursive {
+                    return Err(FsEditError::IsDirectory { path });
+                }
+                self.files.retain(|file, _| !is_within(file, &path));
+                self.dirs.retain(|dir, _| !is_within(dir, &path));
+            }
+            FsEdit::Move { from, to } => {
+                let from = normalize(&from);
+                let mut to = normalize(&to);
+                if !self.exists(&from) {
+                    return Err(FsEditError::NotFound { path: from });
+                }
+                if self.is_dir(&to) {
+                    let name = from.rsplit('/').next().unwrap_or(&from);
+                    to = fnt::child_path(&to, name);
+                }
+                self.check_free(&to)?;
+                if let Some(source) = self.files.remove(&from) {
+                    self.files.insert(to, source);
+                    return Ok(());
+                }
+                if is_within(&to, &from) {
+                    return Err(FsEditError::MoveIntoItself { from });
+                }
+                let moved = |path: &str| format!("{to}{}", &path[from.len()..]);
+                self.files = std::mem::take(&mut self.files)
+                    .into_iter()
+                    .map(|(path, source)| match is_within(&path, &from) {
+                        true => (moved(&path), source),
+                        false => (path, source),
+                    })
+                    .collect();
+                self.dirs = std::mem::take(&mut self.dirs)
+                    .into_iter()
+                    .map(|(path, id)| match is_within(&path, &from) {
+                        true => (moved(&path), id),
+                        false => (path, id),
+                    })
+                    .collect();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes a table of the ROM in place if it fits in its original location, or after everything
+/// else otherwise, updating its header fields.
+fn write_table(
+    rom_data: &mut Vec<u8>,
+    table: &'static str,
+    addr_field: usize,
+    size_field: usize,
+    data: &[u8],
+) -> Result<(), RomParseError> {
+    let range = rom::table_range(rom_data, table, addr_field, size_field)?;
+    // The original table is only cleared once the new one is placed, as placing it reads the
+    // FAT.
+    let start = if data.len() <= range.len() {
+        range.start
+    } else {
+        rom::append_data(rom_data, data)?
+    };
+    rom_data[range.clone()].fill(0xFF);
+    rom_data[start..start + data.len()].copy_from_slice(data);
+    rom::set_u32_at(rom_data, addr_field, start as u32);
+    rom::set_u32_at(rom_data, size_field, data.len() as u32);
+    Ok(())
+}
+
+/// Applies structural edits to the NitroFS of a ROM in order, rebuilding its FNT & FAT.
+///
+/// Files keep their data where it is, and their file IDs whenever the FNT layout allows it;
+/// the files that had to be renumbered are returned. New files are placed after everything
+/// else in the ROM, as are the FNT & FAT if they outgrow their original location. The space
+/// of removed files is filled with `0xFF`.
+pub fn edit_filesystem(
+    rom_data: &mut Vec<u8>,
+    edits: impl IntoIterator<Item = FsEdit>,
+) -> Result<Vec<Renumbered>, FsEditError> {
+    if !rom::has_filesystem(rom_data) {
+        return Err(FsEditError::NoFilesystem);
+    }
+    rom::filesystem(rom_data)?;
+    let fnt_range = rom::table_range(rom_data, "FNT", rom::FNT_ADDR_OFFSET, rom::FNT_SIZE_OFFSET)?;
+    let fat_range = rom::table_range(rom_data, "FAT", rom::FAT_ADDR_OFFSET, rom::FAT_SIZE_OFFSET)?;
+    let parsed = fnt::parse_fnt(&rom_data[fnt_range])?;
+    let old_fat = rom_data[fat_range]
+        .chunks_exact(8)
+        .map(|entry| (rom::u32_at(entry, 0), rom::u32_at(entry, 4)))
+        .collect::<Vec<_>>();
+
+    let mut tree = EditedTree {
+        files: parsed
+            .files
+            .into_iter()
+            .map(|(path, id)| (path, FileSource::Existing(id)))
+            .collect(),
+        dirs: parsed
+            .directories
+            .into_iter()
+            .filter(|(path, _)| !path.is_empty())
+            .collect(),
+    };
+    for edit in edits {
+        tree.apply(edit)?;
+    }
+
+    let first_file_id = [Section::Arm9OverlayTable, Section::Arm7OverlayTable]
+        .into_iter()
+        .filter_map(|section| section.range(rom_data))
+        .flat_map(|range| rom::overlay_file_ids(&rom_data[range]))
+        .map(|file_id| file_id + 1)
+        .max()
+        .unwrap_or(0);
+    let built = fnt::build_fnt(
+        tree.files.iter().map(|(path, source)| {
+            let preferred_id = match source {
+                FileSource::Existing(file_id) => Some(*file_id),
+                FileSource::New(_) => None,
+            };
+            (path.as_str(), preferred_id)
+        }),
+        &tree.dirs,
+        first_file_id,
+    )?;
+
+    let fat_len = built
+        .file_ids
+        .last()
+        .map_or(first_file_id, |(_, file_id)| file_id + 1)
+        .max(first_file_id) as usize;
+    let mut fat = vec![(0, 0); fat_len];
+    for (file_id, entry) in fat.iter_mut().enumerate().take(first_file_id as usize) {
+        *entry = old_fat.get(file_id).copied().unwrap_or_default();
+    }
+    let kept_ids = tree
+        .files
+        .values()
+        .filter_map(|source| match source {
+            FileSource::Existing(file_id) => Some(*file_id),
+            FileSource::New(_) => None,
+        })
+        .collect::<Vec<_>>();
+    for (path, file_id) in &built.file_ids {
+        if let FileSource::Existing(old_id) = tree.files[path] {
+            fat[*file_id as usize] = old_fat[old_id as usize];
+        }
+    }
+
+    // Free the space of removed files, unless a kept file shares it.
+    let kept_ranges = fat
+        .iter()
+        .map(|&(start, end)| start..end)
+        .collect::<Vec<_>>();
+    for (old_id, &(start, end)) in old_fat.iter().enumerate().skip(first_file_id as usize) {
+        let removed = !kept_ids.contains(&(old_id as u16));
+        let shared = kept_ranges
+            .iter()
+            .any(|range| range.start < end && start < range.end);
+        if removed && !shared {
+            rom_data[start as usize..end as usize].fill(0xFF);
+        }
+    }
+
+    let new_ids: HashMap<&str, u16> = built
+        .file_ids
+        .iter()
+        .map(|(path, file_id)| (path.as_str(), *file_id))
+        .collect();
+    for (path, source) in &tree.files {
+        if let FileSource::New(data) = source {
+            let start = rom::append_data(rom_data, data)? as u32;
+            fat[new_ids[path.as_str()] as usize] = (start, start + data.len() as u32);
+        }
+    }
+
+    write_table(
+        rom_data,
+        "FNT",
+        rom::FNT_ADDR_OFFSET,
+        rom::FNT_SIZE_OFFSET,
+        &built.data,
+    )?;
+    let fat_data = fat
+        .iter()
+        .flat_map(|&(start, end)| [start.to_le_bytes(), end.to_le_bytes()])
+        .flatten()
+        .collect::<Vec<_>>();
+    write_table(
+        rom_data,
+        "FAT",
+        rom::FAT_ADDR_OFFSET,
+        rom::FAT_SIZE_OFFSET,
+        &fat_data,
+    )?;
+    rom::fix_header_crc(rom_data);
+    Ok(built.renumbered)
+}
@@ -0,0 +1,1261 @@
+use std::{collections::HashMap, io::Cursor};
+
+use serde::Serialize;
+use thiserror::Error;
+
+const NCGR_MAGIC: &[u8; 4] = b"RGCN";
+const NCLR_MAGIC: &[u8; 4] = b"RLCN";
+const NSCR_MAGIC: &[u8; 4] = b"RCSN";
+const CHAR_MAGIC: &[u8; 4] = b"RAHC";
+const PLTT_MAGIC: &[u8; 4] = b"TTLP";
+const SCRN_MAGIC: &[u8; 4] = b"NRCS";
+const NCER_MAGIC: &[u8; 4] = b"RECN";
+const NANR_MAGIC: &[u8; 4] = b"RNAN";
+const CEBK_MAGIC: &[u8; 4] = b"KBEC";
+const ABNK_MAGIC: &[u8; 4] = b"KNBA";
+
+/// Width & height of a tile, in pixels.
+pub const TILE_SIZE: usize = 8;
+/// Width, in tiles, of graphics that don't specify their dimensions.
+const DEFAULT_TILES_WIDE: usize = 16;
+/// Number of colors in each palette of 4bpp graphics.
+const SUBPALETTE_LEN: usize = 16;
+/// Value of the dimensions of graphics that don't specify them.
+const UNSPECIFIED_DIMENSION: u16 = 0xFFFF;
+/// Number of tiles a screen can refer to.
+const MAX_SCREEN_TILES: usize = 0x400;
+/// Number of pixels in a tile.
+const TILE_PIXELS: usize = TILE_SIZE * TILE_SIZE;
+/// Width in tiles of the character area as seen by objects using 2D tile mapping.
+const MAPPING_2D_TILES_WIDE: usize = 32;
+/// Size in bytes of the units object tile numbers are given in with 2D tile mapping.
+const MAPPING_2D_UNIT: usize = 0x20;
+
+#[derive(Error, Debug)]
+pub enum ParseGfxError {
+    #[error("magic number mismatch: not a {expected} file")]
+    MagicNumberMismatch { expected: &'static str },
+    #[error("file is truncated")]
+    Truncated,
+    #[error("the {section} section is missing")]
+    MissingSection { section: &'static str },
+    #[error("unsupported bit depth value {0}")]
+    UnsupportedBitDepth(u32),
+}
+
+#[derive(Error, Debug)]
+pub enum ImportGfxError {
+    #[error("failed to decode PNG")]
+    Decoding(#[from] png::DecodingError),
+    #[error("image is {found_width}x{found_height} pixels, but should be {expected_width}x{expected_height}")]
+    SizeMismatch {
+        expected_width: usize,
+        expected_height: usize,
+        found_width: usize,
+        found_height: usize,
+    },
+    #[error(
+        "the image needs {0} different tiles, but a screen can only refer to {MAX_SCREEN_TILES}"
+    )]
+    TooManyTiles(usize),
+}
+
+/// Number of bits per pixel of graphics & palettes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitDepth {
+    Bpp4,
+    Bpp8,
+}
+
+impl BitDepth {
+    fn from_raw(value: u32) -> Result<Self, ParseGfxError> {
+        match value {
+            3 => Ok(BitDepth::Bpp4),
+            4 => Ok(BitDepth::Bpp8),
+            _ => Err(ParseGfxError::UnsupportedBitDepth(value)),
+        }
+    }
+
+    /// Size of a tile of this depth, in bytes.
+    fn tile_len(self) -> usize {
+        match self {
+            BitDepth::Bpp4 => 0x20,
+            BitDepth::Bpp8 => 0x40,
+        }
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, ParseGfxError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseGfxError::Truncated)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, ParseGfxError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseGfxError::Truncated)
+}
+
+/// Checks the Nitro header of a file and finds one of its sections, returning its offset.
+fn find_section(
+    data: &[u8],
+    file_magic: &[u8; 4],
+    file_name: &'static str,
+    section_magic: &[u8; 4],
+    section_name: &'static str,
+) -> Result<usize, ParseGfxError> {
+    if !data.starts_with(file_magic) {
+        return Err(ParseGfxError::MagicNumberMismatch {
+            expected: file_name,
+        });
+    }
+    let mut offset = u16_at(data, 0xC)? as usize;
+    for _ in 0..u16_at(data, 0xE)? {
+        if data
+            .get(offset..offset + 4)
+            .ok_or(ParseGfxError::Truncated)?
+            == section_magic
+        {
+            return Ok(offset);
+        }
+        offset += (u32_at(data, offset + 4)? as usize).max(8);
+    }
+    Err(ParseGfxError::MissingSection {
+        section: section_name,
+    })
+}
+
+/// Rebuilds a Nitro file with the section at `offset` replaced, updating the file size.
+fn replace_section(original: &[u8], offset: usize, section: &[u8]) -> Vec<u8> {
+    let old_len = u32_at(original, offset + 4).map_or(0, |len| len as usize);
+    let end = (offset + old_len).min(original.len());
+    let mut data = [&original[..offset], section, &original[end..]].concat();
+    let file_size = data.len() as u32;
+    data[0x8..0xC].copy_from_slice(&file_size.to_le_bytes());
+    data
+}
+
+/// Returns `len` bytes at `offset`, or as many as there are if the file is shorter than its
+/// header claims.
+fn data_at(data: &[u8], offset: usize, len: usize) -> Result<&[u8], ParseGfxError> {
+    let rest = data.get(offset..).ok_or(ParseGfxError::Truncated)?;
+    Ok(&rest[..len.min(rest.len())])
+}
+
+/// Character data: the pixels of tiled graphics (NCGR).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ncgr {
+    pub depth: BitDepth,
+    /// Width & height in tiles, if the file specifies them.
+    pub tiles: Option<(usize, usize)>,
+    /// Whether the pixels are stored as 8x8 tiles, rather than as a bitmap.
+    pub tiled: bool,
+    /// Palette indices, packed two per byte for 4bpp graphics.
+    pub data: Vec<u8>,
+}
+
+impl Ncgr {
+    pub fn parse(data: &[u8]) -> Result<Self, ParseGfxError> {
+        let section = find_section(data, NCGR_MAGIC, "NCGR", CHAR_MAGIC, "CHAR")?;
+        let tiles_high = u16_at(data, section + 0x8)?;
+        let tiles_wide = u16_at(data, section + 0xA)?;
+        let depth = BitDepth::from_raw(u32_at(data, section + 0xC)?)?;
+        let tiled = u32_at(data, section + 0x14)? & 0xFF == 0;
+        let data_len = u32_at(data, section + 0x18)? as usize;
+        let data_offset = section + 0x8 + u32_at(data, section + 0x1C)? as usize;
+        let tiles = (tiles_high != UNSPECIFIED_DIMENSION && tiles_wide != UNSPECIFIED_DIMENSION)
+            .then_some((tiles_wide as usize, tiles_high as usize));
+        Ok(Self {
+            depth,
+            tiles,
+            tiled,
+            data: data_at(data, data_offset, data_len)?.to_vec(),
+        })
+    }
+
+    /// Rebuilds the original file with this character data in place of its own.
+    pub fn to_bytes(&self, original: &[u8]) -> Result<Vec<u8>, ParseGfxError> {
+        let section = find_section(original, NCGR_MAGIC, "NCGR", CHAR_MAGIC, "CHAR")?;
+        let mut char_section = original
+            .get(section..section + 0x20)
+            .ok_or(ParseGfxError::Truncated)?
+            .to_vec();
+        let (tiles_wide, tiles_high) = self.tiles.map_or(
+            (UNSPECIFIED_DIMENSION, UNSPECIFIED_DIMENSION),
+            |(tiles_wide, tiles_high)| (tiles_wide as u16, tiles_high as u16),
+        );
+        char_section[0x4..0x8].copy_from_slice(&(0x20 + self.data.len() as u32).to_le_bytes());
+        char_section[0x8..0xA].copy_from_slice(&tiles_high.to_le_bytes());
+        char_section[0xA..0xC].copy_from_slice(&tiles_wide.to_le_bytes());
+        char_section[0x18..0x1C].copy_from_slice(&(self.data.len() as u32).to_le_bytes());
+        char_section[0x1C..0x20].copy_from_slice(&0x18u32.to_le_bytes());
+        char_section.extend_from_slice(&self.data);
+        Ok(replace_section(original, section, &char_section))
+    }
+
+    pub fn tile_count(&self) -> usize {
+        self.data.len() / self.depth.tile_len()
+    }
+
+    /// Width & height in tiles of the graphics as drawn by [`render_graphics`].
+    pub fn size_in_tiles(&self) -> (usize, usize) {
+        let tile_count = self.tile_count();
+        self.tiles.unwrap_or_else(|| {
+            let tiles_wide = tile_count.clamp(1, DEFAULT_TILES_WIDE);
+            (tiles_wide, tile_count.div_ceil(tiles_wide))
+        })
+    }
+
+    /// Palette index of the pixel at `index` in storage order.
+    fn pixel(&self, index: usize) -> u8 {
+        match self.depth {
+            BitDepth::Bpp4 => self
+                .data
+                .get(index / 2)
+                .map_or(0, |byte| (byte >> (index % 2 * 4)) & 0xF),
+            BitDepth::Bpp8 => self.data.get(index).copied().unwrap_or(0),
+        }
+    }
+
+    /// Sets the palette index of the pixel at `index` in storage order, if it exists.
+    fn set_pixel(&mut self, index: usize, value: u8) {
+        match self.depth {
+            BitDepth::Bpp4 => {
+                if let Some(byte) = self.data.get_mut(index / 2) {
+                    let shift = index % 2 * 4;
+                    *byte = (*byte & !(0xF << shift)) | ((value & 0xF) << shift);
+                }
+            }
+            BitDepth::Bpp8 => {
+                if let Some(byte) = self.data.get_mut(index) {
+                    *byte = value;
+                }
+            }
+        }
+    }
+
+    /// Palette index of a pixel of a tile, `x` & `y` being relative to the tile.
+    fn tile_pixel(&self, tile: usize, x: usize, y: usize) -> u8 {
+        self.pixel(tile * TILE_PIXELS + y * TILE_SIZE + x)
+    }
+
+    /// Storage index of a pixel of the graphics as drawn by [`render_graphics`].
+    fn graphics_pixel_index(&self, x: usize, y: usize) -> usize {
+        let (tiles_wide, _) = self.size_in_tiles();
+        if self.tiled {
+            let tile = (y / TILE_SIZE) * tiles_wide + x / TILE_SIZE;
+            tile * TILE_PIXELS + (y % TILE_SIZE) * TILE_SIZE + x % TILE_SIZE
+        } else {
+            y * tiles_wide * TILE_SIZE + x
+        }
+    }
+}
+
+/// A palette file (NCLR).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nclr {
+    pub depth: BitDepth,
+    /// Colors in BGR555 format.
+    pub colors: Vec<u16>,
+}
+
+impl Nclr {
+    pub fn parse(data: &[u8]) -> Result<Self, ParseGfxError> {
+        let section = find_section(data, NCLR_MAGIC, "NCLR", PLTT_MAGIC, "PLTT")?;
+        let depth = BitDepth::from_raw(u32_at(data, section + 0x8)?)?;
+        let data_len = u32_at(data, section + 0x10)? as usize;
+        let data_offset = section + 0x8 + u32_at(data, section + 0x14)? as usize;
+        let colors = data_at(data, data_offset, data_len)?
+            .chunks_exact(2)
+            .map(|color| u16::from_le_bytes(color.try_into().unwrap()))
+            .collect();
+        Ok(Self { depth, colors })
+    }
+
+    /// Rebuilds the original file with these colors in place of its own.
+    pub fn to_bytes(&self, original: &[u8]) -> Result<Vec<u8>, ParseGfxError> {
+        let section = find_section(original, NCLR_MAGIC, "NCLR", PLTT_MAGIC, "PLTT")?;
+        let mut palette_section = original
+            .get(section..section + 0x18)
+            .ok_or(ParseGfxError::Truncated)?
+            .to_vec();
+        let data_len = self.colors.len() as u32 * 2;
+        palette_section[0x4..0x8].copy_from_slice(&(0x18 + data_len).to_le_bytes());
+        palette_section[0x10..0x14].copy_from_slice(&data_len.to_le_bytes());
+        palette_section[0x14..0x18].copy_from_slice(&0x10u32.to_le_bytes());
+        for color in &self.colors {
+            palette_section.extend_from_slice(&color.to_le_bytes());
+        }
+        Ok(replace_section(original, section, &palette_section))
+    }
+}
+
+/// A tilemap arranging tiles into a screen (NSCR).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nscr {
+    /// Width in pixels.
+    pub width: usize,
+    /// Height in pixels.
+    pub height: usize,
+    /// One entry per tile, in rows: a tile index in bits 0-9, horizontal & vertical flips in
+    /// bits 10 & 11, and a palette index for 4bpp graphics in bits 12-15.
+    pub entries: Vec<u16>,
+}
+
+impl Nscr {
+    pub fn parse(data: &[u8]) -> Result<Self, ParseGfxError> {
+        let section = find_section(data, NSCR_MAGIC, "NSCR", SCRN_MAGIC, "SCRN")?;
+        let width = u16_at(data, section + 0x8)? as usize;
+        let height = u16_at(data, section + 0xA)? as usize;
+        let data_len = u32_at(data, section + 0x10)? as usize;
+        let entries = data_at(data, section + 0x14, data_len)?
+            .chunks_exact(2)
+            .map(|entry| u16::from_le_bytes(entry.try_into().unwrap()))
+            .collect();
+        Ok(Self {
+            width,
+            height,
+            entries,
+        })
+    }
+
+    /// Rebuilds the original file with these entries in place of its own.
+    pub fn to_bytes(&self, original: &[u8]) -> Result<Vec<u8>, ParseGfxError> {
+        let section = find_section(original, NSCR_MAGIC, "NSCR", SCRN_MAGIC, "SCRN")?;
+        let mut screen_section = original
+            .get(section..section + 0x14)
+            .ok_or(ParseGfxError::Truncated)?
+            .to_vec();
+        let data_len = self.entries.len() as u32 * 2;
+        screen_section[0x4..0x8].copy_from_slice(&(0x14 + data_len).to_le_bytes());
+        screen_section[0x8..0xA].copy_from_slice(&(self.width as u16).to_le_bytes());
+        screen_section[0xA..0xC].copy_from_slice(&(self.height as u16).to_le_bytes());
+        screen_section[0x10..0x14].copy_from_slice(&data_len.to_le_bytes());
+        for entry in &self.entries {
+            screen_section.extend_from_slice(&entry.to_le_bytes());
+        }
+        Ok(replace_section(original, section, &screen_section))
+    }
+
+    fn tiles_wide(&self) -> usize {
+        self.width / TILE_SIZE
+    }
+}
+
+/// How objects find their tiles in character data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileMapping {
+    /// Tiles of an object follow each other, starting at its tile number times `boundary`
+    /// bytes.
+    OneDimensional { boundary: usize },
+    /// Tiles of an object are laid out in a grid 32 tiles wide.
+    TwoDimensional,
+}
+
+/// A hardware sprite (OAM entry), part of a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Object {
+    /// Position relative to the cell's origin.
+    pub x: i16,
+    pub y: i16,
+    /// Size in pixels.
+    pub width: usize,
+    pub height: usize,
+    pub tile_number: usize,
+    /// Palette used by 4bpp graphics.
+    pub palette: u8,
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+impl Object {
+    fn parse(attributes: [u16; 3]) -> Self {
+        let [attribute0, attribute1, attribute2] = attributes;
+        // Sizes by shape (square, wide, tall) and size value.
+        const SIZES: [[(usize, usize); 4]; 3] = [
+            [(8, 8), (16, 16), (32, 32), (64, 64)],
+            [(16, 8), (32, 8), (32, 16), (64, 32)],
+            [(8, 16), (8, 32), (16, 32), (32, 64)],
+        ];
+        let shape = ((attribute0 >> 14) as usize).min(2);
+        let (width, height) = SIZES[shape][(attribute1 >> 14) as usize];
+        let affine = attribute0 & 0x100 != 0;
+        Self {
+            // Y coordinates are 8-bit and X coordinates 9-bit, both signed.
+            x: ((attribute1 << 7) as i16) >> 7,
+            y: (attribute0 as u8 as i8).into(),
+            width,
+            height,
+            tile_number: (attribute2 & 0x3FF) as usize,
+            palette: (attribute2 >> 12) as u8,
+            flip_x: !affine && attribute1 & 0x1000 != 0,
+            flip_y: !affine && attribute1 & 0x2000 != 0,
+        }
+    }
+}
+
+/// A sprite made of several objects.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cell {
+    /// Objects in priority order: the first one is drawn over the rest.
+    pub objects: Vec<Object>,
+}
+
+/// A bank of cells (NCER).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ncer {
+    pub mapping: TileMapping,
+    pub cells: Vec<Cell>,
+}
+
+impl Ncer {
+    pub fn parse(data: &[u8]) -> Result<Self, ParseGfxError> {
+        let section = find_section(data, NCER_MAGIC, "NCER", CEBK_MAGIC, "CEBK")?;
+        let cell_count = u16_at(data, section + 0x8)? as usize;
+        // Banks of type 1 give each cell a bounding box.
+        let cell_entry_len = if u16_at(data, section + 0xA)? == 1 {
+            0x10
+        } else {
+            0x8
+        };
+        let cells_offset = section + 0x8 + u32_at(data, section + 0xC)? as usize;
+        let mapping = match u32_at(data, section + 0x10)? {
+            mode @ 0..=3 => TileMapping::OneDimensional {
+                boundary: 0x20 << mode,
+            },
+            _ => TileMapping::TwoDimensional,
+        };
+        let objects_offset = cells_offset + cell_count * cell_entry_len;
+
+        let cells = (0..cell_count)
+            .map(|cell_index| {
+                let entry = cells_offset + cell_index * cell_entry_len;
+                let object_count = u16_at(data, entry)? as usize;
+                let first_object = objects_offset + u32_at(data, entry + 0x4)? as usize;
+                let objects = (0..object_count)
+                    .map(|object_index| {
+                        let object = first_object + object_index * 6;
+                        Ok(Object::parse([
+                            u16_at(data, object)?,
+                            u16_at(data, object + 2)?,
+                            u16_at(data, object + 4)?,
+                        ]))
+                    })
+                    .collect::<Result<_, ParseGfxError>>()?;
+                Ok(Cell { objects })
+            })
+            .collect::<Result<_, ParseGfxError>>()?;
+        Ok(Self { mapping, cells })
+    }
+}
+
+/// How an animation is played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayMode {
+    Forward,
+    Loop,
+    PingPong,
+    PingPongLoop,
+    Unknown(u32),
+}
+
+/// A frame of an animation, showing a cell.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AnimationFrame {
+    pub cell: u16,
+    /// How long the frame is shown, in 1/60ths of a second.
+    pub duration: u16,
+    /// Offset the cell is drawn at.
+    pub x: i16,
+    pub y: i16,
+    /// Rotation, as a fraction of a full turn out of 0x10000.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<u16>,
+    /// Horizontal & vertical scale.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<(f64, f64)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Animation {
+    pub play_mode: PlayMode,
+    pub frames: Vec<AnimationFrame>,
+}
+
+/// A bank of animations of the cells of an NCER file (NANR).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Nanr {
+    pub animations: Vec<Animation>,
+}
+
+impl Nanr {
+    pub fn parse(data: &[u8]) -> Result<Self, ParseGfxError> {
+        let section = find_section(data, NANR_MAGIC, "NANR", ABNK_MAGIC, "ABNK")?;
+        let animation_count = u16_at(data, section + 0x8)? as usize;
+        let animations_offset = section + 0x8 + u32_at(data, section + 0xC)? as usize;
+        let frames_offset = section + 0x8 + u32_at(data, section + 0x10)? as usize;
+        let elements_offset = section + 0x8 + u32_at(data, section + 0x14)? as usize;
+
+        let animations = (0..animation_count)
+            .map(|animation_index| {
+                let animation = animations_offset + animation_index * 0x10;
+                let frame_count = u16_at(data, animation)? as usize;
+                let element_type = u16_at(data, animation + 0x4)?;
+                let play_mode = match u32_at(data, animation + 0x8)? {
+                    1 => PlayMode::Forward,
+                    2 => PlayMode::Loop,
+                    3 => PlayMode::PingPong,
+                    4 => PlayMode::PingPongLoop,
+                    mode => PlayMode::Unknown(mode),
+                };
+                let first_frame = frames_offset + u32_at(data, animation + 0xC)? as usize;
+                let frames = (0..frame_count)
+                    .map(|frame_index| {
+                        let frame = first_frame + frame_index * 0x8;
+                        let element = elements_offset + u32_at(data, frame)? as usize;
+                        let duration = u16_at(data, frame + 0x4)?;
+                        let cell = u16_at(data, element)?;
+                        let signed = |offset| u16_at(data, offset).map(|value| value as i16);
+                        let scale =
+                            |offset| u32_at(data, offset).map(|value| value as i32 as f64 / 4096.0);
+                        Ok(match element_type {
+                            // Cell, rotation, scale & translation.
+                            1 => AnimationFrame {
+                                cell,
+                                duration,
+                                rotation: Some(u16_at(data, element + 0x2)?),
+                                scale: Some((scale(element + 0x4)?, scale(element + 0x8)?)),
+                                x: signed(element + 0xC)?,
+                                y: signed(element + 0xE)?,
+                            },
+                            // Cell & translation.
+                            2 => AnimationFrame {
+                                cell,
+                                duration,
+                                rotation: None,
+                                scale: None,
+                                x: signed(element + 0x4)?,
+                                y: signed(element + 0x6)?,
+                            },
+                            _ => AnimationFrame {
+                                cell,
+                                duration,
+                                rotation: None,
+                                scale: None,
+                                x: 0,
+                                y: 0,
+                            },
+                        })
+                    })
+                    .collect::<Result<_, ParseGfxError>>()?;
+                Ok(Animation { play_mode, frames })
+            })
+            .collect::<Result<_, ParseGfxError>>()?;
+        Ok(Self { animations })
+    }
+}
+
+/// An image made of palette indices, ready to be saved as an indexed PNG.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+    /// RGB colors, by palette index. Indices past the end are drawn black.
+    pub palette: Vec<[u8; 3]>,
+    /// Indices drawn as transparent by the console.
+    pub transparent: Vec<u8>,
+}
+
+impl IndexedImage {
+    fn new(width: usize, height: usize, nclr: &Nclr, depth: BitDepth) -> Self {
+        let expand = |channel: u16| ((channel << 3) | (channel >> 2)) as u8;
+        let palette = nclr
+            .colors
+            .iter()
+            .take(256)
+            .map(|color| {
+                [
+                    expand(color & 0x1F),
+                    expand((color >> 5) & 0x1F),
+                    expand((color >> 10) & 0x1F),
+                ]
+            })
+            .collect();
+        // The first color of each palette is transparent.
+        let transparent = match depth {
+            BitDepth::Bpp4 => (0..256).step_by(SUBPALETTE_LEN).map(|i| i as u8).collect(),
+            BitDepth::Bpp8 => vec![0],
+        };
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height],
+            palette,
+            transparent,
+        }
+    }
+
+    /// Encodes the image as an indexed PNG, keeping palette indices intact.
+    pub fn to_png(&self) -> Result<Vec<u8>, png::EncodingError> {
+        let color_count = self
+            .pixels
+            .iter()
+            .map(|&index| index as usize + 1)
+            .max()
+            .unwrap_or(1)
+            .max(self.palette.len());
+        let mut palette = self.palette.concat();
+        palette.resize(color_count * 3, 0);
+        let mut alpha = vec![0xFF; color_count];
+        for &index in &self.transparent {
+            if let Some(alpha) = alpha.get_mut(index as usize) {
+                *alpha = 0;
+            }
+        }
+
+        let mut png_data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png_data, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(palette);
+        encoder.set_trns(alpha);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(png_data)
+    }
+}
+
+/// Draws every tile of the graphics given in order, using palette `palette_index` of the
+/// palette file for 4bpp graphics. Bitmap graphics are drawn as they are.
+pub fn render_graphics(ncgr: &Ncgr, nclr: &Nclr, palette_index: usize) -> IndexedImage {
+    let (tiles_wide, tiles_high) = ncgr.size_in_tiles();
+    let (width, height) = (tiles_wide * TILE_SIZE, tiles_high * TILE_SIZE);
+    let palette_offset = match ncgr.depth {
+        BitDepth::Bpp4 => palette_index * SUBPALETTE_LEN,
+        BitDepth::Bpp8 => 0,
+    };
+
+    let mut image = IndexedImage::new(width, height, nclr, ncgr.depth);
+    for y in 0..height {
+        for x in 0..width {
+            let index = ncgr.pixel(ncgr.graphics_pixel_index(x, y));
+            image.pixels[y * width + x] = (palette_offset + index as usize) as u8;
+        }
+    }
+    image
+}
+
+/// Draws a screen out of the tiles of the graphics given, as the console would.
+pub fn render_screen(ncgr: &Ncgr, nclr: &Nclr, nscr: &Nscr) -> IndexedImage {
+    let tiles_wide = nscr.tiles_wide();
+    let mut image = IndexedImage::new(nscr.width, nscr.height, nclr, ncgr.depth);
+    for (entry_index, &entry) in nscr.entries.iter().enumerate() {
+        let (tile_x, tile_y) = (entry_index % tiles_wide, entry_index / tiles_wide);
+        if tile_y * TILE_SIZE >= nscr.height {
+            break;
+        }
+        let tile = (entry & 0x3FF) as usize;
+        let (flip_x, flip_y) = (entry & 0x400 != 0, entry & 0x800 != 0);
+        let palette_offset = match ncgr.depth {
+            BitDepth::Bpp4 => (entry >> 12) as usize * SUBPALETTE_LEN,
+            BitDepth::Bpp8 => 0,
+        };
+        for y in 0..TILE_SIZE {
+            for x in 0..TILE_SIZE {
+                let source_x = if flip_x { TILE_SIZE - 1 - x } else { x };
+                let source_y = if flip_y { TILE_SIZE - 1 - y } else { y };
+                let index = ncgr.tile_pixel(tile, source_x, source_y);
+                let (image_x, image_y) = (tile_x * TILE_SIZE + x, tile_y * TILE_SIZE + y);
+                image.pixels[image_y * nscr.width + image_x] =
+                    (palette_offset + index as usize) as u8;
+            }
+        }
+    }
+    image
+}
+
+/// Draws the first `tiles_per_palette` tiles of the graphics once per palette, in rows of
+/// `columns` tiles: every tile with the first palette, then every tile with the second, and so
+/// on. Tiles past the end of the graphics are drawn blank.
+pub fn render_tileset(
+    ncgr: &Ncgr,
+    nclr: &Nclr,
+    tiles_per_palette: usize,
+    palette_count: usize,
+    columns: usize,
+) -> IndexedImage {
+    let tile_count = tiles_per_palette * palette_count;
+    let (width, height) = (
+        columns * TILE_SIZE,
+        tile_count.div_ceil(columns).max(1) * TILE_SIZE,
+    );
+    let mut image = IndexedImage::new(width, height, nclr, ncgr.depth);
+    for index in 0..tile_count {
+        let (tile, palette) = (index % tiles_per_palette, index / tiles_per_palette);
+        let palette_offset = match ncgr.depth {
+            BitDepth::Bpp4 => palette * SUBPALETTE_LEN,
+            BitDepth::Bpp8 => 0,
+        };
+        let (tile_x, tile_y) = (index % columns, index / columns);
+        for y in 0..TILE_SIZE {
+            for x in 0..TILE_SIZE {
+                let pixel = ncgr.tile_pixel(tile, x, y);
+                image.pixels[(tile_y * TILE_SIZE + y) * width + tile_x * TILE_SIZE + x] =
+                    (palette_offset + pixel as usize) as u8;
+            }
+        }
+    }
+    image
+}
+
+/// Draws a cell by assembling its objects, as the console would without rotation or scaling.
+/// The image spans the bounding box of the objects, and is empty if the cell has none.
+pub fn render_cell(ncgr: &Ncgr, nclr: &Nclr, mapping: TileMapping, cell: &Cell) -> IndexedImage {
+    let min_x = cell
+        .objects
+        .iter()
+        .map(|object| object.x)
+        .min()
+        .unwrap_or(0);
+    let min_y = cell
+        .objects
+        .iter()
+        .map(|object| object.y)
+        .min()
+        .unwrap_or(0);
+    let max_x = cell
+        .objects
+        .iter()
+        .map(|object| object.x + object.width as i16)
+        .max()
+        .unwrap_or(0);
+    let max_y = cell
+        .objects
+        .iter()
+        .map(|object| object.y + object.height as i16)
+        .max()
+        .unwrap_or(0);
+    let (width, height) = ((max_x - min_x) as usize, (max_y - min_y) as usize);
+    let mut image = IndexedImage::new(width, height, nclr, ncgr.depth);
+
+    let tile_len = ncgr.depth.tile_len();
+    for object in cell.objects.iter().rev() {
+        let tiles_wide = object.width / TILE_SIZE;
+        let palette_offset = match ncgr.depth {
+            BitDepth::Bpp4 => object.palette as usize * SUBPALETTE_LEN,
+            BitDepth::Bpp8 => 0,
+        };
+        for y in 0..object.height {
+            for x in 0..object.width {
+                let source_x = if object.flip_x {
+                    object.width - 1 - x
+                } else {
+                    x
+                };
+                let source_y = if object.flip_y {
+                    object.height - 1 - y
+                } else {
+                    y
+                };
+                let (tile_x, tile_y) = (source_x / TILE_SIZE, source_y / TILE_SIZE);
+                let tile_offset = match mapping {
+                    TileMapping::OneDimensional { boundary } => {
+                        object.tile_number * boundary + (tile_y * tiles_wide + tile_x) * tile_len
+                    }
+                    TileMapping::TwoDimensional => {
+                        object.tile_number * MAPPING_2D_UNIT
+                            + tile_y * MAPPING_2D_TILES_WIDE * MAPPING_2D_UNIT
+                            + tile_x * tile_len
+                    }
+                };
+                let index = ncgr.tile_pixel(
+                    tile_offset / tile_len,
+                    source_x % TILE_SIZE,
+                    source_y % TILE_SIZE,
+                );
+                // The first color of each palette is transparent.
+                if index != 0 {
+                    let image_x = (object.x - min_x) as usize + x;
+                    let image_y = (object.y - min_y) as usize + y;
+                    image.pixels[image_y * width + image_x] =
+                        (palette_offset + index as usize) as u8;
+                }
+            }
+        }
+    }
+    image
+}
+
+/// Converts an RGB color to the BGR555 format used by the console.
+fn to_bgr555([r, g, b]: [u8; 3]) -> u16 {
+    (r >> 3) as u16 | ((g >> 3) as u16) << 5 | ((b >> 3) as u16) << 10
+}
+
+/// A decoded PNG.
+struct DecodedPng {
+    width: usize,
+    height: usize,
+    /// Colors in BGR555 format, or `None` for transparent pixels.
+    colors: Vec<Option<u16>>,
+    /// Palette indices & BGR555 palette, for 8-bit indexed PNGs.
+    indexed: Option<(Vec<u8>, Vec<u16>)>,
+}
+
+fn decode_png(png_data: &[u8]) -> Result<DecodedPng, png::DecodingError> {
+    let mut reader = png::Decoder::new(Cursor::new(png_data)).read_info()?;
+    let info = reader.info();
+    if info.color_type == png::ColorType::Indexed && info.bit_depth == png::BitDepth::Eight {
+        let palette = info
+            .palette
+            .as_deref()
+            .unwrap_or_default()
+            .chunks_exact(3)
+            .map(|rgb| to_bgr555(rgb.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        let alpha = info.trns.as_deref().unwrap_or_default().to_vec();
+        let (width, height) = (info.width as usize, info.height as usize);
+        let mut indices = vec![0; reader.output_buffer_size().unwrap_or_default()];
+        reader.next_frame(&mut indices)?;
+        indices.truncate(width * height);
+        let colors = indices
+            .iter()
+            .map(|&index| {
+                let opaque = alpha.get(index as usize).is_none_or(|&alpha| alpha >= 0x80);
+                opaque.then(|| palette.get(index as usize).copied().unwrap_or(0))
+            })
+            .collect();
+        return Ok(DecodedPng {
+            width,
+            height,
+            colors,
+            indexed: Some((indices, palette)),
+        });
+    }
+
+    let mut decoder = png::Decoder::new(Cursor::new(png_data));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size().unwrap_or_default()];
+    let output = reader.next_frame(&mut buffer)?;
+    let channels = output.color_type.samples();
+    let colors = buffer[..output.buffer_size()]
+        .chunks_exact(channels)
+        .map(|pixel| {
+            let (rgb, alpha) = match *pixel {
+                [gray] => ([gray; 3], 0xFF),
+                [gray, alpha] => ([gray; 3], alpha),
+                [r, g, b] => ([r, g, b], 0xFF),
+                [r, g, b, alpha, ..] => ([r, g, b], alpha),
+                [] => ([0; 3], 0),
+            };
+            (alpha >= 0x80).then(|| to_bgr555(rgb))
+        })
+        .collect();
+    Ok(DecodedPng {
+        width: output.width as usize,
+        height: output.height as usize,
+        colors,
+        indexed: None,
+    })
+}
+
+/// Range of palette indices available to a subpalette, excluding its transparent color.
+fn subpalette_range(depth: BitDepth, subpalette: usize) -> std::ops::Range<usize> {
+    match depth {
+        BitDepth::Bpp4 => subpalette * SUBPALETTE_LEN + 1..(subpalette + 1) * SUBPALETTE_LEN,
+        BitDepth::Bpp8 => 1..256,
+    }
+}
+
+/// A group of pixels that must share a subpalette: a tile of a screen, or the whole image.
+struct PaletteUnit {
+    pixels: Vec<usize>,
+    /// Subpalette to try first.
+    preferred_subpalette: usize,
+}
+
+/// Maps every pixel to a palette index holding its exact color, choosing a subpalette for each
+/// unit. Returns `None` if a unit has a color missing from every subpalette.
+///
+/// Pixels keep their index in `original` if it still holds their color, so that palettes with
+/// repeated colors don't change the indices of unedited pixels.
+fn map_exactly(
+    colors: &[Option<u16>],
+    original: &[u8],
+    units: &[PaletteUnit],
+    palette: &[u16],
+    depth: BitDepth,
+) -> Option<Vec<u8>> {
+    let subpalette_count = match depth {
+        BitDepth::Bpp4 => palette.len().div_ceil(SUBPALETTE_LEN).max(1),
+        BitDepth::Bpp8 => 1,
+    };
+    let find = |subpalette: usize, color: u16| {
+        let range = subpalette_range(depth, subpalette);
+        palette
+            .get(range.start.min(palette.len())..range.end.min(palette.len()))?
+            .iter()
+            .position(|&candidate| candidate == color)
+            .map(|position| range.start + position)
+    };
+
+    let mut pixels = vec![0; colors.len()];
+    for unit in units {
+        let subpalette = std::iter::once(unit.preferred_subpalette)
+            .chain(0..subpalette_count)
+            .find(|&subpalette| {
+                unit.pixels.iter().all(|&pixel| {
+                    colors[pixel].is_none_or(|color| find(subpalette, color).is_some())
+                })
+            })?;
+        let range = subpalette_range(depth, subpalette);
+        for &pixel in &unit.pixels {
+            let original_index = original[pixel] as usize;
+            pixels[pixel] = match colors[pixel] {
+                Some(color)
+                    if range.contains(&original_index)
+                        && palette.get(original_index) == Some(&color) =>
+                {
+                    original_index
+                }
+                Some(color) => find(subpalette, color).unwrap_or(range.start - 1),
+                None => range.start - 1,
+            } as u8;
+        }
+    }
+    Some(pixels)
+}
+
+fn channel(color: u16, channel: usize) -> u16 {
+    (color >> (channel * 5)) & 0x1F
+}
+
+/// Reduces colors, given with their number of pixels, to at most `max_colors` using the median
+/// cut algorithm.
+fn median_cut(colors: Vec<(u16, usize)>, max_colors: usize) -> Vec<u16> {
+    let widest_channel = |colors: &[(u16, usize)]| {
+        (0..3)
+            .map(|index| {
+                let values = colors.iter().map(|&(color, _)| channel(color, index));
+                let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+                (index, range)
+            })
+            .max_by_key(|&(_, range)| range)
+            .unwrap()
+    };
+
+    let mut boxes = vec![colors];
+    while boxes.len() < max_colors {
+        let Some((box_index, (channel_index, _))) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(box_index, colors)| (box_index, widest_channel(colors)))
+            .max_by_key(|&(_, (_, range))| range)
+        else {
+            break;
+        };
+        let mut colors = boxes.swap_remove(box_index);
+        colors.sort_by_key(|&(color, _)| channel(color, channel_index));
+        let total = colors.iter().map(|&(_, count)| count).sum::<usize>();
+        let mut seen = 0;
+        let split = colors
+            .iter()
+            .position(|&(_, count)| {
+                seen += count;
+                seen * 2 >= total
+            })
+            .map_or(1, |position| position + 1)
+            .clamp(1, colors.len() - 1);
+        let upper = colors.split_off(split);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    boxes
+        .iter()
+        .filter(|colors| !colors.is_empty())
+        .map(|colors| {
+            let total = colors.iter().map(|&(_, count)| count).sum::<usize>().max(1);
+            (0..3)
+                .map(|index| {
+                    let sum = colors
+                        .iter()
+                        .map(|&(color, count)| channel(color, index) as usize * count)
+                        .sum::<usize>();
+                    (((sum + total / 2) / total) as u16) << (index * 5)
+                })
+                .fold(0, |color, component| color | component)
+        })
+        .collect()
+}
+
+/// Reduces the colors of the image to those a single subpalette can hold, writing them to the
+/// palette, and maps every pixel to the nearest one.
+fn quantize(
+    colors: &[Option<u16>],
+    palette: &mut Vec<u16>,
+    depth: BitDepth,
+    subpalette: usize,
+) -> Vec<u8> {
+    let mut counts = HashMap::<u16, usize>::new();
+    for color in colors.iter().flatten() {
+        *counts.entry(*color).or_default() += 1;
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort();
+    let range = subpalette_range(depth, subpalette);
+    let reduced = median_cut(counts, range.len());
+
+    if palette.len() < range.start + reduced.len() {
+        palette.resize(range.start + reduced.len(), 0);
+    }
+    palette[range.start..range.start + reduced.len()].copy_from_slice(&reduced);
+
+    let distance = |a: u16, b: u16| {
+        (0..3)
+            .map(|index| (channel(a, index) as i32 - channel(b, index) as i32).pow(2))
+            .sum::<i32>()
+    };
+    colors
+        .iter()
+        .map(|color| match color {
+            Some(color) => {
+                let nearest = (0..reduced.len())
+                    .min_by_key(|&index| distance(reduced[index], *color))
+                    .unwrap_or(0);
+                (range.start + nearest) as u8
+            }
+            None => (range.start - 1) as u8,
+        })
+        .collect()
+}
+
+/// Returns a tile flipped horizontally and/or vertically.
+fn flip_tile(tile: &[u8; TILE_PIXELS], flip_x: bool, flip_y: bool) -> [u8; TILE_PIXELS] {
+    let mut flipped = [0; TILE_PIXELS];
+    for y in 0..TILE_SIZE {
+        for x in 0..TILE_SIZE {
+            let source_x = if flip_x { TILE_SIZE - 1 - x } else { x };
+            let source_y = if flip_y { TILE_SIZE - 1 - y } else { y };
+            flipped[y * TILE_SIZE + x] = tile[source_y * TILE_SIZE + source_x];
+        }
+    }
+    flipped
+}
+
+/// Graphics rebuilt from an edited PNG.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedGraphics {
+    pub ncgr: Ncgr,
+    pub nclr: Nclr,
+    pub nscr: Option<Nscr>,
+    /// Whether the image had colors missing from the palette, which had to be replaced by a
+    /// palette reduced to the colors the graphics can use.
+    pub quantized: bool,
+}
+
+/// Rebuilds graphics from a PNG drawn like [`render_graphics`] (with the first palette) or
+/// [`render_screen`] would.
+///
+/// Indexed PNGs keep their palette indices, and their palette replaces the original one.
+/// Otherwise, each color is looked up in the original palette, and if some are missing the
+/// colors are reduced to fit in a single subpalette. Screens reuse the tiles they referred to
+/// when these are unchanged, and other identical tiles (flipped or not) when possible; new
+/// tiles are added after the existing ones.
+pub fn import_png(
+    png_data: &[u8],
+    ncgr: &Ncgr,
+    nclr: &Nclr,
+    nscr: Option<&Nscr>,
+) -> Result<ImportedGraphics, ImportGfxError> {
+    let image = decode_png(png_data)?;
+    let (width, height) = match nscr {
+        Some(nscr) => (nscr.width, nscr.height),
+        None => {
+            let (tiles_wide, tiles_high) = ncgr.size_in_tiles();
+            (tiles_wide * TILE_SIZE, tiles_high * TILE_SIZE)
+        }
+    };
+    if (image.width, image.height) != (width, height) {
+        return Err(ImportGfxError::SizeMismatch {
+            expected_width: width,
+            expected_height: height,
+            found_width: image.width,
+            found_height: image.height,
+        });
+    }
+
+    let original = match nscr {
+        Some(nscr) => render_screen(ncgr, nclr, nscr),
+        None => render_graphics(ncgr, nclr, 0),
+    }
+    .pixels;
+    let units = match nscr {
+        Some(nscr) => nscr
+            .entries
+            .iter()
+            .enumerate()
+            .take(nscr.tiles_wide() * (height / TILE_SIZE))
+            .map(|(entry_index, &entry)| {
+                let tile_x = entry_index % nscr.tiles_wide() * TILE_SIZE;
+                let tile_y = entry_index / nscr.tiles_wide() * TILE_SIZE;
+                PaletteUnit {
+                    pixels: (0..TILE_PIXELS)
+                        .map(|i| (tile_y + i / TILE_SIZE) * width + tile_x + i % TILE_SIZE)
+                        .collect(),
+                    preferred_subpalette: (entry >> 12) as usize,
+                }
+            })
+            .collect(),
+        None => vec![PaletteUnit {
+            pixels: (0..width * height).collect(),
+            preferred_subpalette: 0,
+        }],
+    };
+    // Indices can be kept as long as the pixels of each unit use a single subpalette.
+    let fits_subpalettes = |indices: &[u8]| {
+        ncgr.depth == BitDepth::Bpp8
+            || units.iter().all(|unit| {
+                unit.pixels
+                    .iter()
+                    .all(|&pixel| indices[pixel] >> 4 == indices[unit.pixels[0]] >> 4)
+            })
+    };
+
+    let mut colors = nclr.colors.clone();
+    let mut quantized = false;
+    let pixels = match image
+        .indexed
+        .filter(|(indices, _)| fits_subpalettes(indices))
+    {
+        Some((indices, palette)) => {
+            let palette_len = palette.len().min(256);
+            if colors.len() < palette_len {
+                colors.resize(palette_len, 0);
+            }
+            colors[..palette_len].copy_from_slice(&palette[..palette_len]);
+            indices
+        }
+        None => match map_exactly(&image.colors, &original, &units, &colors, ncgr.depth) {
+            Some(pixels) => pixels,
+            None => {
+                quantized = true;
+                let subpalette = match nscr {
+                    Some(nscr) if ncgr.depth == BitDepth::Bpp4 => {
+                        let mut counts = [0usize; 16];
+                        for entry in &nscr.entries {
+                            counts[(entry >> 12) as usize] += 1;
+                        }
+                        (0..16).max_by_key(|&index| counts[index]).unwrap()
+                    }
+                    _ => 0,
+                };
+                quantize(&image.colors, &mut colors, ncgr.depth, subpalette)
+            }
+        },
+    };
+    let to_tile_index = |index: u8| match ncgr.depth {
+        BitDepth::Bpp4 => index & 0xF,
+        BitDepth::Bpp8 => index,
+    };
+
+    let mut new_ncgr = ncgr.clone();
+    let new_nscr = match nscr {
+        None => {
+            for y in 0..height {
+                for x in 0..width {
+                    let index = to_tile_index(pixels[y * width + x]);
+                    new_ncgr.set_pixel(ncgr.graphics_pixel_index(x, y), index);
+                }
+            }
+            None
+        }
+        Some(nscr) => {
+            let mut tiles = (0..ncgr.tile_count())
+                .map(|tile| {
+                    let mut pixels = [0; TILE_PIXELS];
+                    for (i, pixel) in pixels.iter_mut().enumerate() {
+                        *pixel = ncgr.tile_pixel(tile, i % TILE_SIZE, i / TILE_SIZE);
+                    }
+                    pixels
+                })
+                .collect::<Vec<_>>();
+            let mut tile_lookup = HashMap::new();
+            for (tile_index, tile) in tiles.iter().enumerate() {
+                tile_lookup.entry(*tile).or_insert(tile_index);
+            }
+
+            let mut entries = nscr.entries.clone();
+            for (entry, unit) in entries.iter_mut().zip(&units) {
+                let mut tile = [0; TILE_PIXELS];
+                for (pixel, &image_pixel) in tile.iter_mut().zip(&unit.pixels) {
+                    *pixel = to_tile_index(pixels[image_pixel]);
+                }
+                let flips = [(false, false), (true, false), (false, true), (true, true)];
+                let original_tile = (*entry & 0x3FF) as usize;
+                let original_flip = (*entry & 0x400 != 0, *entry & 0x800 != 0);
+                let unchanged = tiles.get(original_tile)
+                    == Some(&flip_tile(&tile, original_flip.0, original_flip.1));
+                let (tile_index, (flip_x, flip_y)) = if unchanged {
+                    (original_tile, original_flip)
+                } else if let Some(found) = flips.iter().find_map(|&(flip_x, flip_y)| {
+                    tile_lookup
+                        .get(&flip_tile(&tile, flip_x, flip_y))
+                        .map(|&tile_index| (tile_index, (flip_x, flip_y)))
+                }) {
+                    found
+                } else {
+                    tiles.push(tile);
+                    tile_lookup.insert(tile, tiles.len() - 1);
+                    (tiles.len() - 1, (false, false))
+                };
+                if tile_index >= MAX_SCREEN_TILES {
+                    return Err(ImportGfxError::TooManyTiles(tiles.len()));
+                }
+
+                let palette_bits = match ncgr.depth {
+                    BitDepth::Bpp4 => (pixels[unit.pixels[0]] as u16 >> 4) << 12,
+                    BitDepth::Bpp8 => *entry & 0xF000,
+                };
+                *entry = tile_index as u16
+                    | (flip_x as u16) << 10
+                    | (flip_y as u16) << 11
+                    | palette_bits;
+            }
+
+            // Tiles added past the declared dimensions grow the graphics by whole rows.
+            if let Some((tiles_wide, tiles_high)) = ncgr.tiles {
+                if tiles.len() > tiles_wide * tiles_high {
+                    let tiles_high = tiles.len().div_ceil(tiles_wide);
+                    tiles.resize(tiles_wide * tiles_high, [0; TILE_PIXELS]);
+                    new_ncgr.tiles = Some((tiles_wide, tiles_high));
+                }
+            }
+            new_ncgr.data = vec![0; tiles.len() * ncgr.depth.tile_len()];
+            for (tile_index, tile) in tiles.iter().enumerate() {
+                for (i, &index) in tile.iter().enumerate() {
+                    new_ncgr.set_pixel(tile_index * TILE_PIXELS + i, index);
+                }
+            }
+            Some(Nscr {
+                entries,
+                ..nscr.clone()
+            })
+        }
+    };
+
+    Ok(ImportedGraphics {
+        ncgr: new_ncgr,
+        nclr: Nclr {
+            depth: nclr.depth,
+            colors,
+        },
+        nscr: new_nscr,
+        quantized,
+    })
+}
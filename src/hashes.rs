@@ -0,0 +1,126 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{anyhow, Context};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::{
+    cache::CACHE_DIR,
+    manifest::{self, HASHES_FILE_NAME, MANIFEST_FILE_NAME, RAVENDS_DIR},
+    pack::walk_files,
+    rom,
+};
+
+const HASHES_VERSION: u32 = 1;
+
+/// SHA-256 hashes of the files of an unpacked ROM as they were written, along with those of its
+/// NitroFS files as stored in the ROM, before being decompressed or converted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hashes {
+    pub version: u32,
+    /// Hashes of the unpacked files, by path relative to the unpack directory.
+    pub files: BTreeMap<String, String>,
+    /// Hashes of the NitroFS files as stored in the ROM, by NitroFS path.
+    pub originals: BTreeMap<String, String>,
+}
+
+/// Hashes every file of an unpacked ROM, leaving out ravends' own bookkeeping files.
+fn hash_files(unpack_dir: &Path) -> anyhow::Result<BTreeMap<String, String>> {
+    let paths = walk_files(
+        unpack_dir,
+        &[MANIFEST_FILE_NAME, HASHES_FILE_NAME, RAVENDS_DIR, CACHE_DIR],
+    )?;
+    paths
+        .par_iter()
+        .map(|path| {
+            let data = fs::read(unpack_dir.join(path))
+                .with_context(|| format!("failed to read {path:?}"))?;
+            Ok((rom::nitro_path(path), manifest::sha256_hex(&data)))
+        })
+        .collect()
+}
+
+impl Hashes {
+    /// Hashes the files of the unpacked ROM at `unpack_dir`, along with the hashes given for
+    /// the files as stored in the ROM.
+    pub fn compute(unpack_dir: &Path, originals: BTreeMap<String, String>) -> anyhow::Result<Self> {
+        Ok(Self {
+            version: HASHES_VERSION,
+            files: hash_files(unpack_dir)?,
+            originals,
+        })
+    }
+
+    /// Reads the hashes of the unpacked ROM at `unpack_dir`, if it has them.
+    pub fn load(unpack_dir: &Path) -> anyhow::Result<Option<Self>> {
+        let path = unpack_dir.join(HASHES_FILE_NAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let hashes: Self = serde_json::from_slice(&fs::read(&path)?)
+            .with_context(|| format!("failed to parse hashes at {path:?}"))?;
+        if hashes.version > HASHES_VERSION {
+            anyhow::bail!(
+                "hashes version {} is not supported (latest supported: {HASHES_VERSION})",
+                hashes.version
+            );
+        }
+        Ok(Some(hashes))
+    }
+
+    pub fn save(&self, unpack_dir: &Path) -> anyhow::Result<()> {
+        fs::write(
+            unpack_dir.join(HASHES_FILE_NAME),
+            serde_json::to_vec_pretty(self)?,
+        )
+        .context("failed to write hashes")
+    }
+}
+
+/// Files of an unpacked ROM that changed since it was unpacked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HashReport {
+    pub modified: Vec<String>,
+    pub added: Vec<String>,
+    pub missing: Vec<String>,
+}
+
+impl HashReport {
+    pub fn is_clean(&self) -> bool {
+        self.modified.is_empty() && self.added.is_empty() && self.missing.is_empty()
+    }
+
+    /// Every change, as a description and the path of the file it applies to.
+    pub fn changes(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        self.modified
+            .iter()
+            .map(|path| ("modified", path.as_str()))
+            .chain(self.added.iter().map(|path| ("added", path.as_str())))
+            .chain(self.missing.iter().map(|path| ("missing", path.as_str())))
+    }
+}
+
+/// Compares the files of the unpacked ROM at `unpack_dir` against the hashes written when it
+/// was unpacked.
+pub fn verify(unpack_dir: &Path) -> anyhow::Result<HashReport> {
+    let hashes = Hashes::load(unpack_dir)?.ok_or_else(|| {
+        anyhow!("{unpack_dir:?} has no {HASHES_FILE_NAME}; unpack the ROM again to create it")
+    })?;
+    let current = hash_files(unpack_dir)?;
+    let mut report = HashReport::default();
+    for (path, hash) in &current {
+        match hashes.files.get(path) {
+            Some(original) if original == hash => {}
+            Some(_) => report.modified.push(path.clone()),
+            None => report.added.push(path.clone()),
+        }
+    }
+    report.missing = hashes
+        .files
+        .keys()
+        .filter(|path| !current.contains_key(*path))
+        .cloned()
+        .collect();
+    Ok(report)
+}
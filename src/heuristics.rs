@@ -0,0 +1,243 @@
+use std::fmt;
+
+/// Minimum number of characters for a run of text to count towards text density.
+const MIN_TEXT_RUN: usize = 6;
+/// Largest record size looked for by stride detection.
+const MAX_STRIDE: usize = 64;
+/// Amount of data looked at by stride detection, which is quadratic in the stride range.
+const STRIDE_SAMPLE_SIZE: usize = 0x10000;
+/// Addresses of the main RAM of the NDS, where most pointers in game data point to.
+const MAIN_RAM_RANGE: std::ops::Range<u32> = 0x0200_0000..0x0240_0000;
+
+/// A table of offsets found at the start of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerTable {
+    /// The file starts with offsets into itself, the first one pointing right after the table.
+    Offsets { count: usize },
+    /// The file starts with a 32-bit count followed by that many offsets into itself.
+    CountedOffsets { count: usize },
+}
+
+/// Statistics about a file of unknown format, meant as hints for reverse engineering it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    /// Shannon entropy, in bits per byte.
+    pub entropy: f64,
+    /// Fraction of the file made of runs of printable UTF-16LE characters.
+    pub utf16_density: f64,
+    /// Fraction of the file made of runs of Shift-JIS text with at least one double-byte
+    /// character.
+    pub shift_jis_density: f64,
+    /// Fraction of the file made of runs of printable ASCII characters.
+    pub ascii_density: f64,
+    /// Size of the records the file seems to be made of, along with the fraction of bytes
+    /// equal to the byte that many positions later.
+    pub stride: Option<(usize, f64)>,
+    pub pointer_table: Option<PointerTable>,
+    /// Number of aligned 32-bit values that look like pointers to main RAM.
+    pub ram_pointers: usize,
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+    counts
+        .iter()
+        .filter(|&&count| count != 0)
+        .map(|&count| {
+            let probability = count as f64 / data.len() as f64;
+            -probability * probability.log2()
+        })
+        .sum()
+}
+
+/// Counts the bytes of `data` belonging to runs of text, given a function that returns the
+/// size of the character at the start of a slice (or `None` if it isn't text) and whether it
+/// is one of the characters a run must contain to count.
+fn text_density(data: &[u8], char_at: impl Fn(&[u8]) -> Option<(usize, bool)>) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    let mut text_bytes = 0;
+    let mut offset = 0;
+    while offset < data.len() {
+        let (start, mut chars, mut required) = (offset, 0, false);
+        while let Some((size, is_required)) = char_at(&data[offset..]) {
+            offset += size;
+            chars += 1;
+            required |= is_required;
+        }
+        if chars >= MIN_TEXT_RUN && required {
+            text_bytes += offset - start;
+        }
+        if chars == 0 {
+            offset += 1;
+        }
+    }
+    text_bytes as f64 / data.len() as f64
+}
+
+fn is_printable_ascii(byte: u8) -> bool {
+    (0x20..0x7F).contains(&byte) || byte == b'\n'
+}
+
+fn utf16_char(data: &[u8]) -> Option<(usize, bool)> {
+    let unit = u16::from_le_bytes(data.get(..2)?.try_into().unwrap());
+    // Pairs of ASCII characters read as CJK ideographs, so runs must contain other characters
+    // to count.
+    match unit {
+        0x0A | 0x20..=0x7E | 0x3000..=0x30FF | 0xFF00..=0xFFEF => Some((2, true)),
+        0x4E00..=0x9FFF => Some((2, false)),
+        _ => None,
+    }
+}
+
+fn shift_jis_char(data: &[u8]) -> Option<(usize, bool)> {
+    match *data {
+        [byte, ..] if is_printable_ascii(byte) => Some((1, false)),
+        [0x81..=0x9F | 0xE0..=0xEF, 0x40..=0x7E | 0x80..=0xFC, ..] => Some((2, true)),
+        _ => None,
+    }
+}
+
+fn ascii_char(data: &[u8]) -> Option<(usize, bool)> {
+    data.first()
+        .filter(|&&byte| is_printable_ascii(byte))
+        .map(|_| (1, true))
+}
+
+/// Finds the smallest record size for which bytes most often repeat one record later.
+fn stride(data: &[u8]) -> Option<(usize, f64)> {
+    let data = &data[..data.len().min(STRIDE_SAMPLE_SIZE)];
+    let score = |stride: usize| {
+        let matches = data
+            .iter()
+            .zip(&data[stride..])
+            .filter(|(a, b)| a == b)
+            .count();
+        matches as f64 / (data.len() - stride) as f64
+    };
+    if data.len() < MAX_STRIDE * 4 {
+        return None;
+    }
+    let scores = (1..=MAX_STRIDE).map(score).collect::<Vec<_>>();
+    let best = scores.iter().copied().fold(0.0, f64::max);
+    let mean = scores.iter().sum::<f64>() / scores.len() as f64;
+    // Multiples of the record size score about as well as it, so the smallest close to the
+    // best is picked. A stride only stands out if it scores clearly above the others, which
+    // rules out data that is uniform (such as padding) or random.
+    let (index, &score) = scores
+        .iter()
+        .enumerate()
+        .find(|(_, &score)| score >= best * 0.9)?;
+    (index > 0 && score >= 0.25 && score >= mean * 1.5).then_some((index + 1, score))
+}
+
+/// Checks whether `count` offsets starting at `start` are non-decreasing, point inside the
+/// file and point after the table.
+fn is_offset_table(data: &[u8], start: usize, count: usize) -> bool {
+    let table_end = start + count * 4;
+    let mut previous = table_end as u32;
+    (0..count).all(|index| {
+        u32_at(data, start + index * 4).is_some_and(|offset| {
+            let valid = offset >= previous && offset as usize <= data.len();
+            previous = offset;
+            valid
+        })
+    })
+}
+
+fn pointer_table(data: &[u8]) -> Option<PointerTable> {
+    let first = u32_at(data, 0)? as usize;
+    if first.is_multiple_of(4) && first >= 8 && is_offset_table(data, 0, first / 4) {
+        return Some(PointerTable::Offsets { count: first / 4 });
+    }
+    let count = first;
+    (count >= 2 && count < data.len() / 4 && is_offset_table(data, 4, count))
+        .then_some(PointerTable::CountedOffsets { count })
+}
+
+/// Computes statistics about a file of unknown format.
+pub fn analyze(data: &[u8]) -> Analysis {
+    Analysis {
+        entropy: entropy(data),
+        utf16_density: text_density(data, utf16_char),
+        shift_jis_density: text_density(data, shift_jis_char),
+        ascii_density: text_density(data, ascii_char),
+        stride: stride(data),
+        pointer_table: pointer_table(data),
+        ram_pointers: data
+            .chunks_exact(4)
+            .filter(|word| {
+                MAIN_RAM_RANGE.contains(&u32::from_le_bytes((*word).try_into().unwrap()))
+            })
+            .count(),
+    }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "entropy: {:.2} bits per byte", self.entropy)?;
+        if self.entropy > 7.5 {
+            write!(f, " (likely compressed or encrypted)")?;
+        } else if self.entropy < 1.0 {
+            write!(f, " (mostly padding)")?;
+        }
+        writeln!(f)?;
+
+        writeln!(
+            f,
+            "text: {:.0}% UTF-16LE, {:.0}% Shift-JIS, {:.0}% ASCII",
+            self.utf16_density * 100.0,
+            self.shift_jis_density * 100.0,
+            self.ascii_density * 100.0
+        )?;
+        let encodings = [
+            ("UTF-16LE", self.utf16_density),
+            ("Shift-JIS", self.shift_jis_density),
+            ("ASCII", self.ascii_density),
+        ];
+        if let Some((name, _)) = encodings
+            .iter()
+            .filter(|(_, density)| *density >= 0.25)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        {
+            writeln!(f, "likely encoding: {name}")?;
+        }
+
+        if let Some((stride, score)) = self.stride {
+            writeln!(
+                f,
+                "repeating structure: likely records of {stride} bytes ({:.0}% of bytes repeat)",
+                score * 100.0
+            )?;
+        }
+        match self.pointer_table {
+            Some(PointerTable::Offsets { count }) => writeln!(
+                f,
+                "pointer table: starts with {count} offsets into the file"
+            )?,
+            Some(PointerTable::CountedOffsets { count }) => writeln!(
+                f,
+                "pointer table: starts with a count of {count}, followed by as many offsets into \
+                 the file"
+            )?,
+            None => {}
+        }
+        if self.ram_pointers > 0 {
+            writeln!(
+                f,
+                "{} values look like pointers to main RAM",
+                self.ram_pointers
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,326 @@
+use std::{collections::HashMap, fmt::Write, ops::Range};
+
+use crate::{
+    manifest::Processor,
+    overlay::{self, processor_name},
+    rom::{self, Section, UnitCode},
+};
+
+/// Bytes shown per line of a hex dump.
+const BYTES_PER_LINE: usize = 16;
+/// Size of the header of Nitro files, up to the offset of their first chunk.
+const NITRO_HEADER_SIZE: usize = 0x10;
+/// Size of the header of each chunk of a Nitro file: its magic number and size.
+const NITRO_CHUNK_HEADER_SIZE: usize = 8;
+/// Most chunks a Nitro file is expected to have, past which its header is taken as garbage.
+const MAX_NITRO_CHUNKS: usize = 16;
+
+/// How the value of a header field is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldKind {
+    /// ASCII text, padded with null bytes.
+    Text,
+    /// A little-endian number.
+    Number,
+    /// Data not worth showing, such as reserved bytes.
+    Opaque,
+}
+
+/// Fields of the NDS header, with their offsets and sizes.
+const HEADER_FIELDS: &[(usize, usize, &str, FieldKind)] = &[
+    (0x000, 12, "game title", FieldKind::Text),
+    (0x00C, 4, "game code", FieldKind::Text),
+    (0x010, 2, "maker code", FieldKind::Text),
+    (0x012, 1, "unit code", FieldKind::Number),
+    (0x013, 1, "encryption seed select", FieldKind::Number),
+    (0x014, 1, "device capacity", FieldKind::Number),
+    (0x015, 7, "reserved", FieldKind::Opaque),
+    (0x01C, 1, "DSi flags", FieldKind::Number),
+    (0x01D, 1, "region", FieldKind::Number),
+    (0x01E, 1, "ROM version", FieldKind::Number),
+    (0x01F, 1, "autostart", FieldKind::Number),
+    (0x020, 4, "ARM9 offset", FieldKind::Number),
+    (0x024, 4, "ARM9 entry address", FieldKind::Number),
+    (0x028, 4, "ARM9 RAM address", FieldKind::Number),
+    (0x02C, 4, "ARM9 size", FieldKind::Number),
+    (0x030, 4, "ARM7 offset", FieldKind::Number),
+    (0x034, 4, "ARM7 entry address", FieldKind::Number),
+    (0x038, 4, "ARM7 RAM address", FieldKind::Number),
+    (0x03C, 4, "ARM7 size", FieldKind::Number),
+    (0x040, 4, "FNT offset", FieldKind::Number),
+    (0x044, 4, "FNT size", FieldKind::Number),
+    (0x048, 4, "FAT offset", FieldKind::Number),
+    (0x04C, 4, "FAT size", FieldKind::Number),
+    (0x050, 4, "ARM9 overlay table offset", FieldKind::Number),
+    (0x054, 4, "ARM9 overlay table size", FieldKind::Number),
+    (0x058, 4, "ARM7 overlay table offset", FieldKind::Number),
+    (0x05C, 4, "ARM7 overlay table size", FieldKind::Number),
+    (0x060, 4, "normal card control settings", FieldKind::Number),
+    (0x064, 4, "secure card control settings", FieldKind::Number),
+    (0x068, 4, "banner offset", FieldKind::Number),
+    (0x06C, 2, "secure area CRC", FieldKind::Number),
+    (0x06E, 2, "secure transfer timeout", FieldKind::Number),
+    (0x070, 4, "ARM9 autoload hook", FieldKind::Number),
+    (0x074, 4, "ARM7 autoload hook", FieldKind::Number),
+    (0x078, 8, "secure area disable", FieldKind::Opaque),
+    (0x080, 4, "used ROM size", FieldKind::Number),
+    (0x084, 4, "header size", FieldKind::Number),
+    (0x088, 0x38, "reserved", FieldKind::Opaque),
+    (0x0C0, 0x9C, "Nintendo logo", FieldKind::Opaque),
+    (0x15C, 2, "Nintendo logo CRC", FieldKind::Number),
+    (0x15E, 2, "header CRC", FieldKind::Number),
+    (0x160, 4, "debug ROM offset", FieldKind::Number),
+    (0x164, 4, "debug size", FieldKind::Number),
+    (0x168, 4, "debug RAM address", FieldKind::Number),
+    (0x16C, 0x94, "reserved", FieldKind::Opaque),
+];
+
+/// Fields of the extended header of DSi ROMs.
+const DSI_HEADER_FIELDS: &[(usize, usize, &str, FieldKind)] = &[
+    (0x1C0, 4, "ARM9i offset", FieldKind::Number),
+    (0x1C8, 4, "ARM9i RAM address", FieldKind::Number),
+    (0x1CC, 4, "ARM9i size", FieldKind::Number),
+    (0x1D0, 4, "ARM7i offset", FieldKind::Number),
+    (0x1D8, 4, "ARM7i RAM address", FieldKind::Number),
+    (0x1DC, 4, "ARM7i size", FieldKind::Number),
+    (
+        0x1F0,
+        4,
+        "digest sector hash table offset",
+        FieldKind::Number,
+    ),
+    (0x1F4, 4, "digest sector hash table size", FieldKind::Number),
+    (
+        0x1F8,
+        4,
+        "digest block hash table offset",
+        FieldKind::Number,
+    ),
+    (0x1FC, 4, "digest block hash table size", FieldKind::Number),
+    (0x210, 4, "DSi used ROM size", FieldKind::Number),
+    (0x220, 4, "modcrypt area 1 offset", FieldKind::Number),
+    (0x224, 4, "modcrypt area 1 size", FieldKind::Number),
+    (0x228, 4, "modcrypt area 2 offset", FieldKind::Number),
+    (0x22C, 4, "modcrypt area 2 size", FieldKind::Number),
+];
+
+/// A label given to a range of bytes of a hex dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    pub range: Range<usize>,
+    pub label: String,
+}
+
+impl Annotation {
+    fn new(range: Range<usize>, label: impl Into<String>) -> Self {
+        Self {
+            range,
+            label: label.into(),
+        }
+    }
+}
+
+fn field_annotation(
+    data: &[u8],
+    (offset, size, name, kind): (usize, usize, &str, FieldKind),
+) -> Option<Annotation> {
+    let bytes = data.get(offset..offset + size)?;
+    let label = match kind {
+        FieldKind::Text => format!(
+            "{name}: {:?}",
+            String::from_utf8_lossy(bytes).trim_end_matches('\0')
+        ),
+        FieldKind::Number => {
+            let value = bytes
+                .iter()
+                .rev()
+                .fold(0u64, |value, &byte| value << 8 | byte as u64);
+            format!("{name}: 0x{value:X}")
+        }
+        FieldKind::Opaque => name.to_owned(),
+    };
+    Some(Annotation::new(offset..offset + size, label))
+}
+
+/// Labels the header and chunk headers of a Nitro file starting at `offset`, such as an NCGR
+/// or SDAT file. Data that doesn't look like a Nitro file gets no labels.
+pub fn nitro_annotations(data: &[u8], offset: usize) -> Vec<Annotation> {
+    let Some(header) = data.get(offset..offset + NITRO_HEADER_SIZE) else {
+        return Vec::new();
+    };
+    let magic = &header[..4];
+    let byte_order_mark = &header[4..6];
+    if !magic.iter().all(u8::is_ascii_graphic)
+        || (byte_order_mark != [0xFF, 0xFE] && byte_order_mark != [0xFE, 0xFF])
+    {
+        return Vec::new();
+    }
+    let file_size = rom::u32_at(header, 8) as usize;
+    let header_size = u16::from_le_bytes([header[0xC], header[0xD]]) as usize;
+    let chunk_count = u16::from_le_bytes([header[0xE], header[0xF]]) as usize;
+    let mut annotations = vec![Annotation::new(
+        offset..offset + NITRO_HEADER_SIZE,
+        format!(
+            "{} file header: 0x{file_size:X} bytes, {chunk_count} chunks",
+            String::from_utf8_lossy(magic)
+        ),
+    )];
+
+    let end = offset.saturating_add(file_size).min(data.len());
+    let mut chunk = offset + header_size.max(NITRO_HEADER_SIZE);
+    for _ in 0..chunk_count.min(MAX_NITRO_CHUNKS) {
+        let Some(chunk_header) = data.get(chunk..chunk + NITRO_CHUNK_HEADER_SIZE) else {
+            break;
+        };
+        if chunk + NITRO_CHUNK_HEADER_SIZE > end {
+            break;
+        }
+        let chunk_size = rom::u32_at(chunk_header, 4) as usize;
+        annotations.push(Annotation::new(
+            chunk..chunk + NITRO_CHUNK_HEADER_SIZE,
+            format!(
+                "{} chunk: 0x{chunk_size:X} bytes",
+                String::from_utf8_lossy(&chunk_header[..4])
+            ),
+        ));
+        chunk += chunk_size.max(NITRO_CHUNK_HEADER_SIZE);
+    }
+    annotations
+}
+
+/// Labels the known structures of a ROM: header fields, the start of each section and file,
+/// FAT and overlay table entries, and the headers of Nitro files and their chunks.
+pub fn rom_annotations(rom_data: &[u8]) -> Vec<Annotation> {
+    let mut annotations = HEADER_FIELDS
+        .iter()
+        .filter_map(|&field| field_annotation(rom_data, field))
+        .collect::<Vec<_>>();
+    if UnitCode::of(rom_data).is_dsi() {
+        annotations.extend(
+            DSI_HEADER_FIELDS
+                .iter()
+                .filter_map(|&field| field_annotation(rom_data, field)),
+        );
+    }
+
+    for section in Section::ALL.into_iter().skip(1) {
+        if let Some(range) = section.range(rom_data) {
+            let size = range.len();
+            annotations.push(Annotation::new(
+                range.start..range.start,
+                format!("start of {} (0x{size:X} bytes)", section.name()),
+            ));
+        }
+    }
+    for processor in [Processor::Arm9, Processor::Arm7] {
+        let section = match processor {
+            Processor::Arm9 => Section::Arm9OverlayTable,
+            Processor::Arm7 => Section::Arm7OverlayTable,
+        };
+        let Some(table) = section.range(rom_data) else {
+            continue;
+        };
+        for (index, entry) in overlay::overlay_table(rom_data, processor)
+            .iter()
+            .enumerate()
+        {
+            let start = table.start + index * rom::OVERLAY_ENTRY_SIZE;
+            annotations.push(Annotation::new(
+                start..start + rom::OVERLAY_ENTRY_SIZE,
+                format!(
+                    "{} overlay {}: RAM 0x{:08X}, file {}",
+                    processor_name(processor),
+                    entry.id,
+                    entry.ram_address,
+                    entry.file_id
+                ),
+            ));
+        }
+    }
+
+    if rom::has_filesystem(rom_data) {
+        let fnt_offset = rom::u32_at(rom_data, rom::FNT_ADDR_OFFSET) as usize;
+        annotations.push(Annotation::new(fnt_offset..fnt_offset, "start of FNT"));
+        let paths = rom::filesystem(rom_data)
+            .map(|fs| {
+                fs.files()
+                    .into_iter()
+                    .map(|entry| (entry.id, rom::nitro_path(&entry.path)))
+                    .collect::<HashMap<_, _>>()
+            })
+            .unwrap_or_default();
+        if let Ok(fat) =
+            rom::table_range(rom_data, "FAT", rom::FAT_ADDR_OFFSET, rom::FAT_SIZE_OFFSET)
+        {
+            for (file_id, entry) in rom_data[fat.clone()].chunks_exact(8).enumerate() {
+                let (start, end) = (
+                    rom::u32_at(entry, 0) as usize,
+                    rom::u32_at(entry, 4) as usize,
+                );
+                let name = paths
+                    .get(&(file_id as u16))
+                    .map_or_else(|| "overlay".to_owned(), |path| format!("{path:?}"));
+                let entry_start = fat.start + file_id * 8;
+                annotations.push(Annotation::new(
+                    entry_start..entry_start + 8,
+                    format!("FAT entry {file_id}: 0x{start:X}..0x{end:X}, {name}"),
+                ));
+                if start < end && end <= rom_data.len() {
+                    annotations.push(Annotation::new(
+                        start..start,
+                        format!(
+                            "start of file {file_id} ({name}, 0x{:X} bytes)",
+                            end - start
+                        ),
+                    ));
+                    annotations.extend(nitro_annotations(&rom_data[..end], start));
+                }
+            }
+        }
+    }
+    annotations.sort_by_key(|annotation| annotation.range.start);
+    annotations
+}
+
+/// Formats `data[range]` as a hex dump of 16 bytes per line, with offsets and ASCII. The labels
+/// of the annotations starting on each line follow it, one per line.
+pub fn hexdump(data: &[u8], range: Range<usize>, annotations: &[Annotation]) -> String {
+    let range = range.start.min(data.len())..range.end.min(data.len());
+    let first_line = range.start - range.start % BYTES_PER_LINE;
+    let mut output = String::new();
+    for line_start in (first_line..range.end).step_by(BYTES_PER_LINE) {
+        let _ = write!(output, "{line_start:08X} ");
+        let mut text = String::new();
+        for offset in line_start..line_start + BYTES_PER_LINE {
+            if offset % 8 == 0 {
+                output.push(' ');
+            }
+            if let Some(&byte) = data.get(offset).filter(|_| range.contains(&offset)) {
+                let _ = write!(output, "{byte:02X} ");
+                text.push(if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                });
+            } else {
+                output.push_str("   ");
+                text.push(' ');
+            }
+        }
+        let _ = writeln!(output, " |{text}|");
+
+        let line = line_start.max(range.start)..(line_start + BYTES_PER_LINE).min(range.end);
+        let first = annotations.partition_point(|annotation| annotation.range.start < line.start);
+        for annotation in annotations[first..]
+            .iter()
+            .take_while(|annotation| annotation.range.start < line.end)
+        {
+            let _ = writeln!(
+                output,
+                "{:>10} 0x{:X}: {}",
+                "^", annotation.range.start, annotation.label
+            );
+        }
+    }
+    output
+}
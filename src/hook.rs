@@ -0,0 +1,226 @@
+use std::{fmt, path::Path, process::Command, sync::Arc};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::{cache::CompressionCache, manifest};
+
+/// Argument of a hook command replaced with the path of the file to convert.
+pub const INPUT_PLACEHOLDER: &str = "{input}";
+/// Argument of a hook command replaced with the path the converted file is to be written to.
+pub const OUTPUT_PLACEHOLDER: &str = "{output}";
+
+/// An external command converting the files matching a glob pattern, for formats ravends doesn't
+/// support. Commands are given as a program followed by its arguments, in which `{input}` and
+/// `{output}` are replaced with the paths of temporary files: the one holding the data to
+/// convert, and the one the command is to write the result to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hook {
+    /// Name of the hook, recorded in the manifest for the files it unpacks.
+    pub name: String,
+    /// Glob pattern of the NitroFS paths of the files converted. Patterns without a `/` are
+    /// matched against file names, so that `*.scb` matches files in every directory.
+    pub glob: String,
+    /// Command converting a file after it's extracted, and decompressed if it was compressed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unpack: Vec<String>,
+    /// Command converting an edited file back before it's packed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pack: Vec<String>,
+    /// Extension of unpacked files, replacing the one of the original file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extension: Option<String>,
+}
+
+impl Hook {
+    fn pattern(&self) -> Result<glob::Pattern, glob::PatternError> {
+        glob::Pattern::new(self.glob.trim_start_matches('/'))
+    }
+
+    /// Whether the file at the NitroFS path given is converted by the hook.
+    pub fn matches(&self, nitro_path: &str) -> bool {
+        let Ok(pattern) = self.pattern() else {
+            return false;
+        };
+        let nitro_path = nitro_path.trim_matches('/');
+        let name = if self.glob.contains('/') {
+            nitro_path
+        } else {
+            nitro_path.rsplit('/').next().unwrap_or(nitro_path)
+        };
+        pattern.matches_with(
+            name,
+            glob::MatchOptions {
+                case_sensitive: true,
+                require_literal_separator: true,
+                require_literal_leading_dot: false,
+            },
+        )
+    }
+
+    /// Converts an extracted file, named `file_name` in the ROM.
+    pub fn unpack(
+        &self,
+        file_name: &str,
+        data: &[u8],
+        cache: &CompressionCache,
+    ) -> anyhow::Result<Vec<u8>> {
+        let output_name = match &self.extension {
+            Some(extension) => Path::new(file_name)
+                .with_extension(extension)
+                .to_string_lossy()
+                .into_owned(),
+            None => file_name.to_owned(),
+        };
+        self.run("unpack", &self.unpack, file_name, &output_name, data, cache)
+    }
+
+    /// Converts an edited file back to the one named `file_name` in the ROM.
+    pub fn pack(
+        &self,
+        file_name: &str,
+        data: &[u8],
+        cache: &CompressionCache,
+    ) -> anyhow::Result<Vec<u8>> {
+        let input_name = match &self.extension {
+            Some(extension) => Path::new(file_name)
+                .with_extension(extension)
+                .to_string_lossy()
+                .into_owned(),
+            None => file_name.to_owned(),
+        };
+        self.run("pack", &self.pack, &input_name, file_name, data, cache)
+    }
+
+    /// Runs a command on the data given, through the cache. The temporary files are named as
+    /// given, so that commands relying on extensions find the ones they expect.
+    fn run(
+        &self,
+        stage: &str,
+        command: &[String],
+        input_name: &str,
+        output_name: &str,
+        data: &[u8],
+        cache: &CompressionCache,
+    ) -> anyhow::Result<Vec<u8>> {
+        let Some((program, args)) = command.split_first() else {
+            return Err(anyhow!("hook {:?} has no {stage} command", self.name));
+        };
+        // Output of a command depends on its arguments and its input, including its name.
+        let key = manifest::sha256_hex(
+            &[
+                command.join("\0").as_bytes(),
+                &[0],
+                input_name.as_bytes(),
+                &[0],
+                output_name.as_bytes(),
+                &[0],
+                data,
+            ]
+            .concat(),
+        );
+        cache.hook_output(&key, || {
+            let dir = tempfile::tempdir().context("failed to create temporary directory")?;
+            let input_path = dir.path().join("input").join(input_name);
+            let output_path = dir.path().join("output").join(output_name);
+            for path in [&input_path, &output_path] {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).context("failed to create temporary directory")?;
+                }
+            }
+            fs::write(&input_path, data).context("failed to write temporary file")?;
+            let args = args.iter().map(|arg| {
+                arg.replace(INPUT_PLACEHOLDER, &input_path.to_string_lossy())
+                    .replace(OUTPUT_PLACEHOLDER, &output_path.to_string_lossy())
+            });
+            let output = Command::new(program)
+                .args(args)
+                .output()
+                .with_context(|| format!("hook {:?}: failed to run {program:?}", self.name))?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "hook {:?}: {program:?} exited with {}: {}",
+                    self.name,
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            fs::read(&output_path).with_context(|| {
+                format!(
+                    "hook {:?}: {program:?} did not write the converted file",
+                    self.name
+                )
+            })
+        })
+    }
+}
+
+/// The hooks given, tried in the order they were given.
+#[derive(Clone, Default)]
+pub struct Hooks(Arc<Vec<Hook>>);
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|hook| &hook.name))
+            .finish()
+    }
+}
+
+/// A file declaring hooks, of which only the `[[hook]]` tables are read so that project files
+/// can be given.
+#[derive(Deserialize)]
+struct HookFile {
+    #[serde(default, rename = "hook")]
+    hooks: Vec<Hook>,
+}
+
+impl Hooks {
+    /// Checks the hooks given, which must have distinct names and valid patterns.
+    pub fn new(hooks: Vec<Hook>) -> anyhow::Result<Self> {
+        for (index, hook) in hooks.iter().enumerate() {
+            hook.pattern()
+                .with_context(|| format!("invalid pattern in hook {:?}", hook.name))?;
+            if hooks[..index].iter().any(|other| other.name == hook.name) {
+                anyhow::bail!("more than one hook is named {:?}", hook.name);
+            }
+        }
+        Ok(Self(Arc::new(hooks)))
+    }
+
+    /// Reads the `[[hook]]` tables of the TOML file at the path given, if one is given.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read hook file {path:?}"))?;
+        let file: HookFile = toml::from_str(&contents)
+            .with_context(|| format!("failed to parse hook file {path:?}"))?;
+        Self::new(file.hooks)
+    }
+
+    /// The first hook converting the file at the NitroFS path given, when unpacking.
+    pub fn find(&self, nitro_path: &str) -> Option<&Hook> {
+        self.0
+            .iter()
+            .find(|hook| !hook.unpack.is_empty() && hook.matches(nitro_path))
+    }
+
+    /// The first hook converting the asset replacing the file at the NitroFS path given, when
+    /// building a project.
+    pub fn find_pack(&self, nitro_path: &str) -> Option<&Hook> {
+        self.0
+            .iter()
+            .find(|hook| !hook.pack.is_empty() && hook.matches(nitro_path))
+    }
+
+    pub fn get(&self, name: &str) -> anyhow::Result<&Hook> {
+        self.0
+            .iter()
+            .find(|hook| hook.name == name)
+            .ok_or_else(|| anyhow!("the {name:?} hook is needed, but wasn't given"))
+    }
+}
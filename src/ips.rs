@@ -0,0 +1,154 @@
+use thiserror::Error;
+
+pub const MAGIC: &[u8; 5] = b"PATCH";
+const EOF_MARKER: &[u8; 3] = b"EOF";
+/// IPS offsets are 24 bits long, so data past this size can't be patched.
+pub const MAX_ADDRESSABLE_SIZE: usize = 1 << 24;
+
+#[derive(Error, Debug)]
+pub enum ApplyIpsError {
+    #[error("not an IPS patch (magic number does not match)")]
+    MagicNumberMismatch,
+    #[error("patch is truncated (record at offset 0x{offset:X} of the patch is incomplete)")]
+    Truncated { offset: usize },
+    #[error("patch is truncated (it has no EOF marker)")]
+    MissingEofMarker,
+}
+
+fn read_be(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |value, &byte| (value << 8) | byte as usize)
+}
+
+/// Applies an IPS patch to `source`, returning the patched data.
+///
+/// Records may write past the end of the source, growing it. The 3-byte truncation size some
+/// tools write after the EOF marker is also supported.
+pub fn decode(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, ApplyIpsError> {
+    let mut rest = patch
+        .strip_prefix(MAGIC)
+        .ok_or(ApplyIpsError::MagicNumberMismatch)?;
+    let mut output = source.to_vec();
+    loop {
+        let offset = patch.len() - rest.len();
+        // An offset of 0x454F46 can't be patched, since it reads as the EOF marker.
+        if let Some(after_eof) = rest.strip_prefix(EOF_MARKER) {
+            if let Some(size) = after_eof.get(..3) {
+                output.truncate(read_be(size));
+            }
+            return Ok(output);
+        }
+        if rest.is_empty() {
+            return Err(ApplyIpsError::MissingEofMarker);
+        }
+
+        let truncated = || ApplyIpsError::Truncated { offset };
+        let header = rest.get(..5).ok_or_else(truncated)?;
+        let (address, size) = (read_be(&header[..3]), read_be(&header[3..]));
+        rest = &rest[5..];
+        let data = if size == 0 {
+            // RLE record: a 16-bit count and the byte to repeat.
+            let rle = rest.get(..3).ok_or_else(truncated)?;
+            rest = &rest[3..];
+            vec![rle[2]; read_be(&rle[..2])]
+        } else {
+            let data = rest.get(..size).ok_or_else(truncated)?.to_vec();
+            rest = &rest[size..];
+            data
+        };
+
+        if output.len() < address + data.len() {
+            output.resize(address + data.len(), 0);
+        }
+        output[address..address + data.len()].copy_from_slice(&data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a patch out of its records, each being its header and data.
+    fn patch(records: &[&[u8]], after_eof: &[u8]) -> Vec<u8> {
+        let mut patch = MAGIC.to_vec();
+        for record in records {
+            patch.extend_from_slice(record);
+        }
+        patch.extend_from_slice(EOF_MARKER);
+        patch.extend_from_slice(after_eof);
+        patch
+    }
+
+    #[test]
+    fn applies_records() {
+        let source = [0u8; 8];
+        let patch = patch(&[&[0, 0, 1, 0, 2, 0xAA, 0xBB], &[0, 0, 6, 0, 1, 0xCC]], &[]);
+        assert_eq!(
+            decode(&source, &patch).unwrap(),
+            [0, 0xAA, 0xBB, 0, 0, 0, 0xCC, 0]
+        );
+        assert_eq!(decode(&source, &self::patch(&[], &[])).unwrap(), source);
+    }
+
+    #[test]
+    fn applies_rle_records() {
+        let source = [1u8; 8];
+        let patch = patch(&[&[0, 0, 2, 0, 0, 0, 4, 0xEE]], &[]);
+        assert_eq!(
+            decode(&source, &patch).unwrap(),
+            [1, 1, 0xEE, 0xEE, 0xEE, 0xEE, 1, 1]
+        );
+    }
+
+    #[test]
+    fn grows_the_output() {
+        let source = [1u8; 4];
+        // A record straddling the end, then one past it leaving a gap of zeroes.
+        let patch = patch(&[&[0, 0, 3, 0, 2, 2, 2], &[0, 0, 7, 0, 0, 0, 2, 3]], &[]);
+        assert_eq!(
+            decode(&source, &patch).unwrap(),
+            [1, 1, 1, 2, 2, 0, 0, 3, 3]
+        );
+    }
+
+    #[test]
+    fn truncates_to_the_size_after_eof() {
+        let source = [1u8; 8];
+        let patch = patch(&[&[0, 0, 0, 0, 1, 9]], &[0, 0, 3]);
+        assert_eq!(decode(&source, &patch).unwrap(), [9, 1, 1]);
+        // The size only shrinks the output.
+        let patch = self::patch(&[], &[0, 0, 0x10]);
+        assert_eq!(decode(&source, &patch).unwrap(), source);
+        // Anything shorter than a size after the marker is ignored.
+        let patch = self::patch(&[], &[0, 0]);
+        assert_eq!(decode(&source, &patch).unwrap(), source);
+    }
+
+    #[test]
+    fn rejects_broken_patches() {
+        assert!(matches!(
+            decode(&[], b"PATCx"),
+            Err(ApplyIpsError::MagicNumberMismatch)
+        ));
+        let full = patch(
+            &[&[0, 0, 1, 0, 2, 0xAA, 0xBB], &[0, 0, 0, 0, 0, 0, 4, 0]],
+            &[],
+        );
+        let without_eof = &full[..full.len() - EOF_MARKER.len()];
+        assert!(matches!(
+            decode(&[], without_eof),
+            Err(ApplyIpsError::MissingEofMarker)
+        ));
+        // Patches cut between records lack the EOF marker, and inside one, the record.
+        for len in MAGIC.len() + 1..without_eof.len() {
+            match decode(&[], &without_eof[..len]) {
+                Err(ApplyIpsError::MissingEofMarker) => assert_eq!(len, 12),
+                Err(ApplyIpsError::Truncated { offset }) => {
+                    assert_eq!(offset, if len < 12 { 5 } else { 12 })
+                }
+                result => panic!("{len}: {result:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! The library behind ravends, for reading, unpacking and patching NDS ROMs.
+//!
+//! Besides the command line tool, it is built as a C-compatible dynamic library; see [`ffi`].
+//!
+//! The heavier subsystems are behind cargo features, all enabled by the default `cli` feature:
+//! `graphics` for graphics, textures and fonts, `audio` for sound archives, `scripting` for
+//! plugins and the scripting console, and `tui` for the terminal browser. `mount` adds mounting ROMs with FUSE.
+
+pub mod asm;
+#[cfg(feature = "graphics")]
+pub mod banner;
+pub mod blz;
+pub mod bmg;
+pub mod bps;
+#[cfg(feature = "tui")]
+pub mod browse;
+pub mod cache;
+pub mod cheat;
+#[cfg(feature = "scripting")]
+pub mod console;
+pub mod control_codes;
+pub mod convert;
+pub mod diff;
+pub mod disasm;
+pub mod entry_template;
+pub mod ffi;
+pub mod fnt;
+pub mod freespace;
+pub mod fs_edit;
+#[cfg(feature = "graphics")]
+pub mod gfx;
+pub mod hashes;
+pub mod heuristics;
+pub mod hexdump;
+pub mod hook;
+pub mod ips;
+pub mod lint;
+pub mod logger;
+pub mod lz;
+pub mod lz10;
+pub mod lz11;
+pub mod magic;
+pub mod manifest;
+pub mod memory;
+#[cfg(feature = "mount")]
+pub mod mount;
+pub mod narc;
+#[cfg(feature = "graphics")]
+pub mod nftr;
+#[cfg(feature = "graphics")]
+pub mod nsbmd;
+#[cfg(feature = "graphics")]
+pub mod nsbtx;
+pub mod overlay;
+pub mod pack;
+pub mod palette;
+pub mod patch;
+pub mod patchdir;
+pub mod path_escape;
+pub mod plugin;
+pub mod profile;
+pub mod project;
+pub mod release;
+pub mod report;
+pub mod rom;
+pub mod rom_diff;
+pub mod rom_map;
+pub mod rom_stats;
+pub mod save;
+#[cfg(feature = "audio")]
+pub mod sbnk;
+#[cfg(feature = "audio")]
+pub mod sdat;
+pub mod search;
+pub mod secure_area;
+#[cfg(feature = "audio")]
+pub mod sf2;
+pub mod source;
+#[cfg(feature = "audio")]
+pub mod sseq;
+pub mod status;
+pub mod string_insert;
+pub mod string_scan;
+pub mod survey;
+pub mod symbols;
+pub mod table;
+pub mod text;
+pub mod text_formats;
+#[cfg(feature = "graphics")]
+pub mod tmx;
+pub mod translation;
+pub mod tree;
+pub mod unpack;
+pub mod vcdiff;
+pub mod verify;
+#[cfg(feature = "cli")]
+pub mod watch;
+#[cfg(feature = "audio")]
+pub mod wave;
@@ -0,0 +1,251 @@
+use std::{fmt, path::Path};
+
+#[cfg(feature = "graphics")]
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::text::{TextEncoding, TextEntry};
+#[cfg(feature = "graphics")]
+use crate::{lz10::decompress_lz10, nftr::Nftr};
+
+/// Limits strings are checked against when linting them, as given in a project file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LintConfig {
+    /// NFTR font the strings are drawn with, optionally LZ10-compressed. If given, characters
+    /// missing from it are reported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font: Option<std::path::PathBuf>,
+    /// Maximum width of a line in pixels, when drawn with `font`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_width: Option<usize>,
+    /// Maximum size of a line in bytes, once encoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_line_bytes: Option<usize>,
+}
+
+/// What strings are checked against.
+#[derive(Debug, Clone, Default)]
+pub struct LintRules {
+    /// The font strings are drawn with. Fonts need the `graphics` feature.
+    #[cfg(feature = "graphics")]
+    pub font: Option<Nftr>,
+    pub max_width: Option<usize>,
+    pub max_line_bytes: Option<usize>,
+}
+
+impl LintRules {
+    /// Loads the rules of a project's lint configuration, whose paths are relative to
+    /// `project_dir`.
+    pub fn load(config: &LintConfig, project_dir: &Path) -> anyhow::Result<Self> {
+        #[cfg(not(feature = "graphics"))]
+        if let Some(path) = &config.font {
+            anyhow::bail!(
+                "ravends was built without the `graphics` feature, so it can't lint with the font {:?}",
+                project_dir.join(path)
+            );
+        }
+        #[cfg(feature = "graphics")]
+        let font = config
+            .font
+            .as_ref()
+            .map(|path| {
+                let path = project_dir.join(path);
+                let data =
+                    std::fs::read(&path).with_context(|| format!("failed to read {path:?}"))?;
+                let data = decompress_lz10(data.as_slice()).unwrap_or(data);
+                Nftr::parse(&data).with_context(|| format!("failed to parse font {path:?}"))
+            })
+            .transpose()?;
+        Ok(Self {
+            #[cfg(feature = "graphics")]
+            font,
+            max_width: config.max_width,
+            max_line_bytes: config.max_line_bytes,
+        })
+    }
+}
+
+/// A problem found in a string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LintProblem {
+    /// An escape starting at the byte offset given is missing its closing delimiter.
+    UnterminatedEscape {
+        position: usize,
+    },
+    /// The string can't be encoded, for the reason given.
+    Unencodable(String),
+    /// Characters the font has no glyph for.
+    MissingCharacters(String),
+    TooWide {
+        width: usize,
+        max_width: usize,
+    },
+    TooLong {
+        bytes: usize,
+        max_bytes: usize,
+    },
+    /// Escapes of the original string missing from the translation, and escapes of the
+    /// translation missing from the original string.
+    PlaceholderMismatch {
+        missing: Vec<String>,
+        extra: Vec<String>,
+    },
+}
+
+/// A problem found in a string, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintIssue {
+    /// Index of the string.
+    pub index: usize,
+    /// Number of the line of the string, counting from 1, for problems found in a line.
+    pub line: Option<usize>,
+    pub problem: LintProblem,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "string {}", self.index)?;
+        if let Some(line) = self.line {
+            write!(f, ", line {line}")?;
+        }
+        f.write_str(": ")?;
+        match &self.problem {
+            LintProblem::UnterminatedEscape { position } => {
+                write!(f, "unterminated control code at byte {position}")
+            }
+            LintProblem::Unencodable(reason) => write!(f, "can't be encoded: {reason}"),
+            LintProblem::MissingCharacters(characters) => {
+                write!(f, "characters missing from the font: {characters:?}")
+            }
+            LintProblem::TooWide { width, max_width } => {
+                write!(f, "{width} pixels wide, over {max_width}")
+            }
+            LintProblem::TooLong { bytes, max_bytes } => {
+                write!(f, "{bytes} bytes long, over {max_bytes}")
+            }
+            LintProblem::PlaceholderMismatch { missing, extra } => {
+                f.write_str("control codes differ from the original")?;
+                if !missing.is_empty() {
+                    write!(f, ", missing {}", missing.join(" "))?;
+                }
+                if !extra.is_empty() {
+                    write!(f, ", adding {}", extra.join(" "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Splits the escapes out of a string, in order, given the delimiters of the encoding. Returns
+/// the byte offset of the first escape with no closing delimiter before the next escape, line
+/// break or the end of the string as an error.
+fn escapes<'a>(string: &'a str, delimiters: &[(char, char)]) -> Result<Vec<&'a str>, usize> {
+    let mut escapes = Vec::new();
+    let mut offset = 0;
+    while let Some(start) =
+        string[offset..].find(|ch| delimiters.iter().any(|&(open, _)| ch == open))
+    {
+        let start = offset + start;
+        let open = string[start..].chars().next().unwrap();
+        let close = delimiters.iter().find(|&&(o, _)| o == open).unwrap().1;
+        let rest = &string[start + open.len_utf8()..];
+        let end = rest
+            .find(|ch| ch == close || ch == '\n' || delimiters.iter().any(|&(o, _)| ch == o))
+            .filter(|&end| rest[end..].starts_with(close))
+            .ok_or(start)?;
+        let end = start + open.len_utf8() + end + close.len_utf8();
+        escapes.push(&string[start..end]);
+        offset = end;
+    }
+    Ok(escapes)
+}
+
+/// Checks strings for problems that would garble them or crash the game: broken control
+/// codes, characters missing from the font, lines over the budgets given, and control codes
+/// that differ from the ones of the original string, for entries giving it.
+pub fn lint(entries: &[TextEntry], encoding: &TextEncoding, rules: &LintRules) -> Vec<LintIssue> {
+    let delimiters = encoding.escape_delimiters();
+    let terminator_size = encoding.encode("", 0).map_or(0, |bytes| bytes.len());
+    let mut issues = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let mut issue = |line, problem| {
+            issues.push(LintIssue {
+                index,
+                line,
+                problem,
+            })
+        };
+        let text = &entry.text;
+        match escapes(text, delimiters) {
+            Err(position) => issue(None, LintProblem::UnterminatedEscape { position }),
+            Ok(mut found) => {
+                if let Some(mut original) = entry
+                    .original
+                    .as_deref()
+                    .and_then(|original| escapes(original, delimiters).ok())
+                {
+                    original.sort_unstable();
+                    found.sort_unstable();
+                    let missing = multiset_difference(&original, &found);
+                    let extra = multiset_difference(&found, &original);
+                    if !missing.is_empty() || !extra.is_empty() {
+                        issue(None, LintProblem::PlaceholderMismatch { missing, extra });
+                    }
+                }
+            }
+        }
+        if let Err(error) = encoding.encode(text, index) {
+            let reason = match std::error::Error::source(&error) {
+                Some(source) => source.to_string(),
+                None => error.to_string(),
+            };
+            issue(None, LintProblem::Unencodable(reason));
+            continue;
+        }
+
+        let mut missing = Vec::<char>::new();
+        for (line_index, line) in text.split('\n').enumerate() {
+            let line_number = Some(line_index + 1);
+            if let Some(max_bytes) = rules.max_line_bytes {
+                let bytes = encoding
+                    .encode(line, index)
+                    .map_or(0, |bytes| bytes.len() - terminator_size);
+                if bytes > max_bytes {
+                    issue(line_number, LintProblem::TooLong { bytes, max_bytes });
+                }
+            }
+            #[cfg(feature = "graphics")]
+            if let Some(font) = &rules.font {
+                let (width, line_missing) = font.measure(&encoding.printable_text(line));
+                missing.extend(line_missing);
+                if let Some(max_width) = rules.max_width.filter(|&max_width| width > max_width) {
+                    issue(line_number, LintProblem::TooWide { width, max_width });
+                }
+            }
+        }
+        missing.sort_unstable();
+        missing.dedup();
+        if !missing.is_empty() {
+            issue(
+                None,
+                LintProblem::MissingCharacters(missing.into_iter().collect()),
+            );
+        }
+    }
+    issues
+}
+
+/// The items of `a` left once each item of `b` removes one equal to it. Both must be sorted.
+fn multiset_difference(a: &[&str], b: &[&str]) -> Vec<String> {
+    let mut b = b.iter().peekable();
+    let mut difference = Vec::new();
+    for item in a {
+        while b.next_if(|other| *other < item).is_some() {}
+        if b.next_if(|other| *other == item).is_none() {
+            difference.push(item.to_string());
+        }
+    }
+    difference
+}
@@ -0,0 +1,51 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Number of warnings logged so far.
+static WARNING_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Logger printing messages as they are, with warnings and errors going to the standard error
+/// and prefixed with their level.
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Error => eprintln!("error: {}", record.args()),
+            Level::Warn => {
+                WARNING_COUNT.fetch_add(1, Ordering::Relaxed);
+                eprintln!("warning: {}", record.args())
+            }
+            Level::Info | Level::Debug | Level::Trace => println!("{}", record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Sets up logging for the verbosity given: warnings only when quiet, then information, details
+/// for every file with one `-v`, and everything else with more.
+pub fn init(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::Warn,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    // Logging is only set up once, so this can't fail.
+    let _ = log::set_logger(&Logger);
+    log::set_max_level(level);
+}
+
+/// Number of warnings logged since logging was set up.
+pub fn warning_count() -> usize {
+    WARNING_COUNT.load(Ordering::Relaxed)
+}
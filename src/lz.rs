@@ -0,0 +1,242 @@
+use clap::ValueEnum;
+
+/// How hard the LZ compressors try to make their output small.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum CompressionLevel {
+    /// Take the longest match at every position, which is fast but can leave bytes on the table
+    #[default]
+    Fast,
+    /// Pick the cheapest combination of matches and literals for the whole file
+    Best,
+}
+
+pub const MIN_MATCH_LEN: usize = 3;
+/// Distance the sliding window reaches back to, shared by LZ10 & LZ11.
+const WINDOW_SIZE: usize = 0x1000;
+const HASH_SIZE: usize = 1 << 16;
+/// Matches this long are carried over to the next position, one byte shorter, instead of being
+/// searched for again. Searching them again would make long runs take quadratic time.
+const CARRIED_MATCH_LEN: usize = 0x100;
+/// Match lengths up to this one are all considered when parsing; past it, only the longest
+/// match is, as all of them cost the same to encode.
+const EXHAUSTIVE_MATCH_LEN: usize = 0x110;
+
+/// Hash chains over 3-byte prefixes of the data, finding earlier occurrences of it.
+struct MatchFinder<'data> {
+    data: &'data [u8],
+    /// Latest position of every hash; `usize::MAX` marks the end of a chain.
+    head: Vec<usize>,
+    /// Previous position with the same hash as every position.
+    prev: Vec<usize>,
+    /// Shortest distance matches may have.
+    min_distance: usize,
+}
+
+impl<'data> MatchFinder<'data> {
+    fn new(data: &'data [u8], min_distance: usize) -> Self {
+        Self {
+            data,
+            head: vec![usize::MAX; HASH_SIZE],
+            prev: vec![usize::MAX; data.len()],
+            min_distance,
+        }
+    }
+
+    fn hash_at(&self, pos: usize) -> usize {
+        let data = self.data;
+        ((data[pos] as usize) << 8 ^ (data[pos + 1] as usize) << 4 ^ data[pos + 2] as usize)
+            & (HASH_SIZE - 1)
+    }
+
+    /// Makes the position given available to later matches.
+    fn insert(&mut self, pos: usize) {
+        if pos + MIN_MATCH_LEN <= self.data.len() {
+            let hash = self.hash_at(pos);
+            self.prev[pos] = self.head[hash];
+            self.head[hash] = pos;
+        }
+    }
+
+    /// Finds the longest match for the data at `pos` within the sliding window, up to `max_len`
+    /// bytes long, returning its length and distance (1-based).
+    fn find(&self, pos: usize, max_len: usize) -> Option<(usize, usize)> {
+        let data = self.data;
+        if pos + MIN_MATCH_LEN > data.len() {
+            return None;
+        }
+        let max_len = max_len.min(data.len() - pos);
+        let mut best: Option<(usize, usize)> = None;
+        let mut candidate = self.head[self.hash_at(pos)];
+        while candidate != usize::MAX && pos - candidate <= WINDOW_SIZE {
+            if pos - candidate < self.min_distance {
+                candidate = self.prev[candidate];
+                continue;
+            }
+            let len = data[candidate..]
+                .iter()
+                .zip(&data[pos..pos + max_len])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len >= MIN_MATCH_LEN && best.is_none_or(|(best_len, _)| len > best_len) {
+                best = Some((len, pos - candidate));
+                if len == max_len {
+                    break;
+                }
+            }
+            candidate = self.prev[candidate];
+        }
+        best
+    }
+}
+
+/// A piece of LZ-compressed data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Literal(u8),
+    /// A copy of `len` bytes from `distance` bytes back.
+    Match {
+        len: usize,
+        distance: usize,
+    },
+}
+
+/// How many matches and literals compressed data is made of.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenCounts {
+    pub literals: usize,
+    pub matches: usize,
+}
+
+/// Splits data into tokens, taking the longest match at every position. Matches are at least
+/// `min_distance` bytes back.
+pub fn greedy_parse(data: &[u8], max_len: usize, min_distance: usize) -> Vec<Token> {
+    let mut finder = MatchFinder::new(data, min_distance);
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        match finder.find(pos, max_len) {
+            Some((len, distance)) => {
+                tokens.push(Token::Match { len, distance });
+                for matched_pos in pos..pos + len {
+                    finder.insert(matched_pos);
+                }
+                pos += len;
+            }
+            None => {
+                tokens.push(Token::Literal(data[pos]));
+                finder.insert(pos);
+                pos += 1;
+            }
+        }
+    }
+    tokens
+}
+
+/// Splits data into the tokens taking the fewest bits to encode, going backwards over the data
+/// to find the cheapest way to encode the rest of it from every position.
+///
+/// `match_bits` gives the size of a match of the length given, and literals take 9 bits: their
+/// flag bit and the byte itself. Matches are at least `min_distance` bytes back.
+pub fn optimal_parse(
+    data: &[u8],
+    max_len: usize,
+    min_distance: usize,
+    match_bits: impl Fn(usize) -> usize,
+) -> Vec<Token> {
+    const LITERAL_BITS: usize = 9;
+
+    let mut finder = MatchFinder::new(data, min_distance);
+    let mut matches = Vec::with_capacity(data.len());
+    let mut carried: Option<(usize, usize)> = None;
+    for pos in 0..data.len() {
+        let found = match carried {
+            Some((len, distance)) if len > CARRIED_MATCH_LEN => {
+                let len = len - 1;
+                // A carried match that was cut short by the maximum length may go on.
+                let extends =
+                    pos + len < data.len() && data[pos + len] == data[pos + len - distance];
+                Some((if extends { len + 1 } else { len }, distance))
+            }
+            _ => finder.find(pos, max_len),
+        };
+        finder.insert(pos);
+        matches.push(found);
+        carried = found;
+    }
+
+    // Bits needed to encode the data from every position on, and the length of the token
+    // starting there.
+    let mut bits = vec![0; data.len() + 1];
+    let mut token_lens = vec![1; data.len()];
+    for pos in (0..data.len()).rev() {
+        bits[pos] = LITERAL_BITS + bits[pos + 1];
+        let Some((longest, _)) = matches[pos] else {
+            continue;
+        };
+        let lens = (MIN_MATCH_LEN..=longest.min(EXHAUSTIVE_MATCH_LEN))
+            .chain((longest > EXHAUSTIVE_MATCH_LEN).then_some(longest));
+        for len in lens {
+            let match_cost = match_bits(len) + bits[pos + len];
+            if match_cost < bits[pos] {
+                bits[pos] = match_cost;
+                token_lens[pos] = len;
+            }
+        }
+    }
+
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let len = token_lens[pos];
+        match matches[pos] {
+            Some((_, distance)) if len > 1 => tokens.push(Token::Match { len, distance }),
+            _ => tokens.push(Token::Literal(data[pos])),
+        }
+        pos += len;
+    }
+    tokens
+}
+
+/// Writes tokens after their flag bytes, one for every 8 tokens, encoding matches with the
+/// function given.
+pub fn write_tokens(
+    tokens: &[Token],
+    output: &mut Vec<u8>,
+    mut write_match: impl FnMut(&mut Vec<u8>, usize, usize),
+) {
+    for group in tokens.chunks(8) {
+        let flag_byte_idx = output.len();
+        output.push(0);
+        for (index, token) in group.iter().enumerate() {
+            match *token {
+                Token::Literal(byte) => output.push(byte),
+                Token::Match { len, distance } => {
+                    output[flag_byte_idx] |= 0x80 >> index;
+                    write_match(output, len, distance);
+                }
+            }
+        }
+    }
+}
+
+/// Data for the tests of the compressors, compressing to various degrees.
+#[cfg(test)]
+pub mod samples {
+    /// Pseudorandom bytes, which barely compress. Different seeds give different bytes.
+    pub fn noise(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    /// Records of 6 bytes differing in a byte or two each, like the tables of games.
+    pub fn records(count: u32) -> Vec<u8> {
+        (0..count)
+            .flat_map(|index| [index as u8, 0, 0x80, (index % 7) as u8, b'A', b'B'])
+            .collect()
+    }
+}
@@ -1,8 +1,10 @@
-use std::io::Read;
+use std::io::{Read, Write};
 
 use byteorder::ReadBytesExt;
 use thiserror::Error;
 
+use crate::lz::{self, CompressionLevel, TokenCounts, MIN_MATCH_LEN};
+
 #[derive(Error, Debug)]
 pub enum Lz10DecompressionError {
     #[error("file read error")]
@@ -21,30 +23,285 @@ pub fn decompress_lz10(mut reader: impl Read) -> Result<Vec<u8>, Lz10Decompressi
         return Err(Lz10DecompressionError::MagicNumberMismatch { found: magic_num });
     }
     let uncompressed_file_size = reader.read_u24::<byteorder::LittleEndian>()?;
+    decompress_lz10_raw(reader, uncompressed_file_size as usize)
+}
+
+/// Decompresses an LZ10 stream lacking the 4-byte header, as some games embed in other
+/// structures with the decompressed size stored elsewhere.
+pub fn decompress_lz10_raw(
+    mut reader: impl Read,
+    uncompressed_file_size: usize,
+) -> Result<Vec<u8>, Lz10DecompressionError> {
     if uncompressed_file_size == 0 {
         return Err(Lz10DecompressionError::InvalidSize);
     }
-    let mut output = Vec::with_capacity(uncompressed_file_size as usize);
+    let mut output = Vec::with_capacity(uncompressed_file_size);
+    while let Ok(decision_byte) = reader.read_u8() {
+        let mut bit = 0;
+        while bit < 8 {
+            if decision_byte & (0x80 >> bit) != 0 {
+                let pointer_data = reader.read_u16::<byteorder::BigEndian>()?;
+                let length = (pointer_data >> 12) as usize + 3;
+                let offset = (pointer_data & 0xFFF) as usize;
+                if output.len() <= offset {
+                    return Err(Lz10DecompressionError::CannotReferencePastData);
+                }
+                let window_offset = output.len() - offset - 1;
+                if offset + 1 >= length {
+                    output.extend_from_within(window_offset..window_offset + length);
+                } else {
+                    // The match overlaps the data it produces, so it must be copied bytewise.
+                    for point_byte in 0..length {
+                        output.push(output[window_offset + point_byte]);
+                    }
+                }
+                bit += 1;
+            } else {
+                // Literals up to the next match are read at once.
+                let literal_count = ((decision_byte << bit).leading_zeros() as usize)
+                    .min(8 - bit)
+                    .min(uncompressed_file_size - output.len());
+                let start = output.len();
+                output.resize(start + literal_count, 0);
+                reader.read_exact(&mut output[start..])?;
+                bit += literal_count;
+            }
+            if output.len() >= uncompressed_file_size {
+                return Ok(output);
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// Counts the matches and literals of LZ10 data, without decompressing it.
+pub fn count_tokens_lz10(mut reader: impl Read) -> Result<TokenCounts, Lz10DecompressionError> {
+    let magic_num = reader.read_u8()?;
+    if magic_num != 0x10 {
+        return Err(Lz10DecompressionError::MagicNumberMismatch { found: magic_num });
+    }
+    let uncompressed_file_size = reader.read_u24::<byteorder::LittleEndian>()?;
+    count_tokens_lz10_raw(reader, uncompressed_file_size as usize)
+}
+
+/// Counts the matches and literals of an LZ10 stream lacking the 4-byte header.
+pub fn count_tokens_lz10_raw(
+    mut reader: impl Read,
+    uncompressed_file_size: usize,
+) -> Result<TokenCounts, Lz10DecompressionError> {
+    if uncompressed_file_size == 0 {
+        return Err(Lz10DecompressionError::InvalidSize);
+    }
+    let mut counts = TokenCounts::default();
+    let mut decompressed_size = 0;
     while let Ok(decision_byte) = reader.read_u8() {
         for bit in (0..8).rev().map(|idx| (decision_byte & (1 << idx)) != 0) {
             if bit {
                 let pointer_data = reader.read_u16::<byteorder::BigEndian>()?;
-                let length = (pointer_data >> 12) + 3;
-                let offset = pointer_data & 0xFFF;
-                if output.len() <= offset as usize {
+                decompressed_size += (pointer_data >> 12) as usize + 3;
+                counts.matches += 1;
+            } else {
+                reader.read_u8()?;
+                decompressed_size += 1;
+                counts.literals += 1;
+            }
+            if decompressed_size >= uncompressed_file_size {
+                return Ok(counts);
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// Decompresses LZ10 data as it's read, writing it out as it goes. Only the sliding window is
+/// kept in memory, so data of any size can be decompressed. Returns the decompressed size.
+pub fn decompress_lz10_to(
+    mut reader: impl Read,
+    mut writer: impl Write,
+) -> Result<usize, Lz10DecompressionError> {
+    let magic_num = reader.read_u8()?;
+    if magic_num != 0x10 {
+        return Err(Lz10DecompressionError::MagicNumberMismatch { found: magic_num });
+    }
+    let uncompressed_file_size = reader.read_u24::<byteorder::LittleEndian>()? as usize;
+    if uncompressed_file_size == 0 {
+        return Err(Lz10DecompressionError::InvalidSize);
+    }
+
+    let mut window = [0; WINDOW_SIZE];
+    let mut written = 0;
+    // The bytes produced by the tokens of every decision byte are written out together.
+    let mut group = Vec::with_capacity(8 * MAX_MATCH_LEN);
+    'decision_bytes: while let Ok(decision_byte) = reader.read_u8() {
+        for bit in (0..8).rev().map(|idx| (decision_byte & (1 << idx)) != 0) {
+            if bit {
+                let pointer_data = reader.read_u16::<byteorder::BigEndian>()?;
+                let length = (pointer_data >> 12) as usize + 3;
+                let offset = (pointer_data & 0xFFF) as usize;
+                if written <= offset {
                     return Err(Lz10DecompressionError::CannotReferencePastData);
                 }
-                let window_offset = output.len() - offset as usize - 1;
-                for point_byte in 0..length as usize {
-                    output.push(output[window_offset + point_byte]);
+                for _ in 0..length {
+                    let byte = window[(written - offset - 1) % WINDOW_SIZE];
+                    window[written % WINDOW_SIZE] = byte;
+                    group.push(byte);
+                    written += 1;
                 }
             } else {
-                output.push(reader.read_u8()?);
+                let byte = reader.read_u8()?;
+                window[written % WINDOW_SIZE] = byte;
+                group.push(byte);
+                written += 1;
             }
-            if output.len() >= uncompressed_file_size as usize {
-                return Ok(output);
+            if written >= uncompressed_file_size {
+                break 'decision_bytes;
             }
         }
+        writer.write_all(&group)?;
+        group.clear();
+    }
+    writer.write_all(&group)?;
+    writer.flush()?;
+    Ok(written)
+}
+
+#[derive(Error, Debug)]
+pub enum Lz10CompressionError {
+    #[error("file too large to compress (found: {size} bytes, maximum: 0xFFFFFF bytes)")]
+    TooLarge { size: usize },
+    #[error("cannot compress an empty file")]
+    Empty,
+}
+
+const MAX_MATCH_LEN: usize = 0xF + MIN_MATCH_LEN;
+/// Distance matches can reach back to.
+const WINDOW_SIZE: usize = 0x1000;
+
+/// Size of an LZ10 match in bits, including its flag bit.
+const MATCH_BITS: usize = 17;
+
+pub fn compress_lz10(
+    data: &[u8],
+    level: CompressionLevel,
+) -> Result<Vec<u8>, Lz10CompressionError> {
+    if data.len() > 0xFFFFFF {
+        return Err(Lz10CompressionError::TooLarge { size: data.len() });
     }
+    let mut output = Vec::with_capacity(data.len() + data.len() / 8 + 4);
+    output.push(0x10);
+    output.extend_from_slice(&(data.len() as u32).to_le_bytes()[..3]);
+    compress_lz10_into(data, level, &mut output)?;
+    Ok(output)
+}
+
+/// Compresses data with the LZ10 algorithm without the 4-byte header, leaving it to the caller
+/// to store the decompressed size. As the size isn't stored in 24 bits, there's no size limit.
+pub fn compress_lz10_raw(
+    data: &[u8],
+    level: CompressionLevel,
+) -> Result<Vec<u8>, Lz10CompressionError> {
+    let mut output = Vec::with_capacity(data.len() + data.len() / 8);
+    compress_lz10_into(data, level, &mut output)?;
     Ok(output)
 }
+
+fn compress_lz10_into(
+    data: &[u8],
+    level: CompressionLevel,
+    output: &mut Vec<u8>,
+) -> Result<(), Lz10CompressionError> {
+    if data.is_empty() {
+        return Err(Lz10CompressionError::Empty);
+    }
+    let tokens = match level {
+        CompressionLevel::Fast => lz::greedy_parse(data, MAX_MATCH_LEN, 1),
+        CompressionLevel::Best => lz::optimal_parse(data, MAX_MATCH_LEN, 1, |_| MATCH_BITS),
+    };
+    lz::write_tokens(&tokens, output, |output, len, distance| {
+        let pointer_data = ((len - MIN_MATCH_LEN) << 12) | (distance - 1);
+        output.extend_from_slice(&(pointer_data as u16).to_be_bytes());
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lz::samples::{noise, records};
+
+    /// Data compressing to various degrees: text, noise, long runs and repeated records.
+    fn samples() -> Vec<Vec<u8>> {
+        let noise = noise(0x2000, 0x1234_5678);
+        vec![
+            include_bytes!("lz10.rs").to_vec(),
+            noise.clone(),
+            vec![0; 0x3000],
+            records(0x400),
+            [&noise[..0x100], &[0xFF; 0x50], &noise[..0x100]].concat(),
+            vec![0x42],
+        ]
+    }
+
+    /// Encodes data as literals alone, like the worst compressor could.
+    fn compress_literals_only(data: &[u8]) -> Vec<u8> {
+        let mut output = vec![0x10];
+        output.extend_from_slice(&(data.len() as u32).to_le_bytes()[..3]);
+        for chunk in data.chunks(8) {
+            output.push(0);
+            output.extend_from_slice(chunk);
+        }
+        output
+    }
+
+    #[test]
+    fn round_trips_at_every_level() {
+        for data in samples() {
+            for level in [CompressionLevel::Fast, CompressionLevel::Best] {
+                let compressed = compress_lz10(&data, level).unwrap();
+                assert_eq!(decompress_lz10(compressed.as_slice()).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn streaming_matches_in_memory_decompression() {
+        for data in samples() {
+            let compressed = compress_lz10(&data, CompressionLevel::Fast).unwrap();
+            let mut streamed = Vec::new();
+            let size = decompress_lz10_to(compressed.as_slice(), &mut streamed).unwrap();
+            assert_eq!(size, data.len());
+            assert_eq!(streamed, data);
+        }
+    }
+
+    #[test]
+    fn raw_round_trips() {
+        for data in samples() {
+            let compressed = compress_lz10_raw(&data, CompressionLevel::Best).unwrap();
+            assert_eq!(
+                decompress_lz10_raw(compressed.as_slice(), data.len()).unwrap(),
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn best_level_never_exceeds_original_compressed_size() {
+        for data in samples() {
+            let originals = [
+                compress_lz10(&data, CompressionLevel::Fast).unwrap(),
+                compress_literals_only(&data),
+            ];
+            for original in originals {
+                let decompressed = decompress_lz10(original.as_slice()).unwrap();
+                let recompressed = compress_lz10(&decompressed, CompressionLevel::Best).unwrap();
+                assert!(
+                    recompressed.len() <= original.len(),
+                    "recompressed to 0x{:X} bytes, originally 0x{:X} bytes",
+                    recompressed.len(),
+                    original.len()
+                );
+            }
+        }
+    }
+}
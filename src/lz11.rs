@@ -0,0 +1,235 @@
+use std::io::Read;
+
+use byteorder::ReadBytesExt;
+use thiserror::Error;
+
+use crate::lz::{self, CompressionLevel, TokenCounts};
+
+#[derive(Error, Debug)]
+pub enum Lz11DecompressionError {
+    #[error("file read error")]
+    Io(#[from] std::io::Error),
+    #[error("invalid decompressed size (0 bytes)")]
+    InvalidSize,
+    #[error("error while referencing past data")]
+    CannotReferencePastData,
+    #[error("magic number does not match (found: 0x{found:x}, expected: 0x11)")]
+    MagicNumberMismatch { found: u8 },
+}
+
+/// Decompresses data compressed with the LZ11 algorithm, a variant of LZ10 allowing longer
+/// references, used by later games.
+pub fn decompress_lz11(mut reader: impl Read) -> Result<Vec<u8>, Lz11DecompressionError> {
+    let magic_num = reader.read_u8()?;
+    if magic_num != 0x11 {
+        return Err(Lz11DecompressionError::MagicNumberMismatch { found: magic_num });
+    }
+    let mut uncompressed_file_size = reader.read_u24::<byteorder::LittleEndian>()?;
+    // Sizes that don't fit in 24 bits follow the header instead.
+    if uncompressed_file_size == 0 {
+        uncompressed_file_size = reader.read_u32::<byteorder::LittleEndian>()?;
+    }
+    if uncompressed_file_size == 0 {
+        return Err(Lz11DecompressionError::InvalidSize);
+    }
+    let mut output = Vec::with_capacity(uncompressed_file_size as usize);
+    while let Ok(decision_byte) = reader.read_u8() {
+        for bit in (0..8).rev().map(|idx| (decision_byte & (1 << idx)) != 0) {
+            if bit {
+                let first = reader.read_u8()? as usize;
+                let (length, high_offset) = match first >> 4 {
+                    0 => {
+                        let second = reader.read_u8()? as usize;
+                        (((first & 0xF) << 4 | second >> 4) + 0x11, second & 0xF)
+                    }
+                    1 => {
+                        let second = reader.read_u8()? as usize;
+                        let third = reader.read_u8()? as usize;
+                        (
+                            ((first & 0xF) << 12 | second << 4 | third >> 4) + 0x111,
+                            third & 0xF,
+                        )
+                    }
+                    length => (length + 1, first & 0xF),
+                };
+                let offset = high_offset << 8 | reader.read_u8()? as usize;
+                if output.len() <= offset {
+                    return Err(Lz11DecompressionError::CannotReferencePastData);
+                }
+                let window_offset = output.len() - offset - 1;
+                for point_byte in 0..length {
+                    output.push(output[window_offset + point_byte]);
+                }
+            } else {
+                output.push(reader.read_u8()?);
+            }
+            if output.len() >= uncompressed_file_size as usize {
+                output.truncate(uncompressed_file_size as usize);
+                return Ok(output);
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// Counts the matches and literals of LZ11 data, without decompressing it.
+pub fn count_tokens_lz11(mut reader: impl Read) -> Result<TokenCounts, Lz11DecompressionError> {
+    let magic_num = reader.read_u8()?;
+    if magic_num != 0x11 {
+        return Err(Lz11DecompressionError::MagicNumberMismatch { found: magic_num });
+    }
+    let mut uncompressed_file_size = reader.read_u24::<byteorder::LittleEndian>()? as usize;
+    if uncompressed_file_size == 0 {
+        uncompressed_file_size = reader.read_u32::<byteorder::LittleEndian>()? as usize;
+    }
+    if uncompressed_file_size == 0 {
+        return Err(Lz11DecompressionError::InvalidSize);
+    }
+    let mut counts = TokenCounts::default();
+    let mut decompressed_size = 0;
+    while let Ok(decision_byte) = reader.read_u8() {
+        for bit in (0..8).rev().map(|idx| (decision_byte & (1 << idx)) != 0) {
+            if bit {
+                let first = reader.read_u8()? as usize;
+                decompressed_size += match first >> 4 {
+                    0 => {
+                        let second = reader.read_u8()? as usize;
+                        ((first & 0xF) << 4 | second >> 4) + 0x11
+                    }
+                    1 => {
+                        let second = reader.read_u8()? as usize;
+                        let third = reader.read_u8()? as usize;
+                        ((first & 0xF) << 12 | second << 4 | third >> 4) + 0x111
+                    }
+                    length => length + 1,
+                };
+                reader.read_u8()?;
+                counts.matches += 1;
+            } else {
+                reader.read_u8()?;
+                decompressed_size += 1;
+                counts.literals += 1;
+            }
+            if decompressed_size >= uncompressed_file_size {
+                return Ok(counts);
+            }
+        }
+    }
+    Ok(counts)
+}
+
+#[derive(Error, Debug)]
+pub enum Lz11CompressionError {
+    #[error("file too large to compress (found: {size} bytes, maximum: 0xFFFFFFFF bytes)")]
+    TooLarge { size: usize },
+    #[error("cannot compress an empty file")]
+    Empty,
+}
+
+const MAX_MATCH_LEN: usize = 0x10110;
+
+/// Size of an LZ11 match of the length given in bits, including its flag bit. Longer matches
+/// take extra bytes to store their length.
+fn match_bits(len: usize) -> usize {
+    match len {
+        ..=0x10 => 17,
+        0x11..=0x110 => 25,
+        _ => 33,
+    }
+}
+
+pub fn compress_lz11(
+    data: &[u8],
+    level: CompressionLevel,
+) -> Result<Vec<u8>, Lz11CompressionError> {
+    if data.is_empty() {
+        return Err(Lz11CompressionError::Empty);
+    }
+    let size = u32::try_from(data.len())
+        .map_err(|_| Lz11CompressionError::TooLarge { size: data.len() })?;
+
+    let mut output = Vec::with_capacity(data.len() + data.len() / 8 + 8);
+    output.push(0x11);
+    if size <= 0xFFFFFF {
+        output.extend_from_slice(&size.to_le_bytes()[..3]);
+    } else {
+        output.extend_from_slice(&[0; 3]);
+        output.extend_from_slice(&size.to_le_bytes());
+    }
+
+    let tokens = match level {
+        CompressionLevel::Fast => lz::greedy_parse(data, MAX_MATCH_LEN, 1),
+        CompressionLevel::Best => lz::optimal_parse(data, MAX_MATCH_LEN, 1, match_bits),
+    };
+    lz::write_tokens(&tokens, &mut output, |output, len, distance| {
+        let distance = distance - 1;
+        match len {
+            ..=0x10 => {
+                output.extend_from_slice(&[((len - 1) << 4 | distance >> 8) as u8, distance as u8])
+            }
+            0x11..=0x110 => {
+                let len = len - 0x11;
+                output.extend_from_slice(&[
+                    (len >> 4) as u8,
+                    ((len & 0xF) << 4 | distance >> 8) as u8,
+                    distance as u8,
+                ]);
+            }
+            _ => {
+                let len = len - 0x111;
+                output.extend_from_slice(&[
+                    (0x10 | len >> 12) as u8,
+                    (len >> 4) as u8,
+                    ((len & 0xF) << 4 | distance >> 8) as u8,
+                    distance as u8,
+                ]);
+            }
+        }
+    });
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lz::samples::{noise, records};
+
+    /// Data compressing to various degrees: text, noise, long runs and repeated records.
+    fn samples() -> Vec<Vec<u8>> {
+        let noise = noise(0x2000, 0x8765_4321);
+        vec![
+            include_bytes!("lz11.rs").to_vec(),
+            noise.clone(),
+            // Long enough for matches needing the 4-byte encoding.
+            vec![0; 0x12000],
+            records(0x400),
+            [&noise[..0x800], &[0xFF; 0x150], &noise[..0x800]].concat(),
+            vec![0x42],
+        ]
+    }
+
+    #[test]
+    fn round_trips_at_every_level() {
+        for data in samples() {
+            for level in [CompressionLevel::Fast, CompressionLevel::Best] {
+                let compressed = compress_lz11(&data, level).unwrap();
+                assert_eq!(decompress_lz11(compressed.as_slice()).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn best_level_never_exceeds_original_compressed_size() {
+        for data in samples() {
+            let original = compress_lz11(&data, CompressionLevel::Fast).unwrap();
+            let decompressed = decompress_lz11(original.as_slice()).unwrap();
+            let recompressed = compress_lz11(&decompressed, CompressionLevel::Best).unwrap();
+            assert!(
+                recompressed.len() <= original.len(),
+                "recompressed to 0x{:X} bytes, originally 0x{:X} bytes",
+                recompressed.len(),
+                original.len()
+            );
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use std::fmt;
+
+use crate::narc::Narc;
+
+/// A file format recognized by the magic number at the start of its files.
+struct Signature {
+    /// Name the format is reported as.
+    name: &'static str,
+    category: Category,
+    magic: &'static [u8],
+    /// Checks the rest of a file starting with the magic number and describes its contents,
+    /// returning `None` if the file isn't actually valid.
+    inspect: fn(&[u8]) -> Option<String>,
+}
+
+/// Formats known to [`identify`], tried in order.
+const SIGNATURES: &[Signature] = &[
+    Signature {
+        name: "NARC archive",
+        category: Category::Archive,
+        magic: b"NARC",
+        inspect: inspect_narc,
+    },
+    Signature {
+        name: "NCGR graphics",
+        category: Category::Graphics,
+        magic: b"RGCN",
+        inspect: inspect_ncgr,
+    },
+    Signature {
+        name: "NCLR palette",
+        category: Category::Graphics,
+        magic: b"RLCN",
+        inspect: inspect_nclr,
+    },
+    Signature {
+        name: "NSCR screen",
+        category: Category::Graphics,
+        magic: b"RCSN",
+        inspect: inspect_nscr,
+    },
+    Signature {
+        name: "NCER cell bank",
+        category: Category::Graphics,
+        magic: b"RECN",
+        inspect: inspect_ncer,
+    },
+    Signature {
+        name: "NANR animation bank",
+        category: Category::Graphics,
+        magic: b"RNAN",
+        inspect: inspect_nanr,
+    },
+    Signature {
+        name: "SDAT sound archive",
+        category: Category::Sound,
+        magic: b"SDAT",
+        inspect: inspect_sdat,
+    },
+    Signature {
+        name: "SSEQ sequence",
+        category: Category::Sound,
+        magic: b"SSEQ",
+        inspect: inspect_sseq,
+    },
+    Signature {
+        name: "SWAV wave",
+        category: Category::Sound,
+        magic: b"SWAV",
+        inspect: inspect_swav,
+    },
+    Signature {
+        name: "SWAR wave archive",
+        category: Category::Sound,
+        magic: b"SWAR",
+        inspect: inspect_swar,
+    },
+    Signature {
+        name: "STRM stream",
+        category: Category::Sound,
+        magic: b"STRM",
+        inspect: inspect_strm,
+    },
+    Signature {
+        name: "NSBMD model",
+        category: Category::Model,
+        magic: b"BMD0",
+        inspect: inspect_nsbmd,
+    },
+    Signature {
+        name: "NSBTX texture archive",
+        category: Category::Model,
+        magic: b"BTX0",
+        inspect: inspect_nsbtx,
+    },
+    Signature {
+        name: "NFTR font",
+        category: Category::Font,
+        magic: b"RTFN",
+        inspect: inspect_nftr,
+    },
+    Signature {
+        name: "BMG message file",
+        category: Category::Text,
+        magic: b"MESGbmg1",
+        inspect: inspect_bmg,
+    },
+];
+
+/// The kind of content a format holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Archive,
+    Graphics,
+    Sound,
+    Model,
+    Font,
+    Text,
+}
+
+impl Category {
+    /// Name of the directory files of this category are grouped into.
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            Category::Archive => "archive",
+            Category::Graphics => "gfx",
+            Category::Sound => "sound",
+            Category::Model => "model",
+            Category::Font => "font",
+            Category::Text => "text",
+        }
+    }
+}
+
+/// A format detected by [`identify`], along with a short description of the file's contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identification {
+    pub format: &'static str,
+    pub category: Category,
+    /// Key metadata, such as dimensions or entry counts. May be empty.
+    pub details: String,
+}
+
+impl fmt::Display for Identification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.details.is_empty() {
+            write!(f, "{}", self.format)
+        } else {
+            write!(f, "{}, {}", self.format, self.details)
+        }
+    }
+}
+
+/// Detects the format of a file from its magic number and validates its structure.
+pub fn identify(data: &[u8]) -> Option<Identification> {
+    SIGNATURES
+        .iter()
+        .filter(|signature| data.starts_with(signature.magic))
+        .find_map(|signature| {
+            Some(Identification {
+                format: signature.name,
+                category: signature.category,
+                details: (signature.inspect)(data)?,
+            })
+        })
+}
+
+/// Describes a file in a single line if its format is known.
+pub fn describe(data: &[u8]) -> Option<String> {
+    identify(data).map(|identification| identification.to_string())
+}
+
+fn u8_at(data: &[u8], offset: usize) -> Option<u8> {
+    data.get(offset).copied()
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Validates the header shared by most Nitro formats, returning its size and section count.
+fn nitro_header(data: &[u8]) -> Option<(usize, u16)> {
+    let byte_order_mark = u16_at(data, 0x4)?;
+    let file_size = u32_at(data, 0x8)? as usize;
+    let header_size = u16_at(data, 0xC)? as usize;
+    let valid = matches!(byte_order_mark, 0xFEFF | 0xFFFE)
+        && header_size >= 0x10
+        && (header_size..=data.len()).contains(&file_size);
+    valid.then_some((header_size, u16_at(data, 0xE)?))
+}
+
+/// Finds a section in a Nitro file whose sections follow each other right after the header.
+fn chained_section(data: &[u8], magic: &[u8; 4]) -> Option<usize> {
+    let (mut offset, section_count) = nitro_header(data)?;
+    for _ in 0..section_count {
+        if data.get(offset..offset + 4)? == magic {
+            return Some(offset);
+        }
+        let size = u32_at(data, offset + 4)? as usize;
+        if size < 8 {
+            return None;
+        }
+        offset += size;
+    }
+    None
+}
+
+/// Finds a section in a Nitro file whose section offsets are listed after the header, as in
+/// 3D model and texture files.
+fn indexed_section(data: &[u8], magic: &[u8; 4]) -> Option<usize> {
+    let (_, section_count) = nitro_header(data)?;
+    (0..section_count as usize)
+        .map(|index| u32_at(data, 0x10 + index * 4).map(|offset| offset as usize))
+        .collect::<Option<Vec<_>>>()?
+        .into_iter()
+        .find(|&offset| data.get(offset..offset + 4) == Some(magic))
+}
+
+/// Number of entries of a 3D resource dictionary.
+fn dictionary_len(data: &[u8], offset: usize) -> Option<u8> {
+    u8_at(data, offset + 1)
+}
+
+fn bit_depth(depth: u32) -> Option<&'static str> {
+    match depth {
+        3 => Some("4bpp"),
+        4 => Some("8bpp"),
+        _ => None,
+    }
+}
+
+fn inspect_narc(data: &[u8]) -> Option<String> {
+    let narc = Narc::parse(data).ok()?;
+    let naming = if narc.is_named() { "named" } else { "nameless" };
+    Some(format!("{} {naming} files", narc.files.len()))
+}
+
+fn inspect_ncgr(data: &[u8]) -> Option<String> {
+    let section = chained_section(data, b"RAHC")?;
+    let tiles_high = u16_at(data, section + 0x8)?;
+    let tiles_wide = u16_at(data, section + 0xA)?;
+    let depth = u32_at(data, section + 0xC)?;
+    let data_size = u32_at(data, section + 0x18)?;
+    let depth_name = bit_depth(depth)?;
+    let tile_count = data_size / if depth == 3 { 0x20 } else { 0x40 };
+    Some(if tiles_high == 0xFFFF || tiles_wide == 0xFFFF {
+        format!("{tile_count} tiles, {depth_name}")
+    } else {
+        format!(
+            "{}x{} pixels ({tile_count} tiles), {depth_name}",
+            tiles_wide as u32 * 8,
+            tiles_high as u32 * 8
+        )
+    })
+}
+
+fn inspect_nclr(data: &[u8]) -> Option<String> {
+    let section = chained_section(data, b"TTLP")?;
+    let depth_name = bit_depth(u32_at(data, section + 0x8)?)?;
+    let data_size = u32_at(data, section + 0x10)?;
+    Some(format!("{} colors, {depth_name}", data_size / 2))
+}
+
+fn inspect_nscr(data: &[u8]) -> Option<String> {
+    let section = chained_section(data, b"NRCS")?;
+    let width = u16_at(data, section + 0x8)?;
+    let height = u16_at(data, section + 0xA)?;
+    Some(format!("{width}x{height} pixels"))
+}
+
+fn inspect_ncer(data: &[u8]) -> Option<String> {
+    let section = chained_section(data, b"KBEC")?;
+    let cell_count = u16_at(data, section + 0x8)?;
+    Some(format!("{cell_count} cells"))
+}
+
+fn inspect_nanr(data: &[u8]) -> Option<String> {
+    let section = chained_section(data, b"KNBA")?;
+    let animation_count = u16_at(data, section + 0x8)?;
+    let frame_count = u16_at(data, section + 0xA)?;
+    Some(format!(
+        "{animation_count} animations, {frame_count} frames"
+    ))
+}
+
+fn inspect_sdat(data: &[u8]) -> Option<String> {
+    nitro_header(data)?;
+    let fat = u32_at(data, 0x20)? as usize;
+    if data.get(fat..fat + 4)? != b"FAT " {
+        return None;
+    }
+    let file_count = u32_at(data, fat + 0x8)?;
+    Some(format!("{file_count} files"))
+}
+
+fn inspect_sseq(data: &[u8]) -> Option<String> {
+    let section = chained_section(data, b"DATA")?;
+    let data_offset = u32_at(data, section + 0x8)? as usize;
+    let file_size = u32_at(data, 0x8)? as usize;
+    let data_size = file_size.checked_sub(data_offset)?;
+    Some(format!("0x{data_size:X} bytes of sequence data"))
+}
+
+fn wave_encoding(encoding: u8) -> Option<&'static str> {
+    match encoding {
+        0 => Some("PCM8"),
+        1 => Some("PCM16"),
+        2 => Some("IMA-ADPCM"),
+        _ => None,
+    }
+}
+
+fn inspect_swav(data: &[u8]) -> Option<String> {
+    let section = chained_section(data, b"DATA")?;
+    let encoding = wave_encoding(u8_at(data, section + 0x8)?)?;
+    let sample_rate = u16_at(data, section + 0xA)?;
+    Some(format!("{sample_rate} Hz, {encoding}"))
+}
+
+fn inspect_swar(data: &[u8]) -> Option<String> {
+    let section = chained_section(data, b"DATA")?;
+    let wave_count = u32_at(data, section + 0x28)?;
+    Some(format!("{wave_count} waves"))
+}
+
+fn inspect_strm(data: &[u8]) -> Option<String> {
+    let section = chained_section(data, b"HEAD")?;
+    let encoding = wave_encoding(u8_at(data, section + 0x8)?)?;
+    let channel_count = u8_at(data, section + 0xA)?;
+    let sample_rate = u16_at(data, section + 0xC)?;
+    Some(format!(
+        "{channel_count} channels, {sample_rate} Hz, {encoding}"
+    ))
+}
+
+fn inspect_nsbmd(data: &[u8]) -> Option<String> {
+    let models = indexed_section(data, b"MDL0")?;
+    let model_count = dictionary_len(data, models + 0x8)?;
+    Some(if indexed_section(data, b"TEX0").is_some() {
+        format!("{model_count} models, with textures")
+    } else {
+        format!("{model_count} models")
+    })
+}
+
+fn inspect_nsbtx(data: &[u8]) -> Option<String> {
+    let textures = indexed_section(data, b"TEX0")?;
+    let texture_dictionary = u16_at(data, textures + 0xE)? as usize;
+    let palette_dictionary = u32_at(data, textures + 0x34)? as usize;
+    let texture_count = dictionary_len(data, textures + texture_dictionary)?;
+    let palette_count = dictionary_len(data, textures + palette_dictionary)?;
+    Some(format!(
+        "{texture_count} textures, {palette_count} palettes"
+    ))
+}
+
+fn inspect_nftr(data: &[u8]) -> Option<String> {
+    let info = chained_section(data, b"FNIF")?;
+    let line_height = u8_at(data, info + 0x9)?;
+    let glyphs = chained_section(data, b"PLGC")?;
+    let glyphs_size = u32_at(data, glyphs + 0x4)?;
+    let cell_width = u8_at(data, glyphs + 0x8)?;
+    let cell_height = u8_at(data, glyphs + 0x9)?;
+    let glyph_size = u16_at(data, glyphs + 0xA)?;
+    let bits_per_pixel = u8_at(data, glyphs + 0xE)?;
+    let glyph_count = glyphs_size.checked_sub(0x10)? / u32::from(glyph_size).max(1);
+    Some(format!(
+        "{glyph_count} glyphs of {cell_width}x{cell_height} pixels, {bits_per_pixel}bpp, \
+         line height {line_height}"
+    ))
+}
+
+fn inspect_bmg(data: &[u8]) -> Option<String> {
+    let encoding = match u8_at(data, 0x10)? {
+        0 | 1 => "CP1252",
+        2 => "UTF-16",
+        3 => "Shift-JIS",
+        4 => "UTF-8",
+        _ => return None,
+    };
+    if data.get(0x20..0x24)? != b"INF1" {
+        return None;
+    }
+    let message_count = u16_at(data, 0x28)?;
+    Some(format!("{message_count} messages, {encoding}"))
+}
@@ -1,89 +1,842 @@
 use std::{
-    char::DecodeUtf16Error,
-    io::{Read, Write},
+    io::{BufRead, BufReader, BufWriter, Read, Write},
     path::{Path, PathBuf},
-    string::FromUtf16Error,
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Context};
-use clap::{Parser, Subcommand};
-use lz10::decompress_lz10;
+use cache::CompressionCache;
+use clap::{Args, Parser, Subcommand};
+use entry_template::EntryTemplate;
+use hook::Hooks;
+use log::{debug, info, warn};
+use lz::{CompressionLevel, TokenCounts};
+use lz10::{compress_lz10, decompress_lz10};
+use manifest::Processor;
+use memory::Binary;
+use patch::PatchFormat;
+use plugin::Plugins;
+use profile::Profile;
+#[cfg(feature = "mount")]
+use ravends::mount;
+use ravends::{
+    asm, banner, browse, cache, cheat, console, convert, disasm, entry_template, freespace,
+    fs_edit, gfx, hashes, heuristics, hexdump, hook, ips, lint, logger, lz, lz10, lz11, manifest,
+    memory, narc, nftr, nsbmd, nsbtx, overlay, pack, palette, patch, patchdir, path_escape, plugin,
+    profile, project, release, report, rom, rom_diff, rom_map, rom_stats, save, sdat, search,
+    secure_area, source, sseq, status, string_insert, string_scan, survey, symbols, text,
+    text_formats, tmx, translation, tree, unpack, verify, watch, wave,
+};
+use save::SaveFormat;
+use search::SearchEncoding;
+use secure_area::SecureAreaForm;
+use source::RomSource;
+use status::{ExitStatus, FailOn, StatusError};
 use std::fs;
-use thiserror::Error;
-
-use byteorder::ReadBytesExt;
-
-mod lz10;
-
-#[derive(Error, Debug)]
-enum ParseTextError {
-    #[error("file read error")]
-    Io(#[from] std::io::Error),
-    #[error("UTF-16 character decode error")]
-    Utf16(#[from] DecodeUtf16Error),
-    #[error("invalid pointer found on header")]
-    InvalidPointer,
-}
-
-fn parse_text_file(data: &[u8]) -> Result<Vec<String>, ParseTextError> {
-    let mut header = data;
-    let text_count = header.read_u32::<byteorder::LittleEndian>()? as usize;
-    let header_size = text_count * std::mem::size_of::<u32>();
-    (0..text_count)
-        .map(|_| {
-            let pointer = header.read_u32::<byteorder::LittleEndian>()? as usize;
-            if pointer < header_size {
-                return Err(ParseTextError::InvalidPointer);
-            }
-            let pointer_data = &data[pointer..];
-            char::decode_utf16(
-                pointer_data
-                    .chunks_exact(2)
-                    .map(|ch| u16::from_le_bytes(ch.try_into().unwrap()))
-                    .take_while(|&ch| ch != 0),
-            )
-            .collect::<Result<String, _>>()
-            .map_err(ParseTextError::from)
-        })
-        .collect()
-}
+use survey::Survey;
+use symbols::SymbolMap;
+use text::{
+    parse_text_file, EncodingKind, TableFormat, TextArchive, TextEncoding, TextEntry, TextLayout,
+};
+use text_formats::{ScriptFormat, TextFormat};
+use translation::TranslationStatus;
+use unpack::{convert_file, Conversion};
 
 #[derive(Debug, Parser)]
 #[command(name = "ravends")]
 #[command(about = "NDS unpacking & patching tool", long_about = None)]
+#[command(after_long_help = EXIT_STATUS_HELP)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Print more details, such as what every unpacked file was detected as; give it twice for even more
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Only print warnings and errors
+    #[arg(
+        short,
+        long,
+        global = true,
+        default_value_t = false,
+        conflicts_with = "verbose"
+    )]
+    quiet: bool,
+    /// Member of a zip archive to read the ROM from, when it holds several ROMs
+    ///
+    /// ROMs given inside zip archives are read directly; this chooses which one is read.
+    #[arg(long, global = true)]
+    member: Option<String>,
+    /// When to exit with a failure status
+    ///
+    /// With 'warnings', commands that succeed but print warnings exit with status 5. With 'none', the status is always 0.
+    #[arg(long, global = true, value_enum, default_value_t)]
+    fail_on: FailOn,
+}
+
+/// The exit statuses, described in `--help`.
+const EXIT_STATUS_HELP: &str = "\
+Exit status:
+  0   success
+  1   any other failure, such as a file that can't be read or written
+  2   a file given couldn't be parsed
+  3   a check failed, such as a round trip, a hash check or a lint
+  4   some files failed to unpack, and were written as they are stored
+  5   warnings were printed, with --fail-on warnings
+  64  the command line is invalid";
+
+/// The `--member` given, choosing which ROM to read from zip archives.
+static ZIP_MEMBER: OnceLock<Option<String>> = OnceLock::new();
+
+/// Reads a ROM, from inside a zip archive if it's given one.
+fn read_rom(path: &Path) -> anyhow::Result<Vec<u8>> {
+    rom::read_rom_member(path, ZIP_MEMBER.get().and_then(Option::as_deref))
+}
+
+/// Opens a ROM to read parts of it, from inside a zip archive if it's given one.
+fn open_rom(path: &Path) -> anyhow::Result<Box<dyn RomSource + Send + Sync>> {
+    source::open(path, ZIP_MEMBER.get().and_then(Option::as_deref))
+}
+
+/// Fails if the path given is a zip archive, which must not be overwritten by a ROM read from
+/// inside it.
+fn check_not_zip(target_path: &Path) -> anyhow::Result<()> {
+    if rom::is_zip(target_path) {
+        return Err(anyhow!(
+            "{target_path:?} is a zip archive, which would be overwritten; give another path to write the ROM to"
+        ));
+    }
+    Ok(())
 }
 
 #[derive(Debug, Subcommand)]
 enum Commands {
-    /// Try to decompress a file using the LZ10 algorithm
+    /// Try to decompress files using the LZ10 or LZ11 algorithm
     Decompress {
-        /// Path of the file to decompress
+        /// Paths of the files to decompress, or `-` to read a single file from the standard input
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Where to place the resulting file, or `-` to write it to the standard output
+        ///
+        /// If empty, `path.decomp` will be used instead, or the standard output when reading from the standard input. When decompressing several files, this is the directory to place them in instead.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Decompress every LZ10- or LZ11-compressed file inside the directories given, skipping the rest
+        #[arg(short, long, default_value_t = false, conflicts_with = "raw")]
+        recursive: bool,
+        /// Decompress LZ10 streams lacking the 4-byte header, as embedded in other structures by some games
+        #[arg(long, default_value_t = false, requires = "size")]
+        raw: bool,
+        /// Size of the decompressed data, for streams decompressed with `--raw`
+        #[arg(long, value_parser = parse_number, requires = "raw")]
+        size: Option<u64>,
+        /// Print the compressed and decompressed sizes, how many matches and literals the data is made of, and the time taken
+        #[arg(long, default_value_t = false)]
+        stats: bool,
+    },
+    /// Compress a file using the LZ10 or LZ11 algorithm
+    Compress {
+        /// Path of the file to compress, or `-` to read it from the standard input
         path: PathBuf,
-        /// Where to place the resulting file
+        /// Where to place the resulting file, or `-` to write it to the standard output
         ///
-        /// If empty, `path + .decomp` will be used instead
+        /// If empty, `path + .lz` will be used instead, or the standard output when reading from the standard input
         target_path: Option<PathBuf>,
+        /// Leave out the 4-byte header holding the decompressed size, for games storing it elsewhere
+        #[arg(long, default_value_t = false)]
+        raw: bool,
+        /// Compress with LZ11 instead, which allows longer matches
+        #[arg(long, default_value_t = false, conflicts_with = "raw")]
+        lz11: bool,
+        /// How hard to try to make the compressed file small
+        #[arg(long, value_enum, default_value_t)]
+        level: CompressionLevel,
+        /// Print the original and compressed sizes, how many matches and literals the result is made of, and the time taken
+        #[arg(long, default_value_t = false)]
+        stats: bool,
     },
     /// Try to identify a file from its contents
+    ///
+    /// Several files can be given, such as regional variants of a game, each identified in turn
+    /// and followed by a summary of them all.
     Identify {
-        /// Path of the file to identify
+        /// Paths of the files to identify
+        ///
+        /// Directories stand for the ROMs inside them, unless `--recursive` is given.
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Identify every file inside a directory, ROM or NARC archive instead, descending into
+        /// compressed files and nested archives, and print a summary of the formats found
+        #[arg(short, long)]
+        recursive: bool,
+        /// Also write the summary as a single HTML file, with the file tree, a chart of the
+        /// formats found, samples of text files and thumbnails of graphics
+        ///
+        /// Only available when identifying a single file or directory.
+        #[arg(long, requires = "recursive")]
+        report: Option<PathBuf>,
+        /// Identify every file of a directory again instead of reusing the results of previous runs
+        ///
+        /// Results are cached by content in `.ravends-cache` inside the directory identified.
+        #[arg(long, default_value_t = false, requires = "recursive")]
+        no_cache: bool,
+        #[command(flatten)]
+        encoding: EncodingArgs,
+        /// Rhai script adding support for one of the game's own formats
+        ///
+        /// Can be given multiple times; the first plugin recognizing a file is used.
+        #[arg(long = "plugin")]
+        plugins: Vec<PathBuf>,
+    },
+    /// Convert a file to an image, a WAV or MIDI file, JSON or its decompressed contents, detecting its format as `identify` does
+    ///
+    /// Compressed files are decompressed first. NCGR graphics are drawn with the palette given by --palette.
+    Convert {
+        /// The file to convert
         path: PathBuf,
+        /// Where to place the result, which is a directory for NARC archives, SWAR wave archives and NSBTX texture archives
+        ///
+        /// If empty, the software will place it alongside the file given, with the extension of the kind of file converted to, or without an extension for directories.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// The format of the file, such as `ncgr`, `swav` or `text`, or `auto` to detect it
+        #[arg(long, default_value = "auto")]
+        from: String,
+        /// The kind of file to convert it to
+        #[arg(long, value_enum)]
+        to: convert::Target,
+        /// The NCLR palette file to draw graphics with, optionally LZ10-compressed
+        #[arg(long)]
+        palette: Option<PathBuf>,
+        #[command(flatten)]
+        encoding: EncodingArgs,
+        /// Rhai script adding support for one of the game's own formats, when detecting the format of the file
+        ///
+        /// Can be given multiple times; the first plugin recognizing a file is used.
+        #[arg(long = "plugin")]
+        plugins: Vec<PathBuf>,
     },
     /// Unpack a ROM file's contents to a directory
+    ///
+    /// Several ROMs can be given, or directories of ROMs, each unpacked to its own directory
+    /// and followed by a summary of them all.
+    #[command(override_usage = "ravends unpack [OPTIONS] <ROM_PATHS>...\n       \
+                                ravends unpack [OPTIONS] <ROM_PATH> [TARGET_PATH]")]
     Unpack {
-        /// The ROM file to unpack
-        rom_path: PathBuf,
+        /// The ROM files to unpack, or directories holding them
+        ///
+        /// A single ROM file may be followed by where to unpack it, as long as that path isn't an existing file and doesn't have the extension of a ROM.
+        #[arg(required = true, value_name = "ROM_PATHS")]
+        paths: Vec<PathBuf>,
         /// Where to unpack the resulting files, without creating a parent folder for them
         ///
-        /// If empty, the software will create a folder of the same name as the ROM in its same path, and unpack it there.
-        target_path: Option<PathBuf>,
+        /// When unpacking several ROMs, each is unpacked to a folder of the same name as the ROM inside it. If empty, the software will create a folder of the same name as each ROM in its same path, and unpack it there.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
 
         /// If set, the software will not do any modifications on the file system
         #[arg(long, default_value_t = false)]
         dry_run: bool,
+        /// Only unpack files whose NitroFS path matches this glob pattern (e.g. `data/message/**`)
+        ///
+        /// Can be given multiple times. If not given, all files will be unpacked.
+        #[arg(long)]
+        include: Vec<String>,
+        /// Do not unpack files whose NitroFS path matches this glob pattern
+        ///
+        /// Can be given multiple times. Takes precedence over `--include`.
+        #[arg(long)]
+        exclude: Vec<String>,
+        /// Extract NARC archives (compressed or not) to a directory in their place, descending into any archives nested inside them
+        #[arg(long, default_value_t = false)]
+        recursive: bool,
+        /// Also export NCGR graphics to PNGs inside the `_gfx` directory, drawn with the NCLR palette and NSCR screen of the same name
+        ///
+        /// NCLR palettes are also exported as Adobe ACT, JASC PAL and GIMP GPL files. Edited PNGs and palette files are imported back when packing. NCER cell banks are also drawn cell by cell, along with a description of their NANR animations, and the textures of NSBTX and NSBMD files are exported, but these are only meant for viewing.
+        #[arg(long, default_value_t = false)]
+        convert_gfx: bool,
+        /// Decrypt the secure area of the ARM9 binary using the key table of this ARM7 BIOS dump (or of a file holding only the 0x1048-byte table)
+        ///
+        /// The secure area will be encrypted again when packing, which then needs the same file.
+        #[arg(long)]
+        bios: Option<PathBuf>,
+        /// State the secure area is to have once packed, instead of the one of the original ROM
+        ///
+        /// Decrypted secure areas also have their `encryObj` ID replaced with the destroyed one (0xE7FFDEFF), as most dumps have it. Decrypting an encrypted secure area, or encrypting it when packing, needs `--bios`.
+        #[arg(long, value_enum)]
+        secure_area: Option<SecureAreaForm>,
+        /// Keep unpacking when a file fails to, writing it as it is stored instead, and list the files that failed at the end
+        #[arg(long, default_value_t = false)]
+        keep_going: bool,
+        /// Place all NitroFS files directly in the target directory, named by their file ID, instead of mirroring their paths
+        #[arg(long, default_value_t = false, conflicts_with = "by_type")]
+        flat: bool,
+        /// Group the NitroFS files by the kind of content they hold, mirroring their paths inside `text/`, `gfx/`, `sound/`, `model/`, `font/`, `archive/` or `other/`
+        #[arg(long, default_value_t = false)]
+        by_type: bool,
+        /// Confidence, in percent, a file must be detected as a text file with to be converted to one
+        ///
+        /// `identify` shows the confidence of each format a file may be in. Raise it if binary files get exported as text files.
+        #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(u8).range(0..=100))]
+        min_confidence: u8,
+        /// Leave LZ10 & LZ11 compressed files compressed, as they are stored in the ROM
+        #[arg(long, default_value_t = false)]
+        no_decompress: bool,
+        /// Don't convert text files, including BMG message files, to text entry templates
+        ///
+        /// Along with `--no-decompress`, every NitroFS file is written byte for byte as the FAT locates it.
+        #[arg(long, default_value_t = false)]
+        no_convert_text: bool,
+        /// Also write a summary of the ROM's contents as a single HTML file, with the file tree, a chart of the formats found, samples of text files and thumbnails of graphics
+        ///
+        /// Only available when unpacking a single ROM.
+        #[arg(long)]
+        report: Option<PathBuf>,
+        /// Rhai script adding support for one of the game's own formats
+        ///
+        /// Can be given multiple times; the first plugin recognizing a file is used.
+        #[arg(long = "plugin")]
+        plugins: Vec<PathBuf>,
+        /// TOML file whose `[[hook]]` tables declare external commands converting the files matching glob patterns, such as a project's ravends.toml
+        ///
+        /// Each hook has a `name`, a `glob` and `unpack` and `pack` commands given as arrays of arguments, in which `{input}` and `{output}` are replaced with temporary files. Hooks are tried before plugins, and their output is cached.
+        #[arg(long)]
+        hooks: Option<PathBuf>,
+        #[command(flatten)]
+        profile: ProfileArgs,
+    },
+    /// Extract a single file, or all files matching a glob pattern, from a ROM
+    Extract {
+        /// The ROM file to extract from
+        rom_path: PathBuf,
+        /// NitroFS path of the file to extract, or a glob pattern such as `data/msg/*`
+        pattern: String,
+        /// Where to place the extracted files
+        ///
+        /// If only one file matches, this is the path of the resulting file. Otherwise, this is the directory to extract the matching files to, mirroring their NitroFS paths.
+        /// If empty, the current directory will be used.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Only decompress LZ10 & LZ11 files, without converting their contents
+        #[arg(long, default_value_t = false, conflicts_with = "raw")]
+        decompress: bool,
+        /// Extract the files exactly as stored in the ROM, without decompressing or converting them
+        #[arg(long, default_value_t = false)]
+        raw: bool,
+    },
+    /// Print a single file of a ROM to the standard output, decompressing it if it's LZ10- or LZ11-compressed
+    Cat {
+        /// The ROM file to read from
+        rom_path: PathBuf,
+        /// NitroFS path of the file to print
+        nitro_path: String,
+        /// Print the file exactly as stored in the ROM, without decompressing it
+        #[arg(long, default_value_t = false)]
+        raw: bool,
+    },
+    /// Print a hex dump of part of a ROM, or of any other file
+    ///
+    /// With `--annotate`, known structures are labeled below the lines they start on: header
+    /// fields with their values, FAT and overlay table entries, the start of sections and files,
+    /// and the headers of Nitro files and their chunks.
+    Hexdump {
+        /// The ROM (or other file) to dump
+        rom_path: PathBuf,
+        /// Offset to start at
+        #[arg(long, value_parser = parse_number, default_value = "0")]
+        at: u64,
+        /// Number of bytes to dump
+        #[arg(long, value_parser = parse_number, default_value = "0x100")]
+        len: u64,
+        /// Label header fields, table entries and Nitro chunk headers
+        #[arg(long, default_value_t = false)]
+        annotate: bool,
+    },
+    /// Print the title, game code, maker, unit code, version and region of a ROM, along with its size and file count
+    Info {
+        /// The ROM files to print the details of, or directories holding them
+        #[arg(required = true)]
+        rom_paths: Vec<PathBuf>,
+    },
+    /// Change fields of the ROM header
+    Header {
+        #[command(subcommand)]
+        command: HeaderCommands,
+    },
+    /// Export the icon of a ROM's banner to PNG, animated DSi icons included, and import it back
+    Icon {
+        #[command(subcommand)]
+        command: IconCommands,
+    },
+    /// Add, remove or move files in the NitroFS of a ROM, rebuilding its FNT & FAT
+    Fs {
+        #[command(subcommand)]
+        command: FsCommands,
+    },
+    /// Assemble ARM & Thumb code and write it into the ARM9 binary and overlays of a ROM
+    ///
+    /// The source places code at RAM addresses with `.org`, as the ARM9 binary and overlays
+    /// are loaded; `.overlay N` makes following addresses refer to overlay N, and `.arm9` back
+    /// to the static ARM9 code. Code is hooked into the game by overwriting its instructions
+    /// with branches to labels. `.freespace` places the code following it in the free space
+    /// `freespace` finds, in the selected overlay or the ARM9 binary.
+    Asmpatch {
+        /// The ROM file to patch
+        rom_path: PathBuf,
+        /// The assembly source to apply
+        patch_path: PathBuf,
+        /// Symbol files naming addresses the source can refer to, such as no$gba `.sym` files,
+        /// GNU ld `.map` files or `name = 0x02001234;` lists
+        #[arg(short, long = "symbols")]
+        symbol_paths: Vec<PathBuf>,
+        /// Where to place the resulting ROM
+        ///
+        /// If empty, the ROM given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Report what would change in the ROM, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Disassemble ARM or Thumb code from the ARM9 binary, ARM7 binary or an ARM9 overlay of a ROM
+    ///
+    /// Addresses are shown as the code is loaded in RAM. Compressed binaries can't be
+    /// disassembled.
+    #[command(group(clap::ArgGroup::new("binary").required(true)))]
+    Disasm {
+        /// The ROM file to disassemble code from
+        rom_path: PathBuf,
+        /// Disassemble the ARM9 binary
+        #[arg(long, group = "binary")]
+        arm9: bool,
+        /// Disassemble the ARM7 binary
+        #[arg(long, group = "binary")]
+        arm7: bool,
+        /// Disassemble the ARM9 overlay with the ID given
+        #[arg(long, group = "binary")]
+        overlay: Option<u32>,
+        /// RAM address to start at, or a symbol; defaults to the start of the binary
+        #[arg(long)]
+        start: Option<String>,
+        /// Number of bytes to disassemble; defaults to the rest of the binary
+        #[arg(long, value_parser = parse_number)]
+        len: Option<u64>,
+        /// Disassemble Thumb code instead of ARM code
+        #[arg(long, default_value_t = false)]
+        thumb: bool,
+        /// Symbol files naming addresses, used to label the listing
+        #[arg(short, long = "symbols")]
+        symbol_paths: Vec<PathBuf>,
+    },
+    /// Translate a RAM address into the offset in the ROM it's loaded from, or a ROM offset into the RAM address it's loaded to
+    ///
+    /// The ARM9 and ARM7 binaries and the ARM9 overlays are looked up; an address overlays share is listed once for each of them. Addresses in compressed binaries have no ROM offset.
+    Addr {
+        /// The ROM the binaries are loaded from
+        rom_path: PathBuf,
+        /// The RAM address, or ROM offset with `--rom-offset`
+        #[arg(value_parser = parse_number)]
+        value: u64,
+        /// Translate a ROM offset into a RAM address instead
+        #[arg(short, long, default_value_t = false)]
+        rom_offset: bool,
+    },
+    /// Bake Action Replay DS codes into a ROM
+    Cheat {
+        #[command(subcommand)]
+        command: CheatCommands,
+    },
+    /// Print the directory hierarchy of a ROM as a tree, with the size, FAT offset and compression of every file
+    Tree {
+        /// The ROM file to print the tree of
+        rom_path: PathBuf,
+    },
+    /// Browse the NitroFS of a ROM in the terminal, previewing files and extracting or replacing them
+    ///
+    /// The tree of files is shown beside what the selected file was identified as and a hex dump
+    /// of its contents, decompressed if it's compressed. Extracted files are converted as with
+    /// `extract` and placed in the current directory, mirroring their NitroFS paths.
+    Browse {
+        /// The ROM file to browse
+        rom_path: PathBuf,
+        /// Where to write the ROM once files have been replaced
+        ///
+        /// If empty, the ROM given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        encoding: EncodingArgs,
+    },
+    /// Run a Rhai script with access to the library: opening ROMs, listing, reading and replacing their files, (de)compressing data and saving
+    ///
+    /// The arguments following the script are given to it as the `args` array of strings. See
+    /// `repl` for the functions available.
+    Run {
+        /// The Rhai script to run
+        script_path: PathBuf,
+        /// Arguments given to the script
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Start an interactive Rhai console, with access to the library as in `run`
+    ///
+    /// Statements are read line by line, and their values printed. Lines ending with `\` are
+    /// continued on the next one. The functions available are:
+    ///
+    /// - `open_rom(path)`, giving a ROM with the `path`, `title` and `game_code` properties
+    /// - `rom.files()` and `rom.files(pattern)`, giving the NitroFS paths of its files
+    /// - `rom.read(path)`, giving the contents of a file as stored
+    /// - `rom.replace(path, data)`, replacing a file as given, without compressing it
+    /// - `rom.save()` and `rom.save(path)`, writing the ROM back over itself or elsewhere
+    /// - `decompress(data)` and `compress(data, "lz10" | "lz11")`
+    /// - `narc_files(data)`, giving the files of a NARC archive with their `name` and `data`
+    /// - `read_file(path)` and `write_file(path, data)`, for files outside ROMs
+    Repl {
+        /// A ROM to open as the `rom` variable
+        rom_path: Option<PathBuf>,
+    },
+    /// Show how the space of a ROM is used: its header, binaries, tables, overlays and files, the padding between them, and any FAT entries overlapping each other
+    ///
+    /// An overview bar of the whole ROM is followed by a line for each region, with a bar locating it.
+    Layout {
+        /// The ROM file to map
+        rom_path: PathBuf,
+        /// Print the regions and overlaps as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Summarize what the space of a ROM is used by: code, overlays, each top-level directory, compressed and uncompressed data, and padding
+    ///
+    /// Useful to find where space can be reclaimed when a ROM grows past the size of its cartridge.
+    Stats {
+        /// The ROM file to summarize
+        rom_path: PathBuf,
+        /// Number of the largest binaries, overlays and files to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Mount the NitroFS of a ROM as a read-only directory, until it's unmounted
+    ///
+    /// LZ10-compressed files get a sibling with the `.decomp` extension holding their
+    /// decompressed contents. Unmount the directory with `fusermount -u` (or `umount` on macOS)
+    /// to stop.
+    #[cfg(feature = "mount")]
+    Mount {
+        /// The ROM file to mount
+        rom_path: PathBuf,
+        /// The empty directory to mount the NitroFS at
+        mount_point: PathBuf,
+    },
+    /// Search every file of a ROM for text, printing the path and offset of each occurrence
+    ///
+    /// Compressed files are decompressed first, with offsets into the decompressed data.
+    Grep {
+        /// The ROM file to search
+        rom_path: PathBuf,
+        /// The text to search for
+        text: String,
+        /// Encodings to search for the text in; defaults to UTF-16LE, Shift-JIS and ASCII
+        #[arg(short, long, value_enum)]
+        encoding: Vec<SearchEncoding>,
+    },
+    /// List the free space of the ARM9 binary and its overlays: runs of padding bytes, and the memory overlays leave unused past their BSS when sharing their memory with larger ones
+    ///
+    /// Runs of 0x00 bytes may be arrays of zeroes the game uses, so check them before relying on them.
+    Freespace {
+        /// The ROM file to analyze
+        rom_path: PathBuf,
+        /// Size of the smallest region to list
+        #[arg(long, value_parser = parse_number, default_value_t = freespace::DEFAULT_MIN_SIZE as u64)]
+        min_size: u64,
+        /// Print the regions as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Scan the code binaries of a ROM for string literals, printing the RAM address of each, the number of words pointing to it, and its text
+    ///
+    /// Compressed binaries are decompressed first. Only words of the binaries scanned count as pointers.
+    ScanStrings {
+        /// The ROM file to scan
+        rom_path: PathBuf,
+        /// Scan the ARM9 binary
+        #[arg(long, default_value_t = false)]
+        arm9: bool,
+        /// Scan the ARM7 binary
+        #[arg(long, default_value_t = false)]
+        arm7: bool,
+        /// Scan the ARM9 overlay with the ID given; can be given several times
+        ///
+        /// If no binary is given, the ARM9 binary and every ARM9 overlay are scanned.
+        #[arg(long)]
+        overlay: Vec<u32>,
+        /// Encoding of the strings; Shift-JIS strings may be ASCII only
+        #[arg(short, long, value_enum, default_value_t = SearchEncoding::ShiftJis)]
+        encoding: SearchEncoding,
+        /// Minimum number of characters of a string
+        #[arg(long, default_value_t = 4)]
+        min_len: usize,
+        /// Print the strings as JSON, with the address and ROM offset of each pointer to them
+        #[arg(long, default_value_t = false)]
+        json: bool,
+        /// Print the strings as CSV, with an empty column for their translation, as `insert-strings` reads them
+        #[arg(long, default_value_t = false, conflicts_with = "json")]
+        csv: bool,
+    },
+    /// Replace string literals of the ARM9 binary and its overlays with their translations, given as CSV with an `address`, an `original` and a `translation` column, as `scan-strings --csv` writes
+    ///
+    /// Translations that don't fit where the original string was are moved to the free space given, and the pointers to the original string are changed to point to them. Strings without a translation are left as they are.
+    InsertStrings {
+        /// The ROM file to insert the strings into
+        rom_path: PathBuf,
+        /// The CSV file with the translations
+        csv_path: PathBuf,
+        /// Where to place the resulting ROM
+        ///
+        /// If empty, the ROM given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Encoding of the strings
+        #[arg(short, long, value_enum, default_value_t = SearchEncoding::ShiftJis)]
+        encoding: SearchEncoding,
+        /// Where to move translations that don't fit: `arm9`, past the end of the ARM9 binary and the memory its static code zeroes, `overlay:<ID>`, past the end of an ARM9 overlay, or `auto`, the free space `freespace` finds
+        ///
+        /// With `arm9` and `overlay:<ID>`, the memory past the binary, which grows, must be unused by the game; it usually belongs to the game's heap, in which case the start of the heap has to be moved past it by patching the code. With `auto`, strings are moved to the binary they were in or to the ARM9 binary.
+        #[arg(long)]
+        free_space: Option<string_insert::FreeSpace>,
+        /// How hard to try to make compressed overlays small
+        #[arg(long, value_enum, default_value_t)]
+        level: CompressionLevel,
+        /// Report what would change in the ROM, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Remove the padding after the used area of a ROM, or pad it to a full-size image
+    Trim {
+        /// The ROM file to trim
+        rom_path: PathBuf,
+        /// Where to place the trimmed ROM
+        ///
+        /// If empty, the ROM given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Report what would change in the ROM, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+        /// Set the device capacity in the header to the smallest one able to hold the resulting ROM
+        #[arg(long, default_value_t = false)]
+        fix_capacity: bool,
+        /// Pad the ROM with 0xFF bytes to the next power of two after trimming it, as some flashcarts require
+        #[arg(long, default_value_t = false)]
+        pad_to_power_of_two: bool,
+    },
+    /// Encrypt or decrypt the secure area at the start of a retail ROM's ARM9 binary
+    SecureArea {
+        #[command(subcommand)]
+        command: SecureAreaCommands,
+    },
+    /// Replace a file inside an existing ROM
+    ///
+    /// If the file replaced is compressed, the new one is compressed the same way, unless it
+    /// already is compressed or `--raw` is given.
+    Insert {
+        /// The ROM file to patch
+        rom_path: PathBuf,
+        /// NitroFS path of the file to replace
+        nitro_path: String,
+        /// The file to insert in its place
+        file_path: PathBuf,
+        /// Where to place the patched ROM
+        ///
+        /// If empty, the ROM given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Report what would change in the ROM, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+
+        /// Compress the file using the LZ10 algorithm before inserting it
+        #[arg(long, default_value_t = false, conflicts_with = "raw")]
+        compress: bool,
+        /// Insert the file as it is, even if the file it replaces is compressed
+        #[arg(long, default_value_t = false)]
+        raw: bool,
+        /// How hard to try to make the compressed file small
+        #[arg(long, value_enum, default_value_t, conflicts_with = "raw")]
+        level: CompressionLevel,
+    },
+    /// Replace the files of a ROM with the ones of a directory mirroring its NitroFS layout, leaving the rest of the ROM untouched
+    ///
+    /// Files can be given as they are stored in the ROM, or as `unpack` converts them, such as
+    /// text files as `.txt` templates and compressed files as `.decomp` files; these are
+    /// converted back and compressed like the files they replace. No unpacked copy of the ROM
+    /// is needed.
+    Patchdir {
+        /// The ROM file to patch
+        rom_path: PathBuf,
+        /// The directory of files to replace the ROM's with
+        patch_dir: PathBuf,
+        /// Where to place the patched ROM
+        ///
+        /// If empty, the ROM given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Report what would change in the ROM, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+        /// How hard to try to make compressed files small
+        #[arg(long, value_enum, default_value_t)]
+        level: CompressionLevel,
+        #[command(flatten)]
+        encoding: EncodingArgs,
+    },
+    /// Compare two ROMs' sections, overlays and files, listing what was added, removed or changed
+    ///
+    /// For text files, the strings that changed are listed too.
+    Diff {
+        /// The original ROM
+        old_rom: PathBuf,
+        /// The modified ROM
+        new_rom: PathBuf,
+        #[command(flatten)]
+        encoding: EncodingArgs,
+        #[command(flatten)]
+        secure_area: SecureAreaArgs,
+    },
+    /// Compare the variants of a game, such as its releases in different regions, listing the files they share and the ones only some have
+    ///
+    /// Sections, overlays and files are matched by name. Files found in every variant are
+    /// reported as differing when their contents aren't all the same, and text files whose
+    /// string counts don't match across variants are listed with their counts.
+    CompareVariants {
+        /// The ROMs of each variant
+        #[arg(required = true, num_args = 2..)]
+        rom_paths: Vec<PathBuf>,
+        /// Also list the items identical in every variant
+        #[arg(long, default_value_t = false)]
+        all: bool,
+        #[command(flatten)]
+        encoding: EncodingArgs,
+    },
+    /// Unpack a ROM to a temporary directory, pack it back and check that the result is identical to the original
+    VerifyRoundtrip {
+        /// The ROM files to verify, or directories holding them
+        #[arg(required = true)]
+        rom_paths: Vec<PathBuf>,
+        #[command(flatten)]
+        secure_area: SecureAreaArgs,
+    },
+    /// Round-trip every ROM in a directory, reporting which games pack back identically
+    ///
+    /// Each ROM has its header read, is unpacked, packed back and compared byte for byte with the original. The report gives the stage each ROM failed at, if any, by game code, followed by the number of ROMs passing per maker.
+    Selftest {
+        /// The directory holding the ROMs to test
+        dir: PathBuf,
+        /// Print the report as JSON, with a `roms` list and a `makers` list
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// List the overlays of a ROM, or extract and replace them
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Overlays {
+        /// The ROM whose overlays to list
+        #[arg(required = true)]
+        rom_path: Option<PathBuf>,
+        #[command(subcommand)]
+        command: Option<OverlayCommands>,
+    },
+    /// List, extract or rebuild NARC archives
+    Narc {
+        #[command(subcommand)]
+        command: NarcCommands,
+    },
+    /// Convert graphics to PNG
+    Gfx {
+        #[command(subcommand)]
+        command: GfxCommands,
+    },
+    /// Export NFTR fonts to an editable glyph sheet and rebuild them
+    Font {
+        #[command(subcommand)]
+        command: FontCommands,
+    },
+    /// Inspect NSBMD 3D models and convert them to glTF
+    Model {
+        #[command(subcommand)]
+        command: ModelCommands,
+    },
+    /// Inspect save files, convert them between the raw, DeSmuME and Action Replay formats, or dump and patch regions of them
+    Save {
+        #[command(subcommand)]
+        command: SaveCommands,
+    },
+    /// List, extract or convert the sequences, banks, wave archives and streams of SDAT sound archives
+    Sdat {
+        #[command(subcommand)]
+        command: SdatCommands,
+    },
+    /// Convert text files from and to the text entry template format
+    Text {
+        #[command(subcommand)]
+        command: TextCommands,
+    },
+    /// Create BPS or VCDIFF (xdelta) patches between ROMs, or apply them and IPS patches
+    #[command(alias = "diffpatch")]
+    Patch {
+        #[command(subcommand)]
+        command: PatchCommands,
+    },
+    /// Create a project file (ravends.toml) describing the files of a base ROM replaced by source assets
+    Init {
+        /// The unmodified ROM the project applies to
+        base_rom: PathBuf,
+        /// The project directory, created if needed
+        #[arg(default_value = ".")]
+        project_dir: PathBuf,
+    },
+    /// Build a project's patched ROM, and its BPS patch if the project gives a path for it
+    Build {
+        /// The project directory, containing a ravends.toml file
+        #[arg(default_value = ".")]
+        project_dir: PathBuf,
+        /// Recompress every file instead of reusing the results of previous builds
+        #[arg(long, default_value_t = false)]
+        no_cache: bool,
+        /// How hard to try to make compressed files small
+        #[arg(long, value_enum, default_value_t)]
+        level: CompressionLevel,
+        /// Keep running, and rebuild every time a file in the project directory changes
+        #[arg(long, default_value_t = false, conflicts_with = "dry_run")]
+        watch: bool,
+        /// Report what would change in the patched ROM, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+        /// Build text assets without checking their strings as `text lint` does
+        #[arg(long, default_value_t = false)]
+        no_lint: bool,
+    },
+    /// Create a release of a modified ROM, ready to publish: BPS and xdelta patches turning the original ROM into it, a file with their SHA-256 hashes, and a README giving the hashes of the ROM they apply to
+    Rom2patch {
+        /// The unmodified ROM
+        original: PathBuf,
+        /// The modified ROM, or a project directory to build it from
+        modified: PathBuf,
+        /// The directory to place the release in, created if needed
+        ///
+        /// If empty, the software will place it alongside the modified ROM (or inside the project directory), named after it with a '-release' suffix.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Name of the patches and title of the README
+        ///
+        /// If empty, the name of the modified ROM (or of the project's output ROM) will be used.
+        #[arg(long)]
+        name: Option<String>,
+        /// How hard to try to make compressed files small, when building a project
+        #[arg(long, value_enum, default_value_t)]
+        level: CompressionLevel,
+        /// Build text assets without checking their strings as `text lint` does
+        #[arg(long, default_value_t = false)]
+        no_lint: bool,
     },
     /// Pack a directory's contents to a ROM file
     Pack {
@@ -93,125 +846,4208 @@ enum Commands {
         ///
         /// If empty, the software will place the ROM alongside the directory given, with a '.nds' extension at the end.
         rom_path: Option<PathBuf>,
+        /// Recompress every file instead of reusing the results of previous packs
+        #[arg(long, default_value_t = false)]
+        no_cache: bool,
+        /// How hard to try to make compressed files small
+        ///
+        /// `best` can make files smaller than the original game's, at the cost of a slower pack.
+        #[arg(long, value_enum, default_value_t)]
+        level: CompressionLevel,
+        /// Keep running, and pack the ROM again every time a file in the directory changes
+        #[arg(long, default_value_t = false, conflicts_with = "dry_run")]
+        watch: bool,
+        /// Store files with identical contents only once, pointing all of their FAT entries to the same data
+        #[arg(long, default_value_t = false)]
+        dedup: bool,
+
+        /// Alignment of the file data in the ROM, such as 0x200
+        ///
+        /// If empty, the alignment of the original ROM will be used.
+        #[arg(long, value_parser = parse_alignment)]
+        align: Option<usize>,
+        /// Byte filling the space between file data, such as 0xFF
+        ///
+        /// If empty, the padding byte of the original ROM will be used.
+        #[arg(long, value_parser = parse_byte)]
+        pad_byte: Option<u8>,
+        /// ARM7 BIOS dump (or file holding only its 0x1048-byte key table) used to encrypt the secure area again, if it was decrypted when unpacking
+        #[arg(long)]
+        bios: Option<PathBuf>,
+        /// Decrypt or encrypt the secure area of the ROM written, instead of giving it the state recorded when unpacking
+        ///
+        /// 'decrypted' writes the ROM as decrypted dumps have it, which needs no BIOS unless the secure area was unpacked encrypted.
+        #[arg(long, value_enum)]
+        secure_area: Option<SecureAreaForm>,
+        /// List the files that changed since unpacking, according to the `hashes.json` written then
+        #[arg(long, default_value_t = false)]
+        verify: bool,
+        /// Report what would change in the ROM at the destination, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+        /// Print how many bytes the compressed and uncompressed files of the ROM take, and how large the compressed ones are once decompressed
+        #[arg(long, default_value_t = false)]
+        stats: bool,
+        /// Pack the ROM a second time without the compression cache, failing if both ROMs aren't byte-identical
+        ///
+        /// Packing is meant to be reproducible, so that anyone packing the same directory gets
+        /// the same ROM. This checks it, such as in CI, before anything is written.
+        #[arg(long, default_value_t = false, conflicts_with = "watch")]
+        check_deterministic: bool,
+        /// Rhai script converting back the files it converted when unpacking
+        ///
+        /// Can be given multiple times. Every plugin used when unpacking must be given again.
+        #[arg(long = "plugin")]
+        plugins: Vec<PathBuf>,
+        /// TOML file whose `[[hook]]` tables declare the external commands converting back the files they converted when unpacking
+        ///
+        /// Every hook used when unpacking must be given again. Their output is cached unless `--no-cache` is given.
+        #[arg(long)]
+        hooks: Option<PathBuf>,
+        #[command(flatten)]
+        profile: ProfileArgs,
+    },
+    /// Compare the files of an unpacked ROM against the `hashes.json` written when unpacking it, listing the files modified, added or missing since
+    VerifyHashes {
+        /// The directory the ROM was unpacked to
+        fs_path: PathBuf,
     },
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Cli::parse();
+#[derive(Debug, Subcommand)]
+enum CheatCommands {
+    /// Write the data of cheats into the ARM9 binary and overlays of a ROM
+    ///
+    /// Only cheats made of unconditional writes (code types 0, 1, 2 and E) to static ARM9 code
+    /// or to an uncompressed overlay can be baked. Other cheats are skipped.
+    Apply {
+        /// The ROM file to bake the cheats into
+        rom_path: PathBuf,
+        /// Action Replay DS code list in plain text, with each cheat's name before its codes
+        codes_path: PathBuf,
+        /// Symbol files naming addresses codes can refer to, as in `0{gPlayerMoney} 0001869F`
+        #[arg(short, long = "symbols")]
+        symbol_paths: Vec<PathBuf>,
+        /// Where to place the resulting ROM
+        ///
+        /// If empty, the ROM given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Report what would change in the ROM, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+}
 
-    match args.command {
-        Commands::Decompress { path, target_path } => {
-            let target_path = target_path.unwrap_or_else(|| path.join(".decomp"));
+#[derive(Debug, Subcommand)]
+enum FsCommands {
+    /// Add a new file to a ROM
+    Add {
+        /// The ROM file to add the file to
+        rom_path: PathBuf,
+        /// Path of the new file in the NitroFS
+        nitro_path: String,
+        /// The file to add
+        file_path: PathBuf,
+        /// Where to place the resulting ROM
+        ///
+        /// If empty, the ROM given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Report what would change in the ROM, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Remove files or directories from a ROM
+    Rm {
+        /// The ROM file to remove files from
+        rom_path: PathBuf,
+        /// Paths of the files or directories to remove, in the NitroFS
+        #[arg(required = true)]
+        nitro_paths: Vec<String>,
+        /// Remove directories along with everything inside them
+        #[arg(short, long)]
+        recursive: bool,
+        /// Where to place the resulting ROM
+        ///
+        /// If empty, the ROM given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Report what would change in the ROM, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Move or rename a file or directory of a ROM
+    ///
+    /// If the destination is an existing directory, the file or directory is moved inside it.
+    Mv {
+        /// The ROM file to move files in
+        rom_path: PathBuf,
+        /// Path of the file or directory to move, in the NitroFS
+        from: String,
+        /// Where to move it to, in the NitroFS
+        to: String,
+        /// Where to place the resulting ROM
+        ///
+        /// If empty, the ROM given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Report what would change in the ROM, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+}
 
-            let reader =
-                std::io::BufReader::new(fs::File::open(path).context("failed to open file given")?);
+#[derive(Debug, Subcommand)]
+enum HeaderCommands {
+    /// Rewrite the chosen header fields and fix the header checksum
+    ///
+    /// Romhacks often need a game code of their own so that their saves and cheat codes don't
+    /// collide with the original game's.
+    #[command(group(clap::ArgGroup::new("fields").required(true).multiple(true)))]
+    Edit {
+        /// The ROM file to edit
+        rom_path: PathBuf,
+        /// Internal title, of up to 12 ASCII characters
+        #[arg(long, group = "fields")]
+        title: Option<String>,
+        /// 4-character game code, whose last letter tells the region
+        #[arg(long, group = "fields")]
+        game_code: Option<String>,
+        /// 2-character maker code, such as `01` for Nintendo
+        #[arg(long, group = "fields")]
+        maker_code: Option<String>,
+        /// ROM version, usually increased by every revision of a game
+        #[arg(long, group = "fields")]
+        rom_version: Option<u8>,
+        /// Where to place the resulting ROM
+        ///
+        /// If empty, the ROM given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Report what would change in the ROM, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+}
 
-            let data = decompress_lz10(reader).context("failed to decompress file")?;
+#[derive(Debug, Subcommand)]
+enum IconCommands {
+    /// Export the icon of a ROM to a directory
+    ///
+    /// The icon is written as an indexed PNG, `icon.png`. DSi-enhanced ROMs with an animated icon
+    /// also get its 8 bitmaps as indexed PNGs, its palettes and animation sequence as
+    /// `icon.json`, and a preview of the animation as `icon.apng`.
+    Export {
+        /// The ROM whose icon to export
+        rom_path: PathBuf,
+        /// Directory to write the icon files to
+        output_dir: PathBuf,
+    },
+    /// Import an icon exported with `icon export`, after editing it
+    ///
+    /// The files missing from the directory are left as they are in the ROM; an animated icon is
+    /// only imported if `icon.json` is present, and then needs all of its bitmaps.
+    Import {
+        /// The ROM whose icon to replace
+        rom_path: PathBuf,
+        /// Directory holding the icon files
+        input_dir: PathBuf,
+        /// Where to place the resulting ROM
+        ///
+        /// If empty, the ROM given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Report what would change in the ROM, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+}
 
-            fs::File::create(target_path)?.write_all(&data)?;
-        }
-        Commands::Identify { path } => {
-            let mut data = Vec::new();
-            fs::File::open(path)
-                .context("could not open file to idenfify")?
-                .read_to_end(&mut data)
-                .context("could not read file to idenfify")?;
+#[derive(Debug, Subcommand)]
+enum SecureAreaCommands {
+    /// KEY1-encrypt the secure area of a ROM, as retail cartridges have it
+    Encrypt {
+        /// The ROM file to encrypt
+        rom_path: PathBuf,
+        /// ARM7 BIOS dump, or file holding only its 0x1048-byte key table
+        #[arg(long)]
+        bios: PathBuf,
+        /// Where to place the resulting ROM
+        ///
+        /// If empty, the ROM given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Report what would change in the ROM, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Decrypt the secure area of a ROM, as decrypted dumps have it
+    Decrypt {
+        /// The ROM file to decrypt
+        rom_path: PathBuf,
+        /// ARM7 BIOS dump, or file holding only its 0x1048-byte key table
+        #[arg(long)]
+        bios: PathBuf,
+        /// Where to place the resulting ROM
+        ///
+        /// If empty, the ROM given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Report what would change in the ROM, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+}
 
-            if let Ok(decompressed_data) = decompress_lz10(data.as_slice()) {
-                print!("compressed LZ10 file, ");
-                match parse_text_file(&decompressed_data) {
-                    Ok(_) => {
-                        println!("text file");
-                    }
-                    Err(_) => {
-                        println!("unknown contents");
-                    }
-                };
-            } else {
-                println!("unknown format");
-            };
-        }
+#[derive(Debug, Subcommand)]
+enum NarcCommands {
+    /// List the files inside a NARC archive
+    List {
+        /// The NARC file to list, optionally LZ10- or LZ11-compressed
+        path: PathBuf,
+    },
+    /// Extract the files inside a NARC archive to a directory
+    Extract {
+        /// The NARC file to extract, optionally LZ10- or LZ11-compressed
+        path: PathBuf,
+        /// Where to extract the files to
+        ///
+        /// If empty, the software will create a folder of the same name as the archive in its same path, and extract it there.
+        target_path: Option<PathBuf>,
+        /// Extract archives found inside the archive to a directory in their place, and so on
+        #[arg(long, default_value_t = false)]
+        recursive: bool,
+    },
+    /// Build a NARC archive from a directory's contents
+    Pack {
+        /// The directory to pack into an archive
+        ///
+        /// If it was created by `narc extract`, the original file order will be kept. Otherwise, every file in it will be added with its path as its name.
+        dir_path: PathBuf,
+        /// Where to place the resulting archive
+        ///
+        /// If empty, the software will place the archive alongside the directory given, with a '.narc' extension at the end.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Build a NARC archive from any directory, choosing how its files are stored
+    ///
+    /// Unlike `narc pack`, any NARC manifest in the directory is ignored: every file in it is added, in path order.
+    Create {
+        /// The directory to build an archive from
+        dir_path: PathBuf,
+        /// Where to place the resulting archive
+        ///
+        /// If empty, the software will place the archive alongside the directory given, with a '.narc' extension at the end.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Leave out the file name table, so that files are only known by their position
+        ///
+        /// If every file name starts with a number, as in '0000.bin', files are placed in the order of their numbers.
+        #[arg(long, default_value_t = false)]
+        nameless: bool,
+        /// Alignment of the data of each file in the archive, in bytes
+        #[arg(long, default_value_t = narc::FILE_ALIGNMENT)]
+        align: usize,
+        /// Compress each file in the archive
+        #[arg(long, value_enum, default_value_t = manifest::Compression::None)]
+        compress: manifest::Compression,
+        /// Compress the archive as a whole
+        #[arg(long, value_enum, default_value_t = manifest::Compression::None)]
+        compress_archive: manifest::Compression,
+        /// How hard to try to make compressed data small
+        #[arg(long, value_enum, default_value_t)]
+        level: CompressionLevel,
+    },
+}
 
-        Commands::Unpack {
-            rom_path,
-            target_path,
+#[derive(Debug, Subcommand)]
+enum OverlayCommands {
+    /// Extract an overlay, decompressing it if it's compressed
+    Extract {
+        /// The ROM to extract the overlay from
+        rom_path: PathBuf,
+        /// ID of the overlay to extract
+        overlay_id: u32,
+        /// Where to place the overlay
+        ///
+        /// If empty, the overlay will be written to `overlay_XXXX.bin` in the current directory.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Look for the overlay in the ARM7 overlay table instead of the ARM9 one
+        #[arg(long, default_value_t = false)]
+        arm7: bool,
+        /// Extract the overlay's file as stored in the ROM, without decompressing it
+        #[arg(long, default_value_t = false)]
+        raw: bool,
+    },
+    /// Replace an overlay, compressing it if the original was compressed and updating its sizes in the overlay table
+    Replace {
+        /// The ROM to patch
+        rom_path: PathBuf,
+        /// ID of the overlay to replace
+        overlay_id: u32,
+        /// The decompressed overlay to insert in its place
+        file_path: PathBuf,
+        /// Where to place the patched ROM
+        ///
+        /// If empty, the ROM given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Report what would change in the ROM, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+        /// Look for the overlay in the ARM7 overlay table instead of the ARM9 one
+        #[arg(long, default_value_t = false)]
+        arm7: bool,
+        /// How hard to try to make the compressed overlay small
+        #[arg(long, value_enum, default_value_t)]
+        level: CompressionLevel,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum FontCommands {
+    /// Draw the glyphs of an NFTR font to a PNG sheet, along with a JSON file describing their widths and characters
+    Export {
+        /// The NFTR file, optionally LZ10-compressed
+        path: PathBuf,
+        /// Where to place the resulting PNG; the JSON file will be placed alongside it, with a '.json' extension
+        ///
+        /// If empty, the software will place the PNG alongside the font file, with a '.png' extension at the end.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Rebuild an NFTR font from a JSON description and a PNG sheet, as exported by `font export`
+    ///
+    /// Glyphs can be added by adding entries to the JSON file and filling in the cells that follow in the sheet, which must grow to fit them.
+    Import {
+        /// The JSON file describing the font
+        description: PathBuf,
+        /// The PNG sheet with the glyphs
+        ///
+        /// If empty, the PNG alongside the JSON file with the same name will be used.
+        #[arg(short, long)]
+        sheet: Option<PathBuf>,
+        /// Where to place the resulting NFTR file
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ModelCommands {
+    /// List the models of an NSBMD file, with their bone hierarchy, materials and meshes
+    Info {
+        /// The NSBMD file, optionally LZ10-compressed
+        path: PathBuf,
+    },
+    /// Convert the models of an NSBMD file in their rest pose to glTF, to preview them in 3D software such as Blender
+    ///
+    /// The glTF file refers to a binary buffer and to the PNGs of the textures, which are written alongside it.
+    Export {
+        /// The NSBMD file, optionally LZ10-compressed
+        path: PathBuf,
+        /// An NSBTX file with the textures of the models, optionally LZ10-compressed
+        ///
+        /// If not given, the textures embedded in the NSBMD file will be used, if any.
+        #[arg(short, long)]
+        textures: Option<PathBuf>,
+        /// Where to place the resulting glTF file
+        ///
+        /// If empty, the software will place it alongside the NSBMD file, with a '.gltf' extension at the end.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum SdatCommands {
+    /// List the files of an SDAT file, with their names, file IDs and sizes
+    List {
+        /// The SDAT file
+        path: PathBuf,
+    },
+    /// Extract the files of an SDAT file to a directory, in a subdirectory for each kind of file
+    Extract {
+        /// The SDAT file
+        path: PathBuf,
+        /// The directory to extract the files to
+        ///
+        /// If empty, the software will extract them to a directory alongside the SDAT file, with the same name as it minus the extension.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Build an SDAT file out of a directory of sound files, as extracted by `sdat extract`
+    ///
+    /// The records of the archive, such as the bank and volume of each sequence, are read from the `ravends-sdat.json` manifest in the directory, which points to the SSEQ, SSAR, SBNK, SWAR and STRM files to store.
+    Build {
+        /// The directory of sound files
+        dir: PathBuf,
+        /// Where to place the resulting SDAT file
+        ///
+        /// If empty, the software will place it alongside the directory, with a '.sdat' extension at the end.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Convert an SWAV wave, the waves of an SWAR wave archive or an STRM stream to WAV files
+    ///
+    /// Waves that loop get a JSON file alongside their WAV file, with the same name, describing their loop points.
+    Wav {
+        /// The SWAV, SWAR or STRM file
+        path: PathBuf,
+        /// Where to place the resulting WAV file, or the directory of WAV files for an SWAR file
+        ///
+        /// If empty, the software will place it alongside the file given, with a '.wav' extension at the end, or in a directory with the same name as the SWAR file minus the extension.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Convert a WAV file to an SWAV wave or an STRM stream, so it can be stored in an SDAT file
+    ///
+    /// Loop points are read from the JSON file alongside the WAV file written by `sdat wav`, if there is one, or else from the sampler chunk of the WAV file. The wave must play at a rate the DS supports, or be resampled to one with --rate.
+    Encode {
+        /// The WAV file
+        path: PathBuf,
+        /// The kind of file to encode the wave as
+        #[arg(short, long, value_enum, default_value_t)]
+        format: wave::WaveFile,
+        /// How to store the samples
+        #[arg(short, long, value_enum, default_value_t = wave::WaveEncoding::ImaAdpcm)]
+        encoding: wave::WaveEncoding,
+        /// Sample rate to resample the wave to, in Hz
+        #[arg(short, long)]
+        rate: Option<u32>,
+        /// Sample the loop starts at, overriding the loop points of the WAV file
+        #[arg(long, requires = "loop_end", conflicts_with = "no_loop")]
+        loop_start: Option<usize>,
+        /// Sample the loop ends at, exclusive
+        #[arg(long, requires = "loop_start")]
+        loop_end: Option<usize>,
+        /// Play the wave once, ignoring the loop points of the WAV file
+        #[arg(long, default_value_t = false)]
+        no_loop: bool,
+        /// Where to place the resulting file
+        ///
+        /// If empty, the software will place it alongside the WAV file, with a '.swav' or '.strm' extension at the end.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Convert the SBNK instrument banks of an SDAT file to SoundFonts (SF2), with the samples of their SWAR wave archives
+    ///
+    /// Instruments keep their program numbers, so that MIDI files converted from the sequences using a bank play with its SoundFont. Envelopes are converted approximately.
+    Sf2 {
+        /// The SDAT file
+        path: PathBuf,
+        /// Name or index of the bank to convert
+        ///
+        /// If empty, every bank will be converted.
+        #[arg(short, long)]
+        bank: Option<String>,
+        /// Where to place the resulting SoundFont, or the directory of SoundFonts if converting every bank
+        ///
+        /// If empty, the software will place it alongside the SDAT file, named after the bank with a '.sf2' extension, or in a directory with the same name as the SDAT file minus the extension.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Convert an SSEQ sequence to a MIDI file
+    ///
+    /// Each track of the sequence plays on the MIDI channel of its number. Loops are played once, between 'loopStart' and 'loopEnd' markers.
+    Midi {
+        /// The SSEQ file
+        path: PathBuf,
+        /// Where to place the resulting MIDI file
+        ///
+        /// If empty, the software will place it alongside the SSEQ file, with a '.mid' extension at the end.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum GfxCommands {
+    /// Draw NCGR graphics with an NCLR palette, optionally arranged by an NSCR screen, into a PNG
+    Export {
+        /// The NCGR graphics file, optionally LZ10-compressed
+        graphics: PathBuf,
+        /// The NCLR palette file, optionally LZ10-compressed
+        palette: PathBuf,
+        /// The NSCR screen file arranging the tiles, optionally LZ10-compressed
+        ///
+        /// If not given, every tile will be drawn in order.
+        #[arg(short, long)]
+        screen: Option<PathBuf>,
+        /// Which 16-color palette of the palette file to use for 4bpp graphics without a screen
+        #[arg(long, default_value_t = 0)]
+        palette_index: usize,
+        /// Where to place the resulting PNG
+        ///
+        /// If empty, the software will place the PNG alongside the graphics file, with a '.png' extension at the end.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Assemble the sprites of an NCER cell bank into one PNG per cell, optionally describing the animations of an NANR file
+    Cells {
+        /// The NCGR graphics file, optionally LZ10-compressed
+        graphics: PathBuf,
+        /// The NCLR palette file, optionally LZ10-compressed
+        palette: PathBuf,
+        /// The NCER cell bank file, optionally LZ10-compressed
+        cells: PathBuf,
+        /// The NANR animation file animating the cells, optionally LZ10-compressed
+        ///
+        /// If given, its animations will be written to an `animations.json` file.
+        #[arg(short, long)]
+        animations: Option<PathBuf>,
+        /// The directory where to place the resulting PNGs
+        ///
+        /// If empty, the software will place them in a directory alongside the cell bank file, with the same name but without extension.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// List the textures of an NSBTX texture archive or NSBMD model and export each of them to a PNG named after it
+    Textures {
+        /// The NSBTX or NSBMD file, optionally LZ10-compressed
+        path: PathBuf,
+        /// The directory where to place the resulting PNGs
+        ///
+        /// If empty, the software will place them in a directory alongside the texture file, with the same name but without extension.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Export an NSCR screen as a Tiled map (TMX), along with a tileset (TSX) of its NCGR tiles drawn with each palette
+    Tilemap {
+        /// The NCGR graphics file, optionally LZ10-compressed
+        graphics: PathBuf,
+        /// The NCLR palette file, optionally LZ10-compressed
+        palette: PathBuf,
+        /// The NSCR screen file, optionally LZ10-compressed
+        screen: PathBuf,
+        /// Where to place the map, without extension
+        ///
+        /// If empty, the software will place it alongside the screen file. The tileset and its image are placed next to the map, with '.tsx' and '_tileset.png' endings.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Replace the entries of an NSCR screen with the tile layer of a Tiled map exported by `gfx tilemap`
+    ///
+    /// The tile layer must be saved in the CSV format, which is Tiled's default. Tiles can be flipped but not rotated.
+    ImportTilemap {
+        /// The edited Tiled map
+        map: PathBuf,
+        /// The NSCR screen file to replace the entries of, optionally LZ10-compressed
+        screen: PathBuf,
+        /// Where to place the resulting NSCR file, compressed like the original
+        ///
+        /// If empty, the NSCR file given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Export the colors of an NCLR palette as Adobe ACT, JASC PAL or GIMP GPL palette files
+    Palette {
+        /// The NCLR palette file, optionally LZ10-compressed
+        path: PathBuf,
+        /// Format of the palette file to write
+        ///
+        /// If empty, the palette will be written in every format.
+        #[arg(long, value_enum)]
+        format: Option<palette::PaletteFormat>,
+        /// Where to place the palette file, without extension
+        ///
+        /// If empty, the software will place it alongside the NCLR file, with the extension of each format instead.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Replace the colors of an NCLR palette with those of an ACT, PAL or GPL palette file
+    ImportPalette {
+        /// The palette file holding the new colors
+        palette_file: PathBuf,
+        /// The NCLR palette file to replace the colors of, optionally LZ10-compressed
+        path: PathBuf,
+        /// Format of the palette file
+        ///
+        /// If empty, it will be guessed from the palette file's extension.
+        #[arg(long, value_enum)]
+        format: Option<palette::PaletteFormat>,
+        /// Where to place the resulting NCLR file, compressed like the original
+        ///
+        /// If empty, the NCLR file given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum SaveCommands {
+    /// Show the format of a save file, the save chip it comes from and how much of it is used
+    Info {
+        /// The save file
+        path: PathBuf,
+    },
+    /// Convert a save file to another format, padding it to the size of its save chip
+    Convert {
+        /// The save file, in any format
+        path: PathBuf,
+        /// Where to place the converted save
+        ///
+        /// If empty, the software will place it alongside the save given, with the extension of the format chosen.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Format to convert the save to
+        ///
+        /// If empty, it will be guessed from the output's extension.
+        #[arg(long, value_enum)]
+        format: Option<SaveFormat>,
+    },
+    /// Write a region of the contents of a save to a file
+    Dump {
+        /// The save file, in any format
+        path: PathBuf,
+        /// Offset of the region in the save
+        #[arg(long, value_parser = parse_number, default_value_t = 0)]
+        offset: u64,
+        /// Size of the region
+        ///
+        /// If empty, the region will span up to the end of the save.
+        #[arg(long, value_parser = parse_number)]
+        size: Option<u64>,
+        /// Where to place the region
+        output: PathBuf,
+    },
+    /// Overwrite a region of the contents of a save with a file, keeping the save's format
+    Patch {
+        /// The save file, in any format
+        path: PathBuf,
+        /// Offset of the region in the save
+        #[arg(long, value_parser = parse_number)]
+        offset: u64,
+        /// The file to write at the offset
+        file_path: PathBuf,
+        /// Where to place the patched save
+        ///
+        /// If empty, the save given will be overwritten.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Reads a save file, detecting its format.
+fn read_save(path: &Path) -> anyhow::Result<(save::Save, SaveFormat)> {
+    let data = fs::read(path).with_context(|| format!("failed to read {path:?}"))?;
+    let format = SaveFormat::detect(&data);
+    let save = save::Save::parse(&data, format).context("failed to parse save file")?;
+    Ok((save, format))
+}
+
+#[derive(Debug, Subcommand)]
+enum PatchCommands {
+    /// Create a patch turning the original ROM into the modified one
+    Create {
+        /// The unmodified ROM
+        original: PathBuf,
+        /// The modified ROM
+        modified: PathBuf,
+        /// Where to place the patch
+        ///
+        /// If empty, the software will place it alongside the modified ROM, with the extension of the format chosen.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Format of the patch
+        ///
+        /// If empty, it will be guessed from the output's extension, defaulting to BPS.
+        #[arg(long, value_enum)]
+        format: Option<PatchFormat>,
+    },
+    /// Apply a BPS, VCDIFF or IPS patch to a ROM, detecting its format automatically
+    ///
+    /// VCDIFF patches created by xdelta3 must not use secondary compression (`xdelta3 -S none`). IPS patches can only modify the first 16 MiB of a ROM.
+    Apply {
+        /// The patch to apply
+        patch: PathBuf,
+        /// The ROM the patch was created from
+        rom: PathBuf,
+        /// Where to place the patched ROM
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Report what would change in the ROM, such as files relocated and its final size, without writing anything
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum TextCommands {
+    /// Export the strings of a binary text file, optionally LZ10-compressed, to an editable format
+    ///
+    /// BMG message files are supported too, their escape sequences being exported as `{bmg:XX XX ...}`.
+    Export {
+        /// The text file to export
+        path: PathBuf,
+        /// Where to place the exported strings
+        ///
+        /// If empty, the software will place them alongside the text file, with the extension of the format chosen.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Format to export the strings to
+        #[arg(long, value_enum, default_value_t = TextFormat::Template)]
+        format: TextFormat,
+        /// Template to export the strings with instead of a format: a template file, or the name of a bundled one (plain, markdown or atlas)
+        ///
+        /// The part of the template between `{{#entry}}` and `{{/entry}}` is repeated for each string, with the placeholders `{{index}}`, `{{text}}`, `{{original}}` and `{{length}}` (the size of the encoded string in bytes); `{{file}}` is the name of the text file. Placeholders can be given a filter: `{{text|oneline}}` writes line breaks as `\n`, `{{text|markdown}}` escapes the text for a Markdown table cell, and `{{text|escaped}}` escapes it as the text entry template format does. Files exported with a template other than the text entry template format can't be packed back.
+        #[arg(long, conflicts_with = "format")]
+        template: Option<PathBuf>,
+        /// The text file the strings are a translation of, optionally LZ10-compressed
+        ///
+        /// Its strings are exported as the original ones of each entry. If empty, the strings
+        /// exported are taken as the original ones.
+        #[arg(long)]
+        original: Option<PathBuf>,
+        /// Format of the table locating the strings of the binary text file
+        ///
+        /// If empty, it will be detected, trying a count followed by 32-bit pointers first.
+        #[arg(long, value_enum)]
+        table_format: Option<TableFormat>,
+        #[command(flatten)]
+        encoding: EncodingArgs,
+    },
+    /// Rebuild a binary text file from a file in one of the formats supported by `text export`
+    #[command(alias = "import")]
+    Pack {
+        /// The file to read the strings from
+        path: PathBuf,
+        /// Where to place the resulting text file
+        ///
+        /// If empty, the software will place it alongside the file given, with a '.bin' extension.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Format of the file given
+        ///
+        /// If empty, it will be guessed from the file's extension, defaulting to the text entry template format.
+        #[arg(long, value_enum)]
+        format: Option<TextFormat>,
+        #[command(flatten)]
+        encoding: EncodingArgs,
+        /// How to lay out the strings in the resulting file
+        #[arg(long, value_enum, default_value_t = TextLayout::Deduplicated)]
+        layout: TextLayout,
+        /// The text file the strings were exported from, optionally LZ10-compressed
+        ///
+        /// Required to preserve its layout with `--layout preserve`, and to rebuild a BMG message file, which keeps the message attributes and other sections of the original.
+        #[arg(long, required_if_eq("layout", "preserve"))]
+        original: Option<PathBuf>,
+        /// Format of the table locating the strings of the resulting file
+        ///
+        /// If empty, the format of the original file will be kept, or a count followed by
+        /// 32-bit pointers will be used if there is none.
+        #[arg(long, value_enum)]
+        table_format: Option<TableFormat>,
+
+        /// Compress the resulting file using the LZ10 algorithm
+        #[arg(long, default_value_t = false)]
+        compress: bool,
+    },
+    /// Export the strings of every text file of an unpacked ROM to a single file, keyed `file#index`
+    ///
+    /// Each key is the NitroFS path of a text file and the index of the string in it, such as
+    /// `data/msg/en.bin#3`. The original strings are those of the ROM, read from the copies of
+    /// the text files `unpack` keeps.
+    ExportAll {
+        /// The unpacked ROM to export the text files of
+        unpack_dir: PathBuf,
+        /// Where to place the exported strings
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Format to export the strings to
+        ///
+        /// If empty, it will be guessed from the output's extension, defaulting to PO.
+        #[arg(long, value_enum)]
+        format: Option<ScriptFormat>,
+        #[command(flatten)]
+        encoding: EncodingArgs,
+    },
+    /// Import the strings of a file made by `text export-all` back into the text files of the unpacked ROM, for `pack` to rebuild them
+    ///
+    /// Strings missing from the file keep their text, and text files with no string changed are left untouched.
+    ImportAll {
+        /// The unpacked ROM the strings were exported from
+        unpack_dir: PathBuf,
+        /// The file to read the strings from
+        path: PathBuf,
+        /// Format of the file given
+        ///
+        /// If empty, it will be guessed from the file's extension, defaulting to PO.
+        #[arg(long, value_enum)]
+        format: Option<ScriptFormat>,
+        #[command(flatten)]
+        encoding: EncodingArgs,
+    },
+    /// Check that every line of a text file fits in a given width when drawn with a font, failing if any doesn't
+    ///
+    /// Lines are measured by adding up the advance of each of their characters. Control codes are not measured.
+    Check {
+        /// The file with the strings: a binary text file, optionally LZ10-compressed, or a file in one of the formats supported by `text export`
+        path: PathBuf,
+        /// Format of the file given, if not a binary text file
+        ///
+        /// If empty, it will be guessed from the file's extension, reading files with unknown extensions as binary text files.
+        #[arg(long, value_enum)]
+        format: Option<TextFormat>,
+        /// The NFTR font the strings are drawn with, optionally LZ10-compressed
+        #[arg(long)]
+        font: PathBuf,
+        /// Maximum width of a line, in pixels
+        #[arg(long)]
+        max_width: usize,
+        /// Format of the table locating the strings of binary text files
+        ///
+        /// If empty, it will be detected, trying a count followed by 32-bit pointers first.
+        #[arg(long, value_enum)]
+        table_format: Option<TableFormat>,
+        #[command(flatten)]
+        encoding: EncodingArgs,
+    },
+    /// Check strings for problems that would garble them or crash the game, failing if any is found
+    ///
+    /// Control codes are checked for being terminated and valid, and for matching the ones of the original strings, which are taken from `--original` or else from the file itself. Lines are checked against the budgets given, and characters against the font.
+    Lint {
+        /// The file with the strings: a binary text file, optionally LZ10-compressed, or a file in one of the formats supported by `text export`
+        path: PathBuf,
+        /// Format of the file given, if not a binary text file
+        ///
+        /// If empty, it will be guessed from the file's extension, reading files with unknown extensions as binary text files.
+        #[arg(long, value_enum)]
+        format: Option<TextFormat>,
+        /// The text file the strings are a translation of, optionally LZ10-compressed
+        #[arg(long)]
+        original: Option<PathBuf>,
+        /// The NFTR font the strings are drawn with, optionally LZ10-compressed
+        #[arg(long)]
+        font: Option<PathBuf>,
+        /// Maximum width of a line, in pixels
+        #[arg(long, requires = "font")]
+        max_width: Option<usize>,
+        /// Maximum size of a line once encoded, in bytes
+        #[arg(long)]
+        max_line_bytes: Option<usize>,
+        #[command(flatten)]
+        encoding: EncodingArgs,
+    },
+    /// Show how many strings have been translated, are fuzzy or are untranslated, per file and
+    /// in total
+    ///
+    /// Strings count as translated once they differ from their original string. The original
+    /// strings of unpacked ROMs are read from the copies of the text files `unpack` keeps,
+    /// and those of exported files from the original strings they give. Fuzzy strings are
+    /// those flagged as such in PO files, and those of unpacked ROMs translated from an original
+    /// string other than the one of the ROM.
+    Status {
+        /// Unpacked ROMs, or files in one of the formats supported by `text export`
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Print the counts as JSON, with a `files` list and a `total`
+        #[arg(long, default_value_t = false)]
+        json: bool,
+        #[command(flatten)]
+        encoding: EncodingArgs,
+    },
+}
+
+/// Options describing how the strings of text files are encoded.
+#[derive(Debug, Args)]
+struct EncodingArgs {
+    /// Character encoding of the strings
+    ///
+    /// If empty, UTF-16LE will be used, unless a table is given with `--table`.
+    #[arg(long, value_enum)]
+    encoding: Option<EncodingKind>,
+    /// Thingy-style character table (.tbl) describing the encoding of the strings
+    #[arg(long)]
+    table: Option<PathBuf>,
+    /// File giving names and parameter counts to the control codes in UTF-16LE strings
+    #[arg(long, conflicts_with = "table")]
+    control_codes: Option<PathBuf>,
+}
+
+impl EncodingArgs {
+    fn load(self) -> anyhow::Result<TextEncoding> {
+        TextEncoding::load(
+            self.encoding,
+            self.table.as_deref(),
+            self.control_codes.as_deref(),
+        )
+    }
+}
+
+/// Options bringing the secure area of ROMs to the same state before they're compared.
+#[derive(Debug, Args)]
+struct SecureAreaArgs {
+    /// Decrypt or encrypt the secure area of the ROMs first, so that dumps differing only in its state compare equal
+    ///
+    /// Decrypted secure areas also have their `encryObj` ID replaced with the destroyed one (0xE7FFDEFF), which needs no BIOS. Decrypting or encrypting needs `--bios`.
+    #[arg(long, value_enum)]
+    secure_area: Option<SecureAreaForm>,
+    /// ARM7 BIOS dump (or file holding only its 0x1048-byte key table) used to decrypt or encrypt the secure area
+    #[arg(long, requires = "secure_area")]
+    bios: Option<PathBuf>,
+}
+
+impl SecureAreaArgs {
+    /// Reads the key table, if a BIOS was given.
+    fn key_table(&self) -> anyhow::Result<Option<secure_area::KeyTable>> {
+        self.bios.as_deref().map(read_key_table).transpose()
+    }
+
+    /// Brings the secure area of the ROM read from `rom_path` to the state asked for, if any.
+    fn normalize(
+        &self,
+        rom_data: &mut [u8],
+        rom_path: &Path,
+        key_table: Option<&secure_area::KeyTable>,
+    ) -> anyhow::Result<()> {
+        let Some(form) = self.secure_area else {
+            return Ok(());
+        };
+        if secure_area::normalize(rom_data, form, key_table)
+            .with_context(|| format!("failed to normalize the secure area of {rom_path:?}"))?
+        {
+            info!("{}: secure area made {}", rom_path.display(), form.name());
+        }
+        Ok(())
+    }
+}
+
+/// Options choosing the game profile applied.
+#[derive(Debug, Args)]
+struct ProfileArgs {
+    /// Directory of game profiles (TOML files keyed by game code) checked before the built-in ones
+    #[arg(long)]
+    profiles: Option<PathBuf>,
+    /// Don't apply the profile of the game, if there is one
+    #[arg(long, default_value_t = false, conflicts_with = "profiles")]
+    no_profile: bool,
+}
+
+impl ProfileArgs {
+    /// Finds the profile of the game with the code given, unless profiles were disabled.
+    fn find(&self, game_code: &str) -> anyhow::Result<Option<Profile>> {
+        if self.no_profile {
+            return Ok(None);
+        }
+        let found = profile::find(game_code, self.profiles.as_deref())?;
+        if let Some(profile) = &found {
+            info!("applying the profile of {}", profile.name);
+        }
+        Ok(found)
+    }
+}
+
+/// Opens the compression cache of the directory given, unless caching was disabled.
+fn open_cache(dir: &Path, no_cache: bool, level: CompressionLevel) -> CompressionCache {
+    if no_cache {
+        CompressionCache::disabled()
+    } else {
+        CompressionCache::new(dir)
+    }
+    .with_level(level)
+}
+
+/// Runs `process` on each ROM given, directories standing for the ROMs directly inside them if
+/// `expand_dirs` is set.
+///
+/// With several ROMs, the output of each is preceded by its path, a failure doesn't stop the
+/// others from being processed, and a summary follows with the line `process` returned for
+/// each ROM, or its error. The summary is left out for a single ROM.
+fn for_each_rom(
+    paths: &[PathBuf],
+    expand_dirs: bool,
+    mut process: impl FnMut(&Path) -> anyhow::Result<String>,
+) -> anyhow::Result<()> {
+    let mut roms = Vec::new();
+    for path in paths {
+        if expand_dirs && path.is_dir() {
+            let found = rom::find_roms(path)?;
+            if found.is_empty() {
+                warn!("no ROMs found in {path:?}");
+            }
+            roms.extend(found);
+        } else {
+            roms.push(path.clone());
+        }
+    }
+    match roms.as_slice() {
+        [] => return Err(anyhow!("no ROMs found")),
+        [rom] if !paths[0].is_dir() || !expand_dirs => return process(rom).map(|_| ()),
+        _ => {}
+    }
+
+    let mut results = Vec::new();
+    for rom in &roms {
+        println!("==> {} <==", rom.display());
+        let result = process(rom);
+        if let Err(error) = &result {
+            println!("error: {error:#}");
+        }
+        println!();
+        results.push(result);
+    }
+    println!("summary of {} ROMs:", roms.len());
+    for (rom, result) in roms.iter().zip(&results) {
+        match result {
+            Ok(line) => println!("  {}: {line}", rom.display()),
+            Err(error) => println!("  {}: failed: {error:#}", rom.display()),
+        }
+    }
+    let mut statuses = results
+        .iter()
+        .filter_map(|result| result.as_ref().err())
+        .map(ExitStatus::of);
+    let Some(first_status) = statuses.next() else {
+        return Ok(());
+    };
+    // ROMs failing in different ways give a generic failure.
+    let status = if statuses.all(|status| status == first_status) {
+        first_status
+    } else {
+        ExitStatus::Failure
+    };
+    let failed = results.iter().filter(|result| result.is_err()).count();
+    Err(StatusError::new(status, format!("{failed} of {} ROMs failed", roms.len())).into())
+}
+
+/// An error exiting with [`ExitStatus::CheckFailed`].
+fn check_failed(message: String) -> anyhow::Error {
+    StatusError::new(ExitStatus::CheckFailed, message).into()
+}
+
+/// Identifies a file, or every file inside a directory, ROM or archive if `recursive` is set,
+/// returning a one-line summary for [`for_each_rom`].
+fn identify_path(
+    path: &Path,
+    recursive: bool,
+    report: Option<&Path>,
+    no_cache: bool,
+    encoding: &TextEncoding,
+    plugins: &Plugins,
+) -> anyhow::Result<String> {
+    let title = path.file_name().unwrap_or_default().to_string_lossy();
+    let summarize = |survey: &Survey| {
+        let counts = survey.format_counts();
+        let mut formats = counts
+            .iter()
+            .take(3)
+            .map(|(format, count)| format!("{count} {format}"))
+            .collect::<Vec<_>>();
+        if counts.len() > formats.len() {
+            formats.push("...".to_owned());
+        }
+        format!("{} files ({})", survey.files.len(), formats.join(", "))
+    };
+    if recursive && path.is_dir() {
+        let cache = (!no_cache)
+            .then(|| Arc::new(survey::IdentificationCache::new(path, encoding, plugins)));
+        let mut survey = Survey {
+            plugins: plugins.clone(),
+            cache: cache.clone(),
+            ..Survey::default()
+        };
+        survey.add_dir(path, encoding)?;
+        if let Some(cache) = &cache {
+            info!(
+                "{} of {} files identified from the cache",
+                cache.hits(),
+                survey.files.len()
+            );
+            if let Err(error) = cache.save() {
+                warn!("{error:#}");
+            }
+        }
+        print_survey(&survey);
+        let summary = summarize(&survey);
+        if let Some(report_path) = report {
+            let mut files = Vec::new();
+            for relative_path in pack::walk_files(path, &[cache::CACHE_DIR])? {
+                let data = fs::read(path.join(&relative_path))
+                    .with_context(|| format!("could not read {relative_path:?}"))?;
+                files.push((rom::nitro_path(&relative_path), data));
+            }
+            let files = files
+                .iter()
+                .map(|(path, data)| (path.clone(), data.as_slice()))
+                .collect::<Vec<_>>();
+            write_report(report_path, &title, survey, &files, encoding)?;
+        }
+        return Ok(summary);
+    }
+
+    let mut data = Vec::new();
+    if rom::is_zip(path) {
+        data = read_rom(path)?;
+    } else {
+        fs::File::open(path)
+            .context("could not open file to idenfify")?
+            .read_to_end(&mut data)
+            .context("could not read file to idenfify")?;
+    }
+
+    if recursive {
+        let mut survey = Survey {
+            plugins: plugins.clone(),
+            ..Survey::default()
+        };
+        if !survey.add_contents("", &data, encoding) {
+            survey.add(title.clone().into_owned(), &data, encoding);
+        }
+        print_survey(&survey);
+        let summary = summarize(&survey);
+        if let Some(report_path) = report {
+            let files = report::rom_files(&data);
+            write_report(report_path, &title, survey, &files, encoding)?;
+        }
+        return Ok(summary);
+    }
+
+    let candidates = survey::candidates(&data, encoding, plugins);
+    let identification = candidates
+        .iter()
+        .find(|candidate| candidate.confidence >= survey::DEFAULT_MIN_CONFIDENCE)
+        .map_or_else(survey::FileIdentification::unknown, |candidate| {
+            candidate.identification.clone()
+        });
+    println!("{}", identification.description);
+    if !candidates.is_empty() {
+        println!("  candidates:");
+    }
+    for candidate in &candidates {
+        println!(
+            "    {:>3.0}% {}: {}",
+            candidate.confidence * 100.0,
+            candidate.identification.format,
+            candidate.evidence.join(", ")
+        );
+    }
+    if identification.format == "unknown format" {
+        let contents = manifest::Codec::detect(&data).map_or(data, |(_, contents)| contents);
+        for line in heuristics::analyze(&contents).to_string().lines() {
+            println!("  {line}");
+        }
+    } else if let Ok(nsbtx) =
+        nsbtx::Nsbtx::parse(&decompress_lz10(data.as_slice()).unwrap_or_else(|_| data.clone()))
+    {
+        for texture in &nsbtx.textures {
+            println!(
+                "  texture {}: {}x{}, {}",
+                texture.name, texture.width, texture.height, texture.format
+            );
+        }
+        for palette in &nsbtx.palettes {
+            println!("  palette {}", palette.name);
+        }
+    }
+    Ok(identification.description)
+}
+
+/// Writes an HTML report of the files of a survey, sampling the text and graphics among `files`.
+fn write_report(
+    path: &Path,
+    title: &str,
+    survey: Survey,
+    files: &[(String, &[u8])],
+    encoding: &TextEncoding,
+) -> anyhow::Result<()> {
+    let mut report = report::Report::new(title.to_owned(), survey);
+    report.add_samples(files, encoding);
+    fs::write(path, report.to_html()).with_context(|| format!("failed to write {path:?}"))?;
+    info!(
+        "report written to {path:?}, with {} thumbnails and {} text samples",
+        report.thumbnails.len(),
+        report.text_samples.len()
+    );
+    Ok(())
+}
+
+/// Prints every file of a survey, followed by the number of files of each format.
+fn print_survey(survey: &Survey) {
+    for file in &survey.files {
+        println!("{}: {}", file.path, file.identification.description);
+    }
+    println!("\n{} files:", survey.files.len());
+    for total in survey.format_totals() {
+        println!(
+            "  {}: {} files, 0x{:X} bytes",
+            total.format, total.files, total.bytes
+        );
+    }
+}
+
+/// Reads a file, decompressing it if it's LZ10-compressed.
+fn read_maybe_compressed(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let data = fs::read(path).with_context(|| format!("failed to read {path:?}"))?;
+    Ok(decompress_lz10(data.as_slice()).unwrap_or(data))
+}
+
+/// Whether a path given is `-`, standing for the standard input or output.
+fn is_standard_stream(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Appends a suffix to the file name of a path, e.g. `file.bin` to `file.bin.lz`. `-` is
+/// returned as-is, so that output follows input to the standard streams.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    if is_standard_stream(path) {
+        return path.to_owned();
+    }
+    let mut target_path = path.as_os_str().to_owned();
+    target_path.push(suffix);
+    target_path.into()
+}
+
+/// Decompresses data compressed with either LZ10 or LZ11, going by its header.
+fn decompress_lz(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    match data.first() {
+        Some(0x11) => Ok(lz11::decompress_lz11(data)?),
+        _ => Ok(decompress_lz10(data)?),
+    }
+}
+
+/// Counts the matches and literals of LZ10 or LZ11 data, or of an LZ10 stream lacking its
+/// header if its decompressed size is given.
+fn count_lz_tokens(data: &[u8], raw_size: Option<usize>) -> anyhow::Result<TokenCounts> {
+    match (raw_size, data.first()) {
+        (Some(size), _) => Ok(lz10::count_tokens_lz10_raw(data, size)?),
+        (None, Some(0x11)) => Ok(lz11::count_tokens_lz11(data)?),
+        (None, _) => Ok(lz10::count_tokens_lz10(data)?),
+    }
+}
+
+/// What compressing or decompressing a file did, printed with `--stats`.
+struct CompressionStats {
+    original_size: usize,
+    result_size: usize,
+    /// Matches and literals of the compressed side.
+    tokens: TokenCounts,
+    elapsed: Duration,
+}
+
+impl CompressionStats {
+    /// Prints the statistics, indented by `indent`, to the standard error if the result itself
+    /// went to the standard output.
+    fn print(&self, indent: &str, to_stderr: bool) {
+        let ratio = self.result_size as f64 / self.original_size.max(1) as f64 * 100.0;
+        let lines = [
+            format!("original size: 0x{:X} bytes", self.original_size),
+            format!(
+                "result size:   0x{:X} bytes ({ratio:.1}% of the original)",
+                self.result_size
+            ),
+            format!(
+                "tokens:        {} matches, {} literals",
+                self.tokens.matches, self.tokens.literals
+            ),
+            format!(
+                "time:          {:.2} ms",
+                self.elapsed.as_secs_f64() * 1000.0
+            ),
+        ];
+        for line in lines {
+            if to_stderr {
+                eprintln!("{indent}{line}");
+            } else {
+                println!("{indent}{line}");
+            }
+        }
+    }
+}
+
+/// Writes a ROM made from the one at `original_path` to `target_path`. With `dry_run`, only
+/// reports what would change in it instead: the items changed, the files relocated and its
+/// final size.
+fn write_rom(
+    original_path: &Path,
+    target_path: &Path,
+    rom_data: &[u8],
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    check_not_zip(target_path)?;
+    if !dry_run {
+        return fs::write(target_path, rom_data)
+            .with_context(|| format!("failed to write ROM to {target_path:?}"));
+    }
+    if original_path.exists() {
+        let original_data = read_rom(original_path)?;
+        for diff in rom_diff::diff_roms(&original_data, rom_data, &TextEncoding::default())? {
+            println!("{}: {}", diff.name, diff.change);
+        }
+        for relocation in rom_diff::relocations(&original_data, rom_data)? {
+            println!("{}: {relocation}", relocation.name);
+        }
+        println!(
+            "ROM size: 0x{:X} -> 0x{:X} bytes",
+            original_data.len(),
+            rom_data.len()
+        );
+    } else {
+        println!("ROM size: 0x{:X} bytes", rom_data.len());
+    }
+    println!("dry run: nothing was written to {target_path:?}");
+    Ok(())
+}
+
+/// Prints how many bytes the compressed and uncompressed NitroFS files of a ROM take, along
+/// with the size of the compressed ones once decompressed.
+fn print_size_summary(rom_data: &[u8]) -> anyhow::Result<()> {
+    // Files, stored bytes and decompressed bytes of the compressed files, then of the rest.
+    let mut compressed = (0, 0, 0);
+    let mut uncompressed = (0, 0, 0);
+    if rom::has_filesystem(rom_data) {
+        for entry in rom::filesystem(rom_data)?.files() {
+            let data = rom::checked_file_data(rom_data, entry)?;
+            let (totals, decompressed_size) = match decompress_lz(data) {
+                Ok(decompressed_data) => (&mut compressed, decompressed_data.len()),
+                Err(_) => (&mut uncompressed, data.len()),
+            };
+            totals.0 += 1;
+            totals.1 += data.len();
+            totals.2 += decompressed_size;
+        }
+    }
+    let total = (
+        compressed.0 + uncompressed.0,
+        compressed.1 + uncompressed.1,
+        compressed.2 + uncompressed.2,
+    );
+    println!(
+        "{:<14}{:>7}{:>14}{:>14}{:>8}",
+        "", "files", "stored", "decompressed", "ratio"
+    );
+    for (name, (files, stored, decompressed)) in [
+        ("compressed", compressed),
+        ("uncompressed", uncompressed),
+        ("total", total),
+    ] {
+        let ratio = stored as f64 / decompressed.max(1) as f64 * 100.0;
+        println!(
+            "{name:<14}{files:>7}{:>14}{:>14}{ratio:>7.1}%",
+            format!("0x{stored:X}"),
+            format!("0x{decompressed:X}")
+        );
+    }
+    Ok(())
+}
+
+/// Opens a file for reading, or the standard input if the path given is `-`.
+fn open_input(path: &Path) -> anyhow::Result<Box<dyn Read>> {
+    if is_standard_stream(path) {
+        Ok(Box::new(std::io::stdin().lock()))
+    } else {
+        let file = fs::File::open(path).with_context(|| format!("failed to open {path:?}"))?;
+        Ok(Box::new(file))
+    }
+}
+
+/// Creates a file for writing, or writes to the standard output if the path given is `-`.
+fn create_output(path: &Path) -> anyhow::Result<Box<dyn Write>> {
+    if is_standard_stream(path) {
+        Ok(Box::new(std::io::stdout().lock()))
+    } else {
+        let file = fs::File::create(path).with_context(|| format!("failed to create {path:?}"))?;
+        Ok(Box::new(file))
+    }
+}
+
+/// Reads a file, or the standard input if the path given is `-`.
+fn read_input(path: &Path) -> anyhow::Result<Vec<u8>> {
+    if is_standard_stream(path) {
+        let mut data = Vec::new();
+        std::io::stdin()
+            .lock()
+            .read_to_end(&mut data)
+            .context("failed to read the standard input")?;
+        Ok(data)
+    } else {
+        fs::read(path).with_context(|| format!("failed to read {path:?}"))
+    }
+}
+
+fn write_stdout(data: &[u8]) -> anyhow::Result<()> {
+    match std::io::stdout().lock().write_all(data) {
+        // The reading end of a pipe may stop early, as `head` does.
+        Err(error) if error.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        result => result.context("failed to write to the standard output"),
+    }
+}
+
+/// Writes a file, or to the standard output if the path given is `-`.
+fn write_output(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    if is_standard_stream(path) {
+        write_stdout(data)
+    } else {
+        fs::write(path, data).with_context(|| format!("failed to write {path:?}"))
+    }
+}
+
+/// Parses a number given in decimal, or in hexadecimal with a `0x` prefix.
+fn parse_number(value: &str) -> Result<u64, String> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+    .map_err(|err| err.to_string())
+}
+
+fn parse_alignment(value: &str) -> Result<usize, String> {
+    let alignment = parse_number(value)?;
+    if alignment < 4 || !alignment.is_power_of_two() {
+        return Err("alignment must be a power of two of at least 4".to_owned());
+    }
+    Ok(alignment as usize)
+}
+
+fn parse_byte(value: &str) -> Result<u8, String> {
+    u8::try_from(parse_number(value)?).map_err(|err| err.to_string())
+}
+
+fn read_key_table(path: &Path) -> anyhow::Result<secure_area::KeyTable> {
+    let data = fs::read(path).context("failed to read BIOS file")?;
+    secure_area::KeyTable::parse(&data).context("failed to read key table")
+}
+
+fn read_narc(path: &Path) -> anyhow::Result<narc::Narc> {
+    let data = fs::read(path).context("failed to read NARC file")?;
+    let data = decompress_lz10(data.as_slice())
+        .or_else(|_| lz11::decompress_lz11(data.as_slice()))
+        .unwrap_or(data);
+    narc::Narc::parse(&data).context("failed to parse NARC file")
+}
+
+fn read_sdat(path: &Path) -> anyhow::Result<sdat::Sdat> {
+    let data = fs::read(path).context("failed to read SDAT file")?;
+    sdat::Sdat::parse(&data).context("failed to parse SDAT file")
+}
+
+fn main() -> std::process::ExitCode {
+    let args = match Cli::try_parse() {
+        Ok(args) => args,
+        Err(error) => {
+            let _ = error.print();
+            // Help and version requests come as errors too, but aren't failures.
+            let status = if error.use_stderr() {
+                ExitStatus::Usage
+            } else {
+                ExitStatus::Success
+            };
+            return status.code().into();
+        }
+    };
+    logger::init(args.verbose, args.quiet);
+    let fail_on = args.fail_on;
+    let status = match run(args) {
+        Ok(()) if fail_on == FailOn::Warnings && logger::warning_count() > 0 => {
+            ExitStatus::Warnings
+        }
+        Ok(()) => ExitStatus::Success,
+        Err(error) => {
+            eprintln!("Error: {error:?}");
+            ExitStatus::of(&error)
+        }
+    };
+    match fail_on {
+        FailOn::None => ExitStatus::Success,
+        FailOn::Warnings | FailOn::Errors => status,
+    }
+    .code()
+    .into()
+}
+
+fn run(args: Cli) -> anyhow::Result<()> {
+    ZIP_MEMBER.get_or_init(|| args.member);
+
+    match args.command {
+        Commands::Decompress {
+            paths,
+            output,
+            recursive,
+            raw: _,
+            size,
+            stats,
+        } => {
+            let decompress = |data: &[u8]| match size {
+                Some(size) => Ok(lz10::decompress_lz10_raw(data, size as usize)?),
+                None => decompress_lz(data),
+            };
+            // Decompresses a file, printing statistics about it if asked to.
+            let decompress_with_stats = |data: &[u8], indent: &str, to_stderr: bool| {
+                let started = Instant::now();
+                let decompressed_data = decompress(data)?;
+                if stats {
+                    CompressionStats {
+                        original_size: data.len(),
+                        result_size: decompressed_data.len(),
+                        tokens: count_lz_tokens(data, size.map(|size| size as usize))?,
+                        elapsed: started.elapsed(),
+                    }
+                    .print(indent, to_stderr);
+                }
+                anyhow::Ok(decompressed_data)
+            };
+            if let [path] = paths.as_slice() {
+                if !path.is_dir() {
+                    let target_path = output.unwrap_or_else(|| with_suffix(path, ".decomp"));
+                    let mut reader = BufReader::new(open_input(path)?);
+                    let header = reader
+                        .fill_buf()
+                        .with_context(|| format!("failed to read {path:?}"))?;
+                    // LZ10 data is decompressed as it's read, so that it needn't fit in memory.
+                    if size.is_none() && !stats && header.first() == Some(&0x10) {
+                        let writer = BufWriter::new(create_output(&target_path)?);
+                        lz10::decompress_lz10_to(reader, writer)
+                            .context("failed to decompress file")?;
+                        return Ok(());
+                    }
+                    let mut data = Vec::new();
+                    reader
+                        .read_to_end(&mut data)
+                        .with_context(|| format!("failed to read {path:?}"))?;
+                    let data = decompress_with_stats(&data, "", is_standard_stream(&target_path))
+                        .context("failed to decompress file")?;
+                    return write_output(&target_path, &data);
+                }
+            }
+            if paths.iter().any(|path| is_standard_stream(path)) {
+                return Err(anyhow!(
+                    "the standard input can only be decompressed on its own"
+                ));
+            }
+
+            let mut decompressed_count = 0;
+            for path in &paths {
+                if !path.is_dir() {
+                    let data =
+                        fs::read(path).with_context(|| format!("failed to read {path:?}"))?;
+                    if stats {
+                        println!("{path:?}:");
+                    }
+                    let data = decompress_with_stats(&data, "  ", false)
+                        .with_context(|| format!("failed to decompress {path:?}"))?;
+                    let target_path = match &output {
+                        Some(output) => output.join(path.file_name().unwrap_or_default()),
+                        None => with_suffix(path, ".decomp"),
+                    };
+                    unpack::write_file(&target_path, &data)?;
+                    decompressed_count += 1;
+                    continue;
+                }
+                if !recursive {
+                    return Err(anyhow!(
+                        "{path:?} is a directory; use --recursive to decompress the files inside it"
+                    ));
+                }
+                for relative_path in pack::walk_files(path, &[])? {
+                    let file_path = path.join(&relative_path);
+                    let data = fs::read(&file_path)
+                        .with_context(|| format!("failed to read {file_path:?}"))?;
+                    if decompress_lz(&data).is_err() {
+                        debug!("{file_path:?}: not compressed, skipping");
+                        continue;
+                    }
+                    if stats {
+                        println!("{file_path:?}:");
+                    }
+                    let data = decompress_with_stats(&data, "  ", false)?;
+                    let target_path = match &output {
+                        Some(output) => output.join(&relative_path),
+                        None => with_suffix(&file_path, ".decomp"),
+                    };
+                    info!("{file_path:?}: decompressed to {target_path:?}");
+                    unpack::write_file(&target_path, &data)?;
+                    decompressed_count += 1;
+                }
+            }
+            info!("{decompressed_count} files decompressed");
+        }
+        Commands::Compress {
+            path,
+            target_path,
+            raw,
+            lz11: use_lz11,
+            level,
+            stats,
+        } => {
+            let target_path = target_path.unwrap_or_else(|| with_suffix(&path, ".lz"));
+
+            let data = read_input(&path)?;
+            let started = Instant::now();
+            let compressed_data = if use_lz11 {
+                lz11::compress_lz11(&data, level).context("failed to compress file")?
+            } else if raw {
+                lz10::compress_lz10_raw(&data, level).context("failed to compress file")?
+            } else {
+                compress_lz10(&data, level).context("failed to compress file")?
+            };
+            if stats {
+                CompressionStats {
+                    original_size: data.len(),
+                    result_size: compressed_data.len(),
+                    tokens: count_lz_tokens(&compressed_data, raw.then_some(data.len()))?,
+                    elapsed: started.elapsed(),
+                }
+                .print("", is_standard_stream(&target_path));
+            }
+
+            write_output(&target_path, &compressed_data)?;
+        }
+        Commands::Identify {
+            paths,
+            recursive,
+            report,
+            no_cache,
+            encoding,
+            plugins,
+        } => {
+            let encoding = encoding.load()?;
+            let plugins = Plugins::load(&plugins)?;
+            if report.is_some() && paths.len() > 1 {
+                return Err(anyhow!("--report can only be used with a single path"));
+            }
+            for_each_rom(&paths, !recursive, |path| {
+                identify_path(
+                    path,
+                    recursive,
+                    report.as_deref(),
+                    no_cache,
+                    &encoding,
+                    &plugins,
+                )
+            })?;
+        }
+
+        Commands::Convert {
+            path,
+            output,
+            from,
+            to,
+            palette,
+            encoding,
+            plugins,
+        } => {
+            let options = convert::ConvertOptions {
+                encoding: encoding.load()?,
+                plugins: Plugins::load(&plugins)?,
+                palette: palette
+                    .map(|palette| {
+                        fs::read(&palette).with_context(|| format!("failed to read {palette:?}"))
+                    })
+                    .transpose()?,
+            };
+            let from = (from != "auto").then_some(from.as_str());
+            let conversion = convert::convert(&path, output.as_deref(), from, to, &options)?;
+            println!(
+                "{}: {} written to {:?}",
+                conversion.source, conversion.description, conversion.output
+            );
+        }
+
+        Commands::Unpack {
+            mut paths,
+            output,
+            dry_run,
+            include,
+            exclude,
+            recursive,
+            convert_gfx,
+            bios,
+            secure_area,
+            keep_going,
+            flat,
+            by_type,
+            min_confidence,
+            no_decompress,
+            no_convert_text,
+            report,
+            plugins,
+            hooks,
+            profile,
+        } => {
+            let layout = if flat {
+                unpack::Layout::Flat
+            } else if by_type {
+                unpack::Layout::ByType
+            } else {
+                unpack::Layout::Mirror
+            };
+            let filter =
+                rom::PathFilter::new(&include, &exclude).context("invalid pattern given")?;
+            // A single ROM may be followed by where to unpack it.
+            let positional_target = matches!(
+                paths.as_slice(),
+                [rom_path, target] if rom_path.is_file()
+                    && !target.is_file()
+                    && !rom::has_rom_extension(target)
+            );
+            let target_path = match output {
+                Some(output) => Some(output),
+                None if positional_target => paths.pop(),
+                None => None,
+            };
+            if let Some(missing) = paths.iter().find(|path| !path.exists()) {
+                return Err(anyhow!("{missing:?} does not exist"));
+            }
+            let batch = paths.len() > 1 || paths[0].is_dir();
+            if batch && report.is_some() {
+                return Err(anyhow!("--report can only be used with a single ROM"));
+            }
+            let key_table = bios.map(|bios| read_key_table(&bios)).transpose()?;
+            let plugins = Plugins::load(&plugins)?;
+            let hooks = Hooks::load(hooks.as_deref())?;
+
+            for_each_rom(&paths, true, |rom_path| {
+                let target_path = match &target_path {
+                    Some(target_path) if batch => {
+                        target_path.join(rom_path.file_stem().unwrap_or_default())
+                    }
+                    Some(target_path) => target_path.clone(),
+                    None => rom_path.with_extension(""),
+                };
+                let rom_data = read_rom(rom_path)?;
+                if !dry_run {
+                    std::fs::create_dir_all(&target_path)
+                        .context("failed to create target directory")?;
+                }
+                let game_code = rom::header_text(&rom_data, rom::GAME_CODE_RANGE);
+                let title = rom::header_text(&rom_data, rom::TITLE_RANGE);
+                unpack::unpack(
+                    &rom_data,
+                    &target_path,
+                    &filter,
+                    &unpack::UnpackOptions {
+                        dry_run,
+                        recursive,
+                        convert_gfx,
+                        keep_going,
+                        key_table: key_table.clone(),
+                        secure_area,
+                        plugins: plugins.clone(),
+                        hooks: hooks.clone(),
+                        profile: profile.find(&game_code)?,
+                        layout,
+                        min_confidence: Some(min_confidence as f64 / 100.0),
+                        conversion: unpack::Conversion::from_flags(
+                            !no_decompress,
+                            !no_convert_text,
+                        ),
+                    },
+                )?;
+                if let Some(report_path) = &report {
+                    let encoding = TextEncoding::default();
+                    let mut survey = Survey::default();
+                    survey.add_contents("", &rom_data, &encoding);
+                    let files = report::rom_files(&rom_data);
+                    write_report(report_path, &title, survey, &files, &encoding)?;
+                }
+                Ok(format!(
+                    "{title} ({game_code}), unpacked to {target_path:?}"
+                ))
+            })?;
+        }
+
+        Commands::Extract {
+            rom_path,
+            pattern,
+            output,
+            decompress,
+            raw,
+        } => {
+            let conversion = if raw {
+                Conversion::None
+            } else if decompress {
+                Conversion::DecompressOnly
+            } else {
+                Conversion::Auto
+            };
+            let filter = rom::PathFilter::new(&[pattern], &[]).context("invalid pattern given")?;
+
+            let rom = open_rom(&rom_path)?;
+            let fs = source::filesystem(&rom)?;
+            let mut entries = fs
+                .files()
+                .into_iter()
+                .filter(|entry| filter.matches(&entry.path))
+                .collect::<Vec<_>>();
+            entries.sort_by_key(|entry| entry.id);
+
+            if entries.is_empty() {
+                return Err(anyhow!("no files in the ROM match the pattern given"));
+            }
+
+            let single_target = match (&output, entries.len()) {
+                (Some(output), 1) => Some(output.clone()),
+                _ => None,
+            };
+            let target_dir = output.unwrap_or_default();
+
+            for entry in entries {
+                let mut target_entry_path = target_dir.join(path_escape::host_path(&entry.path));
+                let converted = convert_file(
+                    &source::file_data(&rom, entry)?,
+                    &mut target_entry_path,
+                    conversion,
+                    &TextEncoding::default(),
+                );
+                info!("{:?}: {}", entry.path, converted.description);
+                let target_entry_path = single_target.clone().unwrap_or(target_entry_path);
+                unpack::write_file(&target_entry_path, &converted.data)?;
+            }
+        }
+
+        Commands::Cat {
+            rom_path,
+            nitro_path,
+            raw,
+        } => {
+            let rom = open_rom(&rom_path)?;
+            let fs = source::filesystem(&rom)?;
+            let entry = fs
+                .files()
+                .into_iter()
+                .find(|entry| rom::nitro_path(&entry.path) == nitro_path.trim_matches('/'))
+                .ok_or_else(|| anyhow!("no file in the ROM has the path given"))?;
+            let data = source::file_data(&rom, entry)?;
+            let data = if raw {
+                data
+            } else {
+                decompress_lz10(data.as_slice())
+                    .ok()
+                    .or_else(|| lz11::decompress_lz11(data.as_slice()).ok())
+                    .unwrap_or(data)
+            };
+            write_stdout(&data)?;
+        }
+
+        Commands::Hexdump {
+            rom_path,
+            at,
+            len,
+            annotate,
+        } => {
+            let data = fs::read(&rom_path)
+                .with_context(|| format!("could not read {}", rom_path.display()))?;
+            let annotations = if !annotate {
+                Vec::new()
+            } else if rom::is_rom(&data) {
+                hexdump::rom_annotations(&data)
+            } else {
+                hexdump::nitro_annotations(&data, 0)
+            };
+            let start = at as usize;
+            print!(
+                "{}",
+                hexdump::hexdump(
+                    &data,
+                    start..start.saturating_add(len as usize),
+                    &annotations
+                )
+            );
+        }
+
+        Commands::Info { rom_paths } => {
+            for_each_rom(&rom_paths, true, |rom_path| {
+                let rom = open_rom(rom_path)?;
+                let header = source::header(&rom)?;
+                let title = rom::header_text(&header, rom::TITLE_RANGE);
+                let game_code = rom::header_text(&header, rom::GAME_CODE_RANGE);
+                let version = header[rom::ROM_VERSION_OFFSET];
+                let region = rom::region_name(&game_code);
+                let fs = source::filesystem(&rom)?;
+                println!("title:       {title}");
+                println!("game code:   {game_code}");
+                println!(
+                    "maker code:  {}",
+                    rom::header_text(&header, rom::MAKER_CODE_RANGE)
+                );
+                println!("unit code:   {}", rom::UnitCode::of(&header).name());
+                println!("ROM version: {version}");
+                println!("region:      {region}");
+                println!(
+                    "size:        0x{:X} bytes (0x{:X} used)",
+                    rom.len(),
+                    rom::u32_at(&header, rom::USED_ROM_SIZE_OFFSET)
+                );
+                println!(
+                    "NitroFS:     {} files, {} overlays",
+                    fs.files().len(),
+                    fs.overlays().len()
+                );
+                Ok(format!(
+                    "{title} ({game_code}, {region}, version {version}), 0x{:X} bytes, {} files",
+                    rom.len(),
+                    fs.files().len()
+                ))
+            })?;
+        }
+
+        Commands::Header { command } => match command {
+            HeaderCommands::Edit {
+                rom_path,
+                title,
+                game_code,
+                maker_code,
+                rom_version,
+                output,
+                dry_run,
+            } => {
+                let mut rom_data = read_rom(&rom_path)?;
+                if let Some(title) = title {
+                    rom::set_title(&mut rom_data, &title)?;
+                }
+                if let Some(game_code) = game_code {
+                    rom::set_header_code(
+                        &mut rom_data,
+                        "game code",
+                        rom::GAME_CODE_RANGE,
+                        &game_code,
+                    )?;
+                }
+                if let Some(maker_code) = maker_code {
+                    rom::set_header_code(
+                        &mut rom_data,
+                        "maker code",
+                        rom::MAKER_CODE_RANGE,
+                        &maker_code,
+                    )?;
+                }
+                if let Some(rom_version) = rom_version {
+                    rom_data[rom::ROM_VERSION_OFFSET] = rom_version;
+                }
+                rom::fix_header_crc(&mut rom_data);
+                write_rom(
+                    &rom_path,
+                    output.as_deref().unwrap_or(&rom_path),
+                    &rom_data,
+                    dry_run,
+                )?;
+            }
+        },
+
+        Commands::Asmpatch {
+            rom_path,
+            patch_path,
+            symbol_paths,
+            output,
+            dry_run,
+        } => {
+            let mut rom_data = read_rom(&rom_path)?;
+            let symbols = SymbolMap::load(&symbol_paths)?;
+            let source = fs::read_to_string(&patch_path)
+                .with_context(|| format!("failed to read {patch_path:?}"))?;
+            let mut allocator = freespace::Allocator::new(freespace::find_free_space(
+                &rom_data,
+                freespace::DEFAULT_MIN_SIZE,
+            )?);
+            let chunks = asm::assemble_with_allocator(&source, &symbols, &mut allocator)
+                .with_context(|| format!("failed to assemble {patch_path:?}"))?;
+            allocator.grow_overlays(&mut rom_data, CompressionLevel::default())?;
+            asm::patch_rom(&mut rom_data, &chunks).context("failed to apply assembled code")?;
+            if allocator.used() > 0 {
+                info!(
+                    "0x{:X} bytes of free space used, 0x{:X} left",
+                    allocator.used(),
+                    allocator.left()
+                );
+            }
+            for chunk in &chunks {
+                debug!(
+                    "0x{:X} bytes written at 0x{:08X}",
+                    chunk.data.len(),
+                    chunk.address
+                );
+            }
+            info!(
+                "0x{:X} bytes of code written",
+                chunks.iter().map(|chunk| chunk.data.len()).sum::<usize>()
+            );
+            write_rom(
+                &rom_path,
+                output.as_deref().unwrap_or(&rom_path),
+                &rom_data,
+                dry_run,
+            )?;
+        }
+
+        Commands::Disasm {
+            rom_path,
+            arm9,
+            arm7: _,
+            overlay,
+            start,
+            len,
+            thumb,
+            symbol_paths,
+        } => {
+            let rom_data = read_rom(&rom_path)?;
+            let symbols = SymbolMap::load(&symbol_paths)?;
+            let binary = match (arm9, overlay) {
+                (true, _) => Binary::Arm9,
+                (false, Some(overlay_id)) => Binary::Overlay(overlay_id),
+                (false, None) => Binary::Arm7,
+            };
+            let (binary_address, location) = memory::binary_location(&rom_data, binary)?;
+            let start = match start {
+                Some(start) => match parse_number(&start) {
+                    Ok(address) => address,
+                    Err(_) => symbols.resolve(&start)?.address as u64,
+                },
+                None => binary_address as u64,
+            };
+            let binary_end = binary_address as u64 + location.len() as u64;
+            let end = len.map_or(binary_end, |len| start.saturating_add(len));
+            if start < binary_address as u64 || end > binary_end {
+                return Err(anyhow!(
+                    "0x{start:08X}..0x{end:08X} is outside of the binary, loaded at \
+                     0x{binary_address:08X}..0x{binary_end:08X}"
+                ));
+            }
+            if binary == Binary::Arm9 {
+                if let Some(encrypted) = secure_area::encrypted_range(&rom_data) {
+                    if location.start < encrypted.end && encrypted.start < location.end {
+                        warn!("the secure area is encrypted, so the code in it is garbled");
+                    }
+                }
+            }
+            let part =
+                (start - binary_address as u64) as usize..(end - binary_address as u64) as usize;
+            print!(
+                "{}",
+                disasm::disassemble(
+                    &rom_data[location],
+                    binary_address,
+                    part,
+                    thumb,
+                    &symbols,
+                    overlay,
+                )
+            );
+        }
+
+        Commands::Addr {
+            rom_path,
+            value,
+            rom_offset,
+        } => {
+            let rom_data = read_rom(&rom_path)?;
+            let map = memory::AddressMap::new(&rom_data)?;
+            let mappings = if rom_offset {
+                let offset = usize::try_from(value).context("ROM offset out of range")?;
+                map.to_ram(offset).into_iter().collect::<Vec<_>>()
+            } else {
+                map.to_rom(u32::try_from(value).context("RAM address out of range")?)
+            };
+            if mappings.is_empty() {
+                return Err(anyhow!(
+                    "0x{value:X} is not {} any binary",
+                    if rom_offset { "in" } else { "loaded from" }
+                ));
+            }
+            for mapping in mappings {
+                let rom_offset = match mapping.rom_offset {
+                    Some(offset) => format!("ROM 0x{offset:08X}"),
+                    None => "compressed".to_owned(),
+                };
+                println!(
+                    "{:<11} RAM 0x{:08X}  {rom_offset}  (+0x{:X})",
+                    mapping.binary.to_string(),
+                    mapping.address,
+                    mapping.binary_offset
+                );
+            }
+        }
+
+        Commands::Cheat { command } => match command {
+            CheatCommands::Apply {
+                rom_path,
+                codes_path,
+                symbol_paths,
+                output,
+                dry_run,
+            } => {
+                let mut rom_data = read_rom(&rom_path)?;
+                let symbols = SymbolMap::load(&symbol_paths)?;
+                let codes = fs::read_to_string(&codes_path)
+                    .with_context(|| format!("failed to read {codes_path:?}"))?;
+                let cheats = cheat::parse_code_list(&codes, &symbols)
+                    .context("failed to parse code list")?;
+                let mut baked = 0;
+                for cheat in &cheats {
+                    match cheat::bake(&mut rom_data, cheat) {
+                        Ok(write_count) => {
+                            info!("{:?}: {write_count} writes baked", cheat.name);
+                            baked += 1;
+                        }
+                        Err(error) => warn!("{:?}: skipped, {error}", cheat.name),
+                    }
+                }
+                info!("{baked} of {} cheats baked", cheats.len());
+                write_rom(
+                    &rom_path,
+                    output.as_deref().unwrap_or(&rom_path),
+                    &rom_data,
+                    dry_run,
+                )?;
+            }
+        },
+
+        Commands::Fs { command } => {
+            let (rom_path, edits, output, dry_run) = match command {
+                FsCommands::Add {
+                    rom_path,
+                    nitro_path,
+                    file_path,
+                    output,
+                    dry_run,
+                } => {
+                    let data = fs::read(&file_path)
+                        .with_context(|| format!("failed to read {file_path:?}"))?;
+                    let edit = fs_edit::FsEdit::Add {
+                        path: nitro_path,
+                        data,
+                    };
+                    (rom_path, vec![edit], output, dry_run)
+                }
+                FsCommands::Rm {
+                    rom_path,
+                    nitro_paths,
+                    recursive,
+                    output,
+                    dry_run,
+                } => {
+                    let edits = nitro_paths
+                        .into_iter()
+                        .map(|path| fs_edit::FsEdit::Remove { path, recursive })
+                        .collect();
+                    (rom_path, edits, output, dry_run)
+                }
+                FsCommands::Mv {
+                    rom_path,
+                    from,
+                    to,
+                    output,
+                    dry_run,
+                } => (
+                    rom_path,
+                    vec![fs_edit::FsEdit::Move { from, to }],
+                    output,
+                    dry_run,
+                ),
+            };
+            let mut rom_data = read_rom(&rom_path)?;
+            let renumbered =
+                fs_edit::edit_filesystem(&mut rom_data, edits).context("failed to edit NitroFS")?;
+            for renumbered in &renumbered {
+                warn!(
+                    "{:?} was renumbered from file ID {} to {}",
+                    renumbered.path, renumbered.preferred_id, renumbered.assigned_id
+                );
+            }
+            write_rom(
+                &rom_path,
+                output.as_deref().unwrap_or(&rom_path),
+                &rom_data,
+                dry_run,
+            )?;
+        }
+
+        Commands::Tree { rom_path } => {
+            let rom_data = read_rom(&rom_path)?;
+            print!("{}", tree::TreeDir::from_rom(&rom_data)?.render());
+        }
+
+        Commands::Layout { rom_path, json } => {
+            let rom_data = read_rom(&rom_path)?;
+            let map = rom_map::RomMap::from_rom(&rom_data)?;
+            if json {
+                let span_json = |span: &rom_map::Span| {
+                    serde_json::json!({
+                        "start": span.range.start,
+                        "end": span.range.end,
+                        "kind": span.kind.to_string(),
+                    })
+                };
+                let overlaps = map
+                    .overlaps
+                    .iter()
+                    .map(|overlap| {
+                        serde_json::json!({
+                            "start": overlap.range.start,
+                            "end": overlap.range.end,
+                            "first": map.spans[overlap.spans.0].kind.to_string(),
+                            "second": map.spans[overlap.spans.1].kind.to_string(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let output = serde_json::json!({
+                    "rom_size": map.rom_size,
+                    "padding_size": map.padding_size(),
+                    "spans": map.spans.iter().map(span_json).collect::<Vec<_>>(),
+                    "overlaps": overlaps,
+                    "past_end": map.past_end.iter().map(span_json).collect::<Vec<_>>(),
+                });
+                println!("{}", serde_json::to_string_pretty(&output)?);
+            } else {
+                print!("{}", map.render());
+            }
+        }
+
+        Commands::Stats { rom_path, top } => {
+            let rom_data = read_rom(&rom_path)?;
+            print!("{}", rom_stats::RomStats::from_rom(&rom_data)?.render(top));
+        }
+
+        Commands::Browse {
+            rom_path,
+            output,
+            encoding,
+        } => {
+            let encoding = encoding.load()?;
+            let rom_data = read_rom(&rom_path)?;
+            let output = output.unwrap_or(rom_path);
+            check_not_zip(&output)?;
+            browse::browse(rom_data, &output, &encoding)?;
+        }
+
+        Commands::Run { script_path, args } => console::run_script(&script_path, args)?,
+
+        Commands::Repl { rom_path } => console::repl(rom_path.as_deref())?,
+
+        #[cfg(feature = "mount")]
+        Commands::Mount {
+            rom_path,
+            mount_point,
+        } => {
+            let rom_data = read_rom(&rom_path)?;
+            info!("mounting {rom_path:?} at {mount_point:?}");
+            mount::mount(rom_data, &mount_point)?;
+        }
+
+        Commands::Grep {
+            rom_path,
+            text,
+            encoding,
+        } => {
+            let rom_data = read_rom(&rom_path)?;
+            let encodings = if encoding.is_empty() {
+                SearchEncoding::ALL.to_vec()
+            } else {
+                encoding
+            };
+            let hits = search::search_rom(&rom_data, &text, &encodings)?;
+            if hits.is_empty() {
+                return Err(anyhow!("no file in the ROM contains the text given"));
+            }
+            for hit in &hits {
+                match hit.compression {
+                    Some(compression) => println!(
+                        "{}:0x{:X} ({}, {}-decompressed)",
+                        hit.path,
+                        hit.offset,
+                        hit.encoding.name(),
+                        compression.name()
+                    ),
+                    None => println!("{}:0x{:X} ({})", hit.path, hit.offset, hit.encoding.name()),
+                }
+            }
+        }
+
+        Commands::Freespace {
+            rom_path,
+            min_size,
+            json,
+        } => {
+            let rom_data = read_rom(&rom_path)?;
+            let regions = freespace::find_free_space(&rom_data, min_size as usize)?;
+            if json {
+                let regions = regions
+                    .iter()
+                    .map(|region| {
+                        serde_json::json!({
+                            "binary": region.binary.to_string(),
+                            "address": region.address,
+                            "size": region.size,
+                            "kind": region.kind.to_string(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                println!("{}", serde_json::to_string_pretty(&regions)?);
+            } else {
+                println!("{:<11} {:>10}  {:>8}  kind", "binary", "address", "size");
+                for region in &regions {
+                    println!(
+                        "{:<11} 0x{:08X}  {:>8}  {}",
+                        region.binary.to_string(),
+                        region.address,
+                        format!("0x{:X}", region.size),
+                        region.kind
+                    );
+                }
+                println!(
+                    "0x{:X} bytes free in {} regions",
+                    regions.iter().map(|region| region.size).sum::<usize>(),
+                    regions.len()
+                );
+            }
+        }
+
+        Commands::ScanStrings {
+            rom_path,
+            arm9,
+            arm7,
+            overlay,
+            encoding,
+            min_len,
+            json,
+            csv,
+        } => {
+            let rom_data = read_rom(&rom_path)?;
+            let mut binaries = Vec::new();
+            if arm9 {
+                binaries.push(Binary::Arm9);
+            }
+            if arm7 {
+                binaries.push(Binary::Arm7);
+            }
+            binaries.extend(overlay.into_iter().map(Binary::Overlay));
+            if binaries.is_empty() {
+                binaries.push(Binary::Arm9);
+                binaries.extend(
+                    overlay::overlay_table(&rom_data, Processor::Arm9)
+                        .iter()
+                        .map(|entry| Binary::Overlay(entry.id)),
+                );
+            }
+            let strings = string_scan::scan_binaries(&rom_data, &binaries, encoding, min_len)?;
+            if json {
+                let strings = strings
+                    .iter()
+                    .map(|string| {
+                        serde_json::json!({
+                            "binary": string.binary.to_string(),
+                            "address": string.address,
+                            "rom_offset": string.rom_offset,
+                            "size": string.size,
+                            "text": string.text,
+                            "pointers": string.pointers.iter().map(|pointer| serde_json::json!({
+                                "binary": pointer.binary.to_string(),
+                                "address": pointer.address,
+                                "rom_offset": pointer.rom_offset,
+                            })).collect::<Vec<_>>(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                println!("{}", serde_json::to_string_pretty(&strings)?);
+            } else if csv {
+                print!("{}", string_insert::export_csv(&strings));
+            } else {
+                for string in &strings {
+                    println!(
+                        "0x{:08X}  {:<11} {:>3}  {:?}",
+                        string.address,
+                        string.binary.to_string(),
+                        string.pointers.len(),
+                        string.text
+                    );
+                }
+            }
+            info!("{} strings found", strings.len());
+        }
+
+        Commands::InsertStrings {
+            rom_path,
+            csv_path,
+            output,
+            encoding,
+            free_space,
+            level,
+            dry_run,
+        } => {
+            let mut rom_data = read_rom(&rom_path)?;
+            let contents = fs::read_to_string(&csv_path)
+                .with_context(|| format!("failed to read {csv_path:?}"))?;
+            let edits = string_insert::import_csv(&contents)
+                .with_context(|| format!("failed to import {csv_path:?}"))?;
+            let report =
+                string_insert::insert_strings(&mut rom_data, &edits, encoding, free_space, level)?;
+            for address in &report.unreferenced {
+                warn!("no pointer to the string at 0x{address:08X} was found, so the game keeps reading the original one");
+            }
+            println!(
+                "{} strings replaced in place, {} moved with {} pointers changed, {} left as they were",
+                report.in_place, report.relocated, report.pointers, report.unchanged
+            );
+            if let Some(free_space) = free_space.filter(|_| report.free_space_used > 0) {
+                println!("0x{:X} bytes used in {free_space}", report.free_space_used);
+            }
+            if let Some(left) = report.free_space_left {
+                println!("0x{left:X} bytes of free space left");
+            }
+            write_rom(
+                &rom_path,
+                output.as_deref().unwrap_or(&rom_path),
+                &rom_data,
+                dry_run,
+            )?;
+        }
+
+        Commands::Trim {
+            rom_path,
+            output,
             dry_run,
+            fix_capacity,
+            pad_to_power_of_two,
         } => {
-            let target_path = target_path.unwrap_or_else(|| rom_path.with_extension(""));
-            if !dry_run {
-                std::fs::create_dir_all(&target_path)
-                    .context("failed to create target directory")?;
+            let mut rom_data = read_rom(&rom_path)?;
+            let original_size = rom_data.len();
+            rom_data.truncate(rom::trimmed_size(&rom_data));
+            if pad_to_power_of_two {
+                let full_size = rom_data.len().next_power_of_two().max(0x20000);
+                rom_data.resize(full_size, rom::PAD_BYTE);
+            }
+            if fix_capacity {
+                rom::fit_device_capacity(&mut rom_data);
+            }
+            println!(
+                "ROM resized from 0x{original_size:X} to 0x{:X} bytes",
+                rom_data.len()
+            );
+            write_rom(
+                &rom_path,
+                output.as_deref().unwrap_or(&rom_path),
+                &rom_data,
+                dry_run,
+            )?;
+        }
+
+        Commands::Icon { command } => match command {
+            IconCommands::Export {
+                rom_path,
+                output_dir,
+            } => {
+                let rom_data = read_rom(&rom_path)?;
+                let range = rom::Section::Banner
+                    .range(&rom_data)
+                    .ok_or_else(|| anyhow!("ROM has no banner"))?;
+                let files = banner::export_icon(&rom_data[range])?;
+                fs::create_dir_all(&output_dir)
+                    .with_context(|| format!("failed to create {output_dir:?}"))?;
+                for (name, data) in &files {
+                    let path = output_dir.join(name);
+                    fs::write(&path, data).with_context(|| format!("failed to write {path:?}"))?;
+                }
+                println!(
+                    "exported {} icon files to {}",
+                    files.len(),
+                    output_dir.display()
+                );
+            }
+            IconCommands::Import {
+                rom_path,
+                input_dir,
+                output,
+                dry_run,
+            } => {
+                let mut rom_data = read_rom(&rom_path)?;
+                let range = rom::Section::Banner
+                    .range(&rom_data)
+                    .ok_or_else(|| anyhow!("ROM has no banner"))?;
+                let files = banner::read_export(&input_dir)?;
+                if files.is_empty() {
+                    anyhow::bail!("no icon files found in {input_dir:?}");
+                }
+                banner::import_icon(&mut rom_data[range], &files)?;
+                write_rom(
+                    &rom_path,
+                    output.as_deref().unwrap_or(&rom_path),
+                    &rom_data,
+                    dry_run,
+                )?;
             }
+        },
 
-            let mut rom_data = Vec::new();
-            std::io::BufReader::new(fs::File::open(rom_path)?).read_to_end(&mut rom_data)?;
+        Commands::SecureArea { command } => match command {
+            SecureAreaCommands::Encrypt {
+                rom_path,
+                bios,
+                output,
+                dry_run,
+            } => {
+                let mut rom_data = read_rom(&rom_path)?;
+                secure_area::encrypt(&mut rom_data, &read_key_table(&bios)?)
+                    .context("failed to encrypt secure area")?;
+                write_rom(
+                    &rom_path,
+                    output.as_deref().unwrap_or(&rom_path),
+                    &rom_data,
+                    dry_run,
+                )?;
+            }
+            SecureAreaCommands::Decrypt {
+                rom_path,
+                bios,
+                output,
+                dry_run,
+            } => {
+                let mut rom_data = read_rom(&rom_path)?;
+                secure_area::decrypt(&mut rom_data, &read_key_table(&bios)?)
+                    .context("failed to decrypt secure area")?;
+                write_rom(
+                    &rom_path,
+                    output.as_deref().unwrap_or(&rom_path),
+                    &rom_data,
+                    dry_run,
+                )?;
+            }
+        },
+
+        Commands::Insert {
+            rom_path,
+            nitro_path,
+            file_path,
+            output,
+            dry_run,
+            compress,
+            raw,
+            level,
+        } => {
+            let mut rom_data = read_rom(&rom_path)?;
+            let fs = rom::filesystem(&rom_data)?;
+            let entry = fs
+                .files()
+                .into_iter()
+                .find(|entry| rom::nitro_path(&entry.path) == nitro_path.trim_matches('/'))
+                .ok_or_else(|| anyhow!("no file in the ROM has the path given"))?;
+            let file_id = entry.id;
 
-            let fnt_addr = u32::from_le_bytes(rom_data[0x40..=0x43].try_into().unwrap()) as usize;
-            let fnt_size = u32::from_le_bytes(rom_data[0x44..=0x47].try_into().unwrap()) as usize;
+            let mut new_data = fs::read(&file_path).context("failed to read file to insert")?;
+            if compress {
+                new_data = compress_lz10(&new_data, level).context("failed to compress file")?;
+            } else if !raw && manifest::Codec::detect(&new_data).is_none() {
+                if let Some((codec, _)) = manifest::Codec::detect(rom::file_data(&rom_data, entry))
+                {
+                    new_data = codec
+                        .compress(&new_data, &CompressionCache::disabled().with_level(level))
+                        .context("failed to compress file")?;
+                    info!("{nitro_path}: compressed with {codec}, like the file it replaces");
+                }
+            }
 
-            let fat_addr = u32::from_le_bytes(rom_data[0x48..=0x4B].try_into().unwrap()) as usize;
-            let fat_size = u32::from_le_bytes(rom_data[0x4C..=0x4F].try_into().unwrap()) as usize;
+            match rom::replace_file(&mut rom_data, file_id, &new_data)? {
+                rom::Placement::InPlace => info!("{nitro_path}: replaced in place"),
+                rom::Placement::Relocated { start } => {
+                    info!("{nitro_path}: relocated to 0x{start:08X}")
+                }
+            }
 
-            let fs = nitro_fs::FileSystem::new(
-                &rom_data[fnt_addr..(fnt_addr + fnt_size)],
-                &rom_data[fat_addr..(fat_addr + fat_size)],
+            write_rom(
+                &rom_path,
+                output.as_deref().unwrap_or(&rom_path),
+                &rom_data,
+                dry_run,
             )?;
-            for entry in fs.files() {
-                print!("{:?}: ", entry.path);
+        }
 
-                let mut target_entry_path = target_path.join(&entry.path);
-                if !dry_run {
-                    std::fs::create_dir_all(target_entry_path.parent().unwrap())
-                        .context("failed to create directory in target")?;
-                }
-
-                let file_data = &rom_data[entry.alloc.start as usize..entry.alloc.end as usize];
-
-                if let Ok(decompressed_data) = decompress_lz10(file_data) {
-                    print!("compressed LZ10 file, ");
-                    target_entry_path.set_extension("decomp");
-                    let data_to_write = match parse_text_file(&decompressed_data) {
-                        Ok(strings) => {
-                            println!("text file");
-                            target_entry_path.set_extension("txt");
-                            strings
-                                .into_iter()
-                                .enumerate()
-                                .map(|(idx, str)| {
-                                    include_str!("text_entry_template")
-                                        .replace("{{text}}", &str)
-                                        .replace("{{index}}", &idx.to_string())
-                                })
-                                .collect::<String>()
-                                .into_bytes()
-                        }
-                        Err(_) => {
-                            println!("unknown contents");
-                            decompressed_data
+        Commands::Patchdir {
+            rom_path,
+            patch_dir,
+            output,
+            dry_run,
+            level,
+            encoding,
+        } => {
+            let encoding = encoding.load()?;
+            let mut rom_data = read_rom(&rom_path)?;
+            let report = patchdir::patch_dir(&mut rom_data, &patch_dir, &encoding, level)?;
+            for file in &report.patched {
+                let source = match file.import {
+                    patchdir::Import::Raw => String::new(),
+                    patchdir::Import::Converted { .. } => format!(" from {:?}", file.patch_path),
+                };
+                match file.placement {
+                    rom::Placement::InPlace => {
+                        info!("{}: replaced in place{source}", file.nitro_path)
+                    }
+                    rom::Placement::Relocated { start } => {
+                        info!("{}: relocated to 0x{start:08X}{source}", file.nitro_path)
+                    }
+                }
+            }
+            for path in &report.unmatched {
+                warn!("{path:?} matches no file of the ROM, skipping it");
+            }
+            info!("{} files replaced", report.patched.len());
+            write_rom(
+                &rom_path,
+                output.as_deref().unwrap_or(&rom_path),
+                &rom_data,
+                dry_run,
+            )?;
+        }
+
+        Commands::Diff {
+            old_rom,
+            new_rom,
+            encoding,
+            secure_area,
+        } => {
+            let encoding = encoding.load()?;
+            let key_table = secure_area.key_table()?;
+            let mut old_data = read_rom(&old_rom)?;
+            secure_area.normalize(&mut old_data, &old_rom, key_table.as_ref())?;
+            let mut new_data = read_rom(&new_rom)?;
+            secure_area.normalize(&mut new_data, &new_rom, key_table.as_ref())?;
+            let diffs = rom_diff::diff_roms(&old_data, &new_data, &encoding)?;
+
+            let mut counts = [0; 3];
+            for diff in &diffs {
+                println!("{}: {}", diff.name, diff.change);
+                for string in &diff.strings {
+                    println!("  {string}");
+                }
+                counts[match diff.change {
+                    rom_diff::Change::Added { .. } => 0,
+                    rom_diff::Change::Removed { .. } => 1,
+                    rom_diff::Change::Changed { .. } => 2,
+                }] += 1;
+            }
+            println!(
+                "{} added, {} removed, {} changed",
+                counts[0], counts[1], counts[2]
+            );
+        }
+
+        Commands::CompareVariants {
+            rom_paths,
+            all,
+            encoding,
+        } => {
+            let encoding = encoding.load()?;
+            let roms = rom_paths
+                .iter()
+                .map(|path| read_rom(path))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let names = rom_paths
+                .iter()
+                .map(|path| path.file_name().unwrap_or_default().to_string_lossy())
+                .collect::<Vec<_>>();
+            for (name, rom_data) in names.iter().zip(&roms) {
+                println!(
+                    "{name}: {} ({})",
+                    rom::header_text(rom_data, rom::TITLE_RANGE),
+                    rom::header_text(rom_data, rom::GAME_CODE_RANGE)
+                );
+            }
+            println!();
+
+            let rom_slices = roms.iter().map(Vec::as_slice).collect::<Vec<_>>();
+            let items = rom_diff::compare_variants(&rom_slices, &encoding)?;
+            let per_variant = |values: &[Option<usize>], format: fn(usize) -> String| {
+                names
+                    .iter()
+                    .zip(values)
+                    .map(|(name, value)| match value {
+                        Some(value) => format!("{name} {}", format(*value)),
+                        None => format!("{name} -"),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let (mut identical, mut differing, mut specific, mut mismatches) = (0, 0, 0, 0);
+            for item in &items {
+                if !item.is_shared() {
+                    specific += 1;
+                    let having = names
+                        .iter()
+                        .zip(&item.sizes)
+                        .filter(|(_, size)| size.is_some())
+                        .map(|(name, _)| name.as_ref())
+                        .collect::<Vec<_>>();
+                    println!("{}: only in {}", item.name, having.join(", "));
+                } else if !item.identical {
+                    differing += 1;
+                    println!(
+                        "{}: differs ({})",
+                        item.name,
+                        per_variant(&item.sizes, |size| format!("0x{size:X}"))
+                    );
+                } else {
+                    identical += 1;
+                    if all {
+                        println!("{}: identical", item.name);
+                    }
+                }
+                if item.string_count_mismatch() {
+                    mismatches += 1;
+                    if let Some(counts) = &item.string_counts {
+                        println!(
+                            "  string counts: {}",
+                            per_variant(counts, |count| count.to_string())
+                        );
+                    }
+                }
+            }
+            println!(
+                "{identical} identical in every variant, {differing} in every variant but differing, {specific} only in some variants, {mismatches} text files with mismatched string counts"
+            );
+        }
+
+        Commands::VerifyRoundtrip {
+            rom_paths,
+            secure_area,
+        } => {
+            let key_table = secure_area.key_table()?;
+            for_each_rom(&rom_paths, true, |rom_path| {
+                let mut rom_data = read_rom(rom_path)?;
+                secure_area.normalize(&mut rom_data, rom_path, key_table.as_ref())?;
+                let report = verify::verify_roundtrip(&rom_data)?;
+
+                println!(
+                    "original size: 0x{:X}, repacked size: 0x{:X}",
+                    report.original_size, report.repacked_size
+                );
+                if let Some(difference) = report.first_difference {
+                    println!(
+                        "first difference at 0x{:08X} (0x{:X} bytes)",
+                        difference.offset, difference.len
+                    );
+                    println!("  in original ROM: {}", difference.original_region);
+                    println!("  in repacked ROM: {}", difference.repacked_region);
+                    return Err(check_failed(format!(
+                        "repacked ROM differs from the original at 0x{:08X}",
+                        difference.offset
+                    )));
+                }
+                println!("repacked ROM is byte-identical to the original");
+                Ok("repacked ROM is byte-identical to the original".to_owned())
+            })?;
+        }
+
+        Commands::Selftest { dir, json } => {
+            let roms = rom::find_roms(&dir)?;
+            if roms.is_empty() {
+                return Err(anyhow!("no ROMs found in {dir:?}"));
+            }
+            let mut results = Vec::with_capacity(roms.len());
+            for rom_path in &roms {
+                info!("testing {}", rom_path.display());
+                let result = match read_rom(rom_path) {
+                    Ok(rom_data) => verify::selftest(&rom_data),
+                    Err(error) => verify::SelftestResult {
+                        title: String::new(),
+                        game_code: String::new(),
+                        maker_code: String::new(),
+                        failure: Some((verify::SelftestStage::Header, format!("{error:#}"))),
+                    },
+                };
+                results.push(result);
+            }
+
+            // Passing and total ROMs per maker code.
+            let mut makers = std::collections::BTreeMap::<&str, (usize, usize)>::new();
+            for result in &results {
+                let counts = makers.entry(&result.maker_code).or_default();
+                counts.0 += result.passed() as usize;
+                counts.1 += 1;
+            }
+            let passed = results.iter().filter(|result| result.passed()).count();
+
+            if json {
+                let output = serde_json::json!({
+                    "roms": roms
+                        .iter()
+                        .zip(&results)
+                        .map(|(rom_path, result)| serde_json::json!({
+                            "path": rom_path,
+                            "title": result.title,
+                            "game_code": result.game_code,
+                            "maker_code": result.maker_code,
+                            "passed": result.passed(),
+                            "failed_stage": result.failure.as_ref().map(|(stage, _)| stage),
+                            "error": result.failure.as_ref().map(|(_, error)| error),
+                        }))
+                        .collect::<Vec<_>>(),
+                    "makers": makers
+                        .iter()
+                        .map(|(maker_code, (passed, total))| serde_json::json!({
+                            "maker_code": maker_code,
+                            "passed": passed,
+                            "total": total,
+                        }))
+                        .collect::<Vec<_>>(),
+                    "passed": passed,
+                    "total": results.len(),
+                });
+                println!("{}", serde_json::to_string_pretty(&output)?);
+            } else {
+                for (rom_path, result) in roms.iter().zip(&results) {
+                    let status = match &result.failure {
+                        None => "pass".to_owned(),
+                        Some((stage, error)) => format!("FAIL at {}: {error}", stage.name()),
+                    };
+                    println!(
+                        "{:<4} {:<2} {:<12} {status} ({})",
+                        result.game_code,
+                        result.maker_code,
+                        result.title,
+                        rom_path.display()
+                    );
+                }
+                println!();
+                println!("by maker:");
+                for (maker_code, (maker_passed, total)) in &makers {
+                    println!("  {maker_code:<2}: {maker_passed} of {total} passed");
+                }
+                println!("{passed} of {} ROMs passed", results.len());
+            }
+            if passed < results.len() {
+                return Err(check_failed(format!(
+                    "{} of {} ROMs failed",
+                    results.len() - passed,
+                    results.len()
+                )));
+            }
+        }
+
+        Commands::Overlays { rom_path, command } => match command {
+            None => {
+                let rom_path = rom_path.expect("the ROM path is required without a subcommand");
+                let rom_data = read_rom(&rom_path)?;
+                for processor in [Processor::Arm9, Processor::Arm7] {
+                    let entries = overlay::overlay_table(&rom_data, processor);
+                    if entries.is_empty() {
+                        continue;
+                    }
+                    println!("{} overlays:", overlay::processor_name(processor));
+                    println!(
+                        "  {:>4}  {:>10}  {:>8}  {:>8}  {:>23}  {:>7}  compressed",
+                        "ID", "RAM", "size", "BSS", "static init", "file ID"
+                    );
+                    for entry in entries {
+                        let compressed = if entry.compressed {
+                            format!("0x{:X} bytes", entry.compressed_size)
+                        } else {
+                            "no".to_owned()
+                        };
+                        println!(
+                            "  {:>4}  0x{:08X}  {:>8}  {:>8}  0x{:08X}-0x{:08X}  {:>7}  {compressed}",
+                            entry.id,
+                            entry.ram_address,
+                            format!("0x{:X}", entry.ram_size),
+                            format!("0x{:X}", entry.bss_size),
+                            entry.static_init_start,
+                            entry.static_init_end,
+                            entry.file_id,
+                        );
+                    }
+                }
+            }
+            Some(OverlayCommands::Extract {
+                rom_path,
+                overlay_id,
+                output,
+                arm7,
+                raw,
+            }) => {
+                let rom_data = read_rom(&rom_path)?;
+                let processor = if arm7 {
+                    Processor::Arm7
+                } else {
+                    Processor::Arm9
+                };
+                let data = overlay::extract_overlay(&rom_data, processor, overlay_id, !raw)?;
+                let output =
+                    output.unwrap_or_else(|| PathBuf::from(format!("overlay_{overlay_id:04}.bin")));
+                fs::write(&output, data)
+                    .with_context(|| format!("failed to write overlay to {output:?}"))?;
+            }
+            Some(OverlayCommands::Replace {
+                rom_path,
+                overlay_id,
+                file_path,
+                output,
+                dry_run,
+                arm7,
+                level,
+            }) => {
+                let mut rom_data = read_rom(&rom_path)?;
+                let processor = if arm7 {
+                    Processor::Arm7
+                } else {
+                    Processor::Arm9
+                };
+                let data = fs::read(&file_path).context("failed to read overlay to insert")?;
+                let (entry, placement) =
+                    overlay::replace_overlay(&mut rom_data, processor, overlay_id, &data, level)?;
+                let stored = if entry.compressed {
+                    format!("compressed to 0x{:X} bytes", entry.compressed_size)
+                } else {
+                    "uncompressed".to_owned()
+                };
+                match placement {
+                    rom::Placement::InPlace => {
+                        info!("overlay {overlay_id}: replaced in place, {stored}")
+                    }
+                    rom::Placement::Relocated { start } => {
+                        info!("overlay {overlay_id}: relocated to 0x{start:08X}, {stored}")
+                    }
+                }
+                write_rom(
+                    &rom_path,
+                    output.as_deref().unwrap_or(&rom_path),
+                    &rom_data,
+                    dry_run,
+                )?;
+            }
+        },
+
+        Commands::Narc { command } => match command {
+            NarcCommands::List { path } => {
+                let narc = read_narc(&path)?;
+                for (file_id, file) in narc.files.iter().enumerate() {
+                    println!(
+                        "{file_id:4}: {} (0x{:X} bytes)",
+                        file.name.as_deref().unwrap_or("<nameless>"),
+                        file.data.len()
+                    );
+                }
+            }
+            NarcCommands::Extract {
+                path,
+                target_path,
+                recursive,
+            } => {
+                let narc = read_narc(&path)?;
+                let target_path = target_path.unwrap_or_else(|| path.with_extension(""));
+                narc::extract(&narc, &target_path, recursive)?;
+            }
+            NarcCommands::Pack { dir_path, output } => {
+                let output = output.unwrap_or_else(|| {
+                    let mut output = dir_path.clone().into_os_string();
+                    output.push(".narc");
+                    output.into()
+                });
+
+                let narc = narc::from_dir(&dir_path, &CompressionCache::disabled())?;
+                let data = narc.to_bytes().context("failed to build NARC file")?;
+                fs::write(output, data).context("failed to write NARC file")?;
+            }
+            NarcCommands::Create {
+                dir_path,
+                output,
+                nameless,
+                align,
+                compress,
+                compress_archive,
+                level,
+            } => {
+                let output = output.unwrap_or_else(|| {
+                    let mut output = dir_path.clone().into_os_string();
+                    output.push(".narc");
+                    output.into()
+                });
+
+                let cache = CompressionCache::disabled().with_level(level);
+                let narc = narc::create(&dir_path, !nameless, compress, &cache)?;
+                let data = narc
+                    .to_bytes_aligned(align)
+                    .context("failed to build NARC file")?;
+                let data = cache
+                    .compress(&data, compress_archive)
+                    .context("failed to compress NARC file")?;
+                fs::write(&output, &data).context("failed to write NARC file")?;
+                println!(
+                    "{} files packed into {output:?} (0x{:X} bytes)",
+                    narc.files.len(),
+                    data.len()
+                );
+            }
+        },
+
+        Commands::Gfx { command } => match command {
+            GfxCommands::Export {
+                graphics,
+                palette,
+                screen,
+                palette_index,
+                output,
+            } => {
+                let ncgr = gfx::Ncgr::parse(&read_maybe_compressed(&graphics)?)
+                    .context("failed to parse graphics file")?;
+                let nclr = gfx::Nclr::parse(&read_maybe_compressed(&palette)?)
+                    .context("failed to parse palette file")?;
+                let image = match screen {
+                    Some(screen) => {
+                        let nscr = gfx::Nscr::parse(&read_maybe_compressed(&screen)?)
+                            .context("failed to parse screen file")?;
+                        gfx::render_screen(&ncgr, &nclr, &nscr)
+                    }
+                    None => gfx::render_graphics(&ncgr, &nclr, palette_index),
+                };
+                let output = output.unwrap_or_else(|| graphics.with_extension("png"));
+                fs::write(&output, image.to_png().context("failed to encode PNG")?)
+                    .with_context(|| format!("failed to write {output:?}"))?;
+                println!(
+                    "{}x{} image written to {output:?}",
+                    image.width, image.height
+                );
+            }
+            GfxCommands::Cells {
+                graphics,
+                palette,
+                cells,
+                animations,
+                output,
+            } => {
+                let ncgr = gfx::Ncgr::parse(&read_maybe_compressed(&graphics)?)
+                    .context("failed to parse graphics file")?;
+                let nclr = gfx::Nclr::parse(&read_maybe_compressed(&palette)?)
+                    .context("failed to parse palette file")?;
+                let ncer = gfx::Ncer::parse(&read_maybe_compressed(&cells)?)
+                    .context("failed to parse cell bank file")?;
+                let nanr = animations
+                    .map(|animations: PathBuf| {
+                        gfx::Nanr::parse(&read_maybe_compressed(&animations)?)
+                            .context("failed to parse animation file")
+                    })
+                    .transpose()?;
+                let output = output.unwrap_or_else(|| cells.with_extension(""));
+                let cell_count =
+                    unpack::write_cells(&output, &ncgr, &nclr, &ncer, nanr.as_ref(), false)?;
+                println!("{cell_count} cells written to {output:?}");
+                if let Some(nanr) = nanr {
+                    println!(
+                        "{} animations written to {:?}",
+                        nanr.animations.len(),
+                        output.join(unpack::ANIMATIONS_FILE_NAME)
+                    );
+                }
+            }
+            GfxCommands::Textures { path, output } => {
+                let nsbtx = nsbtx::Nsbtx::parse(&read_maybe_compressed(&path)?)
+                    .context("failed to parse texture file")?;
+                for texture in &nsbtx.textures {
+                    println!(
+                        "{}: {}x{}, {}",
+                        texture.name, texture.width, texture.height, texture.format
+                    );
+                }
+                let output = output.unwrap_or_else(|| path.with_extension(""));
+                let texture_count = unpack::write_textures(&output, &nsbtx, false)?;
+                println!("{texture_count} textures written to {output:?}");
+            }
+            GfxCommands::Tilemap {
+                graphics,
+                palette,
+                screen,
+                output,
+            } => {
+                let ncgr = gfx::Ncgr::parse(&read_maybe_compressed(&graphics)?)
+                    .context("failed to parse graphics file")?;
+                let nclr = gfx::Nclr::parse(&read_maybe_compressed(&palette)?)
+                    .context("failed to parse palette file")?;
+                let nscr = gfx::Nscr::parse(&read_maybe_compressed(&screen)?)
+                    .context("failed to parse screen file")?;
+                let output = output.unwrap_or_else(|| screen.with_extension(""));
+                let name = output
+                    .file_stem()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let tmx_path = output.with_extension("tmx");
+                let tsx_path = output.with_extension("tsx");
+                let image_path =
+                    output.with_file_name(format!("{name}{}", tmx::TILESET_IMAGE_SUFFIX));
+                let export = tmx::export_tmx(&ncgr, &nclr, &nscr, &name);
+                fs::write(&tmx_path, export.tmx)
+                    .with_context(|| format!("failed to write {tmx_path:?}"))?;
+                fs::write(&tsx_path, export.tsx)
+                    .with_context(|| format!("failed to write {tsx_path:?}"))?;
+                fs::write(
+                    &image_path,
+                    export.tileset.to_png().context("failed to encode PNG")?,
+                )
+                .with_context(|| format!("failed to write {image_path:?}"))?;
+                println!(
+                    "{}x{} tile map written to {tmx_path:?}",
+                    nscr.width / gfx::TILE_SIZE,
+                    nscr.height / gfx::TILE_SIZE
+                );
+            }
+            GfxCommands::ImportTilemap {
+                map,
+                screen,
+                output,
+            } => {
+                let tmx =
+                    fs::read_to_string(&map).with_context(|| format!("failed to read {map:?}"))?;
+                let nscr =
+                    tmx::import_tmx(&tmx).with_context(|| format!("failed to import {map:?}"))?;
+                let data =
+                    fs::read(&screen).with_context(|| format!("failed to read {screen:?}"))?;
+                let decompressed = decompress_lz10(data.as_slice()).ok();
+                let original = decompressed.as_deref().unwrap_or(&data);
+                gfx::Nscr::parse(original).context("failed to parse screen file")?;
+                let mut data = nscr
+                    .to_bytes(original)
+                    .context("failed to build screen file")?;
+                if decompressed.is_some() {
+                    data = compress_lz10(&data, CompressionLevel::Best)
+                        .context("failed to compress screen file")?;
+                }
+                let output = output.unwrap_or(screen);
+                fs::write(&output, data).with_context(|| format!("failed to write {output:?}"))?;
+                println!(
+                    "{}x{} tile screen written to {output:?}",
+                    nscr.width / gfx::TILE_SIZE,
+                    nscr.height / gfx::TILE_SIZE
+                );
+            }
+            GfxCommands::Palette {
+                path,
+                format,
+                output,
+            } => {
+                let nclr = gfx::Nclr::parse(&read_maybe_compressed(&path)?)
+                    .context("failed to parse palette file")?;
+                let output = output.unwrap_or_else(|| path.with_extension(""));
+                let name = output
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy())
+                    .unwrap_or_default();
+                let formats =
+                    format.map_or(palette::PaletteFormat::ALL.to_vec(), |format| vec![format]);
+                for &format in &formats {
+                    let data = match palette::export_palette(&nclr.colors, format, &name) {
+                        Ok(data) => data,
+                        // Palettes too large for some formats are still written in the others.
+                        Err(error) if formats.len() > 1 => {
+                            warn!("not writing a {} file: {error}", format.extension());
+                            continue;
                         }
+                        Err(error) => return Err(error.into()),
                     };
+                    let path = output.with_extension(format.extension());
+                    fs::write(&path, data).with_context(|| format!("failed to write {path:?}"))?;
+                    println!("{} colors written to {path:?}", nclr.colors.len());
+                }
+            }
+            GfxCommands::ImportPalette {
+                palette_file,
+                path,
+                format,
+                output,
+            } => {
+                let format = format
+                    .or_else(|| palette::PaletteFormat::from_path(&palette_file))
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "cannot guess the format of {palette_file:?}, give it with --format"
+                        )
+                    })?;
+                let colors = palette::import_palette(
+                    &fs::read(&palette_file)
+                        .with_context(|| format!("failed to read {palette_file:?}"))?,
+                    format,
+                )
+                .with_context(|| format!("failed to parse {palette_file:?}"))?;
+                let data = fs::read(&path).with_context(|| format!("failed to read {path:?}"))?;
+                let decompressed = decompress_lz10(data.as_slice()).ok();
+                let original = decompressed.as_deref().unwrap_or(&data);
+                let mut nclr =
+                    gfx::Nclr::parse(original).context("failed to parse palette file")?;
+                if colors.len() > nclr.colors.len() {
+                    warn!(
+                        "the palette file has {} colors, but the NCLR file only holds {}, ignoring the rest",
+                        colors.len(),
+                        nclr.colors.len()
+                    );
+                }
+                nclr.colors = palette::apply_palette(
+                    &nclr.colors,
+                    &colors[..colors.len().min(nclr.colors.len())],
+                );
+                let mut data = nclr
+                    .to_bytes(original)
+                    .context("failed to build palette file")?;
+                if decompressed.is_some() {
+                    data = compress_lz10(&data, CompressionLevel::Best)
+                        .context("failed to compress palette file")?;
+                }
+                let output = output.unwrap_or(path);
+                fs::write(&output, data).with_context(|| format!("failed to write {output:?}"))?;
+                println!(
+                    "{} colors imported into {output:?}",
+                    colors.len().min(nclr.colors.len())
+                );
+            }
+        },
+
+        Commands::Font { command } => match command {
+            FontCommands::Export { path, output } => {
+                let font = nftr::Nftr::parse(&read_maybe_compressed(&path)?)
+                    .context("failed to parse font file")?;
+                let output = output.unwrap_or_else(|| path.with_extension("png"));
+                let description_path = output.with_extension("json");
+                fs::write(
+                    &output,
+                    font.to_sheet_png().context("failed to encode PNG")?,
+                )
+                .with_context(|| format!("failed to write {output:?}"))?;
+                fs::write(
+                    &description_path,
+                    serde_json::to_vec_pretty(&font.description())?,
+                )
+                .with_context(|| format!("failed to write {description_path:?}"))?;
+                println!(
+                    "{} glyphs written to {output:?}, described in {description_path:?}",
+                    font.glyphs.len()
+                );
+            }
+            FontCommands::Import {
+                description,
+                sheet,
+                output,
+            } => {
+                let sheet = sheet.unwrap_or_else(|| description.with_extension("png"));
+                let description: nftr::FontDescription = serde_json::from_slice(
+                    &fs::read(&description).context("failed to read font description")?,
+                )
+                .context("failed to parse font description")?;
+                let sheet_data = fs::read(&sheet).context("failed to read glyph sheet")?;
+                let font = nftr::Nftr::import(&description, &sheet_data)
+                    .context("failed to import font")?;
+                fs::write(&output, font.to_bytes())
+                    .with_context(|| format!("failed to write {output:?}"))?;
+                println!(
+                    "font with {} glyphs and {} characters written to {output:?}",
+                    font.glyphs.len(),
+                    font.char_map.len()
+                );
+            }
+        },
+
+        Commands::Model { command } => match command {
+            ModelCommands::Info { path } => {
+                let nsbmd = nsbmd::Nsbmd::parse(&read_maybe_compressed(&path)?)
+                    .context("failed to parse model file")?;
+                for model in &nsbmd.models {
+                    let primitives = model.geometry();
+                    println!(
+                        "model {}: {} bones, {} materials, {} meshes, {} triangles",
+                        model.name,
+                        model.bones.len(),
+                        model.materials.len(),
+                        model.meshes.len(),
+                        primitives.iter().map(|p| p.triangle_count()).sum::<usize>()
+                    );
+                    println!("  bones:");
+                    fn print_bones(bones: &[nsbmd::Bone], parent: Option<usize>, depth: usize) {
+                        for (index, bone) in bones.iter().enumerate() {
+                            if bone.parent == parent {
+                                println!("  {}{}", "  ".repeat(depth), bone.name);
+                                print_bones(bones, Some(index), depth + 1);
+                            }
+                        }
+                    }
+                    print_bones(&model.bones, None, 1);
+                    println!("  materials:");
+                    for material in &model.materials {
+                        print!("    {}", material.name);
+                        if let Some(texture) = &material.texture {
+                            print!(": texture {texture}");
+                            if let Some(palette) = &material.palette {
+                                print!(", palette {palette}");
+                            }
+                        }
+                        println!();
+                    }
+                    println!("  meshes:");
+                    for (index, mesh) in model.meshes.iter().enumerate() {
+                        let triangles = primitives
+                            .iter()
+                            .filter(|primitive| primitive.mesh == index)
+                            .map(|primitive| primitive.triangle_count())
+                            .sum::<usize>();
+                        println!("    {}: {triangles} triangles", mesh.name);
+                    }
+                }
+                if let Some(textures) = &nsbmd.textures {
+                    println!(
+                        "{} embedded textures, {} palettes",
+                        textures.textures.len(),
+                        textures.palettes.len()
+                    );
+                }
+            }
+            ModelCommands::Export {
+                path,
+                textures,
+                output,
+            } => {
+                let nsbmd = nsbmd::Nsbmd::parse(&read_maybe_compressed(&path)?)
+                    .context("failed to parse model file")?;
+                let textures = textures
+                    .map(|textures: PathBuf| {
+                        nsbtx::Nsbtx::parse(&read_maybe_compressed(&textures)?)
+                            .context("failed to parse texture file")
+                    })
+                    .transpose()?;
+                let output = output.unwrap_or_else(|| path.with_extension("gltf"));
+                let directory = output.parent().unwrap_or(Path::new(""));
+                let buffer_name = output
+                    .with_extension("bin")
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned();
+                let gltf = nsbmd
+                    .to_gltf(textures.as_ref(), &buffer_name, unpack::texture_file_name)
+                    .context("failed to encode textures")?;
+                unpack::write_file(&directory.join(&buffer_name), &gltf.buffer)?;
+                for (file_name, png_data) in &gltf.images {
+                    unpack::write_file(&directory.join(file_name), png_data)?;
+                }
+                unpack::write_file(&output, &serde_json::to_vec_pretty(&gltf.document)?)?;
+                println!(
+                    "{} models written to {output:?}, with {} textures",
+                    nsbmd.models.len(),
+                    gltf.images.len()
+                );
+            }
+        },
 
-                    if !dry_run {
-                        fs::File::create(target_entry_path)
-                            .context("failed to create file in target directory")?
-                            .write_all(&data_to_write)
-                            .context("failed to write file in target directory")?;
+        Commands::Sdat { command } => match command {
+            SdatCommands::List { path } => {
+                let sdat = read_sdat(&path)?;
+                for file in &sdat.files {
+                    println!(
+                        "{} {}: {} (file {}, {} bytes)",
+                        file.kind,
+                        file.index,
+                        file.display_name(),
+                        file.file_id,
+                        file.data.len()
+                    );
+                }
+                println!("{} files", sdat.files.len());
+            }
+            SdatCommands::Extract { path, output } => {
+                let sdat = read_sdat(&path)?;
+                let output = output.unwrap_or_else(|| path.with_extension(""));
+                let file_count = unpack::write_sounds(&output, &sdat, false)?;
+                println!("{file_count} files extracted to {output:?}");
+            }
+            SdatCommands::Build { dir, output } => {
+                let sdat = sdat::from_dir(&dir)?;
+                let data = sdat
+                    .to_bytes()
+                    .with_context(|| format!("failed to build SDAT from {dir:?}"))?;
+                let output = output.unwrap_or_else(|| dir.with_extension("sdat"));
+                fs::write(&output, data).with_context(|| format!("failed to write {output:?}"))?;
+                println!("{} files built into {output:?}", sdat.files.len());
+            }
+            SdatCommands::Wav { path, output } => {
+                let data = fs::read(&path).with_context(|| format!("failed to read {path:?}"))?;
+                if data.starts_with(b"SWAR") {
+                    let waves =
+                        wave::Wave::parse_swar(&data).context("failed to parse SWAR file")?;
+                    let output = output.unwrap_or_else(|| path.with_extension(""));
+                    for (index, wave) in waves.iter().enumerate() {
+                        unpack::write_wave(
+                            &output.join(unpack::wave_file_name(index)),
+                            wave,
+                            false,
+                        )?;
                     }
+                    println!("{} waves written to {output:?}", waves.len());
                 } else {
-                    println!("unknown format");
+                    let wave = if data.starts_with(b"STRM") {
+                        wave::Wave::parse_strm(&data).context("failed to parse STRM file")?
+                    } else {
+                        wave::Wave::parse_swav(&data).context("failed to parse SWAV file")?
+                    };
+                    let output = output.unwrap_or_else(|| path.with_extension("wav"));
+                    unpack::write_wave(&output, &wave, false)?;
+                    println!(
+                        "{} samples at {} Hz written to {output:?}",
+                        wave.channels.first().map_or(0, Vec::len),
+                        wave.sample_rate
+                    );
+                }
+            }
+            SdatCommands::Encode {
+                path,
+                format,
+                encoding,
+                rate,
+                loop_start,
+                loop_end,
+                no_loop,
+                output,
+            } => {
+                let data = fs::read(&path).with_context(|| format!("failed to read {path:?}"))?;
+                let mut wave = wave::Wave::from_wav(&data)
+                    .with_context(|| format!("failed to read WAV file {path:?}"))?;
+                let sidecar_path = path.with_extension("json");
+                if let (Some(loop_start), Some(loop_end)) = (loop_start, loop_end) {
+                    wave.loop_points = Some(wave::LoopPoints {
+                        sample_rate: wave.sample_rate,
+                        loop_start,
+                        loop_end,
+                    });
+                } else if sidecar_path.is_file() {
+                    let sidecar = fs::read(&sidecar_path)
+                        .with_context(|| format!("failed to read {sidecar_path:?}"))?;
+                    let loop_points: wave::LoopPoints = serde_json::from_slice(&sidecar)
+                        .with_context(|| format!("failed to parse {sidecar_path:?}"))?;
+                    info!("loop points read from {sidecar_path:?}");
+                    wave.loop_points = Some(loop_points);
+                }
+                if no_loop {
+                    wave.loop_points = None;
+                }
+                if let Some(rate) = rate {
+                    wave = wave.resampled(rate);
+                }
+                if format == wave::WaveFile::Swav && wave.channels.len() > 1 {
+                    info!(
+                        "mixing {} channels down to one, as SWAV files are mono",
+                        wave.channels.len()
+                    );
+                    wave = wave.to_mono();
+                }
+                let encoded = match format {
+                    wave::WaveFile::Swav => wave.to_swav(encoding),
+                    wave::WaveFile::Strm => wave.to_strm(encoding),
+                }
+                .with_context(|| format!("failed to encode {path:?}"))?;
+                let output = output.unwrap_or_else(|| path.with_extension(format.extension()));
+                fs::write(&output, encoded)
+                    .with_context(|| format!("failed to write {output:?}"))?;
+                println!(
+                    "{} samples at {} Hz written to {output:?}",
+                    wave.channels.first().map_or(0, Vec::len),
+                    wave.sample_rate
+                );
+            }
+            SdatCommands::Sf2 { path, bank, output } => {
+                let sdat = read_sdat(&path)?;
+                let banks = sdat
+                    .files
+                    .iter()
+                    .filter(|file| file.kind == sdat::SoundKind::Bank);
+                let banks = match &bank {
+                    Some(bank) => vec![banks
+                        .clone()
+                        .find(|file| {
+                            file.name.as_ref() == Some(bank) || bank.parse() == Ok(file.index)
+                        })
+                        .ok_or_else(|| anyhow!("the SDAT file has no bank {bank:?}"))?],
+                    None => banks.collect(),
+                };
+                let output_dir = match (&bank, &output) {
+                    (None, Some(output)) => output.clone(),
+                    (None, None) => path.with_extension(""),
+                    (Some(_), _) => path.parent().unwrap_or(Path::new("")).to_owned(),
+                };
+                for file in banks {
+                    let soundfont = unpack::bank_soundfont(&sdat, file).with_context(|| {
+                        format!("failed to convert bank {}", file.display_name())
+                    })?;
+                    let sf2_path = match (&bank, &output) {
+                        (Some(_), Some(output)) => output.clone(),
+                        _ => output_dir.join(
+                            unpack::sound_file_path(file)
+                                .with_extension("sf2")
+                                .file_name()
+                                .unwrap_or_default(),
+                        ),
+                    };
+                    if let Some(parent) = sf2_path
+                        .parent()
+                        .filter(|parent| !parent.as_os_str().is_empty())
+                    {
+                        fs::create_dir_all(parent)
+                            .with_context(|| format!("failed to create {parent:?}"))?;
+                    }
+                    fs::write(&sf2_path, soundfont.to_bytes())
+                        .with_context(|| format!("failed to write {sf2_path:?}"))?;
+                    println!(
+                        "{} instruments and {} samples written to {sf2_path:?}",
+                        soundfont.instruments.len(),
+                        soundfont.samples.len()
+                    );
+                }
+            }
+            SdatCommands::Midi { path, output } => {
+                let data = fs::read(&path).with_context(|| format!("failed to read {path:?}"))?;
+                let midi = sseq::Sseq::parse(&data)
+                    .and_then(|sseq| sseq.to_midi())
+                    .context("failed to convert SSEQ file")?;
+                let output = output.unwrap_or_else(|| path.with_extension("mid"));
+                fs::write(&output, midi).with_context(|| format!("failed to write {output:?}"))?;
+                println!("sequence written to {output:?}");
+            }
+        },
+
+        Commands::Text { command } => match command {
+            TextCommands::Export {
+                path,
+                output,
+                format,
+                template,
+                original,
+                table_format,
+                encoding,
+            } => {
+                let encoding = encoding.load()?;
+                let data = read_maybe_compressed(&path)?;
+                let strings = TextArchive::parse_with(&data, &encoding, table_format)
+                    .context("failed to parse text file")?
+                    .strings;
+                let originals = original
+                    .map(|original| {
+                        let data = read_maybe_compressed(&original)?;
+                        let originals = TextArchive::parse_with(&data, &encoding, table_format)
+                            .context("failed to parse original text file")?
+                            .strings;
+                        if originals.len() != strings.len() {
+                            return Err(anyhow!(
+                                "the original text file has {} strings, not {}",
+                                originals.len(),
+                                strings.len()
+                            ));
+                        }
+                        Ok(originals)
+                    })
+                    .transpose()?;
+                let contents = match template {
+                    Some(template) => {
+                        let file = path.file_name().unwrap_or_default().to_string_lossy();
+                        EntryTemplate::load(&template)?.render(
+                            &file,
+                            &strings,
+                            originals.as_deref(),
+                            &encoding,
+                        )
+                    }
+                    None => format.export(&strings, originals.as_deref()),
+                };
+                let output = output.unwrap_or_else(|| path.with_extension(format.extension()));
+                fs::write(output, contents).context("failed to write exported strings")?;
+                println!("{} strings exported", strings.len());
+            }
+            TextCommands::Pack {
+                path,
+                output,
+                format,
+                encoding,
+                layout,
+                original,
+                table_format,
+                compress,
+            } => {
+                let encoding = encoding.load()?;
+                let format = format
+                    .or_else(|| {
+                        path.extension().and_then(|extension| {
+                            TextFormat::from_extension(&extension.to_string_lossy())
+                        })
+                    })
+                    .unwrap_or(TextFormat::Template);
+                let contents = fs::read_to_string(&path).context("failed to read strings")?;
+                let strings = format
+                    .import(&contents)
+                    .with_context(|| format!("failed to import {path:?}"))?;
+                let archive = match original {
+                    Some(original) => {
+                        let data =
+                            fs::read(original).context("failed to read original text file")?;
+                        let data = decompress_lz10(data.as_slice()).unwrap_or(data);
+                        TextArchive::parse_with(&data, &encoding, table_format)
+                            .context("failed to parse original text file")?
+                            .with_strings(strings)
+                    }
+                    None => TextArchive::new(strings).with_format(table_format.unwrap_or_default()),
+                };
+                let mut data = archive
+                    .to_bytes(&encoding, layout)
+                    .context("failed to build text file")?;
+                if compress {
+                    data = compress_lz10(&data, CompressionLevel::Fast)
+                        .context("failed to compress text file")?;
+                }
+                let output = output.unwrap_or_else(|| path.with_extension("bin"));
+                fs::write(output, data).context("failed to write text file")?;
+                println!("{} strings packed", archive.strings.len());
+            }
+            TextCommands::ExportAll {
+                unpack_dir,
+                output,
+                format,
+                encoding,
+            } => {
+                let encoding = encoding.load()?;
+                let format = format
+                    .or_else(|| {
+                        output.extension().and_then(|extension| {
+                            ScriptFormat::from_extension(&extension.to_string_lossy())
+                        })
+                    })
+                    .unwrap_or(ScriptFormat::Po);
+                let entries = translation::export_script(&unpack_dir, &encoding)?;
+                fs::write(&output, format.export(&entries))
+                    .context("failed to write exported strings")?;
+                let files = entries
+                    .iter()
+                    .map(|(key, _)| &key.file)
+                    .collect::<std::collections::BTreeSet<_>>()
+                    .len();
+                println!("{} strings of {files} text files exported", entries.len());
+            }
+            TextCommands::ImportAll {
+                unpack_dir,
+                path,
+                format,
+                encoding,
+            } => {
+                let encoding = encoding.load()?;
+                let format = format
+                    .or_else(|| {
+                        path.extension().and_then(|extension| {
+                            ScriptFormat::from_extension(&extension.to_string_lossy())
+                        })
+                    })
+                    .unwrap_or(ScriptFormat::Po);
+                let contents = fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read {path:?}"))?;
+                let entries = format
+                    .import(&contents)
+                    .with_context(|| format!("failed to import {path:?}"))?;
+                let import = translation::import_script(&unpack_dir, &encoding, entries)?;
+                println!(
+                    "{} strings changed in {} text files",
+                    import.strings, import.files
+                );
+            }
+            TextCommands::Check {
+                path,
+                format,
+                font,
+                max_width,
+                table_format,
+                encoding,
+            } => {
+                let encoding = encoding.load()?;
+                let font = nftr::Nftr::parse(&read_maybe_compressed(&font)?)
+                    .context("failed to parse font file")?;
+                let format = format.or_else(|| {
+                    path.extension().and_then(|extension| {
+                        TextFormat::from_extension(&extension.to_string_lossy())
+                    })
+                });
+                let strings = match format {
+                    Some(format) => {
+                        let contents =
+                            fs::read_to_string(&path).context("failed to read strings")?;
+                        format
+                            .import(&contents)
+                            .with_context(|| format!("failed to import {path:?}"))?
+                    }
+                    None => {
+                        TextArchive::parse_with(
+                            &read_maybe_compressed(&path)?,
+                            &encoding,
+                            table_format,
+                        )
+                        .context("failed to parse text file")?
+                        .strings
+                    }
+                };
+
+                let mut overflowing_lines = 0;
+                for (index, string) in strings.iter().enumerate() {
+                    let text = encoding.printable_text(string);
+                    let mut missing = Vec::new();
+                    for (line_index, line) in text.lines().enumerate() {
+                        let (width, line_missing) = font.measure(line);
+                        missing.extend(line_missing);
+                        if width > max_width {
+                            overflowing_lines += 1;
+                            println!(
+                                "string {index}, line {}: {width} pixels wide: {line:?}",
+                                line_index + 1
+                            );
+                        }
+                    }
+                    missing.sort_unstable();
+                    missing.dedup();
+                    if !missing.is_empty() {
+                        warn!(
+                            "string {index} has characters missing from the font: {:?}",
+                            missing.into_iter().collect::<String>()
+                        );
+                    }
+                }
+                if overflowing_lines > 0 {
+                    return Err(check_failed(format!(
+                        "{overflowing_lines} lines are wider than {max_width} pixels"
+                    )));
+                }
+                println!(
+                    "all lines of {} strings fit in {max_width} pixels",
+                    strings.len()
+                );
+            }
+            TextCommands::Lint {
+                path,
+                format,
+                original,
+                font,
+                max_width,
+                max_line_bytes,
+                encoding,
+            } => {
+                let encoding = encoding.load()?;
+                let format = format.or_else(|| {
+                    path.extension().and_then(|extension| {
+                        TextFormat::from_extension(&extension.to_string_lossy())
+                    })
+                });
+                let mut entries = match format {
+                    Some(format) => {
+                        let contents =
+                            fs::read_to_string(&path).context("failed to read strings")?;
+                        format
+                            .import_entries(&contents)
+                            .with_context(|| format!("failed to import {path:?}"))?
+                    }
+                    None => parse_text_file(&read_maybe_compressed(&path)?, &encoding)
+                        .context("failed to parse text file")?
+                        .into_iter()
+                        .map(|text| TextEntry {
+                            text,
+                            original: None,
+                            fuzzy: false,
+                        })
+                        .collect(),
+                };
+                if let Some(original) = original {
+                    let originals = parse_text_file(&read_maybe_compressed(&original)?, &encoding)
+                        .context("failed to parse original text file")?;
+                    for (entry, original) in entries.iter_mut().zip(originals) {
+                        entry.original = Some(original);
+                    }
+                }
+                let font = font
+                    .map(|font| {
+                        nftr::Nftr::parse(&read_maybe_compressed(&font)?)
+                            .context("failed to parse font file")
+                    })
+                    .transpose()?;
+                let rules = lint::LintRules {
+                    font,
+                    max_width,
+                    max_line_bytes,
+                };
 
-                    if !dry_run {
-                        fs::File::create(target_entry_path)
-                            .context("failed to create file in target directory")?
-                            .write_all(&file_data)
-                            .context("failed to write file in target directory")?;
+                let issues = lint::lint(&entries, &encoding, &rules);
+                for issue in &issues {
+                    println!("{issue}");
+                }
+                if !issues.is_empty() {
+                    return Err(check_failed(format!("{} issues found", issues.len())));
+                }
+                println!("no issues in {} strings", entries.len());
+            }
+            TextCommands::Status {
+                paths,
+                json,
+                encoding,
+            } => {
+                let encoding = encoding.load()?;
+                let mut statuses = Vec::new();
+                for path in paths {
+                    if path.is_dir() {
+                        statuses.extend(translation::unpack_dir_status(&path, &encoding)?);
+                        continue;
                     }
+                    let format = path
+                        .extension()
+                        .and_then(|extension| {
+                            TextFormat::from_extension(&extension.to_string_lossy())
+                        })
+                        .unwrap_or(TextFormat::Template);
+                    let contents = fs::read_to_string(&path)
+                        .with_context(|| format!("failed to read {path:?}"))?;
+                    let entries = format
+                        .import_entries(&contents)
+                        .with_context(|| format!("failed to import {path:?}"))?;
+                    let status = TranslationStatus::of_entries(&entries);
+                    statuses.push((path.display().to_string(), status));
+                }
+
+                let mut total = TranslationStatus::default();
+                for (_, status) in &statuses {
+                    total.add(*status);
+                }
+                if json {
+                    let counts = |status: TranslationStatus| {
+                        serde_json::json!({
+                            "translated": status.translated,
+                            "fuzzy": status.fuzzy,
+                            "untranslated": status.untranslated,
+                            "total": status.total(),
+                        })
+                    };
+                    let files = statuses
+                        .iter()
+                        .map(|(path, status)| {
+                            let mut file = counts(*status);
+                            file["path"] = path.as_str().into();
+                            file
+                        })
+                        .collect::<Vec<_>>();
+                    let report = serde_json::json!({ "files": files, "total": counts(total) });
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                    return Ok(());
+                }
+                let describe = |status: TranslationStatus| {
+                    format!(
+                        "{} translated, {} fuzzy, {} untranslated ({:.1}% done)",
+                        status.translated,
+                        status.fuzzy,
+                        status.untranslated,
+                        status.percentage()
+                    )
+                };
+                for (path, status) in &statuses {
+                    println!("{path}: {}", describe(*status));
+                }
+                if statuses.len() > 1 {
+                    println!("total: {}", describe(total));
+                }
+            }
+        },
+
+        Commands::Save { command } => match command {
+            SaveCommands::Info { path } => {
+                let (save, format) = read_save(&path)?;
+                println!("format: {}", format.extension());
+                println!("save chip: {} (0x{:X} bytes)", save.chip, save.chip.size);
+                println!("used: 0x{:X} bytes", save.used_size());
+            }
+            SaveCommands::Convert {
+                path,
+                output,
+                format,
+            } => {
+                let format = format
+                    .or_else(|| {
+                        output
+                            .as_ref()
+                            .and_then(|output| output.extension())
+                            .and_then(|extension| {
+                                SaveFormat::from_extension(&extension.to_string_lossy())
+                            })
+                    })
+                    .ok_or_else(|| anyhow!("no format given to convert the save to"))?;
+                let (save, _) = read_save(&path)?;
+                let output = output.unwrap_or_else(|| path.with_extension(format.extension()));
+                fs::write(&output, save.to_bytes(format))
+                    .with_context(|| format!("failed to write {output:?}"))?;
+                println!("{} save written to {output:?}", save.chip);
+            }
+            SaveCommands::Dump {
+                path,
+                offset,
+                size,
+                output,
+            } => {
+                let (save, _) = read_save(&path)?;
+                let offset = offset as usize;
+                let end = match size {
+                    Some(size) => offset.checked_add(size as usize),
+                    None => Some(save.data.len()),
+                };
+                let end = end.ok_or(save::SaveError::OutOfBounds {
+                    range: offset..usize::MAX,
+                    size: save.data.len(),
+                })?;
+                let region = save.region(offset..end)?;
+                fs::write(&output, region)
+                    .with_context(|| format!("failed to write {output:?}"))?;
+                println!("0x{:X} bytes written to {output:?}", region.len());
+            }
+            SaveCommands::Patch {
+                path,
+                offset,
+                file_path,
+                output,
+            } => {
+                let (mut save, format) = read_save(&path)?;
+                let data = fs::read(&file_path)
+                    .with_context(|| format!("failed to read {file_path:?}"))?;
+                save.patch(offset as usize, &data)?;
+                let output = output.unwrap_or(path);
+                fs::write(&output, save.to_bytes(format))
+                    .with_context(|| format!("failed to write {output:?}"))?;
+                println!("0x{:X} bytes written at 0x{offset:X}", data.len());
+            }
+        },
+
+        Commands::Patch { command } => match command {
+            PatchCommands::Create {
+                original,
+                modified,
+                output,
+                format,
+            } => {
+                let format = format
+                    .or_else(|| {
+                        output
+                            .as_ref()
+                            .and_then(|output| output.extension())
+                            .and_then(|extension| {
+                                PatchFormat::from_extension(&extension.to_string_lossy())
+                            })
+                    })
+                    .unwrap_or(PatchFormat::Bps);
+                let original_data = read_rom(&original).context("failed to read original ROM")?;
+                let modified_data = read_rom(&modified).context("failed to read modified ROM")?;
+                let patch = format.create(&original_data, &modified_data);
+                let output = output.unwrap_or_else(|| modified.with_extension(format.extension()));
+                fs::write(output, &patch).context("failed to write patch")?;
+                println!("patch created (0x{:X} bytes)", patch.len());
+            }
+            PatchCommands::Apply {
+                patch,
+                rom,
+                output,
+                dry_run,
+            } => {
+                let patch = fs::read(patch).context("failed to read patch")?;
+                let rom_data = read_rom(&rom).context("failed to read ROM")?;
+                if patch.starts_with(ips::MAGIC) && rom_data.len() > ips::MAX_ADDRESSABLE_SIZE {
+                    warn!(
+                        "the ROM is larger than the 16 MiB IPS patches can address, so it can't modify data past that"
+                    );
+                }
+                let patched = patch::apply(&rom_data, &patch)?;
+                write_rom(&rom, &output, &patched, dry_run)?;
+            }
+        },
+
+        Commands::Init {
+            base_rom,
+            project_dir,
+        } => {
+            let project = project::init(&project_dir, &base_rom)?;
+            println!(
+                "created {:?}; add [[file]] entries to it to replace files of the base ROM",
+                project_dir.join(project::PROJECT_FILE_NAME)
+            );
+            println!("patched ROM will be written to {:?}", project.output);
+        }
+        Commands::Build {
+            project_dir,
+            no_cache,
+            level,
+            watch,
+            dry_run,
+            no_lint,
+        } => {
+            if dry_run {
+                // The cache is left alone, so that nothing at all is written.
+                let project = project::Project::load(&project_dir)?;
+                let rom_data = project::build_rom(
+                    &project_dir,
+                    &project,
+                    &open_cache(&project_dir, true, level),
+                    !no_lint,
+                )?;
+                let output = project_dir.join(&project.output);
+                let original = if output.exists() {
+                    output.clone()
+                } else {
+                    project_dir.join(&project.base_rom)
+                };
+                return write_rom(&original, &output, &rom_data, true);
+            }
+            let build = || {
+                let cache = open_cache(&project_dir, no_cache, level);
+                project::build(&project_dir, &cache, !no_lint)?;
+                cache.prune()
+            };
+            if watch {
+                let project = project::Project::load(&project_dir)?;
+                let ignored = [Some(&project.output), project.patch.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .map(|path| project_dir.join(path))
+                    .chain([project_dir.join(cache::CACHE_DIR)])
+                    .collect::<Vec<_>>();
+                watch::watch(&project_dir, &ignored, build)?;
+            } else {
+                build()?;
+            }
+        }
+
+        Commands::Rom2patch {
+            original,
+            modified,
+            output,
+            name,
+            level,
+            no_lint,
+        } => {
+            let original_data = read_rom(&original).context("failed to read original ROM")?;
+            let (modified_data, default_path) = if modified.is_dir() {
+                let project = project::Project::load(&modified)?;
+                let cache = open_cache(&modified, false, level);
+                let rom_data = project::build_rom(&modified, &project, &cache, !no_lint)?;
+                cache.prune()?;
+                (rom_data, modified.join(&project.output))
+            } else {
+                let rom_data = read_rom(&modified).context("failed to read modified ROM")?;
+                (rom_data, modified.clone())
+            };
+            let name = name.unwrap_or_else(|| {
+                default_path
+                    .file_stem()
+                    .map_or("patch".into(), |stem| stem.to_string_lossy().into_owned())
+            });
+            let output = output.unwrap_or_else(|| {
+                let dir = if modified.is_dir() {
+                    modified.clone()
+                } else {
+                    modified.parent().map(Path::to_path_buf).unwrap_or_default()
                 };
+                dir.join(format!("{name}-release"))
+            });
+            let files = release::bundle(&original_data, &modified_data, &name)?;
+            fs::create_dir_all(&output)
+                .with_context(|| format!("failed to create directory {output:?}"))?;
+            for file in &files {
+                let path = output.join(&file.name);
+                fs::write(&path, &file.data)
+                    .with_context(|| format!("failed to write {path:?}"))?;
+                println!("{} (0x{:X} bytes)", path.display(), file.data.len());
+            }
+        }
+
+        Commands::Pack {
+            fs_path,
+            rom_path,
+            no_cache,
+            level,
+            watch,
+            dedup,
+            align,
+            pad_byte,
+            bios,
+            secure_area,
+            verify,
+            dry_run,
+            stats,
+            check_deterministic,
+            plugins,
+            hooks,
+            profile,
+        } => {
+            let rom_path = rom_path.unwrap_or_else(|| {
+                let mut rom_path = fs_path.clone().into_os_string();
+                rom_path.push(".nds");
+                rom_path.into()
+            });
+
+            // A directory without a header can't be packed anyway, which `pack` reports.
+            let header = fs::read(
+                fs_path
+                    .join(manifest::SYSTEM_DIR)
+                    .join(rom::Section::Header.file_name()),
+            )
+            .unwrap_or_default();
+            let profile = match header.get(rom::GAME_CODE_RANGE) {
+                Some(_) => profile.find(&rom::header_text(&header, rom::GAME_CODE_RANGE))?,
+                None => None,
+            };
+            let options = pack::PackOptions {
+                dedup,
+                alignment: align.or_else(|| profile.as_ref().and_then(|profile| profile.alignment)),
+                pad_byte: pad_byte
+                    .or_else(|| profile.as_ref().and_then(|profile| profile.pad_byte)),
+                key_table: bios.map(|bios| read_key_table(&bios)).transpose()?,
+                secure_area,
+                plugins: Plugins::load(&plugins)?,
+                hooks: Hooks::load(hooks.as_deref())?,
+                profile,
+            };
+            let pack = || {
+                if verify {
+                    let report = hashes::verify(&fs_path)?;
+                    for (change, path) in report.changes() {
+                        info!("{change} since unpacking: {path}");
+                    }
+                    if report.is_clean() {
+                        info!("no files changed since unpacking");
+                    }
+                }
+                // The cache is left alone on dry runs, so that nothing at all is written.
+                let cache = open_cache(&fs_path, no_cache || dry_run, level);
+                let rom_data = pack::pack(&fs_path, &cache, &options)?;
+                cache.prune()?;
+                if check_deterministic {
+                    // Without the cache, compressed files are compressed again, which also
+                    // checks that cached data matches what the compressors output now.
+                    let cache = open_cache(&fs_path, true, level);
+                    let repacked = pack::pack(&fs_path, &cache, &options)?;
+                    if let Some(offset) = rom_data
+                        .iter()
+                        .zip(&repacked)
+                        .position(|(a, b)| a != b)
+                        .or((rom_data.len() != repacked.len())
+                            .then(|| rom_data.len().min(repacked.len())))
+                    {
+                        return Err(check_failed(format!(
+                            "packing twice gave different ROMs, first differing at 0x{offset:X} (sizes: 0x{:X} and 0x{:X})",
+                            rom_data.len(),
+                            repacked.len()
+                        )));
+                    }
+                    info!("packing twice gave byte-identical ROMs");
+                }
+                if stats {
+                    print_size_summary(&rom_data)?;
+                }
+                write_rom(&rom_path, &rom_path, &rom_data, dry_run)
+            };
+            if watch {
+                let ignored = [rom_path.clone(), fs_path.join(cache::CACHE_DIR)];
+                watch::watch(&fs_path, &ignored, pack)?;
+            } else {
+                pack()?;
             }
         }
 
-        Commands::Pack { fs_path, rom_path } => {
-            todo!()
+        Commands::VerifyHashes { fs_path } => {
+            let report = hashes::verify(&fs_path)?;
+            for (change, path) in report.changes() {
+                println!("{change}: {path}");
+            }
+            if !report.is_clean() {
+                return Err(check_failed(format!(
+                    "{} files changed since unpacking",
+                    report.modified.len() + report.added.len() + report.missing.len()
+                )));
+            }
+            println!("all files match their hashes");
         }
     }
 
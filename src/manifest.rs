@@ -0,0 +1,698 @@
+use std::path::Path;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+
+#[cfg(feature = "audio")]
+use crate::sdat::{RecordKind, SoundKind};
+use crate::{
+    cache::CompressionCache,
+    lz10::decompress_lz10,
+    lz11::decompress_lz11,
+    palette::PaletteFormat,
+    rom::{self, ExtraData, Section},
+};
+
+/// Name of the manifest file placed at the root of an unpacked ROM.
+pub const MANIFEST_FILE_NAME: &str = "ravends-manifest.json";
+/// Name of the file holding the hashes of the files of an unpacked ROM, placed at its root.
+pub const HASHES_FILE_NAME: &str = "hashes.json";
+/// Directory inside an unpacked ROM where ravends keeps its own bookkeeping data.
+pub const RAVENDS_DIR: &str = ".ravends";
+/// Directory inside [`RAVENDS_DIR`] holding the pristine copies of converted files.
+pub const ORIGINALS_DIR: &str = "original";
+/// File inside [`RAVENDS_DIR`] holding the original FNT.
+pub const ORIGINAL_FNT_FILE_NAME: &str = "fnt.bin";
+/// Directory inside an unpacked ROM holding the header, code binaries, overlays & banner.
+pub const SYSTEM_DIR: &str = "_sys";
+/// Directory inside an unpacked ROM holding graphics exported to PNG for viewing, which is
+/// ignored when packing.
+pub const GRAPHICS_DIR: &str = "_gfx";
+/// Directory inside [`GRAPHICS_DIR`] holding the icon of the banner.
+pub const ICON_DIR: &str = "icon";
+/// Directory inside an unpacked ROM holding the files extracted from SDAT sound archives for
+/// viewing, which is ignored when packing.
+pub const SOUND_DIR: &str = "_snd";
+/// Directory inside an unpacked ROM holding the files of the FAT no FNT entry or overlay table
+/// references.
+pub const ORPHAN_DIR: &str = "_orphan";
+/// Name of the file placed alongside the files extracted from a NARC archive.
+pub const NARC_MANIFEST_FILE_NAME: &str = "ravends-narc.json";
+/// Name of the file placed alongside the files extracted from an SDAT sound archive.
+pub const SDAT_MANIFEST_FILE_NAME: &str = "ravends-sdat.json";
+
+const MANIFEST_VERSION: u32 = 1;
+
+/// Compression a file was stored with in the ROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    None,
+    Lz10,
+    Lz11,
+}
+
+impl Compression {
+    /// Decompresses data stored with this compression, or returns `None` if it isn't.
+    pub fn decompress(self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Compression::None => Some(data.to_vec()),
+            Compression::Lz10 => decompress_lz10(data).ok(),
+            Compression::Lz11 => decompress_lz11(data).ok(),
+        }
+    }
+
+    /// Name of the algorithm, as shown to users.
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "uncompressed",
+            Compression::Lz10 => "LZ10",
+            Compression::Lz11 => "LZ11",
+        }
+    }
+}
+
+/// Magic numbers some games place before compressed streams, which are kept when recompressing.
+const COMPRESSION_PREFIXES: &[&[u8]] = &[b"LZ77"];
+
+/// How a compressed stream is laid out beyond its algorithm, which the game reading it may rely
+/// on.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompressionHeader {
+    /// Bytes preceding the compressed stream, such as an `LZ77` magic number.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prefix: Vec<u8>,
+    /// Whether the decompressed size follows an LZ11 header in 4 more bytes, even though it
+    /// fits in the header itself.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub extended_size: bool,
+}
+
+impl CompressionHeader {
+    /// Whether the stream is laid out as the compressors write it.
+    pub fn is_standard(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Exactly how a file is compressed: the algorithm and the layout of the stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Codec {
+    pub compression: Compression,
+    pub header: CompressionHeader,
+}
+
+impl Codec {
+    /// Data stored as-is.
+    pub const NONE: Codec = Codec {
+        compression: Compression::None,
+        header: CompressionHeader {
+            prefix: Vec::new(),
+            extended_size: false,
+        },
+    };
+
+    pub fn new(compression: Compression) -> Self {
+        Self {
+            compression,
+            header: CompressionHeader::default(),
+        }
+    }
+
+    /// Detects how the data given is compressed, returning the codec and the decompressed data,
+    /// or `None` if it isn't compressed in a way ravends knows.
+    pub fn detect(data: &[u8]) -> Option<(Codec, Vec<u8>)> {
+        let prefix = COMPRESSION_PREFIXES
+            .iter()
+            .find(|prefix| data.len() > prefix.len() + 4 && data.starts_with(prefix))
+            .map_or(&[][..], |prefix| *prefix);
+        let stream = &data[prefix.len()..];
+        let compression = match stream.first() {
+            Some(0x10) => Compression::Lz10,
+            Some(0x11) => Compression::Lz11,
+            _ => return None,
+        };
+        let decompressed = compression.decompress(stream)?;
+        // Sizes that don't fit in the LZ11 header follow it instead.
+        let size_field = u32::from_le_bytes([stream[1], stream[2], stream[3], 0]);
+        let extended_size = compression == Compression::Lz11 && size_field == 0;
+        let declared_size = if extended_size {
+            rom::u32_at(stream, 4)
+        } else {
+            size_field
+        };
+        // 0x11 is a common first byte, so LZ11 streams, like prefixed ones, must decompress to
+        // the size they declare to be taken as such.
+        if (compression == Compression::Lz11 || !prefix.is_empty())
+            && declared_size as usize != decompressed.len()
+        {
+            return None;
+        }
+        let codec = Codec {
+            compression,
+            header: CompressionHeader {
+                prefix: prefix.to_vec(),
+                extended_size: extended_size && declared_size <= 0xFFFFFF,
+            },
+        };
+        Some((codec, decompressed))
+    }
+
+    /// Decompresses data stored with this codec, or returns `None` if it isn't.
+    pub fn decompress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        self.compression
+            .decompress(data.strip_prefix(self.header.prefix.as_slice())?)
+    }
+
+    /// Compresses data with this codec, through the cache given.
+    pub fn compress(&self, data: &[u8], cache: &CompressionCache) -> anyhow::Result<Vec<u8>> {
+        let mut compressed = cache.compress(data, self.compression)?;
+        if self.compression == Compression::None {
+            return Ok(compressed);
+        }
+        if self.header.extended_size
+            && self.compression == Compression::Lz11
+            && compressed[1..4] != [0; 3]
+        {
+            compressed[1..4].fill(0);
+            compressed.splice(4..4, (data.len() as u32).to_le_bytes());
+        }
+        Ok([self.header.prefix.as_slice(), &compressed].concat())
+    }
+}
+
+impl std::fmt::Display for Codec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.compression.name())?;
+        let mut details = Vec::new();
+        if self.header.extended_size {
+            details.push("extended size".to_owned());
+        }
+        if !self.header.prefix.is_empty() {
+            details.push(format!(
+                "{:?} prefix",
+                String::from_utf8_lossy(&self.header.prefix)
+            ));
+        }
+        if !details.is_empty() {
+            write!(f, " ({})", details.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Compression `pack` stores a file with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompressionPolicy {
+    None,
+    Lz10,
+    Lz11,
+    /// The compression the file had in the original ROM.
+    #[default]
+    AsOriginal,
+}
+
+impl CompressionPolicy {
+    /// The compression to store a file with, given the one it had in the original ROM.
+    pub fn resolve(self, original: Compression) -> Compression {
+        match self {
+            CompressionPolicy::None => Compression::None,
+            CompressionPolicy::Lz10 => Compression::Lz10,
+            CompressionPolicy::Lz11 => Compression::Lz11,
+            CompressionPolicy::AsOriginal => original,
+        }
+    }
+}
+
+/// Compression of the files at the NitroFS paths matching a glob pattern.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompressionRule {
+    /// Glob pattern of the NitroFS paths the rule applies to.
+    pub path: String,
+    pub compression: CompressionPolicy,
+}
+
+/// Format a file was converted to when unpacking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// The file was written as-is (after decompression, if any).
+    Binary,
+    /// The file was converted to the text entry template format.
+    Text,
+    /// The file was a NARC archive, extracted to a directory in its place.
+    Narc,
+    /// The file was converted by the plugin named in its record.
+    Plugin,
+    /// The file was converted by the external command of the hook named in its record.
+    Hook,
+}
+
+/// Processor an overlay is loaded by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Processor {
+    Arm9,
+    Arm7,
+}
+
+/// Record of an overlay file, which has a file ID but no NitroFS path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OverlayRecord {
+    pub file_id: u16,
+    pub processor: Processor,
+    /// Path of the unpacked overlay, relative to the unpack directory.
+    pub unpacked_path: String,
+    /// Location of the overlay in the original ROM.
+    #[serde(default)]
+    pub original_offset: Option<u32>,
+}
+
+/// Record of a file of the FAT that neither the FNT nor an overlay table references, which the
+/// game may still load by its ID.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrphanRecord {
+    pub file_id: u16,
+    /// Path of the unpacked file, relative to the unpack directory.
+    pub unpacked_path: String,
+    /// Location of the file in the original ROM.
+    #[serde(default)]
+    pub original_offset: Option<u32>,
+}
+
+/// Record of a NitroFS directory and its ID.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectoryRecord {
+    pub path: String,
+    pub dir_id: u16,
+}
+
+/// Record of how a single NitroFS file was transformed when unpacking.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileRecord {
+    /// Original NitroFS path of the file.
+    pub path: String,
+    /// Path of the unpacked file, relative to the unpack directory.
+    pub unpacked_path: String,
+    pub file_id: u16,
+    pub compression: Compression,
+    /// Layout of the compressed stream, when it isn't the one the compressors write.
+    #[serde(default, skip_serializing_if = "CompressionHeader::is_standard")]
+    pub compression_header: CompressionHeader,
+    pub format: Format,
+    /// Size of the file as stored in the ROM.
+    pub original_size: u32,
+    /// SHA-256 of the unpacked file as it was written, used to detect edits.
+    pub unpacked_hash: String,
+    /// Location of the file in the original ROM.
+    #[serde(default)]
+    pub original_offset: Option<u32>,
+    /// Name of the plugin the file was converted by, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plugin: Option<String>,
+    /// Name of the hook the file was converted by, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hook: Option<String>,
+}
+
+impl FileRecord {
+    /// Whether the file was transformed in any way when unpacking.
+    pub fn is_converted(&self) -> bool {
+        self.compression != Compression::None || self.format != Format::Binary
+    }
+
+    /// How the file was compressed in the original ROM.
+    pub fn codec(&self) -> Codec {
+        Codec {
+            compression: self.compression,
+            header: self.compression_header.clone(),
+        }
+    }
+}
+
+/// Record of graphics exported to a PNG when unpacking, so that edits to the PNG can be
+/// imported back when packing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphicsRecord {
+    /// Path of the PNG, relative to the unpack directory.
+    pub png_path: String,
+    /// NitroFS path of the NCGR file holding the tiles.
+    pub graphics: String,
+    /// NitroFS path of the NCLR file holding the palette.
+    pub palette: String,
+    /// NitroFS path of the NSCR file arranging the tiles, if the PNG shows a screen.
+    #[serde(default)]
+    pub screen: Option<String>,
+    /// SHA-256 of the PNG as it was written, used to detect edits.
+    pub png_hash: String,
+}
+
+/// Record of the icon of the banner exported when unpacking, so that edits to it can be
+/// imported back when packing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IconRecord {
+    /// Path of the directory holding the icon files, relative to the unpack directory.
+    pub export_dir: String,
+    /// Hash of the icon files imported back as they were written, used to detect edits.
+    pub export_hash: String,
+}
+
+/// Record of a palette exported to an image editor's format when unpacking, so that edits to
+/// it can be imported back when packing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaletteRecord {
+    /// Path of the exported palette, relative to the unpack directory.
+    pub export_path: String,
+    pub format: PaletteFormat,
+    /// NitroFS path of the NCLR file holding the palette.
+    pub palette: String,
+    /// SHA-256 of the exported palette as it was written, used to detect edits.
+    pub export_hash: String,
+}
+
+/// Location of a ROM section in the original ROM.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionRecord {
+    pub section: Section,
+    pub offset: u32,
+}
+
+/// Layout of the original ROM, so that `pack` can place everything back where it was.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayoutRecord {
+    /// Size of the ROM file, including any padding after its used area.
+    pub rom_size: u32,
+    pub sections: Vec<SectionRecord>,
+    pub fnt_offset: u32,
+    pub fat_offset: u32,
+    /// Alignment of the file data of the original ROM.
+    #[serde(default = "default_alignment")]
+    pub alignment: u32,
+    /// Byte filling the space between the file data of the original ROM.
+    #[serde(default = "default_pad_byte")]
+    pub pad_byte: u8,
+    /// Whether the secure area of the original ROM was encrypted and decrypted when unpacking,
+    /// so that it must be encrypted again when packing.
+    #[serde(default)]
+    pub decrypted_secure_area: bool,
+    /// Whether the ARM9 binary of the original ROM was BLZ-compressed, so that it can be
+    /// compressed again if it's given decompressed when packing.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub compressed_arm9: bool,
+    /// Data of the original ROM outside of everything its header and FAT locate.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_data: Vec<ExtraDataRecord>,
+}
+
+/// Record of data outside of everything the header and FAT locate, so that `pack` can put it
+/// back.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtraDataRecord {
+    pub kind: ExtraData,
+    /// Location of the data in the original ROM.
+    pub offset: u32,
+    /// Path of the unpacked data, relative to the unpack directory.
+    pub unpacked_path: String,
+}
+
+fn default_alignment() -> u32 {
+    rom::FILE_ALIGNMENT as u32
+}
+
+fn default_pad_byte() -> u8 {
+    rom::PAD_BYTE
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    #[serde(default)]
+    pub layout: Option<LayoutRecord>,
+    /// Overlays, in the order they appear in the overlay tables (ARM9 first).
+    #[serde(default)]
+    pub overlays: Vec<OverlayRecord>,
+    #[serde(default)]
+    pub directories: Vec<DirectoryRecord>,
+    pub files: Vec<FileRecord>,
+    /// Files of the FAT without a path, unpacked inside [`ORPHAN_DIR`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub orphans: Vec<OrphanRecord>,
+    /// Graphics exported to PNGs inside [`GRAPHICS_DIR`].
+    #[serde(default)]
+    pub graphics: Vec<GraphicsRecord>,
+    /// Palettes exported inside [`GRAPHICS_DIR`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub palettes: Vec<PaletteRecord>,
+    /// Icon of the banner exported inside [`GRAPHICS_DIR`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<IconRecord>,
+    /// Compression `pack` stores files with, by path. The first rule matching a file applies;
+    /// files matching none keep the compression they had in the original ROM.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compression: Vec<CompressionRule>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            layout: None,
+            overlays: Vec::new(),
+            directories: Vec::new(),
+            files: Vec::new(),
+            orphans: Vec::new(),
+            graphics: Vec::new(),
+            palettes: Vec::new(),
+            icon: None,
+            compression: Vec::new(),
+        }
+    }
+}
+
+impl Manifest {
+    /// Reads the manifest of the unpacked ROM at `unpack_dir`, if it has one.
+    pub fn load(unpack_dir: &Path) -> anyhow::Result<Option<Self>> {
+        let path = unpack_dir.join(MANIFEST_FILE_NAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let manifest: Self = serde_json::from_slice(&fs::read(&path)?)
+            .with_context(|| format!("failed to parse manifest at {path:?}"))?;
+        if manifest.version > MANIFEST_VERSION {
+            anyhow::bail!(
+                "manifest version {} is not supported (latest supported: {MANIFEST_VERSION})",
+                manifest.version
+            );
+        }
+        Ok(Some(manifest))
+    }
+
+    /// The compression policy of the file at a NitroFS path, from the first rule matching it.
+    pub fn compression_policy(&self, path: &Path) -> anyhow::Result<CompressionPolicy> {
+        for rule in &self.compression {
+            let filter = rom::PathFilter::new(std::slice::from_ref(&rule.path), &[])
+                .with_context(|| format!("invalid compression rule pattern {:?}", rule.path))?;
+            if filter.matches(path) {
+                return Ok(rule.compression);
+            }
+        }
+        Ok(CompressionPolicy::AsOriginal)
+    }
+
+    pub fn save(&self, unpack_dir: &Path) -> anyhow::Result<()> {
+        fs::write(
+            unpack_dir.join(MANIFEST_FILE_NAME),
+            serde_json::to_vec_pretty(self)?,
+        )
+        .context("failed to write manifest")
+    }
+}
+
+/// Describes the files extracted from a NARC archive, so that it can be rebuilt with the same
+/// file order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NarcManifest {
+    pub version: u32,
+    /// Whether the archive had a file name table with names for its files.
+    pub named: bool,
+    /// Paths of the extracted files relative to the manifest, in file ID order. For named
+    /// archives, these are also the paths of the files inside the archive.
+    pub files: Vec<String>,
+    /// Files that were NARC archives themselves, extracted to a directory in their place.
+    #[serde(default)]
+    pub nested: Vec<NestedNarcRecord>,
+}
+
+/// Record of a NARC archive found inside another one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NestedNarcRecord {
+    /// Path of the nested archive, which is also the directory it was extracted to.
+    pub path: String,
+    pub compression: Compression,
+}
+
+impl NarcManifest {
+    pub fn new(named: bool, files: Vec<String>) -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            named,
+            files,
+            nested: Vec::new(),
+        }
+    }
+
+    /// Reads the NARC manifest in `dir`, if it has one.
+    pub fn load(dir: &Path) -> anyhow::Result<Option<Self>> {
+        let path = dir.join(NARC_MANIFEST_FILE_NAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let manifest: Self = serde_json::from_slice(&fs::read(&path)?)
+            .with_context(|| format!("failed to parse NARC manifest at {path:?}"))?;
+        if manifest.version > MANIFEST_VERSION {
+            anyhow::bail!(
+                "NARC manifest version {} is not supported (latest supported: {MANIFEST_VERSION})",
+                manifest.version
+            );
+        }
+        Ok(Some(manifest))
+    }
+
+    pub fn save(&self, dir: &Path) -> anyhow::Result<()> {
+        fs::write(
+            dir.join(NARC_MANIFEST_FILE_NAME),
+            serde_json::to_vec_pretty(self)?,
+        )
+        .context("failed to write NARC manifest")
+    }
+}
+
+#[cfg(feature = "audio")]
+/// Describes the files extracted from an SDAT sound archive along with the records of its
+/// SYMB and INFO blocks, so that it can be rebuilt from them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SdatManifest {
+    pub version: u32,
+    /// Whether the archive had a SYMB block naming its records.
+    pub named: bool,
+    /// The sound files, sorted by kind and index.
+    pub files: Vec<SdatFileRecord>,
+    /// The players and groups, sorted by kind and index.
+    #[serde(default)]
+    pub records: Vec<SdatInfoRecord>,
+}
+
+#[cfg(feature = "audio")]
+/// Record of a sound file extracted from an SDAT sound archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SdatFileRecord {
+    pub kind: SoundKind,
+    pub index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Path of the extracted file, relative to the manifest.
+    pub path: String,
+    /// ID of the file in the FAT block. Files sharing an ID are stored once while their data
+    /// stays the same.
+    pub file_id: u32,
+    /// For banks, the indices of the wave archives their instruments use, by slot.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wave_archives: Vec<Option<usize>>,
+    /// For sequence archives, the names of their sequences.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sequence_names: Vec<Option<String>>,
+    /// The rest of the file's INFO record, in hex, such as the bank and volume of a sequence.
+    #[serde(with = "hex_bytes")]
+    pub info: Vec<u8>,
+}
+
+#[cfg(feature = "audio")]
+/// Record of a player or group of an SDAT sound archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SdatInfoRecord {
+    pub kind: RecordKind,
+    pub index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The INFO record, in hex.
+    #[serde(with = "hex_bytes")]
+    pub info: Vec<u8>,
+}
+
+#[cfg(feature = "audio")]
+impl SdatManifest {
+    pub fn new(named: bool) -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            named,
+            files: Vec::new(),
+            records: Vec::new(),
+        }
+    }
+
+    /// Reads the SDAT manifest in `dir`, if it has one.
+    pub fn load(dir: &Path) -> anyhow::Result<Option<Self>> {
+        let path = dir.join(SDAT_MANIFEST_FILE_NAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let manifest: Self = serde_json::from_slice(&fs::read(&path)?)
+            .with_context(|| format!("failed to parse SDAT manifest at {path:?}"))?;
+        if manifest.version > MANIFEST_VERSION {
+            anyhow::bail!(
+                "SDAT manifest version {} is not supported (latest supported: {MANIFEST_VERSION})",
+                manifest.version
+            );
+        }
+        Ok(Some(manifest))
+    }
+
+    pub fn save(&self, dir: &Path) -> anyhow::Result<()> {
+        fs::write(
+            dir.join(SDAT_MANIFEST_FILE_NAME),
+            serde_json::to_vec_pretty(self)?,
+        )
+        .context("failed to write SDAT manifest")
+    }
+}
+
+#[cfg(feature = "audio")]
+/// Serializes bytes as a string of hex digits.
+mod hex_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(
+            &bytes
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>(),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        if hex.len() % 2 != 0 {
+            return Err(D::Error::custom("odd number of hex digits"));
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|index| {
+                hex.get(index..index + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    .ok_or_else(|| D::Error::custom(format!("invalid hex digits in {hex:?}")))
+            })
+            .collect()
+    }
+}
+
+/// Returns the SHA-256 of the data given, as a lowercase hex string.
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
@@ -0,0 +1,423 @@
+use std::{fmt, ops::Range};
+
+use thiserror::Error;
+
+use crate::{
+    blz::{self, BlzDecompressionError},
+    manifest::Processor,
+    overlay::{self, OverlayEntry},
+    rom::{self, Section},
+    secure_area,
+};
+
+/// Bytes the module parameters of the ARM9 binary end with: the nitrocode in both byte orders.
+const MODULE_PARAMS_MAGIC: [u8; 8] = [0xDE, 0xC0, 0x06, 0x21, 0x21, 0x06, 0xC0, 0xDE];
+/// Offset of the magic inside the module parameters.
+const MODULE_PARAMS_MAGIC_OFFSET: usize = 0x1C;
+/// Offset of the RAM address autoloaded sections (ITCM & DTCM code) start at in the module
+/// parameters. The static ARM9 code ends there.
+const AUTOLOAD_START_OFFSET: usize = 0x08;
+/// Offset of the RAM address the BSS of the static ARM9 code ends at in the module parameters.
+const STATIC_BSS_END_OFFSET: usize = 0x10;
+/// Offset of the end of the compressed ARM9 binary in the module parameters, or 0 if it isn't
+/// compressed.
+const COMPRESSED_STATIC_END_OFFSET: usize = 0x14;
+/// Size of the start of a compressed ARM9 binary that is left uncompressed: its secure area,
+/// which runs before the rest is decompressed.
+pub const ARM9_UNCOMPRESSED_SIZE: usize = 0x4000;
+
+#[derive(Error, Debug)]
+pub enum MapAddressError {
+    #[error("the ARM9 binary is compressed")]
+    CompressedArm9,
+    #[error("address 0x{address:08X} lies in overlay {overlay_id}, which is compressed")]
+    CompressedOverlay { address: u32, overlay_id: u32 },
+    #[error("address 0x{address:08X} lies in overlays {overlay_ids:?}, which share that memory")]
+    SharedOverlayMemory { address: u32, overlay_ids: Vec<u32> },
+    #[error("address 0x{address:08X} lies in the encrypted secure area; decrypt it first")]
+    EncryptedSecureArea { address: u32 },
+    #[error("the ROM has no ARM9 overlay {0}")]
+    NoSuchOverlay(u32),
+    #[error("0x{len:X} bytes at 0x{address:08X} are not in the static ARM9 code or any overlay")]
+    Unmapped { address: u32, len: usize },
+    #[error("the {0} lies outside of the ROM")]
+    OutOfBounds(&'static str),
+    #[error("failed to decompress the {0}")]
+    Decompression(String, #[source] BlzDecompressionError),
+}
+
+/// A binary of the ROM that code is loaded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binary {
+    Arm9,
+    Arm7,
+    /// An ARM9 overlay, by ID.
+    Overlay(u32),
+}
+
+impl fmt::Display for Binary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Binary::Arm9 => f.write_str("ARM9"),
+            Binary::Arm7 => f.write_str("ARM7"),
+            Binary::Overlay(overlay_id) => write!(f, "overlay {overlay_id}"),
+        }
+    }
+}
+
+/// Finds the RAM address a binary is loaded to and its location in the ROM. Compressed binaries
+/// are an error, as their contents aren't what gets loaded.
+pub fn binary_location(
+    rom_data: &[u8],
+    binary: Binary,
+) -> Result<(u32, Range<usize>), MapAddressError> {
+    match binary {
+        Binary::Arm9 => {
+            arm9_static_code(rom_data)?;
+            let range = Section::Arm9
+                .range(rom_data)
+                .ok_or(MapAddressError::OutOfBounds("ARM9 binary"))?;
+            Ok((rom::u32_at(rom_data, rom::ARM9_RAM_ADDR_OFFSET), range))
+        }
+        Binary::Arm7 => {
+            let range = Section::Arm7
+                .range(rom_data)
+                .ok_or(MapAddressError::OutOfBounds("ARM7 binary"))?;
+            Ok((rom::u32_at(rom_data, rom::ARM7_RAM_ADDR_OFFSET), range))
+        }
+        Binary::Overlay(overlay_id) => {
+            let entry = overlay_entry(rom_data, overlay_id)?;
+            if entry.compressed {
+                return Err(MapAddressError::CompressedOverlay {
+                    address: entry.ram_address,
+                    overlay_id,
+                });
+            }
+            let file = overlay::file_range(rom_data, &entry)
+                .map_err(|_| MapAddressError::OutOfBounds("overlay file"))?;
+            Ok((entry.ram_address, file))
+        }
+    }
+}
+
+/// Range of RAM the static code of the ARM9 binary is loaded to, and the offset of the binary
+/// in the ROM.
+fn arm9_static_code(rom_data: &[u8]) -> Result<(Range<u32>, usize), MapAddressError> {
+    let rom_offset = rom::u32_at(rom_data, rom::ARM9_ADDR_OFFSET) as usize;
+    let ram_start = rom::u32_at(rom_data, rom::ARM9_RAM_ADDR_OFFSET);
+    let mut ram_end = ram_start.saturating_add(rom::u32_at(rom_data, rom::ARM9_SIZE_OFFSET));
+    let Some(arm9) = Section::Arm9.range(rom_data).map(|range| &rom_data[range]) else {
+        return Ok((0..0, rom_offset));
+    };
+    // Homebrew binaries have no module parameters, and load whole.
+    if let Some(params) = module_params(arm9) {
+        if rom::u32_at(arm9, params + COMPRESSED_STATIC_END_OFFSET) != 0 {
+            return Err(MapAddressError::CompressedArm9);
+        }
+        let autoload_start = rom::u32_at(arm9, params + AUTOLOAD_START_OFFSET);
+        if (ram_start..ram_end).contains(&autoload_start) {
+            ram_end = autoload_start;
+        }
+    }
+    Ok((ram_start..ram_end, rom_offset))
+}
+
+/// Finds the offset of the module parameters in the ARM9 binary, if it has them.
+fn module_params(arm9: &[u8]) -> Option<usize> {
+    arm9.windows(MODULE_PARAMS_MAGIC.len())
+        .position(|window| window == MODULE_PARAMS_MAGIC)
+        .filter(|&pos| pos >= MODULE_PARAMS_MAGIC_OFFSET)
+        .map(|pos| pos - MODULE_PARAMS_MAGIC_OFFSET)
+}
+
+/// Offset in the ARM9 binary of the field of its module parameters holding the end of its
+/// compressed part, or `None` if it has no module parameters, as homebrew binaries don't.
+pub fn compressed_end_field(arm9: &[u8]) -> Option<usize> {
+    module_params(arm9).map(|params| params + COMPRESSED_STATIC_END_OFFSET)
+}
+
+/// The contents of a binary as loaded into RAM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadedBinary {
+    /// RAM address the binary is loaded at.
+    pub address: u32,
+    pub data: Vec<u8>,
+    /// Offset of the binary in the ROM, unless it's compressed, in which case its contents
+    /// aren't stored as they are loaded.
+    pub rom_offset: Option<usize>,
+}
+
+/// Reads a binary as it's loaded into RAM, decompressing it if it's compressed. Only the static
+/// code of the ARM9 binary is read, as the autoloaded sections following it are copied
+/// elsewhere.
+pub fn loaded_binary(rom_data: &[u8], binary: Binary) -> Result<LoadedBinary, MapAddressError> {
+    let decompress = |name: String, data: &[u8]| {
+        blz::decompress_blz(data).map_err(|error| MapAddressError::Decompression(name, error))
+    };
+    match binary {
+        Binary::Arm9 => {
+            let range = Section::Arm9
+                .range(rom_data)
+                .ok_or(MapAddressError::OutOfBounds("ARM9 binary"))?;
+            let address = rom::u32_at(rom_data, rom::ARM9_RAM_ADDR_OFFSET);
+            let mut data = rom_data[range.clone()].to_vec();
+            let mut rom_offset = Some(range.start);
+            if let Some(params) = module_params(&data) {
+                let compressed_end = rom::u32_at(&data, params + COMPRESSED_STATIC_END_OFFSET);
+                if compressed_end != 0 {
+                    let compressed_size =
+                        (compressed_end.saturating_sub(address) as usize).min(data.len());
+                    let mut decompressed =
+                        decompress("ARM9 binary".to_owned(), &data[..compressed_size])?;
+                    decompressed.extend_from_slice(&data[compressed_size..]);
+                    data = decompressed;
+                    rom_offset = None;
+                }
+                let autoload_start = rom::u32_at(&data, params + AUTOLOAD_START_OFFSET);
+                if let Some(static_size) = autoload_start
+                    .checked_sub(address)
+                    .filter(|&size| (size as usize) < data.len())
+                {
+                    data.truncate(static_size as usize);
+                }
+            }
+            Ok(LoadedBinary {
+                address,
+                data,
+                rom_offset,
+            })
+        }
+        Binary::Arm7 => {
+            let (address, range) = binary_location(rom_data, binary)?;
+            Ok(LoadedBinary {
+                address,
+                data: rom_data[range.clone()].to_vec(),
+                rom_offset: Some(range.start),
+            })
+        }
+        Binary::Overlay(overlay_id) => {
+            let entry = overlay_entry(rom_data, overlay_id)?;
+            let address = entry.ram_address;
+            let file = overlay::file_range(rom_data, &entry)
+                .map_err(|_| MapAddressError::OutOfBounds("overlay file"))?;
+            if entry.compressed {
+                Ok(LoadedBinary {
+                    address,
+                    data: decompress(format!("overlay {overlay_id}"), &rom_data[file])?,
+                    rom_offset: None,
+                })
+            } else {
+                Ok(LoadedBinary {
+                    address,
+                    data: rom_data[file.clone()].to_vec(),
+                    rom_offset: Some(file.start),
+                })
+            }
+        }
+    }
+}
+
+/// A location of memory as one of the binaries of a ROM is loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapping {
+    pub binary: Binary,
+    /// RAM address of the location.
+    pub address: u32,
+    /// Offset of the location from the start of the binary, as loaded.
+    pub binary_offset: usize,
+    /// Offset of the location in the ROM, unless the binary is compressed.
+    pub rom_offset: Option<usize>,
+}
+
+/// Memory a binary is loaded to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MappedBinary {
+    pub binary: Binary,
+    /// Range of RAM the binary is loaded to. The static code of the ARM9 binary only, as the
+    /// autoloaded sections following it are copied elsewhere.
+    pub ram: Range<u32>,
+    /// Offset of the binary in the ROM, unless it's compressed.
+    pub rom_offset: Option<usize>,
+}
+
+/// Translates between offsets in the ROM and the RAM addresses they are loaded to, taking the
+/// load address of the ARM9 and ARM7 binaries and of each ARM9 overlay into account.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddressMap {
+    binaries: Vec<MappedBinary>,
+}
+
+impl AddressMap {
+    /// Maps the ARM9 binary, the ARM7 binary and the ARM9 overlays of a ROM. Compressed
+    /// binaries are decompressed to find their size.
+    pub fn new(rom_data: &[u8]) -> Result<Self, MapAddressError> {
+        let mut binaries = vec![Binary::Arm9, Binary::Arm7];
+        binaries.extend(
+            overlay::overlay_table(rom_data, Processor::Arm9)
+                .into_iter()
+                .map(|entry| Binary::Overlay(entry.id)),
+        );
+        let binaries = binaries
+            .into_iter()
+            .map(|binary| {
+                let loaded = loaded_binary(rom_data, binary)?;
+                Ok(MappedBinary {
+                    binary,
+                    ram: loaded.address..loaded.address.saturating_add(loaded.data.len() as u32),
+                    rom_offset: loaded.rom_offset,
+                })
+            })
+            .collect::<Result<_, MapAddressError>>()?;
+        Ok(Self { binaries })
+    }
+
+    /// The binaries mapped, the ARM9 binary first, then the ARM7 binary and the overlays.
+    pub fn binaries(&self) -> &[MappedBinary] {
+        &self.binaries
+    }
+
+    /// Finds what the RAM address given is loaded from. As overlays may share memory, an
+    /// address may be loaded from several of them.
+    pub fn to_rom(&self, address: u32) -> Vec<Mapping> {
+        self.binaries
+            .iter()
+            .filter(|mapped| mapped.ram.contains(&address))
+            .map(|mapped| {
+                let binary_offset = (address - mapped.ram.start) as usize;
+                Mapping {
+                    binary: mapped.binary,
+                    address,
+                    binary_offset,
+                    rom_offset: mapped.rom_offset.map(|start| start + binary_offset),
+                }
+            })
+            .collect()
+    }
+
+    /// Finds the RAM address the offset in the ROM given is loaded to, unless it lies in no
+    /// binary or in a compressed one.
+    pub fn to_ram(&self, rom_offset: usize) -> Option<Mapping> {
+        self.binaries.iter().find_map(|mapped| {
+            let start = mapped.rom_offset?;
+            let binary_offset = rom_offset.checked_sub(start)?;
+            (binary_offset < mapped.ram.len()).then(|| Mapping {
+                binary: mapped.binary,
+                address: mapped.ram.start + binary_offset as u32,
+                binary_offset,
+                rom_offset: Some(rom_offset),
+            })
+        })
+    }
+}
+
+/// Finds the RAM address past the memory the startup code of the ARM9 binary writes to: the
+/// binary itself and the BSS of its static code. The binary must not be compressed.
+pub fn arm9_end_address(rom_data: &[u8]) -> Result<u32, MapAddressError> {
+    arm9_static_code(rom_data)?;
+    let ram_start = rom::u32_at(rom_data, rom::ARM9_RAM_ADDR_OFFSET);
+    let end = ram_start.saturating_add(rom::u32_at(rom_data, rom::ARM9_SIZE_OFFSET));
+    let bss_end = Section::Arm9
+        .range(rom_data)
+        .and_then(|range| {
+            let arm9 = &rom_data[range];
+            module_params(arm9).map(|params| rom::u32_at(arm9, params + STATIC_BSS_END_OFFSET))
+        })
+        .unwrap_or(0);
+    Ok(end.max(bss_end))
+}
+
+/// Finds the offset in the ROM that `len` bytes at the RAM address given are loaded from.
+///
+/// Addresses are looked up in the static ARM9 code first, then in the ARM9 overlays. As
+/// overlays may share memory, the one to look in can be given; otherwise, the address must lie
+/// in a single overlay.
+pub fn rom_offset(
+    rom_data: &[u8],
+    address: u32,
+    len: usize,
+    overlay_id: Option<u32>,
+) -> Result<usize, MapAddressError> {
+    let end = address.saturating_add(len as u32);
+    let offset = if let Some(offset) = arm9_offset(rom_data, address, end, overlay_id)? {
+        offset
+    } else {
+        overlay_offset(rom_data, address, end, overlay_id)?
+    };
+    if let Some(encrypted) = secure_area::encrypted_range(rom_data) {
+        if offset < encrypted.end && encrypted.start < offset + len {
+            return Err(MapAddressError::EncryptedSecureArea { address });
+        }
+    }
+    Ok(offset)
+}
+
+fn arm9_offset(
+    rom_data: &[u8],
+    address: u32,
+    end: u32,
+    overlay_id: Option<u32>,
+) -> Result<Option<usize>, MapAddressError> {
+    if overlay_id.is_some() {
+        return Ok(None);
+    }
+    let (arm9_ram, arm9_offset) = arm9_static_code(rom_data)?;
+    Ok((arm9_ram.start <= address && end <= arm9_ram.end)
+        .then(|| arm9_offset + (address - arm9_ram.start) as usize))
+}
+
+fn overlay_offset(
+    rom_data: &[u8],
+    address: u32,
+    end: u32,
+    overlay_id: Option<u32>,
+) -> Result<usize, MapAddressError> {
+    let unmapped = MapAddressError::Unmapped {
+        address,
+        len: (end - address) as usize,
+    };
+    let entry = if let Some(overlay_id) = overlay_id {
+        overlay_entry(rom_data, overlay_id)?
+    } else {
+        let overlays = overlay::overlay_table(rom_data, Processor::Arm9)
+            .into_iter()
+            .filter(|entry| {
+                let ram_end = entry.ram_address.saturating_add(entry.ram_size);
+                entry.ram_address <= address && end <= ram_end
+            })
+            .collect::<Vec<_>>();
+        match overlays[..] {
+            [] => return Err(unmapped),
+            [entry] => entry,
+            _ => {
+                return Err(MapAddressError::SharedOverlayMemory {
+                    address,
+                    overlay_ids: overlays.iter().map(|entry| entry.id).collect(),
+                })
+            }
+        }
+    };
+    if entry.compressed {
+        return Err(MapAddressError::CompressedOverlay {
+            address,
+            overlay_id: entry.id,
+        });
+    }
+
+    let Some(offset_in_overlay) = address.checked_sub(entry.ram_address) else {
+        return Err(unmapped);
+    };
+    match overlay::file_range(rom_data, &entry) {
+        Ok(file) if offset_in_overlay as usize + (end - address) as usize <= file.len() => {
+            Ok(file.start + offset_in_overlay as usize)
+        }
+        _ => Err(unmapped),
+    }
+}
+
+/// Finds the entry of an ARM9 overlay in the overlay table.
+fn overlay_entry(rom_data: &[u8], overlay_id: u32) -> Result<OverlayEntry, MapAddressError> {
+    overlay::overlay_table(rom_data, Processor::Arm9)
+        .into_iter()
+        .find(|entry| entry.id == overlay_id)
+        .ok_or(MapAddressError::NoSuchOverlay(overlay_id))
+}
@@ -0,0 +1,295 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsStr,
+    ops::Range,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use fuser::{
+    Config, Errno, FileAttr, FileHandle, FileType, Filesystem, Generation, INodeNo, LockOwner,
+    MountOption, OpenFlags, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry,
+    ReplyXattr, Request,
+};
+use rayon::prelude::*;
+
+use crate::{lz10::decompress_lz10, rom};
+
+/// Extension of the virtual files holding the decompressed contents of LZ10-compressed files.
+const DECOMPRESSED_EXTENSION: &str = "decomp";
+/// How long the kernel may cache attributes and lookups. The ROM never changes while mounted.
+const TTL: Duration = Duration::from_secs(60);
+
+enum Node {
+    Dir {
+        parent: INodeNo,
+        children: BTreeMap<String, INodeNo>,
+    },
+    /// A NitroFS file, as stored in the ROM.
+    File { range: Range<usize> },
+    /// The decompressed contents of an LZ10-compressed file.
+    Decompressed { range: Range<usize>, size: usize },
+}
+
+/// The NitroFS of a ROM as a read-only FUSE filesystem.
+struct RomFs {
+    rom_data: Vec<u8>,
+    /// Nodes by inode number minus one; the root directory comes first.
+    nodes: Vec<Node>,
+    /// Contents of the decompressed files read so far.
+    decompressed: Mutex<HashMap<INodeNo, Arc<Vec<u8>>>>,
+}
+
+impl RomFs {
+    fn new(rom_data: Vec<u8>) -> anyhow::Result<Self> {
+        let fs = rom::filesystem(&rom_data)?;
+        let mut nodes = vec![Node::Dir {
+            parent: INodeNo::ROOT,
+            children: BTreeMap::new(),
+        }];
+        // Directories are added separately so that empty ones are shown too.
+        for dir in fs.dirs.values() {
+            dir_node(&mut nodes, &rom::nitro_path(&dir.path));
+        }
+
+        let files = fs.files();
+        // Files are tried for LZ10 compression up front, so that listings show the
+        // decompressed siblings with the right size.
+        let decompressed_sizes = files
+            .par_iter()
+            .map(|entry| {
+                decompress_lz10(rom::file_data(&rom_data, entry))
+                    .ok()
+                    .map(|data| data.len())
+            })
+            .collect::<Vec<_>>();
+        for (entry, decompressed_size) in files.iter().zip(decompressed_sizes) {
+            let path = rom::nitro_path(&entry.path);
+            let (parent, name) = path.rsplit_once('/').unwrap_or(("", &path));
+            let parent = dir_node(&mut nodes, parent);
+            let range = entry.alloc.start as usize..entry.alloc.end as usize;
+            let mut add = |name: String, node: Node| {
+                nodes.push(node);
+                let ino = INodeNo(nodes.len() as u64);
+                if let Node::Dir { children, .. } = &mut nodes[(u64::from(parent) - 1) as usize] {
+                    children.insert(name, ino);
+                }
+            };
+            add(
+                name.to_owned(),
+                Node::File {
+                    range: range.clone(),
+                },
+            );
+            if let Some(size) = decompressed_size {
+                add(
+                    format!("{name}.{DECOMPRESSED_EXTENSION}"),
+                    Node::Decompressed { range, size },
+                );
+            }
+        }
+        Ok(Self {
+            rom_data,
+            nodes,
+            decompressed: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn node(&self, ino: INodeNo) -> Option<&Node> {
+        self.nodes.get(u64::from(ino).checked_sub(1)? as usize)
+    }
+
+    fn attr(&self, request: &Request, ino: INodeNo) -> Option<FileAttr> {
+        let (kind, size, perm, nlink) = match self.node(ino)? {
+            Node::Dir { .. } => (FileType::Directory, 0, 0o555, 2),
+            Node::File { range } => (FileType::RegularFile, range.len(), 0o444, 1),
+            Node::Decompressed { size, .. } => (FileType::RegularFile, *size, 0o444, 1),
+        };
+        Some(FileAttr {
+            ino,
+            size: size as u64,
+            blocks: (size as u64).div_ceil(512),
+            atime: UNIX_EPOCH,
+            mtime: UNIX_EPOCH,
+            ctime: UNIX_EPOCH,
+            crtime: UNIX_EPOCH,
+            kind,
+            perm,
+            nlink,
+            uid: request.uid(),
+            gid: request.gid(),
+            rdev: 0,
+            flags: 0,
+            blksize: 512,
+        })
+    }
+
+    /// Decompressed contents of a `.decomp` sibling, decompressing it on first read.
+    fn decompressed(&self, ino: INodeNo, range: &Range<usize>) -> Result<Arc<Vec<u8>>, Errno> {
+        let mut cache = self.decompressed.lock().unwrap();
+        if let Some(data) = cache.get(&ino) {
+            return Ok(data.clone());
+        }
+        let data =
+            Arc::new(decompress_lz10(&self.rom_data[range.clone()]).map_err(|_| Errno::EIO)?);
+        cache.insert(ino, data.clone());
+        Ok(data)
+    }
+}
+
+/// Finds the node of a directory by path, creating it and its parents if needed.
+fn dir_node(nodes: &mut Vec<Node>, path: &str) -> INodeNo {
+    let mut ino = INodeNo::ROOT;
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        let Node::Dir { children, .. } = &nodes[(u64::from(ino) - 1) as usize] else {
+            unreachable!("files never have children");
+        };
+        ino = match children.get(name) {
+            Some(&child) => child,
+            None => {
+                nodes.push(Node::Dir {
+                    parent: ino,
+                    children: BTreeMap::new(),
+                });
+                let child = INodeNo(nodes.len() as u64);
+                if let Node::Dir { children, .. } = &mut nodes[(u64::from(ino) - 1) as usize] {
+                    children.insert(name.to_owned(), child);
+                }
+                child
+            }
+        };
+    }
+    ino
+}
+
+impl Filesystem for RomFs {
+    fn lookup(&self, request: &Request, parent: INodeNo, name: &OsStr, reply: ReplyEntry) {
+        let child = match (self.node(parent), name.to_str()) {
+            (Some(Node::Dir { children, .. }), Some(name)) => children.get(name).copied(),
+            _ => None,
+        };
+        match child.and_then(|child| self.attr(request, child)) {
+            Some(attr) => reply.entry(&TTL, &attr, Generation(0)),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn getattr(
+        &self,
+        request: &Request,
+        ino: INodeNo,
+        _file_handle: Option<FileHandle>,
+        reply: ReplyAttr,
+    ) {
+        match self.attr(request, ino) {
+            Some(attr) => reply.attr(&TTL, &attr),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn read(
+        &self,
+        _request: &Request,
+        ino: INodeNo,
+        _file_handle: FileHandle,
+        offset: u64,
+        size: u32,
+        _flags: OpenFlags,
+        _lock_owner: Option<LockOwner>,
+        reply: ReplyData,
+    ) {
+        let slice = |data: &[u8]| {
+            let start = (offset as usize).min(data.len());
+            let end = start.saturating_add(size as usize).min(data.len());
+            start..end
+        };
+        match self.node(ino) {
+            Some(Node::File { range }) => {
+                let data = &self.rom_data[range.clone()];
+                reply.data(&data[slice(data)]);
+            }
+            Some(Node::Decompressed { range, .. }) => match self.decompressed(ino, range) {
+                Ok(data) => reply.data(&data[slice(&data)]),
+                Err(error) => reply.error(error),
+            },
+            Some(Node::Dir { .. }) => reply.error(Errno::EISDIR),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    // Implemented only so that fuser doesn't log every call to them as unimplemented.
+    fn flush(
+        &self,
+        _request: &Request,
+        _ino: INodeNo,
+        _file_handle: FileHandle,
+        _lock_owner: LockOwner,
+        reply: ReplyEmpty,
+    ) {
+        reply.ok();
+    }
+
+    fn getxattr(
+        &self,
+        _request: &Request,
+        _ino: INodeNo,
+        _name: &OsStr,
+        _size: u32,
+        reply: ReplyXattr,
+    ) {
+        reply.error(Errno::ENODATA);
+    }
+
+    fn listxattr(&self, _request: &Request, _ino: INodeNo, size: u32, reply: ReplyXattr) {
+        if size == 0 {
+            reply.size(0);
+        } else {
+            reply.data(&[]);
+        }
+    }
+
+    fn readdir(
+        &self,
+        _request: &Request,
+        ino: INodeNo,
+        _file_handle: FileHandle,
+        offset: u64,
+        mut reply: ReplyDirectory,
+    ) {
+        let Some(Node::Dir { parent, children }) = self.node(ino) else {
+            reply.error(Errno::ENOTDIR);
+            return;
+        };
+        let entries = [(ino, "."), (*parent, "..")]
+            .into_iter()
+            .chain(children.iter().map(|(name, &child)| (child, name.as_str())));
+        for (index, (child, name)) in entries.enumerate().skip(offset as usize) {
+            let kind = match self.node(child) {
+                Some(Node::Dir { .. }) => FileType::Directory,
+                _ => FileType::RegularFile,
+            };
+            // The offset given is that of the entry after this one.
+            if reply.add(child, (index + 1) as u64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+/// Mounts the NitroFS of a ROM read-only at `mount_point`, blocking until it's unmounted.
+/// LZ10-compressed files have a sibling with the `.decomp` extension holding their
+/// decompressed contents.
+pub fn mount(rom_data: Vec<u8>, mount_point: &Path) -> anyhow::Result<()> {
+    let fs = RomFs::new(rom_data)?;
+    let mut config = Config::default();
+    config.mount_options.extend([
+        MountOption::RO,
+        MountOption::FSName("ravends".to_owned()),
+        MountOption::Subtype("nitrofs".to_owned()),
+    ]);
+    fuser::mount(fs, mount_point, &config)
+        .with_context(|| format!("failed to mount the ROM at {mount_point:?}"))
+}
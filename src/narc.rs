@@ -0,0 +1,399 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::Context;
+use thiserror::Error;
+
+use crate::{
+    cache::CompressionCache,
+    fnt::{self, BuildFntError, ParseFntError},
+    manifest::{Compression, NarcManifest, NestedNarcRecord, NARC_MANIFEST_FILE_NAME},
+    pack, path_escape, unpack,
+};
+
+const NARC_MAGIC: &[u8; 4] = b"NARC";
+const BTAF_MAGIC: &[u8; 4] = b"BTAF";
+const BTNF_MAGIC: &[u8; 4] = b"BTNF";
+const GMIF_MAGIC: &[u8; 4] = b"GMIF";
+const BYTE_ORDER_MARK: u16 = 0xFFFE;
+const VERSION: u16 = 0x0100;
+const HEADER_SIZE: usize = 0x10;
+/// Alignment of each file's data inside the GMIF section, unless another one is asked for.
+pub const FILE_ALIGNMENT: usize = 4;
+
+#[derive(Error, Debug)]
+pub enum ParseNarcError {
+    #[error("magic number does not match (expected: NARC)")]
+    MagicNumberMismatch,
+    #[error("NARC data is truncated")]
+    Truncated,
+    #[error("required section {0} not found")]
+    MissingSection(&'static str),
+    #[error("file {file_id} points outside of the file data section")]
+    InvalidFileBounds { file_id: usize },
+    #[error("invalid file name table")]
+    Fnt(#[from] ParseFntError),
+}
+
+#[derive(Error, Debug)]
+pub enum BuildNarcError {
+    #[error("too many files for a NARC (found: {0}, maximum: 65535)")]
+    TooManyFiles(usize),
+    #[error("either all or none of the files of a NARC must be named")]
+    MixedNames,
+    #[error("failed to build file name table")]
+    Fnt(#[from] BuildFntError),
+    #[error("file alignment must be a power of two of at least 4 (found: {0})")]
+    InvalidAlignment(usize),
+}
+
+/// A file inside a NARC archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NarcFile {
+    /// Path of the file inside the archive, or `None` if the archive is nameless.
+    pub name: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// A Nitro Archive: a container of files, optionally with names, used by many NDS games.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Narc {
+    /// The files in the archive, in file ID order.
+    pub files: Vec<NarcFile>,
+}
+
+/// Checks whether the data given starts with a NARC header.
+pub fn is_narc(data: &[u8]) -> bool {
+    data.starts_with(NARC_MAGIC) && data.get(4..6) == Some(BYTE_ORDER_MARK.to_le_bytes().as_slice())
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, ParseNarcError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseNarcError::Truncated)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, ParseNarcError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseNarcError::Truncated)
+}
+
+impl Narc {
+    pub fn parse(data: &[u8]) -> Result<Self, ParseNarcError> {
+        if !is_narc(data) {
+            return Err(ParseNarcError::MagicNumberMismatch);
+        }
+        let header_size = u16_at(data, 0xC)? as usize;
+        let section_count = u16_at(data, 0xE)?;
+
+        let mut sections = BTreeMap::new();
+        let mut offset = header_size;
+        for _ in 0..section_count {
+            let magic: [u8; 4] = data
+                .get(offset..offset + 4)
+                .ok_or(ParseNarcError::Truncated)?
+                .try_into()
+                .unwrap();
+            let size = u32_at(data, offset + 4)? as usize;
+            let contents = data
+                .get(offset + 8..offset + size.max(8))
+                .ok_or(ParseNarcError::Truncated)?;
+            sections.insert(magic, contents);
+            offset += size.max(8);
+        }
+        let section = |magic: &[u8; 4], name| {
+            sections
+                .get(magic)
+                .copied()
+                .ok_or(ParseNarcError::MissingSection(name))
+        };
+        let btaf = section(BTAF_MAGIC, "BTAF")?;
+        let btnf = section(BTNF_MAGIC, "BTNF")?;
+        let gmif = section(GMIF_MAGIC, "GMIF")?;
+
+        let names = fnt::parse_fnt(btnf)?
+            .files
+            .into_iter()
+            .map(|(path, file_id)| (file_id as usize, path))
+            .collect::<BTreeMap<_, _>>();
+
+        let file_count = u16_at(btaf, 0)? as usize;
+        let files = (0..file_count)
+            .map(|file_id| {
+                let start = u32_at(btaf, 4 + file_id * 8)? as usize;
+                let end = u32_at(btaf, 8 + file_id * 8)? as usize;
+                let data = gmif
+                    .get(start..end)
+                    .ok_or(ParseNarcError::InvalidFileBounds { file_id })?;
+                Ok(NarcFile {
+                    name: names.get(&file_id).cloned(),
+                    data: data.to_vec(),
+                })
+            })
+            .collect::<Result<Vec<_>, ParseNarcError>>()?;
+
+        Ok(Self { files })
+    }
+
+    /// Whether the files of this archive have names.
+    pub fn is_named(&self) -> bool {
+        self.files.iter().any(|file| file.name.is_some())
+    }
+
+    /// Serializes the archive. Named archives may have their files reordered, since the files
+    /// of each directory must have consecutive IDs.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, BuildNarcError> {
+        self.to_bytes_aligned(FILE_ALIGNMENT)
+    }
+
+    /// Serializes the archive like [`Narc::to_bytes`], aligning the data of each file to
+    /// `alignment` bytes, which must be a power of two of at least 4.
+    pub fn to_bytes_aligned(&self, alignment: usize) -> Result<Vec<u8>, BuildNarcError> {
+        if !alignment.is_power_of_two() || alignment < FILE_ALIGNMENT {
+            return Err(BuildNarcError::InvalidAlignment(alignment));
+        }
+        if self.files.len() > u16::MAX as usize {
+            return Err(BuildNarcError::TooManyFiles(self.files.len()));
+        }
+
+        let (fnt_data, files) = if self.is_named() {
+            let names = self
+                .files
+                .iter()
+                .map(|file| file.name.as_deref().ok_or(BuildNarcError::MixedNames))
+                .collect::<Result<Vec<_>, _>>()?;
+            let built_fnt = fnt::build_fnt(
+                names
+                    .iter()
+                    .enumerate()
+                    .map(|(file_id, name)| (*name, Some(file_id as u16))),
+                &BTreeMap::new(),
+                0,
+            )?;
+            let by_name = self
+                .files
+                .iter()
+                .map(|file| (file.name.as_deref().unwrap(), file))
+                .collect::<BTreeMap<_, _>>();
+            let files = built_fnt
+                .file_ids
+                .iter()
+                .map(|(path, _)| by_name[path.as_str()])
+                .collect::<Vec<_>>();
+            (built_fnt.data, files)
+        } else {
+            // Nameless archives only have a root directory with no entries.
+            let mut fnt_data = Vec::new();
+            fnt_data.extend_from_slice(&4u32.to_le_bytes());
+            fnt_data.extend_from_slice(&0u16.to_le_bytes());
+            fnt_data.extend_from_slice(&1u16.to_le_bytes());
+            (fnt_data, self.files.iter().collect())
+        };
+
+        let mut fat = Vec::with_capacity(files.len() * 8);
+        let mut gmif = Vec::new();
+        for file in &files {
+            gmif.resize(gmif.len().next_multiple_of(alignment), 0xFF);
+            fat.extend_from_slice(&(gmif.len() as u32).to_le_bytes());
+            gmif.extend_from_slice(&file.data);
+            fat.extend_from_slice(&(gmif.len() as u32).to_le_bytes());
+        }
+        gmif.resize(gmif.len().next_multiple_of(FILE_ALIGNMENT), 0xFF);
+
+        let mut btaf = Vec::with_capacity(fat.len() + 4);
+        btaf.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        btaf.extend_from_slice(&0u16.to_le_bytes());
+        btaf.extend_from_slice(&fat);
+
+        let mut btnf = fnt_data;
+        btnf.resize(btnf.len().next_multiple_of(4), 0xFF);
+
+        let sections = [(BTAF_MAGIC, btaf), (BTNF_MAGIC, btnf), (GMIF_MAGIC, gmif)];
+        let total_size = HEADER_SIZE
+            + sections
+                .iter()
+                .map(|(_, contents)| contents.len() + 8)
+                .sum::<usize>();
+
+        let mut output = Vec::with_capacity(total_size);
+        output.extend_from_slice(NARC_MAGIC);
+        output.extend_from_slice(&BYTE_ORDER_MARK.to_le_bytes());
+        output.extend_from_slice(&VERSION.to_le_bytes());
+        output.extend_from_slice(&(total_size as u32).to_le_bytes());
+        output.extend_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        output.extend_from_slice(&(sections.len() as u16).to_le_bytes());
+        for (magic, contents) in sections {
+            output.extend_from_slice(magic);
+            output.extend_from_slice(&(contents.len() as u32 + 8).to_le_bytes());
+            output.extend_from_slice(&contents);
+        }
+        Ok(output)
+    }
+}
+
+/// Opens the data given as a NARC archive, decompressing it first if needed.
+pub fn open_container(data: &[u8]) -> Option<(Compression, Narc)> {
+    if is_narc(data) {
+        return Narc::parse(data).ok().map(|narc| (Compression::None, narc));
+    }
+    [Compression::Lz10, Compression::Lz11]
+        .into_iter()
+        .find_map(|compression| {
+            let decompressed_data = compression.decompress(data)?;
+            is_narc(&decompressed_data).then_some((compression, decompressed_data))
+        })
+        .and_then(|(compression, decompressed_data)| {
+            Narc::parse(&decompressed_data)
+                .ok()
+                .map(|narc| (compression, narc))
+        })
+}
+
+fn compress(
+    data: Vec<u8>,
+    compression: Compression,
+    cache: &CompressionCache,
+) -> anyhow::Result<Vec<u8>> {
+    cache
+        .compress(&data, compression)
+        .context("failed to compress nested NARC")
+}
+
+/// Name a file of a nameless archive is extracted to.
+pub fn nameless_file_name(file_id: usize) -> String {
+    format!("{file_id:04}.bin")
+}
+
+/// Extracts every file of the archive to `target_path`, along with a NARC manifest describing
+/// them. If `recursive` is set, archives found inside are extracted to a directory in their
+/// place, and so on.
+pub fn extract(narc: &Narc, target_path: &Path, recursive: bool) -> anyhow::Result<()> {
+    let named = narc.is_named();
+    let mut manifest = NarcManifest::new(named, Vec::with_capacity(narc.files.len()));
+    for (file_id, file) in narc.files.iter().enumerate() {
+        let path = match &file.name {
+            Some(name) => name.clone(),
+            None if named => anyhow::bail!("file {file_id} has no name in a named NARC"),
+            None => nameless_file_name(file_id),
+        };
+        let file_path = target_path.join(path_escape::escape(&path));
+        match open_container(&file.data).filter(|_| recursive) {
+            Some((compression, nested)) => {
+                extract(&nested, &file_path, true)?;
+                manifest.nested.push(NestedNarcRecord {
+                    path: path.clone(),
+                    compression,
+                });
+            }
+            None => unpack::write_file(&file_path, &file.data)?,
+        }
+        manifest.files.push(path);
+    }
+    manifest.save(target_path)
+}
+
+/// Builds an archive out of the files in `dir`, rebuilding nested archives recorded in its NARC
+/// manifest. Without a NARC manifest, every file in the directory is added by name, in path
+/// order.
+pub fn from_dir(dir: &Path, cache: &CompressionCache) -> anyhow::Result<Narc> {
+    let manifest = match NarcManifest::load(dir)? {
+        Some(manifest) => manifest,
+        None => NarcManifest::new(
+            true,
+            pack::walk_files(dir, &[NARC_MANIFEST_FILE_NAME])?
+                .iter()
+                .map(|path| path_escape::nitro_path(path))
+                .collect(),
+        ),
+    };
+
+    let files = manifest
+        .files
+        .iter()
+        .map(|path| {
+            let nested = manifest.nested.iter().find(|record| &record.path == path);
+            let file_path = dir.join(path_escape::escape(path));
+            let data = match nested {
+                Some(record) => compress(rebuild(&file_path, cache)?, record.compression, cache)?,
+                None => fs::read(path_escape::long_path(&file_path))
+                    .with_context(|| format!("failed to read {path:?}"))?,
+            };
+            Ok(NarcFile {
+                name: manifest.named.then(|| path.clone()),
+                data,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(Narc { files })
+}
+
+/// Builds an archive out of every file in `dir`, ignoring any NARC manifest, compressing each
+/// with `compression`. Named archives hold the files under their paths. Nameless archives hold
+/// them by position: in the order of the number their names start with if they all start with
+/// one, as in `0.bin` or `0012_title.bin`, or else in path order.
+pub fn create(
+    dir: &Path,
+    named: bool,
+    compression: Compression,
+    cache: &CompressionCache,
+) -> anyhow::Result<Narc> {
+    let mut paths = pack::walk_files(dir, &[NARC_MANIFEST_FILE_NAME])?;
+    if !named {
+        let numbers = paths
+            .iter()
+            .map(|path| leading_number(path))
+            .collect::<Option<Vec<_>>>();
+        if let Some(numbers) = numbers {
+            let mut numbered = numbers.into_iter().zip(paths).collect::<Vec<_>>();
+            numbered.sort();
+            paths = numbered.into_iter().map(|(_, path)| path).collect();
+        }
+    }
+
+    let files = paths
+        .iter()
+        .map(|path| {
+            let data = fs::read(path_escape::long_path(&dir.join(path)))
+                .with_context(|| format!("failed to read {path:?}"))?;
+            Ok(NarcFile {
+                name: named.then(|| path_escape::nitro_path(path)),
+                data: cache
+                    .compress(&data, compression)
+                    .with_context(|| format!("failed to compress {path:?}"))?,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(Narc { files })
+}
+
+/// The number the file name of a path starts with, if it starts with one.
+fn leading_number(path: &Path) -> Option<u64> {
+    let name = path.file_name()?.to_str()?;
+    let digits = name.len() - name.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    name[..digits].parse().ok()
+}
+
+/// Builds the uncompressed archive data for the files in `dir`. See [`from_dir`].
+pub fn rebuild(dir: &Path, cache: &CompressionCache) -> anyhow::Result<Vec<u8>> {
+    from_dir(dir, cache)?
+        .to_bytes()
+        .with_context(|| format!("failed to build NARC from {dir:?}"))
+}
+
+/// Returns the uncompressed data [`rebuild`] would produce for the archive given after
+/// extracting it with [`extract`].
+pub fn rebuilt_bytes(
+    narc: &Narc,
+    recursive: bool,
+    cache: &CompressionCache,
+) -> anyhow::Result<Vec<u8>> {
+    let mut narc = narc.clone();
+    if recursive {
+        for file in &mut narc.files {
+            if let Some((compression, nested)) = open_container(&file.data) {
+                file.data = compress(rebuilt_bytes(&nested, true, cache)?, compression, cache)?;
+            }
+        }
+    }
+    Ok(narc.to_bytes()?)
+}
@@ -0,0 +1,703 @@
+use std::{collections::BTreeMap, io::Cursor};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const NFTR_MAGIC: &[u8; 4] = b"RTFN";
+const FINF_MAGIC: &[u8; 4] = b"FNIF";
+const CGLP_MAGIC: &[u8; 4] = b"PLGC";
+const CWDH_MAGIC: &[u8; 4] = b"HDWC";
+const CMAP_MAGIC: &[u8; 4] = b"PAMC";
+const BYTE_ORDER_MARK: u16 = 0xFEFF;
+const HEADER_SIZE: usize = 0x10;
+/// Size of the header of every section.
+const SECTION_HEADER_SIZE: usize = 0x8;
+/// Size of the font information section, without the glyph metrics added in version 1.2.
+const FINF_SIZE: usize = 0x1C;
+/// Size of the headers of the glyph, width & character map sections.
+const CGLP_HEADER_SIZE: usize = 0x10;
+const CWDH_HEADER_SIZE: usize = 0x10;
+const CMAP_HEADER_SIZE: usize = 0x14;
+/// Character map types.
+const CMAP_DIRECT: u16 = 0;
+const CMAP_TABLE: u16 = 1;
+const CMAP_SCAN: u16 = 2;
+/// Glyph index of characters missing from table character maps.
+const NO_GLYPH: u16 = 0xFFFF;
+/// Number of glyphs per row of a glyph sheet.
+const SHEET_COLUMNS: usize = 16;
+
+#[derive(Error, Debug)]
+pub enum ParseFontError {
+    #[error("magic number does not match (expected: RTFN)")]
+    MagicNumberMismatch,
+    #[error("font data is truncated")]
+    Truncated,
+    #[error("required section {0} not found")]
+    MissingSection(&'static str),
+    #[error("unsupported bit depth of {0} bits per pixel")]
+    UnsupportedBitDepth(u8),
+    #[error("unknown character map type {0}")]
+    UnknownMapType(u16),
+}
+
+#[derive(Error, Debug)]
+pub enum ImportFontError {
+    #[error("failed to decode PNG")]
+    Decoding(#[from] png::DecodingError),
+    #[error("the glyph sheet is {found_width}x{found_height} pixels, but should be {expected_width}x{expected_height}")]
+    SheetSizeMismatch {
+        expected_width: usize,
+        expected_height: usize,
+        found_width: usize,
+        found_height: usize,
+    },
+    #[error("unsupported bit depth of {0} bits per pixel")]
+    UnsupportedBitDepth(u8),
+    #[error("too many glyphs (found: {0}, maximum: 65535)")]
+    TooManyGlyphs(usize),
+    #[error("character {0:?} cannot be represented in the font's encoding")]
+    UnencodableCharacter(String),
+    #[error("character {0:?} is mapped to more than one glyph")]
+    DuplicateCharacter(String),
+}
+
+/// How the character codes of a font are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FontEncoding {
+    Utf8,
+    Utf16,
+    ShiftJis,
+    Cp1252,
+}
+
+impl FontEncoding {
+    fn from_raw(value: u8) -> Self {
+        match value {
+            0 => FontEncoding::Utf8,
+            2 => FontEncoding::ShiftJis,
+            3 => FontEncoding::Cp1252,
+            _ => FontEncoding::Utf16,
+        }
+    }
+
+    fn to_raw(self) -> u8 {
+        match self {
+            FontEncoding::Utf8 => 0,
+            FontEncoding::Utf16 => 1,
+            FontEncoding::ShiftJis => 2,
+            FontEncoding::Cp1252 => 3,
+        }
+    }
+
+    /// Converts a character code to the character it stands for, or to its value in
+    /// hexadecimal if it doesn't stand for a single character.
+    pub fn code_to_string(self, code: u16) -> String {
+        let decoded = match self {
+            FontEncoding::Utf8 | FontEncoding::Utf16 => {
+                char::from_u32(code.into()).map(String::from)
+            }
+            FontEncoding::ShiftJis | FontEncoding::Cp1252 => {
+                let bytes = if code < 0x100 {
+                    vec![code as u8]
+                } else {
+                    code.to_be_bytes().to_vec()
+                };
+                let encoding = if self == FontEncoding::ShiftJis {
+                    encoding_rs::SHIFT_JIS
+                } else {
+                    encoding_rs::WINDOWS_1252
+                };
+                encoding
+                    .decode_without_bom_handling_and_without_replacement(&bytes)
+                    .map(|string| string.into_owned())
+            }
+        };
+        decoded
+            .filter(|string| string.chars().count() == 1)
+            .unwrap_or_else(|| format!("0x{code:04X}"))
+    }
+
+    /// Converts a single character, or a character code in hexadecimal (`0x` followed by
+    /// digits), to a character code.
+    pub fn string_to_code(self, string: &str) -> Option<u16> {
+        if let Some(hex) = string.strip_prefix("0x").filter(|hex| !hex.is_empty()) {
+            return u16::from_str_radix(hex, 16).ok();
+        }
+        let mut chars = string.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else {
+            return None;
+        };
+        match self {
+            FontEncoding::Utf8 | FontEncoding::Utf16 => u16::try_from(u32::from(c)).ok(),
+            FontEncoding::ShiftJis | FontEncoding::Cp1252 => {
+                let encoding = if self == FontEncoding::ShiftJis {
+                    encoding_rs::SHIFT_JIS
+                } else {
+                    encoding_rs::WINDOWS_1252
+                };
+                let (bytes, _, had_errors) = encoding.encode(string);
+                match (had_errors, &*bytes) {
+                    (false, &[byte]) => Some(byte.into()),
+                    (false, &[high, low]) => Some(u16::from_be_bytes([high, low])),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// The position & spacing of a glyph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GlyphWidth {
+    /// Space left before the glyph's bitmap.
+    pub left: i8,
+    /// Width of the glyph's bitmap.
+    pub width: u8,
+    /// How far the next character is drawn from this one.
+    pub advance: u8,
+}
+
+/// A character of a font.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glyph {
+    pub width: GlyphWidth,
+    /// One value per pixel, from 0 (blank) to the maximum value of the font's bit depth.
+    pub pixels: Vec<u8>,
+}
+
+/// A bitmap font (NFTR).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nftr {
+    pub version: u16,
+    pub font_type: u8,
+    pub line_height: u8,
+    /// Glyph drawn for characters missing from the font.
+    pub fallback_glyph: u16,
+    /// Width of glyphs missing from the width table.
+    pub default_width: GlyphWidth,
+    pub encoding: FontEncoding,
+    /// Glyph metrics added in version 1.2: height, width, ascent & left bearing.
+    pub metrics: Option<[u8; 4]>,
+    pub cell_width: u8,
+    pub cell_height: u8,
+    pub baseline: u8,
+    pub max_width: u8,
+    pub bits_per_pixel: u8,
+    pub rotation: u8,
+    pub glyphs: Vec<Glyph>,
+    /// Glyph index of each character code.
+    pub char_map: BTreeMap<u16, u16>,
+}
+
+fn u8_at(data: &[u8], offset: usize) -> Result<u8, ParseFontError> {
+    data.get(offset).copied().ok_or(ParseFontError::Truncated)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, ParseFontError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseFontError::Truncated)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, ParseFontError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseFontError::Truncated)
+}
+
+/// Finds the section an offset from the font information points to. Offsets point right
+/// after the section's header, and 0 means there is no such section.
+fn linked_section(data: &[u8], offset: u32, magic: &[u8; 4]) -> Option<usize> {
+    let section = (offset as usize).checked_sub(SECTION_HEADER_SIZE)?;
+    (data.get(section..section + 4)? == magic).then_some(section)
+}
+
+fn check_bits_per_pixel(bits_per_pixel: u8) -> bool {
+    matches!(bits_per_pixel, 1 | 2 | 4 | 8)
+}
+
+/// Adds a section to a file being built, padding it to a multiple of 4 bytes, and returns its
+/// offset.
+fn push_section(data: &mut Vec<u8>, magic: &[u8; 4], contents: &[u8]) -> usize {
+    let offset = data.len();
+    let size = (SECTION_HEADER_SIZE + contents.len()).next_multiple_of(4);
+    data.extend_from_slice(magic);
+    data.extend_from_slice(&(size as u32).to_le_bytes());
+    data.extend_from_slice(contents);
+    data.resize(offset + size, 0);
+    offset
+}
+
+impl Nftr {
+    pub fn parse(data: &[u8]) -> Result<Self, ParseFontError> {
+        if !data.starts_with(NFTR_MAGIC) {
+            return Err(ParseFontError::MagicNumberMismatch);
+        }
+        let version = u16_at(data, 0x6)?;
+        let info = u16_at(data, 0xC)? as usize;
+        if data.get(info..info + 4) != Some(FINF_MAGIC) {
+            return Err(ParseFontError::MissingSection("FINF"));
+        }
+        let info_size = u32_at(data, info + 0x4)? as usize;
+        let default_width = GlyphWidth {
+            left: u8_at(data, info + 0xC)? as i8,
+            width: u8_at(data, info + 0xD)?,
+            advance: u8_at(data, info + 0xE)?,
+        };
+        let metrics = if info_size >= FINF_SIZE + 4 {
+            let metrics = data
+                .get(info + FINF_SIZE..info + FINF_SIZE + 4)
+                .ok_or(ParseFontError::Truncated)?;
+            Some(metrics.try_into().unwrap())
+        } else {
+            None
+        };
+
+        let glyph_section = linked_section(data, u32_at(data, info + 0x10)?, CGLP_MAGIC)
+            .ok_or(ParseFontError::MissingSection("CGLP"))?;
+        let cell_width = u8_at(data, glyph_section + 0x8)?;
+        let cell_height = u8_at(data, glyph_section + 0x9)?;
+        let glyph_size = u16_at(data, glyph_section + 0xA)? as usize;
+        let bits_per_pixel = u8_at(data, glyph_section + 0xE)?;
+        if !check_bits_per_pixel(bits_per_pixel) {
+            return Err(ParseFontError::UnsupportedBitDepth(bits_per_pixel));
+        }
+        let glyph_section_size = u32_at(data, glyph_section + 0x4)? as usize;
+        let glyph_data = data
+            .get(glyph_section + CGLP_HEADER_SIZE..glyph_section + glyph_section_size)
+            .ok_or(ParseFontError::Truncated)?;
+        let pixel_count = cell_width as usize * cell_height as usize;
+        let max_value = (1u16 << bits_per_pixel) - 1;
+        let mut glyphs = glyph_data
+            .chunks_exact(glyph_size.max(1))
+            .map(|bitmap| Glyph {
+                width: default_width,
+                // Pixels are packed from the most significant bit.
+                pixels: (0..pixel_count)
+                    .map(|pixel| {
+                        let bit = pixel * bits_per_pixel as usize;
+                        let byte = bitmap.get(bit / 8).copied().unwrap_or(0) as u16;
+                        ((byte >> (8 - bits_per_pixel as usize - bit % 8)) & max_value) as u8
+                    })
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+
+        let mut widths_offset = u32_at(data, info + 0x14)?;
+        while let Some(widths) = linked_section(data, widths_offset, CWDH_MAGIC) {
+            let first = u16_at(data, widths + 0x8)? as usize;
+            let last = u16_at(data, widths + 0xA)? as usize;
+            for index in first..=last {
+                let entry = widths + CWDH_HEADER_SIZE + (index - first) * 3;
+                if let Some(glyph) = glyphs.get_mut(index) {
+                    glyph.width = GlyphWidth {
+                        left: u8_at(data, entry)? as i8,
+                        width: u8_at(data, entry + 1)?,
+                        advance: u8_at(data, entry + 2)?,
+                    };
+                }
+            }
+            widths_offset = u32_at(data, widths + 0xC)?;
+        }
+
+        let mut char_map = BTreeMap::new();
+        let mut map_offset = u32_at(data, info + 0x18)?;
+        while let Some(map) = linked_section(data, map_offset, CMAP_MAGIC) {
+            let first = u16_at(data, map + 0x8)?;
+            let last = u16_at(data, map + 0xA)?;
+            let map_data = map + CMAP_HEADER_SIZE;
+            match u16_at(data, map + 0xC)? {
+                CMAP_DIRECT => {
+                    let first_glyph = u16_at(data, map_data)?;
+                    for code in first..=last {
+                        char_map.insert(code, first_glyph + (code - first));
+                    }
+                }
+                CMAP_TABLE => {
+                    for code in first..=last {
+                        let glyph = u16_at(data, map_data + (code - first) as usize * 2)?;
+                        if glyph != NO_GLYPH {
+                            char_map.insert(code, glyph);
+                        }
+                    }
+                }
+                CMAP_SCAN => {
+                    for index in 0..u16_at(data, map_data)? as usize {
+                        let entry = map_data + 2 + index * 4;
+                        char_map.insert(u16_at(data, entry)?, u16_at(data, entry + 2)?);
+                    }
+                }
+                map_type => return Err(ParseFontError::UnknownMapType(map_type)),
+            }
+            map_offset = u32_at(data, map + 0x10)?;
+        }
+
+        Ok(Self {
+            version,
+            font_type: u8_at(data, info + 0x8)?,
+            line_height: u8_at(data, info + 0x9)?,
+            fallback_glyph: u16_at(data, info + 0xA)?,
+            default_width,
+            encoding: FontEncoding::from_raw(u8_at(data, info + 0xF)?),
+            metrics,
+            cell_width,
+            cell_height,
+            baseline: u8_at(data, glyph_section + 0xC)?,
+            max_width: u8_at(data, glyph_section + 0xD)?,
+            bits_per_pixel,
+            rotation: u8_at(data, glyph_section + 0xF)?,
+            glyphs,
+            char_map,
+        })
+    }
+
+    /// Builds the font file. Widths are written as a single table, and the character map as
+    /// a single list of characters with their glyphs.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![0; HEADER_SIZE];
+        let info_size = if self.metrics.is_some() {
+            FINF_SIZE + 4
+        } else {
+            FINF_SIZE
+        };
+        let info = push_section(
+            &mut data,
+            FINF_MAGIC,
+            &vec![0; info_size - SECTION_HEADER_SIZE],
+        );
+
+        let pixel_count = self.cell_width as usize * self.cell_height as usize;
+        let glyph_size = (pixel_count * self.bits_per_pixel as usize).div_ceil(8);
+        let mut glyphs = vec![
+            self.cell_width,
+            self.cell_height,
+            glyph_size as u8,
+            (glyph_size >> 8) as u8,
+            self.baseline,
+            self.max_width,
+            self.bits_per_pixel,
+            self.rotation,
+        ];
+        for glyph in &self.glyphs {
+            let mut bitmap = vec![0; glyph_size];
+            for (pixel, &value) in glyph.pixels.iter().enumerate().take(pixel_count) {
+                let bit = pixel * self.bits_per_pixel as usize;
+                bitmap[bit / 8] |= value << (8 - self.bits_per_pixel as usize - bit % 8);
+            }
+            glyphs.extend(bitmap);
+        }
+        let glyph_section = push_section(&mut data, CGLP_MAGIC, &glyphs);
+
+        let mut widths = Vec::new();
+        widths.extend_from_slice(&0u16.to_le_bytes());
+        widths.extend_from_slice(&(self.glyphs.len().saturating_sub(1) as u16).to_le_bytes());
+        widths.extend_from_slice(&0u32.to_le_bytes());
+        for glyph in &self.glyphs {
+            widths.extend_from_slice(&[
+                glyph.width.left as u8,
+                glyph.width.width,
+                glyph.width.advance,
+            ]);
+        }
+        let widths_section = push_section(&mut data, CWDH_MAGIC, &widths);
+
+        let first = self.char_map.keys().next().copied().unwrap_or(0);
+        let last = self.char_map.keys().next_back().copied().unwrap_or(0);
+        let mut map = Vec::new();
+        map.extend_from_slice(&first.to_le_bytes());
+        map.extend_from_slice(&last.to_le_bytes());
+        map.extend_from_slice(&CMAP_SCAN.to_le_bytes());
+        map.extend_from_slice(&[0; 6]);
+        map.extend_from_slice(&(self.char_map.len() as u16).to_le_bytes());
+        for (&code, &glyph) in &self.char_map {
+            map.extend_from_slice(&code.to_le_bytes());
+            map.extend_from_slice(&glyph.to_le_bytes());
+        }
+        let map_section = push_section(&mut data, CMAP_MAGIC, &map);
+
+        data[info + 0x8] = self.font_type;
+        data[info + 0x9] = self.line_height;
+        data[info + 0xA..info + 0xC].copy_from_slice(&self.fallback_glyph.to_le_bytes());
+        data[info + 0xC] = self.default_width.left as u8;
+        data[info + 0xD] = self.default_width.width;
+        data[info + 0xE] = self.default_width.advance;
+        data[info + 0xF] = self.encoding.to_raw();
+        for (field, section) in [
+            (0x10, glyph_section),
+            (0x14, widths_section),
+            (0x18, map_section),
+        ] {
+            let offset = (section + SECTION_HEADER_SIZE) as u32;
+            data[info + field..info + field + 4].copy_from_slice(&offset.to_le_bytes());
+        }
+        if let Some(metrics) = self.metrics {
+            data[info + FINF_SIZE..info + FINF_SIZE + 4].copy_from_slice(&metrics);
+        }
+
+        let file_size = data.len() as u32;
+        data[0x0..0x4].copy_from_slice(NFTR_MAGIC);
+        data[0x4..0x6].copy_from_slice(&BYTE_ORDER_MARK.to_le_bytes());
+        data[0x6..0x8].copy_from_slice(&self.version.to_le_bytes());
+        data[0x8..0xC].copy_from_slice(&file_size.to_le_bytes());
+        data[0xC..0xE].copy_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        data[0xE..0x10].copy_from_slice(&4u16.to_le_bytes());
+        data
+    }
+
+    /// Finds the glyph drawn for a character, if the font has one.
+    pub fn glyph(&self, ch: char) -> Option<&Glyph> {
+        let code = self.encoding.string_to_code(ch.encode_utf8(&mut [0; 4]))?;
+        self.glyphs.get(*self.char_map.get(&code)? as usize)
+    }
+
+    /// Measures the width in pixels of a line of text, as the sum of the advances of its
+    /// characters. Characters missing from the font are drawn with the fallback glyph, and are
+    /// returned along with the width.
+    pub fn measure(&self, line: &str) -> (usize, Vec<char>) {
+        let mut width = 0;
+        let mut missing = Vec::new();
+        for ch in line.chars() {
+            let glyph = self.glyph(ch).or_else(|| {
+                missing.push(ch);
+                self.glyphs.get(self.fallback_glyph as usize)
+            });
+            width += glyph
+                .map_or(self.default_width, |glyph| glyph.width)
+                .advance as usize;
+        }
+        (width, missing)
+    }
+
+    /// Size in pixels of the sheet the glyphs are drawn to, each glyph being surrounded by a
+    /// 1-pixel grid.
+    fn sheet_size(&self, glyph_count: usize) -> (usize, usize) {
+        let rows = glyph_count.div_ceil(SHEET_COLUMNS).max(1);
+        (
+            SHEET_COLUMNS * (self.cell_width as usize + 1) + 1,
+            rows * (self.cell_height as usize + 1) + 1,
+        )
+    }
+
+    /// Position of the top left pixel of a glyph in the sheet.
+    fn sheet_position(&self, glyph: usize) -> (usize, usize) {
+        (
+            glyph % SHEET_COLUMNS * (self.cell_width as usize + 1) + 1,
+            glyph / SHEET_COLUMNS * (self.cell_height as usize + 1) + 1,
+        )
+    }
+
+    /// Draws every glyph to an indexed PNG, 16 glyphs per row, from white for blank pixels to
+    /// black for pixels of the highest value. The grid between glyphs is drawn in red, unless
+    /// the font uses every palette index.
+    pub fn to_sheet_png(&self) -> Result<Vec<u8>, png::EncodingError> {
+        let max_value = (1usize << self.bits_per_pixel) - 1;
+        let grid_index = if max_value < u8::MAX as usize {
+            max_value as u8 + 1
+        } else {
+            0
+        };
+        let (width, height) = self.sheet_size(self.glyphs.len());
+        let mut pixels = vec![grid_index; width * height];
+        for (index, glyph) in self.glyphs.iter().enumerate() {
+            let (left, top) = self.sheet_position(index);
+            for (pixel, &value) in glyph.pixels.iter().enumerate() {
+                let (x, y) = (
+                    pixel % self.cell_width as usize,
+                    pixel / self.cell_width as usize,
+                );
+                pixels[(top + y) * width + left + x] = value;
+            }
+        }
+        // Blank cells past the last glyph keep the grid color.
+        let mut palette = (0..=max_value)
+            .flat_map(|value| [(255 - value * 255 / max_value) as u8; 3])
+            .collect::<Vec<_>>();
+        if grid_index != 0 {
+            palette.extend_from_slice(&[0xFF, 0x40, 0x40]);
+        }
+
+        let mut png_data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png_data, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(palette);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&pixels)?;
+        writer.finish()?;
+        Ok(png_data)
+    }
+
+    /// Describes the font & its glyphs, for editing alongside the glyph sheet.
+    pub fn description(&self) -> FontDescription {
+        let mut chars = vec![Vec::new(); self.glyphs.len()];
+        for (&code, &glyph) in &self.char_map {
+            if let Some(chars) = chars.get_mut(glyph as usize) {
+                chars.push(self.encoding.code_to_string(code));
+            }
+        }
+        FontDescription {
+            version: self.version,
+            font_type: self.font_type,
+            line_height: self.line_height,
+            fallback_glyph: self.fallback_glyph,
+            default_width: self.default_width,
+            encoding: self.encoding,
+            metrics: self.metrics,
+            cell_width: self.cell_width,
+            cell_height: self.cell_height,
+            baseline: self.baseline,
+            max_width: self.max_width,
+            bits_per_pixel: self.bits_per_pixel,
+            rotation: self.rotation,
+            glyphs: self
+                .glyphs
+                .iter()
+                .zip(chars)
+                .map(|(glyph, chars)| GlyphDescription {
+                    chars,
+                    width: glyph.width,
+                })
+                .collect(),
+        }
+    }
+
+    /// Rebuilds a font from its description and glyph sheet, as exported by
+    /// [`Nftr::description`] and [`Nftr::to_sheet_png`]. Sheets may be saved in any color
+    /// format: pixels are read by brightness, and transparent ones are blank.
+    pub fn import(
+        description: &FontDescription,
+        sheet_png: &[u8],
+    ) -> Result<Self, ImportFontError> {
+        if !check_bits_per_pixel(description.bits_per_pixel) {
+            return Err(ImportFontError::UnsupportedBitDepth(
+                description.bits_per_pixel,
+            ));
+        }
+        if description.glyphs.len() > u16::MAX as usize {
+            return Err(ImportFontError::TooManyGlyphs(description.glyphs.len()));
+        }
+        let mut char_map = BTreeMap::new();
+        for (index, glyph) in description.glyphs.iter().enumerate() {
+            for string in &glyph.chars {
+                let code = description
+                    .encoding
+                    .string_to_code(string)
+                    .ok_or_else(|| ImportFontError::UnencodableCharacter(string.clone()))?;
+                if char_map.insert(code, index as u16).is_some() {
+                    return Err(ImportFontError::DuplicateCharacter(string.clone()));
+                }
+            }
+        }
+        let mut font = Self {
+            version: description.version,
+            font_type: description.font_type,
+            line_height: description.line_height,
+            fallback_glyph: description.fallback_glyph,
+            default_width: description.default_width,
+            encoding: description.encoding,
+            metrics: description.metrics,
+            cell_width: description.cell_width,
+            cell_height: description.cell_height,
+            baseline: description.baseline,
+            max_width: description.max_width,
+            bits_per_pixel: description.bits_per_pixel,
+            rotation: description.rotation,
+            glyphs: Vec::new(),
+            char_map,
+        };
+
+        let mut decoder = png::Decoder::new(Cursor::new(sheet_png));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size().unwrap_or_default()];
+        let output = reader.next_frame(&mut buffer)?;
+        let (width, height) = (output.width as usize, output.height as usize);
+        let (expected_width, expected_height) = font.sheet_size(description.glyphs.len());
+        if (width, height) != (expected_width, expected_height) {
+            return Err(ImportFontError::SheetSizeMismatch {
+                expected_width,
+                expected_height,
+                found_width: width,
+                found_height: height,
+            });
+        }
+        let channels = output.color_type.samples();
+        let max_value = (1u32 << font.bits_per_pixel) - 1;
+        let values = buffer[..output.buffer_size()]
+            .chunks_exact(channels)
+            .map(|pixel| {
+                let (gray, alpha) = match *pixel {
+                    [gray] => (gray as u32, 0xFF),
+                    [gray, alpha] => (gray as u32, alpha),
+                    [r, g, b] => (
+                        (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000,
+                        0xFF,
+                    ),
+                    [r, g, b, alpha, ..] => (
+                        (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000,
+                        alpha,
+                    ),
+                    [] => (0xFF, 0),
+                };
+                if alpha < 0x80 {
+                    0
+                } else {
+                    (((255 - gray) * max_value + 127) / 255) as u8
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let (cell_width, cell_height) = (font.cell_width as usize, font.cell_height as usize);
+        font.glyphs = description
+            .glyphs
+            .iter()
+            .enumerate()
+            .map(|(index, glyph)| {
+                let (left, top) = font.sheet_position(index);
+                Glyph {
+                    width: glyph.width,
+                    pixels: (0..cell_width * cell_height)
+                        .map(|pixel| {
+                            let (x, y) = (pixel % cell_width, pixel / cell_width);
+                            values[(top + y) * width + left + x]
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+        Ok(font)
+    }
+}
+
+/// A glyph of a [`FontDescription`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GlyphDescription {
+    /// Characters drawn with this glyph, each given as the character itself or as its code in
+    /// hexadecimal (such as `0x8140`).
+    pub chars: Vec<String>,
+    #[serde(flatten)]
+    pub width: GlyphWidth,
+}
+
+/// The properties of a font and its glyphs, except for their bitmaps, as edited by users.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FontDescription {
+    pub version: u16,
+    pub font_type: u8,
+    pub line_height: u8,
+    pub fallback_glyph: u16,
+    pub default_width: GlyphWidth,
+    pub encoding: FontEncoding,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<[u8; 4]>,
+    pub cell_width: u8,
+    pub cell_height: u8,
+    pub baseline: u8,
+    pub max_width: u8,
+    pub bits_per_pixel: u8,
+    pub rotation: u8,
+    /// Glyphs in the order they appear in the sheet.
+    pub glyphs: Vec<GlyphDescription>,
+}
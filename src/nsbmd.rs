@@ -0,0 +1,892 @@
+use thiserror::Error;
+
+use crate::nsbtx::{self, Nsbtx};
+
+const BMD0_MAGIC: &[u8; 4] = b"BMD0";
+const MDL0_MAGIC: &[u8; 4] = b"MDL0";
+/// Number of slots of the matrix stack of the 3D engine.
+const MATRIX_STACK_LEN: usize = 32;
+
+#[derive(Error, Debug)]
+pub enum ParseModelError {
+    #[error("magic number does not match (expected: BMD0)")]
+    MagicNumberMismatch,
+    #[error("model data is truncated")]
+    Truncated,
+    #[error("the file has no models")]
+    MissingModels,
+    #[error("unknown render command 0x{0:02X}")]
+    UnknownRenderCommand(u8),
+}
+
+/// A 4x4 transformation matrix, indexed by row then column, applied to column vectors.
+pub type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    std::array::from_fn(|row| {
+        std::array::from_fn(|column| (0..4).map(|i| a[row][i] * b[i][column]).sum())
+    })
+}
+
+fn transform_point(matrix: &Matrix, [x, y, z]: [f64; 3]) -> [f64; 3] {
+    std::array::from_fn(|row| {
+        matrix[row][0] * x + matrix[row][1] * y + matrix[row][2] * z + matrix[row][3]
+    })
+}
+
+fn transform_direction(matrix: &Matrix, [x, y, z]: [f64; 3]) -> [f64; 3] {
+    let direction: [f64; 3] =
+        std::array::from_fn(|row| matrix[row][0] * x + matrix[row][1] * y + matrix[row][2] * z);
+    let length = direction.iter().map(|c| c * c).sum::<f64>().sqrt();
+    if length > 0.0 {
+        direction.map(|c| c / length)
+    } else {
+        direction
+    }
+}
+
+/// Builds a matrix from the 3x3 rotation part of a matrix given to the 3D engine, whose rows
+/// are the images of the axes.
+fn rotation_matrix(m: [f64; 9]) -> Matrix {
+    [
+        [m[0], m[3], m[6], 0.0],
+        [m[1], m[4], m[7], 0.0],
+        [m[2], m[5], m[8], 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+/// Expands a rotation compressed as two values around a pivot, as stored by bones whose
+/// rotation has a single non-zero element in one row & column.
+fn pivot_rotation(select: u16, negate: u16, a: f64, b: f64) -> [f64; 9] {
+    let one = if negate & 1 == 0 { 1.0 } else { -1.0 };
+    let c = if negate & 2 == 0 { b } else { -b };
+    let d = if negate & 4 == 0 { a } else { -a };
+    match select {
+        0 => [one, 0.0, 0.0, 0.0, a, b, 0.0, c, d],
+        1 => [0.0, one, 0.0, a, 0.0, b, c, 0.0, d],
+        2 => [0.0, 0.0, one, a, b, 0.0, c, d, 0.0],
+        3 => [0.0, a, b, one, 0.0, 0.0, 0.0, c, d],
+        4 => [a, 0.0, b, 0.0, one, 0.0, c, 0.0, d],
+        5 => [a, b, 0.0, 0.0, 0.0, one, c, d, 0.0],
+        6 => [0.0, a, b, 0.0, c, d, one, 0.0, 0.0],
+        7 => [a, 0.0, b, c, 0.0, d, 0.0, one, 0.0],
+        8 => [a, b, 0.0, c, d, 0.0, 0.0, 0.0, one],
+        _ => [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+    }
+}
+
+fn u8_at(data: &[u8], offset: usize) -> Result<u8, ParseModelError> {
+    data.get(offset).copied().ok_or(ParseModelError::Truncated)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, ParseModelError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseModelError::Truncated)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, ParseModelError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseModelError::Truncated)
+}
+
+/// Reads a 1.19.12 fixed-point number.
+fn fx32_at(data: &[u8], offset: usize) -> Result<f64, ParseModelError> {
+    Ok(u32_at(data, offset)? as i32 as f64 / 4096.0)
+}
+
+/// Reads a 1.3.12 fixed-point number.
+fn fx16_at(data: &[u8], offset: usize) -> Result<f64, ParseModelError> {
+    Ok(u16_at(data, offset)? as i16 as f64 / 4096.0)
+}
+
+fn dictionary(data: &[u8], offset: usize) -> Result<Vec<(String, &[u8])>, ParseModelError> {
+    nsbtx::dictionary(data, offset).ok_or(ParseModelError::Truncated)
+}
+
+/// A node of a model's skeleton.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bone {
+    pub name: String,
+    /// Index of the parent bone, found by running the model's render commands.
+    pub parent: Option<usize>,
+    /// Transformation relative to the parent bone.
+    pub transform: Matrix,
+}
+
+impl Bone {
+    fn parse(name: String, data: &[u8], offset: usize) -> Result<Self, ParseModelError> {
+        let flags = u16_at(data, offset)?;
+        let mut cursor = offset + 0x4;
+        let mut transform = IDENTITY;
+        if flags & 1 == 0 {
+            for (row, column) in transform.iter_mut().take(3).enumerate() {
+                column[3] = fx32_at(data, cursor + row * 4)?;
+            }
+            cursor += 0xC;
+        }
+        if flags & 2 == 0 {
+            let rotation = if flags & 8 != 0 {
+                let a = fx16_at(data, cursor)?;
+                let b = fx16_at(data, cursor + 2)?;
+                cursor += 0x4;
+                pivot_rotation((flags >> 4) & 0xF, (flags >> 8) & 0xF, a, b)
+            } else {
+                // The first element is stored along with the flags.
+                let mut m = [fx16_at(data, offset + 0x2)?; 9];
+                for (index, element) in m.iter_mut().enumerate().skip(1) {
+                    *element = fx16_at(data, cursor + (index - 1) * 2)?;
+                }
+                cursor += 0x10;
+                m
+            };
+            transform = multiply(&transform, &rotation_matrix(rotation));
+        }
+        if flags & 4 == 0 {
+            let mut scale = IDENTITY;
+            for (axis, row) in scale.iter_mut().take(3).enumerate() {
+                row[axis] = fx32_at(data, cursor + axis * 4)?;
+            }
+            transform = multiply(&transform, &scale);
+        }
+        Ok(Self {
+            name,
+            parent: None,
+            transform,
+        })
+    }
+}
+
+/// The surface properties of a model's polygons.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Material {
+    pub name: String,
+    /// Names of the texture & palette drawn on the material, if any.
+    pub texture: Option<String>,
+    pub palette: Option<String>,
+    /// BGR555 diffuse color.
+    pub diffuse: u16,
+    /// Polygon attributes: which sides are drawn and the alpha of the polygons.
+    pub polygon_attributes: u32,
+    /// Texture parameters: how the texture repeats.
+    pub texture_parameters: u32,
+}
+
+impl Material {
+    /// Whether both the front & back of polygons are drawn.
+    pub fn double_sided(&self) -> bool {
+        self.polygon_attributes & 0xC0 == 0xC0
+    }
+
+    /// Alpha of the polygons, from 0 to 31.
+    pub fn alpha(&self) -> u8 {
+        ((self.polygon_attributes >> 16) & 0x1F) as u8
+    }
+
+    /// Whether the texture repeats horizontally & vertically, and whether repeats are
+    /// mirrored.
+    pub fn wrapping(&self) -> [(bool, bool); 2] {
+        let bit = |index: u32| self.texture_parameters & (1 << index) != 0;
+        [(bit(16), bit(18)), (bit(17), bit(19))]
+    }
+}
+
+/// A piece of geometry of a model, drawn by the 3D engine's display list commands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mesh {
+    pub name: String,
+    pub display_list: Vec<u8>,
+}
+
+/// A command of the bytecode drawing a model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RenderCommand {
+    LoadMatrix(usize),
+    BindMaterial(usize),
+    Draw(usize),
+    MultiplyBone {
+        bone: usize,
+        parent: usize,
+        store: Option<usize>,
+        load: Option<usize>,
+    },
+    Skin {
+        store: usize,
+    },
+}
+
+fn parse_render_commands(
+    data: &[u8],
+    offset: usize,
+) -> Result<Vec<RenderCommand>, ParseModelError> {
+    let mut commands = Vec::new();
+    let mut cursor = offset;
+    loop {
+        let opcode = u8_at(data, cursor)?;
+        let parameter = |index: usize| u8_at(data, cursor + 1 + index).map(usize::from);
+        let (command, parameter_count) = match opcode {
+            0x00 => (None, 0),
+            0x01 => break,
+            0x02 | 0x0C | 0x0D => (None, 2),
+            0x03 => (Some(RenderCommand::LoadMatrix(parameter(0)?)), 1),
+            0x04 | 0x24 | 0x44 => (Some(RenderCommand::BindMaterial(parameter(0)?)), 1),
+            0x05 => (Some(RenderCommand::Draw(parameter(0)?)), 1),
+            0x06 | 0x26 | 0x46 | 0x66 => {
+                let store = (opcode & 0x20 != 0).then_some(parameter(3)?);
+                let load = match opcode {
+                    0x46 => Some(parameter(3)?),
+                    0x66 => Some(parameter(4)?),
+                    _ => None,
+                };
+                let command = RenderCommand::MultiplyBone {
+                    bone: parameter(0)?,
+                    parent: parameter(1)?,
+                    store,
+                    load,
+                };
+                (
+                    Some(command),
+                    3 + store.is_some() as usize + load.is_some() as usize,
+                )
+            }
+            0x07 | 0x47 | 0x08 => (None, 1),
+            0x09 => (
+                Some(RenderCommand::Skin {
+                    store: parameter(0)?,
+                }),
+                2 + parameter(1)? * 3,
+            ),
+            0x0B | 0x2B => (None, 0),
+            _ => return Err(ParseModelError::UnknownRenderCommand(opcode)),
+        };
+        commands.extend(command);
+        cursor += 1 + parameter_count;
+    }
+    Ok(commands)
+}
+
+/// A model of an NSBMD file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Model {
+    pub name: String,
+    pub bones: Vec<Bone>,
+    pub materials: Vec<Material>,
+    pub meshes: Vec<Mesh>,
+    /// Factor applied to the coordinates of every vertex.
+    pub scale: f64,
+    render_commands: Vec<RenderCommand>,
+}
+
+impl Model {
+    fn parse(name: String, data: &[u8], offset: usize) -> Result<Self, ParseModelError> {
+        let render_commands_offset = offset + u32_at(data, offset + 0x4)? as usize;
+        let materials_offset = offset + u32_at(data, offset + 0x8)? as usize;
+        let meshes_offset = offset + u32_at(data, offset + 0xC)? as usize;
+        let scale = fx32_at(data, offset + 0x1C)?;
+
+        let bones_offset = offset + 0x40;
+        let mut bones = dictionary(data, bones_offset)?
+            .into_iter()
+            .map(|(name, entry)| Bone::parse(name, data, bones_offset + u32_at(entry, 0)? as usize))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut materials = dictionary(data, materials_offset + 0x4)?
+            .into_iter()
+            .map(|(name, entry)| {
+                let material = materials_offset + u32_at(entry, 0)? as usize;
+                Ok(Material {
+                    name,
+                    texture: None,
+                    palette: None,
+                    diffuse: u16_at(data, material + 0x4)? & 0x7FFF,
+                    polygon_attributes: u32_at(data, material + 0xC)?,
+                    texture_parameters: u32_at(data, material + 0x14)?,
+                })
+            })
+            .collect::<Result<Vec<_>, ParseModelError>>()?;
+        // Textures & palettes list the materials they are used by.
+        for (pairing_offset, is_texture) in [(0x0, true), (0x2, false)] {
+            let pairing =
+                materials_offset + u16_at(data, materials_offset + pairing_offset)? as usize;
+            for (name, entry) in dictionary(data, pairing)? {
+                let indices = materials_offset + u16_at(entry, 0)? as usize;
+                for index in 0..u8_at(entry, 2)? as usize {
+                    let material = u8_at(data, indices + index)? as usize;
+                    if let Some(material) = materials.get_mut(material) {
+                        let field = if is_texture {
+                            &mut material.texture
+                        } else {
+                            &mut material.palette
+                        };
+                        *field = Some(name.clone());
+                    }
+                }
+            }
+        }
+
+        let meshes = dictionary(data, meshes_offset)?
+            .into_iter()
+            .map(|(name, entry)| {
+                let mesh = meshes_offset + u32_at(entry, 0)? as usize;
+                let display_list = mesh + u32_at(data, mesh + 0x8)? as usize;
+                let display_list_size = u32_at(data, mesh + 0xC)? as usize;
+                Ok(Mesh {
+                    name,
+                    display_list: data
+                        .get(display_list..display_list + display_list_size)
+                        .ok_or(ParseModelError::Truncated)?
+                        .to_vec(),
+                })
+            })
+            .collect::<Result<_, ParseModelError>>()?;
+
+        let render_commands = parse_render_commands(data, render_commands_offset)?;
+        for command in &render_commands {
+            if let RenderCommand::MultiplyBone { bone, parent, .. } = *command {
+                if bone != parent && parent < bones.len() {
+                    if let Some(bone) = bones.get_mut(bone) {
+                        bone.parent = Some(parent);
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            name,
+            bones,
+            materials,
+            meshes,
+            scale,
+            render_commands,
+        })
+    }
+
+    /// Draws the model in its rest pose, returning one primitive per mesh drawn, with
+    /// vertices in model space.
+    pub fn geometry(&self) -> Vec<Primitive> {
+        let mut stack = [IDENTITY; MATRIX_STACK_LEN];
+        let mut current = IDENTITY;
+        let mut material = None;
+        let mut primitives = Vec::new();
+        for command in &self.render_commands {
+            match *command {
+                RenderCommand::LoadMatrix(index) => {
+                    current = stack[index % MATRIX_STACK_LEN];
+                }
+                RenderCommand::BindMaterial(index) => material = Some(index),
+                RenderCommand::Draw(index) => {
+                    if let Some(mesh) = self.meshes.get(index) {
+                        let mut primitive = Primitive {
+                            mesh: index,
+                            material,
+                            ..Default::default()
+                        };
+                        run_display_list(
+                            &mesh.display_list,
+                            current,
+                            &stack,
+                            self.scale,
+                            &mut primitive,
+                        );
+                        primitives.push(primitive);
+                    }
+                }
+                RenderCommand::MultiplyBone {
+                    bone, store, load, ..
+                } => {
+                    if let Some(load) = load {
+                        current = stack[load % MATRIX_STACK_LEN];
+                    }
+                    if let Some(bone) = self.bones.get(bone) {
+                        current = multiply(&current, &bone.transform);
+                    }
+                    if let Some(store) = store {
+                        stack[store % MATRIX_STACK_LEN] = current;
+                    }
+                }
+                // Skinned vertices are stored in the rest pose, where the matrix blending
+                // bones with their inverse bind matrices is the identity.
+                RenderCommand::Skin { store } => stack[store % MATRIX_STACK_LEN] = IDENTITY,
+            }
+        }
+        primitives
+    }
+}
+
+/// Triangles drawn by a mesh, each vertex being given by three consecutive elements of the
+/// attribute lists.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Primitive {
+    pub mesh: usize,
+    pub material: Option<usize>,
+    pub positions: Vec<[f64; 3]>,
+    pub normals: Vec<[f64; 3]>,
+    /// Texture coordinates, in texels.
+    pub texcoords: Vec<[f64; 2]>,
+    pub colors: Vec<[f64; 3]>,
+    /// Which attributes were given by the display list. Attributes that weren't are filled
+    /// with defaults.
+    pub has_normals: bool,
+    pub has_texcoords: bool,
+    pub has_colors: bool,
+}
+
+impl Primitive {
+    pub fn triangle_count(&self) -> usize {
+        self.positions.len() / 3
+    }
+}
+
+/// A vertex as sent to the 3D engine.
+#[derive(Debug, Clone, Copy)]
+struct Vertex {
+    position: [f64; 3],
+    normal: [f64; 3],
+    texcoord: [f64; 2],
+    color: [f64; 3],
+}
+
+/// Number of parameters of a display list command, or `None` if the command is unknown.
+fn parameter_count(command: u8) -> Option<usize> {
+    Some(match command {
+        0x00 | 0x11 | 0x15 | 0x41 => 0,
+        0x10
+        | 0x12..=0x14
+        | 0x20..=0x22
+        | 0x24..=0x2B
+        | 0x30..=0x33
+        | 0x40
+        | 0x50
+        | 0x60
+        | 0x72 => 1,
+        0x23 | 0x71 => 2,
+        0x1B | 0x1C | 0x70 => 3,
+        0x1A => 9,
+        0x17 | 0x19 => 12,
+        0x16 | 0x18 => 16,
+        0x34 => 32,
+        _ => return None,
+    })
+}
+
+/// Sign-extends the 10-bit value at bit `shift` of `value`.
+fn s10(value: u32, shift: u32) -> f64 {
+    (((value >> shift) << 22) as i32 >> 22) as f64
+}
+
+/// Runs the geometry commands of a display list, adding the triangles drawn to `primitive`.
+/// Only the matrix commands used by models are followed.
+fn run_display_list(
+    display_list: &[u8],
+    mut matrix: Matrix,
+    stack: &[Matrix; MATRIX_STACK_LEN],
+    scale: f64,
+    primitive: &mut Primitive,
+) {
+    let word = |offset: usize| {
+        display_list
+            .get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+    };
+    let mut current = Vertex {
+        position: [0.0; 3],
+        normal: [0.0, 1.0, 0.0],
+        texcoord: [0.0; 2],
+        color: [1.0; 3],
+    };
+    let mut primitive_type = 0;
+    let mut vertices = Vec::<Vertex>::new();
+    let mut cursor = 0;
+
+    while let Some(commands) = word(cursor) {
+        cursor += 4;
+        for command in commands.to_le_bytes() {
+            let Some(count) = parameter_count(command) else {
+                return;
+            };
+            let parameters = (0..count)
+                .map(|index| word(cursor + index * 4))
+                .collect::<Option<Vec<_>>>();
+            let Some(parameters) = parameters else {
+                return;
+            };
+            cursor += count * 4;
+
+            let mut position = None;
+            match (command, parameters.as_slice()) {
+                (0x14, &[index]) => matrix = stack[index as usize % MATRIX_STACK_LEN],
+                (0x15, _) => matrix = IDENTITY,
+                (0x20, &[color]) => {
+                    current.color = [0, 5, 10].map(|shift| ((color >> shift) & 0x1F) as f64 / 31.0);
+                    primitive.has_colors = true;
+                }
+                (0x21, &[normal]) => {
+                    current.normal =
+                        [s10(normal, 0), s10(normal, 10), s10(normal, 20)].map(|c| c / 512.0);
+                    primitive.has_normals = true;
+                }
+                (0x22, &[texcoord]) => {
+                    current.texcoord = [
+                        (texcoord as u16 as i16) as f64 / 16.0,
+                        ((texcoord >> 16) as u16 as i16) as f64 / 16.0,
+                    ];
+                    primitive.has_texcoords = true;
+                }
+                (0x23, &[xy, z]) => {
+                    position = Some(
+                        [xy as u16 as i16, (xy >> 16) as u16 as i16, z as u16 as i16]
+                            .map(|c| c as f64 / 4096.0),
+                    );
+                }
+                (0x24, &[xyz]) => {
+                    position = Some([s10(xyz, 0), s10(xyz, 10), s10(xyz, 20)].map(|c| c / 64.0));
+                }
+                (0x25..=0x27, &[pair]) => {
+                    let first = (pair as u16 as i16) as f64 / 4096.0;
+                    let second = ((pair >> 16) as u16 as i16) as f64 / 4096.0;
+                    let [x, y, z] = current.position;
+                    position = Some(match command {
+                        0x25 => [first, second, z],
+                        0x26 => [first, y, second],
+                        _ => [x, first, second],
+                    });
+                }
+                (0x28, &[difference]) => {
+                    let [x, y, z] = current.position;
+                    let [dx, dy, dz] = [0, 10, 20].map(|shift| s10(difference, shift) / 4096.0);
+                    position = Some([x + dx, y + dy, z + dz]);
+                }
+                (0x40, &[kind]) => {
+                    primitive_type = kind & 3;
+                    vertices.clear();
+                }
+                (0x41, _) => vertices.clear(),
+                _ => {}
+            }
+
+            let Some(position) = position else {
+                continue;
+            };
+            current.position = position;
+            vertices.push(Vertex {
+                position: transform_point(&matrix, position.map(|c| c * scale)),
+                normal: transform_direction(&matrix, current.normal),
+                ..current
+            });
+            let n = vertices.len();
+            let triangles: &[[usize; 3]] = match primitive_type {
+                // Separate triangles.
+                0 if n.is_multiple_of(3) => &[[n - 3, n - 2, n - 1]],
+                // Separate quads.
+                1 if n.is_multiple_of(4) => &[[n - 4, n - 3, n - 2], [n - 4, n - 2, n - 1]],
+                // Triangle strips, every other triangle being flipped to keep the same winding.
+                2 if n >= 3 && !n.is_multiple_of(2) => &[[n - 3, n - 2, n - 1]],
+                2 if n >= 3 => &[[n - 2, n - 3, n - 1]],
+                // Quad strips.
+                3 if n >= 4 && n.is_multiple_of(2) => {
+                    &[[n - 4, n - 3, n - 1], [n - 4, n - 1, n - 2]]
+                }
+                _ => &[],
+            };
+            for triangle in triangles {
+                for &index in triangle {
+                    let vertex = vertices[index];
+                    primitive.positions.push(vertex.position);
+                    primitive.normals.push(vertex.normal);
+                    primitive.texcoords.push(vertex.texcoord);
+                    primitive.colors.push(vertex.color);
+                }
+            }
+        }
+    }
+}
+
+/// The models of an NSBMD file, along with the textures it embeds, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Nsbmd {
+    pub models: Vec<Model>,
+    pub textures: Option<Nsbtx>,
+}
+
+impl Nsbmd {
+    pub fn parse(data: &[u8]) -> Result<Self, ParseModelError> {
+        if !data.starts_with(BMD0_MAGIC) {
+            return Err(ParseModelError::MagicNumberMismatch);
+        }
+        let section_count = u16_at(data, 0xE)? as usize;
+        let section = (0..section_count)
+            .map(|index| u32_at(data, 0x10 + index * 4).map(|offset| offset as usize))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .find(|&offset| data.get(offset..offset + 4) == Some(MDL0_MAGIC))
+            .ok_or(ParseModelError::MissingModels)?;
+
+        let models = dictionary(data, section + 0x8)?
+            .into_iter()
+            .map(|(name, entry)| Model::parse(name, data, section + u32_at(entry, 0)? as usize))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            models,
+            textures: Nsbtx::parse(data).ok(),
+        })
+    }
+}
+
+/// A model converted to glTF, made of a JSON document referring to a binary buffer and to
+/// texture images, all meant to be placed in the same directory.
+pub struct Gltf {
+    pub document: serde_json::Value,
+    pub buffer: Vec<u8>,
+    /// File name & PNG data of each texture.
+    pub images: Vec<(String, Vec<u8>)>,
+}
+
+/// glTF wrapping modes.
+const GLTF_CLAMP_TO_EDGE: u32 = 33071;
+const GLTF_MIRRORED_REPEAT: u32 = 33648;
+const GLTF_REPEAT: u32 = 10497;
+/// glTF component type & buffer target of vertex attributes.
+const GLTF_FLOAT: u32 = 5126;
+const GLTF_ARRAY_BUFFER: u32 = 34962;
+
+impl Nsbmd {
+    /// Converts every model of the file in its rest pose to glTF, each model being a node with
+    /// its mesh and its skeleton. Textures are looked up in `textures`, or in the ones the file
+    /// embeds, and the images are named by `image_name`.
+    pub fn to_gltf(
+        &self,
+        textures: Option<&Nsbtx>,
+        buffer_name: &str,
+        image_name: impl Fn(&str) -> String,
+    ) -> Result<Gltf, png::EncodingError> {
+        use serde_json::{json, Value};
+
+        let textures = textures.or(self.textures.as_ref());
+        let mut buffer = Vec::new();
+        let mut buffer_views = Vec::new();
+        let mut accessors = Vec::new();
+        // Adds a list of vectors as an accessor, returning its index.
+        let mut add_accessor = |elements: Vec<Vec<f32>>, kind: &str, bounds: bool| {
+            let offset = buffer.len();
+            for element in &elements {
+                for component in element {
+                    buffer.extend_from_slice(&component.to_le_bytes());
+                }
+            }
+            buffer_views.push(json!({
+                "buffer": 0,
+                "byteOffset": offset,
+                "byteLength": buffer.len() - offset,
+                "target": GLTF_ARRAY_BUFFER,
+            }));
+            let mut accessor = json!({
+                "bufferView": buffer_views.len() - 1,
+                "componentType": GLTF_FLOAT,
+                "count": elements.len(),
+                "type": kind,
+            });
+            if bounds {
+                let fold = |f: fn(f32, f32) -> f32, start| {
+                    (0..elements[0].len())
+                        .map(|i| elements.iter().map(|e| e[i]).fold(start, f))
+                        .collect::<Vec<_>>()
+                };
+                accessor["min"] = json!(fold(f32::min, f32::INFINITY));
+                accessor["max"] = json!(fold(f32::max, f32::NEG_INFINITY));
+            }
+            accessors.push(accessor);
+            accessors.len() - 1
+        };
+
+        let mut nodes = Vec::<Value>::new();
+        let mut scene_nodes = Vec::new();
+        let mut meshes = Vec::new();
+        let mut materials = Vec::new();
+        let mut gltf_textures = Vec::new();
+        let mut samplers = Vec::new();
+        let mut images = Vec::new();
+        let mut gltf_images = Vec::new();
+        for model in &self.models {
+            // Each material's glTF index, along with the size of its texture.
+            let mut model_materials = Vec::new();
+            for material in &model.materials {
+                let texture =
+                    textures
+                        .zip(material.texture.as_ref())
+                        .and_then(|(textures, name)| {
+                            textures
+                                .textures
+                                .iter()
+                                .find(|texture| &texture.name == name)
+                                .map(|texture| {
+                                    let palette = material
+                                        .palette
+                                        .as_ref()
+                                        .and_then(|name| {
+                                            textures.palettes.iter().find(|p| &p.name == name)
+                                        })
+                                        .or_else(|| textures.palette_for(texture));
+                                    (texture, palette)
+                                })
+                        });
+                let alpha = material.alpha() as f64 / 31.0;
+                let [r, g, b] =
+                    [0, 5, 10].map(|shift| ((material.diffuse >> shift) & 0x1F) as f64 / 31.0);
+                let mut gltf_material = json!({
+                    "name": material.name,
+                    "pbrMetallicRoughness": {
+                        "baseColorFactor": [r, g, b, alpha],
+                        "metallicFactor": 0.0,
+                        "roughnessFactor": 1.0,
+                    },
+                    "doubleSided": material.double_sided(),
+                    "alphaMode": if alpha < 1.0 { "BLEND" } else { "OPAQUE" },
+                });
+                let mut texture_size = None;
+                if let Some((texture, palette)) = texture {
+                    if let Ok(image) = nsbtx::render_texture(texture, palette) {
+                        let file_name = image_name(&texture.name);
+                        let image_index =
+                            match images.iter().position(|(name, _)| name == &file_name) {
+                                Some(index) => index,
+                                None => {
+                                    images.push((file_name.clone(), image.to_png()?));
+                                    gltf_images.push(json!({ "uri": file_name }));
+                                    images.len() - 1
+                                }
+                            };
+                        let wrap = |(repeat, mirror)| match (repeat, mirror) {
+                            (false, _) => GLTF_CLAMP_TO_EDGE,
+                            (true, false) => GLTF_REPEAT,
+                            (true, true) => GLTF_MIRRORED_REPEAT,
+                        };
+                        let [s, t] = material.wrapping();
+                        samplers.push(json!({ "wrapS": wrap(s), "wrapT": wrap(t) }));
+                        gltf_textures.push(json!({
+                            "sampler": samplers.len() - 1,
+                            "source": image_index,
+                        }));
+                        gltf_material["pbrMetallicRoughness"]["baseColorFactor"] =
+                            json!([1.0, 1.0, 1.0, alpha]);
+                        gltf_material["pbrMetallicRoughness"]["baseColorTexture"] =
+                            json!({ "index": gltf_textures.len() - 1 });
+                        if alpha == 1.0 {
+                            gltf_material["alphaMode"] = json!("MASK");
+                        }
+                        texture_size = Some((texture.width as f64, texture.height as f64));
+                    }
+                }
+                materials.push(gltf_material);
+                model_materials.push((materials.len() - 1, texture_size));
+            }
+
+            let mut primitives = Vec::new();
+            for primitive in model.geometry() {
+                if primitive.positions.is_empty() {
+                    continue;
+                }
+                let to_f32 = |vectors: &[[f64; 3]]| {
+                    vectors
+                        .iter()
+                        .map(|v| v.iter().map(|&c| c as f32).collect())
+                        .collect()
+                };
+                let mut attributes = json!({
+                    "POSITION": add_accessor(to_f32(&primitive.positions), "VEC3", true),
+                });
+                if primitive.has_normals {
+                    attributes["NORMAL"] =
+                        json!(add_accessor(to_f32(&primitive.normals), "VEC3", false));
+                }
+                if primitive.has_colors {
+                    attributes["COLOR_0"] =
+                        json!(add_accessor(to_f32(&primitive.colors), "VEC3", false));
+                }
+                let material = primitive
+                    .material
+                    .and_then(|index| model_materials.get(index).copied());
+                if let Some((_, Some((width, height)))) = material {
+                    if primitive.has_texcoords {
+                        let texcoords = primitive
+                            .texcoords
+                            .iter()
+                            .map(|&[s, t]| vec![(s / width) as f32, (t / height) as f32])
+                            .collect();
+                        attributes["TEXCOORD_0"] = json!(add_accessor(texcoords, "VEC2", false));
+                    }
+                }
+                let mut gltf_primitive = json!({ "attributes": attributes });
+                if let Some((index, _)) = material {
+                    gltf_primitive["material"] = json!(index);
+                }
+                primitives.push(gltf_primitive);
+            }
+
+            // Bones are added after the model's node, in order.
+            let model_node = nodes.len();
+            let first_bone = model_node + 1;
+            let children = |parent: Option<usize>| {
+                model
+                    .bones
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, bone)| bone.parent == parent)
+                    .map(|(index, _)| first_bone + index)
+                    .collect::<Vec<_>>()
+            };
+            let mut node = json!({ "name": model.name });
+            if !primitives.is_empty() {
+                meshes.push(json!({ "name": model.name, "primitives": primitives }));
+                node["mesh"] = json!(meshes.len() - 1);
+            }
+            let roots = children(None);
+            if !roots.is_empty() {
+                node["children"] = json!(roots);
+            }
+            nodes.push(node);
+            for (index, bone) in model.bones.iter().enumerate() {
+                let matrix = (0..16)
+                    .map(|i| bone.transform[i % 4][i / 4])
+                    .collect::<Vec<_>>();
+                let mut node = json!({ "name": bone.name, "matrix": matrix });
+                let bone_children = children(Some(index));
+                if !bone_children.is_empty() {
+                    node["children"] = json!(bone_children);
+                }
+                nodes.push(node);
+            }
+            scene_nodes.push(model_node);
+        }
+
+        let mut document = json!({
+            "asset": { "version": "2.0", "generator": "ravends" },
+            "scene": 0,
+            "scenes": [{ "nodes": scene_nodes }],
+            "nodes": nodes,
+            "meshes": meshes,
+            "materials": materials,
+            "accessors": accessors,
+            "bufferViews": buffer_views,
+            "buffers": [{ "uri": buffer_name, "byteLength": buffer.len() }],
+        });
+        if !gltf_images.is_empty() {
+            document["images"] = json!(gltf_images);
+            document["samplers"] = json!(samplers);
+            document["textures"] = json!(gltf_textures);
+        }
+        Ok(Gltf {
+            document,
+            buffer,
+            images,
+        })
+    }
+}
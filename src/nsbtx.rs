@@ -0,0 +1,376 @@
+use std::fmt;
+
+use thiserror::Error;
+
+const BTX0_MAGIC: &[u8; 4] = b"BTX0";
+const BMD0_MAGIC: &[u8; 4] = b"BMD0";
+const TEX0_MAGIC: &[u8; 4] = b"TEX0";
+/// Size of the names of 3D resources, padded with zeroes.
+const NAME_LEN: usize = 16;
+/// Suffix commonly given to the palette of a texture, after the texture's name.
+const PALETTE_SUFFIX: &str = "_pl";
+
+#[derive(Error, Debug)]
+pub enum ParseTextureError {
+    #[error("magic number does not match (expected: BTX0 or BMD0)")]
+    MagicNumberMismatch,
+    #[error("texture data is truncated")]
+    Truncated,
+    #[error("the file has no textures")]
+    MissingTextures,
+}
+
+#[derive(Error, Debug)]
+pub enum RenderTextureError {
+    #[error("texture {0:?} needs a palette, but none was found for it")]
+    MissingPalette(String),
+}
+
+/// How the texels of a texture are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFormat {
+    /// 5-bit palette index with 3-bit alpha.
+    A3I5,
+    /// 2-bit palette index.
+    Palette4,
+    /// 4-bit palette index.
+    Palette16,
+    /// 8-bit palette index.
+    Palette256,
+    /// Blocks of 4x4 texels, each with its own 4 colors.
+    Compressed4x4,
+    /// 3-bit palette index with 5-bit alpha.
+    A5I3,
+    /// BGR555 colors with a 1-bit alpha.
+    Direct,
+}
+
+impl TextureFormat {
+    fn from_raw(value: u32) -> Option<Self> {
+        match value {
+            1 => Some(TextureFormat::A3I5),
+            2 => Some(TextureFormat::Palette4),
+            3 => Some(TextureFormat::Palette16),
+            4 => Some(TextureFormat::Palette256),
+            5 => Some(TextureFormat::Compressed4x4),
+            6 => Some(TextureFormat::A5I3),
+            7 => Some(TextureFormat::Direct),
+            _ => None,
+        }
+    }
+
+    fn bits_per_texel(self) -> usize {
+        match self {
+            TextureFormat::Palette4 | TextureFormat::Compressed4x4 => 2,
+            TextureFormat::Palette16 => 4,
+            TextureFormat::A3I5 | TextureFormat::Palette256 | TextureFormat::A5I3 => 8,
+            TextureFormat::Direct => 16,
+        }
+    }
+}
+
+impl fmt::Display for TextureFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TextureFormat::A3I5 => "A3I5",
+            TextureFormat::Palette4 => "4 colors",
+            TextureFormat::Palette16 => "16 colors",
+            TextureFormat::Palette256 => "256 colors",
+            TextureFormat::Compressed4x4 => "4x4 compressed",
+            TextureFormat::A5I3 => "A5I3",
+            TextureFormat::Direct => "direct color",
+        })
+    }
+}
+
+/// A texture of a texture archive, along with its texel data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Texture {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    pub format: TextureFormat,
+    /// Whether the first color of the palette is transparent.
+    pub color0_transparent: bool,
+    pub texels: Vec<u8>,
+    /// For 4x4 compressed textures, the palette offset & mode of each block.
+    pub block_data: Vec<u8>,
+}
+
+impl Texture {
+    pub fn needs_palette(&self) -> bool {
+        self.format != TextureFormat::Direct
+    }
+}
+
+/// A palette of a texture archive. Palettes don't record their size, so `colors` holds every
+/// color from the start of the palette to the end of the palette data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TexturePalette {
+    pub name: String,
+    pub colors: Vec<u16>,
+}
+
+/// The textures & palettes of an NSBTX texture archive, or of an NSBMD model embedding them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Nsbtx {
+    pub textures: Vec<Texture>,
+    pub palettes: Vec<TexturePalette>,
+}
+
+/// A texture drawn in 8-bit RGBA.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+impl RgbaImage {
+    pub fn to_png(&self) -> Result<Vec<u8>, png::EncodingError> {
+        let mut png_data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png_data, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels.concat())?;
+        writer.finish()?;
+        Ok(png_data)
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, ParseTextureError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseTextureError::Truncated)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, ParseTextureError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseTextureError::Truncated)
+}
+
+fn bytes_at(data: &[u8], offset: usize, len: usize) -> Result<&[u8], ParseTextureError> {
+    data.get(offset..offset + len)
+        .ok_or(ParseTextureError::Truncated)
+}
+
+/// Reads a 3D resource dictionary, as used by models & textures, returning the name and data
+/// of each of its entries.
+pub fn dictionary(data: &[u8], offset: usize) -> Option<Vec<(String, &[u8])>> {
+    let count = *data.get(offset + 1)? as usize;
+    let u16_at = |offset: usize| u16_at(data, offset).ok().map(usize::from);
+    // The dictionary starts with a search tree, followed by the entries' data and names.
+    let entries = offset + 0x4 + u16_at(offset + 0x6)?;
+    let entry_size = u16_at(entries)?;
+    let names = entries + 0x4 + count * entry_size;
+    (0..count)
+        .map(|index| {
+            let name = data.get(names + index * NAME_LEN..names + (index + 1) * NAME_LEN)?;
+            let name = String::from_utf8_lossy(name)
+                .trim_end_matches('\0')
+                .to_owned();
+            let entry = entries + 0x4 + index * entry_size;
+            Some((name, data.get(entry..entry + entry_size)?))
+        })
+        .collect()
+}
+
+impl Nsbtx {
+    pub fn parse(data: &[u8]) -> Result<Self, ParseTextureError> {
+        if !data.starts_with(BTX0_MAGIC) && !data.starts_with(BMD0_MAGIC) {
+            return Err(ParseTextureError::MagicNumberMismatch);
+        }
+        let section_count = u16_at(data, 0xE)? as usize;
+        let section = (0..section_count)
+            .map(|index| u32_at(data, 0x10 + index * 4).map(|offset| offset as usize))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .find(|&offset| data.get(offset..offset + 4) == Some(TEX0_MAGIC))
+            .ok_or(ParseTextureError::MissingTextures)?;
+
+        let texture_dictionary = section + u16_at(data, section + 0xE)? as usize;
+        let texture_data = section + u32_at(data, section + 0x14)? as usize;
+        let compressed_data = section + u32_at(data, section + 0x24)? as usize;
+        let block_data = section + u32_at(data, section + 0x28)? as usize;
+        let palette_data_size = (u32_at(data, section + 0x30)? as usize) << 3;
+        let palette_dictionary = section + u32_at(data, section + 0x34)? as usize;
+        let palette_data = section + u32_at(data, section + 0x38)? as usize;
+
+        let mut textures = Vec::new();
+        for (name, entry) in
+            dictionary(data, texture_dictionary).ok_or(ParseTextureError::Truncated)?
+        {
+            let parameters = u32_at(entry, 0)?;
+            let offset = ((parameters & 0xFFFF) as usize) << 3;
+            let width = 8 << ((parameters >> 20) & 7);
+            let height = 8 << ((parameters >> 23) & 7);
+            let Some(format) = TextureFormat::from_raw((parameters >> 26) & 7) else {
+                // Format 0 means there is no texture.
+                continue;
+            };
+            let texels_len = width * height * format.bits_per_texel() / 8;
+            let (texels, block_data) = if format == TextureFormat::Compressed4x4 {
+                (
+                    bytes_at(data, compressed_data + offset, texels_len)?,
+                    // Each block of 4x4 texels has 2 bytes of data.
+                    bytes_at(data, block_data + offset / 2, width * height / 8)?,
+                )
+            } else {
+                (bytes_at(data, texture_data + offset, texels_len)?, &[][..])
+            };
+            textures.push(Texture {
+                name,
+                width,
+                height,
+                format,
+                color0_transparent: parameters & (1 << 29) != 0,
+                texels: texels.to_vec(),
+                block_data: block_data.to_vec(),
+            });
+        }
+
+        let palette_data = bytes_at(data, palette_data, palette_data_size)?;
+        let palettes = dictionary(data, palette_dictionary)
+            .ok_or(ParseTextureError::Truncated)?
+            .into_iter()
+            .map(|(name, entry)| {
+                let offset = (u16_at(entry, 0)? as usize) << 3;
+                let colors = palette_data
+                    .get(offset..)
+                    .unwrap_or_default()
+                    .chunks_exact(2)
+                    .map(|color| u16::from_le_bytes(color.try_into().unwrap()))
+                    .collect();
+                Ok(TexturePalette { name, colors })
+            })
+            .collect::<Result<_, ParseTextureError>>()?;
+
+        Ok(Self { textures, palettes })
+    }
+
+    /// Finds the palette of a texture. Which palette a texture is drawn with is decided by
+    /// models, so the palette named like the texture with a `_pl` suffix is used, then the one
+    /// named like the texture, then the only palette of the archive if there's just one.
+    pub fn palette_for(&self, texture: &Texture) -> Option<&TexturePalette> {
+        let suffixed_name = format!("{}{PALETTE_SUFFIX}", texture.name);
+        self.palettes
+            .iter()
+            .find(|palette| palette.name == suffixed_name)
+            .or_else(|| {
+                self.palettes
+                    .iter()
+                    .find(|palette| palette.name == texture.name)
+            })
+            .or_else(|| {
+                (self.palettes.len() == 1)
+                    .then(|| self.palettes.first())
+                    .flatten()
+            })
+    }
+}
+
+fn to_rgb(color: u16) -> [u8; 3] {
+    let expand = |channel: u16| ((channel << 3) | (channel >> 2)) as u8;
+    [
+        expand(color & 0x1F),
+        expand((color >> 5) & 0x1F),
+        expand((color >> 10) & 0x1F),
+    ]
+}
+
+/// Mixes two colors, weighting the first one by `weight` eighths.
+fn blend(a: [u8; 3], b: [u8; 3], weight: u16) -> [u8; 3] {
+    std::array::from_fn(|channel| {
+        ((a[channel] as u16 * weight + b[channel] as u16 * (8 - weight)) / 8) as u8
+    })
+}
+
+/// Draws a texture with its palette, which every format but direct color needs.
+pub fn render_texture(
+    texture: &Texture,
+    palette: Option<&TexturePalette>,
+) -> Result<RgbaImage, RenderTextureError> {
+    let colors = match palette {
+        Some(palette) => palette.colors.as_slice(),
+        None if texture.needs_palette() => {
+            return Err(RenderTextureError::MissingPalette(texture.name.clone()))
+        }
+        None => &[],
+    };
+    // Indices past the end of the palette are drawn black.
+    let color = |index: usize| to_rgb(colors.get(index).copied().unwrap_or(0));
+    let opaque = |[r, g, b]: [u8; 3]| [r, g, b, 0xFF];
+    let indexed = |index: usize| {
+        if index == 0 && texture.color0_transparent {
+            [0; 4]
+        } else {
+            opaque(color(index))
+        }
+    };
+    let texel = |bits: usize, index: usize| {
+        let bit = index * bits;
+        ((texture.texels[bit / 8] >> (bit % 8)) as usize) & ((1 << bits) - 1)
+    };
+    let texel_count = texture.width * texture.height;
+
+    let pixels = match texture.format {
+        TextureFormat::Palette4 => (0..texel_count).map(|i| indexed(texel(2, i))).collect(),
+        TextureFormat::Palette16 => (0..texel_count).map(|i| indexed(texel(4, i))).collect(),
+        TextureFormat::Palette256 => (0..texel_count).map(|i| indexed(texel(8, i))).collect(),
+        TextureFormat::A3I5 => (0..texel_count)
+            .map(|i| {
+                let [r, g, b] = color(texel(8, i) & 0x1F);
+                let alpha = (texel(8, i) >> 5) as u8;
+                [r, g, b, (alpha << 5) | (alpha << 2) | (alpha >> 1)]
+            })
+            .collect(),
+        TextureFormat::A5I3 => (0..texel_count)
+            .map(|i| {
+                let [r, g, b] = color(texel(8, i) & 0x7);
+                let alpha = (texel(8, i) >> 3) as u8;
+                [r, g, b, (alpha << 3) | (alpha >> 2)]
+            })
+            .collect(),
+        TextureFormat::Direct => (0..texel_count)
+            .map(|i| {
+                let value = u16::from_le_bytes([texture.texels[i * 2], texture.texels[i * 2 + 1]]);
+                let [r, g, b] = to_rgb(value);
+                [r, g, b, if value & 0x8000 != 0 { 0xFF } else { 0 }]
+            })
+            .collect(),
+        TextureFormat::Compressed4x4 => {
+            let blocks_wide = texture.width / 4;
+            let mut pixels = vec![[0; 4]; texel_count];
+            for (block, block_data) in texture.block_data.chunks_exact(2).enumerate() {
+                let block_data = u16::from_le_bytes(block_data.try_into().unwrap());
+                // Palette offsets are given in units of 2 colors.
+                let base = (block_data & 0x3FFF) as usize * 2;
+                let [c0, c1] = [color(base), color(base + 1)];
+                let block_colors = match block_data >> 14 {
+                    0 => [opaque(c0), opaque(c1), opaque(color(base + 2)), [0; 4]],
+                    1 => [opaque(c0), opaque(c1), opaque(blend(c0, c1, 4)), [0; 4]],
+                    2 => [c0, c1, color(base + 2), color(base + 3)].map(opaque),
+                    _ => [c0, c1, blend(c0, c1, 5), blend(c0, c1, 3)].map(opaque),
+                };
+                let (block_x, block_y) = (block % blocks_wide * 4, block / blocks_wide * 4);
+                for y in 0..4 {
+                    for x in 0..4 {
+                        // Each block has 4 bytes of texels, one per row.
+                        let row = texture.texels[block * 4 + y];
+                        let index = (row >> (x * 2)) & 3;
+                        pixels[(block_y + y) * texture.width + block_x + x] =
+                            block_colors[index as usize];
+                    }
+                }
+            }
+            pixels
+        }
+    };
+    Ok(RgbaImage {
+        width: texture.width,
+        height: texture.height,
+        pixels,
+    })
+}
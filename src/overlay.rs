@@ -0,0 +1,223 @@
+use std::ops::Range;
+
+use log::warn;
+use thiserror::Error;
+
+use crate::{
+    blz::{self, BlzCompressionError, BlzDecompressionError},
+    lz::CompressionLevel,
+    manifest::Processor,
+    rom::{self, Placement, Section, OVERLAY_ENTRY_SIZE},
+};
+
+/// Flag of an overlay table entry set on compressed overlays.
+const COMPRESSED_FLAG: u8 = 1 << 0;
+
+#[derive(Error, Debug)]
+pub enum OverlayError {
+    #[error("the ROM has no {} overlay {1}", processor_name(*.0))]
+    NoSuchOverlay(Processor, u32),
+    #[error("file {file_id} of overlay {overlay_id} lies outside the ROM")]
+    OutOfBounds { overlay_id: u32, file_id: u32 },
+    #[error("failed to decompress overlay {overlay_id}")]
+    Decompression {
+        overlay_id: u32,
+        #[source]
+        source: BlzDecompressionError,
+    },
+}
+
+/// An entry of an overlay table, describing where an overlay is loaded and which file holds it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OverlayEntry {
+    pub id: u32,
+    /// Address the overlay is loaded at.
+    pub ram_address: u32,
+    /// Size of the overlay once loaded (and decompressed).
+    pub ram_size: u32,
+    /// Size of the zeroed memory following the overlay.
+    pub bss_size: u32,
+    /// Start of the table of static initializers run when the overlay is loaded.
+    pub static_init_start: u32,
+    /// End of the table of static initializers.
+    pub static_init_end: u32,
+    pub file_id: u32,
+    /// Size of the overlay's file when it's compressed.
+    pub compressed_size: u32,
+    /// Whether the overlay's file is BLZ-compressed.
+    pub compressed: bool,
+    /// The rest of the flags, past the compressed flag.
+    pub flags: u8,
+}
+
+impl OverlayEntry {
+    pub fn parse(entry: &[u8]) -> Self {
+        let field = |index: usize| rom::u32_at(entry, index * 4);
+        let flags = (field(7) >> 24) as u8;
+        Self {
+            id: field(0),
+            ram_address: field(1),
+            ram_size: field(2),
+            bss_size: field(3),
+            static_init_start: field(4),
+            static_init_end: field(5),
+            file_id: field(6),
+            compressed_size: field(7) & 0xFFFFFF,
+            compressed: flags & COMPRESSED_FLAG != 0,
+            flags: flags & !COMPRESSED_FLAG,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; OVERLAY_ENTRY_SIZE] {
+        let flags = self.flags | if self.compressed { COMPRESSED_FLAG } else { 0 };
+        let fields = [
+            self.id,
+            self.ram_address,
+            self.ram_size,
+            self.bss_size,
+            self.static_init_start,
+            self.static_init_end,
+            self.file_id,
+            self.compressed_size & 0xFFFFFF | (flags as u32) << 24,
+        ];
+        let mut bytes = [0; OVERLAY_ENTRY_SIZE];
+        for (chunk, field) in bytes.chunks_exact_mut(4).zip(fields) {
+            chunk.copy_from_slice(&field.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+pub fn processor_name(processor: Processor) -> &'static str {
+    match processor {
+        Processor::Arm9 => "ARM9",
+        Processor::Arm7 => "ARM7",
+    }
+}
+
+fn table_section(processor: Processor) -> Section {
+    match processor {
+        Processor::Arm9 => Section::Arm9OverlayTable,
+        Processor::Arm7 => Section::Arm7OverlayTable,
+    }
+}
+
+/// Reads the overlay table of a processor. ROMs without one have no overlays.
+pub fn overlay_table(rom_data: &[u8], processor: Processor) -> Vec<OverlayEntry> {
+    table_section(processor)
+        .range(rom_data)
+        .map_or(&[][..], |range| &rom_data[range])
+        .chunks_exact(OVERLAY_ENTRY_SIZE)
+        .map(OverlayEntry::parse)
+        .collect()
+}
+
+/// Finds an overlay table entry by overlay ID, along with its offset in the ROM.
+fn find_entry(
+    rom_data: &[u8],
+    processor: Processor,
+    overlay_id: u32,
+) -> Result<(usize, OverlayEntry), OverlayError> {
+    let table = table_section(processor)
+        .range(rom_data)
+        .ok_or(OverlayError::NoSuchOverlay(processor, overlay_id))?;
+    rom_data[table.clone()]
+        .chunks_exact(OVERLAY_ENTRY_SIZE)
+        .enumerate()
+        .map(|(index, entry)| {
+            (
+                table.start + index * OVERLAY_ENTRY_SIZE,
+                OverlayEntry::parse(entry),
+            )
+        })
+        .find(|(_, entry)| entry.id == overlay_id)
+        .ok_or(OverlayError::NoSuchOverlay(processor, overlay_id))
+}
+
+/// Location in the ROM of the file of an overlay, as stored in the FAT.
+pub fn file_range(rom_data: &[u8], entry: &OverlayEntry) -> Result<Range<usize>, OverlayError> {
+    let out_of_bounds = OverlayError::OutOfBounds {
+        overlay_id: entry.id,
+        file_id: entry.file_id,
+    };
+    let Ok(fat) = rom::table_range(rom_data, "FAT", rom::FAT_ADDR_OFFSET, rom::FAT_SIZE_OFFSET)
+    else {
+        return Err(out_of_bounds);
+    };
+    let Some(fat_entry) = rom_data[fat].chunks_exact(8).nth(entry.file_id as usize) else {
+        return Err(out_of_bounds);
+    };
+    let range = rom::u32_at(fat_entry, 0) as usize..rom::u32_at(fat_entry, 4) as usize;
+    if range.start > range.end || range.end > rom_data.len() {
+        return Err(out_of_bounds);
+    }
+    Ok(range)
+}
+
+/// Reads the file of an overlay, decompressing it if it's compressed and `decompress` is set.
+pub fn extract_overlay(
+    rom_data: &[u8],
+    processor: Processor,
+    overlay_id: u32,
+    decompress: bool,
+) -> Result<Vec<u8>, OverlayError> {
+    let (_, entry) = find_entry(rom_data, processor, overlay_id)?;
+    let data = &rom_data[file_range(rom_data, &entry)?];
+    if entry.compressed && decompress {
+        blz::decompress_blz(data)
+            .map_err(|source| OverlayError::Decompression { overlay_id, source })
+    } else {
+        Ok(data.to_vec())
+    }
+}
+
+/// Sets the size of the zeroed memory following an overlay in its overlay table entry.
+pub fn set_bss_size(
+    rom_data: &mut [u8],
+    processor: Processor,
+    overlay_id: u32,
+    bss_size: u32,
+) -> Result<(), OverlayError> {
+    let (entry_offset, mut entry) = find_entry(rom_data, processor, overlay_id)?;
+    entry.bss_size = bss_size;
+    rom_data[entry_offset..entry_offset + OVERLAY_ENTRY_SIZE].copy_from_slice(&entry.to_bytes());
+    Ok(())
+}
+
+/// Replaces the contents of an overlay with decompressed data, compressing it first if the
+/// overlay was compressed, and updates the sizes in its overlay table entry.
+///
+/// Data that doesn't get any smaller when compressed is stored uncompressed instead, clearing
+/// the entry's compressed flag.
+pub fn replace_overlay(
+    rom_data: &mut Vec<u8>,
+    processor: Processor,
+    overlay_id: u32,
+    data: &[u8],
+    level: CompressionLevel,
+) -> anyhow::Result<(OverlayEntry, Placement)> {
+    let (entry_offset, mut entry) = find_entry(rom_data, processor, overlay_id)?;
+    let stored = if entry.compressed {
+        match blz::compress_blz(data, level) {
+            Ok(compressed) => Some(compressed),
+            Err(BlzCompressionError::Incompressible) => {
+                warn!("overlay {overlay_id} doesn't get any smaller when compressed, storing it uncompressed");
+                None
+            }
+            Err(error) => return Err(error.into()),
+        }
+    } else {
+        None
+    };
+
+    entry.ram_size = data.len() as u32;
+    entry.compressed = stored.is_some();
+    entry.compressed_size = stored.as_ref().map_or(0, |stored| stored.len() as u32);
+    let placement = rom::replace_file(
+        rom_data,
+        entry.file_id as u16,
+        stored.as_deref().unwrap_or(data),
+    )?;
+    rom_data[entry_offset..entry_offset + OVERLAY_ENTRY_SIZE].copy_from_slice(&entry.to_bytes());
+    Ok((entry, placement))
+}
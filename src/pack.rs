@@ -0,0 +1,1118 @@
+use std::{
+    collections::{btree_map::Entry, BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{anyhow, Context};
+use log::{info, warn};
+use rayon::prelude::*;
+use std::fs;
+
+#[cfg(feature = "graphics")]
+use crate::{
+    banner,
+    gfx::{self, Ncgr, Nclr, Nscr},
+    manifest::{GraphicsRecord, IconRecord, PaletteRecord},
+    palette,
+};
+use crate::{
+    blz,
+    cache::{CompressionCache, CACHE_DIR},
+    fnt,
+    hook::Hooks,
+    manifest::{
+        self, Codec, Compression, CompressionPolicy, ExtraDataRecord, FileRecord, Format, Manifest,
+        OverlayRecord, Processor, GRAPHICS_DIR, HASHES_FILE_NAME, MANIFEST_FILE_NAME,
+        ORIGINALS_DIR, ORIGINAL_FNT_FILE_NAME, ORPHAN_DIR, RAVENDS_DIR, SOUND_DIR, SYSTEM_DIR,
+    },
+    memory, narc,
+    overlay::OverlayEntry,
+    path_escape,
+    plugin::Plugins,
+    profile::Profile,
+    rom::{self, ExtraData, Section, OVERLAY_ENTRY_SIZE},
+    secure_area::{self, KeyTable, SecureAreaForm},
+    text::{self, TextArchive, TextEncoding, TextLayout},
+    unpack::overlay_file_name,
+};
+
+/// Restores a file to the form it had in the ROM, undoing the conversions recorded for it, and
+/// stores it with the codec given.
+///
+/// Files that have not been edited since unpacking and keep their codec are restored from their
+/// pristine copy, so that they are byte-identical to the original.
+fn restore_file(
+    fs_path: &Path,
+    record: &FileRecord,
+    codec: &Codec,
+    cache: &CompressionCache,
+    encoding: &TextEncoding,
+    plugins: &Plugins,
+    hooks: &Hooks,
+) -> anyhow::Result<Vec<u8>> {
+    let unpacked_path = fs_path.join(&record.unpacked_path);
+    let unpacked_data = if record.format == Format::Narc {
+        narc::rebuild(&unpacked_path, cache)?
+    } else {
+        fs::read(path_escape::long_path(&unpacked_path))
+            .with_context(|| format!("failed to read {:?}", record.unpacked_path))?
+    };
+    if !record.is_converted() && codec.compression == Compression::None {
+        return Ok(unpacked_data);
+    }
+
+    let original_path = fs_path
+        .join(RAVENDS_DIR)
+        .join(ORIGINALS_DIR)
+        .join(path_escape::escape(&record.path));
+    let original_data = fs::read(path_escape::long_path(&original_path)).ok();
+    if *codec == record.codec() && manifest::sha256_hex(&unpacked_data) == record.unpacked_hash {
+        if let Some(original_data) = original_data {
+            return Ok(original_data);
+        }
+    }
+
+    let data = match record.format {
+        Format::Binary | Format::Narc => unpacked_data,
+        Format::Text => {
+            let original_data =
+                original_data.and_then(|original_data| record.codec().decompress(&original_data));
+            build_text_file(&unpacked_data, original_data.as_deref(), encoding)
+                .with_context(|| format!("failed to import {:?}", record.unpacked_path))?
+        }
+        Format::Plugin => {
+            let name = record
+                .plugin
+                .as_deref()
+                .ok_or_else(|| anyhow!("{:?} has no plugin recorded", record.path))?;
+            let original_data = original_data
+                .and_then(|original_data| record.codec().decompress(&original_data))
+                .unwrap_or_default();
+            plugins
+                .get(name)?
+                .pack(&unpacked_data, &original_data)
+                .with_context(|| format!("failed to build {:?}", record.path))?
+        }
+        Format::Hook => {
+            let name = record
+                .hook
+                .as_deref()
+                .ok_or_else(|| anyhow!("{:?} has no hook recorded", record.path))?;
+            let file_name = record.path.rsplit('/').next().unwrap_or(&record.path);
+            hooks
+                .get(name)?
+                .pack(file_name, &unpacked_data, cache)
+                .with_context(|| format!("failed to build {:?}", record.path))?
+        }
+    };
+    codec
+        .compress(&data, cache)
+        .with_context(|| format!("failed to compress {:?}", record.unpacked_path))
+}
+
+/// Builds a text file from the template it was exported to. Edited text files keep the layout
+/// of the original file, given decompressed, where possible.
+pub fn build_text_file(
+    template: &[u8],
+    original_data: Option<&[u8]>,
+    encoding: &TextEncoding,
+) -> anyhow::Result<Vec<u8>> {
+    let template = std::str::from_utf8(template).context("not valid UTF-8")?;
+    let strings = text::import_template(template)?;
+    let original_archive =
+        original_data.and_then(|original_data| TextArchive::parse(original_data, encoding).ok());
+    let archive = match original_archive {
+        Some(archive) => archive.with_strings(strings),
+        None => TextArchive::new(strings),
+    };
+    let layout = if archive.can_preserve_layout() {
+        TextLayout::Preserve
+    } else {
+        TextLayout::Deduplicated
+    };
+    Ok(archive.to_bytes(encoding, layout)?)
+}
+
+/// Contents of the NitroFS files of a ROM being packed, keyed by NitroFS path, along with the
+/// file ID each had in the original ROM.
+type PackedFiles = BTreeMap<String, (Vec<u8>, Option<u16>)>;
+
+#[cfg(feature = "graphics")]
+/// Returns the contents of a file being packed, decompressed, and how it was compressed.
+fn packed_file_contents(files: &PackedFiles, path: &str) -> anyhow::Result<(Vec<u8>, Codec)> {
+    let (data, _) = files
+        .get(path)
+        .ok_or_else(|| anyhow!("{path:?} is missing"))?;
+    Ok(match Codec::detect(data) {
+        Some((codec, decompressed_data)) => (decompressed_data, codec),
+        None => (data.clone(), Codec::NONE),
+    })
+}
+
+#[cfg(feature = "graphics")]
+/// Replaces the contents of a file being packed, compressing them as the file was compressed.
+fn set_packed_file_contents(
+    files: &mut PackedFiles,
+    path: &str,
+    data: Vec<u8>,
+    codec: &Codec,
+    cache: &CompressionCache,
+) -> anyhow::Result<()> {
+    let data = codec
+        .compress(&data, cache)
+        .with_context(|| format!("failed to compress {path:?}"))?;
+    if let Some((contents, _)) = files.get_mut(path) {
+        *contents = data;
+    }
+    Ok(())
+}
+
+/// Imports the palette files exported when unpacking that were edited since, replacing the
+/// colors of the NCLR files they were exported from.
+#[cfg(feature = "graphics")]
+fn import_palettes(
+    fs_path: &Path,
+    records: &[PaletteRecord],
+    files: &mut PackedFiles,
+    cache: &CompressionCache,
+) -> anyhow::Result<()> {
+    for record in records {
+        // A deleted palette file leaves its palette as it is.
+        let Ok(export_data) = fs::read(fs_path.join(&record.export_path)) else {
+            continue;
+        };
+        if manifest::sha256_hex(&export_data) == record.export_hash {
+            continue;
+        }
+
+        let context = || format!("failed to import {:?}", record.export_path);
+        let (palette_data, codec) =
+            packed_file_contents(files, &record.palette).with_context(context)?;
+        let mut nclr = Nclr::parse(&palette_data).with_context(context)?;
+        let colors = palette::import_palette(&export_data, record.format).with_context(context)?;
+        if colors.len() > nclr.colors.len() {
+            warn!(
+                "{:?} has {} colors, but {:?} only holds {}, ignoring the rest",
+                record.export_path,
+                colors.len(),
+                record.palette,
+                nclr.colors.len()
+            );
+        }
+        nclr.colors =
+            palette::apply_palette(&nclr.colors, &colors[..colors.len().min(nclr.colors.len())]);
+        let palette_data = nclr.to_bytes(&palette_data).with_context(context)?;
+        set_packed_file_contents(files, &record.palette, palette_data, &codec, cache)?;
+        info!(
+            "{:?}: imported into {:?}",
+            record.export_path, record.palette
+        );
+    }
+    Ok(())
+}
+
+/// Imports the PNGs exported when unpacking that were edited since, replacing the graphics,
+/// palette & screen files they were drawn from.
+#[cfg(feature = "graphics")]
+fn import_graphics(
+    fs_path: &Path,
+    records: &[GraphicsRecord],
+    files: &mut PackedFiles,
+    cache: &CompressionCache,
+) -> anyhow::Result<()> {
+    for record in records {
+        // A deleted PNG leaves its graphics as they are.
+        let Ok(png_data) = fs::read(fs_path.join(&record.png_path)) else {
+            continue;
+        };
+        if manifest::sha256_hex(&png_data) == record.png_hash {
+            continue;
+        }
+
+        let context = || format!("failed to import {:?}", record.png_path);
+        let (graphics_data, graphics_codec) =
+            packed_file_contents(files, &record.graphics).with_context(context)?;
+        let (palette_data, palette_codec) =
+            packed_file_contents(files, &record.palette).with_context(context)?;
+        let screen = record
+            .screen
+            .as_ref()
+            .map(|path| packed_file_contents(files, path))
+            .transpose()
+            .with_context(context)?;
+        let ncgr = Ncgr::parse(&graphics_data).with_context(context)?;
+        let nclr = Nclr::parse(&palette_data).with_context(context)?;
+        let nscr = screen
+            .as_ref()
+            .map(|(screen_data, _)| Nscr::parse(screen_data))
+            .transpose()
+            .with_context(context)?;
+
+        let imported =
+            gfx::import_png(&png_data, &ncgr, &nclr, nscr.as_ref()).with_context(context)?;
+        if imported.quantized {
+            warn!(
+                "{:?} uses colors missing from its palette, so its colors were reduced to fit",
+                record.png_path
+            );
+        }
+        let graphics_data = imported
+            .ncgr
+            .to_bytes(&graphics_data)
+            .with_context(context)?;
+        set_packed_file_contents(
+            files,
+            &record.graphics,
+            graphics_data,
+            &graphics_codec,
+            cache,
+        )?;
+        let palette_data = imported
+            .nclr
+            .to_bytes(&palette_data)
+            .with_context(context)?;
+        set_packed_file_contents(files, &record.palette, palette_data, &palette_codec, cache)?;
+        if let (Some(path), Some((screen_data, codec)), Some(nscr)) =
+            (&record.screen, screen, imported.nscr)
+        {
+            let screen_data = nscr.to_bytes(&screen_data).with_context(context)?;
+            set_packed_file_contents(files, path, screen_data, &codec, cache)?;
+        }
+        info!("{:?}: imported into {:?}", record.png_path, record.graphics);
+    }
+    Ok(())
+}
+
+/// Imports the icon exported when unpacking into the banner, if it was edited since.
+#[cfg(feature = "graphics")]
+fn import_icon(fs_path: &Path, record: &IconRecord, banner_data: &mut [u8]) -> anyhow::Result<()> {
+    let files = banner::read_export(&fs_path.join(&record.export_dir))?;
+    // Deleted icon files leave the icon as it is.
+    if files.is_empty() || banner::export_hash(&files) == record.export_hash {
+        return Ok(());
+    }
+    banner::import_icon(banner_data, &files)
+        .with_context(|| format!("failed to import {:?}", record.export_dir))?;
+    info!("{:?}: imported into the banner", record.export_dir);
+    Ok(())
+}
+
+/// Lists the files of an unpacked ROM that are not ravends bookkeeping data, as paths relative
+/// to `fs_path`.
+fn walk_unpacked_files(fs_path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    walk_files(
+        fs_path,
+        &[
+            MANIFEST_FILE_NAME,
+            HASHES_FILE_NAME,
+            RAVENDS_DIR,
+            SYSTEM_DIR,
+            ORPHAN_DIR,
+            GRAPHICS_DIR,
+            SOUND_DIR,
+            CACHE_DIR,
+        ],
+    )
+}
+
+/// Lists every file under `dir` recursively, relative to it and sorted by path. Entries at the
+/// top level of `dir` whose name is in `ignored` are skipped.
+pub fn walk_files(dir: &Path, ignored: &[&str]) -> anyhow::Result<Vec<PathBuf>> {
+    fn walk(
+        root: &Path,
+        dir: &Path,
+        ignored: &[&str],
+        files: &mut Vec<PathBuf>,
+    ) -> anyhow::Result<()> {
+        let entries = fs::read_dir(path_escape::long_path(dir))
+            .with_context(|| format!("failed to read {dir:?}"))?;
+        for entry in entries {
+            let path = dir.join(entry?.file_name());
+            let relative = path.strip_prefix(root).unwrap().to_path_buf();
+            if dir == root && ignored.iter().any(|name| relative == Path::new(name)) {
+                continue;
+            }
+            if path_escape::long_path(&path).is_dir() {
+                walk(root, &path, ignored, files)?;
+            } else {
+                files.push(relative);
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    walk(dir, dir, ignored, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+/// Finds the overlays of an unpacked ROM without a manifest, using the overlay tables to find
+/// out which processor each belongs to.
+fn find_overlays(fs_path: &Path) -> anyhow::Result<Vec<OverlayRecord>> {
+    let system_path = fs_path.join(SYSTEM_DIR);
+    let mut overlays = Vec::new();
+    for (processor, table_section) in [
+        (Processor::Arm9, Section::Arm9OverlayTable),
+        (Processor::Arm7, Section::Arm7OverlayTable),
+    ] {
+        let table_path = system_path.join(table_section.file_name());
+        if !table_path.exists() {
+            continue;
+        }
+        for file_id in rom::overlay_file_ids(&fs::read(table_path)?) {
+            overlays.push(OverlayRecord {
+                file_id,
+                processor,
+                unpacked_path: format!("{SYSTEM_DIR}/{}", overlay_file_name(file_id)),
+                original_offset: None,
+            });
+        }
+    }
+    Ok(overlays)
+}
+
+/// Whether data is BLZ-compressed, growing when decompressed.
+fn is_blz_compressed(data: &[u8]) -> bool {
+    blz::decompress_blz(data).is_ok_and(|decompressed| decompressed.len() > data.len())
+}
+
+/// Compresses the ARM9 binary again if it was compressed in the original ROM but is given
+/// decompressed, and makes sure its module parameters tell whether it's compressed, as the
+/// game decompresses it by them at boot.
+fn restore_arm9_compression(
+    arm9: &mut Vec<u8>,
+    ram_address: u32,
+    was_compressed: bool,
+    cache: &CompressionCache,
+) -> anyhow::Result<()> {
+    // Homebrew binaries have no module parameters, and are never compressed.
+    let Some(field) = memory::compressed_end_field(arm9) else {
+        return Ok(());
+    };
+    let compressed_end = rom::u32_at(arm9, field);
+    if compressed_end != 0 {
+        let compressed_len = compressed_end.wrapping_sub(ram_address) as usize;
+        if compressed_len <= arm9.len() && is_blz_compressed(&arm9[..compressed_len]) {
+            return Ok(());
+        }
+        warn!("the ARM9 binary is marked as compressed, but isn't");
+        arm9[field..field + 4].fill(0);
+    }
+    if !was_compressed {
+        return Ok(());
+    }
+    if field + 4 > memory::ARM9_UNCOMPRESSED_SIZE || arm9.len() <= memory::ARM9_UNCOMPRESSED_SIZE {
+        warn!("the ARM9 binary was compressed in the original ROM, but can't be compressed as given, storing it uncompressed");
+        return Ok(());
+    }
+    let Some(compressed) = cache
+        .compress_blz(&arm9[memory::ARM9_UNCOMPRESSED_SIZE..])
+        .context("failed to compress the ARM9 binary")?
+    else {
+        warn!("the ARM9 binary doesn't get any smaller when compressed, storing it uncompressed");
+        return Ok(());
+    };
+    arm9.truncate(memory::ARM9_UNCOMPRESSED_SIZE);
+    arm9.extend_from_slice(&compressed);
+    let compressed_end = ram_address + arm9.len() as u32;
+    arm9[field..field + 4].copy_from_slice(&compressed_end.to_le_bytes());
+    info!("the ARM9 binary was given decompressed, so it was compressed as in the original ROM");
+    Ok(())
+}
+
+/// Compresses an overlay given decompressed, updating its overlay table entry.
+fn compress_overlay(
+    entry: &mut OverlayEntry,
+    data: &mut Vec<u8>,
+    cache: &CompressionCache,
+) -> anyhow::Result<()> {
+    let compressed = cache
+        .compress_blz(data)
+        .with_context(|| format!("failed to compress overlay {}", entry.id))?;
+    entry.ram_size = data.len() as u32;
+    match compressed {
+        Some(compressed) => {
+            info!(
+                "overlay {} was given decompressed, so it was compressed as in the original ROM",
+                entry.id
+            );
+            entry.compressed_size = compressed.len() as u32;
+            *data = compressed;
+        }
+        None => {
+            warn!(
+                "overlay {} doesn't get any smaller when compressed, storing it uncompressed",
+                entry.id
+            );
+            entry.compressed = false;
+            entry.compressed_size = 0;
+        }
+    }
+    Ok(())
+}
+
+/// Compresses the overlays that are compressed according to their overlay table entries but
+/// are given decompressed, updating their sizes in the overlay tables, which the game reads to
+/// load them. Overlays that don't get any smaller are stored uncompressed instead.
+fn restore_overlay_compression(
+    items: &mut [(Item, Vec<u8>, Option<u32>)],
+    cache: &CompressionCache,
+) -> anyhow::Result<()> {
+    // Entries of the overlay tables by file ID, along with the item of their table and their
+    // offset in it.
+    let mut entries = BTreeMap::new();
+    for (index, (item, data, _)) in items.iter().enumerate() {
+        if let Item::Section(Section::Arm9OverlayTable | Section::Arm7OverlayTable) = item {
+            for (offset, entry) in data
+                .chunks_exact(OVERLAY_ENTRY_SIZE)
+                .enumerate()
+                .map(|(entry_index, entry)| (entry_index * OVERLAY_ENTRY_SIZE, entry))
+            {
+                let entry = OverlayEntry::parse(entry);
+                entries.insert(entry.file_id as u16, (index, offset, entry));
+            }
+        }
+    }
+
+    let updated = items
+        .par_iter_mut()
+        .filter_map(|(item, data, _)| match item {
+            Item::Overlay { file_id } => Some((*file_id, data)),
+            _ => None,
+        })
+        .filter_map(|(file_id, data)| {
+            let &(table, offset, entry) = entries.get(&file_id)?;
+            (entry.compressed && !is_blz_compressed(data)).then_some((table, offset, entry, data))
+        })
+        .map(|(table, offset, mut entry, data)| {
+            compress_overlay(&mut entry, data, cache)?;
+            Ok((table, offset, entry))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    for (table, offset, entry) in updated {
+        items[table].1[offset..offset + OVERLAY_ENTRY_SIZE].copy_from_slice(&entry.to_bytes());
+    }
+    Ok(())
+}
+
+/// Decides where to place each item of the ROM, given their sizes and the offsets they had
+/// in the original ROM (if any), without placing anything before `data_start`.
+///
+/// Items keep their original offset as long as they still fit there without overlapping any
+/// other item. The rest are placed after every other item, aligned to `alignment`, in the order
+/// given.
+fn plan_layout(items: &[(usize, Option<u32>)], data_start: usize, alignment: usize) -> Vec<usize> {
+    let mut starts = vec![None; items.len()];
+
+    let mut preferred = items
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, (_, offset))| offset.map(|offset| (offset as usize, idx)))
+        .collect::<Vec<_>>();
+    preferred.sort();
+    let mut used_end = data_start;
+    for (candidate_idx, &(offset, idx)) in preferred.iter().enumerate() {
+        let end = offset + items[idx].0;
+        let next_offset = preferred
+            .get(candidate_idx + 1)
+            .map_or(usize::MAX, |&(next_offset, _)| next_offset);
+        if offset >= used_end && end <= next_offset {
+            starts[idx] = Some(offset);
+            used_end = end;
+        }
+    }
+
+    let mut next_start = starts
+        .iter()
+        .zip(items)
+        .filter_map(|(start, (len, _))| start.map(|start| start + len))
+        .max()
+        .unwrap_or(data_start)
+        .max(data_start);
+    starts
+        .into_iter()
+        .zip(items)
+        .map(|(start, (len, _))| {
+            start.unwrap_or_else(|| {
+                let start = rom::align_up(next_start, alignment);
+                next_start = start + len;
+                start
+            })
+        })
+        .collect()
+}
+
+/// Something to be placed in the ROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Item {
+    Section(Section),
+    Overlay { file_id: u16 },
+    Fnt,
+    Fat,
+    File { file_id: u16 },
+}
+
+/// Options changing how `pack` lays out the ROM.
+#[derive(Debug, Clone, Default)]
+pub struct PackOptions {
+    /// Store files with identical contents only once, with every FAT entry of them pointing to
+    /// the same copy.
+    pub dedup: bool,
+    /// Alignment of the file data placed in the ROM. If `None`, the alignment of the original
+    /// ROM is used.
+    pub alignment: Option<usize>,
+    /// Byte filling the space between file data. If `None`, the one of the original ROM is used.
+    pub pad_byte: Option<u8>,
+    /// Key table used to encrypt the secure area again, if it was decrypted when unpacking.
+    pub key_table: Option<KeyTable>,
+    /// State to give the secure area, decrypting or encrypting it as needed. If `None`, the one
+    /// recorded when unpacking is given.
+    pub secure_area: Option<SecureAreaForm>,
+    /// Plugins converting back the files they converted when unpacking.
+    pub plugins: Plugins,
+    /// Hooks converting back the files they converted when unpacking.
+    pub hooks: Hooks,
+    /// Profile of the game, giving the encoding of its text files and which files it expects
+    /// to be compressed.
+    pub profile: Option<Profile>,
+}
+
+/// Packs a directory created by `unpack` back into a ROM.
+///
+/// If the directory has a manifest, the conversions done when unpacking are reversed. Files
+/// not listed in the manifest are packed as-is. Compressed files are reused from `cache` when
+/// their contents did not change.
+pub fn pack(
+    fs_path: &Path,
+    cache: &CompressionCache,
+    options: &PackOptions,
+) -> anyhow::Result<Vec<u8>> {
+    let manifest = Manifest::load(fs_path)?.unwrap_or_default();
+    let alignment = options
+        .alignment
+        .or_else(|| {
+            manifest
+                .layout
+                .as_ref()
+                .map(|layout| layout.alignment as usize)
+        })
+        .unwrap_or(rom::FILE_ALIGNMENT);
+    let pad_byte = options
+        .pad_byte
+        .or_else(|| manifest.layout.as_ref().map(|layout| layout.pad_byte))
+        .unwrap_or(rom::PAD_BYTE);
+    let system_path = fs_path.join(SYSTEM_DIR);
+    let encoding = match &options.profile {
+        Some(profile) => profile.text_encoding()?,
+        None => TextEncoding::default(),
+    };
+
+    // Gather the contents of every NitroFS file, keyed by NitroFS path, along with the file ID
+    // it had in the original ROM.
+    // Restoring files is dominated by compression, so they are restored in parallel.
+    let started = Instant::now();
+    let stats_before = cache.stats();
+    let restored = manifest
+        .files
+        .par_iter()
+        .map(|record| {
+            let compression = manifest
+                .compression_policy(Path::new(&record.path))?
+                .resolve(record.compression);
+            // Files keeping their compression keep the layout of its stream too.
+            let codec = if compression == record.compression {
+                record.codec()
+            } else {
+                Codec::new(compression)
+            };
+            restore_file(
+                fs_path,
+                record,
+                &codec,
+                cache,
+                &encoding,
+                &options.plugins,
+                &options.hooks,
+            )
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut files = PackedFiles::new();
+    for (record, data) in manifest.files.iter().zip(restored) {
+        files.insert(record.path.clone(), (data, Some(record.file_id)));
+    }
+    let stats = cache.stats();
+    let (compressed_files, compressed_bytes) = (
+        stats.files - stats_before.files,
+        stats.bytes - stats_before.bytes,
+    );
+    if compressed_files > 0 {
+        let seconds = started.elapsed().as_secs_f64();
+        info!(
+            "{compressed_files} files compressed (0x{compressed_bytes:X} bytes) in {seconds:.2}s, {:.1} MiB/s",
+            compressed_bytes as f64 / (1024.0 * 1024.0) / seconds.max(f64::EPSILON)
+        );
+    }
+    let manifest_paths = manifest
+        .files
+        .iter()
+        .map(|record| PathBuf::from(&record.unpacked_path))
+        .collect::<Vec<_>>();
+    for path in walk_unpacked_files(fs_path)? {
+        // Files inside extracted archives belong to the archive, not to the ROM.
+        if !manifest_paths
+            .iter()
+            .any(|unpacked| path.starts_with(unpacked))
+        {
+            let mut data = fs::read(path_escape::long_path(&fs_path.join(&path)))?;
+            let nitro_path = path_escape::nitro_path(&path);
+            let compression = match manifest.compression_policy(Path::new(&nitro_path))? {
+                CompressionPolicy::AsOriginal
+                    if options.profile.as_ref().is_some_and(|profile| {
+                        profile.is_compressed_file(Path::new(&nitro_path))
+                    }) =>
+                {
+                    Compression::Lz10
+                }
+                policy => policy.resolve(Compression::None),
+            };
+            // New files may already be stored compressed.
+            if compression != Compression::None && compression.decompress(&data).is_none() {
+                data = cache
+                    .compress(&data, compression)
+                    .with_context(|| format!("failed to compress {path:?}"))?;
+            }
+            files.insert(nitro_path, (data, None));
+        }
+    }
+    #[cfg(feature = "graphics")]
+    {
+        import_palettes(fs_path, &manifest.palettes, &mut files, cache)?;
+        import_graphics(fs_path, &manifest.graphics, &mut files, cache)?;
+    }
+    #[cfg(not(feature = "graphics"))]
+    if !manifest.palettes.is_empty() || !manifest.graphics.is_empty() || manifest.icon.is_some() {
+        warn!("ravends was built without the `graphics` feature, so edits to exported PNGs, palettes and icons are not imported");
+    }
+
+    // Overlays come before any NitroFS file in the FAT.
+    let overlay_records = if manifest.overlays.is_empty() {
+        find_overlays(fs_path)?
+    } else {
+        manifest.overlays.clone()
+    };
+    let mut overlays = Vec::with_capacity(overlay_records.len());
+    for record in overlay_records {
+        let data = fs::read(fs_path.join(&record.unpacked_path))
+            .with_context(|| format!("failed to read overlay {}", record.file_id))?;
+        overlays.push((record, data));
+    }
+    let first_file_id = overlays
+        .iter()
+        .map(|(record, _)| record.file_id + 1)
+        .max()
+        .unwrap_or(0);
+
+    let preferred_dir_ids = manifest
+        .directories
+        .iter()
+        .map(|record| (record.path.clone(), record.dir_id))
+        .collect();
+    let built_fnt = fnt::build_fnt(
+        files
+            .iter()
+            .map(|(path, (_, preferred_id))| (path.as_str(), *preferred_id)),
+        &preferred_dir_ids,
+        first_file_id,
+    )
+    .context("failed to build FNT")?;
+    for renumbered in &built_fnt.renumbered {
+        warn!(
+            "{:?} was renumbered from file ID {} to {}",
+            renumbered.path, renumbered.preferred_id, renumbered.assigned_id
+        );
+    }
+
+    // The original FNT can be kept as long as no file was added, removed or renumbered.
+    let original_fnt_path = fs_path.join(RAVENDS_DIR).join(ORIGINAL_FNT_FILE_NAME);
+    let fnt_data = if !manifest.files.is_empty()
+        && files
+            .values()
+            .all(|(_, preferred_id)| preferred_id.is_some())
+        && built_fnt.renumbered.is_empty()
+        && original_fnt_path.exists()
+    {
+        fs::read(original_fnt_path)?
+    } else {
+        built_fnt.data
+    };
+
+    let header_path = system_path.join(Section::Header.file_name());
+    let mut rom_data =
+        if !header_path.exists() && system_path.join(Section::Arm9.file_name()).exists() {
+            // Homebrew can be packed from its binaries alone.
+            info!("no header found, using a homebrew header");
+            let title = fs_path.file_name().unwrap_or_default().to_string_lossy();
+            rom::homebrew_header(&title)
+        } else {
+            fs::read(&header_path).with_context(|| format!("no header found in {system_path:?}"))?
+        };
+    if rom_data.len() < rom::HEADER_CRC_OFFSET + 2 {
+        return Err(anyhow!("header file is too small"));
+    }
+    let mut fat_len = built_fnt
+        .file_ids
+        .last()
+        .map_or(first_file_id, |(_, file_id)| file_id + 1);
+    // Files without a path keep their IDs, which the game loads them by, unless a file with a
+    // path took it.
+    let mut taken_ids = built_fnt
+        .file_ids
+        .iter()
+        .map(|(_, file_id)| *file_id)
+        .chain(overlays.iter().map(|(record, _)| record.file_id))
+        .collect::<BTreeSet<_>>();
+    let mut orphans = Vec::with_capacity(manifest.orphans.len());
+    for record in &manifest.orphans {
+        let data = fs::read(fs_path.join(&record.unpacked_path))
+            .with_context(|| format!("failed to read {:?}", record.unpacked_path))?;
+        orphans.push((record, data));
+    }
+    for (record, _) in &orphans {
+        if !taken_ids.contains(&record.file_id) {
+            fat_len = fat_len.max(record.file_id + 1);
+        }
+    }
+    let orphans = orphans
+        .into_iter()
+        .map(|(record, data)| {
+            let file_id = if taken_ids.insert(record.file_id) {
+                record.file_id
+            } else {
+                warn!(
+                    "{:?} was renumbered from file ID {} to {fat_len}, as a file with a path took its ID",
+                    record.unpacked_path, record.file_id
+                );
+                fat_len += 1;
+                fat_len - 1
+            };
+            (file_id, data, record.original_offset)
+        })
+        .collect::<Vec<_>>();
+
+    // Gather everything that goes in the ROM in its default order, along with where it was in
+    // the original ROM. Each processor's overlays are placed right after its overlay table.
+    let layout = manifest.layout.as_ref();
+    let section_offset = |section: Section| {
+        layout.and_then(|layout| {
+            layout
+                .sections
+                .iter()
+                .find(|record| record.section == section)
+                .map(|record| record.offset)
+        })
+    };
+    let mut items: Vec<(Item, Vec<u8>, Option<u32>)> = Vec::new();
+    let push_section = |items: &mut Vec<_>, section: Section| -> anyhow::Result<()> {
+        let path = system_path.join(section.file_name());
+        if path.exists() {
+            let data = fs::read(path)?;
+            items.push((Item::Section(section), data, section_offset(section)));
+        }
+        Ok(())
+    };
+    let push_overlays = |items: &mut Vec<_>, processor: Processor| {
+        for (record, data) in overlays.iter().filter(|(r, _)| r.processor == processor) {
+            let item = Item::Overlay {
+                file_id: record.file_id,
+            };
+            items.push((item, data.clone(), record.original_offset));
+        }
+    };
+    push_section(&mut items, Section::Arm9)?;
+    if let Some((Item::Section(Section::Arm9), data, _)) = items.last_mut() {
+        restore_arm9_compression(
+            data,
+            rom::u32_at(&rom_data, rom::ARM9_RAM_ADDR_OFFSET),
+            layout.is_some_and(|layout| layout.compressed_arm9),
+            cache,
+        )?;
+    }
+    // The footer following the ARM9 binary moves along with it.
+    let extra_data = layout.map_or(&[][..], |layout| layout.extra_data.as_slice());
+    let read_extra_data = |record: &ExtraDataRecord| {
+        fs::read(fs_path.join(&record.unpacked_path))
+            .with_context(|| format!("failed to read {:?}", record.unpacked_path))
+    };
+    let mut arm9_footer_len = 0;
+    if let Some(record) = extra_data
+        .iter()
+        .find(|record| record.kind == ExtraData::Arm9Footer)
+    {
+        if let Some((Item::Section(Section::Arm9), data, _)) = items.last_mut() {
+            let footer = read_extra_data(record)?;
+            arm9_footer_len = footer.len() as u32;
+            data.extend_from_slice(&footer);
+        }
+    }
+    push_section(&mut items, Section::Arm9OverlayTable)?;
+    push_overlays(&mut items, Processor::Arm9);
+    push_section(&mut items, Section::Arm7)?;
+    push_section(&mut items, Section::Arm7OverlayTable)?;
+    push_overlays(&mut items, Processor::Arm7);
+    // ROMs without a NitroFS, such as homebrew ones, keep having none as long as nothing needs
+    // one.
+    if fat_len > 0 || rom::has_filesystem(&rom_data) {
+        items.push((Item::Fnt, fnt_data, layout.map(|l| l.fnt_offset)));
+        // The FAT is written once all file locations are known.
+        let fat_data = vec![0; fat_len as usize * 8];
+        items.push((Item::Fat, fat_data, layout.map(|l| l.fat_offset)));
+    }
+    push_section(&mut items, Section::Banner)?;
+    restore_overlay_compression(&mut items, cache)?;
+    #[cfg(feature = "graphics")]
+    if let (Some(record), Some((Item::Section(Section::Banner), data, _))) =
+        (&manifest.icon, items.last_mut())
+    {
+        import_icon(fs_path, record, data)?;
+    }
+    let file_offsets = manifest
+        .files
+        .iter()
+        .map(|record| (record.path.as_str(), record.original_offset))
+        .collect::<BTreeMap<_, _>>();
+    // Duplicate files are left out of the layout, and get the FAT entry of the first file with
+    // the same contents instead.
+    let mut stored_ids = BTreeMap::new();
+    let mut duplicates = Vec::new();
+    let mut saved_len = 0;
+    for (path, file_id) in built_fnt.file_ids {
+        let (data, preferred_id) = files.remove(&path).unwrap();
+        if options.dedup {
+            match stored_ids.entry(manifest::sha256_hex(&data)) {
+                Entry::Occupied(entry) => {
+                    duplicates.push((file_id, *entry.get()));
+                    saved_len += data.len();
+                    continue;
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(file_id);
+                }
+            }
+        }
+        // Files that changed ID are not placed back at their original offset.
+        let offset = (preferred_id == Some(file_id))
+            .then(|| file_offsets.get(path.as_str()).copied().flatten())
+            .flatten();
+        items.push((Item::File { file_id }, data, offset));
+    }
+    // Empty files take no space, and keep the location their FAT entry gave.
+    let mut empty_orphans = Vec::new();
+    for (file_id, data, offset) in orphans {
+        if data.is_empty() {
+            empty_orphans.push((file_id, offset.unwrap_or(0)));
+        } else {
+            items.push((Item::File { file_id }, data, offset));
+        }
+    }
+    // The DSi area comes after every NitroFS file.
+    let unit_code = rom::UnitCode::of(&rom_data);
+    if unit_code.is_dsi() {
+        for section in Section::ALL.into_iter().filter(|s| s.is_dsi_only()) {
+            push_section(&mut items, section)?;
+        }
+    }
+
+    let starts = plan_layout(
+        &items
+            .iter()
+            .map(|(_, data, offset)| (data.len(), *offset))
+            .collect::<Vec<_>>(),
+        rom_data.len(),
+        alignment,
+    );
+
+    let mut fat = vec![(0u32, 0u32); fat_len as usize];
+    let mut fat_start = 0;
+    let mut ntr_end = 0;
+    // Where the DSi sections were and where they are now, to move the modcrypt areas with them.
+    let mut moved_dsi_sections = Vec::new();
+    let mut placed = Vec::with_capacity(items.len());
+    for ((item, data, _), start) in items.iter().zip(starts) {
+        let end = start + data.len();
+        placed.push(start..end);
+        if rom_data.len() < end {
+            rom_data.resize(end, pad_byte);
+        }
+        rom_data[start..end].copy_from_slice(data);
+
+        if !matches!(*item, Item::Section(section) if section.is_dsi_only()) {
+            ntr_end = ntr_end.max(end);
+        }
+
+        let (start, end) = (start as u32, end as u32);
+        match *item {
+            Item::Section(section) => {
+                let (addr_field, size_field) = section.header_fields();
+                if let (true, Some(addr), Some(size)) =
+                    (section.is_dsi_only(), addr_field, size_field)
+                {
+                    let original_start = rom::u32_at(&rom_data, addr);
+                    let original_end = original_start
+                        .checked_add(rom::u32_at(&rom_data, size))
+                        .ok_or_else(|| {
+                            anyhow!("the header places the {} past 4 GiB", section.name())
+                        })?;
+                    moved_dsi_sections.push((original_start..original_end, start));
+                }
+                if let Some(field) = addr_field {
+                    rom::set_u32_at(&mut rom_data, field, start);
+                }
+                if let Some(field) = size_field {
+                    let footer_len = if section == Section::Arm9 {
+                        arm9_footer_len
+                    } else {
+                        0
+                    };
+                    rom::set_u32_at(&mut rom_data, field, end - start - footer_len);
+                }
+            }
+            Item::Overlay { file_id } | Item::File { file_id } => {
+                fat[file_id as usize] = (start, end);
+            }
+            Item::Fnt => {
+                rom::set_u32_at(&mut rom_data, rom::FNT_ADDR_OFFSET, start);
+                rom::set_u32_at(&mut rom_data, rom::FNT_SIZE_OFFSET, end - start);
+            }
+            Item::Fat => {
+                rom::set_u32_at(&mut rom_data, rom::FAT_ADDR_OFFSET, start);
+                rom::set_u32_at(&mut rom_data, rom::FAT_SIZE_OFFSET, end - start);
+                fat_start = start as usize;
+            }
+        }
+    }
+    // Sections that are not present are cleared from the header.
+    for section in Section::ALL {
+        let present = items
+            .iter()
+            .any(|(item, _, _)| *item == Item::Section(section));
+        // The extended header of NDS ROMs is left untouched.
+        let has_fields = unit_code.is_dsi() || !section.is_dsi_only();
+        if section != Section::Header && !present && has_fields {
+            let (addr_field, size_field) = section.header_fields();
+            for field in [addr_field, size_field].into_iter().flatten() {
+                rom::set_u32_at(&mut rom_data, field, 0);
+            }
+        }
+    }
+    for &(file_id, stored_id) in &duplicates {
+        fat[file_id as usize] = fat[stored_id as usize];
+    }
+    for (file_id, offset) in empty_orphans {
+        fat[file_id as usize] = (offset, offset);
+    }
+    if options.dedup {
+        info!(
+            "{} duplicate files stored once, saving 0x{saved_len:X} bytes",
+            duplicates.len()
+        );
+    }
+    for (idx, (start, end)) in fat.into_iter().enumerate() {
+        let entry_offset = fat_start + idx * 8;
+        rom::set_u32_at(&mut rom_data, entry_offset, start);
+        rom::set_u32_at(&mut rom_data, entry_offset + 4, end);
+    }
+    // Modcrypt areas are kept encrypted as they are, but follow the section they are in.
+    for field in rom::MODCRYPT_ADDR_OFFSETS {
+        let offset = rom::u32_at(&rom_data, field);
+        if let Some((original, start)) = moved_dsi_sections
+            .iter()
+            .find(|(original, _)| original.contains(&offset))
+        {
+            rom::set_u32_at(&mut rom_data, field, offset - original.start + start);
+        }
+    }
+
+    // The used ROM size from the original header is kept if everything still fits in it. In
+    // DSi ROMs, it only covers the NDS area, and another field covers the whole ROM.
+    let used_rom_size = if unit_code.is_dsi() {
+        ntr_end
+    } else {
+        rom_data.len()
+    }
+    .max(rom::u32_at(&rom_data, rom::USED_ROM_SIZE_OFFSET) as usize);
+    rom::set_u32_at(
+        &mut rom_data,
+        rom::USED_ROM_SIZE_OFFSET,
+        used_rom_size as u32,
+    );
+    if unit_code.is_dsi() {
+        let dsi_used_rom_size = rom_data
+            .len()
+            .max(rom::u32_at(&rom_data, rom::DSI_USED_ROM_SIZE_OFFSET) as usize);
+        rom::set_u32_at(
+            &mut rom_data,
+            rom::DSI_USED_ROM_SIZE_OFFSET,
+            dsi_used_rom_size as u32,
+        );
+    }
+    let used_rom_size = used_rom_size.max(rom_data.len());
+    rom_data.resize(used_rom_size, pad_byte);
+
+    // The RSA signature follows the used area wherever it ends now, and the rest of the data
+    // outside of everything the header and FAT locate goes back where it was, unless something
+    // else was placed there.
+    let mut extra_data = extra_data
+        .iter()
+        .filter(|record| record.kind != ExtraData::Arm9Footer)
+        .collect::<Vec<_>>();
+    extra_data.sort_by_key(|record| record.kind != ExtraData::RsaSignature);
+    placed.push(0..rom::u32_at(&rom_data, rom::HEADER_SIZE_OFFSET) as usize);
+    for record in extra_data {
+        let data = read_extra_data(record)?;
+        let start = match record.kind {
+            ExtraData::RsaSignature => used_rom_size,
+            _ => record.offset as usize,
+        };
+        let range = start..start + data.len();
+        if let Some(other) = placed
+            .iter()
+            .find(|other| other.start < range.end && range.start < other.end)
+        {
+            warn!(
+                "{:?} overlaps the data placed at 0x{:08X}, so it was left out",
+                record.unpacked_path, other.start
+            );
+            continue;
+        }
+        if rom_data.len() < range.end {
+            rom_data.resize(range.end, pad_byte);
+        }
+        rom_data[range.clone()].copy_from_slice(&data);
+        placed.push(range);
+    }
+    rom_data[rom::DEVICE_CAPACITY_OFFSET] =
+        rom_data[rom::DEVICE_CAPACITY_OFFSET].max(rom::device_capacity_for(rom_data.len()));
+    rom::fix_header_crc(&mut rom_data);
+
+    // Keep any padding the original ROM had after its used area.
+    if let Some(layout) = layout {
+        if layout.rom_size as usize > rom_data.len() {
+            rom_data.resize(layout.rom_size as usize, pad_byte);
+        }
+    }
+
+    // The secure area checksum covers the padding too, so it's encrypted last.
+    if let Some(form) = options.secure_area {
+        secure_area::normalize(&mut rom_data, form, options.key_table.as_ref())
+            .with_context(|| format!("failed to make the secure area {}", form.name()))?;
+    } else if layout.is_some_and(|layout| layout.decrypted_secure_area) {
+        let key_table = options.key_table.as_ref().ok_or_else(|| {
+            anyhow!("the secure area was decrypted when unpacking; its key table is needed")
+        })?;
+        secure_area::encrypt(&mut rom_data, key_table).context("failed to encrypt secure area")?;
+    }
+
+    Ok(rom_data)
+}
@@ -0,0 +1,205 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Number of colors an Adobe color table holds.
+const ACT_COLOR_COUNT: usize = 256;
+/// Size of an Adobe color table without its trailer.
+const ACT_SIZE: usize = ACT_COLOR_COUNT * 3;
+/// Colors per row in GIMP palettes, matching the 16-color palettes of the console.
+const GPL_COLUMNS: usize = 16;
+
+#[derive(Error, Debug)]
+pub enum PaletteError {
+    #[error("Adobe color tables hold at most 256 colors, but the palette has {0}")]
+    TooManyColors(usize),
+    #[error("Adobe color table too small (found: {0} bytes, expected: 768 bytes)")]
+    InvalidActSize(usize),
+    #[error("missing {0:?} header")]
+    MissingHeader(&'static str),
+    #[error("invalid color count {0:?}")]
+    InvalidColorCount(String),
+    #[error("line {line}: invalid color {text:?}")]
+    InvalidColor { line: usize, text: String },
+    #[error("the palette has no colors")]
+    Empty,
+}
+
+/// Palette file formats of image editors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum PaletteFormat {
+    /// Adobe color table (`.act`), as used by Photoshop
+    Act,
+    /// JASC palette (`.pal`), as used by Paint Shop Pro, Aseprite & Usenti
+    Pal,
+    /// GIMP palette (`.gpl`), also read by Krita & Inkscape
+    Gpl,
+}
+
+impl PaletteFormat {
+    pub const ALL: [PaletteFormat; 3] =
+        [PaletteFormat::Act, PaletteFormat::Pal, PaletteFormat::Gpl];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            PaletteFormat::Act => "act",
+            PaletteFormat::Pal => "pal",
+            PaletteFormat::Gpl => "gpl",
+        }
+    }
+
+    /// Guesses the format of a palette file from its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extension() == extension)
+    }
+}
+
+fn to_rgb(color: u16) -> [u8; 3] {
+    let expand = |channel: u16| ((channel << 3) | (channel >> 2)) as u8;
+    [
+        expand(color & 0x1F),
+        expand((color >> 5) & 0x1F),
+        expand((color >> 10) & 0x1F),
+    ]
+}
+
+fn to_bgr555([r, g, b]: [u8; 3]) -> u16 {
+    (r >> 3) as u16 | ((g >> 3) as u16) << 5 | ((b >> 3) as u16) << 10
+}
+
+/// Writes BGR555 colors as a palette file. `name` is the palette's name in formats storing one.
+pub fn export_palette(
+    colors: &[u16],
+    format: PaletteFormat,
+    name: &str,
+) -> Result<Vec<u8>, PaletteError> {
+    let rgb = colors.iter().map(|&color| to_rgb(color));
+    Ok(match format {
+        PaletteFormat::Act => {
+            if colors.len() > ACT_COLOR_COUNT {
+                return Err(PaletteError::TooManyColors(colors.len()));
+            }
+            let mut data = rgb.flatten().collect::<Vec<_>>();
+            data.resize(ACT_SIZE, 0);
+            data.extend_from_slice(&(colors.len() as u16).to_be_bytes());
+            // The first color of each palette is transparent.
+            data.extend_from_slice(&0u16.to_be_bytes());
+            data
+        }
+        PaletteFormat::Pal => {
+            let mut text = format!("JASC-PAL\r\n0100\r\n{}\r\n", colors.len());
+            for [r, g, b] in rgb {
+                text += &format!("{r} {g} {b}\r\n");
+            }
+            text.into_bytes()
+        }
+        PaletteFormat::Gpl => {
+            let mut text = format!("GIMP Palette\nName: {name}\nColumns: {GPL_COLUMNS}\n#\n");
+            for (index, [r, g, b]) in rgb.enumerate() {
+                text += &format!("{r:3} {g:3} {b:3}\tIndex {index}\n");
+            }
+            text.into_bytes()
+        }
+    })
+}
+
+/// Parses a line of three decimal color components.
+fn parse_rgb(line_number: usize, line: &str) -> Result<[u8; 3], PaletteError> {
+    let invalid = || PaletteError::InvalidColor {
+        line: line_number + 1,
+        text: line.to_owned(),
+    };
+    let mut components = line
+        .split_whitespace()
+        .map(|component| component.parse::<u8>());
+    let mut next = || {
+        components
+            .next()
+            .ok_or_else(invalid)?
+            .map_err(|_| invalid())
+    };
+    Ok([next()?, next()?, next()?])
+}
+
+/// Reads the colors of a palette file, in BGR555 format.
+pub fn import_palette(data: &[u8], format: PaletteFormat) -> Result<Vec<u16>, PaletteError> {
+    let colors: Vec<u16> = match format {
+        PaletteFormat::Act => {
+            if data.len() < ACT_SIZE {
+                return Err(PaletteError::InvalidActSize(data.len()));
+            }
+            let count = match data.get(ACT_SIZE..ACT_SIZE + 2) {
+                Some(count) => (u16::from_be_bytes(count.try_into().unwrap()) as usize)
+                    .clamp(1, ACT_COLOR_COUNT),
+                None => ACT_COLOR_COUNT,
+            };
+            data[..count * 3]
+                .chunks_exact(3)
+                .map(|rgb| to_bgr555(rgb.try_into().unwrap()))
+                .collect()
+        }
+        PaletteFormat::Pal => {
+            let text = String::from_utf8_lossy(data);
+            let mut lines = text.lines().enumerate();
+            if lines.next().map(|(_, line)| line.trim()) != Some("JASC-PAL") {
+                return Err(PaletteError::MissingHeader("JASC-PAL"));
+            }
+            lines.next();
+            let (_, count) = lines.next().ok_or(PaletteError::Empty)?;
+            let count = count
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| PaletteError::InvalidColorCount(count.to_owned()))?;
+            lines
+                .filter(|(_, line)| !line.trim().is_empty())
+                .take(count)
+                .map(|(line_number, line)| parse_rgb(line_number, line).map(to_bgr555))
+                .collect::<Result<_, _>>()?
+        }
+        PaletteFormat::Gpl => {
+            let text = String::from_utf8_lossy(data);
+            let mut lines = text.lines().enumerate();
+            if lines.next().map(|(_, line)| line.trim()) != Some("GIMP Palette") {
+                return Err(PaletteError::MissingHeader("GIMP Palette"));
+            }
+            lines
+                .filter(|(_, line)| {
+                    let line = line.trim();
+                    !line.is_empty()
+                        && !line.starts_with('#')
+                        && !line.starts_with("Name:")
+                        && !line.starts_with("Columns:")
+                })
+                .map(|(line_number, line)| parse_rgb(line_number, line).map(to_bgr555))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    if colors.is_empty() {
+        return Err(PaletteError::Empty);
+    }
+    Ok(colors)
+}
+
+/// Replaces the colors of a palette with imported ones. Colors the imported palette doesn't
+/// reach are kept, as are colors it leaves unchanged, with the unused top bit of their BGR555
+/// value.
+pub fn apply_palette(original: &[u16], imported: &[u16]) -> Vec<u16> {
+    let mut colors = imported
+        .iter()
+        .enumerate()
+        .map(|(index, &color)| match original.get(index) {
+            Some(&original) if original & 0x7FFF == color => original,
+            _ => color,
+        })
+        .collect::<Vec<_>>();
+    if let Some(rest) = original.get(colors.len()..) {
+        colors.extend_from_slice(rest);
+    }
+    colors
+}
@@ -0,0 +1,80 @@
+use clap::ValueEnum;
+use thiserror::Error;
+
+use crate::{
+    bps::{self, ApplyBpsError},
+    ips::{self, ApplyIpsError},
+    vcdiff::{self, VcdiffError},
+};
+
+/// Formats patches can be created in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PatchFormat {
+    /// BPS, with checksums of the original ROM, the modified ROM & the patch itself
+    Bps,
+    /// VCDIFF, as used by xdelta3
+    #[value(alias = "xdelta")]
+    Vcdiff,
+}
+
+#[derive(Error, Debug)]
+pub enum ApplyPatchError {
+    #[error("unknown patch format")]
+    UnknownFormat,
+    #[error("failed to apply BPS patch")]
+    Bps(#[from] ApplyBpsError),
+    #[error("failed to apply VCDIFF patch")]
+    Vcdiff(#[from] VcdiffError),
+    #[error("failed to apply IPS patch")]
+    Ips(#[from] ApplyIpsError),
+}
+
+impl PatchFormat {
+    /// Picks a format from the extension of a file name, if it has a known one.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "bps" => Some(Self::Bps),
+            "xdelta" | "vcdiff" => Some(Self::Vcdiff),
+            _ => None,
+        }
+    }
+
+    /// Extension files in this format are given.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Bps => "bps",
+            Self::Vcdiff => "xdelta",
+        }
+    }
+
+    /// Detects the format of a patch from its magic number.
+    pub fn detect(patch: &[u8]) -> Option<Self> {
+        if patch.starts_with(bps::MAGIC) {
+            Some(Self::Bps)
+        } else if patch.starts_with(&vcdiff::MAGIC) {
+            Some(Self::Vcdiff)
+        } else {
+            None
+        }
+    }
+
+    /// Creates a patch turning `original` into `modified`.
+    pub fn create(self, original: &[u8], modified: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Bps => bps::encode(original, modified),
+            Self::Vcdiff => vcdiff::encode(original, modified),
+        }
+    }
+}
+
+/// Applies a patch in any of the supported formats, or an IPS patch, to `rom_data`, returning the
+/// patched ROM.
+pub fn apply(rom_data: &[u8], patch: &[u8]) -> Result<Vec<u8>, ApplyPatchError> {
+    if patch.starts_with(ips::MAGIC) {
+        return Ok(ips::decode(rom_data, patch)?);
+    }
+    match PatchFormat::detect(patch).ok_or(ApplyPatchError::UnknownFormat)? {
+        PatchFormat::Bps => Ok(bps::decode(rom_data, patch)?),
+        PatchFormat::Vcdiff => Ok(vcdiff::decode(rom_data, patch)?),
+    }
+}
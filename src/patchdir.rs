@@ -0,0 +1,137 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use std::fs;
+
+use crate::{
+    cache::CompressionCache,
+    lz::CompressionLevel,
+    manifest::{Compression, Format},
+    pack,
+    rom::{self, Placement},
+    text::TextEncoding,
+    unpack::{convert_file, Conversion},
+};
+
+/// How a file of a patch directory was put into the ROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Import {
+    /// The file replaced the one at the same path as it is.
+    Raw,
+    /// The file was converted back to the format `unpack` converted the original from, and
+    /// compressed if the original was.
+    Converted {
+        format: Format,
+        compression: Compression,
+    },
+}
+
+/// A file of the ROM replaced by one of a patch directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchedFile {
+    pub nitro_path: String,
+    /// Path of the file of the patch directory, relative to it.
+    pub patch_path: PathBuf,
+    pub import: Import,
+    pub placement: Placement,
+}
+
+/// What applying a patch directory did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PatchDirReport {
+    pub patched: Vec<PatchedFile>,
+    /// Files of the patch directory matching no file of the ROM, which were left out.
+    pub unmatched: Vec<PathBuf>,
+}
+
+/// Replaces the NitroFS files of a ROM with the files of a directory mirroring its layout, as
+/// layeredfs-style mods are laid out, leaving every other file as it is.
+///
+/// A file at the NitroFS path of a file of the ROM replaces it as it is. Files can also be
+/// given in the form `unpack` converts them to, such as `msg/en.txt` for the text file
+/// `msg/en.bin`, or `a.decomp` for the LZ10-compressed file `a.bin`: these are converted back,
+/// keeping the layout of the original text file, and compressed like the original.
+pub fn patch_dir(
+    rom_data: &mut Vec<u8>,
+    patch_dir: &Path,
+    encoding: &TextEncoding,
+    level: CompressionLevel,
+) -> anyhow::Result<PatchDirReport> {
+    let patch_files = pack::walk_files(patch_dir, &[])?
+        .into_iter()
+        .map(|path| rom::nitro_path(&path))
+        .collect::<BTreeSet<_>>();
+    // Replacing a file only changes its own FAT entry, so the entries of the others stay valid.
+    let fs = rom::filesystem(rom_data)?;
+    let mut entries = fs.files();
+    entries.sort_by_key(|entry| entry.id);
+
+    let compressor = CompressionCache::disabled().with_level(level);
+    let mut report = PatchDirReport::default();
+    let mut matched = BTreeSet::new();
+    for entry in entries {
+        let nitro_path = rom::nitro_path(&entry.path);
+        let (patch_path, import, new_data) = if patch_files.contains(&nitro_path) {
+            let data = fs::read(patch_dir.join(&nitro_path))
+                .with_context(|| format!("failed to read {nitro_path:?}"))?;
+            (nitro_path.clone(), Import::Raw, data)
+        } else {
+            // Only files with a converted counterpart in the patch directory are converted, as
+            // converting every file of the ROM would take long.
+            let has_counterpart = ["txt", "decomp"].iter().any(|extension| {
+                patch_files.contains(&rom::nitro_path(&entry.path.with_extension(extension)))
+            });
+            if !has_counterpart {
+                continue;
+            }
+            let original_data = rom::checked_file_data(rom_data, entry)?.to_vec();
+            let mut converted_path = entry.path.clone();
+            let converted = convert_file(
+                &original_data,
+                &mut converted_path,
+                Conversion::Auto,
+                encoding,
+            );
+            let converted_path = rom::nitro_path(&converted_path);
+            if converted_path == nitro_path || !patch_files.contains(&converted_path) {
+                continue;
+            }
+            let data = fs::read(patch_dir.join(&converted_path))
+                .with_context(|| format!("failed to read {converted_path:?}"))?;
+            let data = match converted.format {
+                Format::Text => {
+                    let original_contents = converted.codec.decompress(&original_data);
+                    pack::build_text_file(&data, original_contents.as_deref(), encoding)
+                        .with_context(|| format!("failed to import {converted_path:?}"))?
+                }
+                _ => data,
+            };
+            let data = converted
+                .codec
+                .compress(&data, &compressor)
+                .with_context(|| format!("failed to compress {converted_path:?}"))?;
+            let import = Import::Converted {
+                format: converted.format,
+                compression: converted.codec.compression,
+            };
+            (converted_path, import, data)
+        };
+
+        matched.insert(patch_path.clone());
+        let placement = rom::replace_file(rom_data, entry.id, &new_data)?;
+        report.patched.push(PatchedFile {
+            nitro_path,
+            patch_path: PathBuf::from(patch_path),
+            import,
+            placement,
+        });
+    }
+    report.unmatched = patch_files
+        .difference(&matched)
+        .map(PathBuf::from)
+        .collect();
+    Ok(report)
+}
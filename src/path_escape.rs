@@ -0,0 +1,172 @@
+//! Escaping of NitroFS names, so that every name can be shown as text and unpacked to every
+//! host file system.
+//!
+//! NitroFS names are raw bytes. They are shown as UTF-8, with `%` starting an escape: `%25`
+//! stands for `%` itself, and `%XX` for each byte that isn't part of valid UTF-8, so that names
+//! are rebuilt byte for byte. Paths files are unpacked to additionally escape the characters
+//! Windows doesn't allow in file names, trailing dots & spaces, and the device names Windows
+//! reserves, so that a ROM unpacked on any system can be packed on any other. The manifest
+//! records both paths; files added after unpacking have their path unescaped when packing.
+
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+use crate::rom;
+
+/// Characters Windows doesn't allow in file names, besides control characters.
+const RESERVED_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*', '\\'];
+/// Names of devices Windows reserves, whatever their extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn push_escaped(text: &mut String, byte: u8) {
+    text.push_str(&format!("%{byte:02X}"));
+}
+
+/// Decodes a NitroFS name, escaping `%` and the bytes that aren't valid UTF-8.
+pub fn decode_name(bytes: &[u8]) -> String {
+    let mut name = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c == '%' {
+                push_escaped(&mut name, b'%');
+            } else {
+                name.push(c);
+            }
+        }
+        for &byte in chunk.invalid() {
+            push_escaped(&mut name, byte);
+        }
+    }
+    name
+}
+
+/// Parses the `%XX` escape at the start of the text given.
+fn parse_escape(text: &str) -> Option<u8> {
+    let digits = text.strip_prefix('%')?.get(..2)?;
+    digits
+        .bytes()
+        .all(|digit| digit.is_ascii_hexdigit())
+        .then(|| u8::from_str_radix(digits, 16).ok())
+        .flatten()
+}
+
+/// Encodes a name decoded by [`decode_name`] back to its bytes. A `%` not starting an escape
+/// stands for itself.
+pub fn encode_name(name: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(c) = rest.chars().next() {
+        match parse_escape(rest) {
+            Some(byte) => {
+                bytes.push(byte);
+                rest = &rest[3..];
+            }
+            None => {
+                bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    bytes
+}
+
+/// Whether a raw NitroFS name reads differently once decoded by [`decode_name`], in which case
+/// it can't be taken as UTF-8 as it is.
+pub fn needs_decoding(bytes: &[u8]) -> bool {
+    std::str::from_utf8(bytes).map_or(true, |name| name.contains('%'))
+}
+
+/// Escapes a component of a NitroFS path for host file systems.
+fn escape_component(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if RESERVED_CHARS.contains(&c) || c.is_ascii_control() {
+            push_escaped(&mut escaped, c as u8);
+        } else {
+            escaped.push(c);
+        }
+    }
+    // Windows drops trailing dots and spaces, which also covers `.` and `..`.
+    if let Some(last) = escaped.pop() {
+        if last == '.' || last == ' ' {
+            push_escaped(&mut escaped, last as u8);
+        } else {
+            escaped.push(last);
+        }
+    }
+    let stem = escaped.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        let first = escaped.remove(0);
+        escaped.insert_str(0, &format!("%{:02X}", first as u8));
+    }
+    escaped
+}
+
+/// Escapes a NitroFS path, with `/` separating its components, into the path it's unpacked to.
+pub fn escape(nitro_path: &str) -> String {
+    nitro_path
+        .split('/')
+        .map(escape_component)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Unescapes the path a file was unpacked to into its NitroFS path, leaving the escapes of
+/// [`decode_name`] as they are.
+pub fn unescape(host_path: &str) -> String {
+    let mut unescaped = String::with_capacity(host_path.len());
+    let mut rest = host_path;
+    while let Some(c) = rest.chars().next() {
+        match parse_escape(rest).filter(|&byte| byte.is_ascii() && byte != b'%') {
+            Some(byte) => {
+                unescaped.push(byte as char);
+                rest = &rest[3..];
+            }
+            None => {
+                unescaped.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    unescaped
+}
+
+/// The path a NitroFS file is unpacked to, relative to the unpack directory.
+pub fn host_path(nitro_path: &Path) -> PathBuf {
+    PathBuf::from(escape(&rom::nitro_path(nitro_path)))
+}
+
+/// The NitroFS path of a file unpacked to the path given, relative to the unpack directory.
+pub fn nitro_path(host_path: &Path) -> String {
+    unescape(&rom::nitro_path(host_path))
+}
+
+/// A path Windows can open even if it's longer than `MAX_PATH`, as unpacking nested archives
+/// inside deep directories easily makes. Other systems take paths as they are.
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    #[cfg(windows)]
+    {
+        // Only absolute paths with the `\\?\` prefix can be that long.
+        const MAX_PATH: usize = 260;
+        let text = path.as_os_str().to_string_lossy();
+        if text.len() >= MAX_PATH && !text.starts_with(r"\\?\") {
+            if let Ok(absolute) = std::path::absolute(path) {
+                let absolute = absolute.to_string_lossy().into_owned();
+                let prefixed = match absolute.strip_prefix(r"\\") {
+                    Some(unc) => format!(r"\\?\UNC\{unc}"),
+                    None => format!(r"\\?\{absolute}"),
+                };
+                return Cow::Owned(PathBuf::from(prefixed));
+            }
+        }
+    }
+    Cow::Borrowed(path)
+}
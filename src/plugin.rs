@@ -0,0 +1,215 @@
+use std::{fmt, path::PathBuf, sync::Arc};
+
+use anyhow::anyhow;
+#[cfg(feature = "scripting")]
+use anyhow::Context;
+use log::warn;
+#[cfg(feature = "scripting")]
+use rhai::{Blob, Dynamic, Engine, FuncArgs, Scope, AST};
+#[cfg(feature = "scripting")]
+use std::fs;
+
+#[cfg(feature = "scripting")]
+use crate::manifest;
+
+/// A Rhai script adding support for one of a game's own formats, so that it doesn't have to be
+/// built into ravends. File contents are handed to it as blobs, decompressed if they're
+/// LZ10-compressed. The script defines:
+///
+/// - `detect(data)`, returning whether the file is in the plugin's format.
+/// - `describe(data)`, optionally, describing the file for `identify`. Without it, the
+///   plugin's name is shown.
+/// - `unpack(data)`, converting the file to an editable form, as a blob or a string.
+/// - `pack(data, original)`, converting an edited file back, given the file it replaces.
+/// - `extension()`, optionally, giving the extension of unpacked files.
+///
+/// Plugins need the `scripting` feature: without it, none can be loaded.
+pub struct Plugin {
+    name: String,
+    /// SHA-256 hash of the script, telling apart versions of the same plugin.
+    source_hash: String,
+    #[cfg(feature = "scripting")]
+    engine: Engine,
+    #[cfg(feature = "scripting")]
+    ast: AST,
+    #[cfg(not(feature = "scripting"))]
+    never: std::convert::Infallible,
+}
+
+impl Plugin {
+    /// Name of the plugin, which is the name of its script without the extension.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+impl Plugin {
+    fn detect(&self, _data: &[u8]) -> anyhow::Result<bool> {
+        match self.never {}
+    }
+
+    pub fn describe(&self, _data: &[u8]) -> anyhow::Result<String> {
+        match self.never {}
+    }
+
+    pub fn unpack(&self, _data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self.never {}
+    }
+
+    pub fn pack(&self, _data: &[u8], _original: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self.never {}
+    }
+
+    pub fn extension(&self) -> anyhow::Result<Option<String>> {
+        match self.never {}
+    }
+}
+
+#[cfg(feature = "scripting")]
+impl Plugin {
+    fn has_fn(&self, name: &str, arity: usize) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|function| function.name == name && function.params.len() == arity)
+    }
+
+    fn call(&self, name: &str, args: impl FuncArgs) -> anyhow::Result<Dynamic> {
+        self.engine
+            .call_fn(&mut Scope::new(), &self.ast, name, args)
+            .map_err(|error| anyhow!("plugin {:?}: `{name}` failed: {error}", self.name))
+    }
+
+    /// Calls a function returning file contents, as a blob or a string.
+    fn call_data(&self, name: &str, args: impl FuncArgs) -> anyhow::Result<Vec<u8>> {
+        let value = self.call(name, args)?;
+        if value.is_blob() {
+            Ok(value.cast::<Blob>())
+        } else if value.is_string() {
+            Ok(value.cast::<String>().into_bytes())
+        } else {
+            Err(anyhow!(
+                "plugin {:?}: `{name}` returned a {} instead of a blob or a string",
+                self.name,
+                value.type_name()
+            ))
+        }
+    }
+
+    fn detect(&self, data: &[u8]) -> anyhow::Result<bool> {
+        let value = self.call("detect", (Blob::from(data),))?;
+        value.as_bool().map_err(|type_name| {
+            anyhow!(
+                "plugin {:?}: `detect` returned a {type_name} instead of a bool",
+                self.name
+            )
+        })
+    }
+
+    pub fn describe(&self, data: &[u8]) -> anyhow::Result<String> {
+        if !self.has_fn("describe", 1) {
+            return Ok(self.name.clone());
+        }
+        Ok(self.call("describe", (Blob::from(data),))?.to_string())
+    }
+
+    pub fn unpack(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.call_data("unpack", (Blob::from(data),))
+    }
+
+    pub fn pack(&self, data: &[u8], original: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.call_data("pack", (Blob::from(data), Blob::from(original)))
+    }
+
+    /// Extension of unpacked files, if the plugin gives one.
+    pub fn extension(&self) -> anyhow::Result<Option<String>> {
+        if !self.has_fn("extension", 0) {
+            return Ok(None);
+        }
+        Ok(Some(self.call("extension", ())?.to_string()))
+    }
+}
+
+/// The plugins loaded, tried in the order they were given.
+#[derive(Clone, Default)]
+pub struct Plugins(Arc<Vec<Plugin>>);
+
+impl fmt::Debug for Plugins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|plugin| &plugin.name))
+            .finish()
+    }
+}
+
+impl Plugins {
+    /// Compiles the plugin scripts at the paths given.
+    #[cfg(not(feature = "scripting"))]
+    pub fn load(paths: &[PathBuf]) -> anyhow::Result<Self> {
+        if !paths.is_empty() {
+            anyhow::bail!(
+                "ravends was built without the `scripting` feature, so it can't load plugins"
+            );
+        }
+        Ok(Self::default())
+    }
+
+    /// Compiles the plugin scripts at the paths given.
+    #[cfg(feature = "scripting")]
+    pub fn load(paths: &[PathBuf]) -> anyhow::Result<Self> {
+        let plugins = paths
+            .iter()
+            .map(|path| {
+                let source = fs::read_to_string(path)
+                    .with_context(|| format!("failed to read plugin {path:?}"))?;
+                let engine = Engine::new();
+                let source_hash = manifest::sha256_hex(source.as_bytes());
+                let ast = engine
+                    .compile(source)
+                    .map_err(|error| anyhow!("failed to compile plugin {path:?}: {error}"))?;
+                let plugin = Plugin {
+                    name: path
+                        .file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned(),
+                    source_hash,
+                    engine,
+                    ast,
+                };
+                if !plugin.has_fn("detect", 1) {
+                    anyhow::bail!("plugin {path:?} does not define `detect(data)`");
+                }
+                Ok(plugin)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self(Arc::new(plugins)))
+    }
+
+    /// The first plugin that recognizes the file given. Plugins that fail to tell are skipped.
+    pub fn detect(&self, data: &[u8]) -> Option<&Plugin> {
+        self.0.iter().find(|plugin| {
+            plugin.detect(data).unwrap_or_else(|error| {
+                warn!("{error:#}");
+                false
+            })
+        })
+    }
+
+    /// Identifies the plugins loaded and their scripts, so that results of detection can be
+    /// reused only as long as the same plugins are given.
+    pub fn fingerprint(&self) -> String {
+        self.0
+            .iter()
+            .map(|plugin| format!("{}:{}", plugin.name, plugin.source_hash))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    pub fn get(&self, name: &str) -> anyhow::Result<&Plugin> {
+        self.0
+            .iter()
+            .find(|plugin| plugin.name == name)
+            .ok_or_else(|| anyhow!("the {name:?} plugin is needed, but wasn't given"))
+    }
+}
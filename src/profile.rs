@@ -0,0 +1,143 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use std::fs;
+
+use crate::{
+    rom,
+    text::{EncodingKind, TextEncoding},
+};
+
+/// Profiles shipped in the binary, as the contents of their TOML files. Add one by placing it
+/// in `profiles/` and including it here with `include_str!`.
+const BUILTIN_PROFILES: &[&str] = &[];
+
+/// The known quirks of a game, applied when unpacking and packing its ROMs and building
+/// projects based on them, so that they work without having to pass options by hand.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Name of the game.
+    pub name: String,
+    /// Game codes of the ROMs the profile applies to, such as `AMCE`. The releases of a game in
+    /// different regions usually share their quirks.
+    pub game_codes: Vec<String>,
+    /// Glob patterns of the NitroFS paths of the game's text files, which are converted even
+    /// when they aren't recognized as such.
+    #[serde(default)]
+    pub text_files: Vec<String>,
+    /// Character encoding of the game's text files.
+    #[serde(default)]
+    pub encoding: Option<EncodingKind>,
+    /// Character table of the game's text files, relative to the profile file.
+    #[serde(default)]
+    pub table: Option<PathBuf>,
+    /// Control code file for the game's text files, relative to the profile file.
+    #[serde(default)]
+    pub control_codes: Option<PathBuf>,
+    /// Glob patterns of the NitroFS paths of files the game expects to be LZ10-compressed.
+    /// Files added to the ROM at these paths are compressed.
+    #[serde(default)]
+    pub compressed_files: Vec<String>,
+    /// Alignment the game needs its file data to have, used unless another one is given.
+    #[serde(default)]
+    pub alignment: Option<usize>,
+    /// Byte filling the space between file data, used unless another one is given.
+    #[serde(default)]
+    pub pad_byte: Option<u8>,
+    /// Directory of the profile file, or `None` for built-in profiles.
+    #[serde(skip)]
+    dir: Option<PathBuf>,
+}
+
+/// Whether a NitroFS path matches one of the glob patterns given.
+fn matches_any(patterns: &[String], path: &Path) -> anyhow::Result<bool> {
+    if patterns.is_empty() {
+        return Ok(false);
+    }
+    let filter = rom::PathFilter::new(patterns, &[]).context("invalid pattern in profile")?;
+    Ok(filter.matches(path))
+}
+
+impl Profile {
+    fn parse(contents: &str, dir: Option<PathBuf>) -> anyhow::Result<Self> {
+        let mut profile: Self = toml::from_str(contents)?;
+        rom::PathFilter::new(&profile.text_files, &profile.compressed_files)
+            .context("invalid pattern in profile")?;
+        profile.dir = dir;
+        Ok(profile)
+    }
+
+    /// Whether the file at a NitroFS path is one of the game's text files.
+    pub fn is_text_file(&self, path: &Path) -> bool {
+        matches_any(&self.text_files, path).unwrap_or(false)
+    }
+
+    /// Whether the game expects the file at a NitroFS path to be LZ10-compressed.
+    pub fn is_compressed_file(&self, path: &Path) -> bool {
+        matches_any(&self.compressed_files, path).unwrap_or(false)
+    }
+
+    /// Loads the encoding of the game's text files, along with its character table or control
+    /// code file.
+    pub fn text_encoding(&self) -> anyhow::Result<TextEncoding> {
+        let resolve = |path: &Option<PathBuf>| -> anyhow::Result<Option<PathBuf>> {
+            match (path, &self.dir) {
+                (None, _) => Ok(None),
+                (Some(path), Some(dir)) => Ok(Some(dir.join(path))),
+                (Some(path), None) => Err(anyhow!(
+                    "the built-in {:?} profile refers to {path:?}, but built-in profiles can't use files",
+                    self.name
+                )),
+            }
+        };
+        TextEncoding::load(
+            self.encoding,
+            resolve(&self.table)?.as_deref(),
+            resolve(&self.control_codes)?.as_deref(),
+        )
+        .with_context(|| {
+            format!(
+                "failed to load the text encoding of the {:?} profile",
+                self.name
+            )
+        })
+    }
+}
+
+/// Finds the profile of the game with the code given. Profiles in `dir`, if given, take
+/// precedence over the built-in ones.
+pub fn find(game_code: &str, dir: Option<&Path>) -> anyhow::Result<Option<Profile>> {
+    let mut profiles = Vec::new();
+    if let Some(dir) = dir {
+        let entries = fs::read_dir(dir)
+            .with_context(|| format!("failed to read profile directory {dir:?}"))?;
+        let mut paths = entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("failed to read profile directory {dir:?}"))?;
+        paths.retain(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "toml")
+        });
+        paths.sort();
+        for path in paths {
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read profile {path:?}"))?;
+            profiles.push(
+                Profile::parse(&contents, Some(dir.to_owned()))
+                    .with_context(|| format!("failed to parse profile {path:?}"))?,
+            );
+        }
+    }
+    for contents in BUILTIN_PROFILES {
+        profiles.push(Profile::parse(contents, None).context("failed to parse built-in profile")?);
+    }
+    Ok(profiles.into_iter().find(|profile| {
+        profile
+            .game_codes
+            .iter()
+            .any(|code| code.eq_ignore_ascii_case(game_code))
+    }))
+}
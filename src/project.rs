@@ -0,0 +1,495 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use log::{info, warn};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::{
+    asm, bps,
+    cache::CompressionCache,
+    freespace::{self, Allocator},
+    hook::{Hook, Hooks},
+    lint::{self, LintConfig, LintRules},
+    manifest::{Compression, CompressionPolicy},
+    plugin::Plugins,
+    profile::{self, Profile},
+    rom,
+    symbols::SymbolMap,
+    text::{EncodingKind, TextArchive, TextEncoding, TextLayout},
+    text_formats::TextFormat,
+};
+
+/// Name of the file describing a project, placed at its root.
+pub const PROJECT_FILE_NAME: &str = "ravends.toml";
+
+/// A translation project: a base ROM, and the assets that replace some of its files.
+///
+/// Paths are relative to the project directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Project {
+    /// The unmodified ROM the project applies to.
+    pub base_rom: PathBuf,
+    /// Where `build` places the patched ROM.
+    pub output: PathBuf,
+    /// Where `build` places a BPS patch from the base ROM to the patched one, if anywhere.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patch: Option<PathBuf>,
+    /// The files replaced, in the order they are inserted.
+    #[serde(default, rename = "file", skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileOverride>,
+    /// Assembly sources applied after the files are replaced, in order, as with `asmpatch`.
+    #[serde(default, rename = "asm_patch", skip_serializing_if = "Vec::is_empty")]
+    pub asm_patches: Vec<PathBuf>,
+    /// Symbol files the assembly sources can refer to, as with `asmpatch --symbols`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub symbols: Vec<PathBuf>,
+    /// Rhai plugins converting assets in the game's own formats, as with `pack --plugin`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugins: Vec<PathBuf>,
+    /// External commands converting the assets of the files matching their patterns, for
+    /// formats ravends doesn't support. The project file can also be given to `unpack --hooks`
+    /// and `pack --hooks`, which use these same hooks.
+    #[serde(default, rename = "hook", skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<Hook>,
+    /// Directory of game profiles checked before the built-in ones, as with `unpack --profiles`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profiles: Option<PathBuf>,
+    /// Limits the strings of text assets are checked against before building them, as with
+    /// `text lint`. Broken control codes, and control codes differing from the original
+    /// strings, are checked for even without it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lint: Option<LintConfig>,
+}
+
+/// How an asset is turned into the data of the file it replaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AssetKind {
+    /// Strings in one of the formats supported by `text export`, built into a text file.
+    Text,
+    /// Data inserted as-is.
+    Raw,
+    /// Data converted by a plugin, the one named by `plugin` or else the first recognizing the
+    /// original file.
+    Plugin,
+    /// Data converted by the `pack` command of a hook, the one named by `hook` or else the first
+    /// whose pattern matches the file's path.
+    Hook,
+}
+
+/// A ROM file replaced by an asset.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileOverride {
+    /// NitroFS path of the file replaced.
+    pub path: String,
+    /// The asset replacing it.
+    pub source: PathBuf,
+    /// How to convert the asset. If empty, it's a hook asset if `hook` is set or a hook's pattern
+    /// matches the file's path, a plugin asset if `plugin` is set, and is otherwise guessed from
+    /// its extension: text for the formats supported by `text export`, raw for everything else.
+    #[serde(default)]
+    pub kind: Option<AssetKind>,
+    /// Compression of the file in the ROM: `none`, `lz10`, `lz11` or `as-original`. If empty,
+    /// the original file's is kept, unless the game's profile expects the file to be compressed.
+    #[serde(default)]
+    pub compression: Option<CompressionPolicy>,
+    /// Format of a text asset. If empty, it's guessed from its extension.
+    #[serde(default)]
+    pub format: Option<TextFormat>,
+    /// Character encoding of a text file's strings, as with `text pack --encoding`. If empty
+    /// along with `table` and `control_codes`, the one of the game's profile is used.
+    #[serde(default)]
+    pub encoding: Option<EncodingKind>,
+    /// Character table of a text file's strings, as with `text pack --table`.
+    #[serde(default)]
+    pub table: Option<PathBuf>,
+    /// Control code file for a text file's strings, as with `text pack --control-codes`.
+    #[serde(default)]
+    pub control_codes: Option<PathBuf>,
+    /// Layout of a rebuilt text file. If empty, the original's layout is preserved where
+    /// possible, as `pack` does.
+    #[serde(default)]
+    pub layout: Option<TextLayout>,
+    /// Name of the plugin converting the asset, which is its script's name without the
+    /// extension.
+    #[serde(default)]
+    pub plugin: Option<String>,
+    /// Name of the hook converting the asset.
+    #[serde(default)]
+    pub hook: Option<String>,
+    /// Maximum width of a line of a text asset in pixels, overriding the one of the project's
+    /// lint configuration.
+    #[serde(default)]
+    pub max_width: Option<usize>,
+    /// Maximum size of a line of a text asset in bytes, overriding the one of the project's lint
+    /// configuration.
+    #[serde(default)]
+    pub max_line_bytes: Option<usize>,
+}
+
+impl Project {
+    pub fn load(project_dir: &Path) -> anyhow::Result<Self> {
+        let path = project_dir.join(PROJECT_FILE_NAME);
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read project file {path:?}"))?;
+        toml::from_str(&contents).with_context(|| format!("failed to parse project file {path:?}"))
+    }
+
+    pub fn save(&self, project_dir: &Path) -> anyhow::Result<()> {
+        let contents = toml::to_string(self).context("failed to serialize project file")?;
+        fs::write(project_dir.join(PROJECT_FILE_NAME), contents)
+            .context("failed to write project file")
+    }
+}
+
+/// Creates a project for the base ROM given in `project_dir`, which is created if needed.
+pub fn init(project_dir: &Path, base_rom: &Path) -> anyhow::Result<Project> {
+    if project_dir.join(PROJECT_FILE_NAME).exists() {
+        return Err(anyhow!("{project_dir:?} already contains a project"));
+    }
+    rom::read_rom(base_rom)?;
+    fs::create_dir_all(project_dir).context("failed to create project directory")?;
+
+    // Keep the project relocatable when the ROM is inside it.
+    let project_dir_abs = project_dir
+        .canonicalize()
+        .context("failed to resolve project directory")?;
+    let base_rom_abs = base_rom
+        .canonicalize()
+        .context("failed to resolve base ROM path")?;
+    let base_rom = match base_rom_abs.strip_prefix(&project_dir_abs) {
+        Ok(relative) => relative.to_owned(),
+        Err(_) => base_rom_abs.clone(),
+    };
+    let file_name = base_rom_abs
+        .file_name()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("rom.nds"));
+
+    let project = Project {
+        base_rom,
+        output: Path::new("build").join(file_name),
+        patch: None,
+        files: Vec::new(),
+        asm_patches: Vec::new(),
+        symbols: Vec::new(),
+        plugins: Vec::new(),
+        hooks: Vec::new(),
+        profiles: None,
+        lint: None,
+    };
+    project.save(project_dir)?;
+    Ok(project)
+}
+
+impl FileOverride {
+    fn kind(&self, hooks: &Hooks) -> anyhow::Result<AssetKind> {
+        if let Some(kind) = self.kind {
+            return Ok(kind);
+        }
+        if self.hook.is_some() || hooks.find_pack(&self.path).is_some() {
+            return Ok(AssetKind::Hook);
+        }
+        if self.plugin.is_some() {
+            return Ok(AssetKind::Plugin);
+        }
+        let extension = self
+            .source
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+        if extension.as_deref() == Some("png") {
+            return Err(anyhow!(
+                "PNG assets are not supported yet; convert {:?} to the file's binary format and give it as a raw asset",
+                self.source
+            ));
+        }
+        let is_text = self.format.is_some()
+            || extension
+                .as_deref()
+                .and_then(TextFormat::from_extension)
+                .is_some();
+        Ok(if is_text {
+            AssetKind::Text
+        } else {
+            AssetKind::Raw
+        })
+    }
+
+    /// Builds the data of a hook asset, which doesn't depend on the original file, or returns
+    /// `None` for other assets.
+    fn build_with_hook(
+        &self,
+        project_dir: &Path,
+        hooks: &Hooks,
+        cache: &CompressionCache,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        if self.kind(hooks)? != AssetKind::Hook {
+            return Ok(None);
+        }
+        let hook = match &self.hook {
+            Some(name) => hooks.get(name)?,
+            None => hooks
+                .find_pack(&self.path)
+                .ok_or_else(|| anyhow!("no hook with a pack command matches {:?}", self.path))?,
+        };
+        let source_path = project_dir.join(&self.source);
+        let data =
+            fs::read(&source_path).with_context(|| format!("failed to read {source_path:?}"))?;
+        let path = self.path.trim_matches('/');
+        hook.pack(path.rsplit('/').next().unwrap_or(path), &data, cache)
+            .map(Some)
+    }
+
+    /// Builds the data replacing the original file given, before compression. Text assets are
+    /// linted first if rules are given, failing on any issue. Hook assets are built by
+    /// [`FileOverride::build_with_hook`] instead.
+    fn build(
+        &self,
+        project_dir: &Path,
+        original_data: &[u8],
+        plugins: &Plugins,
+        hooks: &Hooks,
+        profile: Option<&Profile>,
+        lint_rules: Option<&LintRules>,
+    ) -> anyhow::Result<Vec<u8>> {
+        let source_path = project_dir.join(&self.source);
+        match self.kind(hooks)? {
+            AssetKind::Hook => unreachable!("hook assets are built beforehand"),
+            AssetKind::Raw => {
+                fs::read(&source_path).with_context(|| format!("failed to read {source_path:?}"))
+            }
+            AssetKind::Plugin => {
+                let plugin = match &self.plugin {
+                    Some(name) => plugins.get(name)?,
+                    None => plugins.detect(original_data).ok_or_else(|| {
+                        anyhow!("no plugin recognizes the original {:?}", self.path)
+                    })?,
+                };
+                let data = fs::read(&source_path)
+                    .with_context(|| format!("failed to read {source_path:?}"))?;
+                plugin.pack(&data, original_data)
+            }
+            AssetKind::Text => {
+                let format = self
+                    .format
+                    .or_else(|| {
+                        self.source.extension().and_then(|extension| {
+                            TextFormat::from_extension(&extension.to_string_lossy())
+                        })
+                    })
+                    .unwrap_or(TextFormat::Template);
+                let encoding = match profile {
+                    Some(profile)
+                        if self.encoding.is_none()
+                            && self.table.is_none()
+                            && self.control_codes.is_none() =>
+                    {
+                        profile.text_encoding()?
+                    }
+                    _ => TextEncoding::load(
+                        self.encoding,
+                        self.table
+                            .as_ref()
+                            .map(|path| project_dir.join(path))
+                            .as_deref(),
+                        self.control_codes
+                            .as_ref()
+                            .map(|path| project_dir.join(path))
+                            .as_deref(),
+                    )?,
+                };
+                let contents = fs::read_to_string(&source_path)
+                    .with_context(|| format!("failed to read {source_path:?}"))?;
+                let mut entries = format
+                    .import_entries(&contents)
+                    .with_context(|| format!("failed to import {source_path:?}"))?;
+
+                let original_archive = TextArchive::parse(original_data, &encoding).ok();
+                if let Some(rules) = lint_rules {
+                    // The strings of the original file are the ones translated, whatever the
+                    // asset gives.
+                    if let Some(archive) = &original_archive {
+                        for (entry, original) in entries.iter_mut().zip(&archive.strings) {
+                            entry.original = Some(original.clone());
+                        }
+                    }
+                    let mut rules = rules.clone();
+                    rules.max_width = self.max_width.or(rules.max_width);
+                    rules.max_line_bytes = self.max_line_bytes.or(rules.max_line_bytes);
+                    let issues = lint::lint(&entries, &encoding, &rules);
+                    for issue in &issues {
+                        warn!("{}: {issue}", self.source.display());
+                    }
+                    if !issues.is_empty() {
+                        return Err(anyhow!("{} lint issues in {:?}", issues.len(), self.source));
+                    }
+                }
+                let strings = entries.into_iter().map(|entry| entry.text).collect();
+                let archive = match original_archive {
+                    Some(archive) => archive.with_strings(strings),
+                    None => TextArchive::new(strings),
+                };
+                let layout = self.layout.unwrap_or(if archive.can_preserve_layout() {
+                    TextLayout::Preserve
+                } else {
+                    TextLayout::Deduplicated
+                });
+                Ok(archive.to_bytes(&encoding, layout)?)
+            }
+        }
+    }
+}
+
+/// Builds the patched ROM of a project in memory, without writing anything. Compressed files
+/// are reused from `cache` when their contents did not change. Text assets are linted unless
+/// `lint` is false.
+pub fn build_rom(
+    project_dir: &Path,
+    project: &Project,
+    cache: &CompressionCache,
+    lint: bool,
+) -> anyhow::Result<Vec<u8>> {
+    let base_rom_data = rom::read_rom(&project_dir.join(&project.base_rom))?;
+    let mut rom_data = base_rom_data.clone();
+    let plugin_paths = project
+        .plugins
+        .iter()
+        .map(|path| project_dir.join(path))
+        .collect::<Vec<_>>();
+    let plugins = Plugins::load(&plugin_paths)?;
+    let hooks = Hooks::new(project.hooks.clone())?;
+    let profile = profile::find(
+        &rom::header_text(&base_rom_data, rom::GAME_CODE_RANGE),
+        project
+            .profiles
+            .as_ref()
+            .map(|path| project_dir.join(path))
+            .as_deref(),
+    )?;
+    if let Some(profile) = &profile {
+        info!("applying the profile of {}", profile.name);
+    }
+    let lint_rules = lint
+        .then(|| LintRules::load(&project.lint.clone().unwrap_or_default(), project_dir))
+        .transpose()?;
+
+    // Hooks run external commands, so the assets they convert are built in parallel first.
+    let hooked_data = project
+        .files
+        .par_iter()
+        .map(|file| {
+            file.build_with_hook(project_dir, &hooks, cache)
+                .with_context(|| format!("failed to build {:?}", file.path))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    for (file, hooked_data) in project.files.iter().zip(hooked_data) {
+        let filesystem = rom::filesystem(&rom_data)?;
+        let entry = filesystem
+            .files()
+            .into_iter()
+            .find(|entry| rom::nitro_path(&entry.path) == file.path.trim_matches('/'))
+            .ok_or_else(|| anyhow!("no file in the base ROM has the path {:?}", file.path))?;
+        let file_id = entry.id;
+        let stored_data = rom::file_data(&rom_data, entry).to_vec();
+        let (original_compression, original_data) = [Compression::Lz10, Compression::Lz11]
+            .into_iter()
+            .find_map(|compression| Some((compression, compression.decompress(&stored_data)?)))
+            .map_or((Compression::None, None), |(compression, data)| {
+                (compression, Some(data))
+            });
+        let expects_compression = profile.as_ref().is_some_and(|profile| {
+            profile.is_compressed_file(Path::new(file.path.trim_matches('/')))
+        });
+        let compression = match file.compression {
+            Some(policy) => policy.resolve(original_compression),
+            None if original_compression == Compression::None && expects_compression => {
+                Compression::Lz10
+            }
+            None => original_compression,
+        };
+
+        let data = match hooked_data {
+            Some(data) => data,
+            None => file
+                .build(
+                    project_dir,
+                    original_data.as_deref().unwrap_or(&stored_data),
+                    &plugins,
+                    &hooks,
+                    profile.as_ref(),
+                    lint_rules.as_ref(),
+                )
+                .with_context(|| format!("failed to build {:?}", file.path))?,
+        };
+        let data = cache
+            .compress(&data, compression)
+            .with_context(|| format!("failed to compress {:?}", file.path))?;
+
+        match rom::replace_file(&mut rom_data, file_id, &data)? {
+            rom::Placement::InPlace => info!("{}: replaced in place", file.path),
+            rom::Placement::Relocated { start } => {
+                info!("{}: relocated to 0x{start:08X}", file.path)
+            }
+        }
+    }
+
+    let symbol_paths = project
+        .symbols
+        .iter()
+        .map(|path| project_dir.join(path))
+        .collect::<Vec<_>>();
+    let symbols = SymbolMap::load(&symbol_paths)?;
+    if !project.asm_patches.is_empty() {
+        let mut allocator = Allocator::new(freespace::find_free_space(
+            &rom_data,
+            freespace::DEFAULT_MIN_SIZE,
+        )?);
+        for asm_patch in &project.asm_patches {
+            let source_path = project_dir.join(asm_patch);
+            let source = fs::read_to_string(&source_path)
+                .with_context(|| format!("failed to read {source_path:?}"))?;
+            let chunks = asm::assemble_with_allocator(&source, &symbols, &mut allocator)
+                .with_context(|| format!("failed to assemble {source_path:?}"))?;
+            allocator.grow_overlays(&mut rom_data, cache.level())?;
+            asm::patch_rom(&mut rom_data, &chunks)
+                .with_context(|| format!("failed to apply {source_path:?}"))?;
+            info!("{}: assembled into the ROM", asm_patch.display());
+        }
+        if allocator.used() > 0 {
+            info!(
+                "0x{:X} bytes of free space used by assembly patches, 0x{:X} left",
+                allocator.used(),
+                allocator.left()
+            );
+        }
+    }
+    Ok(rom_data)
+}
+
+/// Builds the patched ROM of a project, writing it and its BPS patch where the project says.
+/// Compressed files are reused from `cache` when their contents did not change.
+pub fn build(project_dir: &Path, cache: &CompressionCache, lint: bool) -> anyhow::Result<()> {
+    let project = Project::load(project_dir)?;
+    let rom_data = build_rom(project_dir, &project, cache, lint)?;
+
+    let output = project_dir.join(&project.output);
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent).context("failed to create output directory")?;
+    }
+    fs::write(&output, &rom_data).context("failed to write patched ROM")?;
+    if let Some(patch) = &project.patch {
+        let patch = project_dir.join(patch);
+        if let Some(parent) = patch.parent() {
+            fs::create_dir_all(parent).context("failed to create patch directory")?;
+        }
+        let base_rom_data = rom::read_rom(&project_dir.join(&project.base_rom))?;
+        fs::write(patch, bps::encode(&base_rom_data, &rom_data))
+            .context("failed to write patch")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use std::fmt::Write;
+
+use thiserror::Error;
+
+use crate::{
+    bps,
+    manifest::sha256_hex,
+    patch::{self, PatchFormat},
+    rom,
+};
+
+/// Name of the file listing the SHA-256 hashes of the patches of a release.
+pub const CHECKSUMS_FILE_NAME: &str = "checksums.sha256";
+/// Name of the generated README of a release.
+pub const README_FILE_NAME: &str = "README.txt";
+
+/// Formats the patches of a release are created in.
+const FORMATS: [PatchFormat; 2] = [PatchFormat::Bps, PatchFormat::Vcdiff];
+
+#[derive(Error, Debug)]
+pub enum ReleaseError {
+    #[error("the original and modified ROMs are identical")]
+    Unmodified,
+    #[error("the {} patch created doesn't turn the original ROM into the modified one", .0.extension())]
+    BrokenPatch(PatchFormat),
+}
+
+/// A file of a release bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReleaseFile {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Creates the files of a release turning the original ROM into the modified one: a BPS and an
+/// xdelta patch named after `name`, a file with their SHA-256 hashes in the format
+/// `sha256sum -c` reads, and a README giving the hashes of the ROM the patches apply to and of
+/// the ROM they result in. Each patch is applied back before being bundled, so that a broken
+/// one is never published.
+pub fn bundle(
+    original: &[u8],
+    modified: &[u8],
+    name: &str,
+) -> Result<Vec<ReleaseFile>, ReleaseError> {
+    if original == modified {
+        return Err(ReleaseError::Unmodified);
+    }
+    let mut files = Vec::new();
+    for format in FORMATS {
+        let data = format.create(original, modified);
+        if !patch::apply(original, &data).is_ok_and(|patched| patched == modified) {
+            return Err(ReleaseError::BrokenPatch(format));
+        }
+        files.push(ReleaseFile {
+            name: format!("{name}.{}", format.extension()),
+            data,
+        });
+    }
+
+    let mut checksums = String::new();
+    for file in &files {
+        let _ = writeln!(checksums, "{}  {}", sha256_hex(&file.data), file.name);
+    }
+    let readme = readme(original, modified, name, &files);
+    files.push(ReleaseFile {
+        name: CHECKSUMS_FILE_NAME.to_owned(),
+        data: checksums.into_bytes(),
+    });
+    files.push(ReleaseFile {
+        name: README_FILE_NAME.to_owned(),
+        data: readme.into_bytes(),
+    });
+    Ok(files)
+}
+
+fn describe_rom(output: &mut String, rom_data: &[u8]) {
+    if rom_data.len() >= rom::MAKER_CODE_RANGE.end {
+        let _ = writeln!(
+            output,
+            "  Title:     {}",
+            rom::header_text(rom_data, rom::TITLE_RANGE)
+        );
+        let _ = writeln!(
+            output,
+            "  Game code: {}",
+            rom::header_text(rom_data, rom::GAME_CODE_RANGE)
+        );
+    }
+    let _ = writeln!(output, "  Size:      {} bytes", rom_data.len());
+    let _ = writeln!(output, "  CRC32:     {:08X}", bps::crc32(rom_data));
+    let _ = writeln!(output, "  SHA-256:   {}", sha256_hex(rom_data));
+}
+
+fn readme(original: &[u8], modified: &[u8], name: &str, patches: &[ReleaseFile]) -> String {
+    let mut readme = String::new();
+    let _ = writeln!(readme, "{name}");
+    let _ = writeln!(readme, "{}", "=".repeat(name.chars().count()));
+    let _ = writeln!(readme);
+    let _ = writeln!(
+        readme,
+        "This release is a patch, to be applied to your own dump of the game. It contains no"
+    );
+    let _ = writeln!(readme, "ROM. Both patches included give the same result:");
+    let _ = writeln!(readme);
+    for patch in patches {
+        let how = match PatchFormat::detect(&patch.data) {
+            Some(PatchFormat::Bps) => "BPS, for Rom Patcher JS, Floating IPS or beat",
+            Some(PatchFormat::Vcdiff) | None => "xdelta, for xdelta3 or Delta Patcher",
+        };
+        let _ = writeln!(readme, "  {}: {how}", patch.name);
+    }
+    let _ = writeln!(readme);
+    let _ = writeln!(
+        readme,
+        "Their SHA-256 hashes are listed in {CHECKSUMS_FILE_NAME}; check them with"
+    );
+    let _ = writeln!(readme, "`sha256sum -c {CHECKSUMS_FILE_NAME}`.");
+    let _ = writeln!(readme);
+    let _ = writeln!(readme, "Base ROM");
+    let _ = writeln!(readme, "--------");
+    let _ = writeln!(readme);
+    let _ = writeln!(
+        readme,
+        "The patches only apply to this exact ROM. If the hashes of yours differ, it is a"
+    );
+    let _ = writeln!(
+        readme,
+        "different version or a bad dump, and patching it will fail or give a broken game."
+    );
+    let _ = writeln!(readme);
+    describe_rom(&mut readme, original);
+    let _ = writeln!(readme);
+    let _ = writeln!(readme, "Patched ROM");
+    let _ = writeln!(readme, "-----------");
+    let _ = writeln!(readme);
+    describe_rom(&mut readme, modified);
+    readme
+}
@@ -0,0 +1,364 @@
+#[cfg(feature = "graphics")]
+use std::path::{Path, PathBuf};
+use std::{collections::BTreeMap, f64::consts::TAU, fmt::Write};
+
+#[cfg(feature = "graphics")]
+use log::debug;
+
+#[cfg(feature = "graphics")]
+use crate::{
+    gfx::{self, Ncgr, Nclr, Nscr},
+    unpack::parse_maybe_compressed,
+};
+use crate::{
+    lz10::decompress_lz10,
+    rom,
+    survey::Survey,
+    text::{parse_text_file, TextEncoding},
+};
+
+/// Most text files sampled in a report.
+const MAX_TEXT_SAMPLES: usize = 20;
+/// Strings shown of each text file sampled.
+const STRINGS_PER_SAMPLE: usize = 5;
+/// Characters shown of each string sampled, past which it's cut short.
+const MAX_STRING_LENGTH: usize = 200;
+/// Most graphics drawn as thumbnails in a report.
+#[cfg(feature = "graphics")]
+const MAX_THUMBNAILS: usize = 64;
+/// Formats past this many get grouped together as "other formats" in the pie chart.
+const MAX_CHART_SLICES: usize = 10;
+/// Colors of the slices of the pie chart.
+const CHART_COLORS: [&str; MAX_CHART_SLICES + 1] = [
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7",
+    "#9c755f", "#bab0ac", "#666666",
+];
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+h2{border-bottom:1px solid #ccc}\
+ul{list-style:none;padding-left:1.2em}\
+.description{color:#666}\
+.chart{display:flex;gap:2em;align-items:center}\
+.swatch{display:inline-block;width:1em;height:1em;margin-right:.5em;vertical-align:middle}\
+.thumbnails{display:flex;flex-wrap:wrap;gap:1em}\
+figure{margin:0;text-align:center}\
+figure img{image-rendering:pixelated;max-width:256px;border:1px solid #ccc}\
+figcaption{font-size:.8em;max-width:256px;overflow-wrap:anywhere}\
+blockquote{white-space:pre-wrap;background:#f4f4f4;margin:.3em 0;padding:.3em .6em}";
+
+/// Lists the NitroFS files of a ROM with their paths, sorted by path, for
+/// [`Report::add_samples`]. A damaged filesystem is reported as an empty one.
+pub fn rom_files(rom_data: &[u8]) -> Vec<(String, &[u8])> {
+    let Ok(fs) = rom::filesystem(rom_data) else {
+        return Vec::new();
+    };
+    let mut files = fs
+        .files()
+        .into_iter()
+        .map(|entry| {
+            (
+                rom::nitro_path(&entry.path),
+                rom::file_data(rom_data, entry),
+            )
+        })
+        .collect::<Vec<_>>();
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+    files
+}
+
+/// Strings from the start of a text file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextSample {
+    pub path: String,
+    pub strings: Vec<String>,
+    /// Number of strings in the file, shown or not.
+    pub string_count: usize,
+}
+
+/// Graphics drawn with their palette, and with their screen if they have one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Thumbnail {
+    pub path: String,
+    pub png: Vec<u8>,
+}
+
+/// A summary of what's inside a ROM or directory, written as a single HTML file that can be
+/// opened without ravends: its file tree, how many files of each format it has, samples of its
+/// text and thumbnails of its graphics.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub title: String,
+    pub survey: Survey,
+    pub text_samples: Vec<TextSample>,
+    pub thumbnails: Vec<Thumbnail>,
+}
+
+impl Report {
+    pub fn new(title: String, survey: Survey) -> Self {
+        Self {
+            title,
+            survey,
+            ..Self::default()
+        }
+    }
+
+    /// Samples the text files among the files given, identified as such by the survey, and
+    /// draws their NCGR graphics if built with the `graphics` feature.
+    pub fn add_samples(&mut self, files: &[(String, &[u8])], encoding: &TextEncoding) {
+        let text_paths = self
+            .survey
+            .files
+            .iter()
+            .filter(|file| file.identification.format == "text file")
+            .map(|file| file.path.as_str())
+            .collect::<Vec<_>>();
+        for (path, data) in files {
+            if self.text_samples.len() >= MAX_TEXT_SAMPLES {
+                break;
+            }
+            if !text_paths.contains(&path.as_str()) {
+                continue;
+            }
+            let contents = decompress_lz10(*data).unwrap_or_else(|_| data.to_vec());
+            let Ok(strings) = parse_text_file(&contents, encoding) else {
+                continue;
+            };
+            self.text_samples.push(TextSample {
+                path: path.clone(),
+                string_count: strings.len(),
+                strings: strings.into_iter().take(STRINGS_PER_SAMPLE).collect(),
+            });
+        }
+        #[cfg(feature = "graphics")]
+        self.add_thumbnails(files);
+    }
+
+    /// Draws the NCGR graphics among the files given. As when unpacking, graphics are drawn
+    /// with the NCLR palette of the same name in their directory (or its only palette), and
+    /// with the NSCR screen of the same name if there is one.
+    #[cfg(feature = "graphics")]
+    fn add_thumbnails(&mut self, files: &[(String, &[u8])]) {
+        let mut graphics = BTreeMap::<PathBuf, (Vec<_>, Vec<_>, Vec<_>)>::new();
+        for (path, data) in files {
+            let path = Path::new(path);
+            let stem = path.file_stem().unwrap_or_default().to_owned();
+            let (dir_graphics, palettes, screens) = graphics
+                .entry(path.parent().unwrap_or(Path::new("")).to_path_buf())
+                .or_default();
+            if let Some(ncgr) = parse_maybe_compressed(data, Ncgr::parse) {
+                dir_graphics.push((path, stem, ncgr));
+            } else if let Some(nclr) = parse_maybe_compressed(data, Nclr::parse) {
+                palettes.push((stem, nclr));
+            } else if let Some(nscr) = parse_maybe_compressed(data, Nscr::parse) {
+                screens.push((stem, nscr));
+            }
+        }
+        for (dir_graphics, palettes, screens) in graphics.values() {
+            for (path, stem, ncgr) in dir_graphics {
+                if self.thumbnails.len() >= MAX_THUMBNAILS {
+                    return;
+                }
+                let palette = palettes
+                    .iter()
+                    .find(|(palette_stem, _)| palette_stem == stem)
+                    .or_else(|| (palettes.len() == 1).then(|| &palettes[0]));
+                let Some((_, nclr)) = palette else {
+                    debug!("{path:?}: no palette found, not drawing it");
+                    continue;
+                };
+                let image = match screens.iter().find(|(screen_stem, _)| screen_stem == stem) {
+                    Some((_, nscr)) => gfx::render_screen(ncgr, nclr, nscr),
+                    None => gfx::render_graphics(ncgr, nclr, 0),
+                };
+                match image.to_png() {
+                    Ok(png) => self.thumbnails.push(Thumbnail {
+                        path: path.to_string_lossy().into_owned(),
+                        png,
+                    }),
+                    Err(error) => debug!("{path:?}: failed to encode thumbnail: {error}"),
+                }
+            }
+        }
+    }
+
+    /// Writes the report as an HTML page, with its images and styles embedded.
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        let title = escape(&self.title);
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n"
+        );
+
+        let counts = self.survey.format_counts();
+        let _ = writeln!(
+            html,
+            "<h2>Formats</h2>\n<p>{} files.</p>",
+            self.survey.files.len()
+        );
+        html.push_str(&format_chart(&counts));
+
+        if !self.thumbnails.is_empty() {
+            html.push_str("<h2>Graphics</h2>\n<div class=\"thumbnails\">\n");
+            for thumbnail in &self.thumbnails {
+                let path = escape(&thumbnail.path);
+                let _ = writeln!(
+                    html,
+                    "<figure><img src=\"data:image/png;base64,{}\" alt=\"{path}\"><figcaption>{path}</figcaption></figure>",
+                    base64(&thumbnail.png)
+                );
+            }
+            html.push_str("</div>\n");
+        }
+
+        if !self.text_samples.is_empty() {
+            html.push_str("<h2>Text</h2>\n");
+            for sample in &self.text_samples {
+                let _ = writeln!(
+                    html,
+                    "<h3>{} <span class=\"description\">({} strings)</span></h3>",
+                    escape(&sample.path),
+                    sample.string_count
+                );
+                for string in &sample.strings {
+                    let mut shown = string.chars().take(MAX_STRING_LENGTH).collect::<String>();
+                    if shown.len() < string.len() {
+                        shown.push('…');
+                    }
+                    let _ = writeln!(html, "<blockquote>{}</blockquote>", escape(&shown));
+                }
+            }
+        }
+
+        html.push_str("<h2>Files</h2>\n");
+        let mut tree = TreeNode::default();
+        for file in &self.survey.files {
+            let node = file.path.split('/').fold(&mut tree, |node, name| {
+                node.children.entry(name.to_owned()).or_default()
+            });
+            node.description = Some(file.identification.description.clone());
+        }
+        tree.write_html(&mut html);
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+/// A file or directory of the file tree of a report.
+#[derive(Default)]
+struct TreeNode {
+    /// What the file was identified as. Directories have no description, while ROMs and
+    /// archives have both a description and children.
+    description: Option<String>,
+    children: BTreeMap<String, TreeNode>,
+}
+
+impl TreeNode {
+    fn write_html(&self, html: &mut String) {
+        html.push_str("<ul>\n");
+        for (name, child) in &self.children {
+            let description = child
+                .description
+                .as_ref()
+                .map_or(String::new(), |description| {
+                    format!(
+                        " <span class=\"description\">{}</span>",
+                        escape(description)
+                    )
+                });
+            if child.children.is_empty() {
+                let _ = writeln!(html, "<li>{}{description}</li>", escape(name));
+            } else {
+                let _ = writeln!(
+                    html,
+                    "<li><details><summary>{}/{description}</summary>",
+                    escape(name)
+                );
+                child.write_html(html);
+                html.push_str("</details></li>\n");
+            }
+        }
+        html.push_str("</ul>\n");
+    }
+}
+
+/// Draws the number of files of each format as an SVG pie chart, next to its legend.
+fn format_chart(counts: &[(String, usize)]) -> String {
+    let mut slices = counts
+        .iter()
+        .take(MAX_CHART_SLICES)
+        .map(|(format, count)| (format.clone(), *count))
+        .collect::<Vec<_>>();
+    let other_count = counts
+        .iter()
+        .skip(MAX_CHART_SLICES)
+        .map(|(_, count)| count)
+        .sum::<usize>();
+    if other_count > 0 {
+        slices.push(("other formats".to_owned(), other_count));
+    }
+    let total = slices.iter().map(|(_, count)| count).sum::<usize>();
+    if total == 0 {
+        return String::new();
+    }
+
+    let mut svg = String::from("<svg width=\"200\" height=\"200\" viewBox=\"-1 -1 2 2\">\n");
+    let mut legend = String::from("<ul>\n");
+    let mut angle = 0.0;
+    for ((format, count), color) in slices.iter().zip(CHART_COLORS) {
+        let fraction = *count as f64 / total as f64;
+        if *count == total {
+            let _ = writeln!(svg, "<circle r=\"1\" fill=\"{color}\"/>");
+        } else {
+            let end = angle + fraction * TAU;
+            // Angles start from the top of the circle, going clockwise.
+            let point = |angle: f64| (angle.sin(), -angle.cos());
+            let ((x1, y1), (x2, y2)) = (point(angle), point(end));
+            let large_arc = u8::from(fraction > 0.5);
+            let _ = writeln!(
+                svg,
+                "<path d=\"M0,0 L{x1:.4},{y1:.4} A1,1 0 {large_arc} 1 {x2:.4},{y2:.4} Z\" fill=\"{color}\"/>"
+            );
+            angle = end;
+        }
+        let _ = writeln!(
+            legend,
+            "<li><span class=\"swatch\" style=\"background:{color}\"></span>{}: {count} ({:.1}%)</li>",
+            escape(format),
+            fraction * 100.0
+        );
+    }
+    svg.push_str("</svg>\n");
+    legend.push_str("</ul>\n");
+    format!("<div class=\"chart\">\n{svg}{legend}</div>\n")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Encodes data as standard Base64, with padding, as data URLs hold.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let value = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                output.push(ALPHABET[(value >> (18 - index * 6)) as usize & 0x3F] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
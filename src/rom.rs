@@ -0,0 +1,1487 @@
+use std::{
+    collections::HashSet,
+    io::Read,
+    ops::Range,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::Context;
+use std::fs;
+use thiserror::Error;
+
+use crate::{
+    fnt::{self, ROOT_DIR_ID},
+    path_escape, source,
+};
+
+#[derive(Error, Debug)]
+pub enum RomParseError {
+    #[error(
+        "ROM is too small to hold a header (0x{size:X} bytes, expected at least 0x{HEADER_SIZE:X})"
+    )]
+    TooSmall { size: usize },
+    #[error(
+        "{table} (0x{offset:08X}, 0x{size:X} bytes) lies outside of the ROM (0x{rom_size:X} bytes)"
+    )]
+    TableOutOfBounds {
+        table: &'static str,
+        offset: usize,
+        size: usize,
+        rom_size: usize,
+    },
+    #[error("invalid FAT size (0x{size:X} bytes, expected a multiple of 8)")]
+    InvalidFatSize { size: usize },
+    #[error("invalid FNT: {0}")]
+    InvalidFnt(String),
+    #[error("file {id} points outside of the ROM (0x{start:08X}..0x{end:08X}, ROM size: 0x{rom_size:X})")]
+    FileOutOfBounds {
+        id: u16,
+        start: usize,
+        end: usize,
+        rom_size: usize,
+    },
+    #[error("failed to parse NitroFS: {0}")]
+    Filesystem(String),
+}
+
+#[derive(Error, Debug)]
+pub enum HeaderEditError {
+    #[error("{field} {value:?} is too long (found: {len} bytes, maximum: {max} bytes)")]
+    TooLong {
+        field: &'static str,
+        value: String,
+        len: usize,
+        max: usize,
+    },
+    #[error("{field} {value:?} must be exactly {len} characters long")]
+    InvalidLength {
+        field: &'static str,
+        value: String,
+        len: usize,
+    },
+    #[error("{field} {value:?} may only contain uppercase ASCII letters and digits")]
+    InvalidCharacters { field: &'static str, value: String },
+}
+
+/// Magic number of zip archives, which ROM dumps are often distributed in.
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Extensions of the ROMs looked for inside zip archives.
+const ROM_EXTENSIONS: [&str; 3] = ["nds", "dsi", "srl"];
+
+/// Whether the file at a path is a zip archive.
+pub fn is_zip(path: &Path) -> bool {
+    let mut magic = [0; ZIP_MAGIC.len()];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|()| magic == ZIP_MAGIC)
+}
+
+/// Whether a path has the extension of a ROM, or of a zip archive.
+pub fn has_rom_extension(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        ROM_EXTENSIONS
+            .iter()
+            .chain(&["zip"])
+            .any(|rom_extension| extension.eq_ignore_ascii_case(rom_extension))
+    })
+}
+
+/// Lists the ROMs (and zip archives) directly inside a directory, sorted by path.
+pub fn find_roms(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut roms = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("failed to read {dir:?}"))? {
+        let path = entry?.path();
+        if path.is_file() && has_rom_extension(&path) {
+            roms.push(path);
+        }
+    }
+    roms.sort();
+    Ok(roms)
+}
+
+/// Reads a whole ROM file into memory, checking that it's large enough to hold a header.
+/// Commands reading only parts of a ROM should open it with [`source::open`] instead.
+///
+/// ROMs inside zip archives are read directly, as long as the archive holds a single one.
+pub fn read_rom(path: &Path) -> anyhow::Result<Vec<u8>> {
+    read_rom_member(path, None)
+}
+
+/// Reads a whole ROM file into memory, like [`read_rom`]. If the file is a zip archive, the
+/// ROM read is its member named `member` (by its path inside the archive, or just its file
+/// name), or if none is given, the only ROM inside it.
+pub fn read_rom_member(path: &Path, member: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    let rom_data = source::open(path, member)?
+        .read_all()
+        .context("failed to read ROM")?;
+    check_header(&rom_data)?;
+    Ok(rom_data)
+}
+
+/// Chooses the member of a zip archive holding the ROM to read.
+pub(crate) fn zip_member_name<R: Read + std::io::Seek>(
+    archive: &zip::ZipArchive<R>,
+    member: Option<&str>,
+) -> anyhow::Result<String> {
+    let names = archive
+        .file_names()
+        .map(|name| name.map(|name| name.into_owned()))
+        .collect::<Result<Vec<_>, _>>()
+        .context("failed to read the names of the archive's members")?;
+    if let Some(member) = member {
+        return names
+            .into_iter()
+            .find(|name| name == member || name.rsplit('/').next() == Some(member))
+            .ok_or_else(|| anyhow::anyhow!("the archive has no member named {member:?}"));
+    }
+    let roms = names
+        .into_iter()
+        .filter(|name| {
+            Path::new(name).extension().is_some_and(|extension| {
+                ROM_EXTENSIONS
+                    .iter()
+                    .any(|rom_extension| extension.eq_ignore_ascii_case(rom_extension))
+            })
+        })
+        .collect::<Vec<_>>();
+    match roms.as_slice() {
+        [name] => Ok(name.clone()),
+        [] => Err(anyhow::anyhow!(
+            "the archive holds no ROM (.nds, .dsi or .srl)"
+        )),
+        _ => Err(anyhow::anyhow!(
+            "the archive holds several ROMs ({}); choose which one to read",
+            roms.join(", ")
+        )),
+    }
+}
+
+/// Checks that the data given can hold a ROM header, so that its fields can be read.
+pub fn check_header(rom_data: &[u8]) -> Result<(), RomParseError> {
+    if rom_data.len() < HEADER_SIZE {
+        return Err(RomParseError::TooSmall {
+            size: rom_data.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Size of the regular NDS header, which every ROM starts with.
+pub const HEADER_SIZE: usize = 0x200;
+/// Size of the extended header of DSi ROMs, holding the locations of the DSi sections.
+pub const DSI_HEADER_SIZE: usize = 0x1000;
+
+/// Offset of the device capacity field in the ROM header.
+pub const DEVICE_CAPACITY_OFFSET: usize = 0x14;
+/// Offset of the unit code field in the ROM header, telling which consoles the ROM runs on.
+pub const UNIT_CODE_OFFSET: usize = 0x12;
+/// Offset of the ARM9i binary address field in the extended header of DSi ROMs.
+pub const ARM9I_ADDR_OFFSET: usize = 0x1C0;
+/// Offset of the ARM9i binary size field in the extended header of DSi ROMs.
+pub const ARM9I_SIZE_OFFSET: usize = 0x1CC;
+/// Offset of the ARM7i binary address field in the extended header of DSi ROMs.
+pub const ARM7I_ADDR_OFFSET: usize = 0x1D0;
+/// Offset of the ARM7i binary size field in the extended header of DSi ROMs.
+pub const ARM7I_SIZE_OFFSET: usize = 0x1DC;
+/// Offset of the digest sector hash table address field in the extended header of DSi ROMs.
+pub const DIGEST_SECTOR_HASHTABLE_ADDR_OFFSET: usize = 0x1F0;
+/// Offset of the digest sector hash table size field in the extended header of DSi ROMs.
+pub const DIGEST_SECTOR_HASHTABLE_SIZE_OFFSET: usize = 0x1F4;
+/// Offset of the digest block hash table address field in the extended header of DSi ROMs.
+pub const DIGEST_BLOCK_HASHTABLE_ADDR_OFFSET: usize = 0x1F8;
+/// Offset of the digest block hash table size field in the extended header of DSi ROMs.
+pub const DIGEST_BLOCK_HASHTABLE_SIZE_OFFSET: usize = 0x1FC;
+/// Offset of the total used ROM size field of DSi ROMs, which includes the DSi area.
+pub const DSI_USED_ROM_SIZE_OFFSET: usize = 0x210;
+/// Offsets of the address fields of the two modcrypt areas of DSi ROMs. Each one is followed by
+/// the size of the area.
+pub const MODCRYPT_ADDR_OFFSETS: [usize; 2] = [0x220, 0x228];
+/// Offset of the ARM9 binary address field in the ROM header.
+pub const ARM9_ADDR_OFFSET: usize = 0x20;
+/// Offset of the ARM9 binary RAM address field in the ROM header.
+pub const ARM9_RAM_ADDR_OFFSET: usize = 0x28;
+/// Offset of the ARM9 binary size field in the ROM header.
+pub const ARM9_SIZE_OFFSET: usize = 0x2C;
+/// Offset of the ARM7 binary address field in the ROM header.
+pub const ARM7_ADDR_OFFSET: usize = 0x30;
+/// Offset of the ARM7 binary RAM address field in the ROM header.
+pub const ARM7_RAM_ADDR_OFFSET: usize = 0x38;
+/// Offset of the ARM7 binary size field in the ROM header.
+pub const ARM7_SIZE_OFFSET: usize = 0x3C;
+/// Offset of the FNT address field in the ROM header.
+pub const FNT_ADDR_OFFSET: usize = 0x40;
+/// Offset of the FNT size field in the ROM header.
+pub const FNT_SIZE_OFFSET: usize = 0x44;
+/// Offset of the FAT address field in the ROM header.
+pub const FAT_ADDR_OFFSET: usize = 0x48;
+/// Offset of the FAT size field in the ROM header.
+pub const FAT_SIZE_OFFSET: usize = 0x4C;
+/// Offset of the ARM9 overlay table address field in the ROM header.
+pub const ARM9_OVERLAY_ADDR_OFFSET: usize = 0x50;
+/// Offset of the ARM9 overlay table size field in the ROM header.
+pub const ARM9_OVERLAY_SIZE_OFFSET: usize = 0x54;
+/// Offset of the ARM7 overlay table address field in the ROM header.
+pub const ARM7_OVERLAY_ADDR_OFFSET: usize = 0x58;
+/// Offset of the ARM7 overlay table size field in the ROM header.
+pub const ARM7_OVERLAY_SIZE_OFFSET: usize = 0x5C;
+/// Offset of the banner address field in the ROM header.
+pub const BANNER_ADDR_OFFSET: usize = 0x68;
+/// Offset of the total used ROM size field in the ROM header.
+pub const USED_ROM_SIZE_OFFSET: usize = 0x80;
+/// Offset of the ROM header size field in the ROM header.
+pub const HEADER_SIZE_OFFSET: usize = 0x84;
+/// Size of the RSA signature retail ROMs have right after their used area.
+pub const RSA_SIGNATURE_SIZE: usize = 0x88;
+/// Offset of the header checksum, which covers every byte before it.
+pub const HEADER_CRC_OFFSET: usize = 0x15E;
+/// Size of each entry in the overlay tables.
+pub const OVERLAY_ENTRY_SIZE: usize = 0x20;
+
+/// Alignment used for file data placed in the ROM, unless the original ROM used another one.
+pub const FILE_ALIGNMENT: usize = 0x200;
+/// Largest alignment detected from the file offsets of a ROM.
+const MAX_DETECTED_ALIGNMENT: usize = 0x1000;
+/// Byte filling the space between file data, unless the original ROM used another one.
+pub const PAD_BYTE: u8 = 0xFF;
+
+pub fn u32_at(rom_data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(rom_data[offset..offset + 4].try_into().unwrap())
+}
+
+fn u16_at(rom_data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(rom_data[offset..offset + 2].try_into().unwrap())
+}
+
+pub fn set_u32_at(rom_data: &mut [u8], offset: usize, value: u32) {
+    rom_data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// CRC-16 as used by the NDS for its header, banner & secure area checksums.
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u16, |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// Recalculates the header checksum. Must be called after modifying any header field.
+pub fn fix_header_crc(rom_data: &mut [u8]) {
+    let crc = crc16(&rom_data[..HEADER_CRC_OFFSET]);
+    rom_data[HEADER_CRC_OFFSET..HEADER_CRC_OFFSET + 2].copy_from_slice(&crc.to_le_bytes());
+}
+
+/// Checks whether the data given starts with a ROM header with a valid checksum, whose FNT & FAT
+/// lie within the data.
+pub fn is_rom(data: &[u8]) -> bool {
+    if check_header(data).is_err() {
+        return false;
+    }
+    let crc = u16_at(data, HEADER_CRC_OFFSET);
+    crc == crc16(&data[..HEADER_CRC_OFFSET])
+        && table_range(data, "FNT", FNT_ADDR_OFFSET, FNT_SIZE_OFFSET).is_ok()
+        && table_range(data, "FAT", FAT_ADDR_OFFSET, FAT_SIZE_OFFSET).is_ok()
+}
+
+/// Location of a table of the ROM given, from the header fields holding its address & size,
+/// failing if it doesn't lie inside the ROM.
+pub fn table_range(
+    rom_data: &[u8],
+    table: &'static str,
+    addr_field: usize,
+    size_field: usize,
+) -> Result<Range<usize>, RomParseError> {
+    let offset = u32_at(rom_data, addr_field) as usize;
+    let size = u32_at(rom_data, size_field) as usize;
+    offset
+        .checked_add(size)
+        .filter(|&end| end <= rom_data.len())
+        .map(|end| offset..end)
+        .ok_or(RomParseError::TableOutOfBounds {
+            table,
+            offset,
+            size,
+            rom_size: rom_data.len(),
+        })
+}
+
+pub fn align_up(value: usize, alignment: usize) -> usize {
+    value.div_ceil(alignment) * alignment
+}
+
+/// Returns the largest power of two (up to 0x1000) dividing the offset of every non-empty file
+/// of the NitroFS, which is the alignment the ROM was built with.
+pub fn detect_alignment(fs: &nitro_fs::FileSystem) -> usize {
+    let trailing_zeros = fs
+        .files()
+        .iter()
+        .filter(|entry| entry.alloc.end > entry.alloc.start)
+        .map(|entry| entry.alloc.start.trailing_zeros())
+        .min();
+    match trailing_zeros {
+        Some(zeros) => (1usize << zeros.min(31)).clamp(4, MAX_DETECTED_ALIGNMENT),
+        None => FILE_ALIGNMENT,
+    }
+}
+
+/// Returns the byte filling the gaps between the files of the NitroFS, either `0x00` or
+/// `0xFF`, whichever is most common.
+pub fn detect_pad_byte(rom_data: &[u8], fs: &nitro_fs::FileSystem) -> u8 {
+    let mut allocs = fs
+        .files()
+        .iter()
+        .map(|entry| (entry.alloc.start as usize, entry.alloc.end as usize))
+        .collect::<Vec<_>>();
+    allocs.sort();
+    let (mut zeros, mut ones) = (0, 0);
+    for pair in allocs.windows(2) {
+        let gap = rom_data.get(pair[0].1..pair[1].0).unwrap_or_default();
+        zeros += gap.iter().filter(|&&byte| byte == 0x00).count();
+        ones += gap.iter().filter(|&&byte| byte == 0xFF).count();
+    }
+    if zeros > ones {
+        0x00
+    } else {
+        PAD_BYTE
+    }
+}
+
+/// Returns the smallest device capacity value (a shift over 128 KiB) able to hold a ROM of
+/// the size given.
+pub fn device_capacity_for(rom_size: usize) -> u8 {
+    let mut capacity = 0;
+    while (0x20000usize << capacity) < rom_size {
+        capacity += 1;
+    }
+    capacity
+}
+
+/// Returns the size of the ROM without the padding after its used area. The RSA signature of
+/// retail ROMs is kept, along with any other data that isn't `0x00` or `0xFF` padding.
+pub fn trimmed_size(rom_data: &[u8]) -> usize {
+    let used_size = (u32_at(rom_data, USED_ROM_SIZE_OFFSET) as usize).min(rom_data.len());
+    let data_end = rom_data
+        .iter()
+        .rposition(|&byte| byte != 0x00 && byte != 0xFF)
+        .map_or(0, |last| last + 1);
+    let signature_end = (used_size + RSA_SIGNATURE_SIZE).min(rom_data.len());
+    let has_signature = rom_data[used_size..signature_end]
+        .iter()
+        .any(|&byte| byte != 0x00 && byte != 0xFF);
+    let used_end = if has_signature {
+        signature_end
+    } else {
+        used_size
+    };
+    used_end.max(data_end)
+}
+
+/// Sets the device capacity in the header to the smallest one able to hold the ROM.
+pub fn fit_device_capacity(rom_data: &mut [u8]) {
+    rom_data[DEVICE_CAPACITY_OFFSET] = device_capacity_for(rom_data.len());
+    fix_header_crc(rom_data);
+}
+
+/// Which consoles a ROM runs on, according to its unit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnitCode {
+    /// The ROM only has the regular NDS header and binaries.
+    Nds,
+    /// The ROM runs on both the NDS and the DSi, with extra binaries for the DSi.
+    DsiEnhanced,
+    /// The ROM only runs on the DSi.
+    DsiExclusive,
+}
+
+impl UnitCode {
+    /// Reads the unit code of a ROM. Headers too small to hold the DSi fields count as NDS ones.
+    pub fn of(rom_data: &[u8]) -> Self {
+        if rom_data.len() < DSI_HEADER_SIZE {
+            return UnitCode::Nds;
+        }
+        match rom_data[UNIT_CODE_OFFSET] {
+            0x02 => UnitCode::DsiEnhanced,
+            0x03 => UnitCode::DsiExclusive,
+            _ => UnitCode::Nds,
+        }
+    }
+
+    /// Whether the ROM has an extended header with DSi sections.
+    pub fn is_dsi(self) -> bool {
+        self != UnitCode::Nds
+    }
+
+    /// Name of the unit code as shown to users.
+    pub fn name(self) -> &'static str {
+        match self {
+            UnitCode::Nds => "NDS",
+            UnitCode::DsiEnhanced => "DSi-enhanced",
+            UnitCode::DsiExclusive => "DSi-exclusive",
+        }
+    }
+}
+
+/// Location of the internal title in the ROM header, padded with null bytes.
+pub const TITLE_RANGE: Range<usize> = 0x0..0xC;
+/// Location of the 4-letter game code in the ROM header, whose last letter tells the region.
+pub const GAME_CODE_RANGE: Range<usize> = 0xC..0x10;
+/// Location of the 2-letter maker code in the ROM header, such as `01` for Nintendo.
+pub const MAKER_CODE_RANGE: Range<usize> = 0x10..0x12;
+/// Offset of the ROM version field in the ROM header.
+pub const ROM_VERSION_OFFSET: usize = 0x1E;
+
+/// Reads a text field of the ROM header, leaving out its padding.
+pub fn header_text(rom_data: &[u8], range: Range<usize>) -> String {
+    String::from_utf8_lossy(&rom_data[range])
+        .trim_end_matches('\0')
+        .to_owned()
+}
+
+/// Writes the internal title of a ROM, padding it with null bytes. Titles are printable ASCII of
+/// up to 12 characters.
+pub fn set_title(rom_data: &mut [u8], title: &str) -> Result<(), HeaderEditError> {
+    if title.len() > TITLE_RANGE.len() {
+        return Err(HeaderEditError::TooLong {
+            field: "title",
+            value: title.to_owned(),
+            len: title.len(),
+            max: TITLE_RANGE.len(),
+        });
+    }
+    if !title
+        .bytes()
+        .all(|byte| byte.is_ascii_graphic() || byte == b' ')
+    {
+        return Err(HeaderEditError::InvalidCharacters {
+            field: "title",
+            value: title.to_owned(),
+        });
+    }
+    let field = &mut rom_data[TITLE_RANGE];
+    field.fill(0);
+    field[..title.len()].copy_from_slice(title.as_bytes());
+    Ok(())
+}
+
+/// Writes a fixed-length code field of the header, such as the game code or the maker code.
+pub fn set_header_code(
+    rom_data: &mut [u8],
+    field: &'static str,
+    range: Range<usize>,
+    code: &str,
+) -> Result<(), HeaderEditError> {
+    if code.len() != range.len() {
+        return Err(HeaderEditError::InvalidLength {
+            field,
+            value: code.to_owned(),
+            len: range.len(),
+        });
+    }
+    if !code
+        .bytes()
+        .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit())
+    {
+        return Err(HeaderEditError::InvalidCharacters {
+            field,
+            value: code.to_owned(),
+        });
+    }
+    rom_data[range].copy_from_slice(code.as_bytes());
+    Ok(())
+}
+
+/// Name of the region a game code is for, going by its last letter.
+pub fn region_name(game_code: &str) -> &'static str {
+    match game_code.chars().nth(3) {
+        Some('A') => "Asia",
+        Some('C') => "China",
+        Some('D') => "Germany",
+        Some('E') => "USA",
+        Some('F') => "France",
+        Some('H') => "Netherlands",
+        Some('I') => "Italy",
+        Some('J') => "Japan",
+        Some('K') => "Korea",
+        Some('L') => "USA (second release)",
+        Some('M') => "Sweden",
+        Some('N') => "Norway",
+        Some('O') => "International",
+        Some('P' | 'W' | 'X' | 'Y' | 'Z') => "Europe",
+        Some('Q') => "Denmark",
+        Some('R') => "Russia",
+        Some('S') => "Spain",
+        Some('T') => "USA & Australia",
+        Some('U') => "Australia",
+        Some('V') => "Europe & Australia",
+        _ => "unknown",
+    }
+}
+
+/// Size of the header of homebrew ROMs, which have no secure area after it.
+const HOMEBREW_HEADER_SIZE: usize = 0x200;
+/// Address homebrew ARM9 binaries are loaded and started at.
+const HOMEBREW_ARM9_ADDRESS: u32 = 0x0200_0000;
+/// Address homebrew ARM7 binaries are loaded and started at.
+const HOMEBREW_ARM7_ADDRESS: u32 = 0x0238_0000;
+
+/// Builds the header of a homebrew ROM with the title given, whose binaries are loaded at the
+/// addresses devkitARM links them to. The locations of the sections are left for `pack` to fill.
+pub fn homebrew_header(title: &str) -> Vec<u8> {
+    let mut header = vec![0; HOMEBREW_HEADER_SIZE];
+    let title = title
+        .chars()
+        .filter(|ch| ch.is_ascii_graphic() || *ch == ' ')
+        .map(|ch| ch.to_ascii_uppercase() as u8)
+        .take(TITLE_RANGE.len())
+        .collect::<Vec<_>>();
+    header[..title.len()].copy_from_slice(&title);
+    header[GAME_CODE_RANGE].copy_from_slice(b"####");
+    header[MAKER_CODE_RANGE].copy_from_slice(b"00");
+    set_u32_at(&mut header, ARM9_ADDR_OFFSET + 4, HOMEBREW_ARM9_ADDRESS);
+    set_u32_at(&mut header, ARM9_ADDR_OFFSET + 8, HOMEBREW_ARM9_ADDRESS);
+    set_u32_at(&mut header, ARM7_ADDR_OFFSET + 4, HOMEBREW_ARM7_ADDRESS);
+    set_u32_at(&mut header, ARM7_ADDR_OFFSET + 8, HOMEBREW_ARM7_ADDRESS);
+    set_u32_at(&mut header, HEADER_SIZE_OFFSET, HOMEBREW_HEADER_SIZE as u32);
+    fix_header_crc(&mut header);
+    header
+}
+
+/// Size of the banner, which depends on its version.
+pub fn banner_size(version: u16) -> usize {
+    match version {
+        0x0002 => 0x940,
+        0x0003 => 0xA40,
+        0x0103 => 0x23C0,
+        _ => 0x840,
+    }
+}
+
+/// Regions of the ROM outside of the NitroFS, located through the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Section {
+    Header,
+    Arm9,
+    Arm9OverlayTable,
+    Arm7,
+    Arm7OverlayTable,
+    Banner,
+    /// ARM9 binary run by the DSi in addition to the ARM9 binary, usually modcrypt-encrypted.
+    Arm9i,
+    /// ARM7 binary run by the DSi in addition to the ARM7 binary, usually modcrypt-encrypted.
+    Arm7i,
+    DigestSectorHashtable,
+    DigestBlockHashtable,
+}
+
+impl Section {
+    pub const ALL: [Section; 10] = [
+        Section::Header,
+        Section::Arm9,
+        Section::Arm9OverlayTable,
+        Section::Arm7,
+        Section::Arm7OverlayTable,
+        Section::Banner,
+        Section::Arm9i,
+        Section::Arm7i,
+        Section::DigestSectorHashtable,
+        Section::DigestBlockHashtable,
+    ];
+
+    /// Whether this section is only found in DSi ROMs, located through the extended header.
+    pub fn is_dsi_only(self) -> bool {
+        matches!(
+            self,
+            Section::Arm9i
+                | Section::Arm7i
+                | Section::DigestSectorHashtable
+                | Section::DigestBlockHashtable
+        )
+    }
+
+    /// Human-readable name of this section.
+    pub fn name(self) -> &'static str {
+        match self {
+            Section::Header => "header",
+            Section::Arm9 => "ARM9 binary",
+            Section::Arm9OverlayTable => "ARM9 overlay table",
+            Section::Arm7 => "ARM7 binary",
+            Section::Arm7OverlayTable => "ARM7 overlay table",
+            Section::Banner => "banner",
+            Section::Arm9i => "ARM9i binary",
+            Section::Arm7i => "ARM7i binary",
+            Section::DigestSectorHashtable => "digest sector hash table",
+            Section::DigestBlockHashtable => "digest block hash table",
+        }
+    }
+
+    /// Name of the file this section is unpacked to.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Section::Header => "header.bin",
+            Section::Arm9 => "arm9.bin",
+            Section::Arm9OverlayTable => "arm9_overlay_table.bin",
+            Section::Arm7 => "arm7.bin",
+            Section::Arm7OverlayTable => "arm7_overlay_table.bin",
+            Section::Banner => "banner.bin",
+            Section::Arm9i => "arm9i.bin",
+            Section::Arm7i => "arm7i.bin",
+            Section::DigestSectorHashtable => "digest_sector_hashtable.bin",
+            Section::DigestBlockHashtable => "digest_block_hashtable.bin",
+        }
+    }
+
+    /// Offsets of the header fields holding the address & size of this section.
+    ///
+    /// The header and banner have no size field; their size is derived from their contents.
+    pub fn header_fields(self) -> (Option<usize>, Option<usize>) {
+        match self {
+            Section::Header => (None, Some(HEADER_SIZE_OFFSET)),
+            Section::Arm9 => (Some(ARM9_ADDR_OFFSET), Some(ARM9_SIZE_OFFSET)),
+            Section::Arm9OverlayTable => (
+                Some(ARM9_OVERLAY_ADDR_OFFSET),
+                Some(ARM9_OVERLAY_SIZE_OFFSET),
+            ),
+            Section::Arm7 => (Some(ARM7_ADDR_OFFSET), Some(ARM7_SIZE_OFFSET)),
+            Section::Arm7OverlayTable => (
+                Some(ARM7_OVERLAY_ADDR_OFFSET),
+                Some(ARM7_OVERLAY_SIZE_OFFSET),
+            ),
+            Section::Banner => (Some(BANNER_ADDR_OFFSET), None),
+            Section::Arm9i => (Some(ARM9I_ADDR_OFFSET), Some(ARM9I_SIZE_OFFSET)),
+            Section::Arm7i => (Some(ARM7I_ADDR_OFFSET), Some(ARM7I_SIZE_OFFSET)),
+            Section::DigestSectorHashtable => (
+                Some(DIGEST_SECTOR_HASHTABLE_ADDR_OFFSET),
+                Some(DIGEST_SECTOR_HASHTABLE_SIZE_OFFSET),
+            ),
+            Section::DigestBlockHashtable => (
+                Some(DIGEST_BLOCK_HASHTABLE_ADDR_OFFSET),
+                Some(DIGEST_BLOCK_HASHTABLE_SIZE_OFFSET),
+            ),
+        }
+    }
+
+    /// Location of this section in the ROM given, or `None` if the ROM doesn't have it.
+    pub fn range(self, rom_data: &[u8]) -> Option<Range<usize>> {
+        if self.is_dsi_only() && !UnitCode::of(rom_data).is_dsi() {
+            return None;
+        }
+        let (addr_field, size_field) = self.header_fields();
+        let start = addr_field.map_or(0, |field| u32_at(rom_data, field) as usize);
+        if addr_field.is_some() && start == 0 {
+            return None;
+        }
+        let size = match size_field {
+            Some(field) => u32_at(rom_data, field) as usize,
+            None => {
+                let version =
+                    u16::from_le_bytes(rom_data.get(start..start + 2)?.try_into().unwrap());
+                banner_size(version)
+            }
+        };
+        let end = start
+            .checked_add(size)
+            .filter(|&end| size != 0 && end <= rom_data.len())?;
+        Some(start..end)
+    }
+}
+
+/// Size of the smallest FNT, holding only the root directory.
+const MIN_FNT_SIZE: usize = 8;
+
+/// Whether the ROM given has a NitroFS. Homebrew ROMs often have none, leaving the FNT & FAT
+/// locations in their header empty.
+pub fn has_filesystem(rom_data: &[u8]) -> bool {
+    u32_at(rom_data, FNT_SIZE_OFFSET) as usize >= MIN_FNT_SIZE
+}
+
+/// Largest number of directories a FNT can hold, as directory IDs go from `0xF000` to `0xFFFF`.
+const MAX_DIR_COUNT: usize = 0x1000;
+
+/// Parses the NitroFS contained in the ROM given, using the FNT & FAT locations from its header.
+/// ROMs without a NitroFS get an empty one.
+pub fn filesystem(rom_data: &[u8]) -> Result<nitro_fs::FileSystem, RomParseError> {
+    if !has_filesystem(rom_data) {
+        return Ok(nitro_fs::FileSystem::default());
+    }
+    let fnt = &rom_data[table_range(rom_data, "FNT", FNT_ADDR_OFFSET, FNT_SIZE_OFFSET)?];
+    let fat = &rom_data[table_range(rom_data, "FAT", FAT_ADDR_OFFSET, FAT_SIZE_OFFSET)?];
+    parse_filesystem(fnt, fat)
+}
+
+/// Parses a NitroFS from its FNT & FAT.
+pub fn parse_filesystem(fnt: &[u8], fat: &[u8]) -> Result<nitro_fs::FileSystem, RomParseError> {
+    if !fat.len().is_multiple_of(8) {
+        return Err(RomParseError::InvalidFatSize { size: fat.len() });
+    }
+    validate_fnt(fnt, fat.len() / 8)?;
+
+    let parsed =
+        fnt::parse_fnt(fnt).map_err(|error| RomParseError::InvalidFnt(error.to_string()))?;
+    if !parsed
+        .names
+        .iter()
+        .any(|range| path_escape::needs_decoding(&fnt[range.clone()]))
+    {
+        return nitro_fs::FileSystem::new(fnt, fat)
+            .map_err(|error| RomParseError::Filesystem(format!("{error:#}")));
+    }
+
+    // The NitroFS parser only takes names as they are and as UTF-8, so parse a copy of the FNT
+    // with placeholder names, then give every file & directory its decoded path.
+    let mut placeholder_fnt = fnt.to_vec();
+    for range in parsed.names {
+        placeholder_fnt[range].fill(b'_');
+    }
+    let mut fs = nitro_fs::FileSystem::new(&placeholder_fnt, fat)
+        .map_err(|error| RomParseError::Filesystem(format!("{error:#}")))?;
+    let file_paths = parsed.files.into_iter().map(|(path, id)| (id, path));
+    let file_paths = file_paths.collect::<std::collections::HashMap<_, _>>();
+    let dir_paths = parsed.directories.into_iter().map(|(path, id)| (id, path));
+    let dir_paths = dir_paths.collect::<std::collections::HashMap<_, _>>();
+    for dir in fs.dirs.values_mut() {
+        if let Some(path) = dir_paths.get(&dir.id()) {
+            dir.set_path(path);
+        }
+        for file in &mut dir.files {
+            if let Some(path) = file_paths.get(&file.id) {
+                file.path = PathBuf::from(path);
+            }
+        }
+    }
+    Ok(fs)
+}
+
+/// Walks the directory tree of a FNT, checking that its subtables lie inside it and that it
+/// only references directories it holds and files the FAT holds. The NitroFS parser assumes
+/// all of this, and panics or recurses forever otherwise.
+fn validate_fnt(fnt: &[u8], fat_len: usize) -> Result<(), RomParseError> {
+    let invalid = |message: String| Err(RomParseError::InvalidFnt(message));
+    if fnt.len() < MIN_FNT_SIZE {
+        return invalid(format!(
+            "too small to hold the root directory (0x{:X} bytes)",
+            fnt.len()
+        ));
+    }
+    let dir_count = u16_at(fnt, 6) as usize;
+    if dir_count == 0 || dir_count > MAX_DIR_COUNT || dir_count * 8 > fnt.len() {
+        return invalid(format!("invalid directory count ({dir_count})"));
+    }
+    // File IDs are 16-bit, and the parser counts one past the last one of each directory.
+    let file_count = fat_len.min(u16::MAX as usize);
+    // Every file ID below the first one of the root directory is an overlay.
+    let overlay_count = u16_at(fnt, 4) as usize;
+    if overlay_count > file_count {
+        return invalid(format!(
+            "root directory starts at file ID {overlay_count}, past the end of the FAT"
+        ));
+    }
+
+    let mut visited = vec![false; dir_count];
+    let mut pending = vec![ROOT_DIR_ID];
+    while let Some(dir_id) = pending.pop() {
+        let index = dir_id.wrapping_sub(ROOT_DIR_ID) as usize;
+        if index >= dir_count {
+            return invalid(format!("reference to missing directory 0x{dir_id:04X}"));
+        }
+        if std::mem::replace(&mut visited[index], true) {
+            return invalid(format!(
+                "directory 0x{dir_id:04X} is referenced more than once"
+            ));
+        }
+        let past_end = || {
+            invalid(format!(
+                "subtable of directory 0x{dir_id:04X} runs past the end of the FNT"
+            ))
+        };
+        let mut offset = u32_at(fnt, index * 8) as usize;
+        let mut file_id = u16_at(fnt, index * 8 + 4) as usize;
+        loop {
+            let Some(&len) = fnt.get(offset) else {
+                return past_end();
+            };
+            if len == 0 {
+                break;
+            }
+            // A directory with an empty name, which the NitroFS parser reads as a file instead.
+            if len == 0x80 {
+                return invalid(format!(
+                    "empty directory name in directory 0x{dir_id:04X} at 0x{offset:X}"
+                ));
+            }
+            let is_dir = len > 0x80;
+            offset += 1 + (if is_dir { len - 0x80 } else { len }) as usize;
+            if is_dir {
+                let Some(id_bytes) = fnt.get(offset..offset + 2) else {
+                    return past_end();
+                };
+                pending.push(u16::from_le_bytes(id_bytes.try_into().unwrap()));
+                offset += 2;
+            } else {
+                if file_id >= file_count {
+                    return invalid(format!(
+                        "file ID {file_id} in directory 0x{dir_id:04X} is not in the FAT"
+                    ));
+                }
+                file_id += 1;
+            }
+        }
+    }
+    if let Some(index) = visited.iter().position(|&visited| !visited) {
+        return invalid(format!(
+            "directory 0x{:04X} is not inside any other",
+            ROOT_DIR_ID as usize + index
+        ));
+    }
+    Ok(())
+}
+
+/// Finds the entries of the FAT that neither the FNT nor an overlay table references, such as
+/// files games only load by ID, returning the file ID and location of each.
+pub fn orphan_files(rom_data: &[u8], fs: &nitro_fs::FileSystem) -> Vec<(u16, Range<usize>)> {
+    if !has_filesystem(rom_data) {
+        return Vec::new();
+    }
+    let Ok(fat) = table_range(rom_data, "FAT", FAT_ADDR_OFFSET, FAT_SIZE_OFFSET) else {
+        return Vec::new();
+    };
+    let fat = &rom_data[fat];
+    let mut referenced = fs
+        .files()
+        .iter()
+        .map(|entry| entry.id)
+        .collect::<HashSet<_>>();
+    for table in [Section::Arm9OverlayTable, Section::Arm7OverlayTable] {
+        if let Some(range) = table.range(rom_data) {
+            referenced.extend(overlay_file_ids(&rom_data[range]));
+        }
+    }
+    fat.chunks_exact(8)
+        .enumerate()
+        .filter(|&(file_id, _)| !referenced.contains(&(file_id as u16)))
+        .map(|(file_id, entry)| {
+            (
+                file_id as u16,
+                u32_at(entry, 0) as usize..u32_at(entry, 4) as usize,
+            )
+        })
+        .collect()
+}
+
+/// Returns the data of a file entry of the NitroFS. Any part of it lying outside of the ROM, as
+/// happens with malformed FATs, is left out.
+pub fn file_data<'rom>(rom_data: &'rom [u8], entry: &nitro_fs::fnt::FileEntry) -> &'rom [u8] {
+    let end = (entry.alloc.end as usize).min(rom_data.len());
+    let start = (entry.alloc.start as usize).min(end);
+    &rom_data[start..end]
+}
+
+/// Returns the data of a file entry of the NitroFS, failing if it doesn't lie inside the ROM.
+pub fn checked_file_data<'rom>(
+    rom_data: &'rom [u8],
+    entry: &nitro_fs::fnt::FileEntry,
+) -> Result<&'rom [u8], RomParseError> {
+    let (start, end) = (entry.alloc.start as usize, entry.alloc.end as usize);
+    rom_data
+        .get(start..end)
+        .ok_or(RomParseError::FileOutOfBounds {
+            id: entry.id,
+            start,
+            end,
+            rom_size: rom_data.len(),
+        })
+}
+
+/// Returns the path given as a NitroFS path string, with components separated by `/`
+/// regardless of the host platform.
+pub fn nitro_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Returns the file IDs referenced by an overlay table, in table order.
+pub fn overlay_file_ids(overlay_table: &[u8]) -> Vec<u16> {
+    overlay_table
+        .chunks_exact(OVERLAY_ENTRY_SIZE)
+        .map(|entry| u32_at(entry, 0x18) as u16)
+        .collect()
+}
+
+/// Include/exclude filter over NitroFS paths, using glob patterns.
+///
+/// A path passes the filter if it matches any of the include patterns (or there are none), and
+/// none of the exclude patterns.
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+}
+
+impl PathFilter {
+    const MATCH_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, glob::PatternError> {
+        let parse = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| glob::Pattern::new(pattern.trim_start_matches('/')))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Self {
+            include: parse(include)?,
+            exclude: parse(exclude)?,
+        })
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        let path = nitro_path(path);
+        let matches_any = |patterns: &[glob::Pattern]| {
+            patterns
+                .iter()
+                .any(|pattern| pattern.matches_with(&path, Self::MATCH_OPTIONS))
+        };
+        (self.include.is_empty() || matches_any(&self.include)) && !matches_any(&self.exclude)
+    }
+}
+
+/// Offset past everything the header and FAT reference, after which data can be added freely.
+fn data_end(rom_data: &[u8]) -> Result<usize, RomParseError> {
+    let fnt_range = table_range(rom_data, "FNT", FNT_ADDR_OFFSET, FNT_SIZE_OFFSET)?;
+    let fat_range = table_range(rom_data, "FAT", FAT_ADDR_OFFSET, FAT_SIZE_OFFSET)?;
+    let file_ends = rom_data[fat_range.clone()]
+        .chunks_exact(8)
+        .map(|entry| u32_at(entry, 4) as usize);
+    let section_ends = Section::ALL
+        .into_iter()
+        .filter_map(|section| section.range(rom_data))
+        .map(|range| range.end);
+    let mut ends = vec![
+        fnt_range.end,
+        fat_range.end,
+        u32_at(rom_data, USED_ROM_SIZE_OFFSET) as usize,
+    ];
+    if UnitCode::of(rom_data).is_dsi() {
+        ends.push(u32_at(rom_data, DSI_USED_ROM_SIZE_OFFSET) as usize);
+    }
+    Ok(ends
+        .into_iter()
+        .chain(file_ends)
+        .chain(section_ends)
+        .max()
+        .unwrap_or_default())
+}
+
+/// Grows the used ROM size and device capacity of the header to cover data up to `end`.
+fn cover_used_size(rom_data: &mut [u8], end: usize) {
+    if end > u32_at(rom_data, USED_ROM_SIZE_OFFSET) as usize {
+        set_u32_at(rom_data, USED_ROM_SIZE_OFFSET, end as u32);
+    }
+    // Data past the DSi area counts towards the DSi used size too.
+    if UnitCode::of(rom_data).is_dsi() && end > u32_at(rom_data, DSI_USED_ROM_SIZE_OFFSET) as usize
+    {
+        set_u32_at(rom_data, DSI_USED_ROM_SIZE_OFFSET, end as u32);
+    }
+    rom_data[DEVICE_CAPACITY_OFFSET] =
+        rom_data[DEVICE_CAPACITY_OFFSET].max(device_capacity_for(rom_data.len()));
+}
+
+/// Appends data to the ROM after everything it references, aligned to [`FILE_ALIGNMENT`], and
+/// returns the offset it was placed at. The header CRC is left for the caller to fix.
+pub fn append_data(rom_data: &mut Vec<u8>, data: &[u8]) -> Result<usize, RomParseError> {
+    let start = align_up(data_end(rom_data)?, FILE_ALIGNMENT);
+    let end = start + data.len();
+    if rom_data.len() < end {
+        rom_data.resize(end, 0xFF);
+    }
+    rom_data[start..end].copy_from_slice(data);
+    cover_used_size(rom_data, end);
+    Ok(start)
+}
+
+/// Appends data to the ARM9 binary, moving the footer following it along, and updates the
+/// header fields that depend on its size. Fails if data located past the binary leaves no room
+/// for it.
+pub fn extend_arm9(rom_data: &mut Vec<u8>, data: &[u8]) -> anyhow::Result<()> {
+    let arm9 = Section::Arm9
+        .range(rom_data)
+        .ok_or_else(|| anyhow::anyhow!("the ARM9 binary lies outside of the ROM"))?;
+    let footer_size = if rom_data
+        .get(arm9.end..arm9.end + ARM9_FOOTER_SIZE)
+        .is_some_and(|footer| u32_at(footer, 0) == NITROCODE)
+    {
+        ARM9_FOOTER_SIZE
+    } else {
+        0
+    };
+    let old_end = arm9.end + footer_size;
+    let new_end = old_end + data.len();
+    let limit = next_data_start(rom_data, arm9.end);
+    if limit < rom_data.len() && new_end > limit {
+        anyhow::bail!(
+            "no room in the ROM past the ARM9 binary: 0x{:X} bytes needed, 0x{:X} free",
+            data.len(),
+            limit.saturating_sub(old_end)
+        );
+    }
+    if rom_data.len() < new_end {
+        rom_data.resize(new_end, 0xFF);
+    }
+    rom_data.copy_within(arm9.end..old_end, arm9.end + data.len());
+    rom_data[arm9.end..arm9.end + data.len()].copy_from_slice(data);
+    set_u32_at(rom_data, ARM9_SIZE_OFFSET, (arm9.len() + data.len()) as u32);
+    cover_used_size(rom_data, new_end);
+    fix_header_crc(rom_data);
+    Ok(())
+}
+
+/// Where a replaced file ended up in the ROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// The new data fit in the file's original allocation.
+    InPlace,
+    /// The new data did not fit, so it was moved to the end of the ROM.
+    Relocated { start: u32 },
+}
+
+/// Replaces the data of the file with the given ID, updating its FAT entry and the header
+/// fields that depend on the ROM's layout.
+///
+/// Files that grew past their original allocation are relocated to the end of the ROM. The
+/// space freed in place is filled with `0xFF`.
+pub fn replace_file(
+    rom_data: &mut Vec<u8>,
+    file_id: u16,
+    new_data: &[u8],
+) -> anyhow::Result<Placement> {
+    let fat_range = table_range(rom_data, "FAT", FAT_ADDR_OFFSET, FAT_SIZE_OFFSET)?;
+    let entry_offset = fat_range.start + file_id as usize * 8;
+    if entry_offset + 8 > fat_range.end {
+        anyhow::bail!("file ID {file_id} is not in the FAT");
+    }
+
+    let start = u32_at(rom_data, entry_offset) as usize;
+    let end = u32_at(rom_data, entry_offset + 4) as usize;
+    if start > end || end > rom_data.len() {
+        return Err(RomParseError::FileOutOfBounds {
+            id: file_id,
+            start,
+            end,
+            rom_size: rom_data.len(),
+        }
+        .into());
+    }
+
+    rom_data[start..end].fill(0xFF);
+    let (placement, new_start) = if new_data.len() <= end - start {
+        rom_data[start..start + new_data.len()].copy_from_slice(new_data);
+        cover_used_size(rom_data, start + new_data.len());
+        (Placement::InPlace, start)
+    } else {
+        let new_start = append_data(rom_data, new_data)?;
+        let placement = Placement::Relocated {
+            start: new_start as u32,
+        };
+        (placement, new_start)
+    };
+    set_u32_at(rom_data, entry_offset, new_start as u32);
+    set_u32_at(
+        rom_data,
+        entry_offset + 4,
+        (new_start + new_data.len()) as u32,
+    );
+    fix_header_crc(rom_data);
+
+    Ok(placement)
+}
+
+/// What a location of the ROM belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Region {
+    Section(Section),
+    Fnt,
+    Fat,
+    Overlay {
+        file_id: u16,
+    },
+    File {
+        file_id: u16,
+        path: String,
+    },
+    /// A file of the FAT that neither the FNT nor an overlay table references.
+    Orphan {
+        file_id: u16,
+    },
+    /// Padding, or data not referenced from the header or FAT.
+    Unused,
+}
+
+impl std::fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Region::Section(section) => f.write_str(section.name()),
+            Region::Fnt => f.write_str("FNT"),
+            Region::Fat => f.write_str("FAT"),
+            Region::Overlay { file_id } => write!(f, "overlay (file ID {file_id})"),
+            Region::File { file_id, path } => write!(f, "file {path:?} (file ID {file_id})"),
+            Region::Orphan { file_id } => write!(f, "file without a path (file ID {file_id})"),
+            Region::Unused => f.write_str("unused space"),
+        }
+    }
+}
+
+/// Finds out which region of the ROM the offset given belongs to.
+pub fn region_at(rom_data: &[u8], fs: Option<&nitro_fs::FileSystem>, offset: usize) -> Region {
+    let field_range = |addr_field: usize, size_field: usize| {
+        let start = u32_at(rom_data, addr_field) as usize;
+        start..start + u32_at(rom_data, size_field) as usize
+    };
+    if let Some(section) = Section::ALL.into_iter().find(|section| {
+        section
+            .range(rom_data)
+            .is_some_and(|range| range.contains(&offset))
+    }) {
+        return Region::Section(section);
+    }
+    if field_range(FNT_ADDR_OFFSET, FNT_SIZE_OFFSET).contains(&offset) {
+        return Region::Fnt;
+    }
+    if field_range(FAT_ADDR_OFFSET, FAT_SIZE_OFFSET).contains(&offset) {
+        return Region::Fat;
+    }
+
+    let contains = |entry: &nitro_fs::fnt::FileEntry| {
+        (entry.alloc.start as usize..entry.alloc.end as usize).contains(&offset)
+    };
+    let Some(fs) = fs else {
+        return Region::Unused;
+    };
+    if let Some(overlay) = fs.overlays().iter().find(|overlay| contains(overlay)) {
+        return Region::Overlay {
+            file_id: overlay.id,
+        };
+    }
+    if let Some(file) = fs.files().into_iter().find(|file| contains(file)) {
+        return Region::File {
+            file_id: file.id,
+            path: nitro_path(&file.path),
+        };
+    }
+    if let Some((file_id, _)) = orphan_files(rom_data, fs)
+        .into_iter()
+        .find(|(_, range)| range.contains(&offset))
+    {
+        return Region::Orphan { file_id };
+    }
+    Region::Unused
+}
+
+/// Magic number starting the footer that follows the ARM9 binary of retail ROMs.
+const NITROCODE: u32 = 0xDEC00621;
+/// Size of the footer following the ARM9 binary, which the ARM9 size in the header leaves out.
+pub const ARM9_FOOTER_SIZE: usize = 12;
+
+/// Data of a ROM that nothing in its header or FAT locates, but that is kept when packing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtraData {
+    /// The footer following the ARM9 binary, starting with the nitrocode.
+    Arm9Footer,
+    /// The RSA signature following the used area of retail ROMs.
+    RsaSignature,
+    /// Any other data lying between the regions of the ROM, such as wifi initialization
+    /// tables.
+    Unreferenced,
+}
+
+/// Ranges of the ROM its header and FAT locate: its sections, FNT, FAT and files, cut to the
+/// end of the ROM.
+fn referenced_ranges(rom_data: &[u8]) -> Vec<Range<usize>> {
+    let mut covered = Section::ALL
+        .into_iter()
+        .filter_map(|section| section.range(rom_data))
+        .collect::<Vec<_>>();
+    if has_filesystem(rom_data) {
+        let tables = [
+            table_range(rom_data, "FNT", FNT_ADDR_OFFSET, FNT_SIZE_OFFSET),
+            table_range(rom_data, "FAT", FAT_ADDR_OFFSET, FAT_SIZE_OFFSET),
+        ];
+        for table in tables.into_iter().flatten() {
+            covered.push(table);
+        }
+        if let Ok(fat) = table_range(rom_data, "FAT", FAT_ADDR_OFFSET, FAT_SIZE_OFFSET) {
+            covered.extend(
+                rom_data[fat]
+                    .chunks_exact(8)
+                    .map(|entry| u32_at(entry, 0) as usize..u32_at(entry, 4) as usize),
+            );
+        }
+    }
+    // FAT entries may point past the end of the ROM, or be reversed.
+    covered
+        .into_iter()
+        .map(|range| range.start.min(rom_data.len())..range.end.min(rom_data.len()))
+        .filter(|range| range.start < range.end)
+        .collect()
+}
+
+/// Finds where the first data the header or FAT locate past `offset` starts, or the end of the
+/// ROM if there is none, so that data at `offset` can grow up to there.
+pub fn next_data_start(rom_data: &[u8], offset: usize) -> usize {
+    referenced_ranges(rom_data)
+        .into_iter()
+        .filter(|range| range.start >= offset && range.start < range.end)
+        .map(|range| range.start)
+        .min()
+        .unwrap_or(rom_data.len())
+}
+
+/// Finds the data of a ROM lying outside of its sections, FNT, FAT and files, leaving out
+/// padding made of `pad_byte`, `0x00` or `0xFF` bytes.
+pub fn extra_data(rom_data: &[u8], pad_byte: u8) -> Vec<(ExtraData, Range<usize>)> {
+    let mut covered = referenced_ranges(rom_data);
+
+    let mut extra = Vec::new();
+    if let Some(arm9) = Section::Arm9.range(rom_data) {
+        let footer = arm9.end..arm9.end + ARM9_FOOTER_SIZE;
+        if rom_data
+            .get(footer.clone())
+            .is_some_and(|footer| u32_at(footer, 0) == NITROCODE)
+        {
+            covered.push(footer.clone());
+            extra.push((ExtraData::Arm9Footer, footer));
+        }
+    }
+    let used_size = u32_at(rom_data, USED_ROM_SIZE_OFFSET) as usize;
+    let signature = used_size..used_size + RSA_SIGNATURE_SIZE;
+    let overlaps = |range: &Range<usize>, covered: &[Range<usize>]| {
+        covered
+            .iter()
+            .any(|other| other.start < range.end && range.start < other.end)
+    };
+    if signature.end <= rom_data.len()
+        && !overlaps(&signature, &covered)
+        && rom_data[signature.clone()]
+            .iter()
+            .any(|&byte| byte != 0x00 && byte != 0xFF)
+    {
+        covered.push(signature.clone());
+        extra.push((ExtraData::RsaSignature, signature));
+    }
+
+    covered.sort_by_key(|range| range.start);
+    let mut gaps = Vec::new();
+    let mut covered_end = 0;
+    for range in covered {
+        if range.start > covered_end {
+            gaps.push(covered_end..range.start);
+        }
+        covered_end = covered_end.max(range.end);
+    }
+    if covered_end < rom_data.len() {
+        gaps.push(covered_end..rom_data.len());
+    }
+    for gap in gaps {
+        let data = &rom_data[gap.clone()];
+        let Some(first) = data.iter().position(|&byte| byte != pad_byte) else {
+            continue;
+        };
+        let last = data.iter().rposition(|&byte| byte != pad_byte).unwrap();
+        let data = &data[first..=last];
+        if data
+            .iter()
+            .all(|&byte| byte == data[0] && (byte == 0x00 || byte == 0xFF))
+        {
+            continue;
+        }
+        extra.push((
+            ExtraData::Unreferenced,
+            gap.start + first..gap.start + last + 1,
+        ));
+    }
+    extra.sort_by_key(|(_, range)| range.start);
+    extra
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FNT_START: usize = 0x200;
+    const FAT_START: usize = 0x210;
+    const FILE_RANGE: Range<usize> = 0x220..0x230;
+
+    /// FNT of a ROM holding a single file named `a` in its root directory.
+    fn sample_fnt() -> Vec<u8> {
+        let mut fnt = vec![0; 8];
+        fnt[0] = 8;
+        fnt[6] = 1;
+        fnt.extend_from_slice(&[0x01, b'a', 0x00]);
+        fnt
+    }
+
+    fn sample_fat() -> Vec<u8> {
+        let mut fat = vec![0; 8];
+        set_u32_at(&mut fat, 0, FILE_RANGE.start as u32);
+        set_u32_at(&mut fat, 4, FILE_RANGE.end as u32);
+        fat
+    }
+
+    /// A ROM with no code whose NitroFS holds the file of [`sample_fnt`].
+    fn sample_rom() -> Vec<u8> {
+        let mut rom = homebrew_header("TEST");
+        rom.resize(FILE_RANGE.end + 0x10, 0);
+        let (fnt, fat) = (sample_fnt(), sample_fat());
+        rom[FNT_START..FNT_START + fnt.len()].copy_from_slice(&fnt);
+        rom[FAT_START..FAT_START + fat.len()].copy_from_slice(&fat);
+        rom[FILE_RANGE].fill(0x11);
+        set_u32_at(&mut rom, FNT_ADDR_OFFSET, FNT_START as u32);
+        set_u32_at(&mut rom, FNT_SIZE_OFFSET, fnt.len() as u32);
+        set_u32_at(&mut rom, FAT_ADDR_OFFSET, FAT_START as u32);
+        set_u32_at(&mut rom, FAT_SIZE_OFFSET, fat.len() as u32);
+        rom
+    }
+
+    #[test]
+    fn sample_rom_parses() {
+        let rom = sample_rom();
+        let fs = filesystem(&rom).unwrap();
+        let files = fs.files();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, PathBuf::from("a"));
+        assert_eq!(checked_file_data(&rom, files[0]).unwrap(), &rom[FILE_RANGE]);
+        assert!(extra_data(&rom, PAD_BYTE).is_empty());
+    }
+
+    #[test]
+    fn truncated_header_is_rejected() {
+        let rom = sample_rom();
+        assert!(check_header(&rom[..HEADER_SIZE - 1]).is_err());
+        assert!(check_header(&[]).is_err());
+    }
+
+    #[test]
+    fn tables_outside_of_the_rom_are_rejected() {
+        for (addr_field, size_field) in [
+            (FNT_ADDR_OFFSET, FNT_SIZE_OFFSET),
+            (FAT_ADDR_OFFSET, FAT_SIZE_OFFSET),
+        ] {
+            let mut rom = sample_rom();
+            set_u32_at(&mut rom, size_field, 0x1000);
+            assert!(filesystem(&rom).is_err());
+            let mut rom = sample_rom();
+            set_u32_at(&mut rom, addr_field, u32::MAX);
+            assert!(filesystem(&rom).is_err());
+        }
+    }
+
+    #[test]
+    fn truncated_fnt_is_rejected() {
+        let (fnt, fat) = (sample_fnt(), sample_fat());
+        for len in 0..fnt.len() {
+            assert!(parse_filesystem(&fnt[..len], &fat).is_err(), "length {len}");
+        }
+    }
+
+    #[test]
+    fn corrupted_fnt_is_rejected() {
+        let fat = sample_fat();
+        let corrupt = |offset: usize, value: u8| {
+            let mut fnt = sample_fnt();
+            fnt[offset] = value;
+            parse_filesystem(&fnt, &fat)
+        };
+        // No directories.
+        assert!(corrupt(6, 0).is_err());
+        // More directories than the FNT holds.
+        assert!(corrupt(6, 2).is_err());
+        // Subtable past the end.
+        assert!(corrupt(0, 0x40).is_err());
+        // First file ID past the end of the FAT.
+        assert!(corrupt(4, 1).is_err());
+        // Directory with an empty name, which the NitroFS parser reads as a file.
+        assert!(corrupt(8, 0x80).is_err());
+        // Directory referencing a missing directory.
+        let mut fnt = sample_fnt();
+        fnt.splice(8..8, [0x81, b'd', 0x01, 0xF0]);
+        assert!(parse_filesystem(&fnt, &fat).is_err());
+    }
+
+    #[test]
+    fn invalid_fat_is_rejected() {
+        let (fnt, fat) = (sample_fnt(), sample_fat());
+        assert!(parse_filesystem(&fnt, &fat[..7]).is_err());
+        assert!(parse_filesystem(&fnt, &[]).is_err());
+    }
+
+    #[test]
+    fn files_cannot_be_placed_inside_files() {
+        use crate::fs_edit::{edit_filesystem, FsEdit, FsEditError};
+
+        let mut rom = sample_rom();
+        let add = |path: &str| FsEdit::Add {
+            path: path.to_owned(),
+            data: vec![0x22; 0x10],
+        };
+        assert!(matches!(
+            edit_filesystem(&mut rom, [add("a/x")]),
+            Err(FsEditError::NotADirectory { .. })
+        ));
+        assert!(matches!(
+            edit_filesystem(&mut rom, [add("a/x/y")]),
+            Err(FsEditError::NotADirectory { .. })
+        ));
+        let moved = FsEdit::Move {
+            from: "b".to_owned(),
+            to: "a/b".to_owned(),
+        };
+        assert!(matches!(
+            edit_filesystem(&mut rom, [add("b"), moved]),
+            Err(FsEditError::NotADirectory { .. })
+        ));
+        assert_eq!(rom, sample_rom());
+
+        edit_filesystem(&mut rom, [add("b/x")]).unwrap();
+        let fs = filesystem(&rom).unwrap();
+        let mut paths = fs
+            .files()
+            .iter()
+            .map(|file| file.path.clone())
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(paths, [PathBuf::from("a"), PathBuf::from("b/x")]);
+    }
+
+    #[test]
+    fn files_outside_of_the_rom_are_rejected() {
+        for (start, end) in [
+            (FILE_RANGE.start, 0x1000),
+            (FILE_RANGE.end, FILE_RANGE.start),
+            (0x1000, 0x2000),
+        ] {
+            let mut rom = sample_rom();
+            set_u32_at(&mut rom, FAT_START, start as u32);
+            set_u32_at(&mut rom, FAT_START + 4, end as u32);
+            let fs = filesystem(&rom).unwrap();
+            assert!(checked_file_data(&rom, fs.files()[0]).is_err());
+            // Extra data is found among the parts of the entry inside the ROM.
+            let extra = extra_data(&rom, PAD_BYTE);
+            assert!(extra
+                .iter()
+                .all(|(_, range)| range.start < range.end && range.end <= rom.len()));
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use crate::{
+    lz10::decompress_lz10,
+    rom::{self, Section},
+    text::{self, TextEncoding},
+};
+
+/// How a piece of data differs between two ROMs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added {
+        size: usize,
+    },
+    Removed {
+        size: usize,
+    },
+    Changed {
+        old_size: usize,
+        new_size: usize,
+        /// Bytes that differ within the common size, plus the size difference.
+        differing_bytes: usize,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Change::Added { size } => write!(f, "added (0x{size:X} bytes)"),
+            Change::Removed { size } => write!(f, "removed (0x{size:X} bytes)"),
+            Change::Changed {
+                old_size,
+                new_size,
+                differing_bytes,
+            } => {
+                if old_size == new_size {
+                    write!(f, "changed (0x{old_size:X} bytes")?;
+                } else {
+                    write!(f, "changed (0x{old_size:X} -> 0x{new_size:X} bytes")?;
+                }
+                write!(f, ", 0x{differing_bytes:X} bytes differ)")
+            }
+        }
+    }
+}
+
+/// How a string of a text file differs between two ROMs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StringChange {
+    Added {
+        index: usize,
+        text: String,
+    },
+    Removed {
+        index: usize,
+        text: String,
+    },
+    Changed {
+        index: usize,
+        old: String,
+        new: String,
+    },
+}
+
+impl fmt::Display for StringChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StringChange::Added { index, text } => write!(f, "string {index} added: {text:?}"),
+            StringChange::Removed { index, text } => {
+                write!(f, "string {index} removed: {text:?}")
+            }
+            StringChange::Changed { index, old, new } => {
+                write!(f, "string {index}: {old:?} -> {new:?}")
+            }
+        }
+    }
+}
+
+/// A section, overlay or file that differs between two ROMs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemDiff {
+    /// Section name, overlay name or NitroFS path.
+    pub name: String,
+    pub change: Change,
+    /// For text files present in both ROMs, the strings that differ.
+    pub strings: Vec<StringChange>,
+}
+
+/// Splits a ROM into named items: its sections, its overlays and its NitroFS files, in that
+/// order.
+fn items(rom_data: &[u8]) -> anyhow::Result<Vec<(String, &[u8])>> {
+    let mut items = Vec::new();
+    for section in Section::ALL {
+        if let Some(range) = section.range(rom_data) {
+            items.push((section.name().to_owned(), &rom_data[range]));
+        }
+    }
+
+    let fs = rom::filesystem(rom_data)?;
+    for (processor, table_section) in [
+        ("ARM9", Section::Arm9OverlayTable),
+        ("ARM7", Section::Arm7OverlayTable),
+    ] {
+        let Some(table_range) = table_section.range(rom_data) else {
+            continue;
+        };
+        for (index, file_id) in rom::overlay_file_ids(&rom_data[table_range])
+            .into_iter()
+            .enumerate()
+        {
+            if let Some(overlay) = fs.overlays().iter().find(|overlay| overlay.id == file_id) {
+                items.push((
+                    format!("{processor} overlay {index}"),
+                    rom::file_data(rom_data, overlay),
+                ));
+            }
+        }
+    }
+
+    let mut files = fs
+        .files()
+        .into_iter()
+        .map(|entry| {
+            (
+                rom::nitro_path(&entry.path),
+                rom::file_data(rom_data, entry),
+            )
+        })
+        .collect::<Vec<_>>();
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+    items.extend(files);
+    Ok(items)
+}
+
+/// Parses the strings of a text file, optionally LZ10-compressed.
+fn text_strings(data: &[u8], encoding: &TextEncoding) -> Option<Vec<String>> {
+    let decompressed_data = decompress_lz10(data).ok();
+    text::parse_text_file(decompressed_data.as_deref().unwrap_or(data), encoding)
+        .ok()
+        .filter(|strings| !strings.is_empty())
+}
+
+fn diff_strings(old: &[String], new: &[String]) -> Vec<StringChange> {
+    let mut changes = Vec::new();
+    for index in 0..old.len().max(new.len()) {
+        match (old.get(index), new.get(index)) {
+            (Some(old), Some(new)) if old != new => changes.push(StringChange::Changed {
+                index,
+                old: old.clone(),
+                new: new.clone(),
+            }),
+            (Some(text), None) => changes.push(StringChange::Removed {
+                index,
+                text: text.clone(),
+            }),
+            (None, Some(text)) => changes.push(StringChange::Added {
+                index,
+                text: text.clone(),
+            }),
+            _ => {}
+        }
+    }
+    changes
+}
+
+/// Compares two ROMs at the filesystem level, returning the items that differ: the ones removed
+/// or changed in the order of the old ROM, then the ones added. Text files are decoded with
+/// `encoding` to compare their strings.
+pub fn diff_roms(
+    old_rom: &[u8],
+    new_rom: &[u8],
+    encoding: &TextEncoding,
+) -> anyhow::Result<Vec<ItemDiff>> {
+    let old_items = items(old_rom)?;
+    let new_items = items(new_rom)?;
+    let mut new_by_name = new_items.iter().cloned().collect::<BTreeMap<_, _>>();
+
+    let mut diffs = Vec::new();
+    for (name, old_data) in old_items {
+        let Some(new_data) = new_by_name.remove(&name) else {
+            diffs.push(ItemDiff {
+                name,
+                change: Change::Removed {
+                    size: old_data.len(),
+                },
+                strings: Vec::new(),
+            });
+            continue;
+        };
+        if old_data == new_data {
+            continue;
+        }
+
+        let differing_bytes = old_data
+            .iter()
+            .zip(new_data)
+            .filter(|(old, new)| old != new)
+            .count()
+            + old_data.len().abs_diff(new_data.len());
+        let strings = match (
+            text_strings(old_data, encoding),
+            text_strings(new_data, encoding),
+        ) {
+            (Some(old_strings), Some(new_strings)) => diff_strings(&old_strings, &new_strings),
+            _ => Vec::new(),
+        };
+        diffs.push(ItemDiff {
+            name,
+            change: Change::Changed {
+                old_size: old_data.len(),
+                new_size: new_data.len(),
+                differing_bytes,
+            },
+            strings,
+        });
+    }
+    diffs.extend(
+        new_items
+            .into_iter()
+            .filter(|(name, _)| new_by_name.contains_key(name))
+            .map(|(name, new_data)| ItemDiff {
+                name,
+                change: Change::Added {
+                    size: new_data.len(),
+                },
+                strings: Vec::new(),
+            }),
+    );
+    Ok(diffs)
+}
+
+/// A file or overlay whose data is placed elsewhere in the new ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    /// NitroFS path, or overlay name.
+    pub name: String,
+    pub old_start: u32,
+    pub new_start: u32,
+    pub old_size: u32,
+    pub new_size: u32,
+}
+
+impl Relocation {
+    /// Whether the data grew past its original allocation, which is why files are moved when
+    /// replaced in place.
+    pub fn outgrew_allocation(&self) -> bool {
+        self.new_size > self.old_size
+    }
+}
+
+impl fmt::Display for Relocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "moved from 0x{:08X} to 0x{:08X}",
+            self.old_start, self.new_start
+        )?;
+        if self.outgrew_allocation() {
+            write!(
+                f,
+                " (grew past its allocation: 0x{:X} -> 0x{:X} bytes)",
+                self.old_size, self.new_size
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Lists the files and overlays kept in both ROMs whose data starts somewhere else in the new
+/// one, by file ID, in the order of the new ROM's file IDs.
+pub fn relocations(old_rom: &[u8], new_rom: &[u8]) -> anyhow::Result<Vec<Relocation>> {
+    // Start, end and name of every file and overlay, by file ID.
+    let allocations = |rom_data: &[u8]| -> anyhow::Result<BTreeMap<u16, (u32, u32, String)>> {
+        let fs = rom::filesystem(rom_data)?;
+        let overlays = fs.overlays().iter().map(|overlay| {
+            (
+                overlay.id,
+                (
+                    overlay.alloc.start,
+                    overlay.alloc.end,
+                    format!("overlay (file ID {})", overlay.id),
+                ),
+            )
+        });
+        let files = fs.files().into_iter().map(|entry| {
+            (
+                entry.id,
+                (
+                    entry.alloc.start,
+                    entry.alloc.end,
+                    rom::nitro_path(&entry.path),
+                ),
+            )
+        });
+        Ok(overlays.chain(files).collect())
+    };
+    let old_allocations = allocations(old_rom)?;
+    Ok(allocations(new_rom)?
+        .into_iter()
+        .filter_map(|(file_id, (new_start, new_end, name))| {
+            let &(old_start, old_end, _) = old_allocations.get(&file_id)?;
+            (old_start != new_start).then(|| Relocation {
+                name,
+                old_start,
+                new_start,
+                old_size: old_end.saturating_sub(old_start),
+                new_size: new_end.saturating_sub(new_start),
+            })
+        })
+        .collect())
+}
+
+/// A section, overlay or file as found across several variants of a game, such as its
+/// releases in different regions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantItem {
+    /// Section name, overlay name or NitroFS path.
+    pub name: String,
+    /// Size of the item in each variant, or `None` in the variants lacking it.
+    pub sizes: Vec<Option<usize>>,
+    /// Whether the item has the same contents in every variant having it.
+    pub identical: bool,
+    /// For text files, the number of strings in each variant, or `None` in the variants lacking
+    /// it or where it isn't a text file.
+    pub string_counts: Option<Vec<Option<usize>>>,
+}
+
+impl VariantItem {
+    /// Whether every variant has the item.
+    pub fn is_shared(&self) -> bool {
+        self.sizes.iter().all(Option::is_some)
+    }
+
+    /// Whether the item is a text file whose string count isn't the same in every variant
+    /// having it.
+    pub fn string_count_mismatch(&self) -> bool {
+        self.string_counts.as_ref().is_some_and(|counts| {
+            let mut counts = counts.iter().flatten();
+            counts
+                .next()
+                .is_some_and(|first| counts.any(|count| count != first))
+        })
+    }
+}
+
+/// Aligns the sections, overlays and NitroFS files of several variants of a game by name,
+/// returning every item found in any of them, in the order they were first found in. Text
+/// files are decoded with `encoding` to count their strings.
+pub fn compare_variants(
+    roms: &[&[u8]],
+    encoding: &TextEncoding,
+) -> anyhow::Result<Vec<VariantItem>> {
+    let mut names = Vec::new();
+    let mut seen = BTreeSet::new();
+    let mut rom_items = Vec::new();
+    for rom_data in roms {
+        let items = items(rom_data)?;
+        for (name, _) in &items {
+            if seen.insert(name.clone()) {
+                names.push(name.clone());
+            }
+        }
+        rom_items.push(items.into_iter().collect::<BTreeMap<_, _>>());
+    }
+
+    Ok(names
+        .into_iter()
+        .map(|name| {
+            let data = rom_items
+                .iter()
+                .map(|items| items.get(&name).copied())
+                .collect::<Vec<_>>();
+            let mut present = data.iter().flatten();
+            let first = present.next();
+            let identical = present.all(|other| Some(other) == first);
+            let string_counts = data
+                .iter()
+                .map(|data| data.and_then(|data| text_strings(data, encoding)))
+                .map(|strings| strings.map(|strings| strings.len()))
+                .collect::<Vec<_>>();
+            VariantItem {
+                sizes: data.iter().map(|data| data.map(<[u8]>::len)).collect(),
+                identical,
+                string_counts: string_counts
+                    .iter()
+                    .any(Option::is_some)
+                    .then_some(string_counts),
+                name,
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,302 @@
+use std::{fmt, fmt::Write, ops::Range};
+
+use crate::rom::{self, ExtraData, Region, RomParseError, Section};
+
+/// What a span of the ROM holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpanKind {
+    /// A section, table or file located through the header or FAT.
+    Region(Region),
+    /// Data outside of everything the header and FAT locate, which packing keeps.
+    Extra(ExtraData),
+    /// Space holding nothing but padding bytes.
+    Padding,
+}
+
+impl SpanKind {
+    /// Character standing for this kind of span in the overview bar.
+    fn symbol(&self) -> char {
+        match self {
+            SpanKind::Region(Region::Section(Section::Header)) => 'H',
+            SpanKind::Region(Region::Section(
+                Section::Arm9 | Section::Arm7 | Section::Arm9i | Section::Arm7i,
+            )) => 'C',
+            SpanKind::Region(Region::Section(Section::Banner)) => 'B',
+            SpanKind::Region(
+                Region::Section(Section::Arm9OverlayTable | Section::Arm7OverlayTable)
+                | Region::Overlay { .. },
+            ) => 'O',
+            SpanKind::Region(
+                Region::Section(Section::DigestSectorHashtable | Section::DigestBlockHashtable)
+                | Region::Fnt
+                | Region::Fat,
+            ) => 'T',
+            SpanKind::Region(Region::File { .. } | Region::Orphan { .. }) => 'F',
+            SpanKind::Region(Region::Unused) | SpanKind::Extra(_) => 'E',
+            SpanKind::Padding => '.',
+        }
+    }
+}
+
+impl fmt::Display for SpanKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpanKind::Region(region) => write!(f, "{region}"),
+            SpanKind::Extra(ExtraData::Arm9Footer) => f.write_str("ARM9 footer"),
+            SpanKind::Extra(ExtraData::RsaSignature) => f.write_str("RSA signature"),
+            SpanKind::Extra(ExtraData::Unreferenced) => f.write_str("unreferenced data"),
+            SpanKind::Padding => f.write_str("padding"),
+        }
+    }
+}
+
+/// A range of the ROM and what it holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub range: Range<usize>,
+    pub kind: SpanKind,
+}
+
+/// Two spans sharing bytes of the ROM, such as FAT entries pointing into each other, which
+/// some anti-piracy schemes rely on, or files stored once for several FAT entries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overlap {
+    /// Indices of the spans in [`RomMap::spans`].
+    pub spans: (usize, usize),
+    pub range: Range<usize>,
+}
+
+/// How the address space of a ROM is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomMap {
+    pub rom_size: usize,
+    /// Every span of the ROM, including padding, sorted by offset. Spans may overlap.
+    pub spans: Vec<Span>,
+    pub overlaps: Vec<Overlap>,
+    /// Regions located past the end of the ROM, which can't be read.
+    pub past_end: Vec<Span>,
+}
+
+/// Characters of the overview bar of [`RomMap::render`].
+const OVERVIEW_WIDTH: usize = 64;
+/// Characters of the bar drawn for each span by [`RomMap::render`].
+const SPAN_BAR_WIDTH: usize = 32;
+
+impl RomMap {
+    /// Maps the sections, tables, overlays and files of a ROM, along with the data and padding
+    /// between them.
+    pub fn from_rom(rom_data: &[u8]) -> Result<Self, RomParseError> {
+        let fs = rom::filesystem(rom_data)?;
+        let mut regions = Section::ALL
+            .into_iter()
+            .filter_map(|section| Some((section.range(rom_data)?, Region::Section(section))))
+            .collect::<Vec<_>>();
+        if rom::has_filesystem(rom_data) {
+            let tables = [
+                (
+                    "FNT",
+                    rom::FNT_ADDR_OFFSET,
+                    rom::FNT_SIZE_OFFSET,
+                    Region::Fnt,
+                ),
+                (
+                    "FAT",
+                    rom::FAT_ADDR_OFFSET,
+                    rom::FAT_SIZE_OFFSET,
+                    Region::Fat,
+                ),
+            ];
+            for (name, addr_field, size_field, region) in tables {
+                regions.push((
+                    rom::table_range(rom_data, name, addr_field, size_field)?,
+                    region,
+                ));
+            }
+        }
+        let entry_range =
+            |entry: &nitro_fs::fnt::FileEntry| entry.alloc.start as usize..entry.alloc.end as usize;
+        for overlay in fs.overlays() {
+            regions.push((
+                entry_range(overlay),
+                Region::Overlay {
+                    file_id: overlay.id,
+                },
+            ));
+        }
+        for file in fs.files() {
+            regions.push((
+                entry_range(file),
+                Region::File {
+                    file_id: file.id,
+                    path: rom::nitro_path(&file.path),
+                },
+            ));
+        }
+        for (file_id, range) in rom::orphan_files(rom_data, &fs) {
+            // Overlays the overlay tables leave out are listed as overlays already.
+            if !fs.overlays().iter().any(|overlay| overlay.id == file_id) {
+                regions.push((range, Region::Orphan { file_id }));
+            }
+        }
+
+        let mut spans = Vec::new();
+        let mut past_end = Vec::new();
+        for (range, region) in regions {
+            let span = Span {
+                range: range.clone(),
+                kind: SpanKind::Region(region),
+            };
+            if range.end > rom_data.len() || range.start > range.end {
+                past_end.push(span);
+            } else if !range.is_empty() {
+                spans.push(span);
+            }
+        }
+        let pad_byte = rom::detect_pad_byte(rom_data, &fs);
+        for (kind, range) in rom::extra_data(rom_data, pad_byte) {
+            if !spans.iter().any(|span| span.range == range) {
+                spans.push(Span {
+                    range,
+                    kind: SpanKind::Extra(kind),
+                });
+            }
+        }
+        spans.sort_by_key(|span| (span.range.start, span.range.end));
+
+        let mut padding = Vec::new();
+        let mut covered_end = 0;
+        for span in &spans {
+            if span.range.start > covered_end {
+                padding.push(covered_end..span.range.start);
+            }
+            covered_end = covered_end.max(span.range.end);
+        }
+        if covered_end < rom_data.len() {
+            padding.push(covered_end..rom_data.len());
+        }
+        spans.extend(padding.into_iter().map(|range| Span {
+            range,
+            kind: SpanKind::Padding,
+        }));
+        spans.sort_by_key(|span| (span.range.start, span.range.end));
+
+        let mut overlaps = Vec::new();
+        for (index, span) in spans.iter().enumerate() {
+            for (other_index, other) in spans.iter().enumerate().skip(index + 1) {
+                if other.range.start >= span.range.end {
+                    break;
+                }
+                overlaps.push(Overlap {
+                    spans: (index, other_index),
+                    range: other.range.start..span.range.end.min(other.range.end),
+                });
+            }
+        }
+        Ok(Self {
+            rom_size: rom_data.len(),
+            spans,
+            overlaps,
+            past_end,
+        })
+    }
+
+    /// Bytes of padding in the ROM.
+    pub fn padding_size(&self) -> usize {
+        self.spans
+            .iter()
+            .filter(|span| span.kind == SpanKind::Padding)
+            .map(|span| span.range.len())
+            .sum()
+    }
+
+    /// Draws `range` as a bar of `width` characters standing for the whole ROM.
+    fn bar(&self, range: &Range<usize>, width: usize) -> String {
+        let cell = |offset: usize| offset * width / self.rom_size.max(1);
+        let start = cell(range.start);
+        let end = cell(range.end.saturating_sub(1)).max(start);
+        (0..width)
+            .map(|index| {
+                if (start..=end).contains(&index) {
+                    '█'
+                } else {
+                    '·'
+                }
+            })
+            .collect()
+    }
+
+    /// Renders the map as text: an overview of the whole ROM, a line for every span with a bar
+    /// locating it, and the overlaps found.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let overview = (0..OVERVIEW_WIDTH)
+            .map(|cell| {
+                let offset = cell * self.rom_size / OVERVIEW_WIDTH;
+                let end = ((cell + 1) * self.rom_size / OVERVIEW_WIDTH).max(offset + 1);
+                let overlapping = self
+                    .overlaps
+                    .iter()
+                    .any(|overlap| overlap.range.start < end && offset < overlap.range.end);
+                if overlapping {
+                    return 'X';
+                }
+                self.spans
+                    .iter()
+                    .filter(|span| span.range.start < end && offset < span.range.end)
+                    .max_by_key(|span| span.range.end.min(end) - span.range.start.max(offset))
+                    .map_or(' ', |span| span.kind.symbol())
+            })
+            .collect::<String>();
+        let _ = writeln!(output, "[{overview}]");
+        let _ = writeln!(
+            output,
+            "H header  C code  O overlays  T tables  B banner  F files  E extra data  . padding  X overlap"
+        );
+        let _ = writeln!(output);
+        for span in &self.spans {
+            let _ = writeln!(
+                output,
+                "0x{:08X}..0x{:08X} {:>10}  {}  {}",
+                span.range.start,
+                span.range.end,
+                format!("0x{:X}", span.range.len()),
+                self.bar(&span.range, SPAN_BAR_WIDTH),
+                span.kind
+            );
+        }
+        let _ = writeln!(output);
+        let _ = writeln!(
+            output,
+            "0x{:X} bytes, 0x{:X} of them padding",
+            self.rom_size,
+            self.padding_size()
+        );
+        for span in &self.past_end {
+            let _ = writeln!(
+                output,
+                "{} lies past the end of the ROM (0x{:08X}..0x{:08X})",
+                span.kind, span.range.start, span.range.end
+            );
+        }
+        if !self.overlaps.is_empty() {
+            let _ = writeln!(output, "{} overlaps:", self.overlaps.len());
+        }
+        for overlap in &self.overlaps {
+            let (first, second) = (&self.spans[overlap.spans.0], &self.spans[overlap.spans.1]);
+            let shared = if first.range == second.range {
+                ", stored once for both"
+            } else {
+                ""
+            };
+            let _ = writeln!(
+                output,
+                "  {} and {} share 0x{:X} bytes at 0x{:08X}{shared}",
+                first.kind,
+                second.kind,
+                overlap.range.len(),
+                overlap.range.start
+            );
+        }
+        output
+    }
+}
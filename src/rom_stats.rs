@@ -0,0 +1,266 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use rayon::prelude::*;
+
+use crate::{
+    manifest::{Codec, Processor},
+    memory, overlay,
+    rom::{self, Region, RomParseError, Section},
+    rom_map::{RomMap, SpanKind},
+};
+
+/// What the space of a ROM is counted as used by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    Header,
+    /// The ARM9 and ARM7 binaries, and their DSi counterparts.
+    Code,
+    /// The overlay tables and the overlays they list.
+    Overlays,
+    /// The FNT, the FAT and the DSi digest tables.
+    Tables,
+    Banner,
+    /// NitroFS files.
+    Files,
+    /// Files of the FAT that neither the FNT nor an overlay table references.
+    Orphans,
+    /// Data outside of everything the header and FAT locate.
+    Extra,
+    Padding,
+}
+
+impl Category {
+    pub const ALL: [Category; 9] = [
+        Category::Header,
+        Category::Code,
+        Category::Overlays,
+        Category::Tables,
+        Category::Banner,
+        Category::Files,
+        Category::Orphans,
+        Category::Extra,
+        Category::Padding,
+    ];
+
+    fn of(kind: &SpanKind) -> Self {
+        match kind {
+            SpanKind::Region(Region::Section(Section::Header)) => Category::Header,
+            SpanKind::Region(Region::Section(
+                Section::Arm9 | Section::Arm7 | Section::Arm9i | Section::Arm7i,
+            )) => Category::Code,
+            SpanKind::Region(
+                Region::Section(Section::Arm9OverlayTable | Section::Arm7OverlayTable)
+                | Region::Overlay { .. },
+            ) => Category::Overlays,
+            SpanKind::Region(
+                Region::Section(Section::DigestSectorHashtable | Section::DigestBlockHashtable)
+                | Region::Fnt
+                | Region::Fat,
+            ) => Category::Tables,
+            SpanKind::Region(Region::Section(Section::Banner)) => Category::Banner,
+            SpanKind::Region(Region::File { .. }) => Category::Files,
+            SpanKind::Region(Region::Orphan { .. }) => Category::Orphans,
+            SpanKind::Region(Region::Unused) | SpanKind::Extra(_) => Category::Extra,
+            SpanKind::Padding => Category::Padding,
+        }
+    }
+
+    /// Human-readable name of this category.
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Header => "header",
+            Category::Code => "code",
+            Category::Overlays => "overlays",
+            Category::Tables => "file tables",
+            Category::Banner => "banner",
+            Category::Files => "files",
+            Category::Orphans => "files without a path",
+            Category::Extra => "extra data",
+            Category::Padding => "padding",
+        }
+    }
+}
+
+/// A binary, overlay or file of the ROM, and the space it takes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredData {
+    pub region: Region,
+    pub size: usize,
+    /// Name of the compression it's stored with, if it's compressed.
+    pub compression: Option<&'static str>,
+}
+
+/// What the space of a ROM is used by. Bytes shared by several regions, as some anti-piracy
+/// schemes make FAT entries do, are counted for each.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomStats {
+    pub rom_size: usize,
+    /// Size of the cartridge the header declares, which the ROM must fit in.
+    pub capacity: usize,
+    /// Bytes used by each category, in the order of [`Category::ALL`].
+    pub categories: Vec<(Category, usize)>,
+    /// Bytes used by the NitroFS files inside each top-level directory, with the files of the
+    /// root directory under an empty name.
+    pub directories: BTreeMap<String, usize>,
+    /// Code binaries, overlays and files of the ROM, largest first.
+    pub stored: Vec<StoredData>,
+}
+
+impl RomStats {
+    /// Counts the space used by each part of a ROM, detecting the compression of its binaries,
+    /// overlays and files.
+    pub fn from_rom(rom_data: &[u8]) -> Result<Self, RomParseError> {
+        let map = RomMap::from_rom(rom_data)?;
+        let mut categories = BTreeMap::new();
+        let mut directories = BTreeMap::new();
+        for span in &map.spans {
+            *categories.entry(Category::of(&span.kind)).or_default() += span.range.len();
+            if let SpanKind::Region(Region::File { path, .. }) = &span.kind {
+                let dir = path.split_once('/').map_or("", |(dir, _)| dir);
+                *directories.entry(dir.to_owned()).or_default() += span.range.len();
+            }
+        }
+
+        let compressed_overlays = [Processor::Arm9, Processor::Arm7]
+            .into_iter()
+            .flat_map(|processor| overlay::overlay_table(rom_data, processor))
+            .filter(|entry| entry.compressed)
+            .map(|entry| entry.file_id)
+            .collect::<Vec<_>>();
+        let mut stored = map
+            .spans
+            .par_iter()
+            .filter_map(|span| {
+                let SpanKind::Region(region) = &span.kind else {
+                    return None;
+                };
+                let data = &rom_data[span.range.clone()];
+                let compression = match region {
+                    Region::Section(Section::Arm9) => memory::compressed_end_field(data)
+                        .filter(|&field| rom::u32_at(data, field) != 0)
+                        .map(|_| "BLZ"),
+                    Region::Section(Section::Arm7 | Section::Arm9i | Section::Arm7i) => None,
+                    Region::Overlay { file_id } => compressed_overlays
+                        .contains(&(*file_id as u32))
+                        .then_some("BLZ"),
+                    Region::File { .. } | Region::Orphan { .. } => {
+                        Codec::detect(data).map(|(codec, _)| codec.compression.name())
+                    }
+                    _ => return None,
+                };
+                Some(StoredData {
+                    region: region.clone(),
+                    size: span.range.len(),
+                    compression,
+                })
+            })
+            .collect::<Vec<_>>();
+        stored.sort_by_key(|data| std::cmp::Reverse(data.size));
+
+        Ok(Self {
+            rom_size: rom_data.len(),
+            capacity: 0x20000usize << rom_data[rom::DEVICE_CAPACITY_OFFSET].min(16),
+            categories: Category::ALL
+                .into_iter()
+                .map(|category| (category, categories.get(&category).copied().unwrap_or(0)))
+                .collect(),
+            directories,
+            stored,
+        })
+    }
+
+    /// Bytes of code, overlays and files stored compressed, and stored as they are.
+    pub fn compression_sizes(&self) -> (usize, usize) {
+        let compressed = self
+            .stored
+            .iter()
+            .filter(|data| data.compression.is_some())
+            .map(|data| data.size)
+            .sum();
+        let total = self.stored.iter().map(|data| data.size).sum::<usize>();
+        (compressed, total - compressed)
+    }
+
+    fn percentage(&self, size: usize) -> f64 {
+        size as f64 * 100.0 / self.rom_size.max(1) as f64
+    }
+
+    /// Renders the statistics as text, listing the `top` largest binaries, overlays and files.
+    pub fn render(&self, top: usize) -> String {
+        let mut output = String::new();
+        let line = |output: &mut String, name: &str, size: usize| {
+            let _ = writeln!(
+                output,
+                "{name:<24} {:>12} {:>6.1}%",
+                format!("0x{size:X}"),
+                self.percentage(size)
+            );
+        };
+        let _ = write!(
+            output,
+            "0x{:X} bytes ({:.2} MiB), ",
+            self.rom_size,
+            self.rom_size as f64 / (1024.0 * 1024.0),
+        );
+        let capacity = if self.capacity >= 1024 * 1024 {
+            format!("{} MiB", self.capacity / (1024 * 1024))
+        } else {
+            format!("{} KiB", self.capacity / 1024)
+        };
+        if self.rom_size > self.capacity {
+            let _ = writeln!(
+                output,
+                "larger than the {capacity} cartridge its header declares"
+            );
+        } else {
+            let _ = writeln!(
+                output,
+                "in a {capacity} cartridge with 0x{:X} bytes to spare",
+                self.capacity - self.rom_size
+            );
+        }
+        let _ = writeln!(output);
+        for &(category, size) in &self.categories {
+            if size == 0 {
+                continue;
+            }
+            line(&mut output, category.name(), size);
+            if category == Category::Files {
+                for (dir, &size) in &self.directories {
+                    let name = if dir.is_empty() {
+                        "  (root directory)".to_owned()
+                    } else {
+                        format!("  {dir}/")
+                    };
+                    line(&mut output, &name, size);
+                }
+            }
+        }
+
+        let (compressed, uncompressed) = self.compression_sizes();
+        let _ = writeln!(output);
+        line(&mut output, "stored compressed", compressed);
+        line(&mut output, "stored uncompressed", uncompressed);
+
+        if top > 0 && !self.stored.is_empty() {
+            let _ = writeln!(output);
+            let _ = writeln!(output, "Largest binaries, overlays and files:");
+            for data in self.stored.iter().take(top) {
+                let name = match &data.region {
+                    Region::File { path, .. } => path.clone(),
+                    region => region.to_string(),
+                };
+                let compression = data
+                    .compression
+                    .map_or(String::new(), |compression| format!(" ({compression})"));
+                let _ = writeln!(
+                    output,
+                    "{:>12} {:>6.1}%  {name}{compression}",
+                    format!("0x{:X}", data.size),
+                    self.percentage(data.size)
+                );
+            }
+        }
+        output
+    }
+}
@@ -0,0 +1,384 @@
+use std::{fmt, ops::Range};
+
+use clap::ValueEnum;
+use thiserror::Error;
+
+/// Text preceding the footer DeSmuME appends to its saves.
+const DSV_FOOTER_TEXT: &[u8] =
+    b"|<--Snip above here to create a raw sav by excluding this DeSmuME savedata footer:";
+/// Magic number ending DeSmuME saves.
+const DSV_COOKIE: &[u8; 16] = b"|-DESMUME SAVE-|";
+/// Size of the fields of the DeSmuME footer: the save size, padded size, save type, address
+/// size, memory size and footer version.
+const DSV_FIELDS_SIZE: usize = 6 * 4;
+const DSV_FOOTER_SIZE: usize = DSV_FOOTER_TEXT.len() + DSV_FIELDS_SIZE + DSV_COOKIE.len();
+/// Save type DeSmuME gives chips it has no type for.
+const DSV_UNKNOWN_TYPE: u32 = 0xFF;
+/// Magic number starting Action Replay saves.
+const DUC_MAGIC: &[u8; 16] = b"ARDS000000000001";
+/// Size of the header of Action Replay saves.
+const DUC_HEADER_SIZE: usize = 500;
+/// Value of erased save memory.
+const ERASED_BYTE: u8 = 0xFF;
+
+#[derive(Error, Debug)]
+pub enum SaveError {
+    #[error("save too large for any save chip ({0} bytes)")]
+    TooLarge(usize),
+    #[error("invalid DeSmuME save footer")]
+    InvalidDsvFooter,
+    #[error("Action Replay save too small to hold its header ({0} bytes)")]
+    TruncatedDuc(usize),
+    #[error("range 0x{:X}..0x{:X} lies outside the save (size: 0x{size:X})", .range.start, .range.end)]
+    OutOfBounds { range: Range<usize>, size: usize },
+}
+
+/// Kinds of memory chips saves are stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveType {
+    Eeprom,
+    Flash,
+    Fram,
+}
+
+impl fmt::Display for SaveType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SaveType::Eeprom => "EEPROM",
+            SaveType::Flash => "FLASH",
+            SaveType::Fram => "FRAM",
+        })
+    }
+}
+
+/// A save chip, as cartridges carry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveChip {
+    pub save_type: SaveType,
+    /// Size of the chip in bytes.
+    pub size: usize,
+}
+
+/// Save chips found in cartridges, by size. 32 KiB chips are usually FRAM, and 64 KiB ones
+/// EEPROM.
+const CHIPS: [SaveChip; 14] = {
+    const fn chip(save_type: SaveType, size: usize) -> SaveChip {
+        SaveChip { save_type, size }
+    }
+    [
+        chip(SaveType::Eeprom, 0x200),
+        chip(SaveType::Eeprom, 0x2000),
+        chip(SaveType::Fram, 0x8000),
+        chip(SaveType::Eeprom, 0x10000),
+        chip(SaveType::Eeprom, 0x20000),
+        chip(SaveType::Flash, 0x40000),
+        chip(SaveType::Flash, 0x80000),
+        chip(SaveType::Flash, 0x100000),
+        chip(SaveType::Flash, 0x200000),
+        chip(SaveType::Flash, 0x400000),
+        chip(SaveType::Flash, 0x800000),
+        chip(SaveType::Flash, 0x1000000),
+        chip(SaveType::Flash, 0x2000000),
+        chip(SaveType::Flash, 0x4000000),
+    ]
+};
+
+impl SaveChip {
+    /// Finds the smallest chip a save of `size` bytes fits in. Saves trimmed by some tools are
+    /// smaller than their chip.
+    pub fn for_size(size: usize) -> Result<Self, SaveError> {
+        CHIPS
+            .into_iter()
+            .find(|chip| chip.size >= size)
+            .ok_or(SaveError::TooLarge(size))
+    }
+
+    /// Number of bytes of the addresses sent to the chip.
+    fn address_size(self) -> u32 {
+        match self.size {
+            0..=0x200 => 1,
+            0x201..=0x10000 => 2,
+            _ => 3,
+        }
+    }
+
+    /// Index of the chip in DeSmuME's list of save types.
+    fn dsv_type(self) -> u32 {
+        match (self.save_type, self.size) {
+            (SaveType::Eeprom, 0x200) => 0,
+            (SaveType::Eeprom, 0x2000) => 1,
+            (SaveType::Eeprom, 0x10000) => 2,
+            (SaveType::Fram, 0x8000) => 3,
+            (SaveType::Flash, size) => 4 + (size / 0x40000).trailing_zeros(),
+            _ => DSV_UNKNOWN_TYPE,
+        }
+    }
+}
+
+impl fmt::Display for SaveChip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kilobits = self.size * 8 / 1024;
+        if kilobits >= 1024 {
+            write!(f, "{} {} Mbit", self.save_type, kilobits / 1024)
+        } else {
+            write!(f, "{} {} Kbit", self.save_type, kilobits)
+        }
+    }
+}
+
+/// Formats save files are stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SaveFormat {
+    /// Raw contents of the save chip (`.sav`), as flashcarts and most emulators use
+    Raw,
+    /// DeSmuME save (`.dsv`): raw contents followed by a footer
+    Dsv,
+    /// Action Replay save (`.duc`): a header followed by raw contents
+    Duc,
+}
+
+impl SaveFormat {
+    /// Picks a format from the extension of a file name, if it has a known one.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "sav" => Some(Self::Raw),
+            "dsv" => Some(Self::Dsv),
+            "duc" => Some(Self::Duc),
+            _ => None,
+        }
+    }
+
+    /// Extension files in this format are given.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Raw => "sav",
+            Self::Dsv => "dsv",
+            Self::Duc => "duc",
+        }
+    }
+
+    /// Detects the format of a save from its header or footer. Saves with neither are raw.
+    pub fn detect(data: &[u8]) -> Self {
+        if data.starts_with(DUC_MAGIC) {
+            Self::Duc
+        } else if data.ends_with(DSV_COOKIE) {
+            Self::Dsv
+        } else {
+            Self::Raw
+        }
+    }
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// The contents of a save chip.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Save {
+    pub data: Vec<u8>,
+    pub chip: SaveChip,
+}
+
+impl Save {
+    /// Reads a save file of the given format, padding its contents to the size of its chip.
+    pub fn parse(data: &[u8], format: SaveFormat) -> Result<Self, SaveError> {
+        let contents = match format {
+            SaveFormat::Raw => data,
+            SaveFormat::Dsv => {
+                if data.len() < DSV_FOOTER_SIZE || !data.ends_with(DSV_COOKIE) {
+                    return Err(SaveError::InvalidDsvFooter);
+                }
+                let fields = data.len() - DSV_COOKIE.len() - DSV_FIELDS_SIZE;
+                let padded_size = u32_at(data, fields + 4) as usize;
+                let footer_start = data.len() - DSV_FOOTER_SIZE;
+                if padded_size > footer_start {
+                    return Err(SaveError::InvalidDsvFooter);
+                }
+                &data[..padded_size]
+            }
+            SaveFormat::Duc => data
+                .get(DUC_HEADER_SIZE..)
+                .ok_or(SaveError::TruncatedDuc(data.len()))?,
+        };
+        let chip = SaveChip::for_size(contents.len())?;
+        let mut data = contents.to_vec();
+        data.resize(chip.size, ERASED_BYTE);
+        Ok(Self { data, chip })
+    }
+
+    /// Writes the save in the given format.
+    pub fn to_bytes(&self, format: SaveFormat) -> Vec<u8> {
+        match format {
+            SaveFormat::Raw => self.data.clone(),
+            SaveFormat::Dsv => {
+                let mut output = Vec::with_capacity(self.data.len() + DSV_FOOTER_SIZE);
+                output.extend_from_slice(&self.data);
+                output.extend_from_slice(DSV_FOOTER_TEXT);
+                let fields = [
+                    self.data.len() as u32,
+                    self.data.len() as u32,
+                    self.chip.dsv_type(),
+                    self.chip.address_size(),
+                    self.chip.size as u32,
+                    0,
+                ];
+                for field in fields {
+                    output.extend_from_slice(&field.to_le_bytes());
+                }
+                output.extend_from_slice(DSV_COOKIE);
+                output
+            }
+            SaveFormat::Duc => {
+                let mut output = DUC_MAGIC.to_vec();
+                output.resize(DUC_HEADER_SIZE, 0);
+                output.extend_from_slice(&self.data);
+                output
+            }
+        }
+    }
+
+    /// Number of bytes of the save before the erased bytes ending it.
+    pub fn used_size(&self) -> usize {
+        self.data
+            .iter()
+            .rposition(|&byte| byte != ERASED_BYTE)
+            .map_or(0, |last| last + 1)
+    }
+
+    fn check_range(&self, range: &Range<usize>) -> Result<(), SaveError> {
+        if range.start > range.end || range.end > self.data.len() {
+            return Err(SaveError::OutOfBounds {
+                range: range.clone(),
+                size: self.data.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Reads a region of the save.
+    pub fn region(&self, range: Range<usize>) -> Result<&[u8], SaveError> {
+        self.check_range(&range)?;
+        Ok(&self.data[range])
+    }
+
+    /// Overwrites the save with `data` from `offset` on.
+    pub fn patch(&mut self, offset: usize, data: &[u8]) -> Result<(), SaveError> {
+        let range = offset..offset.saturating_add(data.len());
+        self.check_range(&range)?;
+        self.data[range].copy_from_slice(data);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_save(size: usize) -> Save {
+        let mut data = vec![ERASED_BYTE; size];
+        for (index, byte) in data[..size / 2].iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        Save {
+            data,
+            chip: SaveChip::for_size(size).unwrap(),
+        }
+    }
+
+    #[test]
+    fn picks_the_smallest_chip() {
+        for (size, save_type, chip_size) in [
+            (0, SaveType::Eeprom, 0x200),
+            (0x200, SaveType::Eeprom, 0x200),
+            (0x201, SaveType::Eeprom, 0x2000),
+            (0x8000, SaveType::Fram, 0x8000),
+            (0x10000, SaveType::Eeprom, 0x10000),
+            (0x3F000, SaveType::Flash, 0x40000),
+            (0x4000000, SaveType::Flash, 0x4000000),
+        ] {
+            assert_eq!(
+                SaveChip::for_size(size).unwrap(),
+                SaveChip {
+                    save_type,
+                    size: chip_size
+                },
+                "{size:#X}"
+            );
+        }
+        assert!(matches!(
+            SaveChip::for_size(0x4000001),
+            Err(SaveError::TooLarge(0x4000001))
+        ));
+    }
+
+    #[test]
+    fn round_trips() {
+        for size in [0x200, 0x8000, 0x80000] {
+            let save = sample_save(size);
+            for format in [SaveFormat::Raw, SaveFormat::Dsv, SaveFormat::Duc] {
+                let bytes = save.to_bytes(format);
+                assert_eq!(SaveFormat::detect(&bytes), format);
+                assert_eq!(Save::parse(&bytes, format).unwrap(), save, "{format:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn writes_dsv_footers() {
+        let bytes = sample_save(0x80000).to_bytes(SaveFormat::Dsv);
+        assert_eq!(bytes.len(), 0x80000 + DSV_FOOTER_SIZE);
+        let fields = bytes.len() - DSV_COOKIE.len() - DSV_FIELDS_SIZE;
+        let fields = (0..6)
+            .map(|index| u32_at(&bytes, fields + index * 4))
+            .collect::<Vec<_>>();
+        assert_eq!(fields, [0x80000, 0x80000, 5, 3, 0x80000, 0]);
+    }
+
+    #[test]
+    fn pads_trimmed_saves() {
+        let save = Save::parse(&[1, 2, 3], SaveFormat::Raw).unwrap();
+        assert_eq!(save.chip.size, 0x200);
+        assert_eq!(save.data[..3], [1, 2, 3]);
+        assert!(save.data[3..].iter().all(|&byte| byte == ERASED_BYTE));
+        assert_eq!(save.used_size(), 3);
+
+        let mut duc = DUC_MAGIC.to_vec();
+        duc.resize(DUC_HEADER_SIZE, 0);
+        duc.push(7);
+        assert_eq!(Save::parse(&duc, SaveFormat::Duc).unwrap().data[0], 7);
+        assert!(matches!(
+            Save::parse(&duc[..10], SaveFormat::Duc),
+            Err(SaveError::TruncatedDuc(10))
+        ));
+    }
+
+    #[test]
+    fn rejects_broken_dsv_footers() {
+        let mut bytes = sample_save(0x200).to_bytes(SaveFormat::Dsv);
+        assert!(matches!(
+            Save::parse(&bytes[1..0x100], SaveFormat::Dsv),
+            Err(SaveError::InvalidDsvFooter)
+        ));
+        // A padded size running into the footer.
+        let fields = bytes.len() - DSV_COOKIE.len() - DSV_FIELDS_SIZE;
+        bytes[fields + 4..fields + 8].copy_from_slice(&0x300u32.to_le_bytes());
+        assert!(matches!(
+            Save::parse(&bytes, SaveFormat::Dsv),
+            Err(SaveError::InvalidDsvFooter)
+        ));
+    }
+
+    #[test]
+    fn rejects_ranges_outside_the_save() {
+        let mut save = sample_save(0x200);
+        assert_eq!(save.region(0x1FE..0x200).unwrap(), [ERASED_BYTE; 2]);
+        assert!(save.region(0x1FE..0x201).is_err());
+        save.patch(0x1FF, &[1]).unwrap();
+        assert!(save.patch(0x1FF, &[1, 2]).is_err());
+        assert!(matches!(
+            save.patch(usize::MAX, &[1]),
+            Err(SaveError::OutOfBounds { .. })
+        ));
+    }
+}
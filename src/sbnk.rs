@@ -0,0 +1,399 @@
+use log::warn;
+use thiserror::Error;
+
+use crate::{
+    sf2::{self, SoundFont},
+    wave::Wave,
+};
+
+const SBNK_MAGIC: &[u8; 4] = b"SBNK";
+/// Offset of the instrument count, past the header, the DATA block header and 32 reserved bytes.
+const INSTRUMENT_COUNT_OFFSET: usize = 0x38;
+/// Size of the note definitions of instruments.
+const NOTE_DEFINITION_SIZE: usize = 10;
+/// Number of regions of key split instruments.
+const KEY_SPLIT_REGIONS: usize = 8;
+
+/// Instrument types, as stored in the instrument records.
+const INSTRUMENT_PCM: u8 = 1;
+const INSTRUMENT_PSG: u8 = 2;
+const INSTRUMENT_NOISE: u8 = 3;
+const INSTRUMENT_DRUM_SET: u8 = 16;
+const INSTRUMENT_KEY_SPLIT: u8 = 17;
+
+/// Rate at which the sound driver updates envelopes, in updates per second.
+const ENVELOPE_UPDATE_RATE: f64 = 192.0;
+/// Amplitude of silence for the sound driver's envelopes, in 1/128 centibels below full volume.
+const SILENT_AMPLITUDE: f64 = 723.0 * 128.0;
+/// Attack rates of the highest attack values, from 127 down to 109.
+const FAST_ATTACK_RATES: [u8; 19] = [
+    0x00, 0x01, 0x05, 0x0E, 0x1A, 0x26, 0x33, 0x3F, 0x49, 0x54, 0x5C, 0x64, 0x6D, 0x74, 0x7B, 0x7F,
+    0x84, 0x89, 0x8F,
+];
+/// Shortest and longest times SoundFont envelopes can take, in timecents.
+const MIN_TIMECENTS: f64 = -12000.0;
+const MAX_TIMECENTS: f64 = 8000.0;
+
+/// Sample rate and root key of the period of square waves synthesized for PSG instruments.
+const PSG_SAMPLE_RATE: u32 = 440 * 8;
+const PSG_ROOT_KEY: u8 = 69;
+/// Number of periods of the square waves synthesized for PSG instruments.
+const PSG_PERIODS: usize = 32;
+/// Sample rate and length of the noise synthesized for noise instruments.
+const NOISE_SAMPLE_RATE: u32 = 32768;
+const NOISE_LEN: usize = 0x7FFF;
+/// Amplitude of synthesized samples.
+const SYNTHESIZED_AMPLITUDE: i16 = 0x3000;
+
+#[derive(Error, Debug)]
+pub enum ParseBankError {
+    #[error("magic number does not match (expected: SBNK)")]
+    MagicNumberMismatch,
+    #[error("bank data is truncated")]
+    Truncated,
+}
+
+/// The sound a note is played with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteSound {
+    /// A wave of one of the wave archives of the bank.
+    Pcm {
+        wave_archive_slot: usize,
+        wave: usize,
+    },
+    /// A square wave of the programmable sound generator, high for `(duty + 1) / 8` of its
+    /// period.
+    Psg { duty: u8 },
+    /// White noise of the programmable sound generator.
+    Noise,
+}
+
+/// How a range of notes of an instrument is played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoteDefinition {
+    pub sound: NoteSound,
+    /// Note at which the sound plays at its own sample rate.
+    pub base_note: u8,
+    pub attack: u8,
+    pub decay: u8,
+    pub sustain: u8,
+    pub release: u8,
+    /// Pan, from 0 (left) to 127 (right).
+    pub pan: u8,
+}
+
+/// A range of notes of an instrument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub low_note: u8,
+    pub high_note: u8,
+    pub note: NoteDefinition,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instrument {
+    pub regions: Vec<Region>,
+}
+
+/// A bank of instruments (SBNK), made of the waves of up to 4 wave archives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sbnk {
+    /// Instruments by program number. Unused program numbers are `None`.
+    pub instruments: Vec<Option<Instrument>>,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, ParseBankError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseBankError::Truncated)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, ParseBankError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseBankError::Truncated)
+}
+
+/// Parses a note definition of an instrument of the given type. Types without a sound, and
+/// types the driver doesn't play from banks, give `None`.
+fn parse_note_definition(
+    data: &[u8],
+    offset: usize,
+    instrument_type: u8,
+) -> Result<Option<NoteDefinition>, ParseBankError> {
+    let definition = data
+        .get(offset..offset + NOTE_DEFINITION_SIZE)
+        .ok_or(ParseBankError::Truncated)?;
+    let wave = u16_at(definition, 0)?;
+    let sound = match instrument_type {
+        INSTRUMENT_PCM => NoteSound::Pcm {
+            wave_archive_slot: u16_at(definition, 2)? as usize,
+            wave: wave as usize,
+        },
+        INSTRUMENT_PSG => NoteSound::Psg { duty: wave as u8 },
+        INSTRUMENT_NOISE => NoteSound::Noise,
+        _ => return Ok(None),
+    };
+    Ok(Some(NoteDefinition {
+        sound,
+        base_note: definition[4],
+        attack: definition[5],
+        decay: definition[6],
+        sustain: definition[7],
+        release: definition[8],
+        pan: definition[9],
+    }))
+}
+
+impl Sbnk {
+    pub fn parse(data: &[u8]) -> Result<Self, ParseBankError> {
+        if !data.starts_with(SBNK_MAGIC) {
+            return Err(ParseBankError::MagicNumberMismatch);
+        }
+        let count = u32_at(data, INSTRUMENT_COUNT_OFFSET)? as usize;
+        let instruments = (0..count)
+            .map(|index| {
+                let record = INSTRUMENT_COUNT_OFFSET + 4 + index * 4;
+                let instrument_type = *data.get(record).ok_or(ParseBankError::Truncated)?;
+                let offset = u16_at(data, record + 1)? as usize;
+                let regions = match instrument_type {
+                    INSTRUMENT_DRUM_SET => {
+                        let (low_note, high_note) = (
+                            *data.get(offset).ok_or(ParseBankError::Truncated)?,
+                            *data.get(offset + 1).ok_or(ParseBankError::Truncated)?,
+                        );
+                        let mut regions = Vec::new();
+                        for (index, key) in (low_note..=high_note).enumerate() {
+                            let entry = offset + 2 + index * (NOTE_DEFINITION_SIZE + 2);
+                            let entry_type = u16_at(data, entry)? as u8;
+                            if let Some(note) = parse_note_definition(data, entry + 2, entry_type)?
+                            {
+                                regions.push(Region {
+                                    low_note: key,
+                                    high_note: key,
+                                    note,
+                                });
+                            }
+                        }
+                        regions
+                    }
+                    INSTRUMENT_KEY_SPLIT => {
+                        let upper_notes = data
+                            .get(offset..offset + KEY_SPLIT_REGIONS)
+                            .ok_or(ParseBankError::Truncated)?;
+                        let mut regions = Vec::new();
+                        let mut low_note = 0;
+                        for (index, &high_note) in upper_notes
+                            .iter()
+                            .take_while(|&&high_note| high_note != 0)
+                            .enumerate()
+                        {
+                            let entry =
+                                offset + KEY_SPLIT_REGIONS + index * (NOTE_DEFINITION_SIZE + 2);
+                            let entry_type = u16_at(data, entry)? as u8;
+                            if let Some(note) = parse_note_definition(data, entry + 2, entry_type)?
+                            {
+                                regions.push(Region {
+                                    low_note,
+                                    high_note,
+                                    note,
+                                });
+                            }
+                            low_note = high_note.saturating_add(1);
+                        }
+                        regions
+                    }
+                    instrument_type => match parse_note_definition(data, offset, instrument_type) {
+                        Ok(Some(note)) => vec![Region {
+                            low_note: 0,
+                            high_note: 0x7F,
+                            note,
+                        }],
+                        Ok(None) => return Ok(None),
+                        Err(error) => return Err(error),
+                    },
+                };
+                Ok(Some(Instrument { regions }))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { instruments })
+    }
+
+    /// Converts the bank to a SoundFont, taking its samples from `wave_archives`, the waves of
+    /// the wave archive of each slot of the bank.
+    ///
+    /// Program numbers past 127 are given to presets of the following banks, as SSEQ
+    /// sequences select them when converted to MIDI. Envelopes are converted approximately, as
+    /// the sound driver doesn't work in the same units as SoundFonts, and regions playing waves
+    /// missing from the wave archives are left out.
+    pub fn to_soundfont(&self, name: &str, wave_archives: &[Option<Vec<Wave>>]) -> SoundFont {
+        let mut soundfont = SoundFont {
+            name: name.to_owned(),
+            ..Default::default()
+        };
+        let mut sample_indices = Vec::<(NoteSound, usize)>::new();
+        for (program, instrument) in self.instruments.iter().enumerate() {
+            let Some(instrument) = instrument else {
+                continue;
+            };
+            let mut zones = Vec::new();
+            for region in &instrument.regions {
+                let note = region.note;
+                let found = sample_indices
+                    .iter()
+                    .find(|(sound, _)| *sound == note.sound)
+                    .map(|&(_, index)| index);
+                let sample = match found {
+                    Some(index) => index,
+                    None => {
+                        let Some(sample) = sample_for(note.sound, wave_archives) else {
+                            warn!(
+                                "instrument {program} plays missing wave {:?}, leaving it out",
+                                note.sound
+                            );
+                            continue;
+                        };
+                        soundfont.samples.push(sample);
+                        sample_indices.push((note.sound, soundfont.samples.len() - 1));
+                        soundfont.samples.len() - 1
+                    }
+                };
+                let root_key = match note.sound {
+                    NoteSound::Psg { .. } => PSG_ROOT_KEY,
+                    _ => note.base_note,
+                };
+                zones.push(sf2::Zone {
+                    key_range: (region.low_note, region.high_note),
+                    sample,
+                    root_key,
+                    pan: ((note.pan as i16 - 64) * 500 / 64).clamp(-500, 500),
+                    envelope: sf2::Envelope {
+                        attack: timecents(attack_updates(note.attack) / ENVELOPE_UPDATE_RATE),
+                        // SoundFont decay and release times are for a fall of 100 dB.
+                        decay: timecents(
+                            1000.0 * 128.0 / fall_rate(note.decay) / ENVELOPE_UPDATE_RATE,
+                        ),
+                        sustain: sustain_attenuation(note.sustain),
+                        release: timecents(
+                            1000.0 * 128.0 / fall_rate(note.release) / ENVELOPE_UPDATE_RATE,
+                        ),
+                    },
+                });
+            }
+            let instrument_name = format!("Instrument {program}");
+            soundfont.instruments.push(sf2::Instrument {
+                name: instrument_name.clone(),
+                zones,
+            });
+            soundfont.presets.push(sf2::Preset {
+                name: instrument_name,
+                bank: (program >> 7) as u16,
+                program: (program & 0x7F) as u16,
+                instrument: soundfont.instruments.len() - 1,
+            });
+        }
+        soundfont
+    }
+}
+
+/// Builds the sample a sound is played with, or returns `None` if its wave is missing.
+fn sample_for(sound: NoteSound, wave_archives: &[Option<Vec<Wave>>]) -> Option<sf2::Sample> {
+    Some(match sound {
+        NoteSound::Pcm {
+            wave_archive_slot,
+            wave,
+        } => {
+            let waves = wave_archives.get(wave_archive_slot)?.as_ref()?;
+            let wave_data = waves.get(wave)?;
+            sf2::Sample {
+                name: format!("wave{wave_archive_slot}_{wave:04}"),
+                samples: wave_data.channels.first().cloned().unwrap_or_default(),
+                sample_rate: wave_data.sample_rate,
+                loop_points: wave_data
+                    .loop_points
+                    .map(|loop_points| (loop_points.loop_start, loop_points.loop_end)),
+            }
+        }
+        NoteSound::Psg { duty } => {
+            // Duty 7 is silent.
+            let high_samples = if duty < 7 { duty as usize + 1 } else { 0 };
+            let period = (0..8).map(|index| {
+                if index < high_samples {
+                    SYNTHESIZED_AMPLITUDE
+                } else {
+                    -SYNTHESIZED_AMPLITUDE
+                }
+            });
+            let samples = period.cycle().take(8 * PSG_PERIODS).collect::<Vec<_>>();
+            sf2::Sample {
+                name: format!("square{duty}"),
+                loop_points: Some((0, samples.len())),
+                samples,
+                sample_rate: PSG_SAMPLE_RATE,
+            }
+        }
+        NoteSound::Noise => {
+            // The linear-feedback shift register of the sound hardware's noise channels.
+            let mut state = 0x7FFFu16;
+            let samples = (0..NOISE_LEN)
+                .map(|_| {
+                    let carry = state & 1 != 0;
+                    state >>= 1;
+                    if carry {
+                        state ^= 0x6000;
+                        -SYNTHESIZED_AMPLITUDE
+                    } else {
+                        SYNTHESIZED_AMPLITUDE
+                    }
+                })
+                .collect::<Vec<_>>();
+            sf2::Sample {
+                name: "noise".to_owned(),
+                loop_points: Some((0, samples.len())),
+                samples,
+                sample_rate: NOISE_SAMPLE_RATE,
+            }
+        }
+    })
+}
+
+/// Converts a time in seconds to timecents.
+fn timecents(seconds: f64) -> i16 {
+    if seconds <= 0.0 {
+        return MIN_TIMECENTS as i16;
+    }
+    (1200.0 * seconds.log2()).clamp(MIN_TIMECENTS, MAX_TIMECENTS) as i16
+}
+
+/// Number of envelope updates an attack takes. Each update multiplies the attenuation by the
+/// attack rate, out of 256.
+fn attack_updates(attack: u8) -> f64 {
+    let rate = match attack {
+        109..=127 => FAST_ATTACK_RATES[127 - attack as usize],
+        _ => 255 - attack.min(127),
+    } as f64;
+    if rate == 0.0 {
+        0.0
+    } else {
+        SILENT_AMPLITUDE.ln() / (256.0 / rate).ln()
+    }
+}
+
+/// Amplitude a decay or release lowers the volume by on each envelope update.
+fn fall_rate(value: u8) -> f64 {
+    match value {
+        127.. => 0xFFFF as f64,
+        126 => 0x3C00 as f64,
+        0..=49 => value as f64 * 2.0 + 1.0,
+        _ => 0x1E00 as f64 / (126 - value) as f64,
+    }
+}
+
+/// Attenuation of a sustain level, in centibels. The driver squares the level, from 0 to 127.
+fn sustain_attenuation(sustain: u8) -> i16 {
+    let silent = SILENT_AMPLITUDE / 128.0;
+    if sustain == 0 {
+        return silent as i16;
+    }
+    (400.0 * (127.0 / sustain.min(127) as f64).log10()).min(silent) as i16
+}
@@ -0,0 +1,780 @@
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    manifest::{SdatFileRecord, SdatInfoRecord, SdatManifest, SDAT_MANIFEST_FILE_NAME},
+    rom,
+};
+
+const SDAT_MAGIC: &[u8; 4] = b"SDAT";
+const SYMB_MAGIC: &[u8; 4] = b"SYMB";
+const INFO_MAGIC: &[u8; 4] = b"INFO";
+const FAT_MAGIC: &[u8; 4] = b"FAT ";
+const FILE_MAGIC: &[u8; 4] = b"FILE";
+/// Size of the SDAT header, including its reserved bytes.
+const HEADER_SIZE: usize = 0x40;
+/// Size of the header of the SYMB and INFO blocks, holding the offsets of their record lists.
+const RECORD_BLOCK_HEADER_SIZE: usize = 0x40;
+/// Number of record lists in the SYMB and INFO blocks.
+const RECORD_LIST_COUNT: usize = 8;
+/// Size of each entry of the FAT block.
+const FAT_ENTRY_SIZE: usize = 0x10;
+/// Size of the header of the FILE block.
+const FILE_BLOCK_HEADER_SIZE: usize = 0x10;
+/// Alignment of the files in the FILE block.
+const FILE_ALIGNMENT: usize = 0x20;
+/// Number of wave archives a bank can take samples from.
+const BANK_WAVE_ARCHIVE_SLOTS: usize = 4;
+/// Wave archive index of unused slots of banks.
+const NO_WAVE_ARCHIVE: u16 = 0xFFFF;
+
+#[derive(Error, Debug)]
+pub enum ParseSoundArchiveError {
+    #[error("magic number does not match (expected: SDAT)")]
+    MagicNumberMismatch,
+    #[error("sound archive data is truncated")]
+    Truncated,
+    #[error("required block {0} not found")]
+    MissingBlock(&'static str),
+    #[error("{kind} {index} references missing file {file_id}")]
+    InvalidFileId {
+        kind: SoundKind,
+        index: usize,
+        file_id: u32,
+    },
+    #[error("file {file_id} points outside of the sound archive")]
+    InvalidFileBounds { file_id: u32 },
+}
+
+#[derive(Error, Debug)]
+pub enum BuildSoundArchiveError {
+    #[error("too many files for a sound archive (found: {0}, maximum: 65535)")]
+    TooManyFiles(usize),
+    #[error("file {file_id} is given different data by {kind} {index}")]
+    ConflictingFileId {
+        kind: SoundKind,
+        index: usize,
+        file_id: u32,
+    },
+}
+
+/// The kinds of sound files kept in a sound archive, in the order of their records in the
+/// SYMB and INFO blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SoundKind {
+    /// SSEQ sequence, a song or sound effect played with the instruments of a bank.
+    Sequence,
+    /// SSAR archive of sequences, usually sound effects.
+    SequenceArchive,
+    /// SBNK bank of instruments, made of the samples of up to 4 wave archives.
+    Bank,
+    /// SWAR archive of wave samples.
+    WaveArchive,
+    /// STRM stream of wave data, usually music or voices.
+    Stream,
+}
+
+impl SoundKind {
+    pub const ALL: [SoundKind; 5] = [
+        SoundKind::Sequence,
+        SoundKind::SequenceArchive,
+        SoundKind::Bank,
+        SoundKind::WaveArchive,
+        SoundKind::Stream,
+    ];
+
+    /// Index of the record list of this kind in the SYMB and INFO blocks.
+    fn record_index(self) -> usize {
+        match self {
+            SoundKind::Sequence => 0,
+            SoundKind::SequenceArchive => 1,
+            SoundKind::Bank => 2,
+            SoundKind::WaveArchive => 3,
+            SoundKind::Stream => 7,
+        }
+    }
+
+    /// Size of the INFO records of this kind.
+    fn record_size(self) -> usize {
+        match self {
+            SoundKind::Sequence | SoundKind::Bank | SoundKind::Stream => 0xC,
+            SoundKind::SequenceArchive | SoundKind::WaveArchive => 0x4,
+        }
+    }
+
+    /// Extension of the files of this kind.
+    pub fn extension(self) -> &'static str {
+        match self {
+            SoundKind::Sequence => "sseq",
+            SoundKind::SequenceArchive => "ssar",
+            SoundKind::Bank => "sbnk",
+            SoundKind::WaveArchive => "swar",
+            SoundKind::Stream => "strm",
+        }
+    }
+
+    /// Name of the directory files of this kind are extracted to.
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            SoundKind::Sequence => "sequences",
+            SoundKind::SequenceArchive => "sequence_archives",
+            SoundKind::Bank => "banks",
+            SoundKind::WaveArchive => "wave_archives",
+            SoundKind::Stream => "streams",
+        }
+    }
+}
+
+impl fmt::Display for SoundKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SoundKind::Sequence => "sequence",
+            SoundKind::SequenceArchive => "sequence archive",
+            SoundKind::Bank => "bank",
+            SoundKind::WaveArchive => "wave archive",
+            SoundKind::Stream => "stream",
+        })
+    }
+}
+
+/// The kinds of records of the INFO block that aren't sound files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordKind {
+    /// Player sequences are played on, limiting how many play at once.
+    Player,
+    /// Group of files loaded together.
+    Group,
+    /// Player streams are played on, giving the channels they use.
+    StreamPlayer,
+}
+
+impl RecordKind {
+    pub const ALL: [RecordKind; 3] = [
+        RecordKind::Player,
+        RecordKind::Group,
+        RecordKind::StreamPlayer,
+    ];
+
+    /// Index of the record list of this kind in the SYMB and INFO blocks.
+    fn record_index(self) -> usize {
+        match self {
+            RecordKind::Player => 4,
+            RecordKind::Group => 5,
+            RecordKind::StreamPlayer => 6,
+        }
+    }
+
+    /// Size of the INFO record at `offset`.
+    fn record_size(self, data: &[u8], offset: usize) -> Result<usize, ParseSoundArchiveError> {
+        Ok(match self {
+            RecordKind::Player => 0x8,
+            // A count of entries of 8 bytes.
+            RecordKind::Group => 4 + u32_at(data, offset)? as usize * 8,
+            RecordKind::StreamPlayer => 0x18,
+        })
+    }
+}
+
+impl fmt::Display for RecordKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RecordKind::Player => "player",
+            RecordKind::Group => "group",
+            RecordKind::StreamPlayer => "stream player",
+        })
+    }
+}
+
+/// A record of the INFO block that isn't a sound file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InfoRecord {
+    pub kind: RecordKind,
+    /// Index of the record among the records of its kind.
+    pub index: usize,
+    /// Name of the record in the SYMB block, if the archive has one and the record is named.
+    pub name: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// A sound file inside a sound archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoundFile {
+    pub kind: SoundKind,
+    /// Index of the file among the files of its kind.
+    pub index: usize,
+    /// Name of the file in the SYMB block, if the archive has one and the file is named.
+    pub name: Option<String>,
+    /// ID of the file in the FAT block. Several sound files may share the same data.
+    pub file_id: u32,
+    /// For banks, the indices of the wave archives their instruments take samples from, by
+    /// slot. Slots without a wave archive are `None`.
+    pub wave_archives: Vec<Option<usize>>,
+    /// For sequence archives, the names of their sequences in the SYMB block.
+    pub sequence_names: Vec<Option<String>>,
+    /// The record of the file in the INFO block past its file ID, such as the bank and volume
+    /// of a sequence. The wave archive slots of banks are written from `wave_archives`.
+    pub info: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+impl SoundFile {
+    /// The name of the file, or a name made up from its kind and index if it has none.
+    pub fn display_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| {
+            format!("{}_{:04}", self.kind.extension().to_uppercase(), self.index)
+        })
+    }
+}
+
+/// A sound data archive, holding every sequence, instrument and sample of a game.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sdat {
+    /// Whether the archive has a SYMB block naming its records.
+    pub named: bool,
+    /// The files of the archive, sorted by kind and index.
+    pub files: Vec<SoundFile>,
+    /// The players and groups of the archive, sorted by kind and index.
+    pub records: Vec<InfoRecord>,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, ParseSoundArchiveError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseSoundArchiveError::Truncated)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, ParseSoundArchiveError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseSoundArchiveError::Truncated)
+}
+
+/// Reads a list of offsets, relative to `block`, from a record list of a SYMB or INFO block.
+/// The list of sequence archives in the SYMB block has pairs of offsets instead, of which only
+/// the first is read.
+fn record_offsets(
+    data: &[u8],
+    block: usize,
+    record_index: usize,
+    stride: usize,
+) -> Result<Vec<u32>, ParseSoundArchiveError> {
+    let list = block + u32_at(data, block + 0x8 + record_index * 4)? as usize;
+    if list == block {
+        return Ok(Vec::new());
+    }
+    let count = u32_at(data, list)? as usize;
+    (0..count)
+        .map(|index| u32_at(data, list + 4 + index * stride))
+        .collect()
+}
+
+/// Reads the name at `offset` in a SYMB block, if there is one.
+fn name_at(
+    data: &[u8],
+    symb: usize,
+    offset: u32,
+) -> Result<Option<String>, ParseSoundArchiveError> {
+    if offset == 0 {
+        return Ok(None);
+    }
+    string_at(data, symb + offset as usize).map(Some)
+}
+
+/// Reads the names of a record list of a SYMB block.
+fn record_names(
+    data: &[u8],
+    symb: Option<usize>,
+    record_index: usize,
+) -> Result<Vec<Option<String>>, ParseSoundArchiveError> {
+    let Some(symb) = symb else {
+        return Ok(Vec::new());
+    };
+    let stride = if record_index == SoundKind::SequenceArchive.record_index() {
+        8
+    } else {
+        4
+    };
+    record_offsets(data, symb, record_index, stride)?
+        .into_iter()
+        .map(|name| name_at(data, symb, name))
+        .collect()
+}
+
+/// Reads the names of the sequences of each sequence archive, from the lists the second offset
+/// of each of their SYMB records points to.
+fn sequence_archive_names(
+    data: &[u8],
+    symb: Option<usize>,
+) -> Result<Vec<Vec<Option<String>>>, ParseSoundArchiveError> {
+    let Some(symb) = symb else {
+        return Ok(Vec::new());
+    };
+    let index = SoundKind::SequenceArchive.record_index();
+    let list = symb + u32_at(data, symb + 0x8 + index * 4)? as usize;
+    if list == symb {
+        return Ok(Vec::new());
+    }
+    let count = u32_at(data, list)? as usize;
+    (0..count)
+        .map(|archive| {
+            let names = u32_at(data, list + 4 + archive * 8 + 4)? as usize;
+            if names == 0 {
+                return Ok(Vec::new());
+            }
+            let name_count = u32_at(data, symb + names)? as usize;
+            (0..name_count)
+                .map(|name| name_at(data, symb, u32_at(data, symb + names + 4 + name * 4)?))
+                .collect()
+        })
+        .collect()
+}
+
+/// Reads a null-terminated string.
+fn string_at(data: &[u8], offset: usize) -> Result<String, ParseSoundArchiveError> {
+    let bytes = data
+        .get(offset..)
+        .ok_or(ParseSoundArchiveError::Truncated)?;
+    let len = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or(ParseSoundArchiveError::Truncated)?;
+    Ok(String::from_utf8_lossy(&bytes[..len]).into_owned())
+}
+
+impl Sdat {
+    /// Finds a file by kind and index.
+    pub fn file(&self, kind: SoundKind, index: usize) -> Option<&SoundFile> {
+        self.files
+            .iter()
+            .find(|file| file.kind == kind && file.index == index)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, ParseSoundArchiveError> {
+        if !data.starts_with(SDAT_MAGIC) {
+            return Err(ParseSoundArchiveError::MagicNumberMismatch);
+        }
+        let block = |index: usize, magic: &[u8; 4], name| {
+            let offset = u32_at(data, 0x10 + index * 8)? as usize;
+            if offset == 0 {
+                return Ok(None);
+            }
+            if data.get(offset..offset + 4) != Some(magic.as_slice()) {
+                return Err(ParseSoundArchiveError::MissingBlock(name));
+            }
+            Ok(Some(offset))
+        };
+        // Archives without names have no SYMB block, and its offset is left as zero.
+        let symb = block(0, SYMB_MAGIC, "SYMB")?;
+        let info =
+            block(1, INFO_MAGIC, "INFO")?.ok_or(ParseSoundArchiveError::MissingBlock("INFO"))?;
+        let fat = block(2, FAT_MAGIC, "FAT")?.ok_or(ParseSoundArchiveError::MissingBlock("FAT"))?;
+
+        let fat_count = u32_at(data, fat + 0x8)?;
+        let sequence_names = sequence_archive_names(data, symb)?;
+        let mut files = Vec::new();
+        for kind in SoundKind::ALL {
+            let names = record_names(data, symb, kind.record_index())?;
+            let entries = record_offsets(data, info, kind.record_index(), 4)?;
+            for (index, &entry) in entries.iter().enumerate() {
+                // Unused records have no entry.
+                if entry == 0 {
+                    continue;
+                }
+                let file_id = u16_at(data, info + entry as usize)? as u32;
+                if file_id >= fat_count {
+                    return Err(ParseSoundArchiveError::InvalidFileId {
+                        kind,
+                        index,
+                        file_id,
+                    });
+                }
+                let fat_entry = fat + 0xC + file_id as usize * FAT_ENTRY_SIZE;
+                let offset = u32_at(data, fat_entry)? as usize;
+                let size = u32_at(data, fat_entry + 4)? as usize;
+                let file_data = data
+                    .get(offset..offset + size)
+                    .ok_or(ParseSoundArchiveError::InvalidFileBounds { file_id })?;
+                let wave_archives = if kind == SoundKind::Bank {
+                    (0..BANK_WAVE_ARCHIVE_SLOTS)
+                        .map(|slot| {
+                            let index = u16_at(data, info + entry as usize + 4 + slot * 2)?;
+                            Ok((index != NO_WAVE_ARCHIVE).then_some(index as usize))
+                        })
+                        .collect::<Result<_, _>>()?
+                } else {
+                    Vec::new()
+                };
+                let record = info + entry as usize;
+                files.push(SoundFile {
+                    kind,
+                    index,
+                    name: names.get(index).cloned().flatten(),
+                    file_id,
+                    wave_archives,
+                    sequence_names: match kind {
+                        SoundKind::SequenceArchive => {
+                            sequence_names.get(index).cloned().unwrap_or_default()
+                        }
+                        _ => Vec::new(),
+                    },
+                    info: data
+                        .get(record + 2..record + kind.record_size())
+                        .ok_or(ParseSoundArchiveError::Truncated)?
+                        .to_vec(),
+                    data: file_data.to_vec(),
+                });
+            }
+        }
+
+        let mut records = Vec::new();
+        for kind in RecordKind::ALL {
+            let names = record_names(data, symb, kind.record_index())?;
+            let entries = record_offsets(data, info, kind.record_index(), 4)?;
+            for (index, &entry) in entries.iter().enumerate() {
+                if entry == 0 {
+                    continue;
+                }
+                let record = info + entry as usize;
+                let size = kind.record_size(data, record)?;
+                records.push(InfoRecord {
+                    kind,
+                    index,
+                    name: names.get(index).cloned().flatten(),
+                    data: data
+                        .get(record..record + size)
+                        .ok_or(ParseSoundArchiveError::Truncated)?
+                        .to_vec(),
+                });
+            }
+        }
+        Ok(Sdat {
+            named: symb.is_some(),
+            files,
+            records,
+        })
+    }
+
+    /// The records of the SYMB and INFO blocks, by record list: each record's name, its name
+    /// list for sequence archives, and its INFO record.
+    fn record_lists(&self) -> Vec<Vec<Option<ListedRecord>>> {
+        let mut lists: Vec<Vec<Option<ListedRecord>>> = vec![Vec::new(); RECORD_LIST_COUNT];
+        let mut place = |list: usize, index: usize, record: ListedRecord| {
+            let list = &mut lists[list];
+            if list.len() <= index {
+                list.resize(index + 1, None);
+            }
+            list[index] = Some(record);
+        };
+        for file in &self.files {
+            let mut info = (file.file_id as u16).to_le_bytes().to_vec();
+            info.extend(&file.info);
+            info.resize(info.len().max(file.kind.record_size()), 0);
+            if file.kind == SoundKind::Bank {
+                let slots = file.wave_archives.iter().take(BANK_WAVE_ARCHIVE_SLOTS);
+                for (slot, wave_archive) in slots.enumerate() {
+                    let value = wave_archive.map_or(NO_WAVE_ARCHIVE, |index| index as u16);
+                    info[4 + slot * 2..6 + slot * 2].copy_from_slice(&value.to_le_bytes());
+                }
+            }
+            let sequence_names =
+                (file.kind == SoundKind::SequenceArchive).then(|| file.sequence_names.clone());
+            place(
+                file.kind.record_index(),
+                file.index,
+                ListedRecord {
+                    name: file.name.clone(),
+                    sequence_names,
+                    info,
+                },
+            );
+        }
+        for record in &self.records {
+            place(
+                record.kind.record_index(),
+                record.index,
+                ListedRecord {
+                    name: record.name.clone(),
+                    sequence_names: None,
+                    info: record.data.clone(),
+                },
+            );
+        }
+        lists
+    }
+
+    /// Serializes the archive. Files sharing a file ID are stored once.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, BuildSoundArchiveError> {
+        let mut fat_files = BTreeMap::<u32, &[u8]>::new();
+        for file in &self.files {
+            if file.file_id > u16::MAX as u32 {
+                return Err(BuildSoundArchiveError::TooManyFiles(
+                    file.file_id as usize + 1,
+                ));
+            }
+            let data = fat_files.entry(file.file_id).or_insert(&file.data);
+            if *data != file.data.as_slice() {
+                return Err(BuildSoundArchiveError::ConflictingFileId {
+                    kind: file.kind,
+                    index: file.index,
+                    file_id: file.file_id,
+                });
+            }
+        }
+        let file_count = fat_files.keys().next_back().map_or(0, |&last| last + 1) as usize;
+        let lists = self.record_lists();
+
+        let symb = self.named.then(|| symb_block(&lists));
+        let info = info_block(&lists);
+        let symb_offset = HEADER_SIZE;
+        let info_offset = symb_offset + symb.as_ref().map_or(0, Vec::len);
+        let fat_offset = info_offset + info.len();
+        let fat_size = 0xC + file_count * FAT_ENTRY_SIZE;
+        let file_offset = rom::align_up(fat_offset + fat_size, FILE_ALIGNMENT);
+
+        let mut fat = vec![0; fat_size];
+        fat[..4].copy_from_slice(FAT_MAGIC);
+        set_u32(&mut fat, 4, fat_size);
+        set_u32(&mut fat, 8, file_count);
+        let mut file_block = vec![0; FILE_BLOCK_HEADER_SIZE];
+        for (&file_id, data) in &fat_files {
+            let offset = rom::align_up(file_offset + file_block.len(), FILE_ALIGNMENT);
+            file_block.resize(offset - file_offset, 0);
+            file_block.extend_from_slice(data);
+            let entry = 0xC + file_id as usize * FAT_ENTRY_SIZE;
+            set_u32(&mut fat, entry, offset);
+            set_u32(&mut fat, entry + 4, data.len());
+        }
+        file_block.resize(rom::align_up(file_block.len(), FILE_ALIGNMENT), 0);
+        file_block[..4].copy_from_slice(FILE_MAGIC);
+        let file_block_size = file_block.len();
+        set_u32(&mut file_block, 4, file_block_size);
+        set_u32(&mut file_block, 8, file_count);
+
+        let mut data = vec![0; HEADER_SIZE];
+        data[..4].copy_from_slice(SDAT_MAGIC);
+        // Byte order mark and version.
+        data[4..8].copy_from_slice(&[0xFF, 0xFE, 0x00, 0x01]);
+        data[0xC..0xE].copy_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        let blocks = [
+            symb.as_ref().map(|symb| (symb_offset, symb.len())),
+            Some((info_offset, info.len())),
+            Some((fat_offset, fat.len())),
+            Some((file_offset, file_block.len())),
+        ];
+        let block_count = blocks.iter().flatten().count() as u16;
+        data[0xE..0x10].copy_from_slice(&block_count.to_le_bytes());
+        for (index, block) in blocks.iter().enumerate() {
+            let (offset, size) = block.unwrap_or((0, 0));
+            set_u32(&mut data, 0x10 + index * 8, offset);
+            set_u32(&mut data, 0x14 + index * 8, size);
+        }
+        if let Some(symb) = symb {
+            data.extend(symb);
+        }
+        data.extend(info);
+        data.extend(fat);
+        data.resize(file_offset, 0);
+        data.extend(file_block);
+        let size = data.len();
+        set_u32(&mut data, 8, size);
+        Ok(data)
+    }
+}
+
+/// Builds a sound archive out of the files in `dir` and the records of its SDAT manifest, as
+/// extracted by [`unpack::write_sounds`](crate::unpack::write_sounds). Files that shared a
+/// file ID keep sharing it while their data is the same; the others get new IDs.
+pub fn from_dir(dir: &Path) -> anyhow::Result<Sdat> {
+    let manifest = SdatManifest::load(dir)?.ok_or_else(|| {
+        anyhow!("{dir:?} has no {SDAT_MANIFEST_FILE_NAME} describing its records")
+    })?;
+    let mut file_ids = BTreeMap::<u32, Vec<u8>>::new();
+    let mut next_file_id = manifest
+        .files
+        .iter()
+        .map(|record| record.file_id + 1)
+        .max()
+        .unwrap_or(0);
+    let mut files = Vec::with_capacity(manifest.files.len());
+    for record in manifest.files {
+        let SdatFileRecord {
+            kind,
+            index,
+            name,
+            path,
+            mut file_id,
+            wave_archives,
+            sequence_names,
+            info,
+        } = record;
+        let data = fs::read(dir.join(&path)).with_context(|| format!("failed to read {path:?}"))?;
+        match file_ids.get(&file_id) {
+            Some(shared) if *shared != data => {
+                file_id = next_file_id;
+                next_file_id += 1;
+            }
+            Some(_) => {}
+            None => {
+                file_ids.insert(file_id, data.clone());
+            }
+        }
+        files.push(SoundFile {
+            kind,
+            index,
+            name,
+            file_id,
+            wave_archives,
+            sequence_names,
+            info,
+            data,
+        });
+    }
+    let records = manifest
+        .records
+        .into_iter()
+        .map(|record| InfoRecord {
+            kind: record.kind,
+            index: record.index,
+            name: record.name,
+            data: record.info,
+        })
+        .collect();
+    Ok(Sdat {
+        named: manifest.named,
+        files,
+        records,
+    })
+}
+
+/// Describes the files of a sound archive extracted to the paths given, with the records of
+/// its SYMB and INFO blocks.
+pub fn manifest(sdat: &Sdat, path: impl Fn(&SoundFile) -> String) -> SdatManifest {
+    let mut manifest = SdatManifest::new(sdat.named);
+    manifest.files = sdat
+        .files
+        .iter()
+        .map(|file| SdatFileRecord {
+            kind: file.kind,
+            index: file.index,
+            name: file.name.clone(),
+            path: path(file),
+            file_id: file.file_id,
+            wave_archives: file.wave_archives.clone(),
+            sequence_names: file.sequence_names.clone(),
+            info: file.info.clone(),
+        })
+        .collect();
+    manifest.records = sdat
+        .records
+        .iter()
+        .map(|record| SdatInfoRecord {
+            kind: record.kind,
+            index: record.index,
+            name: record.name.clone(),
+            info: record.data.clone(),
+        })
+        .collect();
+    manifest
+}
+
+/// A record of the SYMB and INFO blocks, as [`Sdat::to_bytes`] writes it.
+#[derive(Debug, Clone)]
+struct ListedRecord {
+    name: Option<String>,
+    /// For sequence archives, the names of their sequences.
+    sequence_names: Option<Vec<Option<String>>>,
+    info: Vec<u8>,
+}
+
+/// Fills the size field of a SYMB or INFO block, padding it to 4 bytes.
+fn finish_block(mut block: Vec<u8>) -> Vec<u8> {
+    block.resize(rom::align_up(block.len(), 4), 0);
+    let size = block.len();
+    set_u32(&mut block, 4, size);
+    block
+}
+
+fn push_u32(block: &mut Vec<u8>, value: usize) {
+    block.extend((value as u32).to_le_bytes());
+}
+
+fn set_u32(block: &mut [u8], offset: usize, value: usize) {
+    block[offset..offset + 4].copy_from_slice(&(value as u32).to_le_bytes());
+}
+
+/// Sets the offset at `offset` of a block to point to its current end.
+fn point_to_end(block: &mut [u8], offset: usize) {
+    let end = block.len();
+    set_u32(block, offset, end);
+}
+
+/// Builds a SYMB block naming the records given, with the names after every list.
+fn symb_block(lists: &[Vec<Option<ListedRecord>>]) -> Vec<u8> {
+    let mut block = vec![0; RECORD_BLOCK_HEADER_SIZE];
+    block[..4].copy_from_slice(SYMB_MAGIC);
+    // Offsets of the block to point to each name, once the names are placed.
+    let mut names = Vec::<(usize, &str)>::new();
+    for (list_index, list) in lists.iter().enumerate() {
+        point_to_end(&mut block, 0x8 + list_index * 4);
+        push_u32(&mut block, list.len());
+        let is_sequence_archives = list_index == SoundKind::SequenceArchive.record_index();
+        let entries = block.len();
+        let stride = if is_sequence_archives { 8 } else { 4 };
+        block.resize(entries + list.len() * stride, 0);
+        for (index, record) in list.iter().enumerate() {
+            let Some(record) = record else {
+                continue;
+            };
+            if let Some(name) = &record.name {
+                names.push((entries + index * stride, name));
+            }
+            if let Some(sequence_names) = record
+                .sequence_names
+                .as_ref()
+                .filter(|_| is_sequence_archives)
+            {
+                point_to_end(&mut block, entries + index * stride + 4);
+                push_u32(&mut block, sequence_names.len());
+                let sequence_entries = block.len();
+                block.resize(sequence_entries + sequence_names.len() * 4, 0);
+                for (sequence, name) in sequence_names.iter().enumerate() {
+                    if let Some(name) = name {
+                        names.push((sequence_entries + sequence * 4, name));
+                    }
+                }
+            }
+        }
+    }
+    for (entry, name) in names {
+        point_to_end(&mut block, entry);
+        block.extend(name.as_bytes());
+        block.push(0);
+    }
+    finish_block(block)
+}
+
+/// Builds an INFO block holding the records given, each list being followed by its records.
+fn info_block(lists: &[Vec<Option<ListedRecord>>]) -> Vec<u8> {
+    let mut block = vec![0; RECORD_BLOCK_HEADER_SIZE];
+    block[..4].copy_from_slice(INFO_MAGIC);
+    for (list_index, list) in lists.iter().enumerate() {
+        point_to_end(&mut block, 0x8 + list_index * 4);
+        push_u32(&mut block, list.len());
+        let entries = block.len();
+        block.resize(entries + list.len() * 4, 0);
+        for (index, record) in list.iter().enumerate() {
+            let Some(record) = record else {
+                continue;
+            };
+            point_to_end(&mut block, entries + index * 4);
+            block.extend(&record.info);
+            block.resize(rom::align_up(block.len(), 4), 0);
+        }
+    }
+    finish_block(block)
+}
@@ -0,0 +1,119 @@
+use std::collections::HashSet;
+
+use clap::ValueEnum;
+use rayon::prelude::*;
+
+use crate::{lz10::decompress_lz10, lz11::decompress_lz11, rom, tree::Compression};
+
+/// Encodings text is searched for in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SearchEncoding {
+    Utf16le,
+    ShiftJis,
+    Ascii,
+}
+
+impl SearchEncoding {
+    pub const ALL: [SearchEncoding; 3] = [
+        SearchEncoding::Utf16le,
+        SearchEncoding::ShiftJis,
+        SearchEncoding::Ascii,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SearchEncoding::Utf16le => "UTF-16LE",
+            SearchEncoding::ShiftJis => "Shift-JIS",
+            SearchEncoding::Ascii => "ASCII",
+        }
+    }
+
+    /// Encodes text as it would be stored in this encoding, or `None` if it can't be.
+    fn encode(self, text: &str) -> Option<Vec<u8>> {
+        match self {
+            SearchEncoding::Utf16le => {
+                Some(text.encode_utf16().flat_map(u16::to_le_bytes).collect())
+            }
+            // ASCII text is the same in Shift-JIS, so it's only reported once.
+            SearchEncoding::ShiftJis if text.is_ascii() => None,
+            SearchEncoding::ShiftJis => {
+                let (bytes, _, had_errors) = encoding_rs::SHIFT_JIS.encode(text);
+                (!had_errors).then(|| bytes.into_owned())
+            }
+            SearchEncoding::Ascii => text.is_ascii().then(|| text.as_bytes().to_vec()),
+        }
+    }
+}
+
+/// An occurrence of the text searched for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hit {
+    pub path: String,
+    /// Compression of the file, in which case the offset is into the decompressed data.
+    pub compression: Option<Compression>,
+    pub encoding: SearchEncoding,
+    pub offset: usize,
+}
+
+fn find_all<'a>(haystack: &'a [u8], needle: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+    haystack
+        .windows(needle.len())
+        .enumerate()
+        .filter(move |(_, window)| *window == needle)
+        .map(|(offset, _)| offset)
+}
+
+/// Searches every file of the NitroFS of a ROM for text in the encodings given, decompressing
+/// LZ10- and LZ11-compressed files first. Hits are sorted by path and offset.
+pub fn search_rom(
+    rom_data: &[u8],
+    text: &str,
+    encodings: &[SearchEncoding],
+) -> anyhow::Result<Vec<Hit>> {
+    let needles = encodings
+        .iter()
+        .filter_map(|&encoding| Some((encoding, encoding.encode(text)?)))
+        .filter(|(_, needle)| !needle.is_empty())
+        .collect::<Vec<_>>();
+    let fs = rom::filesystem(rom_data)?;
+    let mut hits = fs
+        .files()
+        .par_iter()
+        .flat_map_iter(|entry| {
+            let path = rom::nitro_path(&entry.path);
+            let data = rom::file_data(rom_data, entry);
+            let (compression, data) = match decompress_lz10(data) {
+                Ok(decompressed) => (Some(Compression::Lz10), decompressed),
+                Err(_) => match decompress_lz11(data) {
+                    Ok(decompressed) => (Some(Compression::Lz11), decompressed),
+                    Err(_) => (None, data.to_vec()),
+                },
+            };
+            let mut hits = needles
+                .iter()
+                .flat_map(|(encoding, needle)| {
+                    find_all(&data, needle)
+                        .map(|offset| Hit {
+                            path: path.clone(),
+                            compression,
+                            encoding: *encoding,
+                            offset,
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            // Text with a single character also matches the first byte of its UTF-16 encoding.
+            let utf16_offsets = hits
+                .iter()
+                .filter(|hit| hit.encoding == SearchEncoding::Utf16le)
+                .map(|hit| hit.offset)
+                .collect::<HashSet<_>>();
+            hits.retain(|hit| {
+                hit.encoding == SearchEncoding::Utf16le || !utf16_offsets.contains(&hit.offset)
+            });
+            hits
+        })
+        .collect::<Vec<_>>();
+    hits.sort_by(|a, b| (&a.path, a.offset).cmp(&(&b.path, b.offset)));
+    Ok(hits)
+}
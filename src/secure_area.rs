@@ -0,0 +1,383 @@
+use clap::ValueEnum;
+use thiserror::Error;
+
+use crate::rom;
+
+/// Offset of the secure area in the ROM, which is also where the ARM9 binary of retail ROMs
+/// starts.
+const SECURE_AREA_OFFSET: usize = 0x4000;
+/// Size of the secure area, covered by the secure area checksum.
+const SECURE_AREA_SIZE: usize = 0x4000;
+/// Size of the KEY1-encrypted part of the secure area.
+const ENCRYPTED_SIZE: usize = 0x800;
+/// Offset of the secure area checksum field in the ROM header.
+const SECURE_AREA_CRC_OFFSET: usize = 0x6C;
+/// The ID the secure area starts with once decrypted.
+const SECURE_AREA_ID: &[u8; 8] = b"encryObj";
+/// What the ID is replaced with once checked by the console, and by decrypted dumps.
+const DESTROYED_ID: [u8; 8] = [0xFF, 0xDE, 0xFF, 0xE7, 0xFF, 0xDE, 0xFF, 0xE7];
+/// Size of the Blowfish key table, in 32-bit words.
+const KEY_TABLE_LEN: usize = 0x412;
+/// Size of the ARM7 BIOS.
+const ARM7_BIOS_SIZE: usize = 0x4000;
+/// Offset of the key table in the ARM7 BIOS.
+const ARM7_BIOS_KEY_TABLE_OFFSET: usize = 0x30;
+
+#[derive(Error, Debug)]
+pub enum SecureAreaError {
+    #[error("invalid key table size (found: 0x{found:X} bytes, expected: 0x1048 bytes or a 0x4000-byte ARM7 BIOS)")]
+    InvalidKeyTableSize { found: usize },
+    #[error("the ROM has no secure area")]
+    Missing,
+    #[error("the secure area is already encrypted")]
+    AlreadyEncrypted,
+    #[error("the secure area is not encrypted")]
+    NotEncrypted,
+    #[error("the secure area did not decrypt to a valid ID; the key table may be wrong")]
+    InvalidId,
+    #[error("the secure area is {state}; the key table of an ARM7 BIOS dump is needed to make it {form}")]
+    KeyTableNeeded {
+        state: &'static str,
+        form: &'static str,
+    },
+}
+
+/// The Blowfish key table used for KEY1 encryption, found in the ARM7 BIOS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyTable(Vec<u32>);
+
+impl KeyTable {
+    /// Reads the key table from a dump of the ARM7 BIOS, or from the table alone.
+    pub fn parse(data: &[u8]) -> Result<Self, SecureAreaError> {
+        let table = match data.len() {
+            ARM7_BIOS_SIZE => {
+                &data[ARM7_BIOS_KEY_TABLE_OFFSET..ARM7_BIOS_KEY_TABLE_OFFSET + KEY_TABLE_LEN * 4]
+            }
+            len if len == KEY_TABLE_LEN * 4 => data,
+            found => return Err(SecureAreaError::InvalidKeyTableSize { found }),
+        };
+        Ok(KeyTable(
+            table
+                .chunks_exact(4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect(),
+        ))
+    }
+}
+
+/// KEY1 encryption state, made from the key table and a game code.
+struct Key1 {
+    keys: Vec<u32>,
+    key_code: [u32; 3],
+}
+
+impl Key1 {
+    /// Sets up the keys for a game code, applying the key code to them `level` times.
+    fn new(table: &KeyTable, game_code: u32, level: u32, modulo: usize) -> Self {
+        let mut key1 = Key1 {
+            keys: table.0.clone(),
+            key_code: [game_code, game_code / 2, game_code.wrapping_mul(2)],
+        };
+        if level >= 1 {
+            key1.apply_key_code(modulo);
+        }
+        if level >= 2 {
+            key1.apply_key_code(modulo);
+        }
+        key1.key_code[1] = key1.key_code[1].wrapping_mul(2);
+        key1.key_code[2] /= 2;
+        if level >= 3 {
+            key1.apply_key_code(modulo);
+        }
+        key1
+    }
+
+    fn apply_key_code(&mut self, modulo: usize) {
+        let [a, b, c] = self.key_code;
+        let (b, c) = self.encrypt_pair(b, c);
+        let (a, b) = self.encrypt_pair(a, b);
+        self.key_code = [a, b, c];
+        let words = modulo / 4;
+        for (index, key) in self.keys[..0x12].iter_mut().enumerate() {
+            *key ^= self.key_code[index % words].swap_bytes();
+        }
+        let mut scratch = (0, 0);
+        for index in (0..KEY_TABLE_LEN).step_by(2) {
+            scratch = self.encrypt_pair(scratch.0, scratch.1);
+            self.keys[index] = scratch.1;
+            self.keys[index + 1] = scratch.0;
+        }
+    }
+
+    fn round(&self, z: u32) -> u32 {
+        let [b0, b1, b2, b3] = z.to_be_bytes().map(usize::from);
+        let x = self.keys[0x12 + b0].wrapping_add(self.keys[0x112 + b1]);
+        (x ^ self.keys[0x212 + b2]).wrapping_add(self.keys[0x312 + b3])
+    }
+
+    fn encrypt_pair(&self, mut y: u32, mut x: u32) -> (u32, u32) {
+        for index in 0..0x10 {
+            let z = self.keys[index] ^ x;
+            x = y ^ self.round(z);
+            y = z;
+        }
+        (x ^ self.keys[0x10], y ^ self.keys[0x11])
+    }
+
+    fn decrypt_pair(&self, mut y: u32, mut x: u32) -> (u32, u32) {
+        for index in (0x2..0x12).rev() {
+            let z = self.keys[index] ^ x;
+            x = y ^ self.round(z);
+            y = z;
+        }
+        (x ^ self.keys[0x1], y ^ self.keys[0x0])
+    }
+
+    fn encrypt(&self, data: &mut [u8]) {
+        self.transform(data, Key1::encrypt_pair);
+    }
+
+    fn decrypt(&self, data: &mut [u8]) {
+        self.transform(data, Key1::decrypt_pair);
+    }
+
+    fn transform(&self, data: &mut [u8], transform: fn(&Self, u32, u32) -> (u32, u32)) {
+        for block in data.chunks_exact_mut(8) {
+            let y = u32::from_le_bytes(block[..4].try_into().unwrap());
+            let x = u32::from_le_bytes(block[4..].try_into().unwrap());
+            let (y, x) = transform(self, y, x);
+            block[..4].copy_from_slice(&y.to_le_bytes());
+            block[4..].copy_from_slice(&x.to_le_bytes());
+        }
+    }
+}
+
+/// Whether the secure area of a ROM is KEY1-encrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecureAreaState {
+    /// The ROM has no secure area, as is the case for homebrew.
+    Missing,
+    Encrypted,
+    Decrypted,
+}
+
+/// Returns the part of the ROM holding the secure area, if it has one.
+fn secure_area(rom_data: &[u8]) -> Option<&[u8]> {
+    let arm9_offset = rom::u32_at(rom_data, rom::ARM9_ADDR_OFFSET) as usize;
+    (arm9_offset == SECURE_AREA_OFFSET)
+        .then(|| rom_data.get(SECURE_AREA_OFFSET..SECURE_AREA_OFFSET + ENCRYPTED_SIZE))
+        .flatten()
+        .filter(|area| area.iter().any(|&byte| byte != 0))
+}
+
+impl SecureAreaState {
+    pub fn name(self) -> &'static str {
+        match self {
+            SecureAreaState::Missing => "missing",
+            SecureAreaState::Encrypted => "encrypted",
+            SecureAreaState::Decrypted => "decrypted",
+        }
+    }
+}
+
+/// The state to bring the secure area of ROMs to, so that dumps made by different tools can be
+/// compared and patched alike.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SecureAreaForm {
+    /// Decrypted, with the ID destroyed as the console leaves it
+    Decrypted,
+    /// Encrypted, as on cartridges
+    Encrypted,
+}
+
+impl SecureAreaForm {
+    pub fn name(self) -> &'static str {
+        match self {
+            SecureAreaForm::Decrypted => "decrypted",
+            SecureAreaForm::Encrypted => "encrypted",
+        }
+    }
+}
+
+pub fn state(rom_data: &[u8]) -> SecureAreaState {
+    match secure_area(rom_data) {
+        None => SecureAreaState::Missing,
+        Some(area) if area[..8] == DESTROYED_ID || area[..8] == *SECURE_AREA_ID => {
+            SecureAreaState::Decrypted
+        }
+        Some(_) => SecureAreaState::Encrypted,
+    }
+}
+
+/// Part of the ROM holding the KEY1-encrypted part of the secure area, if it's encrypted.
+pub fn encrypted_range(rom_data: &[u8]) -> Option<std::ops::Range<usize>> {
+    (state(rom_data) == SecureAreaState::Encrypted)
+        .then_some(SECURE_AREA_OFFSET..SECURE_AREA_OFFSET + ENCRYPTED_SIZE)
+}
+
+fn game_code(rom_data: &[u8]) -> u32 {
+    rom::u32_at(rom_data, rom::GAME_CODE_RANGE.start)
+}
+
+/// Decrypts the secure area of a ROM in place, as decrypted dumps have it.
+pub fn decrypt(rom_data: &mut [u8], table: &KeyTable) -> Result<(), SecureAreaError> {
+    match state(rom_data) {
+        SecureAreaState::Missing => return Err(SecureAreaError::Missing),
+        SecureAreaState::Decrypted => return Err(SecureAreaError::NotEncrypted),
+        SecureAreaState::Encrypted => {}
+    }
+    let game_code = game_code(rom_data);
+    let area = &mut rom_data[SECURE_AREA_OFFSET..SECURE_AREA_OFFSET + ENCRYPTED_SIZE];
+    // The ID is encrypted twice: along with the rest of the area, and then on its own.
+    Key1::new(table, game_code, 2, 8).decrypt(&mut area[..8]);
+    Key1::new(table, game_code, 3, 8).decrypt(area);
+    if area[..8] != *SECURE_AREA_ID {
+        return Err(SecureAreaError::InvalidId);
+    }
+    area[..8].copy_from_slice(&DESTROYED_ID);
+    Ok(())
+}
+
+/// Brings the secure area of a ROM to the form given, returning whether it changed. ROMs without
+/// one are left as they are.
+///
+/// The key table is only needed to encrypt or decrypt the secure area: decrypted secure areas
+/// whose `encryObj` ID is intact only have it replaced with the destroyed one, as most dumps
+/// have it.
+pub fn normalize(
+    rom_data: &mut [u8],
+    form: SecureAreaForm,
+    table: Option<&KeyTable>,
+) -> Result<bool, SecureAreaError> {
+    let state = state(rom_data);
+    let table = table.ok_or(SecureAreaError::KeyTableNeeded {
+        state: state.name(),
+        form: form.name(),
+    });
+    match (state, form) {
+        (SecureAreaState::Missing, _) => Ok(false),
+        (SecureAreaState::Encrypted, SecureAreaForm::Encrypted) => Ok(false),
+        (SecureAreaState::Encrypted, SecureAreaForm::Decrypted) => {
+            decrypt(rom_data, table?)?;
+            Ok(true)
+        }
+        (SecureAreaState::Decrypted, SecureAreaForm::Encrypted) => {
+            encrypt(rom_data, table?)?;
+            Ok(true)
+        }
+        (SecureAreaState::Decrypted, SecureAreaForm::Decrypted) => {
+            let id = &mut rom_data[SECURE_AREA_OFFSET..SECURE_AREA_OFFSET + 8];
+            if *id == DESTROYED_ID {
+                return Ok(false);
+            }
+            id.copy_from_slice(&DESTROYED_ID);
+            Ok(true)
+        }
+    }
+}
+
+/// Encrypts the secure area of a ROM in place, as the console expects it, and updates the secure
+/// area checksum to match.
+pub fn encrypt(rom_data: &mut [u8], table: &KeyTable) -> Result<(), SecureAreaError> {
+    match state(rom_data) {
+        SecureAreaState::Missing => return Err(SecureAreaError::Missing),
+        SecureAreaState::Encrypted => return Err(SecureAreaError::AlreadyEncrypted),
+        SecureAreaState::Decrypted => {}
+    }
+    let game_code = game_code(rom_data);
+    let area = &mut rom_data[SECURE_AREA_OFFSET..SECURE_AREA_OFFSET + ENCRYPTED_SIZE];
+    area[..8].copy_from_slice(SECURE_AREA_ID);
+    Key1::new(table, game_code, 3, 8).encrypt(area);
+    Key1::new(table, game_code, 2, 8).encrypt(&mut area[..8]);
+
+    let area_end = (SECURE_AREA_OFFSET + SECURE_AREA_SIZE).min(rom_data.len());
+    let crc = rom::crc16(&rom_data[SECURE_AREA_OFFSET..area_end]);
+    rom_data[SECURE_AREA_CRC_OFFSET..SECURE_AREA_CRC_OFFSET + 2]
+        .copy_from_slice(&crc.to_le_bytes());
+    rom::fix_header_crc(rom_data);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_table(seed: u32) -> KeyTable {
+        let mut state = seed;
+        let data = (0..KEY_TABLE_LEN)
+            .flat_map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                state.to_le_bytes()
+            })
+            .collect::<Vec<_>>();
+        KeyTable::parse(&data).unwrap()
+    }
+
+    /// A ROM whose ARM9 binary starts with a decrypted secure area.
+    fn sample_rom() -> Vec<u8> {
+        let mut rom_data = vec![0; SECURE_AREA_OFFSET + SECURE_AREA_SIZE];
+        rom_data[rom::GAME_CODE_RANGE].copy_from_slice(b"ARVE");
+        rom_data[rom::ARM9_ADDR_OFFSET..rom::ARM9_ADDR_OFFSET + 4]
+            .copy_from_slice(&(SECURE_AREA_OFFSET as u32).to_le_bytes());
+        for (index, byte) in rom_data[SECURE_AREA_OFFSET..].iter_mut().enumerate() {
+            *byte = (index * 31 + 7) as u8;
+        }
+        rom_data[SECURE_AREA_OFFSET..SECURE_AREA_OFFSET + 8].copy_from_slice(&DESTROYED_ID);
+        rom::fix_header_crc(&mut rom_data);
+        rom_data
+    }
+
+    #[test]
+    fn round_trips() {
+        let table = sample_table(1);
+        let original = sample_rom();
+        assert_eq!(state(&original), SecureAreaState::Decrypted);
+
+        let mut rom_data = original.clone();
+        encrypt(&mut rom_data, &table).unwrap();
+        assert_eq!(state(&rom_data), SecureAreaState::Encrypted);
+        let area = SECURE_AREA_OFFSET..SECURE_AREA_OFFSET + ENCRYPTED_SIZE;
+        assert_ne!(rom_data[area.clone()], original[area.clone()]);
+        assert_eq!(rom_data[area.end..], original[area.end..]);
+        let crc_field = |rom_data: &[u8]| {
+            u16::from_le_bytes([
+                rom_data[SECURE_AREA_CRC_OFFSET],
+                rom_data[SECURE_AREA_CRC_OFFSET + 1],
+            ])
+        };
+        let crc = rom::crc16(&rom_data[SECURE_AREA_OFFSET..]);
+        assert_eq!(crc_field(&rom_data), crc);
+        assert_ne!(crc_field(&original), crc);
+        assert!(matches!(
+            encrypt(&mut rom_data, &table),
+            Err(SecureAreaError::AlreadyEncrypted)
+        ));
+
+        let encrypted = rom_data.clone();
+        assert!(matches!(
+            decrypt(&mut rom_data.clone(), &sample_table(2)),
+            Err(SecureAreaError::InvalidId)
+        ));
+        decrypt(&mut rom_data, &table).unwrap();
+        assert_eq!(state(&rom_data), SecureAreaState::Decrypted);
+        assert_eq!(rom_data[area.start..area.start + 8], DESTROYED_ID);
+        assert_eq!(rom_data[area.clone()], original[area.clone()]);
+
+        // Decrypted areas whose ID is intact encrypt the same.
+        rom_data[area.start..area.start + 8].copy_from_slice(SECURE_AREA_ID);
+        encrypt(&mut rom_data, &table).unwrap();
+        assert_eq!(rom_data, encrypted);
+    }
+
+    #[test]
+    fn requires_a_secure_area() {
+        let table = sample_table(1);
+        let mut rom_data = sample_rom();
+        rom_data[SECURE_AREA_OFFSET..].fill(0);
+        assert_eq!(state(&rom_data), SecureAreaState::Missing);
+        assert!(matches!(
+            encrypt(&mut rom_data, &table),
+            Err(SecureAreaError::Missing)
+        ));
+    }
+}
@@ -0,0 +1,243 @@
+/// Number of silent samples SoundFont requires after each sample.
+const SAMPLE_PADDING: usize = 46;
+/// Size of a name in the records of the pdta list.
+const NAME_SIZE: usize = 20;
+
+/// Generator operators, as numbered by the SoundFont 2.01 specification.
+const GEN_PAN: u16 = 17;
+const GEN_ATTACK_VOL_ENV: u16 = 34;
+const GEN_DECAY_VOL_ENV: u16 = 36;
+const GEN_SUSTAIN_VOL_ENV: u16 = 37;
+const GEN_RELEASE_VOL_ENV: u16 = 38;
+const GEN_INSTRUMENT: u16 = 41;
+const GEN_KEY_RANGE: u16 = 43;
+const GEN_SAMPLE_ID: u16 = 53;
+const GEN_SAMPLE_MODES: u16 = 54;
+const GEN_OVERRIDING_ROOT_KEY: u16 = 58;
+/// Sample type of mono samples.
+const MONO_SAMPLE: u16 = 1;
+
+/// A mono 16-bit sample.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    pub name: String,
+    pub samples: Vec<i16>,
+    pub sample_rate: u32,
+    /// Start and end of the looping part, in samples. The end is exclusive.
+    pub loop_points: Option<(usize, usize)>,
+}
+
+/// A volume envelope, in SoundFont units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Envelope {
+    /// Attack time, in timecents.
+    pub attack: i16,
+    /// Time to decay from full volume to silence, in timecents.
+    pub decay: i16,
+    /// Attenuation of the sustain level, in centibels.
+    pub sustain: i16,
+    /// Time to release from full volume to silence, in timecents.
+    pub release: i16,
+}
+
+/// A key range of an instrument, played with a sample.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Zone {
+    /// Lowest and highest MIDI keys of the range.
+    pub key_range: (u8, u8),
+    /// Index of the sample in [`SoundFont::samples`].
+    pub sample: usize,
+    /// Key at which the sample plays at its own sample rate.
+    pub root_key: u8,
+    /// Pan, from -500 (left) to 500 (right).
+    pub pan: i16,
+    pub envelope: Envelope,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instrument {
+    pub name: String,
+    pub zones: Vec<Zone>,
+}
+
+/// An instrument as MIDI files select it, with a bank and program number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preset {
+    pub name: String,
+    pub bank: u16,
+    pub program: u16,
+    /// Index of the instrument in [`SoundFont::instruments`].
+    pub instrument: usize,
+}
+
+/// A SoundFont 2 bank of instruments, as read by MIDI players & synthesizers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SoundFont {
+    pub name: String,
+    pub samples: Vec<Sample>,
+    pub instruments: Vec<Instrument>,
+    pub presets: Vec<Preset>,
+}
+
+/// Appends a RIFF chunk, padded to an even size.
+fn chunk(output: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    output.extend_from_slice(id);
+    output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    output.extend_from_slice(data);
+    if !data.len().is_multiple_of(2) {
+        output.push(0);
+    }
+}
+
+/// Appends a RIFF list of chunks.
+fn list(output: &mut Vec<u8>, list_type: &[u8; 4], chunks: &[u8]) {
+    let mut data = list_type.to_vec();
+    data.extend_from_slice(chunks);
+    chunk(output, b"LIST", &data);
+}
+
+/// Appends a name, truncated or padded with zeroes to the size of names in records.
+fn name(output: &mut Vec<u8>, name: &str) {
+    let mut bytes = name
+        .bytes()
+        .filter(u8::is_ascii)
+        .take(NAME_SIZE - 1)
+        .collect::<Vec<_>>();
+    bytes.resize(NAME_SIZE, 0);
+    output.extend_from_slice(&bytes);
+}
+
+/// Appends a null-terminated string of even size, as INFO chunks hold.
+fn info_string(text: &str) -> Vec<u8> {
+    let mut bytes = text.bytes().filter(u8::is_ascii).collect::<Vec<_>>();
+    bytes.push(0);
+    if !bytes.len().is_multiple_of(2) {
+        bytes.push(0);
+    }
+    bytes
+}
+
+fn generator(output: &mut Vec<u8>, operator: u16, amount: u16) {
+    output.extend_from_slice(&operator.to_le_bytes());
+    output.extend_from_slice(&amount.to_le_bytes());
+}
+
+/// Appends a bag record, the index of its first generator followed by its first modulator.
+fn bag(output: &mut Vec<u8>, generator_index: usize) {
+    output.extend_from_slice(&(generator_index as u16).to_le_bytes());
+    output.extend_from_slice(&0u16.to_le_bytes());
+}
+
+impl SoundFont {
+    /// Encodes the bank as an SF2 file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut info = Vec::new();
+        chunk(&mut info, b"ifil", &[2, 0, 1, 0]);
+        chunk(&mut info, b"isng", &info_string("EMU8000"));
+        chunk(&mut info, b"INAM", &info_string(&self.name));
+        chunk(&mut info, b"ISFT", &info_string("ravends"));
+
+        let mut smpl = Vec::new();
+        let mut shdr = Vec::new();
+        let mut start = 0;
+        for sample in &self.samples {
+            for value in &sample.samples {
+                smpl.extend_from_slice(&value.to_le_bytes());
+            }
+            smpl.resize(smpl.len() + SAMPLE_PADDING * 2, 0);
+            let end = start + sample.samples.len();
+            let (loop_start, loop_end) = sample
+                .loop_points
+                .map_or((start, end), |(loop_start, loop_end)| {
+                    (start + loop_start, start + loop_end)
+                });
+            name(&mut shdr, &sample.name);
+            for value in [start, end, loop_start, loop_end] {
+                shdr.extend_from_slice(&(value as u32).to_le_bytes());
+            }
+            shdr.extend_from_slice(&sample.sample_rate.to_le_bytes());
+            // The original pitch is overridden by each zone's root key.
+            shdr.extend_from_slice(&[60, 0]);
+            shdr.extend_from_slice(&0u16.to_le_bytes());
+            shdr.extend_from_slice(&MONO_SAMPLE.to_le_bytes());
+            start = end + SAMPLE_PADDING;
+        }
+        name(&mut shdr, "EOS");
+        shdr.resize(shdr.len() + 26, 0);
+        let mut sdta = Vec::new();
+        chunk(&mut sdta, b"smpl", &smpl);
+
+        let (mut inst, mut ibag, mut igen) = (Vec::new(), Vec::new(), Vec::new());
+        let mut bag_count = 0;
+        let mut generator_count = 0;
+        for instrument in &self.instruments {
+            name(&mut inst, &instrument.name);
+            inst.extend_from_slice(&(bag_count as u16).to_le_bytes());
+            for zone in &instrument.zones {
+                bag(&mut ibag, generator_count);
+                bag_count += 1;
+                let envelope = zone.envelope;
+                let loops = self.samples[zone.sample].loop_points.is_some();
+                let generators = [
+                    (
+                        GEN_KEY_RANGE,
+                        u16::from_le_bytes([zone.key_range.0, zone.key_range.1]),
+                    ),
+                    (GEN_PAN, zone.pan as u16),
+                    (GEN_ATTACK_VOL_ENV, envelope.attack as u16),
+                    (GEN_DECAY_VOL_ENV, envelope.decay as u16),
+                    (GEN_SUSTAIN_VOL_ENV, envelope.sustain as u16),
+                    (GEN_RELEASE_VOL_ENV, envelope.release as u16),
+                    (GEN_OVERRIDING_ROOT_KEY, zone.root_key as u16),
+                    (GEN_SAMPLE_MODES, loops as u16),
+                    // The sample must come last.
+                    (GEN_SAMPLE_ID, zone.sample as u16),
+                ];
+                for (operator, amount) in generators {
+                    generator(&mut igen, operator, amount);
+                }
+                generator_count += generators.len();
+            }
+        }
+        name(&mut inst, "EOI");
+        inst.extend_from_slice(&(bag_count as u16).to_le_bytes());
+        bag(&mut ibag, generator_count);
+        generator(&mut igen, 0, 0);
+
+        let (mut phdr, mut pbag, mut pgen) = (Vec::new(), Vec::new(), Vec::new());
+        for (index, preset) in self.presets.iter().enumerate() {
+            name(&mut phdr, &preset.name);
+            phdr.extend_from_slice(&preset.program.to_le_bytes());
+            phdr.extend_from_slice(&preset.bank.to_le_bytes());
+            phdr.extend_from_slice(&(index as u16).to_le_bytes());
+            phdr.resize(phdr.len() + 12, 0);
+            bag(&mut pbag, index);
+            generator(&mut pgen, GEN_INSTRUMENT, preset.instrument as u16);
+        }
+        name(&mut phdr, "EOP");
+        phdr.extend_from_slice(&[0; 4]);
+        phdr.extend_from_slice(&(self.presets.len() as u16).to_le_bytes());
+        phdr.resize(phdr.len() + 12, 0);
+        bag(&mut pbag, self.presets.len());
+        generator(&mut pgen, 0, 0);
+
+        let mut pdta = Vec::new();
+        chunk(&mut pdta, b"phdr", &phdr);
+        chunk(&mut pdta, b"pbag", &pbag);
+        chunk(&mut pdta, b"pmod", &[0; 10]);
+        chunk(&mut pdta, b"pgen", &pgen);
+        chunk(&mut pdta, b"inst", &inst);
+        chunk(&mut pdta, b"ibag", &ibag);
+        chunk(&mut pdta, b"imod", &[0; 10]);
+        chunk(&mut pdta, b"igen", &igen);
+        chunk(&mut pdta, b"shdr", &shdr);
+
+        let mut body = b"sfbk".to_vec();
+        list(&mut body, b"INFO", &info);
+        list(&mut body, b"sdta", &sdta);
+        list(&mut body, b"pdta", &pdta);
+        let mut output = Vec::with_capacity(body.len() + 8);
+        chunk(&mut output, b"RIFF", &body);
+        output
+    }
+}
@@ -0,0 +1,293 @@
+use std::{
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+    path::Path,
+    sync::Mutex,
+};
+
+use anyhow::Context;
+
+use crate::rom::{self, RomParseError};
+
+/// Where ROM data is read from. Data is read at the offsets needed rather than all at once, so
+/// that commands reading a few tables and files of a large ROM don't load all of it.
+pub trait RomSource {
+    /// Size of the ROM in bytes.
+    fn len(&self) -> u64;
+
+    /// Fills `buf` with the bytes at `offset`, failing if they run past the end of the ROM.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()>;
+
+    /// The whole ROM, if the source holds it in memory and it can be borrowed without reading it.
+    fn as_bytes(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads the bytes in the range given.
+    fn read_range(&self, range: Range<u64>) -> io::Result<Vec<u8>> {
+        let len = range.end.checked_sub(range.start).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "range ends before it starts")
+        })?;
+        let mut buf = vec![0; len as usize];
+        self.read_at(range.start, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Reads the whole ROM.
+    fn read_all(&self) -> io::Result<Vec<u8>> {
+        match self.as_bytes() {
+            Some(bytes) => Ok(bytes.to_vec()),
+            None => self.read_range(0..self.len()),
+        }
+    }
+}
+
+fn past_end() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "read past the end of the ROM")
+}
+
+impl RomSource for [u8] {
+    fn len(&self) -> u64 {
+        <[u8]>::len(self) as u64
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let start = usize::try_from(offset).map_err(|_| past_end())?;
+        let data = start
+            .checked_add(buf.len())
+            .and_then(|end| self.get(start..end))
+            .ok_or_else(past_end)?;
+        buf.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        Some(self)
+    }
+}
+
+impl RomSource for Vec<u8> {
+    fn len(&self) -> u64 {
+        self.as_slice().len() as u64
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.as_slice().read_at(offset, buf)
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        Some(self)
+    }
+}
+
+impl<S: RomSource + ?Sized> RomSource for Box<S> {
+    fn len(&self) -> u64 {
+        (**self).len()
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        (**self).read_at(offset, buf)
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        (**self).as_bytes()
+    }
+}
+
+/// A ROM read from a seekable reader, such as a file, starting at an offset of it.
+#[derive(Debug)]
+pub struct SeekSource<R> {
+    reader: Mutex<R>,
+    start: u64,
+    len: u64,
+}
+
+impl<R: Read + Seek> SeekSource<R> {
+    /// Reads the ROM from all of the reader given.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        Ok(Self::with_range(reader, 0..len))
+    }
+
+    /// Reads the ROM from the range of the reader given, such as a member stored uncompressed
+    /// in an archive.
+    pub fn with_range(reader: R, range: Range<u64>) -> Self {
+        Self {
+            reader: Mutex::new(reader),
+            start: range.start,
+            len: range.end.saturating_sub(range.start),
+        }
+    }
+}
+
+impl<R: Read + Seek> RomSource for SeekSource<R> {
+    fn len(&self) -> u64 {
+        self.len
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        if offset
+            .checked_add(buf.len() as u64)
+            .is_none_or(|end| end > self.len)
+        {
+            return Err(past_end());
+        }
+        let mut reader = self
+            .reader
+            .lock()
+            .map_err(|_| io::Error::other("ROM reader poisoned"))?;
+        reader.seek(SeekFrom::Start(self.start + offset))?;
+        reader.read_exact(buf)
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl RomSource for memmap2::Mmap {
+    fn len(&self) -> u64 {
+        <[u8]>::len(self) as u64
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        (**self).read_at(offset, buf)
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        Some(self)
+    }
+}
+
+/// Opens a ROM file, mapping it into memory where possible.
+fn open_file(path: &Path) -> anyhow::Result<Box<dyn RomSource + Send + Sync>> {
+    let file = fs::File::open(path).context("failed to open ROM file")?;
+    #[cfg(not(target_family = "wasm"))]
+    {
+        // SAFETY: the map is only read from. Another program truncating the file while it's
+        // mapped makes reads fault, as with any tool mapping files it reads.
+        if let Ok(map) = unsafe { memmap2::Mmap::map(&file) } {
+            return Ok(Box::new(map));
+        }
+    }
+    Ok(Box::new(
+        SeekSource::new(file).context("failed to read ROM file")?,
+    ))
+}
+
+/// Opens a ROM inside a zip archive. Members stored uncompressed are read from the archive
+/// directly; compressed ones are decompressed into memory, as they can't be read at an offset.
+fn open_zip_member(
+    path: &Path,
+    member: Option<&str>,
+) -> anyhow::Result<Box<dyn RomSource + Send + Sync>> {
+    let file = fs::File::open(path).context("failed to open ROM archive")?;
+    let mut archive =
+        zip::ZipArchive::new(io::BufReader::new(file)).context("failed to read ROM archive")?;
+    let name = rom::zip_member_name(&archive, member)
+        .with_context(|| format!("failed to find the ROM inside {path:?}"))?;
+    let mut entry = archive
+        .by_name(&name)
+        .context("failed to open ROM inside archive")?;
+    if entry.compression() == zip::CompressionMethod::Stored && !entry.encrypted() {
+        let start = entry
+            .data_start()
+            .context("failed to locate ROM inside archive")?;
+        let range = start..start + entry.size();
+        drop(entry);
+        return Ok(Box::new(SeekSource::with_range(
+            archive.into_inner(),
+            range,
+        )));
+    }
+    let mut rom_data = Vec::new();
+    entry
+        .read_to_end(&mut rom_data)
+        .context("failed to extract ROM from archive")?;
+    Ok(Box::new(rom_data))
+}
+
+/// Opens a ROM to read it lazily, checking that it's large enough to hold a header. If the file
+/// is a zip archive, the ROM read is its member named `member`, or if none is given, the only
+/// ROM inside it, as with [`rom::read_rom_member`].
+pub fn open(path: &Path, member: Option<&str>) -> anyhow::Result<Box<dyn RomSource + Send + Sync>> {
+    let source = if rom::is_zip(path) {
+        open_zip_member(path, member)?
+    } else {
+        open_file(path)?
+    };
+    if source.len() < rom::HEADER_SIZE as u64 {
+        return Err(RomParseError::TooSmall {
+            size: source.len() as usize,
+        }
+        .into());
+    }
+    Ok(source)
+}
+
+/// Reads the header of a ROM, along with the extended header of DSi ROMs when the ROM is large
+/// enough to hold it, so that the locations of the DSi sections can be read too.
+pub fn header(source: &(impl RomSource + ?Sized)) -> io::Result<Vec<u8>> {
+    source.read_range(0..source.len().min(rom::DSI_HEADER_SIZE as u64))
+}
+
+/// Reads a table of the ROM located through the header fields holding its address & size.
+fn table(
+    source: &(impl RomSource + ?Sized),
+    header: &[u8],
+    table: &'static str,
+    addr_field: usize,
+    size_field: usize,
+) -> anyhow::Result<Vec<u8>> {
+    let offset = rom::u32_at(header, addr_field) as u64;
+    let size = rom::u32_at(header, size_field) as u64;
+    if offset + size > source.len() {
+        return Err(RomParseError::TableOutOfBounds {
+            table,
+            offset: offset as usize,
+            size: size as usize,
+            rom_size: source.len() as usize,
+        }
+        .into());
+    }
+    Ok(source.read_range(offset..offset + size)?)
+}
+
+/// Parses the NitroFS of a ROM like [`rom::filesystem`], reading only its header, FNT and FAT.
+pub fn filesystem(source: &(impl RomSource + ?Sized)) -> anyhow::Result<nitro_fs::FileSystem> {
+    if let Some(rom_data) = source.as_bytes() {
+        return Ok(rom::filesystem(rom_data)?);
+    }
+    let header = header(source)?;
+    if !rom::has_filesystem(&header) {
+        return Ok(nitro_fs::FileSystem::default());
+    }
+    let fnt = table(
+        source,
+        &header,
+        "FNT",
+        rom::FNT_ADDR_OFFSET,
+        rom::FNT_SIZE_OFFSET,
+    )?;
+    let fat = table(
+        source,
+        &header,
+        "FAT",
+        rom::FAT_ADDR_OFFSET,
+        rom::FAT_SIZE_OFFSET,
+    )?;
+    Ok(rom::parse_filesystem(&fnt, &fat)?)
+}
+
+/// Reads the data of a file entry of the NitroFS like [`rom::file_data`], leaving out any part
+/// of it lying outside of the ROM.
+pub fn file_data(
+    source: &(impl RomSource + ?Sized),
+    entry: &nitro_fs::fnt::FileEntry,
+) -> io::Result<Vec<u8>> {
+    let end = (entry.alloc.end as u64).min(source.len());
+    let start = (entry.alloc.start as u64).min(end);
+    source.read_range(start..end)
+}
@@ -0,0 +1,392 @@
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+const SSEQ_MAGIC: &[u8; 4] = b"SSEQ";
+const DATA_MAGIC: &[u8; 4] = b"DATA";
+/// Offset of the DATA block, right after the header.
+const DATA_BLOCK_OFFSET: usize = 0x10;
+/// Ticks per quarter note of sequences, which MIDI files are written with too.
+const TICKS_PER_QUARTER_NOTE: u16 = 48;
+/// Tempo sequences start with, in beats per minute.
+const DEFAULT_TEMPO: u32 = 120;
+/// Number of tracks a sequence can have, one per MIDI channel.
+const TRACK_COUNT: usize = 16;
+/// Depth of the call stack of each track.
+const CALL_STACK_DEPTH: usize = 3;
+/// Maximum number of commands run per track, so that sequences that never end still do when
+/// converted.
+const MAX_COMMANDS: usize = 0x10000;
+
+#[derive(Error, Debug)]
+pub enum ParseSequenceError {
+    #[error("magic number does not match (expected: SSEQ)")]
+    MagicNumberMismatch,
+    #[error("sequence data is truncated")]
+    Truncated,
+    #[error("unknown sequence command 0x{command:02X} at offset 0x{offset:X}")]
+    UnknownCommand { command: u8, offset: usize },
+}
+
+/// Kinds of the arguments of sequence commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Arg {
+    U8,
+    S8,
+    U16,
+    S16,
+    U24,
+    VariableLength,
+}
+
+fn command_args(command: u8) -> Option<&'static [Arg]> {
+    Some(match command {
+        // Notes are followed by their velocity and duration.
+        0x00..=0x7F => &[Arg::U8, Arg::VariableLength],
+        0x80 | 0x81 => &[Arg::VariableLength],
+        0x93 => &[Arg::U8, Arg::U24],
+        0x94 | 0x95 => &[Arg::U24],
+        0xB0..=0xBD => &[Arg::U8, Arg::S16],
+        0xC3 | 0xC4 => &[Arg::S8],
+        0xC0..=0xD6 => &[Arg::U8],
+        0xE0..=0xE3 => &[Arg::U16],
+        0xFE => &[Arg::U16],
+        0xFC | 0xFD | 0xFF => &[],
+        _ => return None,
+    })
+}
+
+/// A sequence command, with its arguments read as integers.
+struct Command {
+    command: u8,
+    args: Vec<i32>,
+}
+
+/// Where a loop of a track started, and how many more times it is played, if not forever.
+struct LoopState {
+    start: usize,
+    remaining: Option<u8>,
+}
+
+/// A MIDI event of a track, at the tick it happens.
+struct Event {
+    tick: u32,
+    bytes: Vec<u8>,
+}
+
+/// An SSEQ sequence: a song or sound effect made of up to 16 tracks of commands that play
+/// notes with the instruments of a bank.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sseq {
+    /// The commands of the sequence. Offsets in commands are relative to its start.
+    pub data: Vec<u8>,
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, ParseSequenceError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseSequenceError::Truncated)
+}
+
+/// Appends a MIDI variable-length quantity.
+fn push_variable_length(output: &mut Vec<u8>, value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    let mut value = value >> 7;
+    while value != 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    output.extend(bytes.iter().rev());
+}
+
+fn meta_event(kind: u8, data: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0xFF, kind];
+    push_variable_length(&mut bytes, data.len() as u32);
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+fn tempo_event(tempo: u32) -> Vec<u8> {
+    let microseconds_per_quarter_note = 60_000_000 / tempo.max(1);
+    meta_event(0x51, &microseconds_per_quarter_note.to_be_bytes()[1..])
+}
+
+impl Sseq {
+    pub fn parse(data: &[u8]) -> Result<Self, ParseSequenceError> {
+        if !data.starts_with(SSEQ_MAGIC)
+            || data.get(DATA_BLOCK_OFFSET..DATA_BLOCK_OFFSET + 4) != Some(DATA_MAGIC.as_slice())
+        {
+            return Err(ParseSequenceError::MagicNumberMismatch);
+        }
+        let data_offset = u32_at(data, DATA_BLOCK_OFFSET + 8)? as usize;
+        Ok(Sseq {
+            data: data
+                .get(data_offset..)
+                .ok_or(ParseSequenceError::Truncated)?
+                .to_vec(),
+        })
+    }
+
+    fn read_arg(&self, arg: Arg, offset: &mut usize) -> Result<i32, ParseSequenceError> {
+        let byte = |offset: usize| {
+            self.data
+                .get(offset)
+                .copied()
+                .ok_or(ParseSequenceError::Truncated)
+        };
+        let value = match arg {
+            Arg::U8 => byte(*offset)? as i32,
+            Arg::S8 => byte(*offset)? as i8 as i32,
+            Arg::U16 => u16::from_le_bytes([byte(*offset)?, byte(*offset + 1)?]) as i32,
+            Arg::S16 => i16::from_le_bytes([byte(*offset)?, byte(*offset + 1)?]) as i32,
+            Arg::U24 => {
+                i32::from_le_bytes([byte(*offset)?, byte(*offset + 1)?, byte(*offset + 2)?, 0])
+            }
+            Arg::VariableLength => {
+                let mut value = 0;
+                loop {
+                    let byte = byte(*offset)?;
+                    *offset += 1;
+                    value = (value << 7) | (byte & 0x7F) as i32;
+                    if byte & 0x80 == 0 {
+                        return Ok(value);
+                    }
+                }
+            }
+        };
+        *offset += match arg {
+            Arg::U8 | Arg::S8 => 1,
+            Arg::U16 | Arg::S16 => 2,
+            Arg::U24 => 3,
+            Arg::VariableLength => unreachable!(),
+        };
+        Ok(value)
+    }
+
+    /// Reads the command at `offset`, moving past it. Commands whose last argument is random
+    /// or read from a variable take the smallest random value, or zero for variables, as their
+    /// value isn't known.
+    fn read_command(&self, offset: &mut usize) -> Result<Command, ParseSequenceError> {
+        let mut command = *self
+            .data
+            .get(*offset)
+            .ok_or(ParseSequenceError::Truncated)?;
+        let command_offset = *offset;
+        *offset += 1;
+        // Conditional commands are always run.
+        if command == 0xA2 {
+            return self.read_command(offset);
+        }
+        let prefix = matches!(command, 0xA0 | 0xA1).then_some(command);
+        if prefix.is_some() {
+            command = *self
+                .data
+                .get(*offset)
+                .ok_or(ParseSequenceError::Truncated)?;
+            *offset += 1;
+        }
+        let unknown = ParseSequenceError::UnknownCommand {
+            command,
+            offset: command_offset,
+        };
+        let arg_kinds = command_args(command).ok_or(unknown)?;
+        let mut args = Vec::with_capacity(arg_kinds.len());
+        for (index, &arg) in arg_kinds.iter().enumerate() {
+            let is_last = index + 1 == arg_kinds.len();
+            args.push(match prefix {
+                Some(0xA0) if is_last => {
+                    let min = self.read_arg(Arg::S16, offset)?;
+                    self.read_arg(Arg::S16, offset)?;
+                    min
+                }
+                Some(_) if is_last => {
+                    self.read_arg(Arg::U8, offset)?;
+                    0
+                }
+                _ => self.read_arg(arg, offset)?,
+            });
+        }
+        Ok(Command { command, args })
+    }
+
+    /// Finds the tracks opened by the first track, which starts at the start of the data.
+    fn track_offsets(&self) -> Result<Vec<(u8, usize)>, ParseSequenceError> {
+        let mut tracks = vec![(0, 0)];
+        let mut offset = 0;
+        loop {
+            let command = self.read_command(&mut offset)?;
+            match command.command {
+                0xFE => {}
+                0x93 => tracks.push((command.args[0] as u8, command.args[1] as usize)),
+                _ => return Ok(tracks),
+            }
+        }
+    }
+
+    /// Runs a track, returning the MIDI events it plays along with the tempo changes it makes.
+    /// Loops are played through once and marked with `loopStart` and `loopEnd` markers.
+    fn run_track(
+        &self,
+        channel: u8,
+        start: usize,
+    ) -> Result<(Vec<Event>, Vec<Event>), ParseSequenceError> {
+        let mut events = Vec::new();
+        let mut tempo_events = Vec::new();
+        let mut event = |tick: u32, bytes: Vec<u8>| events.push(Event { tick, bytes });
+        let status = |kind: u8| kind | (channel & 0xF);
+
+        let mut offset = start;
+        let mut tick = 0u32;
+        let mut transpose = 0;
+        let mut note_wait = true;
+        let mut calls = Vec::new();
+        let mut loop_state = None;
+        // Ticks at which each command was first run, to find where jumps backwards loop to.
+        let mut visited = BTreeMap::new();
+        for _ in 0..MAX_COMMANDS {
+            visited.entry(offset).or_insert(tick);
+            let Command { command, args } = self.read_command(&mut offset)?;
+            match command {
+                0x00..=0x7F => {
+                    let note = (command as i32 + transpose).clamp(0, 0x7F) as u8;
+                    let velocity = (args[0] as u8).min(0x7F);
+                    let duration = args[1] as u32;
+                    event(tick, vec![status(0x90), note, velocity]);
+                    event(tick + duration, vec![status(0x80), note, 0]);
+                    if note_wait {
+                        tick += duration;
+                    }
+                }
+                0x80 => tick += args[0] as u32,
+                0x81 => {
+                    let bank = ((args[0] >> 7) & 0x7F) as u8;
+                    event(tick, vec![status(0xB0), 0, bank]);
+                    event(tick, vec![status(0xC0), (args[0] & 0x7F) as u8]);
+                }
+                0x94 => {
+                    let target = args[0] as usize;
+                    if let Some(&loop_start) = visited.get(&target) {
+                        event(loop_start, meta_event(0x06, b"loopStart"));
+                        event(tick, meta_event(0x06, b"loopEnd"));
+                        break;
+                    }
+                    offset = target;
+                }
+                0x95 if calls.len() < CALL_STACK_DEPTH => {
+                    calls.push(offset);
+                    offset = args[0] as usize;
+                }
+                0xFD => {
+                    if let Some(return_offset) = calls.pop() {
+                        offset = return_offset;
+                    }
+                }
+                0xC0 => event(tick, vec![status(0xB0), 10, (args[0] as u8).min(0x7F)]),
+                0xC1 => event(tick, vec![status(0xB0), 7, (args[0] as u8).min(0x7F)]),
+                0xC3 => transpose = args[0],
+                0xC4 => {
+                    let bend = (0x2000 + args[0] * 0x40).clamp(0, 0x3FFF) as u16;
+                    event(
+                        tick,
+                        vec![status(0xE0), (bend & 0x7F) as u8, (bend >> 7) as u8],
+                    );
+                }
+                0xC5 => {
+                    // Registered parameter 0 is the pitch bend range, in semitones.
+                    event(tick, vec![status(0xB0), 101, 0]);
+                    event(tick, vec![status(0xB0), 100, 0]);
+                    event(tick, vec![status(0xB0), 6, (args[0] as u8).min(0x7F)]);
+                }
+                0xC7 => note_wait = args[0] != 0,
+                0xCA => event(tick, vec![status(0xB0), 1, (args[0] as u8).min(0x7F)]),
+                0xCE => event(
+                    tick,
+                    vec![status(0xB0), 65, if args[0] != 0 { 0x7F } else { 0 }],
+                ),
+                0xCF => event(tick, vec![status(0xB0), 5, (args[0] as u8).min(0x7F)]),
+                0xD4 => {
+                    loop_state = Some(LoopState {
+                        start: offset,
+                        remaining: (args[0] != 0).then_some(args[0] as u8),
+                    });
+                    if args[0] == 0 {
+                        event(tick, meta_event(0x06, b"loopStart"));
+                    }
+                }
+                0xD5 => event(tick, vec![status(0xB0), 11, (args[0] as u8).min(0x7F)]),
+                0xE1 => tempo_events.push(Event {
+                    tick,
+                    bytes: tempo_event(args[0] as u32),
+                }),
+                0xFC => match &mut loop_state {
+                    Some(LoopState {
+                        remaining: None, ..
+                    }) => {
+                        event(tick, meta_event(0x06, b"loopEnd"));
+                        break;
+                    }
+                    Some(LoopState {
+                        start,
+                        remaining: Some(remaining),
+                    }) => {
+                        // The loop count is the number of times the loop is played.
+                        *remaining -= 1;
+                        if *remaining == 0 {
+                            loop_state = None;
+                        } else {
+                            offset = *start;
+                        }
+                    }
+                    None => {}
+                },
+                0xFF => break,
+                _ => {}
+            }
+        }
+        Ok((events, tempo_events))
+    }
+
+    /// Converts the sequence to a type 1 MIDI file, with a track holding the tempo changes
+    /// followed by a track for each track of the sequence. Each track plays on the MIDI
+    /// channel of its number.
+    pub fn to_midi(&self) -> Result<Vec<u8>, ParseSequenceError> {
+        let mut tempo_events = vec![Event {
+            tick: 0,
+            bytes: tempo_event(DEFAULT_TEMPO),
+        }];
+        let mut tracks = Vec::new();
+        for (number, offset) in self.track_offsets()? {
+            if number as usize >= TRACK_COUNT {
+                continue;
+            }
+            let (events, tempo) = self.run_track(number, offset)?;
+            tempo_events.extend(tempo);
+            tracks.push(events);
+        }
+        tracks.insert(0, tempo_events);
+
+        let mut midi = Vec::new();
+        midi.extend_from_slice(b"MThd");
+        midi.extend_from_slice(&6u32.to_be_bytes());
+        midi.extend_from_slice(&1u16.to_be_bytes());
+        midi.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        midi.extend_from_slice(&TICKS_PER_QUARTER_NOTE.to_be_bytes());
+        for mut events in tracks {
+            // Note offs go before note ons at the same tick, so that repeated notes play.
+            events.sort_by_key(|event| (event.tick, event.bytes[0] & 0xF0 != 0x80));
+            let mut track = Vec::new();
+            let mut previous_tick = 0;
+            for event in events {
+                push_variable_length(&mut track, event.tick - previous_tick);
+                track.extend_from_slice(&event.bytes);
+                previous_tick = event.tick;
+            }
+            push_variable_length(&mut track, 0);
+            track.extend_from_slice(&meta_event(0x2F, &[]));
+            midi.extend_from_slice(b"MTrk");
+            midi.extend_from_slice(&(track.len() as u32).to_be_bytes());
+            midi.extend_from_slice(&track);
+        }
+        Ok(midi)
+    }
+}
@@ -0,0 +1,126 @@
+//! Exit statuses of the command line tool, telling apart the ways it can fail so that scripts
+//! wrapping it don't have to read its output:
+//!
+//! | Status | Meaning                                                                 |
+//! |--------|-------------------------------------------------------------------------|
+//! | 0      | Success.                                                                |
+//! | 1      | Any other failure, such as a file that can't be read or written.        |
+//! | 2      | A file given couldn't be parsed.                                        |
+//! | 3      | A check failed, such as a round trip, a hash check or a lint.           |
+//! | 4      | Some files failed to unpack, and were written as they are stored.       |
+//! | 5      | Warnings were printed, with `--fail-on warnings`.                       |
+//! | 64     | The command line is invalid.                                            |
+
+use std::error::Error as StdError;
+
+use clap::ValueEnum;
+use thiserror::Error;
+
+use crate::{
+    bmg::ParseBmgError, cheat::ParseCheatError, control_codes::ParseControlCodesError,
+    entry_template::ParseEntryTemplateError, fnt::ParseFntError, narc::ParseNarcError,
+    rom::RomParseError, symbols::ParseSymbolsError, table::ParseTableError, text::ParseTextError,
+};
+
+/// How the command line tool exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Success,
+    Failure,
+    ParseError,
+    CheckFailed,
+    PartialUnpack,
+    Warnings,
+    Usage,
+}
+
+impl ExitStatus {
+    pub fn code(self) -> u8 {
+        match self {
+            ExitStatus::Success => 0,
+            ExitStatus::Failure => 1,
+            ExitStatus::ParseError => 2,
+            ExitStatus::CheckFailed => 3,
+            ExitStatus::PartialUnpack => 4,
+            ExitStatus::Warnings => 5,
+            // EX_USAGE of the BSD sysexits.
+            ExitStatus::Usage => 64,
+        }
+    }
+
+    /// The status an error exits with: the one given by the first [`StatusError`] among its
+    /// causes, [`ExitStatus::ParseError`] if one of them is a parse error, or else
+    /// [`ExitStatus::Failure`].
+    pub fn of(error: &anyhow::Error) -> Self {
+        if let Some(error) = error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<StatusError>())
+        {
+            return error.status;
+        }
+        if error.chain().any(is_parse_error) {
+            return ExitStatus::ParseError;
+        }
+        ExitStatus::Failure
+    }
+}
+
+/// An error exiting with a status of its own.
+#[derive(Error, Debug)]
+#[error("{message}")]
+pub struct StatusError {
+    pub status: ExitStatus,
+    pub message: String,
+}
+
+impl StatusError {
+    pub fn new(status: ExitStatus, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+/// When the command line tool exits with a failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum FailOn {
+    /// When a warning is printed, even if the command succeeds
+    Warnings,
+    /// When the command fails
+    #[default]
+    Errors,
+    /// Never: the status is always 0, whatever happens
+    None,
+}
+
+/// Whether an error comes from parsing a file.
+fn is_parse_error(error: &(dyn StdError + 'static)) -> bool {
+    #[cfg(feature = "graphics")]
+    if error.is::<crate::gfx::ParseGfxError>()
+        || error.is::<crate::nftr::ParseFontError>()
+        || error.is::<crate::nsbmd::ParseModelError>()
+        || error.is::<crate::nsbtx::ParseTextureError>()
+    {
+        return true;
+    }
+    #[cfg(feature = "audio")]
+    if error.is::<crate::sbnk::ParseBankError>()
+        || error.is::<crate::sdat::ParseSoundArchiveError>()
+        || error.is::<crate::sseq::ParseSequenceError>()
+        || error.is::<crate::wave::ParseWaveError>()
+        || error.is::<crate::wave::ReadWavError>()
+    {
+        return true;
+    }
+    error.is::<ParseBmgError>()
+        || error.is::<ParseCheatError>()
+        || error.is::<ParseControlCodesError>()
+        || error.is::<ParseEntryTemplateError>()
+        || error.is::<ParseFntError>()
+        || error.is::<ParseNarcError>()
+        || error.is::<RomParseError>()
+        || error.is::<ParseSymbolsError>()
+        || error.is::<ParseTableError>()
+        || error.is::<ParseTextError>()
+}
@@ -0,0 +1,376 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{
+    freespace::{self, Allocator},
+    lz::CompressionLevel,
+    manifest::Processor,
+    memory::{self, Binary, LoadedBinary, MapAddressError},
+    overlay, rom,
+    search::SearchEncoding,
+    string_scan::{self, BinaryString},
+    text_formats::{self, ImportTextError},
+};
+
+const CSV_HEADER: &str = "address,original,translation";
+
+#[derive(Error, Debug)]
+pub enum InsertStringsError {
+    #[error("no binary has the string {original:?} at 0x{address:08X}")]
+    NotFound { address: u32, original: String },
+    #[error(
+        "the string at 0x{address:08X} is in overlays {overlay_ids:?}, which share that memory"
+    )]
+    Ambiguous { address: u32, overlay_ids: Vec<u32> },
+    #[error("the translation of the string at 0x{address:08X} can't be encoded in {}", .encoding.name())]
+    Unencodable {
+        address: u32,
+        encoding: SearchEncoding,
+    },
+    #[error("the translation of the string at 0x{address:08X} doesn't fit in its place, and no free space was given to move it to")]
+    NoFreeSpace { address: u32 },
+    #[error("no free space is left to move the translation of the string at 0x{address:08X} to")]
+    OutOfFreeSpace { address: u32 },
+    #[error(transparent)]
+    Map(#[from] MapAddressError),
+}
+
+/// A string of a binary to replace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StringEdit {
+    /// RAM address of the string.
+    pub address: u32,
+    /// The string at the address, checked for before replacing it.
+    pub original: String,
+    /// The string to replace it with. Empty translations leave the string as it is.
+    pub translation: String,
+}
+
+/// Where strings that don't fit in their place are moved to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FreeSpace {
+    /// Past the end of the ARM9 binary and the BSS of its static code, growing the binary.
+    Arm9,
+    /// Past the end of the ARM9 overlay with the ID given and its BSS, growing the overlay.
+    Overlay(u32),
+    /// The free space `freespace` finds in the binaries, in the binary of each string or else
+    /// in the ARM9 binary.
+    Auto,
+}
+
+impl FromStr for FreeSpace {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.eq_ignore_ascii_case("arm9") {
+            return Ok(Self::Arm9);
+        }
+        if value.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+        value
+            .strip_prefix("overlay:")
+            .and_then(|overlay_id| overlay_id.trim().parse().ok())
+            .map(Self::Overlay)
+            .ok_or_else(|| format!("expected `arm9`, `overlay:<ID>` or `auto`, found {value:?}"))
+    }
+}
+
+impl fmt::Display for FreeSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FreeSpace::Arm9 => f.write_str("the space past the ARM9 binary"),
+            FreeSpace::Overlay(overlay_id) => write!(f, "the space past overlay {overlay_id}"),
+            FreeSpace::Auto => f.write_str("the free space of the binaries"),
+        }
+    }
+}
+
+/// What inserting strings did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InsertReport {
+    /// Strings replaced where they were.
+    pub in_place: usize,
+    /// Strings moved to the free space.
+    pub relocated: usize,
+    /// Strings left as they were, having no translation or an identical one.
+    pub unchanged: usize,
+    /// Pointers changed to point to the new location of a string.
+    pub pointers: usize,
+    /// Addresses of the strings moved that no pointer was found to, which the game keeps
+    /// reading from their original location.
+    pub unreferenced: Vec<u32>,
+    /// Bytes of free space used.
+    pub free_space_used: usize,
+    /// Bytes of free space left, when it's the free space found in the binaries.
+    pub free_space_left: Option<usize>,
+}
+
+/// Exports the strings found by a scan to CSV, with a column for their translation.
+pub fn export_csv(strings: &[BinaryString]) -> String {
+    let mut output = format!("{CSV_HEADER}\n");
+    for string in strings {
+        output.push_str(&format!(
+            "0x{:08X},{},\"\"\n",
+            string.address,
+            text_formats::quote_csv_field(&string.text)
+        ));
+    }
+    output
+}
+
+/// Imports strings to replace from CSV with an `address`, an `original` and a `translation`
+/// column. Other columns are ignored.
+pub fn import_csv(contents: &str) -> Result<Vec<StringEdit>, ImportTextError> {
+    let mut records =
+        text_formats::parse_csv_records(contents.trim_start_matches('\u{FEFF}'))?.into_iter();
+    let Some((_, header)) = records.next() else {
+        return Ok(Vec::new());
+    };
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|field| field.trim().eq_ignore_ascii_case(name))
+            .ok_or_else(|| text_formats::syntax_error(1, format!("missing {name:?} column")))
+    };
+    let address_column = column("address")?;
+    let original_column = column("original")?;
+    let translation_column = column("translation")?;
+
+    records
+        .filter(|(_, fields)| fields.iter().any(|field| !field.is_empty()))
+        .map(|(line_number, fields)| {
+            let address = fields
+                .get(address_column)
+                .map(|address| address.trim())
+                .and_then(|address| match address.strip_prefix("0x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => address.parse().ok(),
+                })
+                .ok_or_else(|| text_formats::syntax_error(line_number, "invalid address"))?;
+            let field = |column: usize| fields.get(column).cloned().unwrap_or_default();
+            Ok(StringEdit {
+                address,
+                original: field(original_column),
+                translation: field(translation_column),
+            })
+        })
+        .collect()
+}
+
+/// Replaces strings of the ARM9 binary and its overlays, as `scan-strings` finds them.
+///
+/// Translations that fit in the place of the original string, including the padding aligning
+/// what follows it, replace it there. The others are placed in the free space given, and every
+/// word of the binaries holding the address of the original string is changed to point to
+/// them. The original strings are kept, in case code computes their address rather than
+/// reading it from a pointer.
+///
+/// Growing a binary takes memory the game may use for something else, such as its heap: it's
+/// for the caller to make sure it doesn't.
+pub fn insert_strings(
+    rom_data: &mut Vec<u8>,
+    edits: &[StringEdit],
+    encoding: SearchEncoding,
+    free_space: Option<FreeSpace>,
+    level: CompressionLevel,
+) -> anyhow::Result<InsertReport> {
+    let overlays = overlay::overlay_table(rom_data, Processor::Arm9);
+    let mut binaries = vec![Binary::Arm9];
+    binaries.extend(overlays.iter().map(|entry| Binary::Overlay(entry.id)));
+    let mut loaded = binaries
+        .into_iter()
+        .map(|binary| Ok((binary, memory::loaded_binary(rom_data, binary)?, false)))
+        .collect::<Result<Vec<(Binary, LoadedBinary, bool)>, MapAddressError>>()?;
+
+    let mut allocator = match free_space {
+        Some(FreeSpace::Auto) => Some(Allocator::new(freespace::find_free_space(
+            rom_data,
+            freespace::DEFAULT_MIN_SIZE,
+        )?)),
+        _ => None,
+    };
+    let free_start = match free_space {
+        None | Some(FreeSpace::Auto) => None,
+        Some(FreeSpace::Arm9) => Some(memory::arm9_end_address(rom_data)?),
+        Some(FreeSpace::Overlay(overlay_id)) => {
+            let entry = overlays
+                .iter()
+                .find(|entry| entry.id == overlay_id)
+                .ok_or(MapAddressError::NoSuchOverlay(overlay_id))?;
+            Some(entry.ram_address + entry.ram_size + entry.bss_size)
+        }
+    }
+    .map(|start| rom::align_up(start as usize, 4) as u32);
+
+    let mut report = InsertReport::default();
+    let mut free = Vec::new();
+    // Strings moved, by the binary and address they are moved to.
+    let mut moved = Vec::new();
+    let mut relocations = HashMap::new();
+    for edit in edits {
+        if edit.translation.is_empty() || edit.translation == edit.original {
+            report.unchanged += 1;
+            continue;
+        }
+        let not_found = || InsertStringsError::NotFound {
+            address: edit.address,
+            original: edit.original.clone(),
+        };
+        let original = string_scan::encode(&edit.original, encoding).ok_or_else(not_found)?;
+        let matching = loaded
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, binary, _))| {
+                edit.address
+                    .checked_sub(binary.address)
+                    .and_then(|offset| binary.data.get(offset as usize..))
+                    .is_some_and(|data| data.starts_with(&original))
+            })
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        let index = match matching[..] {
+            [] => return Err(not_found().into()),
+            [index] => index,
+            _ => {
+                return Err(InsertStringsError::Ambiguous {
+                    address: edit.address,
+                    overlay_ids: matching
+                        .iter()
+                        .filter_map(|&index| match loaded[index].0 {
+                            Binary::Overlay(overlay_id) => Some(overlay_id),
+                            _ => None,
+                        })
+                        .collect(),
+                }
+                .into())
+            }
+        };
+        let translation = string_scan::encode(&edit.translation, encoding).ok_or(
+            InsertStringsError::Unencodable {
+                address: edit.address,
+                encoding,
+            },
+        )?;
+
+        let (user, binary, modified) = &mut loaded[index];
+        let offset = (edit.address - binary.address) as usize;
+        let mut size = original.len();
+        while !(edit.address as usize + size).is_multiple_of(4)
+            && binary.data.get(offset + size) == Some(&0)
+        {
+            size += 1;
+        }
+        if translation.len() <= size {
+            let place = &mut binary.data[offset..offset + size];
+            place.fill(0);
+            place[..translation.len()].copy_from_slice(&translation);
+            *modified = true;
+            report.in_place += 1;
+        } else if let Some(allocator) = &mut allocator {
+            let allocation = allocator.allocate(translation.len(), 4, *user).ok_or(
+                InsertStringsError::OutOfFreeSpace {
+                    address: edit.address,
+                },
+            )?;
+            relocations.insert(edit.address, allocation.address);
+            moved.push((allocation.binary, allocation.address, translation));
+            report.relocated += 1;
+        } else {
+            let free_start = free_start.ok_or(InsertStringsError::NoFreeSpace {
+                address: edit.address,
+            })?;
+            relocations.insert(edit.address, free_start + free.len() as u32);
+            free.extend_from_slice(&translation);
+            free.resize(rom::align_up(free.len(), 4), 0);
+            report.relocated += 1;
+        }
+    }
+
+    let mut referenced = HashMap::<u32, usize>::new();
+    for (_, binary, modified) in &mut loaded {
+        for word in binary.data.chunks_exact_mut(4) {
+            if let Some(&new_address) = relocations.get(&rom::u32_at(word, 0)) {
+                *referenced.entry(rom::u32_at(word, 0)).or_default() += 1;
+                word.copy_from_slice(&new_address.to_le_bytes());
+                *modified = true;
+                report.pointers += 1;
+            }
+        }
+    }
+    report.unreferenced = relocations
+        .keys()
+        .filter(|address| !referenced.contains_key(address))
+        .copied()
+        .collect();
+    report.unreferenced.sort_unstable();
+    report.free_space_used = free.len();
+    if let Some(allocator) = &allocator {
+        report.free_space_used = allocator.used();
+        report.free_space_left = Some(allocator.left());
+    }
+    if let (Some(FreeSpace::Overlay(overlay_id)), Some(free_start)) = (free_space, free_start) {
+        if !free.is_empty() {
+            moved.push((
+                Binary::Overlay(overlay_id),
+                free_start,
+                std::mem::take(&mut free),
+            ));
+        }
+    }
+
+    // Overlays grow to hold the strings moved past their end, their BSS being stored as
+    // zeroes so that the strings can follow it.
+    for (binary, address, data) in moved {
+        let (_, loaded, modified) = loaded
+            .iter_mut()
+            .find(|(other, ..)| *other == binary)
+            .expect("strings are only moved to binaries loaded");
+        let offset = (address - loaded.address) as usize;
+        if loaded.data.len() < offset + data.len() {
+            loaded.data.resize(offset + data.len(), 0);
+        }
+        loaded.data[offset..offset + data.len()].copy_from_slice(&data);
+        *modified = true;
+    }
+
+    for (binary, loaded, modified) in loaded {
+        if !modified {
+            continue;
+        }
+        match binary {
+            Binary::Arm9 => {
+                let rom_offset = loaded.rom_offset.ok_or(MapAddressError::CompressedArm9)?;
+                rom_data[rom_offset..rom_offset + loaded.data.len()].copy_from_slice(&loaded.data);
+            }
+            Binary::Overlay(overlay_id) => {
+                let grown = overlays.iter().any(|entry| {
+                    entry.id == overlay_id && loaded.data.len() > entry.ram_size as usize
+                });
+                overlay::replace_overlay(
+                    rom_data,
+                    Processor::Arm9,
+                    overlay_id,
+                    &loaded.data,
+                    level,
+                )?;
+                if grown {
+                    overlay::set_bss_size(rom_data, Processor::Arm9, overlay_id, 0)?;
+                }
+            }
+            Binary::Arm7 => {}
+        }
+    }
+    if let (Some(FreeSpace::Arm9), Some(free_start)) = (free_space, free_start) {
+        if !free.is_empty() {
+            let ram_end = rom::u32_at(rom_data, rom::ARM9_RAM_ADDR_OFFSET)
+                + rom::u32_at(rom_data, rom::ARM9_SIZE_OFFSET);
+            let mut data = vec![0; (free_start - ram_end) as usize];
+            data.extend_from_slice(&free);
+            rom::extend_arm9(rom_data, &data)?;
+        }
+    }
+    rom::fix_header_crc(rom_data);
+    Ok(report)
+}
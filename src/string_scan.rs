@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use crate::{
+    memory::{self, Binary, LoadedBinary, MapAddressError},
+    rom,
+    search::SearchEncoding,
+};
+
+/// A string literal found in a binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryString {
+    pub binary: Binary,
+    /// RAM address of the string.
+    pub address: u32,
+    /// Offset of the string in the ROM, unless the binary is compressed.
+    pub rom_offset: Option<usize>,
+    /// Size of the string in bytes, without its terminator.
+    pub size: usize,
+    pub text: String,
+    /// Words of the binaries scanned holding the address of the string.
+    pub pointers: Vec<Pointer>,
+}
+
+/// A word holding the address of a string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pointer {
+    pub binary: Binary,
+    /// RAM address of the word.
+    pub address: u32,
+    /// Offset of the word in the ROM, unless the binary is compressed.
+    pub rom_offset: Option<usize>,
+}
+
+fn is_ascii_text(byte: u8) -> bool {
+    matches!(byte, 0x20..=0x7E | b'\n' | b'\t' | b'\r')
+}
+
+/// Size of a character of the encoding starting at the beginning of `data`, if it starts with
+/// one likely to be part of text.
+fn char_size(data: &[u8], encoding: SearchEncoding) -> Option<usize> {
+    match encoding {
+        SearchEncoding::Ascii => is_ascii_text(*data.first()?).then_some(1),
+        SearchEncoding::ShiftJis => match *data.first()? {
+            byte if is_ascii_text(byte) => Some(1),
+            // Half-width katakana.
+            0xA1..=0xDF => Some(1),
+            0x81..=0x9F | 0xE0..=0xFC => {
+                matches!(data.get(1)?, 0x40..=0x7E | 0x80..=0xFC).then_some(2)
+            }
+            _ => None,
+        },
+        SearchEncoding::Utf16le => {
+            let unit = u16::from_le_bytes([*data.first()?, *data.get(1)?]);
+            let is_text = match unit {
+                0..=0x7F => is_ascii_text(unit as u8),
+                // Latin-1 and Latin Extended-A, general punctuation, CJK symbols and kana, CJK
+                // ideographs, and full-width forms.
+                0xA0..=0x17F | 0x2010..=0x206F | 0x3000..=0x30FF | 0x4E00..=0x9FFF => true,
+                0xFF01..=0xFFEF => true,
+                _ => false,
+            };
+            is_text.then_some(2)
+        }
+    }
+}
+
+/// Whether data passes for UTF-16 text. Pairs of ASCII characters pass for CJK ideographs, and
+/// an ASCII character followed by the terminator of an ASCII string passes for an UTF-16 one, so
+/// text with no other character doesn't.
+fn looks_like_utf16(data: &[u8]) -> bool {
+    let units = data.chunks_exact(2).collect::<Vec<_>>();
+    data.len().is_multiple_of(2)
+        && units
+            .iter()
+            .all(|unit| char_size(unit, SearchEncoding::Utf16le).is_some())
+        && units.iter().enumerate().any(|(index, unit)| {
+            if unit[1] == 0 {
+                index + 1 < units.len() || units.len() == 1
+            } else {
+                !(is_ascii_text(unit[0]) && is_ascii_text(unit[1]))
+            }
+        })
+}
+
+/// Encodes a string as the scans find them, terminator included, or returns `None` if it can't
+/// be.
+pub fn encode(text: &str, encoding: SearchEncoding) -> Option<Vec<u8>> {
+    if text.contains('\0') {
+        return None;
+    }
+    match encoding {
+        SearchEncoding::Ascii => text.is_ascii().then(|| text.bytes().chain([0]).collect()),
+        SearchEncoding::ShiftJis => {
+            let (bytes, _, had_errors) = encoding_rs::SHIFT_JIS.encode(text);
+            (!had_errors).then(|| bytes.iter().copied().chain([0]).collect())
+        }
+        SearchEncoding::Utf16le => Some(
+            text.encode_utf16()
+                .chain([0])
+                .flat_map(u16::to_le_bytes)
+                .collect(),
+        ),
+    }
+}
+
+fn decode(data: &[u8], encoding: SearchEncoding) -> Option<String> {
+    match encoding {
+        SearchEncoding::Ascii => String::from_utf8(data.to_vec()).ok(),
+        SearchEncoding::ShiftJis => encoding_rs::SHIFT_JIS
+            .decode_without_bom_handling_and_without_replacement(data)
+            .map(|text| text.into_owned()),
+        SearchEncoding::Utf16le => String::from_utf16(
+            &data
+                .chunks_exact(2)
+                .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                .collect::<Vec<_>>(),
+        )
+        .ok(),
+    }
+}
+
+/// Finds the null-terminated strings of at least `min_len` characters in data, returning the
+/// offset, size in bytes (without the terminator) and text of each. UTF-16 strings are looked
+/// for at even offsets only, and strings without a letter or digit are left out, as runs of
+/// code and data bytes often pass for symbols.
+pub fn scan_strings(
+    data: &[u8],
+    encoding: SearchEncoding,
+    min_len: usize,
+) -> Vec<(usize, usize, String)> {
+    let unit = match encoding {
+        SearchEncoding::Utf16le => 2,
+        SearchEncoding::ShiftJis | SearchEncoding::Ascii => 1,
+    };
+    let terminator = &[0, 0][..unit];
+    let mut strings = Vec::new();
+    let mut start = 0;
+    while start + unit <= data.len() {
+        let mut end = start;
+        let mut len = 0;
+        while let Some(size) = char_size(&data[end..], encoding) {
+            end += size;
+            len += 1;
+        }
+        // UTF-16 text passes for Shift-JIS text with half-width katakana, so it's left to
+        // scans for UTF-16.
+        let plausible = match encoding {
+            SearchEncoding::Utf16le => looks_like_utf16(&data[start..end]),
+            SearchEncoding::ShiftJis => {
+                !start.is_multiple_of(2) || !looks_like_utf16(&data[start..end])
+            }
+            SearchEncoding::Ascii => true,
+        };
+        if len >= min_len.max(1) && plausible && data[end..].starts_with(terminator) {
+            if let Some(text) = decode(&data[start..end], encoding)
+                .filter(|text| text.chars().any(|ch| ch.is_alphanumeric()))
+            {
+                strings.push((start, end - start, text));
+            }
+        }
+        // Characters of two bytes may end at an odd offset, which starts no UTF-16 string.
+        start = if end > start {
+            end.next_multiple_of(unit)
+        } else {
+            start + unit
+        };
+    }
+    strings
+}
+
+/// Finds the string literals of the binaries given, along with the words of those binaries
+/// pointing to them, for building tables of the pointers to patch when moving the strings.
+pub fn scan_binaries(
+    rom_data: &[u8],
+    binaries: &[Binary],
+    encoding: SearchEncoding,
+    min_len: usize,
+) -> Result<Vec<BinaryString>, MapAddressError> {
+    let loaded = binaries
+        .iter()
+        .map(|&binary| Ok((binary, memory::loaded_binary(rom_data, binary)?)))
+        .collect::<Result<Vec<(Binary, LoadedBinary)>, MapAddressError>>()?;
+
+    let mut pointers = HashMap::<u32, Vec<Pointer>>::new();
+    for (binary, loaded) in &loaded {
+        for (index, word) in loaded.data.chunks_exact(4).enumerate() {
+            let offset = index * 4;
+            pointers
+                .entry(rom::u32_at(word, 0))
+                .or_default()
+                .push(Pointer {
+                    binary: *binary,
+                    address: loaded.address.wrapping_add(offset as u32),
+                    rom_offset: loaded.rom_offset.map(|start| start + offset),
+                });
+        }
+    }
+
+    Ok(loaded
+        .iter()
+        .flat_map(|(binary, loaded)| {
+            scan_strings(&loaded.data, encoding, min_len)
+                .into_iter()
+                .map(|(offset, size, text)| {
+                    let address = loaded.address.wrapping_add(offset as u32);
+                    BinaryString {
+                        binary: *binary,
+                        address,
+                        rom_offset: loaded.rom_offset.map(|start| start + offset),
+                        size,
+                        text,
+                        pointers: pointers.get(&address).cloned().unwrap_or_default(),
+                    }
+                })
+        })
+        .collect())
+}
@@ -0,0 +1,510 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::Context;
+use log::warn;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cache::CACHE_DIR,
+    magic,
+    manifest::{self, Codec, Compression},
+    narc, pack,
+    plugin::Plugins,
+    rom,
+    text::{TableFormat, TextArchive, TextEncoding},
+};
+
+/// What a file was identified as.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileIdentification {
+    /// How the file is compressed.
+    pub compression: Compression,
+    /// Name of the format of the file's (decompressed) contents, used to group files together.
+    pub format: String,
+    /// Description of the file, including key metadata when the format is known.
+    pub description: String,
+}
+
+impl FileIdentification {
+    /// A file none of the detectors recognized.
+    pub fn unknown() -> Self {
+        Self {
+            compression: Compression::None,
+            format: "unknown format".to_owned(),
+            description: "unknown format".to_owned(),
+        }
+    }
+}
+
+/// Confidence a guess needs for a file to be identified, and converted when unpacking, as what
+/// it guesses, by default.
+pub const DEFAULT_MIN_CONFIDENCE: f64 = 0.5;
+
+/// A format a file may be in, as guessed by one of the detectors of [`candidates`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub identification: FileIdentification,
+    /// How likely the file is to be in this format, from 0 to 1.
+    pub confidence: f64,
+    /// What the guess is based on, such as "valid LZ10 header".
+    pub evidence: Vec<String>,
+}
+
+/// Describes the ROM header given by its game title and code.
+fn describe_rom(rom_data: &[u8]) -> String {
+    let kind = match rom::UnitCode::of(rom_data) {
+        rom::UnitCode::Nds => "NDS ROM",
+        rom::UnitCode::DsiEnhanced => "DSi-enhanced NDS ROM",
+        rom::UnitCode::DsiExclusive => "DSi-exclusive ROM",
+    };
+    format!(
+        "{kind}, {} ({})",
+        rom::header_text(rom_data, rom::TITLE_RANGE),
+        rom::header_text(rom_data, rom::GAME_CODE_RANGE)
+    )
+}
+
+/// Characters found in the text of games, which the strings of files wrongly taken as text
+/// files are mostly not made of.
+fn is_common_text_char(ch: char) -> bool {
+    matches!(
+        ch,
+        '\n' | ' '..='~'
+            | '\u{A0}'..='\u{17F}'
+            | '\u{2010}'..='\u{206F}'
+            | '\u{3000}'..='\u{30FF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{FF00}'..='\u{FFEF}'
+    )
+}
+
+/// Works out how likely a file read as a text file is to really be one, along with the evidence
+/// for it: how its strings are laid out, and how much of them reads as text.
+pub fn text_confidence(archive: &TextArchive) -> (f64, Vec<String>) {
+    let mut evidence = Vec::new();
+    match archive.format() {
+        Some(format) => evidence.push(format!(
+            "{} strings located by a table of {format}",
+            archive.strings.len()
+        )),
+        None => evidence.push(format!("{} BMG messages", archive.strings.len())),
+    }
+    let layout_factor = if archive.format().is_none() || archive.starts_after_table() {
+        evidence.push("the first string lies right after the table".to_owned());
+        1.0
+    } else {
+        evidence.push("the strings don't start right after the table".to_owned());
+        0.75
+    };
+    let (chars, common_chars) = archive
+        .strings
+        .iter()
+        .flat_map(|string| string.chars())
+        .fold((0, 0), |(chars, common), ch| {
+            (chars + 1, common + is_common_text_char(ch) as usize)
+        });
+    if chars == 0 {
+        evidence.push("every string is empty".to_owned());
+        return (0.0, evidence);
+    }
+    let common_share = common_chars as f64 / chars as f64;
+    evidence.push(format!(
+        "{:.0}% of the characters are common text characters",
+        common_share * 100.0
+    ));
+    let count_factor = if archive.strings.len() >= 2 { 1.0 } else { 0.8 };
+    (0.9 * common_share * layout_factor * count_factor, evidence)
+}
+
+/// Runs the detectors of formats on the contents of a file, after decompression if any.
+fn content_candidates(
+    contents: &[u8],
+    encoding: &TextEncoding,
+    plugins: &Plugins,
+) -> Vec<(String, String, f64, Vec<String>)> {
+    let mut candidates = Vec::new();
+    if let Some(plugin) = plugins.detect(contents) {
+        let description = plugin.describe(contents).unwrap_or_else(|error| {
+            warn!("{error:#}");
+            plugin.name().to_owned()
+        });
+        let evidence = vec![format!("recognized by plugin {:?}", plugin.name())];
+        candidates.push((plugin.name().to_owned(), description, 0.9, evidence));
+    }
+    if let Some(identification) = magic::identify(contents) {
+        let evidence = vec![format!(
+            "{} magic number, valid structure",
+            identification.format
+        )];
+        candidates.push((
+            identification.format.to_owned(),
+            identification.to_string(),
+            0.95,
+            evidence,
+        ));
+    }
+    // BMG files are recognized by their magic number already.
+    if let Some(archive) = TextArchive::parse(contents, encoding)
+        .ok()
+        .filter(|archive| !archive.strings.is_empty() && archive.format().is_some())
+    {
+        let (confidence, evidence) = text_confidence(&archive);
+        candidates.push((
+            "text file".to_owned(),
+            describe_text_file(&archive),
+            confidence,
+            evidence,
+        ));
+    }
+    candidates
+}
+
+/// Evaluates every detector on a file, looking inside it if it's compressed, and returns the
+/// formats it may be in, most likely first. Formats recognized by one of `plugins` are named
+/// after the plugin.
+pub fn candidates(data: &[u8], encoding: &TextEncoding, plugins: &Plugins) -> Vec<Candidate> {
+    if rom::is_rom(data) {
+        return vec![Candidate {
+            identification: FileIdentification {
+                compression: Compression::None,
+                format: "NDS ROM".to_owned(),
+                description: describe_rom(data),
+            },
+            confidence: 1.0,
+            evidence: vec!["valid ROM header, with a matching checksum".to_owned()],
+        }];
+    }
+
+    let mut candidates = Vec::new();
+    if let Some((codec, contents)) = Codec::detect(data) {
+        let compression_evidence = format!(
+            "valid {codec} header, decompresses cleanly to 0x{:X} bytes",
+            contents.len()
+        );
+        for (format, description, confidence, evidence) in
+            content_candidates(&contents, encoding, plugins)
+        {
+            candidates.push(Candidate {
+                identification: FileIdentification {
+                    compression: codec.compression,
+                    format,
+                    description: format!("compressed {codec} file, {description}"),
+                },
+                confidence,
+                evidence: [vec![compression_evidence.clone()], evidence].concat(),
+            });
+        }
+        // Contents no detector is confident about are still known to be compressed.
+        if candidates
+            .iter()
+            .all(|candidate| candidate.confidence < DEFAULT_MIN_CONFIDENCE)
+        {
+            candidates.push(Candidate {
+                identification: FileIdentification {
+                    compression: codec.compression,
+                    format: "unknown format".to_owned(),
+                    description: format!("compressed {codec} file, unknown contents"),
+                },
+                confidence: 0.6,
+                evidence: vec![compression_evidence],
+            });
+        }
+    }
+    for (format, description, confidence, evidence) in content_candidates(data, encoding, plugins) {
+        candidates.push(Candidate {
+            identification: FileIdentification {
+                compression: Compression::None,
+                format,
+                description,
+            },
+            confidence,
+            evidence,
+        });
+    }
+    candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    candidates
+}
+
+/// Identifies a file from its contents as the most likely of its [`candidates`], if it's likely
+/// enough, looking inside it if it's compressed.
+pub fn identify_file(
+    data: &[u8],
+    encoding: &TextEncoding,
+    plugins: &Plugins,
+) -> FileIdentification {
+    candidates(data, encoding, plugins)
+        .into_iter()
+        .find(|candidate| candidate.confidence >= DEFAULT_MIN_CONFIDENCE)
+        .map_or_else(FileIdentification::unknown, |candidate| {
+            candidate.identification
+        })
+}
+
+/// File inside [`CACHE_DIR`] holding the identifications of the files of a directory.
+const IDENTIFICATIONS_FILE: &str = "identify.json";
+/// Version of the detectors, part of the settings identifications are cached for. It must be
+/// bumped whenever the detectors change what they identify a file as, so that identifications
+/// made by an older version aren't reused.
+const DETECTOR_VERSION: u32 = 2;
+
+/// Contents of [`IDENTIFICATIONS_FILE`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CachedIdentifications {
+    /// Hash of the settings the files were identified with.
+    settings: String,
+    /// Identifications keyed by the SHA-256 hash of the file identified.
+    files: BTreeMap<String, FileIdentification>,
+}
+
+/// Cache of file identifications, keyed by the SHA-256 hash of the files, so that identifying
+/// a directory again only runs the detectors on the files that changed since.
+///
+/// The cache can be shared between threads identifying files in parallel.
+#[derive(Debug, Default)]
+pub struct IdentificationCache {
+    path: Option<PathBuf>,
+    settings: String,
+    cached: BTreeMap<String, FileIdentification>,
+    /// Identifications made or reused since the cache was opened, which are the ones saved.
+    used: Mutex<BTreeMap<String, FileIdentification>>,
+    /// Number of files identified from the cache since it was opened.
+    hits: AtomicUsize,
+}
+
+impl IdentificationCache {
+    /// Opens the cache of the directory given, for files identified with the encoding and
+    /// plugins given. Identifications made with other settings are discarded.
+    pub fn new(dir: &Path, encoding: &TextEncoding, plugins: &Plugins) -> Self {
+        let path = dir.join(CACHE_DIR).join(IDENTIFICATIONS_FILE);
+        let settings = manifest::sha256_hex(
+            format!(
+                "v{DETECTOR_VERSION}\n{encoding:?}\n{}",
+                plugins.fingerprint()
+            )
+            .as_bytes(),
+        );
+        // A damaged cache is started over, as it only saves time.
+        let cached = fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<CachedIdentifications>(&data).ok())
+            .filter(|cached| cached.settings == settings)
+            .map(|cached| cached.files)
+            .unwrap_or_default();
+        Self {
+            path: Some(path),
+            settings,
+            cached,
+            ..Self::default()
+        }
+    }
+
+    /// Identifies a file like [`identify_file`], unless it was identified already.
+    pub fn identify(
+        &self,
+        data: &[u8],
+        encoding: &TextEncoding,
+        plugins: &Plugins,
+    ) -> FileIdentification {
+        let hash = manifest::sha256_hex(data);
+        let known = self
+            .cached
+            .get(&hash)
+            .cloned()
+            .or_else(|| self.used.lock().unwrap().get(&hash).cloned());
+        let identification = match known {
+            Some(identification) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                identification
+            }
+            None => identify_file(data, encoding, plugins),
+        };
+        self.used
+            .lock()
+            .unwrap()
+            .insert(hash, identification.clone());
+        identification
+    }
+
+    /// Number of files identified from the cache since it was opened.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Writes the identifications made or reused since the cache was opened, dropping the
+    /// others.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let cached = CachedIdentifications {
+            settings: self.settings.clone(),
+            files: self.used.lock().unwrap().clone(),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("failed to create cache directory")?;
+        }
+        fs::write(path, serde_json::to_vec(&cached)?)
+            .with_context(|| format!("failed to write {path:?}"))
+    }
+}
+
+/// A file of a [`Survey`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SurveyedFile {
+    pub path: String,
+    /// Size of the file as stored, before any decompression.
+    pub size: usize,
+    pub identification: FileIdentification,
+}
+
+/// The files of a format found by a [`Survey`], and their total size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatTotal {
+    pub format: String,
+    pub files: usize,
+    pub bytes: usize,
+}
+
+/// The identification of a set of files, along with every file found inside them.
+#[derive(Debug, Clone, Default)]
+pub struct Survey {
+    /// Each file surveyed, containers being followed by their contents.
+    pub files: Vec<SurveyedFile>,
+    /// Plugins identifying the formats they support.
+    pub plugins: Plugins,
+    /// Cache identifications are reused from, if any.
+    pub cache: Option<Arc<IdentificationCache>>,
+}
+
+impl Survey {
+    /// Identifies a file and adds it to the survey, followed by its contents if it's a ROM or
+    /// a NARC archive.
+    pub fn add(&mut self, path: String, data: &[u8], encoding: &TextEncoding) {
+        let identification = match &self.cache {
+            Some(cache) => cache.identify(data, encoding, &self.plugins),
+            None => identify_file(data, encoding, &self.plugins),
+        };
+        self.files.push(SurveyedFile {
+            path: path.clone(),
+            size: data.len(),
+            identification,
+        });
+        self.add_contents(&path, data, encoding);
+    }
+
+    /// Adds every file under a directory, sorted by path, along with the files inside them.
+    /// Files are identified in parallel, and [`CACHE_DIR`] is left out.
+    pub fn add_dir(&mut self, dir: &Path, encoding: &TextEncoding) -> anyhow::Result<()> {
+        let surveys = pack::walk_files(dir, &[CACHE_DIR])?
+            .par_iter()
+            .map(|relative_path| {
+                let data = fs::read(dir.join(relative_path))
+                    .with_context(|| format!("could not read {relative_path:?}"))?;
+                let mut survey = Survey {
+                    files: Vec::new(),
+                    plugins: self.plugins.clone(),
+                    cache: self.cache.clone(),
+                };
+                survey.add(rom::nitro_path(relative_path), &data, encoding);
+                Ok(survey)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        for survey in surveys {
+            self.files.extend(survey.files);
+        }
+        Ok(())
+    }
+
+    /// Adds every file inside a ROM or a (possibly compressed) NARC archive, with paths
+    /// relative to `prefix`. Returns whether the data given was such a container.
+    pub fn add_contents(&mut self, prefix: &str, data: &[u8], encoding: &TextEncoding) -> bool {
+        let join = |name: &str| {
+            if prefix.is_empty() {
+                name.to_owned()
+            } else {
+                format!("{prefix}/{name}")
+            }
+        };
+
+        if rom::is_rom(data) {
+            // A damaged filesystem is reported as an empty one, as surveys are best-effort.
+            let Ok(fs) = rom::filesystem(data) else {
+                return true;
+            };
+            let mut files = fs
+                .files()
+                .into_iter()
+                .map(|entry| (rom::nitro_path(&entry.path), rom::file_data(data, entry)))
+                .collect::<Vec<_>>();
+            files.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (path, file_data) in files {
+                self.add(join(&path), file_data, encoding);
+            }
+            true
+        } else if let Some((_, archive)) = narc::open_container(data) {
+            for (file_id, file) in archive.files.iter().enumerate() {
+                let name = file
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| narc::nameless_file_name(file_id));
+                self.add(join(&name), &file.data, encoding);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Number of files of each format, most common first. Compressed files are counted apart
+    /// from uncompressed ones.
+    pub fn format_counts(&self) -> Vec<(String, usize)> {
+        self.format_totals()
+            .into_iter()
+            .map(|total| (total.format, total.files))
+            .collect()
+    }
+
+    /// Number of files of each format and their total size, most common first. Compressed
+    /// files are counted apart from uncompressed ones. Files inside containers count towards
+    /// the size of their formats as well as the container's.
+    pub fn format_totals(&self) -> Vec<FormatTotal> {
+        let mut totals = BTreeMap::<String, (usize, usize)>::new();
+        for file in &self.files {
+            let identification = &file.identification;
+            let key = match identification.compression {
+                Compression::None => identification.format.clone(),
+                compression => format!("{} ({})", identification.format, compression.name()),
+            };
+            let (files, bytes) = totals.entry(key).or_default();
+            *files += 1;
+            *bytes += file.size;
+        }
+        let mut totals = totals
+            .into_iter()
+            .map(|(format, (files, bytes))| FormatTotal {
+                format,
+                files,
+                bytes,
+            })
+            .collect::<Vec<_>>();
+        totals.sort_by_key(|total| std::cmp::Reverse(total.files));
+        totals
+    }
+}
+
+/// Describes a text file, naming the format of its table unless it's the usual one.
+fn describe_text_file(archive: &TextArchive) -> String {
+    match archive.format() {
+        Some(format) if format != TableFormat::default() => format!("text file ({format})"),
+        _ => "text file".to_owned(),
+    }
+}
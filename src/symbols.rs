@@ -0,0 +1,201 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::Context;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ParseSymbolsError {
+    #[error("line {line}: invalid symbol definition {text:?}")]
+    InvalidLine { line: usize, text: String },
+    #[error("line {line}: invalid overlay section {text:?}")]
+    InvalidSection { line: usize, text: String },
+    #[error("symbol {name:?} is defined more than once")]
+    Duplicate { name: String },
+}
+
+#[derive(Error, Debug)]
+pub enum ResolveSymbolError {
+    #[error("unknown symbol {0:?}")]
+    Unknown(String),
+    #[error(
+        "invalid symbol reference {0:?} (expected a name, optionally plus or minus an offset)"
+    )]
+    Invalid(String),
+}
+
+/// Where a symbol lies in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol {
+    pub address: u32,
+    /// ARM9 overlay the symbol belongs to, or `None` for the static ARM9 code.
+    pub overlay_id: Option<u32>,
+}
+
+/// Names of functions & variables of a game, mapped to their RAM addresses.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolMap {
+    symbols: HashMap<String, Symbol>,
+}
+
+fn parse_address(text: &str) -> Option<u32> {
+    let hex = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u32::from_str_radix(hex, 16)
+        .ok()
+        .or_else(|| u64::from_str_radix(hex, 16).ok().map(|value| value as u32))
+}
+
+fn is_name(text: &str) -> bool {
+    text.starts_with(|char: char| char.is_ascii_alphabetic() || char == '_')
+        && text
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || matches!(char, '_' | '.' | '$'))
+}
+
+impl SymbolMap {
+    /// Parses a symbol file, with one symbol per line in any of these forms:
+    ///
+    /// - `02004A30 fn_LoadText`, as in no$gba `.sym` files and GNU ld `.map` files, or the other
+    ///   way around;
+    /// - `fn_LoadText = 0x02004A30;`, as in linker scripts.
+    ///
+    /// Symbols following an `[overlay N]` line belong to overlay N, and those following an
+    /// `[arm9]` line to the static ARM9 code, which is the default. Lines starting with `;`,
+    /// `#` or `//` are comments. In lenient mode, lines that don't define a symbol are skipped
+    /// instead of being an error, as map files are full of them.
+    pub fn parse(text: &str, lenient: bool) -> Result<Self, ParseSymbolsError> {
+        let mut map = SymbolMap::default();
+        let mut overlay_id = None;
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with([';', '#']) || line.starts_with("//") {
+                continue;
+            }
+            if let Some(section) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                let section = section.trim().to_ascii_lowercase();
+                overlay_id =
+                    match section.strip_prefix("overlay") {
+                        _ if section == "arm9" => None,
+                        Some(id) => Some(id.trim().parse().map_err(|_| {
+                            ParseSymbolsError::InvalidSection {
+                                line: index + 1,
+                                text: line.to_owned(),
+                            }
+                        })?),
+                        None => {
+                            return Err(ParseSymbolsError::InvalidSection {
+                                line: index + 1,
+                                text: line.to_owned(),
+                            })
+                        }
+                    };
+                continue;
+            }
+
+            let definition = match line.split_once('=') {
+                Some((name, value)) => {
+                    let value = value.trim().trim_end_matches(';').trim_end();
+                    Some((name.trim(), value))
+                }
+                None => match line.split_whitespace().collect::<Vec<_>>()[..] {
+                    [first, second] if is_name(first) && !is_name(second) => Some((first, second)),
+                    [value, name] => Some((name, value)),
+                    _ => None,
+                },
+            };
+            let symbol = definition
+                .filter(|(name, _)| is_name(name))
+                .and_then(|(name, value)| Some((name, parse_address(value)?)));
+            let Some((name, address)) = symbol else {
+                if lenient {
+                    continue;
+                }
+                return Err(ParseSymbolsError::InvalidLine {
+                    line: index + 1,
+                    text: line.to_owned(),
+                });
+            };
+            map.insert(
+                name,
+                Symbol {
+                    address,
+                    overlay_id,
+                },
+            )?;
+        }
+        Ok(map)
+    }
+
+    fn insert(&mut self, name: &str, symbol: Symbol) -> Result<(), ParseSymbolsError> {
+        match self.symbols.insert(name.to_owned(), symbol) {
+            Some(existing) if existing != symbol => Err(ParseSymbolsError::Duplicate {
+                name: name.to_owned(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Loads and merges symbol files. Files with the `.map` extension are parsed leniently.
+    pub fn load(paths: &[impl AsRef<Path>]) -> anyhow::Result<Self> {
+        let mut map = SymbolMap::default();
+        for path in paths {
+            let path = path.as_ref();
+            let text = fs::read_to_string(path)
+                .with_context(|| format!("failed to read symbol file {path:?}"))?;
+            let lenient = path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("map"));
+            let parsed = SymbolMap::parse(&text, lenient)
+                .with_context(|| format!("failed to parse symbol file {path:?}"))?;
+            for (name, symbol) in parsed.symbols {
+                map.insert(&name, symbol)
+                    .with_context(|| format!("failed to merge symbol file {path:?}"))?;
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Symbol)> {
+        self.symbols
+            .iter()
+            .map(|(name, symbol)| (name.as_str(), *symbol))
+    }
+
+    /// Resolves a reference to a symbol, optionally plus or minus a hexadecimal offset, such as
+    /// `fn_LoadText+0x10`.
+    pub fn resolve(&self, reference: &str) -> Result<Symbol, ResolveSymbolError> {
+        let reference = reference.trim();
+        let invalid = || ResolveSymbolError::Invalid(reference.to_owned());
+        let (name, offset) = match reference.find(['+', '-']) {
+            Some(position) => {
+                let offset = parse_address(reference[position + 1..].trim()).ok_or_else(invalid)?;
+                let offset = if reference[position..].starts_with('-') {
+                    offset.wrapping_neg()
+                } else {
+                    offset
+                };
+                (reference[..position].trim(), offset)
+            }
+            None => (reference, 0),
+        };
+        if !is_name(name) {
+            return Err(invalid());
+        }
+        let symbol = self
+            .get(name)
+            .ok_or_else(|| ResolveSymbolError::Unknown(name.to_owned()))?;
+        Ok(Symbol {
+            address: symbol.address.wrapping_add(offset),
+            ..symbol
+        })
+    }
+}
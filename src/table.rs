@@ -0,0 +1,306 @@
+use std::{collections::BTreeMap, fmt::Write, path::Path};
+
+use anyhow::Context;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ParseTableError {
+    #[error("invalid entry on line {line_number}: {line:?}")]
+    InvalidEntry { line_number: usize, line: String },
+    #[error("invalid hex sequence on line {line_number}: {hex:?}")]
+    InvalidHex { line_number: usize, hex: String },
+}
+
+#[derive(Error, Debug)]
+pub enum EncodeTableError {
+    #[error("character {ch:?} has no entry in the table")]
+    UnmappedCharacter { ch: char },
+    #[error("control code {name:?} takes {expected} parameter bytes (found: {found})")]
+    ParameterCountMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("invalid control code parameters {params:?}")]
+    InvalidParameters { params: String },
+}
+
+/// A control code with a name and a fixed number of parameter bytes following it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ControlCode {
+    name: String,
+    param_count: usize,
+}
+
+/// A Thingy-style character table, mapping byte sequences to text.
+///
+/// Each line of a table file is one of:
+/// - `XXXX=text`: the byte sequence `XXXX` (in hex, as stored in the file) decodes to `text`.
+/// - `*XX`: a line break, decoded as `\n`.
+/// - `/XX`: a string terminator.
+/// - `$XX=name,N`: a control code followed by `N` parameter bytes, decoded as `[name]` or
+///   `[name:AA BB]`.
+///
+/// Blank lines and lines starting with `;` or `#` are ignored. Bytes with no entry are decoded
+/// as `{XX}`, which is also accepted when encoding.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CharTable {
+    decode: BTreeMap<Vec<u8>, String>,
+    /// Text to bytes. When several sequences decode to the same text, the first one is used.
+    encode: BTreeMap<String, Vec<u8>>,
+    control_codes: BTreeMap<Vec<u8>, ControlCode>,
+    terminators: Vec<Vec<u8>>,
+    max_sequence_len: usize,
+    /// Length in bytes of the longest text in the table.
+    max_text_len: usize,
+}
+
+fn parse_hex(line_number: usize, hex: &str) -> Result<Vec<u8>, ParseTableError> {
+    let invalid_hex = || ParseTableError::InvalidHex {
+        line_number,
+        hex: hex.to_owned(),
+    };
+    if hex.is_empty() || !hex.len().is_multiple_of(2) {
+        return Err(invalid_hex());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|idx| {
+            hex.get(idx..idx + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(invalid_hex)
+        })
+        .collect()
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut output, byte| {
+        if !output.is_empty() {
+            output.push(' ');
+        }
+        let _ = write!(output, "{byte:02X}");
+        output
+    })
+}
+
+impl CharTable {
+    pub fn parse(contents: &str) -> Result<Self, ParseTableError> {
+        let mut table = Self::default();
+        for (line_idx, line) in contents.trim_start_matches('\u{FEFF}').lines().enumerate() {
+            let line_number = line_idx + 1;
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with([';', '#']) {
+                continue;
+            }
+            let invalid_entry = || ParseTableError::InvalidEntry {
+                line_number,
+                line: line.to_owned(),
+            };
+
+            if let Some(entry) = line.strip_prefix('*') {
+                let (hex, _) = entry.split_once('=').unwrap_or((entry, ""));
+                table.insert(parse_hex(line_number, hex.trim())?, "\n".to_owned());
+            } else if let Some(entry) = line.strip_prefix('/') {
+                let (hex, _) = entry.split_once('=').unwrap_or((entry, ""));
+                let sequence = parse_hex(line_number, hex.trim())?;
+                table.max_sequence_len = table.max_sequence_len.max(sequence.len());
+                table.terminators.push(sequence);
+            } else if let Some(entry) = line.strip_prefix('$') {
+                let (hex, code) = entry.split_once('=').ok_or_else(invalid_entry)?;
+                let (name, param_count) = match code.rsplit_once(',') {
+                    Some((name, param_count)) => (
+                        name,
+                        param_count.trim().parse().map_err(|_| invalid_entry())?,
+                    ),
+                    None => (code, 0),
+                };
+                let name = name.trim_matches(|ch| ch == '[' || ch == ']');
+                if name.is_empty() {
+                    return Err(invalid_entry());
+                }
+                let sequence = parse_hex(line_number, hex.trim())?;
+                table.max_sequence_len = table.max_sequence_len.max(sequence.len());
+                table.control_codes.insert(
+                    sequence,
+                    ControlCode {
+                        name: name.to_owned(),
+                        param_count,
+                    },
+                );
+            } else {
+                let (hex, text) = line.split_once('=').ok_or_else(invalid_entry)?;
+                table.insert(parse_hex(line_number, hex.trim())?, text.to_owned());
+            }
+        }
+        Ok(table)
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read table file {path:?}"))?;
+        Self::parse(&contents).with_context(|| format!("failed to parse table file {path:?}"))
+    }
+
+    fn insert(&mut self, sequence: Vec<u8>, text: String) {
+        self.max_sequence_len = self.max_sequence_len.max(sequence.len());
+        if !text.is_empty() {
+            self.max_text_len = self.max_text_len.max(text.len());
+            self.encode
+                .entry(text.clone())
+                .or_insert_with(|| sequence.clone());
+        }
+        self.decode.insert(sequence, text);
+    }
+
+    /// The byte sequence strings are terminated with: the first terminator in the table, or a
+    /// single 0 byte if it has none.
+    pub fn terminator(&self) -> &[u8] {
+        self.terminators.first().map_or(&[0], Vec::as_slice)
+    }
+
+    fn starts_with_terminator(&self, data: &[u8]) -> bool {
+        if self.terminators.is_empty() {
+            return data.first() == Some(&0) && !self.decode.contains_key(&data[..1]);
+        }
+        self.terminators
+            .iter()
+            .any(|terminator| data.starts_with(terminator))
+    }
+
+    /// Decodes a string from the start of `data`, up to and not including its terminator.
+    /// Returns `None` if no terminator is found before the end of the data.
+    pub fn decode(&self, data: &[u8]) -> Option<String> {
+        let mut output = String::new();
+        let mut rest = data;
+        loop {
+            if rest.is_empty() {
+                return None;
+            }
+            if self.starts_with_terminator(rest) {
+                return Some(output);
+            }
+
+            let longest_match = (1..=self.max_sequence_len.min(rest.len()))
+                .rev()
+                .find_map(|len| {
+                    let sequence = &rest[..len];
+                    if let Some(text) = self.decode.get(sequence) {
+                        return Some((len, text.clone()));
+                    }
+                    let code = self.control_codes.get(sequence)?;
+                    let params = rest.get(len..len + code.param_count)?;
+                    let text = if params.is_empty() {
+                        format!("[{}]", code.name)
+                    } else {
+                        format!("[{}:{}]", code.name, hex_string(params))
+                    };
+                    Some((len + params.len(), text))
+                });
+            match longest_match {
+                Some((len, text)) => {
+                    output.push_str(&text);
+                    rest = &rest[len..];
+                }
+                None => {
+                    let _ = write!(output, "{{{:02X}}}", rest[0]);
+                    rest = &rest[1..];
+                }
+            }
+        }
+    }
+
+    /// Encodes a string, appending the table's terminator.
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, EncodeTableError> {
+        let mut output = Vec::new();
+        let mut rest = text;
+        while !rest.is_empty() {
+            if let Some((len, bytes)) = self.encode_escape(rest)? {
+                output.extend_from_slice(&bytes);
+                rest = &rest[len..];
+                continue;
+            }
+
+            // Try the longest text with an entry first.
+            let longest_match = rest
+                .char_indices()
+                .map(|(idx, ch)| idx + ch.len_utf8())
+                .take_while(|&len| len <= self.max_text_len)
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+                .find_map(|len| Some((len, self.encode.get(&rest[..len])?)));
+            match longest_match {
+                Some((len, bytes)) => {
+                    output.extend_from_slice(bytes);
+                    rest = &rest[len..];
+                }
+                None => {
+                    let ch = rest.chars().next().unwrap();
+                    return Err(EncodeTableError::UnmappedCharacter { ch });
+                }
+            }
+        }
+        output.extend_from_slice(self.terminator());
+        Ok(output)
+    }
+
+    /// Removes the control codes and raw bytes of a string, keeping the characters that are
+    /// drawn.
+    pub fn printable_text(&self, text: &str) -> String {
+        let mut output = String::new();
+        let mut rest = text;
+        while let Some(ch) = rest.chars().next() {
+            if let Ok(Some((len, _))) = self.encode_escape(rest) {
+                rest = &rest[len..];
+            } else {
+                output.push(ch);
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+        output
+    }
+
+    /// Encodes a `[name]`/`[name:AA BB]` control code or a `{XX}` raw byte at the start of the
+    /// text given, if there is one. Returns the length of text consumed and the resulting bytes.
+    fn encode_escape(&self, text: &str) -> Result<Option<(usize, Vec<u8>)>, EncodeTableError> {
+        if let Some(hex) = text
+            .strip_prefix('{')
+            .and_then(|rest| rest.get(..3))
+            .and_then(|hex| hex.strip_suffix('}'))
+            .filter(|hex| hex.bytes().all(|digit| digit.is_ascii_hexdigit()))
+        {
+            return Ok(Some((4, vec![u8::from_str_radix(hex, 16).unwrap()])));
+        }
+
+        let Some(end) = text.strip_prefix('[').and_then(|rest| rest.find(']')) else {
+            return Ok(None);
+        };
+        let contents = &text[1..end + 1];
+        let (name, params) = contents.split_once(':').unwrap_or((contents, ""));
+        let Some((sequence, code)) = self
+            .control_codes
+            .iter()
+            .find(|(_, code)| code.name == name)
+        else {
+            // Not a control code: leave it to the regular table entries.
+            return Ok(None);
+        };
+        let params = params
+            .split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| EncodeTableError::InvalidParameters {
+                params: params.to_owned(),
+            })?;
+        if params.len() != code.param_count {
+            return Err(EncodeTableError::ParameterCountMismatch {
+                name: name.to_owned(),
+                expected: code.param_count,
+                found: params.len(),
+            });
+        }
+        let mut bytes = sequence.clone();
+        bytes.extend_from_slice(&params);
+        Ok(Some((end + 2, bytes)))
+    }
+}
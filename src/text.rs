@@ -0,0 +1,989 @@
+use std::{char::DecodeUtf16Error, collections::BTreeMap, fmt, ops::Range, path::Path};
+
+use anyhow::anyhow;
+use byteorder::ReadBytesExt;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    bmg::{self, Bmg, BuildBmgError, ParseBmgError},
+    control_codes::{ControlCodes, EncodeControlCodeError},
+    table::{CharTable, EncodeTableError},
+};
+
+#[derive(Error, Debug)]
+pub enum ParseTextError {
+    #[error("file read error")]
+    Io(#[from] std::io::Error),
+    #[error("UTF-16 character decode error")]
+    Utf16(#[from] DecodeUtf16Error),
+    #[error("invalid pointer found on header")]
+    InvalidPointer,
+    #[error("{0} records don't evenly fill the file")]
+    UnevenRecords(usize),
+    #[error("string {index} is not terminated")]
+    UnterminatedString { index: usize },
+    #[error("string {index} is not valid {encoding}")]
+    InvalidEncoding {
+        index: usize,
+        encoding: &'static str,
+    },
+    #[error("invalid BMG file")]
+    Bmg(#[from] ParseBmgError),
+}
+
+#[derive(Error, Debug)]
+pub enum BuildTextError {
+    #[error("failed to encode control codes in string {index}")]
+    ControlCode {
+        index: usize,
+        #[source]
+        source: EncodeControlCodeError,
+    },
+    #[error("failed to encode string {index}")]
+    Table {
+        index: usize,
+        #[source]
+        source: EncodeTableError,
+    },
+    #[error("the original layout can only be preserved with the same number of strings")]
+    LayoutUnavailable,
+    #[error("string {index} contains characters that can't be encoded as {encoding}")]
+    Unencodable {
+        index: usize,
+        encoding: &'static str,
+    },
+    #[error("string {index} lies out of the reach of 16-bit pointers")]
+    PointerOutOfRange { index: usize },
+    #[error("string {index} is longer than the {size}-byte records of the file")]
+    RecordOverflow { index: usize, size: usize },
+    #[error("failed to build BMG file")]
+    Bmg(#[from] BuildBmgError),
+}
+
+/// Character encodings text files can use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EncodingKind {
+    /// NUL-terminated UTF-16LE
+    Utf16le,
+    /// NUL-terminated Shift-JIS
+    ShiftJis,
+    /// NUL-terminated ASCII
+    Ascii,
+    /// A Thingy-style character table
+    Table,
+}
+
+/// How the strings of a text file are encoded.
+#[derive(Debug, Clone)]
+pub enum TextEncoding {
+    /// NUL-terminated UTF-16LE, with control codes escaped as described by [`ControlCodes`].
+    Utf16Le(ControlCodes),
+    /// NUL-terminated Shift-JIS.
+    ShiftJis,
+    /// NUL-terminated printable ASCII, plus tabs and line breaks.
+    Ascii,
+    /// A custom encoding described by a character table.
+    Table(CharTable),
+}
+
+/// Splits a NUL-terminated byte string from the start of `data`.
+fn byte_string(data: &[u8], index: usize) -> Result<&[u8], ParseTextError> {
+    let len = data
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or(ParseTextError::UnterminatedString { index })?;
+    Ok(&data[..len])
+}
+
+fn is_ascii_text(ch: char) -> bool {
+    matches!(ch, ' '..='~' | '\t' | '\n' | '\r')
+}
+
+impl Default for TextEncoding {
+    fn default() -> Self {
+        Self::Utf16Le(ControlCodes::default())
+    }
+}
+
+impl TextEncoding {
+    /// Loads an encoding along with the character table or control code file it uses. If no
+    /// kind is given, UTF-16LE is used, unless a table is given.
+    pub fn load(
+        kind: Option<EncodingKind>,
+        table: Option<&Path>,
+        control_codes: Option<&Path>,
+    ) -> anyhow::Result<Self> {
+        let kind = kind.unwrap_or(if table.is_some() {
+            EncodingKind::Table
+        } else {
+            EncodingKind::Utf16le
+        });
+        if table.is_some() && kind != EncodingKind::Table {
+            return Err(anyhow!(
+                "a character table can only be used with the `table` encoding"
+            ));
+        }
+        if control_codes.is_some() && kind != EncodingKind::Utf16le {
+            return Err(anyhow!("control codes can only be used with UTF-16LE text"));
+        }
+
+        Ok(match kind {
+            EncodingKind::Utf16le => match control_codes {
+                Some(path) => Self::Utf16Le(ControlCodes::load(path)?),
+                None => Self::default(),
+            },
+            EncodingKind::ShiftJis => Self::ShiftJis,
+            EncodingKind::Ascii => Self::Ascii,
+            EncodingKind::Table => {
+                let path = table.ok_or_else(|| {
+                    anyhow!("the `table` encoding requires a character table file")
+                })?;
+                Self::Table(CharTable::load(path)?)
+            }
+        })
+    }
+
+    fn decode(&self, data: &[u8], index: usize) -> Result<String, ParseTextError> {
+        match self {
+            Self::Utf16Le(control_codes) => Ok(control_codes.decode(data)?),
+            Self::ShiftJis => encoding_rs::SHIFT_JIS
+                .decode_without_bom_handling_and_without_replacement(byte_string(data, index)?)
+                .map(|string| string.into_owned())
+                .ok_or(ParseTextError::InvalidEncoding {
+                    index,
+                    encoding: "Shift-JIS",
+                }),
+            Self::Ascii => {
+                let bytes = byte_string(data, index)?;
+                if !bytes.iter().all(|&byte| is_ascii_text(byte.into())) {
+                    return Err(ParseTextError::InvalidEncoding {
+                        index,
+                        encoding: "ASCII",
+                    });
+                }
+                Ok(bytes.iter().map(|&byte| char::from(byte)).collect())
+            }
+            Self::Table(table) => table
+                .decode(data)
+                .ok_or(ParseTextError::UnterminatedString { index }),
+        }
+    }
+
+    /// Encodes a string, terminator included. `index` is the index of the string, given in
+    /// errors.
+    pub fn encode(&self, string: &str, index: usize) -> Result<Vec<u8>, BuildTextError> {
+        match self {
+            Self::Utf16Le(control_codes) => control_codes
+                .encode(string)
+                .map_err(|source| BuildTextError::ControlCode { index, source }),
+            Self::ShiftJis => {
+                let (bytes, _, had_errors) = encoding_rs::SHIFT_JIS.encode(string);
+                if had_errors || bytes.contains(&0) {
+                    return Err(BuildTextError::Unencodable {
+                        index,
+                        encoding: "Shift-JIS",
+                    });
+                }
+                Ok(bytes.iter().copied().chain([0]).collect())
+            }
+            Self::Ascii => {
+                if !string.chars().all(is_ascii_text) {
+                    return Err(BuildTextError::Unencodable {
+                        index,
+                        encoding: "ASCII",
+                    });
+                }
+                Ok(string.bytes().chain([0]).collect())
+            }
+            Self::Table(table) => table
+                .encode(string)
+                .map_err(|source| BuildTextError::Table { index, source }),
+        }
+    }
+}
+
+impl TextEncoding {
+    /// Removes the escaped control codes of a string, keeping the characters that are drawn.
+    pub fn printable_text(&self, string: &str) -> String {
+        match self {
+            Self::Utf16Le(control_codes) => control_codes.printable_text(string),
+            Self::ShiftJis | Self::Ascii => string.to_owned(),
+            Self::Table(table) => table.printable_text(string),
+        }
+    }
+
+    /// Opening and closing delimiters of the escapes strings of this encoding are exported with:
+    /// control codes, and bytes or units with no character.
+    pub fn escape_delimiters(&self) -> &'static [(char, char)] {
+        match self {
+            Self::Utf16Le(_) => &[('{', '}')],
+            Self::Table(_) => &[('[', ']'), ('{', '}')],
+            Self::ShiftJis | Self::Ascii => &[],
+        }
+    }
+
+    /// The control codes of UTF-16 text, used for UTF-16 BMG files whatever the encoding.
+    fn control_codes(&self) -> ControlCodes {
+        match self {
+            Self::Utf16Le(control_codes) => control_codes.clone(),
+            Self::ShiftJis | Self::Ascii | Self::Table(_) => ControlCodes::default(),
+        }
+    }
+
+    /// Width in bytes of the code units of the encoding. Strings are aligned to it.
+    fn unit_size(&self) -> usize {
+        match self {
+            Self::Utf16Le(_) => 2,
+            Self::ShiftJis | Self::Ascii | Self::Table(_) => 1,
+        }
+    }
+}
+
+/// How the strings of a text file are laid out when building it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TextLayout {
+    /// Every string gets its own data block, in entry order
+    Sequential,
+    /// Identical strings share one data block
+    #[default]
+    Deduplicated,
+    /// Keep every string where it was in the original file, moving only the ones that no longer
+    /// fit to the end
+    Preserve,
+}
+
+/// How the table at the start of a text file locates its strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TableFormat {
+    /// A u32 count, followed by absolute u32 pointers to terminated strings
+    #[default]
+    Pointers32,
+    /// A u16 count, followed by absolute u16 pointers to terminated strings
+    Pointers16,
+    /// A u32 count, followed by u32 pointers relative to the end of the table
+    RelativePointers,
+    /// A u32 count, followed by pairs of absolute u32 offsets and u32 lengths in bytes, the
+    /// lengths counting the terminators of the strings
+    OffsetLength,
+    /// A u32 count, followed by records of equal size filling the rest of the file, each holding
+    /// a terminated string padded with zeroes
+    FixedRecords,
+}
+
+impl fmt::Display for TableFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pointers32 => "32-bit pointers",
+            Self::Pointers16 => "16-bit pointers",
+            Self::RelativePointers => "relative pointers",
+            Self::OffsetLength => "offset and length pairs",
+            Self::FixedRecords => "fixed-size records",
+        })
+    }
+}
+
+impl TableFormat {
+    /// Size of the table of a file with `count` strings, count included.
+    fn table_size(self, count: usize) -> usize {
+        match self {
+            Self::Pointers32 | Self::RelativePointers => (count + 1) * 4,
+            Self::Pointers16 => (count + 1) * 2,
+            Self::OffsetLength => (count + 1) * 8 - 4,
+            Self::FixedRecords => 4,
+        }
+    }
+
+    /// Reads the table of a file, returning the absolute offset of each string and the data it
+    /// is read from, which ends at the end of the string for formats giving lengths.
+    fn read_table(self, data: &[u8]) -> Result<Vec<Range<usize>>, ParseTextError> {
+        use byteorder::LittleEndian;
+
+        let mut header = data;
+        let count = match self {
+            Self::Pointers16 => header.read_u16::<LittleEndian>()? as usize,
+            _ => header.read_u32::<LittleEndian>()? as usize,
+        };
+        if self == Self::FixedRecords {
+            let records_size = data.len() - 4;
+            // Records are at least a byte long.
+            if count == 0 || count > records_size || !records_size.is_multiple_of(count) {
+                return Err(ParseTextError::UnevenRecords(count));
+            }
+            let record_size = records_size / count;
+            return Ok((0..count)
+                .map(|index| 4 + index * record_size..4 + (index + 1) * record_size)
+                .collect());
+        }
+        // Kept as it always was for the original format, which doesn't count the count.
+        let minimum_pointer = match self {
+            Self::Pointers32 => count * 4,
+            _ => self.table_size(count),
+        };
+        (0..count)
+            .map(|_| {
+                let range = match self {
+                    Self::Pointers16 => header.read_u16::<LittleEndian>()? as usize..data.len(),
+                    Self::RelativePointers => {
+                        let pointer = header.read_u32::<LittleEndian>()? as usize;
+                        self.table_size(count).saturating_add(pointer)..data.len()
+                    }
+                    Self::OffsetLength => {
+                        let offset = header.read_u32::<LittleEndian>()? as usize;
+                        let length = header.read_u32::<LittleEndian>()? as usize;
+                        offset..offset.saturating_add(length)
+                    }
+                    _ => header.read_u32::<LittleEndian>()? as usize..data.len(),
+                };
+                if range.start < minimum_pointer
+                    || range.start > range.end
+                    || range.end > data.len()
+                {
+                    return Err(ParseTextError::InvalidPointer);
+                }
+                Ok(range)
+            })
+            .collect()
+    }
+
+    /// Writes the table of a file over its start, given where each string was placed.
+    fn write_table(
+        self,
+        data: &mut [u8],
+        pointers: &[usize],
+        encoded: &[Vec<u8>],
+    ) -> Result<(), BuildTextError> {
+        let table_size = self.table_size(pointers.len());
+        let mut table = Vec::with_capacity(table_size);
+        match self {
+            Self::Pointers16 => table.extend_from_slice(&(pointers.len() as u16).to_le_bytes()),
+            _ => table.extend_from_slice(&(pointers.len() as u32).to_le_bytes()),
+        }
+        for (index, &pointer) in pointers.iter().enumerate() {
+            match self {
+                Self::Pointers32 => table.extend_from_slice(&(pointer as u32).to_le_bytes()),
+                Self::Pointers16 => {
+                    let pointer = u16::try_from(pointer)
+                        .map_err(|_| BuildTextError::PointerOutOfRange { index })?;
+                    table.extend_from_slice(&pointer.to_le_bytes());
+                }
+                Self::RelativePointers => {
+                    table.extend_from_slice(&((pointer - table_size) as u32).to_le_bytes())
+                }
+                Self::OffsetLength => {
+                    table.extend_from_slice(&(pointer as u32).to_le_bytes());
+                    table.extend_from_slice(&(encoded[index].len() as u32).to_le_bytes());
+                }
+                Self::FixedRecords => {}
+            }
+        }
+        data[..table_size].copy_from_slice(&table);
+        Ok(())
+    }
+}
+
+/// Where the strings of a parsed text file were stored.
+#[derive(Debug, Clone)]
+struct OriginalLayout {
+    pointers: Vec<usize>,
+    data: Vec<u8>,
+}
+
+/// The strings of a text file: a table locating them, in one of the [`TableFormat`]s, and the
+/// terminated strings themselves. BMG message files are also read as text files, their messages
+/// being the strings, and are rebuilt as BMG files.
+#[derive(Debug, Clone, Default)]
+pub struct TextArchive {
+    pub strings: Vec<String>,
+    format: TableFormat,
+    original: Option<OriginalLayout>,
+    /// The BMG file the strings were read from, if they were.
+    bmg: Option<Bmg>,
+}
+
+/// Alignment of the size of a text file. The space left after the last string is zeroed.
+const TEXT_FILE_ALIGNMENT: usize = 4;
+
+impl TextArchive {
+    pub fn new(strings: Vec<String>) -> Self {
+        Self {
+            strings,
+            format: TableFormat::default(),
+            original: None,
+            bmg: None,
+        }
+    }
+
+    /// Parses a text file, detecting the format of its table.
+    pub fn parse(data: &[u8], encoding: &TextEncoding) -> Result<Self, ParseTextError> {
+        Self::parse_with(data, encoding, None)
+    }
+
+    /// Parses a text file whose table is in the format given, or in the first format it can be
+    /// read as if none is. Formats are first tried requiring the first string to lie right after
+    /// the table, as the tables of other formats and random data could pass for them otherwise;
+    /// only the original format is then tried without.
+    pub fn parse_with(
+        data: &[u8],
+        encoding: &TextEncoding,
+        format: Option<TableFormat>,
+    ) -> Result<Self, ParseTextError> {
+        if bmg::is_bmg(data) {
+            let bmg = Bmg::parse(data, &encoding.control_codes())?;
+            return Ok(Self {
+                strings: bmg.messages.clone(),
+                format: TableFormat::default(),
+                original: None,
+                bmg: Some(bmg),
+            });
+        }
+        if let Some(format) = format {
+            return Self::parse_table(data, encoding, format);
+        }
+        // Fixed-size records go last, as the table of files of other formats can often be read
+        // as part of the first record.
+        let pointer_formats = TableFormat::value_variants()
+            .iter()
+            .filter(|&&format| format != TableFormat::FixedRecords);
+        for &format in pointer_formats {
+            if let Some(archive) = Self::parse_table(data, encoding, format)
+                .ok()
+                .filter(Self::starts_after_table)
+            {
+                return Ok(archive);
+            }
+        }
+        Self::parse_table(data, encoding, TableFormat::Pointers32).or_else(|error| {
+            Self::parse_table(data, encoding, TableFormat::FixedRecords).map_err(|_| error)
+        })
+    }
+
+    fn parse_table(
+        data: &[u8],
+        encoding: &TextEncoding,
+        format: TableFormat,
+    ) -> Result<Self, ParseTextError> {
+        let ranges = format.read_table(data)?;
+        let strings = ranges
+            .iter()
+            .enumerate()
+            .map(|(index, range)| encoding.decode(&data[range.clone()], index))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            strings,
+            format,
+            original: Some(OriginalLayout {
+                pointers: ranges.into_iter().map(|range| range.start).collect(),
+                data: data.to_vec(),
+            }),
+            bmg: None,
+        })
+    }
+
+    /// Whether the first string of the parsed file lies right after its table.
+    pub fn starts_after_table(&self) -> bool {
+        self.original.as_ref().is_some_and(|original| {
+            original.pointers.iter().min() == Some(&self.format.table_size(original.pointers.len()))
+        })
+    }
+
+    /// The format of the table of the file, or `None` for BMG files.
+    pub fn format(&self) -> Option<TableFormat> {
+        self.bmg.is_none().then_some(self.format)
+    }
+
+    /// Builds the archive with a table in the format given.
+    pub fn with_format(self, format: TableFormat) -> Self {
+        Self { format, ..self }
+    }
+
+    /// Replaces the strings of the archive, keeping the original layout around so that it can
+    /// still be preserved.
+    pub fn with_strings(self, strings: Vec<String>) -> Self {
+        Self { strings, ..self }
+    }
+
+    /// Whether the archive can be built with [`TextLayout::Preserve`]: it must come from a
+    /// parsed file, and have the same number of strings as it. BMG files are rebuilt with their
+    /// messages in order, which preserves their layout as long as their size is unchanged.
+    pub fn can_preserve_layout(&self) -> bool {
+        self.bmg.is_some()
+            || self
+                .original
+                .as_ref()
+                .is_some_and(|original| original.pointers.len() == self.strings.len())
+    }
+
+    pub fn to_bytes(
+        &self,
+        encoding: &TextEncoding,
+        layout: TextLayout,
+    ) -> Result<Vec<u8>, BuildTextError> {
+        if let Some(bmg) = &self.bmg {
+            let bmg = bmg.clone().with_messages(self.strings.clone());
+            return Ok(bmg.to_bytes(
+                &encoding.control_codes(),
+                layout == TextLayout::Deduplicated,
+            )?);
+        }
+        let encoded = self
+            .strings
+            .iter()
+            .enumerate()
+            .map(|(index, string)| encoding.encode(string, index))
+            .collect::<Result<Vec<_>, _>>()?;
+        if self.format == TableFormat::FixedRecords {
+            return self.fixed_records(&encoded);
+        }
+
+        let (mut data, pointers) = match (layout, &self.original) {
+            (TextLayout::Preserve, Some(original)) if self.can_preserve_layout() => {
+                preserved_layout(original, &encoded, encoding.unit_size())
+            }
+            (TextLayout::Preserve, _) => return Err(BuildTextError::LayoutUnavailable),
+            (TextLayout::Sequential | TextLayout::Deduplicated, _) => {
+                let header_size = self.format.table_size(encoded.len());
+                let mut data = vec![0; header_size];
+                let mut offsets = BTreeMap::new();
+                let pointers = encoded
+                    .iter()
+                    .map(|string| {
+                        if layout == TextLayout::Deduplicated {
+                            if let Some(&offset) = offsets.get(string) {
+                                return offset;
+                            }
+                        }
+                        let offset = data.len();
+                        data.extend_from_slice(string);
+                        offsets.insert(string, offset);
+                        offset
+                    })
+                    .collect();
+                data.resize(data.len().next_multiple_of(TEXT_FILE_ALIGNMENT), 0);
+                (data, pointers)
+            }
+        };
+
+        self.format.write_table(&mut data, &pointers, &encoded)?;
+        Ok(data)
+    }
+
+    /// Builds a file of fixed-size records, as large as the original ones or, for new files,
+    /// as the longest string.
+    fn fixed_records(&self, encoded: &[Vec<u8>]) -> Result<Vec<u8>, BuildTextError> {
+        let record_size = match &self.original {
+            Some(original) if !original.pointers.is_empty() => {
+                (original.data.len() - 4) / original.pointers.len()
+            }
+            _ => encoded.iter().map(Vec::len).max().unwrap_or(0),
+        };
+        let mut data = (encoded.len() as u32).to_le_bytes().to_vec();
+        for (index, string) in encoded.iter().enumerate() {
+            if string.len() > record_size {
+                return Err(BuildTextError::RecordOverflow {
+                    index,
+                    size: record_size,
+                });
+            }
+            data.extend_from_slice(string);
+            data.resize(4 + (index + 1) * record_size, 0);
+        }
+        Ok(data)
+    }
+}
+
+/// Lays out encoded strings over the original data of a text file. Strings keep their original
+/// location if they are unchanged or still fit in it; the rest are appended at the end.
+fn preserved_layout(
+    original: &OriginalLayout,
+    encoded: &[Vec<u8>],
+    unit_size: usize,
+) -> (Vec<u8>, Vec<usize>) {
+    let mut data = original.data.clone();
+    let mut pointers = original.pointers.clone();
+    // The space each original pointer owns, up to the next one.
+    let mut distinct_pointers = original.pointers.clone();
+    distinct_pointers.sort_unstable();
+    distinct_pointers.dedup();
+    let allocation_end = |pointer: usize| {
+        distinct_pointers
+            .iter()
+            .find(|&&other| other > pointer)
+            .copied()
+            .unwrap_or(original.data.len())
+    };
+
+    let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (index, &pointer) in original.pointers.iter().enumerate() {
+        groups.entry(pointer).or_default().push(index);
+    }
+    let mut relocated = Vec::new();
+    for (pointer, indices) in groups {
+        let original_bytes = &original.data[pointer..];
+        let all_same = indices
+            .iter()
+            .all(|&index| encoded[index] == encoded[indices[0]]);
+        let first = &encoded[indices[0]];
+        if all_same
+            && !original_bytes.starts_with(first)
+            && pointer + first.len() <= allocation_end(pointer)
+        {
+            // Every string in the block changed the same way, and still fits.
+            data[pointer..allocation_end(pointer)].fill(0);
+            data[pointer..pointer + first.len()].copy_from_slice(first);
+            continue;
+        }
+        relocated.extend(
+            indices
+                .into_iter()
+                .filter(|&index| !original_bytes.starts_with(&encoded[index])),
+        );
+    }
+
+    let mut offsets: BTreeMap<&[u8], usize> = BTreeMap::new();
+    for index in relocated {
+        let string = encoded[index].as_slice();
+        pointers[index] = *offsets.entry(string).or_insert_with(|| {
+            data.resize(data.len().next_multiple_of(unit_size), 0);
+            let offset = data.len();
+            data.extend_from_slice(string);
+            offset
+        });
+    }
+    if data.len() != original.data.len() {
+        data.resize(data.len().next_multiple_of(TEXT_FILE_ALIGNMENT), 0);
+    }
+    (data, pointers)
+}
+
+pub fn parse_text_file(
+    data: &[u8],
+    encoding: &TextEncoding,
+) -> Result<Vec<String>, ParseTextError> {
+    Ok(TextArchive::parse(data, encoding)?.strings)
+}
+
+const TEMPLATE: &str = include_str!("text_entry_template");
+
+/// Converts the strings of a text file to the text entry template format, along with the
+/// original strings they are a translation of, which are the strings themselves if `None`.
+pub fn export_template(strings: &[String], originals: Option<&[String]>) -> String {
+    let originals = originals.unwrap_or(strings);
+    strings
+        .iter()
+        .enumerate()
+        .map(|(idx, str)| {
+            let original = originals.get(idx).unwrap_or(str);
+            // The strings go in after the markers before them are substituted, so that markers
+            // in them aren't.
+            let (head, tail) = TEMPLATE.split_once("{{text}}").unwrap();
+            let head = head
+                .replace("{{index}}", &idx.to_string())
+                .replace("{{original}}", &escape_template_text(original));
+            format!("{head}{}{tail}", escape_template_text(str))
+        })
+        .collect()
+}
+
+/// Escapes a string for the text block of a template entry, which ends at the first `'''`.
+///
+/// Quotes are escaped as `\'` only where they would end the block: as the third of a run, or
+/// at the end of the string, right before the closing quotes. Backslashes are escaped as `\\`
+/// only before quotes, backslashes and the end of the string, so that they read back the same
+/// in files written before escaping existed.
+pub(crate) fn escape_template_text(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+    let mut quote_run = 0;
+    let mut chars = string.chars().peekable();
+    while let Some(ch) = chars.next() {
+        let next = chars.peek();
+        match ch {
+            '\\' if matches!(next, None | Some('\\' | '\'')) => escaped.push_str("\\\\"),
+            '\'' if quote_run == 2 || next.is_none() => {
+                escaped.push_str("\\'");
+                quote_run = 0;
+                continue;
+            }
+            _ => escaped.push(ch),
+        }
+        quote_run = if ch == '\'' { quote_run + 1 } else { 0 };
+    }
+    escaped
+}
+
+/// Reads the text block of a template entry up to its closing quotes, returning the unescaped
+/// text and the length of the block. Backslashes followed by anything other than a quote or
+/// another backslash are kept as they are.
+fn unescape_template_text(block: &str) -> Option<(String, usize)> {
+    let mut text = String::new();
+    let mut chars = block.char_indices().peekable();
+    while let Some((position, ch)) = chars.next() {
+        match ch {
+            '\\' => match chars.peek() {
+                Some(&(_, next @ ('\\' | '\''))) => {
+                    text.push(next);
+                    chars.next();
+                }
+                _ => text.push(ch),
+            },
+            '\'' if block[position..].starts_with("'''") => return Some((text, position)),
+            _ => text.push(ch),
+        }
+    }
+    None
+}
+
+#[derive(Error, Debug)]
+pub enum ImportTemplateError {
+    #[error("unterminated text entry (index {index})")]
+    UnterminatedEntry { index: usize },
+    #[error("text entry without a [[strings]] header (index {index})")]
+    MissingHeader { index: usize },
+    #[error("[[strings]] header without text (index {index})")]
+    MissingText { index: usize },
+    #[error("entries are out of order (expected index {expected}, found {found})")]
+    IndexMismatch { expected: usize, found: usize },
+    #[error("unexpected contents on line {line_number}: {line:?}")]
+    UnexpectedLine { line_number: usize, line: String },
+}
+
+/// An entry of a file the strings of a text file were exported to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEntry {
+    pub text: String,
+    /// The original string the text is a translation of, if the entry gives it.
+    pub original: Option<String>,
+    /// Whether the entry is marked as needing review, as PO files do with the `fuzzy` flag.
+    pub fuzzy: bool,
+}
+
+impl TextEntry {
+    /// Whether the text was changed from the original string, which is unknown if the entry
+    /// doesn't give it.
+    pub fn is_translated(&self) -> Option<bool> {
+        self.original
+            .as_ref()
+            .map(|original| *original != self.text)
+    }
+}
+
+/// Reads back the strings from a file in the text entry template format. Entries without text
+/// fall back to their original string.
+pub fn import_template(template: &str) -> Result<Vec<String>, ImportTemplateError> {
+    Ok(import_template_entries(template)?
+        .into_iter()
+        .map(|entry| entry.text)
+        .collect())
+}
+
+/// Reads back the entries of a file in the text entry template format.
+///
+/// Each entry starts with a `[[strings]]` header, optionally followed by an `// Index N`
+/// comment which must match the position of the entry, and then its original string between
+/// `original = '''` and `'''` and its text between `text = '''` and `'''`, both escaped as
+/// [`export_template`] does. Either of them can be left out, entries without text taking their
+/// original string as it. Blank lines between entries are ignored.
+pub fn import_template_entries(template: &str) -> Result<Vec<TextEntry>, ImportTemplateError> {
+    const HEADER: &str = "[[strings]]";
+    const INDEX_PREFIX: &str = "// Index ";
+    const ORIGINAL_START: &str = "original = '''\n";
+    const TEXT_START: &str = "text = '''\n";
+    const TEXT_END: &str = "'''";
+
+    /// The entry being read: its text and original string, if read yet.
+    type PartialEntry = (Option<String>, Option<String>);
+    let finish = |(text, original): PartialEntry, index| match (text, original) {
+        (Some(text), original) => Ok(TextEntry {
+            text,
+            original,
+            fuzzy: false,
+        }),
+        (None, Some(original)) => Ok(TextEntry {
+            text: original.clone(),
+            original: Some(original),
+            fuzzy: false,
+        }),
+        (None, None) => Err(ImportTemplateError::MissingText { index }),
+    };
+
+    let mut entries = Vec::new();
+    let mut entry: Option<PartialEntry> = None;
+    let mut rest = template;
+    let mut line_number = 1;
+    while !rest.is_empty() {
+        let index = entries.len();
+        let block = rest
+            .strip_prefix(TEXT_START)
+            .map(|block| (block, true))
+            .or_else(|| {
+                rest.strip_prefix(ORIGINAL_START)
+                    .map(|block| (block, false))
+            });
+        if let Some((block, is_text)) = block {
+            let Some((text, original)) = &mut entry else {
+                return Err(ImportTemplateError::MissingHeader { index });
+            };
+            let (string, end) = unescape_template_text(block)
+                .ok_or(ImportTemplateError::UnterminatedEntry { index })?;
+            let field = if is_text { text } else { original };
+            if field.is_some() {
+                return Err(ImportTemplateError::UnexpectedLine {
+                    line_number,
+                    line: rest.lines().next().unwrap_or_default().to_owned(),
+                });
+            }
+            *field = Some(string);
+            line_number += block[..end].matches('\n').count() + 1;
+            rest = &block[end + TEXT_END.len()..];
+            continue;
+        }
+
+        let (line, next) = rest.split_once('\n').unwrap_or((rest, ""));
+        let unexpected_line = || ImportTemplateError::UnexpectedLine {
+            line_number,
+            line: line.to_owned(),
+        };
+        let trimmed = line.trim();
+        if trimmed == HEADER {
+            if let Some(entry) = entry.take() {
+                entries.push(finish(entry, index)?);
+            }
+            entry = Some((None, None));
+        } else if let Some(found) = trimmed.strip_prefix(INDEX_PREFIX) {
+            let found = found.trim().parse().map_err(|_| unexpected_line())?;
+            if found != index {
+                return Err(ImportTemplateError::IndexMismatch {
+                    expected: index,
+                    found,
+                });
+            }
+        } else if !trimmed.is_empty() {
+            return Err(unexpected_line());
+        }
+        rest = next;
+        line_number += 1;
+    }
+    if let Some(entry) = entry {
+        let index = entries.len();
+        entries.push(finish(entry, index)?);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Strings containing the quotes, backslashes and markers of the template format.
+    fn adversarial_strings() -> Vec<String> {
+        [
+            "",
+            "plain text",
+            "don't",
+            "'",
+            "''",
+            "'''",
+            "''''''''",
+            "ends with a quote'",
+            "'starts with a quote",
+            "a ''' in the middle",
+            "\\",
+            "\\\\",
+            "\\'",
+            "'\\",
+            "\\n is not a line break",
+            "ends with a backslash\\",
+            "\n",
+            "\n\n",
+            "line\n'''\n[[strings]]\ntext = '''\nline",
+            "// Index 3",
+            "{{text}}",
+            "{{index}}",
+            "{braces} {{and}} }}more{{",
+            "\r\n",
+            "ünïcödé 日本語 \u{1F600}",
+        ]
+        .into_iter()
+        .map(str::to_owned)
+        .collect()
+    }
+
+    #[test]
+    fn template_roundtrip() {
+        let strings = adversarial_strings();
+        let template = export_template(&strings, None);
+        assert_eq!(import_template(&template).unwrap(), strings);
+    }
+
+    #[test]
+    fn template_roundtrip_each() {
+        for string in adversarial_strings() {
+            let strings = vec![string];
+            let template = export_template(&strings, None);
+            assert_eq!(
+                import_template(&template).unwrap(),
+                strings,
+                "template: {template:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn template_roundtrip_concatenated() {
+        let strings = adversarial_strings();
+        for first in &strings {
+            for second in &strings {
+                let pair = [format!("{first}{second}")];
+                let template = export_template(&pair, None);
+                assert_eq!(import_template(&template).unwrap(), pair);
+            }
+        }
+    }
+
+    #[test]
+    fn template_keeps_unescaped_text() {
+        let template = export_template(&["don't \\d".to_owned()], None);
+        assert!(template.contains("text = '''\ndon't \\d'''"));
+        let imported = import_template("[[strings]]\ntext = '''\na \\b'''\n").unwrap();
+        assert_eq!(imported, ["a \\b"]);
+    }
+
+    #[test]
+    fn template_keeps_originals() {
+        let strings = ["translated '".to_owned(), "same".to_owned()];
+        let originals = ["original '''".to_owned(), "same".to_owned()];
+        let template = export_template(&strings, Some(&originals));
+        let entries = import_template_entries(&template).unwrap();
+        let originals = entries
+            .iter()
+            .map(|entry| entry.original.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(originals, [Some("original '''"), Some("same")]);
+        let translated = entries
+            .iter()
+            .map(TextEntry::is_translated)
+            .collect::<Vec<_>>();
+        assert_eq!(translated, [Some(true), Some(false)]);
+    }
+
+    #[test]
+    fn template_falls_back_to_originals() {
+        let template = "[[strings]]\noriginal = '''\nkept'''\n[[strings]]\ntext = '''\nnew'''\n";
+        assert_eq!(import_template(template).unwrap(), ["kept", "new"]);
+        assert!(import_template("[[strings]]\n[[strings]]\ntext = '''\na'''\n").is_err());
+    }
+
+    #[test]
+    fn fixed_records_reject_empty_records() {
+        let data = [0xFF, 0xFF, 0xFF, 0x7F];
+        let encoding = TextEncoding::default();
+        assert!(
+            TextArchive::parse_with(&data, &encoding, Some(TableFormat::FixedRecords)).is_err()
+        );
+        assert!(TextArchive::parse(&data, &encoding).is_err());
+    }
+}
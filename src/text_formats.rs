@@ -0,0 +1,554 @@
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::text::{self, ImportTemplateError, TextEntry};
+
+/// Human-editable formats the strings of a text file can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TextFormat {
+    /// The text entry template format used by `unpack`
+    Template,
+    /// Comma-separated values, with an `index`, an `original` and a `text` column
+    Csv,
+    /// gettext PO, with the entry index as the message context
+    Po,
+}
+
+#[derive(Error, Debug)]
+pub enum ImportTextError {
+    #[error("invalid template")]
+    Template(#[from] ImportTemplateError),
+    #[error("line {line_number}: {message}")]
+    Syntax { line_number: usize, message: String },
+    #[error("entry index {index} is missing")]
+    MissingIndex { index: usize },
+    #[error("entry index {index} appears more than once")]
+    DuplicateIndex { index: usize },
+    #[error("entry {key} appears more than once")]
+    DuplicateKey { key: ScriptKey },
+}
+
+pub(crate) fn syntax_error(line_number: usize, message: impl Into<String>) -> ImportTextError {
+    ImportTextError::Syntax {
+        line_number,
+        message: message.into(),
+    }
+}
+
+impl TextFormat {
+    /// Picks a format from the extension of a file name, if it has a known one.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "txt" => Some(Self::Template),
+            "csv" => Some(Self::Csv),
+            "po" | "pot" => Some(Self::Po),
+            _ => None,
+        }
+    }
+
+    /// Extension files in this format are given.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Template => "txt",
+            Self::Csv => "csv",
+            Self::Po => "po",
+        }
+    }
+
+    /// Exports strings along with the original strings they are a translation of, which are
+    /// the strings themselves if `None`.
+    pub fn export(self, strings: &[String], originals: Option<&[String]>) -> String {
+        match self {
+            Self::Template => text::export_template(strings, originals),
+            Self::Csv => export_csv(strings, originals),
+            Self::Po => export_po(strings, originals),
+        }
+    }
+
+    pub fn import(self, contents: &str) -> Result<Vec<String>, ImportTextError> {
+        Ok(self
+            .import_entries(contents)?
+            .into_iter()
+            .map(|entry| entry.text)
+            .collect())
+    }
+
+    /// Imports strings along with the original strings the file gives for them.
+    pub fn import_entries(self, contents: &str) -> Result<Vec<TextEntry>, ImportTextError> {
+        match self {
+            Self::Template => Ok(text::import_template_entries(contents)?),
+            Self::Csv => import_csv(contents),
+            Self::Po => import_po(contents),
+        }
+    }
+}
+
+/// Puts indexed entries back in order, checking that every index from 0 up is present once.
+fn collect_indexed(
+    entries: impl IntoIterator<Item = (usize, TextEntry)>,
+) -> Result<Vec<TextEntry>, ImportTextError> {
+    let mut strings = BTreeMap::new();
+    for (index, string) in entries {
+        if strings.insert(index, string).is_some() {
+            return Err(ImportTextError::DuplicateIndex { index });
+        }
+    }
+    strings
+        .into_iter()
+        .enumerate()
+        .map(|(expected, (index, string))| {
+            if index == expected {
+                Ok(string)
+            } else {
+                Err(ImportTextError::MissingIndex { index: expected })
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn quote_csv_field(field: &str) -> String {
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
+
+/// Exports strings to CSV, quoting every text field so that newlines & commas survive. The
+/// original strings go in a column of their own, left out on import.
+pub fn export_csv(strings: &[String], originals: Option<&[String]>) -> String {
+    let originals = originals.unwrap_or(strings);
+    write_csv(
+        "index",
+        strings.iter().enumerate().map(|(index, string)| {
+            let original = originals.get(index).unwrap_or(string);
+            (index.to_string(), original.as_str(), string.as_str())
+        }),
+    )
+}
+
+/// Writes rows of a key, an original string and a text to CSV, the keys going in a column
+/// named `key_column`. Keys are written as they are given.
+fn write_csv<'a>(
+    key_column: &str,
+    rows: impl IntoIterator<Item = (String, &'a str, &'a str)>,
+) -> String {
+    let mut output = format!("{key_column},original,text\n");
+    for (key, original, text) in rows {
+        output.push_str(&format!(
+            "{key},{},{}\n",
+            quote_csv_field(original),
+            quote_csv_field(text)
+        ));
+    }
+    output
+}
+
+/// Splits CSV contents into records of fields, following RFC 4180. Returns the line number each
+/// record starts at along with its fields.
+pub(crate) fn parse_csv_records(
+    contents: &str,
+) -> Result<Vec<(usize, Vec<String>)>, ImportTextError> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut line_number = 1;
+    let mut record_line_number = 1;
+    let mut chars = contents.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if field.is_empty() => {
+                let quote_line_number = line_number;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(ch) => {
+                            if ch == '\n' {
+                                line_number += 1;
+                            }
+                            field.push(ch);
+                        }
+                        None => return Err(syntax_error(quote_line_number, "unterminated quote")),
+                    }
+                }
+                if !matches!(chars.peek(), None | Some(',' | '\r' | '\n')) {
+                    return Err(syntax_error(line_number, "unexpected data after quote"));
+                }
+            }
+            ',' => fields.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                fields.push(std::mem::take(&mut field));
+                records.push((record_line_number, std::mem::take(&mut fields)));
+                line_number += 1;
+                record_line_number = line_number;
+            }
+            ch => field.push(ch),
+        }
+    }
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        records.push((record_line_number, fields));
+    }
+    Ok(records)
+}
+
+/// Imports strings from CSV with an `index` and a `text` column, and optionally an `original`
+/// one. Other columns are ignored, so that translators can add their own (e.g. notes).
+pub fn import_csv(contents: &str) -> Result<Vec<TextEntry>, ImportTextError> {
+    let entries = read_csv(contents, "index")?
+        .into_iter()
+        .map(|(line_number, index, entry)| {
+            let index = index
+                .trim()
+                .parse()
+                .map_err(|_| syntax_error(line_number, "invalid index"))?;
+            Ok((index, entry))
+        })
+        .collect::<Result<Vec<_>, ImportTextError>>()?;
+    collect_indexed(entries)
+}
+
+/// Reads the rows of CSV with a `key_column` and a `text` column, and optionally an `original`
+/// one, returning the line number each starts at, its key and its entry.
+fn read_csv(
+    contents: &str,
+    key_column: &str,
+) -> Result<Vec<(usize, String, TextEntry)>, ImportTextError> {
+    let mut records = parse_csv_records(contents.trim_start_matches('\u{FEFF}'))?.into_iter();
+    let Some((_, header)) = records.next() else {
+        return Ok(Vec::new());
+    };
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|field| field.trim().eq_ignore_ascii_case(name))
+            .ok_or_else(|| syntax_error(1, format!("missing {name:?} column")))
+    };
+    let key_column = column(key_column)?;
+    let text_column = column("text")?;
+    let original_column = column("original").ok();
+
+    records
+        .filter(|(_, fields)| fields.iter().any(|field| !field.is_empty()))
+        .map(|(line_number, mut fields)| {
+            let key = fields
+                .get(key_column)
+                .cloned()
+                .ok_or_else(|| syntax_error(line_number, "missing key"))?;
+            if fields.len() <= text_column {
+                return Err(syntax_error(line_number, "missing text"));
+            }
+            let original = original_column.and_then(|column| fields.get(column).cloned());
+            let text = fields.swap_remove(text_column);
+            let fuzzy = false;
+            Ok((
+                line_number,
+                key,
+                TextEntry {
+                    text,
+                    original,
+                    fuzzy,
+                },
+            ))
+        })
+        .collect()
+}
+
+fn quote_po_string(string: &str) -> String {
+    let escaped = |line: &str| {
+        let mut output = String::with_capacity(line.len() + 2);
+        output.push('"');
+        for ch in line.chars() {
+            match ch {
+                '"' => output.push_str("\\\""),
+                '\\' => output.push_str("\\\\"),
+                '\n' => output.push_str("\\n"),
+                '\t' => output.push_str("\\t"),
+                '\r' => output.push_str("\\r"),
+                ch => output.push(ch),
+            }
+        }
+        output.push('"');
+        output
+    };
+
+    // Multi-line strings are split after each newline, as gettext tools do.
+    if !string.trim_end_matches('\n').contains('\n') {
+        return escaped(string);
+    }
+    let mut output = String::from("\"\"");
+    for line in string.split_inclusive('\n') {
+        output.push('\n');
+        output.push_str(&escaped(line));
+    }
+    output
+}
+
+/// Exports strings to gettext PO, using each entry's index as its `msgctxt` and its original
+/// string as its `msgid`. Translations are left empty for strings equal to their original.
+pub fn export_po(strings: &[String], originals: Option<&[String]>) -> String {
+    let originals = originals.unwrap_or(strings);
+    write_po(strings.iter().enumerate().map(|(index, string)| {
+        let original = originals.get(index).unwrap_or(string);
+        (index.to_string(), original.as_str(), string.as_str())
+    }))
+}
+
+/// Writes rows of a message context, an original string and a text to gettext PO.
+fn write_po<'a>(rows: impl IntoIterator<Item = (String, &'a str, &'a str)>) -> String {
+    let mut output =
+        String::from("msgid \"\"\nmsgstr \"\"\n\"Content-Type: text/plain; charset=UTF-8\\n\"\n");
+    for (msgctxt, original, text) in rows {
+        let translation = if text == original { "" } else { text };
+        output.push_str(&format!(
+            "\nmsgctxt {}\nmsgid {}\nmsgstr {}\n",
+            quote_po_string(&msgctxt),
+            quote_po_string(original),
+            quote_po_string(translation)
+        ));
+    }
+    output
+}
+
+fn unquote_po_string(line_number: usize, quoted: &str) -> Result<String, ImportTextError> {
+    let inner = quoted
+        .strip_prefix('"')
+        .and_then(|quoted| quoted.strip_suffix('"'))
+        .filter(|_| quoted.len() >= 2)
+        .ok_or_else(|| syntax_error(line_number, "expected a quoted string"))?;
+    let mut output = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            output.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => output.push('\n'),
+            Some('t') => output.push('\t'),
+            Some('r') => output.push('\r'),
+            Some(ch @ ('"' | '\\')) => output.push(ch),
+            _ => return Err(syntax_error(line_number, "invalid escape sequence")),
+        }
+    }
+    Ok(output)
+}
+
+#[derive(Default)]
+struct PoEntry {
+    line_number: usize,
+    msgctxt: Option<String>,
+    msgid: String,
+    msgstr: String,
+    fuzzy: bool,
+}
+
+/// Imports strings from gettext PO. Each entry's `msgctxt` is its index; its translation is
+/// used if there is one, and its source text otherwise. The header entry is skipped, and the
+/// `fuzzy` flag of the others kept.
+pub fn import_po(contents: &str) -> Result<Vec<TextEntry>, ImportTextError> {
+    let indexed = read_po(contents)?
+        .into_iter()
+        .map(|(line_number, msgctxt, entry)| {
+            let index = msgctxt
+                .as_deref()
+                .and_then(|msgctxt| msgctxt.trim().parse().ok())
+                .ok_or_else(|| syntax_error(line_number, "msgctxt is not an entry index"))?;
+            Ok((index, entry))
+        })
+        .collect::<Result<Vec<_>, ImportTextError>>()?;
+    collect_indexed(indexed)
+}
+
+/// Reads the entries of gettext PO, returning the line number each starts at, its `msgctxt`
+/// and its entry. The header entry is skipped.
+fn read_po(contents: &str) -> Result<Vec<(usize, Option<String>, TextEntry)>, ImportTextError> {
+    #[derive(Clone, Copy)]
+    enum Field {
+        Msgctxt,
+        Msgid,
+        Msgstr,
+    }
+
+    let mut entries = Vec::new();
+    let mut entry: Option<PoEntry> = None;
+    let mut field = None;
+    let mut fuzzy = false;
+    for (line_idx, line) in contents.trim_start_matches('\u{FEFF}').lines().enumerate() {
+        let line_number = line_idx + 1;
+        let line = line.trim();
+        // Flags come before the entry they apply to.
+        if let Some(flags) = line.strip_prefix("#,") {
+            fuzzy |= flags.split(',').any(|flag| flag.trim() == "fuzzy");
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (keyword, value) = if line.starts_with('"') {
+            (None, line)
+        } else {
+            let (keyword, value) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| syntax_error(line_number, "expected a keyword and a string"))?;
+            (Some(keyword), value.trim())
+        };
+        let value = unquote_po_string(line_number, value)?;
+
+        if let Some(keyword) = keyword {
+            let new_field = match keyword {
+                "msgctxt" => Field::Msgctxt,
+                "msgid" => Field::Msgid,
+                "msgstr" | "msgstr[0]" => Field::Msgstr,
+                keyword => {
+                    return Err(syntax_error(
+                        line_number,
+                        format!("unsupported keyword {keyword:?}"),
+                    ))
+                }
+            };
+            // A msgctxt, or a msgid without one, starts a new entry.
+            let starts_entry = match new_field {
+                Field::Msgctxt => true,
+                Field::Msgid => !matches!(field, Some(Field::Msgctxt)),
+                Field::Msgstr => entry.is_none(),
+            };
+            if starts_entry {
+                entries.extend(entry.take());
+                entry = Some(PoEntry {
+                    line_number,
+                    fuzzy: std::mem::take(&mut fuzzy),
+                    ..Default::default()
+                });
+            }
+            field = Some(new_field);
+        }
+
+        let (Some(entry), Some(field)) = (&mut entry, field) else {
+            return Err(syntax_error(line_number, "string outside of an entry"));
+        };
+        match field {
+            Field::Msgctxt => entry
+                .msgctxt
+                .get_or_insert_with(String::new)
+                .push_str(&value),
+            Field::Msgid => entry.msgid.push_str(&value),
+            Field::Msgstr => entry.msgstr.push_str(&value),
+        }
+    }
+    entries.extend(entry);
+
+    Ok(entries
+        .into_iter()
+        .filter(|entry| entry.msgctxt.is_some() || !entry.msgid.is_empty())
+        .map(|entry| {
+            let text = if entry.msgstr.is_empty() {
+                entry.msgid.clone()
+            } else {
+                entry.msgstr
+            };
+            let original = Some(entry.msgid);
+            let fuzzy = entry.fuzzy;
+            (
+                entry.line_number,
+                entry.msgctxt,
+                TextEntry {
+                    text,
+                    original,
+                    fuzzy,
+                },
+            )
+        })
+        .collect())
+}
+
+/// Key of a string among those of several text files exported to a single file: the NitroFS
+/// path of its text file and its index in it, written `file#index`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ScriptKey {
+    pub file: String,
+    pub index: usize,
+}
+
+impl std::fmt::Display for ScriptKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}#{}", self.file, self.index)
+    }
+}
+
+impl std::str::FromStr for ScriptKey {
+    type Err = ();
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        let (file, index) = key.trim().rsplit_once('#').ok_or(())?;
+        Ok(Self {
+            file: file.to_owned(),
+            index: index.parse().map_err(|_| ())?,
+        })
+    }
+}
+
+/// Formats the strings of several text files can be exported to at once, as a single file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ScriptFormat {
+    /// Comma-separated values, with a `key`, an `original` and a `text` column
+    Csv,
+    /// gettext PO, with the key of each string as the message context
+    Po,
+}
+
+impl ScriptFormat {
+    /// Picks a format from the extension of a file name, if it has a known one.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match TextFormat::from_extension(extension)? {
+            TextFormat::Csv => Some(Self::Csv),
+            TextFormat::Po => Some(Self::Po),
+            TextFormat::Template => None,
+        }
+    }
+
+    /// Exports keyed entries, in the order given. Entries without an original string are taken
+    /// as untranslated.
+    pub fn export(self, entries: &[(ScriptKey, TextEntry)]) -> String {
+        let rows = entries.iter().map(|(key, entry)| {
+            let original = entry.original.as_deref().unwrap_or(&entry.text);
+            (key.to_string(), original, entry.text.as_str())
+        });
+        match self {
+            Self::Csv => write_csv(
+                "key",
+                rows.map(|(key, original, text)| (quote_csv_field(&key), original, text)),
+            ),
+            Self::Po => write_po(rows),
+        }
+    }
+
+    /// Imports keyed entries, checking that no key appears twice.
+    pub fn import(self, contents: &str) -> Result<Vec<(ScriptKey, TextEntry)>, ImportTextError> {
+        let rows = match self {
+            Self::Csv => read_csv(contents, "key")?
+                .into_iter()
+                .map(|(line_number, key, entry)| (line_number, Some(key), entry))
+                .collect(),
+            Self::Po => read_po(contents)?,
+        };
+        let mut keys = std::collections::BTreeSet::new();
+        rows.into_iter()
+            .map(|(line_number, key, entry)| {
+                let key = key
+                    .and_then(|key| key.parse::<ScriptKey>().ok())
+                    .ok_or_else(|| syntax_error(line_number, "invalid key, expected file#index"))?;
+                if !keys.insert(key.clone()) {
+                    return Err(ImportTextError::DuplicateKey { key });
+                }
+                Ok((key, entry))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,278 @@
+use thiserror::Error;
+
+use crate::gfx::{self, BitDepth, IndexedImage, Ncgr, Nclr, Nscr, TILE_SIZE};
+
+/// Ending of the file name of tileset images, after the name of the map.
+pub const TILESET_IMAGE_SUFFIX: &str = "_tileset.png";
+/// Tiles per row of exported tileset images.
+const TILESET_COLUMNS: usize = 32;
+/// Flags Tiled stores in the high bits of tile IDs.
+const FLIPPED_HORIZONTALLY: u32 = 1 << 31;
+const FLIPPED_VERTICALLY: u32 = 1 << 30;
+const FLIPPED_DIAGONALLY: u32 = 1 << 29;
+const ROTATED_HEXAGONAL: u32 = 1 << 28;
+/// Map property holding how many tiles each palette's copy of the tileset has.
+const TILES_PER_PALETTE_PROPERTY: &str = "tiles_per_palette";
+/// Number of 16-color palettes a screen entry can pick from.
+const MAX_PALETTES: usize = 16;
+/// Number of tiles a screen entry can refer to.
+const MAX_TILES: usize = 0x400;
+
+#[derive(Error, Debug)]
+pub enum TmxError {
+    #[error("missing <{0}> element")]
+    MissingElement(&'static str),
+    #[error("missing or invalid {attribute:?} attribute of <{element}>")]
+    InvalidAttribute {
+        element: &'static str,
+        attribute: &'static str,
+    },
+    #[error("missing {0:?} map property, was the map exported by ravends?")]
+    MissingProperty(&'static str),
+    #[error("unsupported tile layer format {0:?}, save the map with the CSV tile layer format")]
+    UnsupportedEncoding(String),
+    #[error("invalid tile {0:?}")]
+    InvalidTile(String),
+    #[error("the layer has {found} tiles, but the map is {width}x{height} tiles")]
+    TileCountMismatch {
+        found: usize,
+        width: usize,
+        height: usize,
+    },
+    #[error("the tile at ({x}, {y}) is rotated, which screens cannot do")]
+    Rotated { x: usize, y: usize },
+    #[error("the tile at ({x}, {y}) lies outside the tileset")]
+    OutsideTileset { x: usize, y: usize },
+}
+
+/// A screen exported as a Tiled map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TmxExport {
+    /// The map (`.tmx`), referring to the tileset.
+    pub tmx: String,
+    /// The tileset (`.tsx`), referring to its image.
+    pub tsx: String,
+    /// The tileset image: every tile, drawn once with each palette.
+    pub tileset: IndexedImage,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('"', "&quot;")
+}
+
+/// Exports a screen as a Tiled map whose single layer holds its entries. The map refers to its
+/// tileset as `<name>.tsx`, and the tileset to its image as `<name>_tileset.png`, both meant to
+/// be written next to the map.
+///
+/// As a tile's palette and flips are part of its screen entry, the tileset holds a copy of the
+/// tiles per 16-color palette, and flips are stored as Tiled's flip flags.
+pub fn export_tmx(ncgr: &Ncgr, nclr: &Nclr, nscr: &Nscr, name: &str) -> TmxExport {
+    let (width, height) = (nscr.width / TILE_SIZE, nscr.height / TILE_SIZE);
+    let entries = || nscr.entries.iter().take(width * height);
+    let tiles_per_palette = entries()
+        .map(|&entry| (entry & 0x3FF) as usize + 1)
+        .max()
+        .unwrap_or(0)
+        .max(ncgr.tile_count())
+        .max(1);
+    let palette_count = match ncgr.depth {
+        BitDepth::Bpp4 => entries()
+            .map(|&entry| (entry >> 12) as usize + 1)
+            .max()
+            .unwrap_or(0)
+            .max(nclr.colors.len() / 16)
+            .clamp(1, MAX_PALETTES),
+        BitDepth::Bpp8 => 1,
+    };
+    let tileset = gfx::render_tileset(
+        ncgr,
+        nclr,
+        tiles_per_palette,
+        palette_count,
+        TILESET_COLUMNS,
+    );
+
+    let tiles = (0..width * height)
+        .map(|index| {
+            let entry = nscr.entries.get(index).copied().unwrap_or(0);
+            let palette = match ncgr.depth {
+                BitDepth::Bpp4 => (entry >> 12) as u32,
+                BitDepth::Bpp8 => 0,
+            };
+            let mut id = 1 + palette * tiles_per_palette as u32 + (entry & 0x3FF) as u32;
+            if entry & 0x400 != 0 {
+                id |= FLIPPED_HORIZONTALLY;
+            }
+            if entry & 0x800 != 0 {
+                id |= FLIPPED_VERTICALLY;
+            }
+            id.to_string()
+        })
+        .collect::<Vec<_>>();
+    let rows = tiles
+        .chunks(width.max(1))
+        .map(|row| row.join(","))
+        .collect::<Vec<_>>()
+        .join(",\n");
+
+    let tmx = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" renderorder="right-down" width="{width}" height="{height}" tilewidth="{TILE_SIZE}" tileheight="{TILE_SIZE}" infinite="0" nextlayerid="2" nextobjectid="1">
+ <properties>
+  <property name="{TILES_PER_PALETTE_PROPERTY}" type="int" value="{tiles_per_palette}"/>
+ </properties>
+ <tileset firstgid="1" source="{}"/>
+ <layer id="1" name="screen" width="{width}" height="{height}">
+  <data encoding="csv">
+{rows}
+</data>
+ </layer>
+</map>
+"#,
+        escape(&format!("{name}.tsx"))
+    );
+    let tsx = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" name="{}" tilewidth="{TILE_SIZE}" tileheight="{TILE_SIZE}" tilecount="{}" columns="{TILESET_COLUMNS}">
+ <image source="{}" width="{}" height="{}"/>
+</tileset>
+"#,
+        escape(name),
+        tiles_per_palette * palette_count,
+        escape(&format!("{name}{TILESET_IMAGE_SUFFIX}")),
+        tileset.width,
+        tileset.height
+    );
+    TmxExport { tmx, tsx, tileset }
+}
+
+/// Finds the first start tag of an element, returning its attributes and the text following it.
+fn find_tag<'a>(xml: &'a str, element: &str) -> Option<(&'a str, &'a str)> {
+    let mut rest = xml;
+    loop {
+        rest = &rest[rest.find('<')? + 1..];
+        if let Some(after) = rest.strip_prefix(element) {
+            if after.starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/') {
+                let end = after.find('>')?;
+                return Some((&after[..end], &after[end + 1..]));
+            }
+        }
+    }
+}
+
+/// Reads the value of an attribute of a tag, as returned by [`find_tag`].
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+    loop {
+        let index = rest.find(name)?;
+        let preceded_by_space = rest[..index].ends_with(char::is_whitespace) || index == 0;
+        rest = &rest[index + name.len()..];
+        let Some(value) = rest.trim_start().strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        let Some(quote) = value.chars().next().filter(|&c| c == '"' || c == '\'') else {
+            continue;
+        };
+        if preceded_by_space {
+            let value = &value[1..];
+            return Some(&value[..value.find(quote)?]);
+        }
+    }
+}
+
+fn numeric_attribute(
+    tag: &str,
+    element: &'static str,
+    attribute_name: &'static str,
+) -> Result<usize, TmxError> {
+    attribute(tag, attribute_name)
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or(TmxError::InvalidAttribute {
+            element,
+            attribute: attribute_name,
+        })
+}
+
+/// Rebuilds a screen from the first tile layer of a Tiled map exported by [`export_tmx`]. The
+/// map can be resized, but its tile layer must be stored as CSV, which is Tiled's default.
+///
+/// Empty cells are given the first tile, with the first palette.
+pub fn import_tmx(tmx: &str) -> Result<Nscr, TmxError> {
+    let (map, _) = find_tag(tmx, "map").ok_or(TmxError::MissingElement("map"))?;
+    let width = numeric_attribute(map, "map", "width")?;
+    let height = numeric_attribute(map, "map", "height")?;
+
+    let mut rest = tmx;
+    let tiles_per_palette = loop {
+        let (property, after) = find_tag(rest, "property")
+            .ok_or(TmxError::MissingProperty(TILES_PER_PALETTE_PROPERTY))?;
+        if attribute(property, "name") == Some(TILES_PER_PALETTE_PROPERTY) {
+            break numeric_attribute(property, "property", "value")?.max(1);
+        }
+        rest = after;
+    };
+    let (tileset, _) = find_tag(tmx, "tileset").ok_or(TmxError::MissingElement("tileset"))?;
+    let first_id = numeric_attribute(tileset, "tileset", "firstgid")? as u32;
+
+    let (data, contents) = find_tag(tmx, "data").ok_or(TmxError::MissingElement("data"))?;
+    let encoding = attribute(data, "encoding").unwrap_or("xml");
+    if encoding != "csv" || attribute(data, "compression").is_some() {
+        return Err(TmxError::UnsupportedEncoding(encoding.to_owned()));
+    }
+    let contents = &contents[..contents.find('<').unwrap_or(contents.len())];
+    let tiles = contents
+        .split(',')
+        .map(str::trim)
+        .filter(|tile| !tile.is_empty())
+        .map(|tile| {
+            tile.parse::<u32>()
+                .map_err(|_| TmxError::InvalidTile(tile.to_owned()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if tiles.len() != width * height {
+        return Err(TmxError::TileCountMismatch {
+            found: tiles.len(),
+            width,
+            height,
+        });
+    }
+
+    let entries = tiles
+        .iter()
+        .enumerate()
+        .map(|(index, &id)| {
+            let (x, y) = (index % width, index / width);
+            if id & (FLIPPED_DIAGONALLY | ROTATED_HEXAGONAL) != 0 {
+                return Err(TmxError::Rotated { x, y });
+            }
+            let flags = id & (FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY);
+            let id = id & !(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY);
+            if id == 0 {
+                return Ok(0);
+            }
+            let index = id
+                .checked_sub(first_id)
+                .ok_or(TmxError::OutsideTileset { x, y })? as usize;
+            let (tile, palette) = (index % tiles_per_palette, index / tiles_per_palette);
+            if tile >= MAX_TILES || palette >= MAX_PALETTES {
+                return Err(TmxError::OutsideTileset { x, y });
+            }
+            let mut entry = tile as u16 | (palette as u16) << 12;
+            if flags & FLIPPED_HORIZONTALLY != 0 {
+                entry |= 0x400;
+            }
+            if flags & FLIPPED_VERTICALLY != 0 {
+                entry |= 0x800;
+            }
+            Ok(entry)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Nscr {
+        width: width * TILE_SIZE,
+        height: height * TILE_SIZE,
+        entries,
+    })
+}
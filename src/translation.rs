@@ -0,0 +1,216 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    manifest::{FileRecord, Format, Manifest, ORIGINALS_DIR, RAVENDS_DIR},
+    path_escape,
+    text::{self, TextEncoding, TextEntry},
+    text_formats::ScriptKey,
+};
+
+/// How many strings of a text file have been translated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TranslationStatus {
+    pub translated: usize,
+    /// Strings marked as needing review, or translated from an original string that has changed
+    /// since.
+    pub fuzzy: usize,
+    pub untranslated: usize,
+}
+
+impl TranslationStatus {
+    /// Counts the entries whose text differs from their original string. Entries without an
+    /// original string are left out, as are those with an empty one, which have nothing to
+    /// translate.
+    pub fn of_entries(entries: &[TextEntry]) -> Self {
+        let mut status = Self::default();
+        for entry in entries {
+            if entry
+                .original
+                .as_ref()
+                .is_some_and(|original| original.is_empty())
+            {
+                continue;
+            }
+            match entry.is_translated() {
+                None => {}
+                Some(_) if entry.fuzzy => status.fuzzy += 1,
+                Some(true) => status.translated += 1,
+                Some(false) => status.untranslated += 1,
+            }
+        }
+        status
+    }
+
+    /// Number of strings to translate.
+    pub fn total(self) -> usize {
+        self.translated + self.fuzzy + self.untranslated
+    }
+
+    /// Share of the strings translated, in percent. Files with nothing to translate are fully
+    /// translated.
+    pub fn percentage(self) -> f64 {
+        if self.total() == 0 {
+            100.0
+        } else {
+            self.translated as f64 * 100.0 / self.total() as f64
+        }
+    }
+
+    pub fn add(&mut self, other: Self) {
+        self.translated += other.translated;
+        self.fuzzy += other.fuzzy;
+        self.untranslated += other.untranslated;
+    }
+}
+
+/// Reads the entries of every text file of an unpacked ROM, along with its record. The original
+/// strings are read from the pristine copies `unpack` keeps of the text files, rather than from
+/// the exported files, so that edits to those don't count. Entries translated from an original
+/// string other than the pristine one are fuzzy.
+fn unpack_dir_entries(
+    unpack_dir: &Path,
+    encoding: &TextEncoding,
+) -> anyhow::Result<Vec<(FileRecord, Vec<TextEntry>)>> {
+    let manifest = Manifest::load(unpack_dir)?
+        .with_context(|| format!("{unpack_dir:?} has no manifest, was it unpacked?"))?;
+    let originals_dir = unpack_dir.join(RAVENDS_DIR).join(ORIGINALS_DIR);
+    manifest
+        .files
+        .into_iter()
+        .filter(|record| record.format == Format::Text)
+        .map(|record| {
+            let original_path = originals_dir.join(path_escape::escape(&record.path));
+            let original_data = fs::read(path_escape::long_path(&original_path))
+                .with_context(|| format!("failed to read the original of {:?}", record.path))?;
+            let original_data = record
+                .codec()
+                .decompress(&original_data)
+                .unwrap_or(original_data);
+            let originals = text::parse_text_file(&original_data, encoding)
+                .with_context(|| format!("failed to parse the original of {:?}", record.path))?;
+            let template = fs::read_to_string(unpack_dir.join(&record.unpacked_path))
+                .with_context(|| format!("failed to read {:?}", record.unpacked_path))?;
+            let mut entries = text::import_template_entries(&template)
+                .with_context(|| format!("failed to import {:?}", record.unpacked_path))?
+                .into_iter();
+            // Strings missing from the template are untranslated, and those added past the
+            // original ones have no original string to be a translation of.
+            let mut merged = originals
+                .into_iter()
+                .map(|original| match entries.next() {
+                    Some(entry) => TextEntry {
+                        fuzzy: entry.fuzzy
+                            || entry.text != original
+                                && entry.original.is_some_and(|from| from != original),
+                        text: entry.text,
+                        original: Some(original),
+                    },
+                    None => TextEntry {
+                        text: original.clone(),
+                        original: Some(original),
+                        fuzzy: false,
+                    },
+                })
+                .collect::<Vec<_>>();
+            merged.extend(entries.map(|entry| TextEntry {
+                original: None,
+                ..entry
+            }));
+            Ok((record, merged))
+        })
+        .collect()
+}
+
+/// Works out the translation status of every text file of an unpacked ROM, keyed by its
+/// unpacked path, as read by [`unpack_dir_entries`].
+pub fn unpack_dir_status(
+    unpack_dir: &Path,
+    encoding: &TextEncoding,
+) -> anyhow::Result<Vec<(String, TranslationStatus)>> {
+    Ok(unpack_dir_entries(unpack_dir, encoding)?
+        .into_iter()
+        .map(|(record, entries)| {
+            (
+                record.unpacked_path,
+                TranslationStatus::of_entries(&entries),
+            )
+        })
+        .collect())
+}
+
+/// Gathers the strings of every text file of an unpacked ROM into a single list, keyed by the
+/// NitroFS path of their file and their index in it, with the original strings of the ROM.
+pub fn export_script(
+    unpack_dir: &Path,
+    encoding: &TextEncoding,
+) -> anyhow::Result<Vec<(ScriptKey, TextEntry)>> {
+    let mut files = unpack_dir_entries(unpack_dir, encoding)?;
+    files.sort_by(|(a, _), (b, _)| a.path.cmp(&b.path));
+    Ok(files
+        .into_iter()
+        .flat_map(|(record, entries)| {
+            entries.into_iter().enumerate().map(move |(index, entry)| {
+                let key = ScriptKey {
+                    file: record.path.clone(),
+                    index,
+                };
+                (key, entry)
+            })
+        })
+        .collect())
+}
+
+/// What [`import_script`] changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScriptImport {
+    /// Text files rewritten.
+    pub files: usize,
+    /// Strings changed across them.
+    pub strings: usize,
+}
+
+/// Writes the strings of a list made by [`export_script`] back to the text files of the
+/// unpacked ROM they belong to, for `pack` to rebuild them. Strings left out of the list keep
+/// their text, and files with no string changed are left untouched.
+pub fn import_script(
+    unpack_dir: &Path,
+    encoding: &TextEncoding,
+    script: Vec<(ScriptKey, TextEntry)>,
+) -> anyhow::Result<ScriptImport> {
+    let mut files = unpack_dir_entries(unpack_dir, encoding)?
+        .into_iter()
+        .map(|(record, entries)| (record.path.clone(), (record, entries, 0)))
+        .collect::<BTreeMap<_, _>>();
+    for (key, entry) in script {
+        let (_, entries, changed) = files
+            .get_mut(&key.file)
+            .ok_or_else(|| anyhow!("{:?} is not a text file of the unpacked ROM", key.file))?;
+        let count = entries.len();
+        let target = entries
+            .get_mut(key.index)
+            .ok_or_else(|| anyhow!("{key} is past the {count} strings of its file"))?;
+        if target.text != entry.text {
+            target.text = entry.text;
+            *changed += 1;
+        }
+    }
+
+    let mut import = ScriptImport::default();
+    for (record, entries, changed) in files.into_values().filter(|(_, _, changed)| *changed > 0) {
+        let (strings, originals): (Vec<_>, Vec<_>) = entries
+            .into_iter()
+            .map(|entry| {
+                let original = entry.original.unwrap_or_else(|| entry.text.clone());
+                (entry.text, original)
+            })
+            .unzip();
+        let path = unpack_dir.join(&record.unpacked_path);
+        fs::write(&path, text::export_template(&strings, Some(&originals)))
+            .with_context(|| format!("failed to write {:?}", record.unpacked_path))?;
+        import.files += 1;
+        import.strings += changed;
+    }
+    Ok(import)
+}
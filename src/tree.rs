@@ -0,0 +1,143 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{lz10::decompress_lz10, lz11::decompress_lz11, rom};
+
+/// Compression detected on a file of the ROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Lz10,
+    Lz11,
+}
+
+impl Compression {
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Lz10 => "LZ10",
+            Compression::Lz11 => "LZ11",
+        }
+    }
+}
+
+/// A file of the NitroFS, as shown in the tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeFile {
+    pub name: String,
+    pub id: u16,
+    /// Offset of the file in the ROM, as stored in the FAT.
+    pub offset: u32,
+    pub size: usize,
+    /// The compression of the file and its decompressed size, if it decompresses successfully.
+    pub compression: Option<(Compression, usize)>,
+}
+
+/// A directory of the NitroFS, along with everything inside it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeDir {
+    pub dirs: BTreeMap<String, TreeDir>,
+    pub files: Vec<TreeFile>,
+}
+
+impl TreeDir {
+    /// Builds the tree of the NitroFS of a ROM, detecting the compression of every file.
+    pub fn from_rom(rom_data: &[u8]) -> anyhow::Result<Self> {
+        let fs = rom::filesystem(rom_data)?;
+        let mut root = TreeDir::default();
+        // Directories are added separately so that empty ones are shown too.
+        for dir in fs.dirs.values() {
+            root.dir_mut(&rom::nitro_path(&dir.path));
+        }
+        for entry in fs.files() {
+            let path = rom::nitro_path(&entry.path);
+            let (parent, name) = path.rsplit_once('/').unwrap_or(("", &path));
+            let data = rom::file_data(rom_data, entry);
+            let compression = decompress_lz10(data)
+                .ok()
+                .map(|decompressed| (Compression::Lz10, decompressed.len()))
+                .or_else(|| {
+                    decompress_lz11(data)
+                        .ok()
+                        .map(|decompressed| (Compression::Lz11, decompressed.len()))
+                });
+            root.dir_mut(parent).files.push(TreeFile {
+                name: name.to_owned(),
+                id: entry.id,
+                offset: entry.alloc.start,
+                size: data.len(),
+                compression,
+            });
+        }
+        Ok(root)
+    }
+
+    fn dir_mut(&mut self, path: &str) -> &mut TreeDir {
+        path.split('/')
+            .filter(|name| !name.is_empty())
+            .fold(self, |dir, name| {
+                dir.dirs.entry(name.to_owned()).or_default()
+            })
+    }
+
+    /// Number of files inside this directory and its subdirectories.
+    pub fn file_count(&self) -> usize {
+        self.files.len() + self.dirs.values().map(TreeDir::file_count).sum::<usize>()
+    }
+
+    /// Size of the files inside this directory and its subdirectories, as stored in the ROM.
+    pub fn total_size(&self) -> usize {
+        self.files.iter().map(|file| file.size).sum::<usize>()
+            + self.dirs.values().map(TreeDir::total_size).sum::<usize>()
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "{} files, 0x{:X} bytes",
+            self.file_count(),
+            self.total_size()
+        )
+    }
+
+    /// Renders the tree with box-drawing characters, one line per file or directory.
+    pub fn render(&self) -> String {
+        let mut output = format!("/ ({})\n", self.summary());
+        self.render_children(&mut output, "");
+        output
+    }
+
+    fn render_children(&self, output: &mut String, prefix: &str) {
+        let count = self.dirs.len() + self.files.len();
+        for (index, (name, dir)) in self.dirs.iter().enumerate() {
+            let last = index + 1 == count;
+            let _ = writeln!(
+                output,
+                "{prefix}{}{name}/ ({})",
+                if last { "└── " } else { "├── " },
+                dir.summary()
+            );
+            dir.render_children(
+                output,
+                &format!("{prefix}{}", if last { "    " } else { "│   " }),
+            );
+        }
+        for (index, file) in self.files.iter().enumerate() {
+            let last = self.dirs.len() + index + 1 == count;
+            let _ = write!(
+                output,
+                "{prefix}{}{} (ID {}, 0x{:X} bytes at 0x{:08X})",
+                if last { "└── " } else { "├── " },
+                file.name,
+                file.id,
+                file.size,
+                file.offset
+            );
+            if let Some((compression, decompressed_size)) = file.compression {
+                let _ = write!(
+                    output,
+                    " [{}, 0x{decompressed_size:X} bytes decompressed, {:.1}%]",
+                    compression.name(),
+                    file.size as f64 * 100.0 / decompressed_size.max(1) as f64
+                );
+            }
+            output.push('\n');
+        }
+    }
+}
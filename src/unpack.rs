@@ -0,0 +1,1323 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use log::{debug, info, warn};
+use std::fs;
+
+#[cfg(feature = "graphics")]
+use crate::{
+    banner,
+    gfx::{self, Nanr, Ncer, Ncgr, Nclr, Nscr},
+    manifest::{GraphicsRecord, IconRecord, PaletteRecord, GRAPHICS_DIR, ICON_DIR},
+    nsbtx::{self, Nsbtx},
+    palette::{self, PaletteFormat},
+};
+use crate::{
+    bmg,
+    cache::CompressionCache,
+    hashes::Hashes,
+    hook::Hooks,
+    lz10::decompress_lz10,
+    magic,
+    manifest::{
+        self, Codec, Compression, CompressionHeader, DirectoryRecord, ExtraDataRecord, FileRecord,
+        Format, LayoutRecord, Manifest, OrphanRecord, OverlayRecord, Processor, SectionRecord,
+        ORIGINALS_DIR, ORIGINAL_FNT_FILE_NAME, ORPHAN_DIR, RAVENDS_DIR, SYSTEM_DIR,
+    },
+    memory, narc, path_escape,
+    plugin::Plugins,
+    profile::Profile,
+    rom::{self, ExtraData, PathFilter, Section},
+    secure_area::{self, KeyTable, SecureAreaForm, SecureAreaState},
+    status::{ExitStatus, StatusError},
+    survey,
+    text::{self, TextArchive, TextEncoding},
+};
+#[cfg(feature = "audio")]
+use crate::{
+    manifest::SOUND_DIR,
+    sbnk::{ParseBankError, Sbnk},
+    sdat::{self, Sdat, SoundFile, SoundKind},
+    sf2::SoundFont,
+    sseq::Sseq,
+    wave::Wave,
+};
+
+/// Which transformations to apply to files taken out of a ROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Conversion {
+    /// Decompress LZ10 & LZ11 files and convert text files, including BMG message files, to the
+    /// text entry template format.
+    #[default]
+    Auto,
+    /// Decompress LZ10 & LZ11 files, but leave their contents as-is.
+    DecompressOnly,
+    /// Convert uncompressed text files, such as BMG message files, but leave compressed files
+    /// as they are stored.
+    TextOnly,
+    /// Leave files exactly as they are stored in the ROM.
+    None,
+}
+
+impl Conversion {
+    /// Picks the conversion applying the transformations given.
+    pub fn from_flags(decompress: bool, convert_text: bool) -> Self {
+        match (decompress, convert_text) {
+            (true, true) => Conversion::Auto,
+            (true, false) => Conversion::DecompressOnly,
+            (false, true) => Conversion::TextOnly,
+            (false, false) => Conversion::None,
+        }
+    }
+
+    /// Whether compressed files are decompressed.
+    pub fn decompresses(self) -> bool {
+        matches!(self, Conversion::Auto | Conversion::DecompressOnly)
+    }
+
+    /// Whether text files are converted to the text entry template format.
+    pub fn converts_text(self) -> bool {
+        matches!(self, Conversion::Auto | Conversion::TextOnly)
+    }
+}
+
+/// A file taken out of a ROM, after conversion.
+pub struct ConvertedFile {
+    pub data: Vec<u8>,
+    /// How the file was compressed in the ROM.
+    pub codec: Codec,
+    pub format: Format,
+    /// What the file was detected as, and what was done to it.
+    pub description: String,
+}
+
+/// Converts a file taken out of a ROM according to `conversion`, describing what was detected
+/// and updating the extension of `target_path` to match the resulting format. Text files are
+/// read with `encoding`.
+pub fn convert_file(
+    file_data: &[u8],
+    target_path: &mut PathBuf,
+    conversion: Conversion,
+    encoding: &TextEncoding,
+) -> ConvertedFile {
+    convert_file_with(
+        file_data,
+        target_path,
+        conversion,
+        encoding,
+        survey::DEFAULT_MIN_CONFIDENCE,
+    )
+}
+
+/// Converts a file like [`convert_file`], only converting it to a text file if it's detected as
+/// one with at least the confidence given, from 0 to 1.
+pub fn convert_file_with(
+    file_data: &[u8],
+    target_path: &mut PathBuf,
+    conversion: Conversion,
+    encoding: &TextEncoding,
+    min_confidence: f64,
+) -> ConvertedFile {
+    let unconverted = |description: String| ConvertedFile {
+        data: file_data.to_vec(),
+        codec: Codec::NONE,
+        format: Format::Binary,
+        description,
+    };
+
+    if conversion == Conversion::None {
+        return unconverted("raw".to_owned());
+    }
+
+    let Some((codec, decompressed_data)) = Codec::detect(file_data) else {
+        // BMG files are recognized by their magic number, so they are converted even when
+        // uncompressed.
+        if let Some(strings) = (conversion.converts_text() && bmg::is_bmg(file_data))
+            .then(|| text::parse_text_file(file_data, encoding).ok())
+            .flatten()
+        {
+            target_path.set_extension("txt");
+            return ConvertedFile {
+                data: text::export_template(&strings, None).into_bytes(),
+                codec: Codec::NONE,
+                format: Format::Text,
+                description: "BMG message file".to_owned(),
+            };
+        }
+        return unconverted(
+            magic::describe(file_data).unwrap_or_else(|| "unknown format".to_owned()),
+        );
+    };
+
+    if !conversion.decompresses() {
+        return unconverted(format!("compressed {codec} file, left compressed"));
+    }
+    target_path.set_extension("decomp");
+    if !conversion.converts_text() {
+        return ConvertedFile {
+            data: decompressed_data,
+            description: format!("compressed {codec} file, decompressed"),
+            codec,
+            format: Format::Binary,
+        };
+    }
+
+    let archive = TextArchive::parse(&decompressed_data, encoding)
+        .ok()
+        .filter(|archive| {
+            let (confidence, evidence) = survey::text_confidence(archive);
+            if confidence < min_confidence {
+                debug!(
+                    "not converting a possible text file, as it's one with only {:.0}% confidence: {}",
+                    confidence * 100.0,
+                    evidence.join(", ")
+                );
+            }
+            confidence >= min_confidence
+        });
+    match archive {
+        Some(archive) => {
+            target_path.set_extension("txt");
+            ConvertedFile {
+                data: text::export_template(&archive.strings, None).into_bytes(),
+                description: format!("compressed {codec} file, text file"),
+                codec,
+                format: Format::Text,
+            }
+        }
+        None => ConvertedFile {
+            description: format!(
+                "compressed {codec} file, {}",
+                magic::describe(&decompressed_data)
+                    .unwrap_or_else(|| "unknown contents".to_owned())
+            ),
+            data: decompressed_data,
+            codec,
+            format: Format::Binary,
+        },
+    }
+}
+
+/// Writes a file, creating its parent directories if needed.
+pub fn write_file(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let long_path = path_escape::long_path(path);
+    if let Some(parent) = long_path.parent() {
+        fs::create_dir_all(parent).context("failed to create directory in target")?;
+    }
+    fs::write(&long_path, data).with_context(|| format!("failed to write {path:?}"))
+}
+
+/// Name of the file an overlay with the given file ID is unpacked to, inside [`SYSTEM_DIR`].
+pub fn overlay_file_name(file_id: u16) -> String {
+    format!("overlay/overlay_{file_id:04}.bin")
+}
+
+/// Name of the file a FAT entry without a path, with the given file ID, is unpacked to, inside
+/// [`ORPHAN_DIR`].
+pub fn orphan_file_name(file_id: u16) -> String {
+    format!("file{file_id:04}.bin")
+}
+
+/// Name of the file data outside of everything the header and FAT locate is unpacked to, in
+/// the system directory.
+pub fn extra_data_file_name(kind: ExtraData, offset: usize) -> String {
+    match kind {
+        ExtraData::Arm9Footer => "arm9_footer.bin".to_owned(),
+        ExtraData::RsaSignature => "rsa_signature.bin".to_owned(),
+        ExtraData::Unreferenced => format!("extra/extra_{offset:08X}.bin"),
+    }
+}
+
+/// Name of the file a cell is exported to by [`write_cells`].
+#[cfg(feature = "graphics")]
+pub fn cell_file_name(index: usize) -> String {
+    format!("cell_{index:04}.png")
+}
+
+/// Name of the file animations are described in by [`write_cells`].
+#[cfg(feature = "graphics")]
+pub const ANIMATIONS_FILE_NAME: &str = "animations.json";
+
+/// Draws every cell of a cell bank to a PNG inside `target_dir`, along with a description of
+/// its animations if given, and returns the number of cells written.
+#[cfg(feature = "graphics")]
+pub fn write_cells(
+    target_dir: &Path,
+    ncgr: &Ncgr,
+    nclr: &Nclr,
+    ncer: &Ncer,
+    nanr: Option<&Nanr>,
+    dry_run: bool,
+) -> anyhow::Result<usize> {
+    for (index, cell) in ncer.cells.iter().enumerate() {
+        let image = gfx::render_cell(ncgr, nclr, ncer.mapping, cell);
+        let png_data = image
+            .to_png()
+            .with_context(|| format!("failed to encode cell {index}"))?;
+        if !dry_run {
+            write_file(&target_dir.join(cell_file_name(index)), &png_data)?;
+        }
+    }
+    if let Some(nanr) = nanr {
+        let json = serde_json::to_string_pretty(nanr).context("failed to serialize animations")?;
+        if !dry_run {
+            write_file(&target_dir.join(ANIMATIONS_FILE_NAME), json.as_bytes())?;
+        }
+    }
+    Ok(ncer.cells.len())
+}
+
+/// Replaces the characters of a name that are not safe in file names.
+#[cfg(any(feature = "graphics", feature = "audio"))]
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Name of the file a texture is exported to by [`write_textures`], keeping only characters
+/// that are safe in file names.
+#[cfg(feature = "graphics")]
+pub fn texture_file_name(name: &str) -> String {
+    format!("{}.png", sanitize_file_name(name))
+}
+
+/// Path of the file a sound file is extracted to by [`write_sounds`], inside a directory for
+/// its kind.
+#[cfg(feature = "audio")]
+pub fn sound_file_path(file: &SoundFile) -> PathBuf {
+    Path::new(file.kind.dir_name()).join(format!(
+        "{}.{}",
+        sanitize_file_name(&file.display_name()),
+        file.kind.extension()
+    ))
+}
+
+/// Name of the file a wave of a wave archive is converted to by [`write_sounds`], inside a
+/// directory of the same name as the archive.
+#[cfg(feature = "audio")]
+pub fn wave_file_name(index: usize) -> String {
+    format!("wave_{index:04}.wav")
+}
+
+/// Converts a wave to a WAV file, along with a JSON file of the same name describing its loop
+/// points if it loops.
+#[cfg(feature = "audio")]
+pub fn write_wave(path: &Path, wave: &Wave, dry_run: bool) -> anyhow::Result<()> {
+    if dry_run {
+        return Ok(());
+    }
+    write_file(path, &wave.to_wav())?;
+    if let Some(loop_points) = &wave.loop_points {
+        let json =
+            serde_json::to_string_pretty(loop_points).context("failed to serialize loop points")?;
+        write_file(&path.with_extension("json"), json.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Converts a bank of a sound archive to a SoundFont, with the waves of its wave archives.
+/// Wave archives that fail to parse are left out with a warning.
+#[cfg(feature = "audio")]
+pub fn bank_soundfont(sdat: &Sdat, bank: &SoundFile) -> Result<SoundFont, ParseBankError> {
+    let sbnk = Sbnk::parse(&bank.data)?;
+    let wave_archives = bank
+        .wave_archives
+        .iter()
+        .map(|&index| {
+            let file = sdat.file(SoundKind::WaveArchive, index?)?;
+            match Wave::parse_swar(&file.data) {
+                Ok(waves) => Some(waves),
+                Err(error) => {
+                    warn!(
+                        "failed to decode {} {}: {error}, leaving its waves out of bank {}",
+                        file.kind,
+                        file.display_name(),
+                        bank.display_name()
+                    );
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(sbnk.to_soundfont(&bank.display_name(), &wave_archives))
+}
+
+/// Extracts every file of a sound archive inside `target_dir`, and returns the number of files
+/// written. Sequences are also converted to MIDI files alongside them, banks to SoundFonts
+/// with the waves of their wave archives, and streams and the waves of wave archives to WAV
+/// files.
+#[cfg(feature = "audio")]
+pub fn write_sounds(target_dir: &Path, sdat: &Sdat, dry_run: bool) -> anyhow::Result<usize> {
+    for file in &sdat.files {
+        let path = target_dir.join(sound_file_path(file));
+        if !dry_run {
+            write_file(&path, &file.data)?;
+        }
+        let converted = match file.kind {
+            SoundKind::Sequence => Sseq::parse(&file.data)
+                .and_then(|sseq| sseq.to_midi())
+                .map_err(anyhow::Error::from)
+                .map(|midi| {
+                    if dry_run {
+                        return Ok(());
+                    }
+                    write_file(&path.with_extension("mid"), &midi)
+                }),
+            SoundKind::Bank => {
+                bank_soundfont(sdat, file)
+                    .map_err(anyhow::Error::from)
+                    .map(|soundfont| {
+                        if dry_run {
+                            return Ok(());
+                        }
+                        write_file(&path.with_extension("sf2"), &soundfont.to_bytes())
+                    })
+            }
+            SoundKind::Stream => Wave::parse_strm(&file.data)
+                .map_err(anyhow::Error::from)
+                .map(|wave| write_wave(&path.with_extension("wav"), &wave, dry_run)),
+            SoundKind::WaveArchive => Wave::parse_swar(&file.data)
+                .map_err(anyhow::Error::from)
+                .map(|waves| {
+                    waves.iter().enumerate().try_for_each(|(index, wave)| {
+                        let wave_path = path.with_extension("").join(wave_file_name(index));
+                        write_wave(&wave_path, wave, dry_run)
+                    })
+                }),
+            _ => continue,
+        };
+        match converted {
+            Ok(result) => result?,
+            Err(error) => warn!(
+                "failed to decode {} {}: {error}, not converting it",
+                file.kind,
+                file.display_name()
+            ),
+        }
+    }
+    if !dry_run {
+        sdat::manifest(sdat, |file| rom::nitro_path(&sound_file_path(file))).save(target_dir)?;
+    }
+    Ok(sdat.files.len())
+}
+
+/// Draws every texture of a texture archive to a PNG inside `target_dir`, and returns the
+/// number of textures written. Textures without a palette are skipped with a warning.
+#[cfg(feature = "graphics")]
+pub fn write_textures(target_dir: &Path, nsbtx: &Nsbtx, dry_run: bool) -> anyhow::Result<usize> {
+    let mut texture_count = 0;
+    for texture in &nsbtx.textures {
+        let image = match nsbtx::render_texture(texture, nsbtx.palette_for(texture)) {
+            Ok(image) => image,
+            Err(error) => {
+                warn!("{error}, not exporting it");
+                continue;
+            }
+        };
+        let png_data = image
+            .to_png()
+            .with_context(|| format!("failed to encode texture {:?}", texture.name))?;
+        if !dry_run {
+            write_file(
+                &target_dir.join(texture_file_name(&texture.name)),
+                &png_data,
+            )?;
+        }
+        texture_count += 1;
+    }
+    Ok(texture_count)
+}
+
+/// Parses a file that may be LZ10-compressed.
+pub fn parse_maybe_compressed<T, E>(
+    data: &[u8],
+    parse: impl Fn(&[u8]) -> Result<T, E>,
+) -> Option<T> {
+    parse(data)
+        .ok()
+        .or_else(|| parse(&decompress_lz10(data).ok()?).ok())
+}
+
+/// Graphics files found in a directory of the ROM, keyed by file stem.
+#[cfg(feature = "graphics")]
+#[derive(Default)]
+struct GraphicsDir {
+    graphics: BTreeMap<String, (PathBuf, Ncgr)>,
+    palettes: BTreeMap<String, (PathBuf, Nclr)>,
+    screens: BTreeMap<String, (PathBuf, Nscr)>,
+    cells: BTreeMap<String, (PathBuf, Ncer)>,
+    animations: BTreeMap<String, Nanr>,
+    textures: Vec<(PathBuf, Nsbtx)>,
+}
+
+#[cfg(feature = "graphics")]
+impl GraphicsDir {
+    /// The palette of the same name, or the only palette of the directory.
+    fn palette(&self, stem: &str) -> Option<&(PathBuf, Nclr)> {
+        let only_palette = (self.palettes.len() == 1)
+            .then(|| self.palettes.values().next())
+            .flatten();
+        self.palettes.get(stem).or(only_palette)
+    }
+}
+
+/// Exports the icon of the ROM's banner inside [`GRAPHICS_DIR`], animated DSi icons included,
+/// returning a record of it if the ROM has a banner.
+#[cfg(feature = "graphics")]
+fn export_icon(
+    rom_data: &[u8],
+    target_path: &Path,
+    dry_run: bool,
+) -> anyhow::Result<Option<IconRecord>> {
+    let Some(range) = Section::Banner.range(rom_data) else {
+        return Ok(None);
+    };
+    let files = banner::export_icon(&rom_data[range]).context("failed to export icon")?;
+    let export_dir = format!("{GRAPHICS_DIR}/{ICON_DIR}");
+    if !dry_run {
+        for (name, data) in &files {
+            write_file(&target_path.join(&export_dir).join(name), data)?;
+        }
+    }
+    Ok(Some(IconRecord {
+        export_hash: banner::export_hash(&files),
+        export_dir,
+    }))
+}
+
+/// Exports the NCGR graphics of the ROM to PNGs inside [`GRAPHICS_DIR`], and its NCLR palettes
+/// to the palette formats of image editors, returning records of the PNGs and palettes written.
+///
+/// Each NCGR file is drawn with the NCLR palette of the same name in its directory, or with
+/// the only palette of the directory if there is no such palette. If there is an NSCR screen
+/// of the same name, the screen is drawn instead of the raw tiles.
+///
+/// NCER cell banks are drawn cell by cell inside a directory of the same name as the file,
+/// using the NCGR graphics of the same name (or the only graphics of the directory), along with
+/// a description of the NANR animations of the same name. These are only meant for viewing and
+/// have no records. So are the textures of NSBTX texture archives and NSBMD models, drawn
+/// inside a directory of the same name as the file.
+#[cfg(feature = "graphics")]
+fn export_graphics(
+    rom_data: &[u8],
+    filter: &PathFilter,
+    target_path: &Path,
+    dry_run: bool,
+) -> anyhow::Result<(Vec<GraphicsRecord>, Vec<PaletteRecord>)> {
+    let fs = rom::filesystem(rom_data)?;
+    let mut dirs = BTreeMap::<PathBuf, GraphicsDir>::new();
+    for entry in fs.files() {
+        if !filter.matches(&entry.path) {
+            continue;
+        }
+        let data = rom::file_data(rom_data, entry);
+        let dir = dirs
+            .entry(entry.path.parent().unwrap_or(Path::new("")).to_path_buf())
+            .or_default();
+        let stem = entry
+            .path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let path = entry.path.clone();
+        if let Some(ncgr) = parse_maybe_compressed(data, Ncgr::parse) {
+            dir.graphics.insert(stem, (path, ncgr));
+        } else if let Some(nclr) = parse_maybe_compressed(data, Nclr::parse) {
+            dir.palettes.insert(stem, (path, nclr));
+        } else if let Some(nscr) = parse_maybe_compressed(data, Nscr::parse) {
+            dir.screens.insert(stem, (path, nscr));
+        } else if let Some(ncer) = parse_maybe_compressed(data, Ncer::parse) {
+            dir.cells.insert(stem, (path, ncer));
+        } else if let Some(nanr) = parse_maybe_compressed(data, Nanr::parse) {
+            dir.animations.insert(stem, nanr);
+        } else if let Some(nsbtx) = parse_maybe_compressed(data, Nsbtx::parse) {
+            dir.textures.push((path, nsbtx));
+        }
+    }
+
+    let mut records = Vec::new();
+    let mut palette_records = Vec::new();
+    for dir in dirs.values() {
+        for (stem, (path, nclr)) in &dir.palettes {
+            for format in PaletteFormat::ALL {
+                let export_path = Path::new(GRAPHICS_DIR)
+                    .join(path_escape::host_path(path))
+                    .with_extension(format.extension());
+                let export_data = match palette::export_palette(&nclr.colors, format, stem) {
+                    Ok(data) => data,
+                    Err(error) => {
+                        debug!("{path:?}: not exported to {export_path:?}: {error}");
+                        continue;
+                    }
+                };
+                debug!("{path:?}: exported to {export_path:?}");
+                if !dry_run {
+                    write_file(&target_path.join(&export_path), &export_data)?;
+                }
+                palette_records.push(PaletteRecord {
+                    export_path: rom::nitro_path(&export_path),
+                    format,
+                    palette: rom::nitro_path(path),
+                    export_hash: manifest::sha256_hex(&export_data),
+                });
+            }
+        }
+
+        for (stem, (path, ncgr)) in &dir.graphics {
+            let Some((palette_path, nclr)) = dir.palette(stem) else {
+                warn!("no palette found for {path:?}, not exporting it");
+                continue;
+            };
+            let screen = dir.screens.get(stem);
+            let image = match screen {
+                Some((_, nscr)) => gfx::render_screen(ncgr, nclr, nscr),
+                None => gfx::render_graphics(ncgr, nclr, 0),
+            };
+            let png_path = Path::new(GRAPHICS_DIR)
+                .join(path_escape::host_path(path))
+                .with_extension("png");
+            debug!("{path:?}: exported to {png_path:?}");
+            let png_data = image
+                .to_png()
+                .with_context(|| format!("failed to encode {png_path:?}"))?;
+            if !dry_run {
+                write_file(&target_path.join(&png_path), &png_data)?;
+            }
+            records.push(GraphicsRecord {
+                png_path: rom::nitro_path(&png_path),
+                graphics: rom::nitro_path(path),
+                palette: rom::nitro_path(palette_path),
+                screen: screen.map(|(screen_path, _)| rom::nitro_path(screen_path)),
+                png_hash: manifest::sha256_hex(&png_data),
+            });
+        }
+
+        for (stem, (path, ncer)) in &dir.cells {
+            let only_graphics = (dir.graphics.len() == 1)
+                .then(|| dir.graphics.values().next())
+                .flatten();
+            let Some((_, ncgr)) = dir.graphics.get(stem).or(only_graphics) else {
+                warn!("no graphics found for {path:?}, not exporting its cells");
+                continue;
+            };
+            let Some((_, nclr)) = dir.palette(stem) else {
+                warn!("no palette found for {path:?}, not exporting its cells");
+                continue;
+            };
+            let cells_path = Path::new(GRAPHICS_DIR)
+                .join(path_escape::host_path(path))
+                .with_extension("");
+            let cell_count = write_cells(
+                &target_path.join(&cells_path),
+                ncgr,
+                nclr,
+                ncer,
+                dir.animations.get(stem),
+                dry_run,
+            )?;
+            debug!("{path:?}: exported {cell_count} cells to {cells_path:?}");
+        }
+
+        for (path, nsbtx) in &dir.textures {
+            let textures_path = Path::new(GRAPHICS_DIR)
+                .join(path_escape::host_path(path))
+                .with_extension("");
+            let texture_count = write_textures(&target_path.join(&textures_path), nsbtx, dry_run)?;
+            debug!("{path:?}: exported {texture_count} textures to {textures_path:?}");
+        }
+    }
+    Ok((records, palette_records))
+}
+
+/// Extracts the files of the SDAT sound archives of the ROM inside [`SOUND_DIR`], in a
+/// directory of the same name as each archive, converting their sequences to MIDI files, their
+/// banks to SoundFonts and their streams and waves to WAV files. These are only meant for viewing and listening to, and have no records.
+#[cfg(feature = "audio")]
+fn export_sounds(
+    rom_data: &[u8],
+    filter: &PathFilter,
+    target_path: &Path,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let fs = rom::filesystem(rom_data)?;
+    for entry in fs.files() {
+        if !filter.matches(&entry.path) {
+            continue;
+        }
+        let data = rom::file_data(rom_data, entry);
+        if !data.starts_with(b"SDAT") {
+            continue;
+        }
+        let path = &entry.path;
+        let sdat = match Sdat::parse(data) {
+            Ok(sdat) => sdat,
+            Err(error) => {
+                warn!("failed to parse sound archive {path:?}: {error}");
+                continue;
+            }
+        };
+        let sounds_path = Path::new(SOUND_DIR)
+            .join(path_escape::host_path(path))
+            .with_extension("");
+        let file_count = write_sounds(&target_path.join(&sounds_path), &sdat, dry_run)?;
+        debug!("{path:?}: extracted {file_count} sound files to {sounds_path:?}");
+    }
+    Ok(())
+}
+
+/// How the NitroFS files of a ROM are laid out in the unpack directory. Packing finds them
+/// through the manifest whatever the layout, but files added to the directory become NitroFS
+/// files at their path inside it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// Files mirror their NitroFS paths.
+    #[default]
+    Mirror,
+    /// Files are placed directly in the unpack directory, named by their file ID.
+    Flat,
+    /// Files mirror their NitroFS paths inside a directory for the kind of content they hold,
+    /// such as `text/` or `gfx/`, or `other/` if it isn't known.
+    ByType,
+}
+
+/// Directory files of no known kind are grouped into with [`Layout::ByType`].
+const OTHER_DIR: &str = "other";
+
+impl Layout {
+    /// Path a NitroFS file is unpacked to, given the path it has once converted, escaped for
+    /// host file systems. `data` is its contents once converted, from which its kind is found.
+    pub fn path(
+        self,
+        entry: &nitro_fs::fnt::FileEntry,
+        path: &Path,
+        data: &[u8],
+        format: Format,
+    ) -> PathBuf {
+        let path = match self {
+            Layout::Mirror => path.to_owned(),
+            Layout::Flat => {
+                let mut flat_path = PathBuf::from(format!("{:04}", entry.id));
+                if let Some(extension) = path.extension() {
+                    flat_path.set_extension(extension);
+                }
+                flat_path
+            }
+            Layout::ByType => {
+                let dir = match format {
+                    Format::Text => magic::Category::Text.dir_name(),
+                    Format::Narc => magic::Category::Archive.dir_name(),
+                    Format::Binary | Format::Plugin | Format::Hook => magic::identify(data)
+                        .map_or(OTHER_DIR, |identification| {
+                            identification.category.dir_name()
+                        }),
+                };
+                Path::new(dir).join(path)
+            }
+        };
+        path_escape::host_path(&path)
+    }
+}
+
+/// Options changing what `unpack` does.
+#[derive(Debug, Clone, Default)]
+pub struct UnpackOptions {
+    /// Don't modify the file system, only report what would be unpacked.
+    pub dry_run: bool,
+    /// Extract NARC archives to a directory in their place, along with any archives nested
+    /// inside them.
+    pub recursive: bool,
+    /// Also export graphics to PNGs, and palettes to the palette formats of image editors,
+    /// inside [`GRAPHICS_DIR`].
+    pub convert_gfx: bool,
+    /// Keep unpacking the rest of the files when one of them fails, writing it as-is.
+    pub keep_going: bool,
+    /// Key table used to decrypt an encrypted secure area, so that the ARM9 binary can be
+    /// edited.
+    pub key_table: Option<KeyTable>,
+    /// State the secure area is to have once packed. Decrypted secure areas also have their ID
+    /// destroyed when unpacking. If `None`, packing gives the state of the original ROM.
+    pub secure_area: Option<SecureAreaForm>,
+    /// Plugins converting the files in the formats they recognize.
+    pub plugins: Plugins,
+    /// Hooks converting the files matching their patterns with external commands, before
+    /// plugins are tried.
+    pub hooks: Hooks,
+    /// Profile of the game, giving the encoding of its text files and which files they are.
+    pub profile: Option<Profile>,
+    /// How the NitroFS files are laid out.
+    pub layout: Layout,
+    /// Confidence files must be detected as text files with to be converted, from 0 to 1. If
+    /// `None`, [`survey::DEFAULT_MIN_CONFIDENCE`] is used.
+    pub min_confidence: Option<f64>,
+    /// Which transformations to apply to the NitroFS files. Plugins, profiles and recursive
+    /// extraction only apply to the files this leaves them able to read.
+    pub conversion: Conversion,
+}
+
+/// Converts a file the profile lists as a text file, even if it isn't recognized as one.
+fn convert_profile_text_file(
+    file_data: &[u8],
+    target_path: &mut PathBuf,
+    encoding: &TextEncoding,
+) -> Option<ConvertedFile> {
+    let (codec, data) = Codec::detect(file_data).unwrap_or((Codec::NONE, file_data.to_vec()));
+    let strings = text::parse_text_file(&data, encoding).ok()?;
+    target_path.set_extension("txt");
+    Some(ConvertedFile {
+        data: text::export_template(&strings, None).into_bytes(),
+        description: match codec.compression {
+            Compression::None => "text file".to_owned(),
+            _ => format!("compressed {codec} file, text file"),
+        },
+        codec,
+        format: Format::Text,
+    })
+}
+
+/// Converts a file with the first hook matching its path, if any does, updating the extension of
+/// `target_path` if the hook gives one.
+fn convert_with_hook(
+    entry: &nitro_fs::fnt::FileEntry,
+    file_data: &[u8],
+    target_path: &mut PathBuf,
+    hooks: &Hooks,
+    conversion: Conversion,
+    cache: &CompressionCache,
+) -> anyhow::Result<Option<(ConvertedFile, String)>> {
+    if conversion == Conversion::None {
+        return Ok(None);
+    }
+    let Some(hook) = hooks.find(&rom::nitro_path(&entry.path)) else {
+        return Ok(None);
+    };
+    let (codec, data) = Codec::detect(file_data).unwrap_or((Codec::NONE, file_data.to_vec()));
+    if codec.compression != Compression::None && !conversion.decompresses() {
+        return Ok(None);
+    }
+    let file_name = entry
+        .path
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    if let Some(extension) = &hook.extension {
+        target_path.set_extension(extension);
+    }
+    let description = match codec.compression {
+        Compression::None => format!("converted by hook {:?}", hook.name),
+        _ => format!("compressed {codec} file, converted by hook {:?}", hook.name),
+    };
+    let converted = ConvertedFile {
+        data: hook.unpack(&file_name, &data, cache)?,
+        codec,
+        format: Format::Hook,
+        description,
+    };
+    Ok(Some((converted, hook.name.clone())))
+}
+
+/// Converts a file with the first plugin that recognizes it, if any does, updating the
+/// extension of `target_path` if the plugin gives one.
+fn convert_with_plugin(
+    file_data: &[u8],
+    target_path: &mut PathBuf,
+    plugins: &Plugins,
+    conversion: Conversion,
+) -> anyhow::Result<Option<(ConvertedFile, String)>> {
+    if conversion == Conversion::None {
+        return Ok(None);
+    }
+    let (codec, data) = Codec::detect(file_data).unwrap_or((Codec::NONE, file_data.to_vec()));
+    if codec.compression != Compression::None && !conversion.decompresses() {
+        return Ok(None);
+    }
+    let Some(plugin) = plugins.detect(&data) else {
+        return Ok(None);
+    };
+    if let Some(extension) = plugin.extension()? {
+        target_path.set_extension(extension);
+    }
+    let description = match codec.compression {
+        Compression::None => format!("converted by plugin {:?}", plugin.name()),
+        _ => format!(
+            "compressed {codec} file, converted by plugin {:?}",
+            plugin.name()
+        ),
+    };
+    let converted = ConvertedFile {
+        data: plugin.unpack(&data)?,
+        codec,
+        format: Format::Plugin,
+        description,
+    };
+    Ok(Some((converted, plugin.name().to_owned())))
+}
+
+/// Unpacks a single NitroFS file of the ROM, converting it, and returns its record.
+fn unpack_file(
+    rom_data: &[u8],
+    entry: &nitro_fs::fnt::FileEntry,
+    target_path: &Path,
+    options: &UnpackOptions,
+    encoding: &TextEncoding,
+    cache: &CompressionCache,
+) -> anyhow::Result<FileRecord> {
+    let file_data = rom::checked_file_data(rom_data, entry)?;
+    let mut record = FileRecord {
+        path: rom::nitro_path(&entry.path),
+        unpacked_path: path_escape::escape(&rom::nitro_path(&entry.path)),
+        file_id: entry.id,
+        compression: Compression::None,
+        compression_header: CompressionHeader::default(),
+        format: Format::Binary,
+        original_size: file_data.len() as u32,
+        unpacked_hash: String::new(),
+        original_offset: Some(entry.alloc.start),
+        plugin: None,
+        hook: None,
+    };
+
+    let container = narc::open_container(file_data).filter(|(compression, _)| {
+        options.recursive
+            && options.conversion != Conversion::None
+            && (*compression == Compression::None || options.conversion.decompresses())
+    });
+    match container {
+        Some((compression, archive)) => {
+            debug!(
+                "{:?}: NARC archive, {} files, extracted",
+                entry.path,
+                archive.files.len()
+            );
+            let unpacked_path = options.layout.path(entry, &entry.path, &[], Format::Narc);
+            record.unpacked_path = rom::nitro_path(&unpacked_path);
+            record.compression = compression;
+            record.format = Format::Narc;
+            record.unpacked_hash =
+                manifest::sha256_hex(&narc::rebuilt_bytes(&archive, true, cache)?);
+            if !options.dry_run {
+                narc::extract(&archive, &target_path.join(&unpacked_path), true)?;
+            }
+        }
+        None => {
+            let mut target_entry_path = entry.path.clone();
+            let converted = match convert_with_hook(
+                entry,
+                file_data,
+                &mut target_entry_path,
+                &options.hooks,
+                options.conversion,
+                cache,
+            )? {
+                Some((converted, hook)) => {
+                    record.hook = Some(hook);
+                    converted
+                }
+                None => match convert_with_plugin(
+                    file_data,
+                    &mut target_entry_path,
+                    &options.plugins,
+                    options.conversion,
+                )? {
+                    Some((converted, plugin)) => {
+                        record.plugin = Some(plugin);
+                        converted
+                    }
+                    None => options
+                        .profile
+                        .as_ref()
+                        .filter(|profile| {
+                            options.conversion.converts_text()
+                                && profile.is_text_file(&entry.path)
+                                && (options.conversion.decompresses()
+                                    || Codec::detect(file_data).is_none())
+                        })
+                        .and_then(|_| {
+                            let converted = convert_profile_text_file(
+                                file_data,
+                                &mut target_entry_path,
+                                encoding,
+                            );
+                            if converted.is_none() {
+                                warn!("{:?} is listed as a text file by the game's profile, but couldn't be read as one", entry.path);
+                            }
+                            converted
+                        })
+                        .unwrap_or_else(|| {
+                            convert_file_with(
+                                file_data,
+                                &mut target_entry_path,
+                                options.conversion,
+                                encoding,
+                                options
+                                    .min_confidence
+                                    .unwrap_or(survey::DEFAULT_MIN_CONFIDENCE),
+                            )
+                        }),
+                },
+            };
+            debug!("{:?}: {}", entry.path, converted.description);
+            let target_entry_path =
+                options
+                    .layout
+                    .path(entry, &target_entry_path, &converted.data, converted.format);
+            record.unpacked_path = rom::nitro_path(&target_entry_path);
+            record.compression = converted.codec.compression;
+            record.compression_header = converted.codec.header.clone();
+            record.format = converted.format;
+            record.unpacked_hash = manifest::sha256_hex(&converted.data);
+            if !options.dry_run {
+                write_file(&target_path.join(&target_entry_path), &converted.data)?;
+            }
+        }
+    }
+
+    if !options.dry_run && record.is_converted() {
+        let originals_path = target_path.join(RAVENDS_DIR).join(ORIGINALS_DIR);
+        write_file(
+            &originals_path.join(path_escape::host_path(&entry.path)),
+            file_data,
+        )?;
+    }
+    Ok(record)
+}
+
+/// A file unpacked in memory by [`unpack_files`].
+#[derive(Debug, Clone)]
+pub struct UnpackedFile {
+    /// Path the file would be unpacked to, relative to the unpack directory.
+    pub path: String,
+    pub data: Vec<u8>,
+    /// What the file is, and what was done to it.
+    pub description: String,
+}
+
+/// Unpacks the ROM given in memory, for callers without a file system to unpack to: its
+/// sections and overlays go inside [`SYSTEM_DIR`], and its NitroFS files are converted as
+/// [`unpack`] converts them. Nothing needed to pack the ROM back, such as the manifest, is
+/// produced, and archives are left as they are.
+pub fn unpack_files(rom_data: &[u8], filter: &PathFilter) -> anyhow::Result<Vec<UnpackedFile>> {
+    let fs = rom::filesystem(rom_data)?;
+    let mut files = Vec::new();
+    for section in Section::ALL {
+        if let Some(range) = section.range(rom_data) {
+            files.push(UnpackedFile {
+                path: format!("{SYSTEM_DIR}/{}", section.file_name()),
+                data: rom_data[range].to_vec(),
+                description: section.name().to_owned(),
+            });
+        }
+    }
+    let mut overlays = fs.overlays().iter().collect::<Vec<_>>();
+    overlays.sort_by_key(|overlay| overlay.id);
+    for overlay in overlays {
+        files.push(UnpackedFile {
+            path: format!("{SYSTEM_DIR}/{}", overlay_file_name(overlay.id)),
+            data: rom::checked_file_data(rom_data, overlay)?.to_vec(),
+            description: "overlay".to_owned(),
+        });
+    }
+    for (file_id, range) in rom::orphan_files(rom_data, &fs) {
+        let end = range.end.min(rom_data.len());
+        files.push(UnpackedFile {
+            path: format!("{ORPHAN_DIR}/{}", orphan_file_name(file_id)),
+            data: rom_data[range.start.min(end)..end].to_vec(),
+            description: "file without a path".to_owned(),
+        });
+    }
+    let mut entries = fs.files();
+    entries.sort_by_key(|entry| entry.id);
+    for entry in entries
+        .into_iter()
+        .filter(|entry| filter.matches(&entry.path))
+    {
+        let mut target_path = entry.path.clone();
+        let converted = convert_file(
+            rom::checked_file_data(rom_data, entry)?,
+            &mut target_path,
+            Conversion::Auto,
+            &TextEncoding::default(),
+        );
+        files.push(UnpackedFile {
+            path: path_escape::escape(&rom::nitro_path(&target_path)),
+            data: converted.data,
+            description: converted.description,
+        });
+    }
+    Ok(files)
+}
+
+/// Unpacks the ROM given to `target_path`, converting its NitroFS files and writing a
+/// manifest so that the ROM can be packed back. The files of sound archives are extracted
+/// inside [`SOUND_DIR`].
+///
+/// If `options.keep_going` is set, files that fail to unpack are written as they are stored
+/// (as much of them as lies inside the ROM), and an error listing them is returned once
+/// everything else is unpacked.
+pub fn unpack(
+    rom_data: &[u8],
+    target_path: &Path,
+    filter: &PathFilter,
+    options: &UnpackOptions,
+) -> anyhow::Result<()> {
+    let dry_run = options.dry_run;
+    let original_state = secure_area::state(rom_data);
+    // Secure areas are unpacked decrypted whenever possible, so that the ARM9 binary can be
+    // edited, but left as they are if no state is asked for and there's no key table.
+    let decrypt = match options.secure_area {
+        None => original_state == SecureAreaState::Encrypted && options.key_table.is_some(),
+        Some(SecureAreaForm::Encrypted) => options.key_table.is_some(),
+        Some(SecureAreaForm::Decrypted) => true,
+    };
+    let mut decrypted_rom_data = Vec::new();
+    if decrypt {
+        decrypted_rom_data = rom_data.to_vec();
+        secure_area::normalize(
+            &mut decrypted_rom_data,
+            SecureAreaForm::Decrypted,
+            options.key_table.as_ref(),
+        )
+        .context("failed to decrypt secure area")?;
+    }
+    let rom_data = if decrypt {
+        &decrypted_rom_data
+    } else {
+        rom_data
+    };
+    // Packing encrypts the secure area again to give the state asked for, or the original one.
+    let decrypted_secure_area = secure_area::state(rom_data) == SecureAreaState::Decrypted
+        && match options.secure_area {
+            None => original_state == SecureAreaState::Encrypted,
+            Some(form) => form == SecureAreaForm::Encrypted,
+        };
+    let fs = rom::filesystem(rom_data)?;
+    let encoding = match &options.profile {
+        Some(profile) => profile.text_encoding()?,
+        None => TextEncoding::default(),
+    };
+    if !rom::has_filesystem(rom_data) {
+        info!("ROM has no NitroFS, as is common for homebrew; only its header, binaries and banner will be unpacked");
+    }
+    let system_path = target_path.join(SYSTEM_DIR);
+    // Nested archives are compressed to record their hash, which also prepares the cache used
+    // when packing.
+    let cache = if dry_run {
+        CompressionCache::disabled()
+    } else {
+        CompressionCache::new(target_path)
+    };
+
+    let mut manifest = Manifest::default();
+    let mut layout = LayoutRecord {
+        rom_size: rom_data.len() as u32,
+        sections: Vec::new(),
+        fnt_offset: rom::u32_at(rom_data, rom::FNT_ADDR_OFFSET),
+        fat_offset: rom::u32_at(rom_data, rom::FAT_ADDR_OFFSET),
+        alignment: rom::detect_alignment(&fs) as u32,
+        pad_byte: rom::detect_pad_byte(rom_data, &fs),
+        decrypted_secure_area,
+        compressed_arm9: Section::Arm9.range(rom_data).is_some_and(|range| {
+            let arm9 = &rom_data[range];
+            memory::compressed_end_field(arm9).is_some_and(|field| rom::u32_at(arm9, field) != 0)
+        }),
+        extra_data: Vec::new(),
+    };
+
+    for section in Section::ALL {
+        if let Some(range) = section.range(rom_data) {
+            layout.sections.push(SectionRecord {
+                section,
+                offset: range.start as u32,
+            });
+            if !dry_run {
+                write_file(&system_path.join(section.file_name()), &rom_data[range])?;
+            }
+        }
+    }
+    for (kind, range) in rom::extra_data(rom_data, layout.pad_byte) {
+        let unpacked_path = format!("{SYSTEM_DIR}/{}", extra_data_file_name(kind, range.start));
+        debug!(
+            "{unpacked_path}: 0x{:X} bytes at 0x{:08X} outside of everything the header and FAT locate",
+            range.len(),
+            range.start
+        );
+        if !dry_run {
+            write_file(&target_path.join(&unpacked_path), &rom_data[range.clone()])?;
+        }
+        layout.extra_data.push(ExtraDataRecord {
+            kind,
+            offset: range.start as u32,
+            unpacked_path,
+        });
+    }
+    manifest.layout = Some(layout);
+    if !dry_run && rom::has_filesystem(rom_data) {
+        let fnt_start = rom::u32_at(rom_data, rom::FNT_ADDR_OFFSET) as usize;
+        let fnt_size = rom::u32_at(rom_data, rom::FNT_SIZE_OFFSET) as usize;
+        write_file(
+            &target_path.join(RAVENDS_DIR).join(ORIGINAL_FNT_FILE_NAME),
+            &rom_data[fnt_start..fnt_start + fnt_size],
+        )?;
+    }
+
+    let mut failed_paths = Vec::new();
+    let overlay_tables = [
+        (Processor::Arm9, Section::Arm9OverlayTable),
+        (Processor::Arm7, Section::Arm7OverlayTable),
+    ];
+    for (processor, table_section) in overlay_tables {
+        let Some(table_range) = table_section.range(rom_data) else {
+            continue;
+        };
+        for file_id in rom::overlay_file_ids(&rom_data[table_range]) {
+            let Some(overlay) = fs.overlays().iter().find(|overlay| overlay.id == file_id) else {
+                anyhow::bail!("overlay table references missing overlay file {file_id}");
+            };
+            let unpacked_path = format!("{SYSTEM_DIR}/{}", overlay_file_name(file_id));
+            let overlay_data = match rom::checked_file_data(rom_data, overlay) {
+                Ok(data) => data,
+                Err(error) if options.keep_going => {
+                    warn!("overlay {file_id}: {error:#}, writing it as-is");
+                    failed_paths.push(PathBuf::from(&unpacked_path));
+                    rom::file_data(rom_data, overlay)
+                }
+                Err(error) => {
+                    return Err(anyhow::Error::from(error)
+                        .context(format!("failed to unpack overlay {file_id}")));
+                }
+            };
+            if !dry_run {
+                write_file(&target_path.join(&unpacked_path), overlay_data)?;
+            }
+            manifest.overlays.push(OverlayRecord {
+                file_id,
+                processor,
+                unpacked_path,
+                original_offset: Some(overlay.alloc.start),
+            });
+        }
+    }
+
+    for (file_id, range) in rom::orphan_files(rom_data, &fs) {
+        let unpacked_path = format!("{ORPHAN_DIR}/{}", orphan_file_name(file_id));
+        let end = range.end.min(rom_data.len());
+        let start = range.start.min(end);
+        if (start, end) != (range.start, range.end) {
+            warn!(
+                "file {file_id} (0x{:08X}..0x{:08X}) lies outside of the ROM; only the part inside it is kept",
+                range.start, range.end
+            );
+        }
+        debug!(
+            "{unpacked_path}: file {file_id} of the FAT, which neither the FNT nor an overlay table references"
+        );
+        if !dry_run {
+            write_file(&target_path.join(&unpacked_path), &rom_data[start..end])?;
+        }
+        manifest.orphans.push(OrphanRecord {
+            file_id,
+            unpacked_path,
+            original_offset: Some(range.start as u32),
+        });
+    }
+    if !manifest.orphans.is_empty() {
+        info!(
+            "{} files of the FAT have no path; they were unpacked to {ORPHAN_DIR}",
+            manifest.orphans.len()
+        );
+    }
+
+    manifest.directories = fs
+        .dirs
+        .values()
+        .map(|dir| DirectoryRecord {
+            path: rom::nitro_path(&dir.path),
+            dir_id: dir.id(),
+        })
+        .collect();
+
+    let mut entries = fs.files();
+    entries.sort_by_key(|entry| entry.id);
+    let mut original_hashes = BTreeMap::new();
+    for entry in entries {
+        if !filter.matches(&entry.path) {
+            continue;
+        }
+        original_hashes.insert(
+            rom::nitro_path(&entry.path),
+            manifest::sha256_hex(rom::file_data(rom_data, entry)),
+        );
+        match unpack_file(rom_data, entry, target_path, options, &encoding, &cache) {
+            Ok(record) => manifest.files.push(record),
+            Err(error) if options.keep_going => {
+                warn!("{:?}: {error:#}, writing it as-is", entry.path);
+                failed_paths.push(entry.path.clone());
+                // The file is still written, so that packing doesn't leave it out.
+                let file_data = rom::file_data(rom_data, entry);
+                let unpacked_path =
+                    options
+                        .layout
+                        .path(entry, &entry.path, file_data, Format::Binary);
+                if !dry_run {
+                    write_file(&target_path.join(&unpacked_path), file_data)?;
+                }
+                manifest.files.push(FileRecord {
+                    path: rom::nitro_path(&entry.path),
+                    unpacked_path: rom::nitro_path(&unpacked_path),
+                    file_id: entry.id,
+                    compression: Compression::None,
+                    compression_header: CompressionHeader::default(),
+                    format: Format::Binary,
+                    original_size: file_data.len() as u32,
+                    unpacked_hash: manifest::sha256_hex(file_data),
+                    original_offset: None,
+                    plugin: None,
+                    hook: None,
+                });
+            }
+            Err(error) => {
+                return Err(error.context(format!("failed to unpack {:?}", entry.path)));
+            }
+        }
+    }
+
+    #[cfg(feature = "audio")]
+    export_sounds(rom_data, filter, target_path, dry_run)?;
+    #[cfg(feature = "graphics")]
+    if options.convert_gfx {
+        (manifest.graphics, manifest.palettes) =
+            export_graphics(rom_data, filter, target_path, dry_run)?;
+        manifest.icon = export_icon(rom_data, target_path, dry_run)?;
+    }
+    #[cfg(not(feature = "graphics"))]
+    if options.convert_gfx {
+        warn!("ravends was built without the `graphics` feature, so graphics were not converted");
+    }
+    if !dry_run {
+        manifest.save(target_path)?;
+        Hashes::compute(target_path, original_hashes)?.save(target_path)?;
+    }
+    info!("{} files unpacked to {target_path:?}", manifest.files.len());
+
+    if !failed_paths.is_empty() {
+        warn!("these files failed to unpack and were written as-is:");
+        for path in &failed_paths {
+            warn!("  {path:?}");
+        }
+        return Err(StatusError::new(
+            ExitStatus::PartialUnpack,
+            format!("{} files failed to unpack", failed_paths.len()),
+        )
+        .into());
+    }
+    Ok(())
+}
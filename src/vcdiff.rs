@@ -0,0 +1,500 @@
+use thiserror::Error;
+
+use crate::diff::{find_operations, Operation, SourceIndex};
+
+pub const MAGIC: [u8; 4] = [0xD6, 0xC3, 0xC4, 0x00];
+
+/// Header indicator bits.
+const VCD_DECOMPRESS: u8 = 0x01;
+const VCD_CODETABLE: u8 = 0x02;
+const VCD_APPHEADER: u8 = 0x04;
+
+/// Window indicator bits. `VCD_ADLER32` is an xdelta3 extension.
+const VCD_SOURCE: u8 = 0x01;
+const VCD_TARGET: u8 = 0x02;
+const VCD_ADLER32: u8 = 0x04;
+
+/// Sizes of the address caches of the default code table.
+const NEAR_CACHE_SIZE: usize = 4;
+const SAME_CACHE_SIZE: usize = 3;
+
+/// Maximum size of each target window produced when encoding.
+const WINDOW_SIZE: usize = 1 << 24;
+
+#[derive(Error, Debug)]
+pub enum VcdiffError {
+    #[error("not a VCDIFF patch (magic number does not match)")]
+    MagicNumberMismatch,
+    #[error("patch is truncated")]
+    Truncated,
+    #[error("patches using secondary compression are not supported (create them with `xdelta3 -S none`)")]
+    SecondaryCompression,
+    #[error("patches using a custom code table are not supported")]
+    CustomCodeTable,
+    #[error("window {window} reads outside of the source file (is this the right base ROM?)")]
+    InvalidSourceSegment { window: usize },
+    #[error("window {window} contains an invalid instruction")]
+    InvalidInstruction { window: usize },
+    #[error("window {window} produced 0x{found:X} bytes instead of 0x{expected:X}")]
+    WindowSizeMismatch {
+        window: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error("checksum of window {window} does not match (is this the right base ROM?)")]
+    ChecksumMismatch { window: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Instruction {
+    Noop,
+    Add { size: u8 },
+    Run { size: u8 },
+    Copy { size: u8, mode: u8 },
+}
+
+/// Builds the default code table from RFC 3284, section 5.6.
+fn default_code_table() -> Vec<(Instruction, Instruction)> {
+    use Instruction::*;
+
+    let mut table = Vec::with_capacity(256);
+    table.push((Run { size: 0 }, Noop));
+    for size in 0..=17 {
+        table.push((Add { size }, Noop));
+    }
+    for mode in 0..9 {
+        table.push((Copy { size: 0, mode }, Noop));
+        for size in 4..=18 {
+            table.push((Copy { size, mode }, Noop));
+        }
+    }
+    for mode in 0..6 {
+        for add_size in 1..=4 {
+            for copy_size in 4..=6 {
+                table.push((
+                    Add { size: add_size },
+                    Copy {
+                        size: copy_size,
+                        mode,
+                    },
+                ));
+            }
+        }
+    }
+    for mode in 6..9 {
+        for add_size in 1..=4 {
+            table.push((Add { size: add_size }, Copy { size: 4, mode }));
+        }
+    }
+    for mode in 0..9 {
+        table.push((Copy { size: 4, mode }, Add { size: 1 }));
+    }
+    table
+}
+
+/// Index in the default code table of the ADD instruction for the size given, and whether the
+/// size has to be written separately.
+fn add_code(size: usize) -> (u8, bool) {
+    if (1..=17).contains(&size) {
+        (size as u8 + 1, false)
+    } else {
+        (1, true)
+    }
+}
+
+fn copy_code(size: usize, mode: u8) -> (u8, bool) {
+    let base = 19 + mode * 16;
+    if (4..=18).contains(&size) {
+        (base + size as u8 - 3, false)
+    } else {
+        (base, true)
+    }
+}
+
+/// The near & same address caches, shared by the encoder and the decoder.
+struct AddressCache {
+    near: [usize; NEAR_CACHE_SIZE],
+    next_near_slot: usize,
+    same: Vec<usize>,
+}
+
+impl AddressCache {
+    fn new() -> Self {
+        Self {
+            near: [0; NEAR_CACHE_SIZE],
+            next_near_slot: 0,
+            same: vec![0; SAME_CACHE_SIZE * 256],
+        }
+    }
+
+    fn update(&mut self, address: usize) {
+        self.near[self.next_near_slot] = address;
+        self.next_near_slot = (self.next_near_slot + 1) % NEAR_CACHE_SIZE;
+        let same_len = self.same.len();
+        self.same[address % same_len] = address;
+    }
+
+    fn decode(&mut self, here: usize, mode: u8, reader: &mut Reader) -> Option<usize> {
+        let mode = mode as usize;
+        let address = match mode {
+            0 => reader.integer()?,
+            1 => here.checked_sub(reader.integer()?)?,
+            mode if mode < 2 + NEAR_CACHE_SIZE => {
+                self.near[mode - 2].checked_add(reader.integer()?)?
+            }
+            mode => {
+                let bucket = mode - 2 - NEAR_CACHE_SIZE;
+                *self.same.get(bucket * 256 + reader.byte()? as usize)?
+            }
+        };
+        self.update(address);
+        Some(address)
+    }
+
+    /// Picks the cheapest way of encoding an address, writing it to `output` and returning the
+    /// mode used.
+    fn encode(&mut self, here: usize, address: usize, output: &mut Vec<u8>) -> u8 {
+        let same_len = self.same.len();
+        let mode = if self.same[address % same_len] == address {
+            let slot = address % same_len;
+            output.push((slot % 256) as u8);
+            (2 + NEAR_CACHE_SIZE + slot / 256) as u8
+        } else {
+            let mut best = (0, address);
+            let candidates = [(1, here - address)].into_iter().chain(
+                self.near
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &near)| address >= near)
+                    .map(|(idx, &near)| (2 + idx as u8, address - near)),
+            );
+            for (mode, value) in candidates {
+                if integer_len(value) < integer_len(best.1) {
+                    best = (mode, value);
+                }
+            }
+            write_integer(output, best.1);
+            best.0
+        };
+        self.update(address);
+        mode
+    }
+}
+
+fn integer_len(mut value: usize) -> usize {
+    let mut len = 1;
+    while value >= 0x80 {
+        value >>= 7;
+        len += 1;
+    }
+    len
+}
+
+/// Writes a variable-length integer: big-endian groups of 7 bits, with the top bit set on every
+/// byte but the last.
+fn write_integer(output: &mut Vec<u8>, value: usize) {
+    let len = integer_len(value);
+    for idx in (0..len).rev() {
+        let group = ((value >> (idx * 7)) & 0x7F) as u8;
+        output.push(if idx == 0 { group } else { group | 0x80 });
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    fn integer(&mut self) -> Option<usize> {
+        let mut value = 0usize;
+        loop {
+            let byte = self.byte()?;
+            value = value.checked_mul(0x80)? | (byte & 0x7F) as usize;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+    }
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // Sums can be deferred for this many bytes without overflowing.
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+/// Applies a VCDIFF patch to `source`, returning the patched data.
+pub fn decode(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, VcdiffError> {
+    use VcdiffError::*;
+
+    let mut reader = Reader { data: patch };
+    if reader.bytes(4) != Some(MAGIC.as_slice()) {
+        return Err(MagicNumberMismatch);
+    }
+    let header_indicator = reader.byte().ok_or(Truncated)?;
+    if header_indicator & VCD_DECOMPRESS != 0 {
+        return Err(SecondaryCompression);
+    }
+    if header_indicator & VCD_CODETABLE != 0 {
+        return Err(CustomCodeTable);
+    }
+    if header_indicator & VCD_APPHEADER != 0 {
+        let len = reader.integer().ok_or(Truncated)?;
+        reader.bytes(len).ok_or(Truncated)?;
+    }
+
+    let code_table = default_code_table();
+    let mut output = Vec::new();
+    let mut window = 0;
+    while !reader.data.is_empty() {
+        let window_indicator = reader.byte().ok_or(Truncated)?;
+        let segment = if window_indicator & (VCD_SOURCE | VCD_TARGET) != 0 {
+            let len = reader.integer().ok_or(Truncated)?;
+            let position = reader.integer().ok_or(Truncated)?;
+            let base = if window_indicator & VCD_SOURCE != 0 {
+                source
+            } else {
+                output.as_slice()
+            };
+            position
+                .checked_add(len)
+                .and_then(|end| base.get(position..end))
+                .ok_or(InvalidSourceSegment { window })?
+                .to_vec()
+        } else {
+            Vec::new()
+        };
+
+        let delta_len = reader.integer().ok_or(Truncated)?;
+        let mut delta = Reader {
+            data: reader.bytes(delta_len).ok_or(Truncated)?,
+        };
+        let target_len = delta.integer().ok_or(Truncated)?;
+        let delta_indicator = delta.byte().ok_or(Truncated)?;
+        if delta_indicator != 0 {
+            return Err(SecondaryCompression);
+        }
+        let data_len = delta.integer().ok_or(Truncated)?;
+        let instructions_len = delta.integer().ok_or(Truncated)?;
+        let addresses_len = delta.integer().ok_or(Truncated)?;
+        let checksum = if window_indicator & VCD_ADLER32 != 0 {
+            let bytes = delta.bytes(4).ok_or(Truncated)?;
+            Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+        } else {
+            None
+        };
+        let mut data = Reader {
+            data: delta.bytes(data_len).ok_or(Truncated)?,
+        };
+        let mut instructions = Reader {
+            data: delta.bytes(instructions_len).ok_or(Truncated)?,
+        };
+        let mut addresses = Reader {
+            data: delta.bytes(addresses_len).ok_or(Truncated)?,
+        };
+
+        let invalid = || InvalidInstruction { window };
+        // The target size comes from the patch, so the target only grows as instructions add
+        // to it, failing once it would exceed that size.
+        let mut target = Vec::new();
+        let mut cache = AddressCache::new();
+        while let Some(code) = instructions.byte() {
+            let (first, second) = code_table[code as usize];
+            for instruction in [first, second] {
+                let size = match instruction {
+                    Instruction::Noop => continue,
+                    Instruction::Add { size }
+                    | Instruction::Run { size }
+                    | Instruction::Copy { size, .. } => size as usize,
+                };
+                let size = if size == 0 {
+                    instructions.integer().ok_or_else(invalid)?
+                } else {
+                    size
+                };
+                if size > target_len - target.len() {
+                    return Err(invalid());
+                }
+
+                match instruction {
+                    Instruction::Noop => {}
+                    Instruction::Add { .. } => {
+                        target.extend_from_slice(data.bytes(size).ok_or_else(invalid)?)
+                    }
+                    Instruction::Run { .. } => {
+                        let byte = data.byte().ok_or_else(invalid)?;
+                        target.resize(target.len() + size, byte);
+                    }
+                    Instruction::Copy { mode, .. } => {
+                        let here = segment.len() + target.len();
+                        let address = cache
+                            .decode(here, mode, &mut addresses)
+                            .filter(|&address| address < here)
+                            .ok_or_else(invalid)?;
+                        if address + size <= segment.len() {
+                            target.extend_from_slice(&segment[address..address + size]);
+                        } else {
+                            // The copy may overlap with the data it produces.
+                            for position in address..address + size {
+                                let byte = match position.checked_sub(segment.len()) {
+                                    Some(target_position) => target[target_position],
+                                    None => segment[position],
+                                };
+                                target.push(byte);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if target.len() != target_len {
+            return Err(WindowSizeMismatch {
+                window,
+                expected: target_len,
+                found: target.len(),
+            });
+        }
+        if checksum.is_some_and(|checksum| checksum != adler32(&target)) {
+            return Err(ChecksumMismatch { window });
+        }
+        output.extend_from_slice(&target);
+        window += 1;
+    }
+    Ok(output)
+}
+
+/// Creates a VCDIFF patch turning `source` into `target`. Every window carries an Adler-32
+/// checksum of its data, as xdelta3 does, so that applying the patch to the wrong file fails.
+pub fn encode(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let index = SourceIndex::new(source);
+    let mut output = MAGIC.to_vec();
+    output.push(0);
+
+    for window_start in (0..target.len().max(1)).step_by(WINDOW_SIZE) {
+        let window_end = (window_start + WINDOW_SIZE).min(target.len());
+        let operations = find_operations(source, &index, target, window_start, window_end);
+
+        let mut data = Vec::new();
+        let mut instructions = Vec::new();
+        let mut addresses = Vec::new();
+        let mut cache = AddressCache::new();
+        let mut target_len = 0;
+        for operation in operations {
+            match operation {
+                Operation::Add { start, end } => {
+                    let (code, explicit_size) = add_code(end - start);
+                    instructions.push(code);
+                    if explicit_size {
+                        write_integer(&mut instructions, end - start);
+                    }
+                    data.extend_from_slice(&target[start..end]);
+                    target_len += end - start;
+                }
+                Operation::Run { byte, len } => {
+                    instructions.push(0);
+                    write_integer(&mut instructions, len);
+                    data.push(byte);
+                    target_len += len;
+                }
+                Operation::Copy { address, len } => {
+                    let here = source.len() + target_len;
+                    let mode = cache.encode(here, address, &mut addresses);
+                    let (code, explicit_size) = copy_code(len, mode);
+                    instructions.push(code);
+                    if explicit_size {
+                        write_integer(&mut instructions, len);
+                    }
+                    target_len += len;
+                }
+            }
+        }
+
+        let mut delta = Vec::new();
+        write_integer(&mut delta, target_len);
+        delta.push(0);
+        write_integer(&mut delta, data.len());
+        write_integer(&mut delta, instructions.len());
+        write_integer(&mut delta, addresses.len());
+        delta.extend_from_slice(&adler32(&target[window_start..window_end]).to_be_bytes());
+        delta.extend_from_slice(&data);
+        delta.extend_from_slice(&instructions);
+        delta.extend_from_slice(&addresses);
+
+        if source.is_empty() {
+            output.push(VCD_ADLER32);
+        } else {
+            output.push(VCD_SOURCE | VCD_ADLER32);
+            write_integer(&mut output, source.len());
+            write_integer(&mut output, 0);
+        }
+        write_integer(&mut output, delta.len());
+        output.extend_from_slice(&delta);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let source = (0..0x1000u32)
+            .map(|index| (index * 7 % 251) as u8)
+            .collect::<Vec<_>>();
+        let mut edited = source.clone();
+        edited[0x100..0x120].fill(0x42);
+        edited.extend_from_slice(b"appended data");
+        for target in [source.clone(), edited, Vec::new()] {
+            let patch = encode(&source, &target);
+            assert_eq!(decode(&source, &patch).unwrap(), target);
+        }
+    }
+
+    #[test]
+    fn round_trips_targets_far_larger_than_the_patch() {
+        // A small ROM padded up to the size of its chip.
+        let source = (0..0x1000u32).map(|index| index as u8).collect::<Vec<_>>();
+        let mut target = source.clone();
+        target.resize(0x100000, 0xFF);
+        let patch = encode(&source, &target);
+        assert!(patch.len() < 0x100);
+        assert_eq!(decode(&source, &patch).unwrap(), target);
+    }
+
+    #[test]
+    fn rejects_truncated_patches() {
+        let source = vec![0x11; 0x100];
+        let patch = encode(&source, &[0x22; 0x80]);
+        // The magic number and header indicator alone make a patch without windows.
+        assert!(decode(&source, &patch[..4]).is_err());
+        for len in MAGIC.len() + 2..patch.len() {
+            assert!(decode(&source, &patch[..len]).is_err(), "length {len}");
+        }
+    }
+}
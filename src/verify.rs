@@ -0,0 +1,203 @@
+use std::{
+    cell::Cell,
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::Path,
+};
+
+use serde::Serialize;
+
+use crate::{
+    cache::CompressionCache,
+    pack,
+    rom::{self, PathFilter, Region},
+    unpack,
+};
+
+/// A run of differing bytes between the original and repacked ROMs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    pub offset: usize,
+    pub len: usize,
+    /// What the differing data belongs to in the original ROM.
+    pub original_region: Region,
+    /// What the differing data belongs to in the repacked ROM.
+    pub repacked_region: Region,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoundtripReport {
+    pub original_size: usize,
+    pub repacked_size: usize,
+    /// The first region where the ROMs differ, or `None` if they are identical.
+    pub first_difference: Option<Difference>,
+}
+
+impl RoundtripReport {
+    pub fn is_identical(&self) -> bool {
+        self.first_difference.is_none()
+    }
+}
+
+/// Finds the first run of differing bytes between two buffers. A size mismatch is reported as
+/// a difference covering the extra bytes.
+fn first_difference(a: &[u8], b: &[u8]) -> Option<(usize, usize)> {
+    let common_len = a.len().min(b.len());
+    match (0..common_len).find(|&idx| a[idx] != b[idx]) {
+        Some(offset) => {
+            let len = (offset..common_len)
+                .take_while(|&idx| a[idx] != b[idx])
+                .count();
+            Some((offset, len))
+        }
+        None if a.len() != b.len() => Some((common_len, a.len().abs_diff(b.len()))),
+        None => None,
+    }
+}
+
+/// Unpacks the ROM given to a temporary directory, packs it back and compares the result with
+/// the original.
+pub fn verify_roundtrip(rom_data: &[u8]) -> anyhow::Result<RoundtripReport> {
+    let unpack_dir = tempfile::tempdir()?;
+    unpack_to(rom_data, unpack_dir.path())?;
+    let repacked_data = repack(unpack_dir.path())?;
+    compare(rom_data, &repacked_data)
+}
+
+fn unpack_to(rom_data: &[u8], dir: &Path) -> anyhow::Result<()> {
+    unpack::unpack(
+        rom_data,
+        dir,
+        &PathFilter::default(),
+        &unpack::UnpackOptions::default(),
+    )
+}
+
+fn repack(dir: &Path) -> anyhow::Result<Vec<u8>> {
+    pack::pack(
+        dir,
+        &CompressionCache::new(dir),
+        &pack::PackOptions::default(),
+    )
+}
+
+fn compare(rom_data: &[u8], repacked_data: &[u8]) -> anyhow::Result<RoundtripReport> {
+    let first_difference = match first_difference(rom_data, repacked_data) {
+        Some((offset, len)) => {
+            let original_fs = rom::filesystem(rom_data)?;
+            let repacked_fs = rom::filesystem(repacked_data)?;
+            Some(Difference {
+                offset,
+                len,
+                original_region: rom::region_at(rom_data, Some(&original_fs), offset),
+                repacked_region: rom::region_at(repacked_data, Some(&repacked_fs), offset),
+            })
+        }
+        None => None,
+    };
+
+    Ok(RoundtripReport {
+        original_size: rom_data.len(),
+        repacked_size: repacked_data.len(),
+        first_difference,
+    })
+}
+
+/// A step of [`selftest`], in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelftestStage {
+    /// Reading the header and file system of the ROM.
+    Header,
+    Unpack,
+    Repack,
+    /// Comparing the repacked ROM with the original, byte for byte.
+    Compare,
+}
+
+impl SelftestStage {
+    pub fn name(self) -> &'static str {
+        match self {
+            SelftestStage::Header => "header",
+            SelftestStage::Unpack => "unpack",
+            SelftestStage::Repack => "repack",
+            SelftestStage::Compare => "compare",
+        }
+    }
+}
+
+/// How a ROM fared in [`selftest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelftestResult {
+    pub title: String,
+    pub game_code: String,
+    pub maker_code: String,
+    /// The stage that failed and why, or `None` if the ROM round-trips byte for byte.
+    pub failure: Option<(SelftestStage, String)>,
+}
+
+impl SelftestResult {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// Runs each stage of a round trip on the ROM given, as [`verify_roundtrip`] does, stopping at
+/// the first one that fails. Panics are caught and reported as failures of their stage, so
+/// that a corpus can be tested in one go.
+pub fn selftest(rom_data: &[u8]) -> SelftestResult {
+    let header_field = |range| {
+        rom::check_header(rom_data)
+            .map(|()| rom::header_text(rom_data, range))
+            .unwrap_or_default()
+            // Files that aren't ROMs have garbage in their header fields.
+            .chars()
+            .map(|c| {
+                if c.is_ascii_graphic() || c == ' ' {
+                    c
+                } else {
+                    '?'
+                }
+            })
+            .collect::<String>()
+    };
+    SelftestResult {
+        title: header_field(rom::TITLE_RANGE),
+        game_code: header_field(rom::GAME_CODE_RANGE),
+        maker_code: header_field(rom::MAKER_CODE_RANGE),
+        failure: {
+            let stage = Cell::new(SelftestStage::Header);
+            catch_unwind(AssertUnwindSafe(|| selftest_stages(rom_data, &stage)))
+                .unwrap_or_else(|_| {
+                    Err((stage.get(), "ravends panicked; this is a bug".to_owned()))
+                })
+                .err()
+        },
+    }
+}
+
+fn selftest_stages(
+    rom_data: &[u8],
+    stage: &Cell<SelftestStage>,
+) -> Result<(), (SelftestStage, String)> {
+    let fail = |error: anyhow::Error| (stage.get(), format!("{error:#}"));
+    rom::check_header(rom_data)
+        .and_then(|()| rom::filesystem(rom_data))
+        .map_err(|error| fail(error.into()))?;
+    stage.set(SelftestStage::Unpack);
+    let unpack_dir = tempfile::tempdir().map_err(|error| fail(error.into()))?;
+    unpack_to(rom_data, unpack_dir.path()).map_err(fail)?;
+    stage.set(SelftestStage::Repack);
+    let repacked_data = repack(unpack_dir.path()).map_err(fail)?;
+    stage.set(SelftestStage::Compare);
+    let report = compare(rom_data, &repacked_data).map_err(fail)?;
+    match report.first_difference {
+        Some(difference) => Err((
+            SelftestStage::Compare,
+            format!(
+                "differs at 0x{:08X} (0x{:X} bytes), in {} of the original",
+                difference.offset, difference.len, difference.original_region
+            ),
+        )),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,67 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use log::{error, info};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+/// How long to wait for more changes before rebuilding, so that saving several files at once
+/// triggers a single rebuild.
+const DEBOUNCE_TIME: Duration = Duration::from_millis(200);
+
+/// Runs `build` once, then again every time a file in `dir` changes, until interrupted. Changes
+/// to paths inside `ignored` (such as the build's own outputs) are not reported.
+///
+/// Build errors are printed rather than returned, so that a mistake in a file being edited
+/// doesn't end the session.
+pub fn watch(
+    dir: &Path,
+    ignored: &[PathBuf],
+    mut build: impl FnMut() -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let dir = std::path::absolute(dir).context("failed to resolve watched directory")?;
+    let ignored = ignored
+        .iter()
+        .map(std::path::absolute)
+        .collect::<Result<Vec<_>, _>>()
+        .context("failed to resolve ignored paths")?;
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).context("failed to create watcher")?;
+    watcher
+        .watch(&dir, RecursiveMode::Recursive)
+        .with_context(|| format!("failed to watch {dir:?}"))?;
+
+    let is_relevant = |event: &Event| {
+        matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        ) && event
+            .paths
+            .iter()
+            .any(|path| !ignored.iter().any(|ignored| path.starts_with(ignored)))
+    };
+
+    loop {
+        match build() {
+            Ok(()) => info!("build finished; watching {dir:?} for changes"),
+            Err(error) => {
+                error!("build failed: {error:?}");
+                info!("watching {dir:?} for changes");
+            }
+        }
+
+        // Wait for a relevant change, then for things to settle down.
+        loop {
+            let event = receiver.recv().context("watcher stopped")?;
+            if event.is_ok_and(|event| is_relevant(&event)) {
+                break;
+            }
+        }
+        while receiver.recv_timeout(DEBOUNCE_TIME).is_ok() {}
+        info!("changes detected, rebuilding...");
+    }
+}
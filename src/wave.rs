@@ -0,0 +1,770 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const SWAV_MAGIC: &[u8; 4] = b"SWAV";
+const SWAR_MAGIC: &[u8; 4] = b"SWAR";
+const STRM_MAGIC: &[u8; 4] = b"STRM";
+/// Size of the header of the Nitro files holding waves.
+const HEADER_SIZE: usize = 0x10;
+/// Size of the information preceding the samples of each wave of SWAV and SWAR files.
+const WAVE_INFO_SIZE: usize = 0xC;
+/// Size of the initial sample and step index preceding IMA-ADPCM samples.
+const ADPCM_HEADER_SIZE: usize = 4;
+/// Size of the HEAD block of STRM files, including its magic number and size.
+const STRM_HEAD_SIZE: usize = 0x50;
+/// Size of each block of each channel of the STRM files ravends writes, as in most games.
+const STRM_BLOCK_LEN: usize = 0x200;
+/// Clock the sound timers of the DS count at, in Hz. Waves store the timer value playing them
+/// at their sample rate.
+const SOUND_CLOCK: u32 = 16_756_991;
+/// Lowest sample rate the DS can play, whose timer value still fits in 16 bits.
+pub const MIN_SAMPLE_RATE: u32 = 256;
+/// Highest sample rate worth storing, as the DS mixes its channels at 32768 Hz.
+pub const MAX_SAMPLE_RATE: u32 = 32768;
+/// Largest offset, in bytes, the loop of an SWAV can start at, as it's stored in 32-bit words
+/// in a 16-bit field.
+const MAX_LOOP_OFFSET: usize = 0xFFFF * 4;
+/// Largest size, in bytes, of the samples of an SWAV, as the DS can play at most 2^22 - 1
+/// 32-bit words at once.
+const MAX_SWAV_SIZE: usize = 0x3F_FFFF * 4;
+
+const ADPCM_INDEX_TABLE: [i32; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
+const ADPCM_STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66,
+    73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449,
+    494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272,
+    2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493,
+    10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+
+#[derive(Error, Debug)]
+pub enum ParseWaveError {
+    #[error("magic number does not match (expected: SWAV, SWAR or STRM)")]
+    MagicNumberMismatch,
+    #[error("wave data is truncated")]
+    Truncated,
+    #[error("unknown wave encoding {0}")]
+    UnknownEncoding(u8),
+}
+
+#[derive(Error, Debug)]
+pub enum ReadWavError {
+    #[error("not a RIFF WAVE file")]
+    NotWav,
+    #[error("WAV data is truncated")]
+    Truncated,
+    #[error("required chunk {0} not found")]
+    MissingChunk(&'static str),
+    #[error("unsupported WAV sample format {format} with {bits} bits per sample")]
+    UnsupportedFormat { format: u16, bits: u16 },
+}
+
+#[derive(Error, Debug)]
+pub enum EncodeWaveError {
+    #[error("the wave has no samples")]
+    Empty,
+    #[error("sample rate {0} Hz is out of the range the DS plays ({MIN_SAMPLE_RATE} to {MAX_SAMPLE_RATE} Hz)")]
+    SampleRateOutOfRange(u32),
+    #[error("{file} files hold at most {max} channels, not {channels}")]
+    TooManyChannels {
+        file: &'static str,
+        channels: usize,
+        max: usize,
+    },
+    #[error("loop points {start}..{end} don't fit in the {len} samples of the wave")]
+    InvalidLoopPoints {
+        start: usize,
+        end: usize,
+        len: usize,
+    },
+    #[error("the loop starts 0x{offset:X} bytes into the wave, past the 0x{MAX_LOOP_OFFSET:X} bytes an SWAV loop can start at")]
+    LoopStartTooLate { offset: usize },
+    #[error("the wave takes 0x{size:X} bytes, more than the 0x{MAX_SWAV_SIZE:X} bytes the DS plays at once")]
+    TooLong { size: usize },
+}
+
+/// The kinds of file waves can be encoded as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum WaveFile {
+    /// A single wave, for sound effects and instruments
+    #[default]
+    Swav,
+    /// A stream of up to 2 channels, for music
+    Strm,
+}
+
+impl WaveFile {
+    pub fn extension(self) -> &'static str {
+        match self {
+            WaveFile::Swav => "swav",
+            WaveFile::Strm => "strm",
+        }
+    }
+}
+
+/// How the samples of a wave are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum WaveEncoding {
+    /// Signed 8-bit samples.
+    Pcm8,
+    /// Signed 16-bit samples.
+    Pcm16,
+    /// 4-bit IMA-ADPCM samples, preceded by the initial sample and step index.
+    ImaAdpcm,
+}
+
+impl WaveEncoding {
+    fn from_raw(value: u8) -> Result<Self, ParseWaveError> {
+        match value {
+            0 => Ok(WaveEncoding::Pcm8),
+            1 => Ok(WaveEncoding::Pcm16),
+            2 => Ok(WaveEncoding::ImaAdpcm),
+            _ => Err(ParseWaveError::UnknownEncoding(value)),
+        }
+    }
+
+    fn to_raw(self) -> u8 {
+        match self {
+            WaveEncoding::Pcm8 => 0,
+            WaveEncoding::Pcm16 => 1,
+            WaveEncoding::ImaAdpcm => 2,
+        }
+    }
+
+    /// Number of samples stored in each 32-bit word, which loops must start and end on.
+    fn samples_per_word(self) -> usize {
+        match self {
+            WaveEncoding::Pcm8 => 4,
+            WaveEncoding::Pcm16 => 2,
+            WaveEncoding::ImaAdpcm => 8,
+        }
+    }
+
+    /// Number of bytes `count` samples are stored in, including the ADPCM header if any.
+    fn encoded_len(self, count: usize) -> usize {
+        match self {
+            WaveEncoding::Pcm8 => count,
+            WaveEncoding::Pcm16 => count * 2,
+            WaveEncoding::ImaAdpcm => ADPCM_HEADER_SIZE + count.div_ceil(2),
+        }
+    }
+
+    /// Number of samples stored in `len` bytes, past the ADPCM header if any.
+    fn sample_count(self, len: usize) -> usize {
+        match self {
+            WaveEncoding::Pcm8 => len,
+            WaveEncoding::Pcm16 => len / 2,
+            WaveEncoding::ImaAdpcm => len.saturating_sub(ADPCM_HEADER_SIZE) * 2,
+        }
+    }
+
+    /// Decodes samples of this encoding into 16-bit samples.
+    fn decode(self, data: &[u8]) -> Vec<i16> {
+        match self {
+            WaveEncoding::Pcm8 => data.iter().map(|&byte| (byte as i8 as i16) << 8).collect(),
+            WaveEncoding::Pcm16 => data
+                .chunks_exact(2)
+                .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+                .collect(),
+            WaveEncoding::ImaAdpcm => decode_adpcm(data),
+        }
+    }
+
+    /// Encodes 16-bit samples with this encoding. IMA-ADPCM samples continue from `state`,
+    /// which is updated to follow them.
+    fn encode(self, samples: &[i16], state: &mut AdpcmState) -> Vec<u8> {
+        match self {
+            WaveEncoding::Pcm8 => samples
+                .iter()
+                .map(|&sample| (sample >> 8) as i8 as u8)
+                .collect(),
+            WaveEncoding::Pcm16 => samples
+                .iter()
+                .flat_map(|sample| sample.to_le_bytes())
+                .collect(),
+            WaveEncoding::ImaAdpcm => encode_adpcm(samples, state),
+        }
+    }
+}
+
+/// The last sample and step index of an IMA-ADPCM decoder.
+#[derive(Debug, Clone, Copy, Default)]
+struct AdpcmState {
+    sample: i32,
+    index: usize,
+}
+
+impl AdpcmState {
+    /// Decodes a nibble, updating the state, as the DS sound hardware does.
+    fn decode(&mut self, nibble: u8) -> i16 {
+        let step = ADPCM_STEP_TABLE[self.index];
+        let mut diff = step >> 3;
+        if nibble & 1 != 0 {
+            diff += step >> 2;
+        }
+        if nibble & 2 != 0 {
+            diff += step >> 1;
+        }
+        if nibble & 4 != 0 {
+            diff += step;
+        }
+        self.sample = if nibble & 8 != 0 {
+            (self.sample - diff).max(-0x7FFF)
+        } else {
+            (self.sample + diff).min(0x7FFF)
+        };
+        self.index =
+            (self.index as i32 + ADPCM_INDEX_TABLE[(nibble & 7) as usize]).clamp(0, 88) as usize;
+        self.sample as i16
+    }
+
+    /// Picks the nibble bringing the decoder closest to `sample`, and decodes it.
+    fn encode(&mut self, sample: i16) -> u8 {
+        let step = ADPCM_STEP_TABLE[self.index];
+        let mut delta = sample as i32 - self.sample;
+        let mut nibble = 0;
+        if delta < 0 {
+            nibble = 8;
+            delta = -delta;
+        }
+        for (bit, threshold) in [(4, step), (2, step >> 1), (1, step >> 2)] {
+            if delta >= threshold {
+                nibble |= bit;
+                delta -= threshold;
+            }
+        }
+        self.decode(nibble);
+        nibble
+    }
+}
+
+/// Encodes samples as IMA-ADPCM, preceded by the header holding the state they continue from.
+fn encode_adpcm(samples: &[i16], state: &mut AdpcmState) -> Vec<u8> {
+    let mut data = Vec::with_capacity(ADPCM_HEADER_SIZE + samples.len().div_ceil(2));
+    data.extend((state.sample as i16).to_le_bytes());
+    data.extend((state.index as u16).to_le_bytes());
+    for pair in samples.chunks(2) {
+        let low = state.encode(pair[0]);
+        let high = pair.get(1).map_or(0, |&sample| state.encode(sample));
+        data.push(low | high << 4);
+    }
+    data
+}
+
+/// Decodes IMA-ADPCM samples as the DS sound hardware does, starting from the initial sample
+/// and step index of their header.
+fn decode_adpcm(data: &[u8]) -> Vec<i16> {
+    let Some((header, nibbles)) = data.split_first_chunk::<ADPCM_HEADER_SIZE>() else {
+        return Vec::new();
+    };
+    let mut state = AdpcmState {
+        sample: i16::from_le_bytes([header[0], header[1]]) as i32,
+        index: (u16::from_le_bytes([header[2], header[3]]) as usize).min(88),
+    };
+    let mut samples = Vec::with_capacity(nibbles.len() * 2);
+    for &byte in nibbles {
+        for nibble in [byte & 0xF, byte >> 4] {
+            samples.push(state.decode(nibble));
+        }
+    }
+    samples
+}
+
+/// Where a wave loops, in samples. Waves play from the start and then repeat the samples
+/// between the loop start and end forever.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoopPoints {
+    pub sample_rate: u32,
+    pub loop_start: usize,
+    pub loop_end: usize,
+}
+
+/// Decoded sound samples, along with how they are played.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wave {
+    pub encoding: WaveEncoding,
+    pub sample_rate: u32,
+    /// The samples of each channel, all of the same length.
+    pub channels: Vec<Vec<i16>>,
+    pub loop_points: Option<LoopPoints>,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, ParseWaveError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseWaveError::Truncated)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, ParseWaveError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(ParseWaveError::Truncated)
+}
+
+fn bytes_at(data: &[u8], offset: usize, len: usize) -> Result<&[u8], ParseWaveError> {
+    data.get(offset..offset + len)
+        .ok_or(ParseWaveError::Truncated)
+}
+
+/// Checks that the DS can play samples at the rate given.
+fn check_sample_rate(sample_rate: u32) -> Result<(), EncodeWaveError> {
+    if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&sample_rate) {
+        return Err(EncodeWaveError::SampleRateOutOfRange(sample_rate));
+    }
+    Ok(())
+}
+
+/// The timer value playing samples at the rate given.
+fn timer_value(sample_rate: u32) -> u16 {
+    (SOUND_CLOCK / sample_rate) as u16
+}
+
+/// Builds a Nitro file of the kind given out of its blocks.
+fn nitro_file(magic: &[u8; 4], blocks: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let size = HEADER_SIZE + blocks.iter().map(|(_, data)| 8 + data.len()).sum::<usize>();
+    let mut file = Vec::with_capacity(size);
+    file.extend_from_slice(magic);
+    // Byte order mark and version.
+    file.extend_from_slice(&[0xFF, 0xFE, 0x00, 0x01]);
+    file.extend((size as u32).to_le_bytes());
+    file.extend((HEADER_SIZE as u16).to_le_bytes());
+    file.extend((blocks.len() as u16).to_le_bytes());
+    for (block_magic, data) in blocks {
+        file.extend_from_slice(*block_magic);
+        file.extend(((8 + data.len()) as u32).to_le_bytes());
+        file.extend_from_slice(data);
+    }
+    file
+}
+
+/// Reads an integer of up to 4 bytes, little-endian.
+fn uint_le(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |value, &byte| value << 8 | byte as u32)
+}
+
+impl Wave {
+    /// Parses a wave of an SWAV or SWAR file: its information followed by its samples.
+    fn parse_entry(data: &[u8]) -> Result<Self, ParseWaveError> {
+        let info = bytes_at(data, 0, WAVE_INFO_SIZE)?;
+        let encoding = WaveEncoding::from_raw(info[0])?;
+        let looping = info[1] != 0;
+        let sample_rate = u16_at(info, 2)? as u32;
+        // Lengths are counted in 32-bit words, including the header of ADPCM samples.
+        let loop_offset = u16_at(info, 6)? as usize * 4;
+        let loop_len = u32_at(info, 8)? as usize * 4;
+        let samples = bytes_at(data, WAVE_INFO_SIZE, loop_offset + loop_len)?;
+        let channel = encoding.decode(samples);
+        Ok(Wave {
+            encoding,
+            sample_rate,
+            loop_points: looping.then(|| LoopPoints {
+                sample_rate,
+                loop_start: encoding.sample_count(loop_offset),
+                loop_end: channel.len(),
+            }),
+            channels: vec![channel],
+        })
+    }
+
+    /// Parses an SWAV file, holding a single wave.
+    pub fn parse_swav(data: &[u8]) -> Result<Self, ParseWaveError> {
+        if !data.starts_with(SWAV_MAGIC) {
+            return Err(ParseWaveError::MagicNumberMismatch);
+        }
+        // The wave follows the header of the DATA block.
+        Wave::parse_entry(
+            data.get(HEADER_SIZE + 8..)
+                .ok_or(ParseWaveError::Truncated)?,
+        )
+    }
+
+    /// Parses an SWAR file, an archive of waves used by the instruments of banks.
+    pub fn parse_swar(data: &[u8]) -> Result<Vec<Self>, ParseWaveError> {
+        if !data.starts_with(SWAR_MAGIC) {
+            return Err(ParseWaveError::MagicNumberMismatch);
+        }
+        // The DATA block has 32 reserved bytes before the wave count and offsets.
+        let table = HEADER_SIZE + 8 + 0x20;
+        let count = u32_at(data, table)? as usize;
+        (0..count)
+            .map(|index| {
+                let offset = u32_at(data, table + 4 + index * 4)? as usize;
+                Wave::parse_entry(data.get(offset..).ok_or(ParseWaveError::Truncated)?)
+            })
+            .collect()
+    }
+
+    /// Parses an STRM file, a stream of samples split into blocks, each holding a part of every
+    /// channel in turn.
+    pub fn parse_strm(data: &[u8]) -> Result<Self, ParseWaveError> {
+        if !data.starts_with(STRM_MAGIC) {
+            return Err(ParseWaveError::MagicNumberMismatch);
+        }
+        let head = HEADER_SIZE + 8;
+        let encoding = WaveEncoding::from_raw(*data.get(head).ok_or(ParseWaveError::Truncated)?)?;
+        let looping = *data.get(head + 1).ok_or(ParseWaveError::Truncated)? != 0;
+        let channel_count = *data.get(head + 2).ok_or(ParseWaveError::Truncated)? as usize;
+        let sample_rate = u16_at(data, head + 4)? as u32;
+        let loop_start = u32_at(data, head + 8)? as usize;
+        let sample_count = u32_at(data, head + 0xC)? as usize;
+        let data_offset = u32_at(data, head + 0x10)? as usize;
+        let block_count = u32_at(data, head + 0x14)? as usize;
+        let block_len = u32_at(data, head + 0x18)? as usize;
+        let block_samples = u32_at(data, head + 0x1C)? as usize;
+        let last_block_len = u32_at(data, head + 0x20)? as usize;
+        let last_block_samples = u32_at(data, head + 0x24)? as usize;
+
+        // Counts come from the header, but no encoding packs more than two samples in a byte.
+        let capacity = sample_count.min(data.len() * 2 / channel_count.max(1));
+        let mut channels = vec![Vec::with_capacity(capacity); channel_count];
+        let mut offset = data_offset;
+        for block in 0..block_count {
+            let (len, samples, stride) = if block + 1 == block_count {
+                // The last block of each channel is padded to a multiple of 4 bytes.
+                (
+                    last_block_len,
+                    last_block_samples,
+                    last_block_len.next_multiple_of(4),
+                )
+            } else {
+                (block_len, block_samples, block_len)
+            };
+            for channel in &mut channels {
+                let mut decoded = encoding.decode(bytes_at(data, offset, len)?);
+                decoded.truncate(samples);
+                channel.extend(decoded);
+                offset += stride;
+            }
+        }
+        for channel in &mut channels {
+            channel.truncate(sample_count);
+        }
+        Ok(Wave {
+            encoding,
+            sample_rate,
+            loop_points: looping.then_some(LoopPoints {
+                sample_rate,
+                loop_start,
+                loop_end: sample_count,
+            }),
+            channels,
+        })
+    }
+
+    /// Reads a WAV file of integer or floating-point PCM samples. Loop points are read from the
+    /// first loop of its `smpl` chunk, if it has one.
+    pub fn from_wav(data: &[u8]) -> Result<Self, ReadWavError> {
+        if data.get(..4) != Some(b"RIFF") || data.get(8..12) != Some(b"WAVE") {
+            return Err(ReadWavError::NotWav);
+        }
+        let mut chunks = Vec::new();
+        let mut offset = 12;
+        while let Some(header) = data.get(offset..offset + 8) {
+            let size = uint_le(&header[4..]) as usize;
+            let body = data
+                .get(offset + 8..offset + 8 + size)
+                .ok_or(ReadWavError::Truncated)?;
+            chunks.push((&header[..4], body));
+            // Chunks are padded to an even size.
+            offset += 8 + size + size % 2;
+        }
+        let chunk = |id: &'static str| {
+            chunks
+                .iter()
+                .find(|(chunk_id, _)| *chunk_id == id.as_bytes())
+                .map(|(_, body)| *body)
+        };
+        let fmt = chunk("fmt ").ok_or(ReadWavError::MissingChunk("fmt"))?;
+        let samples = chunk("data").ok_or(ReadWavError::MissingChunk("data"))?;
+        let field = |offset: usize, len: usize| {
+            fmt.get(offset..offset + len)
+                .map(uint_le)
+                .ok_or(ReadWavError::Truncated)
+        };
+        let mut format = field(0, 2)? as u16;
+        let channel_count = (field(2, 2)? as usize).max(1);
+        let sample_rate = field(4, 4)?;
+        let bits = field(14, 2)? as u16;
+        // WAVE_FORMAT_EXTENSIBLE files give the actual format at the start of their subformat.
+        if format == 0xFFFE {
+            format = field(24, 2)? as u16;
+        }
+        let bytes_per_sample = (bits as usize).div_ceil(8);
+        let decode: fn(&[u8]) -> i16 = match (format, bytes_per_sample) {
+            (1, 1) => |bytes| (bytes[0] as i16 - 0x80) << 8,
+            (1, 2) => |bytes| i16::from_le_bytes([bytes[0], bytes[1]]),
+            (1, 3) => |bytes| i16::from_le_bytes([bytes[1], bytes[2]]),
+            (1, 4) => |bytes| i16::from_le_bytes([bytes[2], bytes[3]]),
+            (3, 4) => |bytes| {
+                let sample = f32::from_le_bytes(bytes.try_into().unwrap());
+                (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+            },
+            _ => return Err(ReadWavError::UnsupportedFormat { format, bits }),
+        };
+        let mut channels = vec![Vec::new(); channel_count];
+        for frame in samples.chunks_exact(bytes_per_sample * channel_count) {
+            for (channel, bytes) in channels
+                .iter_mut()
+                .zip(frame.chunks_exact(bytes_per_sample))
+            {
+                channel.push(decode(bytes));
+            }
+        }
+        // The first sample loop: its start, and its end, which includes the last sample.
+        let loop_points = chunk("smpl")
+            .filter(|smpl| smpl.get(28..32).map(uint_le).unwrap_or(0) > 0)
+            .and_then(|smpl| smpl.get(36 + 8..36 + 16))
+            .map(|bounds| LoopPoints {
+                sample_rate,
+                loop_start: uint_le(&bounds[..4]) as usize,
+                loop_end: uint_le(&bounds[4..]) as usize + 1,
+            });
+        Ok(Wave {
+            encoding: WaveEncoding::Pcm16,
+            sample_rate,
+            channels,
+            loop_points,
+        })
+    }
+
+    /// The wave with its channels mixed into one.
+    pub fn to_mono(&self) -> Wave {
+        let len = self.channels.first().map_or(0, Vec::len);
+        let count = self.channels.len().max(1) as i32;
+        let mixed = (0..len)
+            .map(|index| {
+                let sum = self
+                    .channels
+                    .iter()
+                    .map(|channel| channel[index] as i32)
+                    .sum::<i32>();
+                (sum / count) as i16
+            })
+            .collect();
+        Wave {
+            channels: vec![mixed],
+            ..self.clone()
+        }
+    }
+
+    /// The wave resampled to the rate given with linear interpolation, moving its loop points
+    /// along.
+    pub fn resampled(&self, sample_rate: u32) -> Wave {
+        if sample_rate == self.sample_rate || self.sample_rate == 0 {
+            return self.clone();
+        }
+        let ratio = self.sample_rate as f64 / sample_rate as f64;
+        let scale = |position: usize| (position as f64 / ratio).round() as usize;
+        let channels = self
+            .channels
+            .iter()
+            .map(|channel| {
+                (0..scale(channel.len()))
+                    .map(|index| {
+                        let position = index as f64 * ratio;
+                        let before = (position.floor() as usize).min(channel.len() - 1);
+                        let after = (before + 1).min(channel.len() - 1);
+                        let fraction = position - before as f64;
+                        (channel[before] as f64 * (1.0 - fraction)
+                            + channel[after] as f64 * fraction)
+                            .round() as i16
+                    })
+                    .collect()
+            })
+            .collect();
+        Wave {
+            sample_rate,
+            channels,
+            loop_points: self.loop_points.map(|loop_points| LoopPoints {
+                sample_rate,
+                loop_start: scale(loop_points.loop_start),
+                loop_end: scale(loop_points.loop_end),
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// Checks that the wave can be encoded, returning its samples cut at the end of its loop
+    /// and the loop points.
+    fn checked_samples(
+        &self,
+        file: &'static str,
+        max_channels: usize,
+    ) -> Result<(Vec<Vec<i16>>, Option<LoopPoints>), EncodeWaveError> {
+        check_sample_rate(self.sample_rate)?;
+        if self.channels.len() > max_channels {
+            return Err(EncodeWaveError::TooManyChannels {
+                file,
+                channels: self.channels.len(),
+                max: max_channels,
+            });
+        }
+        let len = self.channels.first().map_or(0, Vec::len);
+        if len == 0 {
+            return Err(EncodeWaveError::Empty);
+        }
+        let mut channels = self.channels.clone();
+        if let Some(loop_points) = self.loop_points {
+            if loop_points.loop_start >= loop_points.loop_end || loop_points.loop_end > len {
+                return Err(EncodeWaveError::InvalidLoopPoints {
+                    start: loop_points.loop_start,
+                    end: loop_points.loop_end,
+                    len,
+                });
+            }
+            // Samples past the loop are never played.
+            for channel in &mut channels {
+                channel.truncate(loop_points.loop_end);
+            }
+        }
+        Ok((channels, self.loop_points))
+    }
+
+    /// Encodes the wave as an SWAV file, holding a single channel. The DS can only loop waves
+    /// on 32-bit word boundaries, so silence is added before looping waves to move their loop
+    /// start to one, and samples from the loop start are repeated after their loop end to end
+    /// on one without a gap.
+    pub fn to_swav(&self, encoding: WaveEncoding) -> Result<Vec<u8>, EncodeWaveError> {
+        let (channels, loop_points) = self.checked_samples("SWAV", 1)?;
+        let mut samples = channels.into_iter().next().unwrap_or_default();
+        let alignment = encoding.samples_per_word();
+        let loop_start = match loop_points {
+            Some(loop_points) => {
+                let padding =
+                    loop_points.loop_start.next_multiple_of(alignment) - loop_points.loop_start;
+                samples.splice(0..0, std::iter::repeat_n(0, padding));
+                let loop_start = loop_points.loop_start + padding;
+                let mut position = loop_start;
+                while samples.len() % alignment != 0 {
+                    samples.push(samples[position]);
+                    position += 1;
+                }
+                Some(loop_start)
+            }
+            None => {
+                samples.resize(samples.len().next_multiple_of(alignment), 0);
+                None
+            }
+        };
+
+        let loop_offset = loop_start.map_or(0, |start| encoding.encoded_len(start));
+        if loop_offset > MAX_LOOP_OFFSET {
+            return Err(EncodeWaveError::LoopStartTooLate {
+                offset: loop_offset,
+            });
+        }
+        let encoded = encoding.encode(
+            &samples,
+            &mut AdpcmState {
+                sample: samples[0] as i32,
+                index: 0,
+            },
+        );
+        if encoded.len() > MAX_SWAV_SIZE {
+            return Err(EncodeWaveError::TooLong {
+                size: encoded.len(),
+            });
+        }
+        let mut data = Vec::with_capacity(WAVE_INFO_SIZE + encoded.len());
+        data.push(encoding.to_raw());
+        data.push(loop_start.is_some() as u8);
+        data.extend((self.sample_rate as u16).to_le_bytes());
+        data.extend(timer_value(self.sample_rate).to_le_bytes());
+        data.extend(((loop_offset / 4) as u16).to_le_bytes());
+        data.extend((((encoded.len() - loop_offset) / 4) as u32).to_le_bytes());
+        data.extend(encoded);
+        Ok(nitro_file(SWAV_MAGIC, &[(b"DATA", data)]))
+    }
+
+    /// Encodes the wave as an STRM file of up to 2 channels, split into blocks of 0x200 bytes
+    /// per channel.
+    pub fn to_strm(&self, encoding: WaveEncoding) -> Result<Vec<u8>, EncodeWaveError> {
+        let (channels, loop_points) = self.checked_samples("STRM", 2)?;
+        let sample_count = channels[0].len();
+        let block_samples = match encoding {
+            WaveEncoding::Pcm8 => STRM_BLOCK_LEN,
+            WaveEncoding::Pcm16 => STRM_BLOCK_LEN / 2,
+            WaveEncoding::ImaAdpcm => (STRM_BLOCK_LEN - ADPCM_HEADER_SIZE) * 2,
+        };
+        let block_count = sample_count.div_ceil(block_samples);
+        let last_block_samples = sample_count - (block_count - 1) * block_samples;
+        let last_block_len = encoding.encoded_len(last_block_samples);
+
+        let mut states = channels
+            .iter()
+            .map(|channel| AdpcmState {
+                sample: channel[0] as i32,
+                index: 0,
+            })
+            .collect::<Vec<_>>();
+        let mut samples = Vec::new();
+        for block in 0..block_count {
+            let start = block * block_samples;
+            let end = (start + block_samples).min(sample_count);
+            for (channel, state) in channels.iter().zip(&mut states) {
+                let mut encoded = encoding.encode(&channel[start..end], state);
+                // The last block of each channel is padded to a multiple of 4 bytes.
+                encoded.resize(encoded.len().next_multiple_of(4), 0);
+                samples.extend(encoded);
+            }
+        }
+
+        let mut head = Vec::with_capacity(STRM_HEAD_SIZE - 8);
+        head.push(encoding.to_raw());
+        head.push(loop_points.is_some() as u8);
+        head.push(channels.len() as u8);
+        head.push(0);
+        head.extend((self.sample_rate as u16).to_le_bytes());
+        head.extend(timer_value(self.sample_rate).to_le_bytes());
+        for value in [
+            loop_points.map_or(0, |loop_points| loop_points.loop_start),
+            sample_count,
+            // The samples follow the header of the DATA block.
+            HEADER_SIZE + STRM_HEAD_SIZE + 8,
+            block_count,
+            encoding.encoded_len(block_samples),
+            block_samples,
+            last_block_len,
+            last_block_samples,
+        ] {
+            head.extend((value as u32).to_le_bytes());
+        }
+        head.resize(STRM_HEAD_SIZE - 8, 0);
+        Ok(nitro_file(
+            STRM_MAGIC,
+            &[(b"HEAD", head), (b"DATA", samples)],
+        ))
+    }
+
+    /// Encodes the wave as a 16-bit PCM WAV file.
+    pub fn to_wav(&self) -> Vec<u8> {
+        let channel_count = self.channels.len().max(1) as u16;
+        let sample_count = self.channels.first().map_or(0, Vec::len);
+        let data_size = (sample_count * channel_count as usize * 2) as u32;
+        let block_align = channel_count * 2;
+
+        let mut wav = Vec::with_capacity(44 + data_size as usize);
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&channel_count.to_le_bytes());
+        wav.extend_from_slice(&self.sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(self.sample_rate * block_align as u32).to_le_bytes());
+        wav.extend_from_slice(&block_align.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_size.to_le_bytes());
+        for index in 0..sample_count {
+            for channel in &self.channels {
+                wav.extend_from_slice(&channel[index].to_le_bytes());
+            }
+        }
+        wav
+    }
+}
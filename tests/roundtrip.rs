@@ -0,0 +1,64 @@
+use std::{fs, path::Path};
+
+use ravends::{
+    cache::CompressionCache,
+    lz::CompressionLevel,
+    lz10::compress_lz10,
+    manifest::SYSTEM_DIR,
+    pack::{self, PackOptions},
+    rom::{self, PathFilter, Section},
+    unpack::{self, UnpackOptions},
+};
+
+/// Writes the binaries and files of a small homebrew game to `dir`, to be packed into a ROM.
+fn write_game(dir: &Path) {
+    let system = dir.join(SYSTEM_DIR);
+    fs::create_dir_all(&system).unwrap();
+    fs::create_dir_all(dir.join("data/msg")).unwrap();
+    fs::write(
+        system.join(Section::Header.file_name()),
+        rom::homebrew_header("Roundtrip"),
+    )
+    .unwrap();
+    let arm9 = (0..0x800u32)
+        .map(|index| (index * 7) as u8)
+        .collect::<Vec<_>>();
+    fs::write(system.join(Section::Arm9.file_name()), arm9).unwrap();
+    fs::write(system.join(Section::Arm7.file_name()), [0x11; 0x400]).unwrap();
+
+    let text = "Hello from the round trip test.\n".repeat(40);
+    fs::write(dir.join("data/msg/en.txt"), &text).unwrap();
+    let compressed = compress_lz10(text.as_bytes(), CompressionLevel::Best).unwrap();
+    fs::write(dir.join("data/msg/en.lz"), compressed).unwrap();
+    fs::write(dir.join("data/empty.bin"), []).unwrap();
+    fs::write(dir.join("readme.bin"), (0..=255u8).collect::<Vec<_>>()).unwrap();
+}
+
+fn pack_dir(dir: &Path) -> Vec<u8> {
+    pack::pack(dir, &CompressionCache::new(dir), &PackOptions::default()).unwrap()
+}
+
+#[test]
+fn homebrew_rom_round_trips() {
+    let game_dir = tempfile::tempdir().unwrap();
+    write_game(game_dir.path());
+    let rom_data = pack_dir(game_dir.path());
+    assert_eq!(rom::filesystem(&rom_data).unwrap().files().len(), 4);
+
+    let unpack_dir = tempfile::tempdir().unwrap();
+    unpack::unpack(
+        &rom_data,
+        unpack_dir.path(),
+        &PathFilter::default(),
+        &UnpackOptions::default(),
+    )
+    .unwrap();
+    let repacked_data = pack_dir(unpack_dir.path());
+    assert_eq!(repacked_data.len(), rom_data.len());
+    let first_difference =
+        (0..rom_data.len()).find(|&index| rom_data[index] != repacked_data[index]);
+    assert_eq!(
+        first_difference, None,
+        "repacked ROM differs from the original"
+    );
+}
@@ -0,0 +1,58 @@
+//! WebAssembly bindings for ravends, for tools running in the browser. Build them with
+//! `wasm-pack build wasm`.
+
+use ravends::{
+    lz10::decompress_lz10,
+    lz11::decompress_lz11,
+    plugin::Plugins,
+    rom::{self, PathFilter},
+    survey,
+    text::TextEncoding,
+    unpack,
+};
+use wasm_bindgen::prelude::*;
+
+fn error(error: impl Into<anyhow::Error>) -> JsError {
+    JsError::new(&format!("{:#}", error.into()))
+}
+
+/// Describes what a file is from its contents, looking inside it if it's LZ10-compressed.
+#[wasm_bindgen]
+pub fn identify(data: &[u8]) -> String {
+    survey::identify_file(data, &TextEncoding::default(), &Plugins::default()).description
+}
+
+/// Decompresses LZ10- or LZ11-compressed data.
+#[wasm_bindgen]
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, JsError> {
+    decompress_lz10(data)
+        .or_else(|_| decompress_lz11(data))
+        .map_err(|_| JsError::new("the data is neither LZ10- nor LZ11-compressed"))
+}
+
+/// A file of an unpacked ROM.
+#[wasm_bindgen(getter_with_clone)]
+pub struct UnpackedFile {
+    /// Path of the file, as it would be unpacked to a directory.
+    pub path: String,
+    pub data: Vec<u8>,
+    /// What the file is, and what was done to it.
+    pub description: String,
+}
+
+/// Unpacks a ROM: its sections and overlays inside `_sys`, and its NitroFS files converted
+/// to editable formats where possible.
+#[wasm_bindgen]
+pub fn unpack(rom_data: &[u8]) -> Result<Vec<UnpackedFile>, JsError> {
+    rom::check_header(rom_data).map_err(error)?;
+    let filter = PathFilter::new(&[], &[]).map_err(error)?;
+    Ok(unpack::unpack_files(rom_data, &filter)
+        .map_err(error)?
+        .into_iter()
+        .map(|file| UnpackedFile {
+            path: file.path,
+            data: file.data,
+            description: file.description,
+        })
+        .collect())
+}